@@ -0,0 +1,90 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use indexmap::IndexMap;
+use pbase::{
+    pbase::PBase,
+    query::{CreateTableQuery, InsertQuery},
+    schema::{FieldSchema, TableSchema},
+    value::Value,
+};
+use rand::prelude::*;
+
+const ROW_COUNTS: [u64; 2] = [100_000, 1_000_000];
+
+// Removed when dropped, which criterion does outside of the measured routine.
+struct BenchDir(PathBuf);
+
+impl Drop for BenchDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+// Fresh directory with an empty, indexed table.
+fn setup(row_count: u64) -> (PBase, BenchDir) {
+    let dir = std::env::temp_dir().join(format!("pbase_insert_bench_{row_count}"));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "bench".into(),
+            fields: IndexMap::from([
+                ("field1".into(), FieldSchema::I32),
+                ("field2".into(), FieldSchema::I32),
+            ]),
+            indices: HashMap::from([("field1_index".into(), vec!["field1".into()])]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+
+    (db, BenchDir(dir))
+}
+
+fn load_rows(db: &PBase, row_count: u64) {
+    let mut rng = rand::rng();
+    for i in 0..row_count {
+        let insert_query = InsertQuery {
+            table: "bench".into(),
+            values: HashMap::from([
+                ("field1".into(), Value::I32(rng.random::<i32>())),
+                ("field2".into(), Value::I32(i32::try_from(i).unwrap())),
+            ]),
+        };
+        db.run_insert_query(&insert_query).unwrap();
+    }
+}
+
+fn bench_insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert");
+
+    for row_count in ROW_COUNTS {
+        group.throughput(Throughput::Elements(row_count));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(row_count),
+            &row_count,
+            |b, &row_count| {
+                b.iter_batched(
+                    || setup(row_count),
+                    |(db, dir)| {
+                        load_rows(&db, row_count);
+                        (db, dir)
+                    },
+                    BatchSize::PerIteration,
+                );
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_insert
+}
+criterion_main!(benches);
@@ -0,0 +1,118 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use indexmap::IndexMap;
+use pbase::{
+    pbase::PBase,
+    query::{
+        CompareOp, CreateTableQuery, FieldSelector, InsertQuery, RhsValue, RowFilter, SelectQuery,
+    },
+    schema::{FieldSchema, TableSchema},
+    table_opener::IoStrategy,
+    value::Value,
+};
+
+const ROW_COUNT: u64 = 100_000;
+
+// Removed when dropped, after all measurements.
+struct BenchDir(PathBuf);
+
+impl Drop for BenchDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+// Directory with a loaded, indexed table.
+fn setup() -> BenchDir {
+    let dir = std::env::temp_dir().join("pbase_scan_bench");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "bench".into(),
+            fields: IndexMap::from([
+                ("field1".into(), FieldSchema::I32),
+                ("field2".into(), FieldSchema::I32),
+            ]),
+            indices: HashMap::from([("field1_index".into(), vec!["field1".into()])]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    for i in 0..ROW_COUNT {
+        let i = i32::try_from(i).unwrap();
+        db.run_insert_query(&InsertQuery {
+            table: "bench".into(),
+            values: HashMap::from([
+                ("field1".into(), Value::I32(i % 1000)),
+                ("field2".into(), Value::I32(i)),
+            ]),
+        })
+        .unwrap();
+    }
+    db.merge_index_deltas().unwrap();
+
+    BenchDir(dir)
+}
+
+fn filter(field_name: &str, value: i32) -> RowFilter {
+    RowFilter {
+        field: FieldSelector {
+            name: field_name.into(),
+            source: "bench".into(),
+        },
+        op: CompareOp::Eq,
+        rhs: RhsValue::Value(Value::I32(value)),
+    }
+}
+
+fn bench_scan(c: &mut Criterion) {
+    let dir = setup();
+    let mut group = c.benchmark_group("scan");
+    group.throughput(Throughput::Elements(ROW_COUNT));
+
+    for io_strategy in [IoStrategy::Mmap, IoStrategy::Buffered] {
+        let db = PBase::new(dir.0.clone()).with_io_strategy(io_strategy);
+
+        group.bench_with_input(
+            BenchmarkId::new("full", format!("{io_strategy:?}")),
+            &db,
+            |b, db| {
+                b.iter(|| {
+                    db.run_select_query(SelectQuery {
+                        from: "bench".into(),
+                        filters: vec![filter("field2", 7)],
+                        ..Default::default()
+                    })
+                    .unwrap()
+                });
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("index", format!("{io_strategy:?}")),
+            &db,
+            |b, db| {
+                b.iter(|| {
+                    db.run_select_query(SelectQuery {
+                        from: "bench".into(),
+                        filters: vec![filter("field1", 7)],
+                        ..Default::default()
+                    })
+                    .unwrap()
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_scan
+}
+criterion_main!(benches);
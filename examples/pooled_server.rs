@@ -0,0 +1,60 @@
+use indexmap::IndexMap;
+use pbase::{
+    common::Error,
+    pbase::PBase,
+    pool::PBasePool,
+    query::{CreateTableQuery, InsertQuery, SelectQuery},
+    schema::{FieldSchema, TableSchema},
+    value::Value,
+};
+use std::collections::HashMap;
+
+///
+/// Shares a database between request handlers the way a web server would keep it in its app state
+/// (eg. axum's `State<PBasePool>` or actix's `web::Data<PBasePool>`). Threads stand in for the
+/// server's request workers.
+///
+/// Usage: `cargo run --example pooled_server`, on a temporary directory.
+///
+fn main() -> Result<(), Error> {
+    let dir = std::env::temp_dir().join("pbase_pooled_server_example");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir)?;
+
+    let pool = PBasePool::new(PBase::new(dir.clone())).with_max_connections(4);
+    pool.write()?.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "visits".into(),
+            fields: IndexMap::from([("page".into(), FieldSchema::I32)]),
+            ..Default::default()
+        },
+    })?;
+
+    let workers: Vec<_> = (0..8)
+        .map(|worker| {
+            let pool = pool.clone();
+            std::thread::spawn(move || -> Result<usize, Error> {
+                // POST /visits
+                pool.write()?.run_insert_query(&InsertQuery {
+                    table: "visits".into(),
+                    values: HashMap::from([("page".into(), Value::I32(worker % 3))]),
+                })?;
+
+                // GET /visits
+                let visits = pool.read()?.run_select_query(SelectQuery {
+                    from: "visits".into(),
+                    ..Default::default()
+                })?;
+                Ok(visits.len())
+            })
+        })
+        .collect();
+
+    for worker in workers {
+        let visits = worker.join().expect("Worker panicked")?;
+        println!("Request saw {visits} visits");
+    }
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,207 @@
+use std::io::{BufRead, BufReader, Read, Write};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::{Error, PBaseError},
+    schema::TableSchema,
+    value::Value,
+};
+
+pub const TABLE_ARCHIVE_FORMAT: &str = "pbase-table-archive";
+// Highest archive version this build reads.
+pub const TABLE_ARCHIVE_VERSION: u32 = 1;
+
+///
+/// First record of a table archive, describing the rows that follow.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableArchiveHeader {
+    pub format: String,
+    pub version: u32,
+    pub schema: TableSchema,
+    pub row_count: usize,
+}
+
+impl TableArchiveHeader {
+    #[must_use]
+    pub fn new(schema: TableSchema, row_count: usize) -> Self {
+        Self {
+            format: TABLE_ARCHIVE_FORMAT.to_string(),
+            version: TABLE_ARCHIVE_VERSION,
+            schema,
+            row_count,
+        }
+    }
+}
+
+///
+/// Writes a table archive: a gzip stream of JSON lines.
+///
+/// The header comes first, then each row as the list of its values in schema field order. Nothing
+/// of the data or index file layouts is kept, so archives can be read by any version knowing the
+/// archive format.
+///
+pub struct TableArchiveWriter<W: Write> {
+    encoder: GzEncoder<W>,
+}
+
+impl<W: Write> TableArchiveWriter<W> {
+    /// # Errors
+    ///
+    /// On write errors.
+    pub fn new(writer: W, header: &TableArchiveHeader) -> Result<Self, Error> {
+        let mut archive_writer = Self {
+            encoder: GzEncoder::new(writer, Compression::default()),
+        };
+        archive_writer.write_line(header)?;
+
+        Ok(archive_writer)
+    }
+
+    /// # Errors
+    ///
+    /// On write errors.
+    pub fn write_row(&mut self, values: &[Value]) -> Result<(), Error> {
+        self.write_line(&values)
+    }
+
+    ///
+    /// Completes the compressed stream and returns the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// On write errors.
+    pub fn finish(self) -> Result<W, Error> {
+        Ok(self.encoder.finish()?)
+    }
+
+    fn write_line<T: Serialize>(&mut self, record: &T) -> Result<(), Error> {
+        serde_json::to_writer(&mut self.encoder, record)?;
+        self.encoder.write_all(b"\n")?;
+
+        Ok(())
+    }
+}
+
+///
+/// Reads a table archive written by `TableArchiveWriter`.
+///
+pub struct TableArchiveReader<R: Read> {
+    lines: std::io::Lines<BufReader<GzDecoder<R>>>,
+    header: TableArchiveHeader,
+}
+
+impl<R: Read> TableArchiveReader<R> {
+    /// # Errors
+    ///
+    /// On read errors, when the input is not a table archive, or when its version is newer than
+    /// `TABLE_ARCHIVE_VERSION`.
+    pub fn new(reader: R) -> Result<Self, Error> {
+        let mut lines = BufReader::new(GzDecoder::new(reader)).lines();
+        let header_line = lines
+            .next()
+            .ok_or_else(|| PBaseError::InvalidArchive("missing header".into()))??;
+        let header: TableArchiveHeader = serde_json::from_str(&header_line)
+            .map_err(|err| PBaseError::InvalidArchive(format!("bad header: {err}")))?;
+
+        if header.format != TABLE_ARCHIVE_FORMAT {
+            return Err(
+                PBaseError::InvalidArchive(format!("unknown format {}", header.format)).into(),
+            );
+        }
+        if header.version > TABLE_ARCHIVE_VERSION {
+            return Err(PBaseError::InvalidArchive(format!(
+                "version {} is newer than supported {TABLE_ARCHIVE_VERSION}",
+                header.version
+            ))
+            .into());
+        }
+
+        Ok(Self { lines, header })
+    }
+
+    #[must_use]
+    pub const fn header(&self) -> &TableArchiveHeader {
+        &self.header
+    }
+
+    ///
+    /// The next row's values, in schema field order.
+    ///
+    /// # Errors
+    ///
+    /// On read errors, or when the row does not match the schema's field count.
+    pub fn next_row(&mut self) -> Result<Option<Vec<Value>>, Error> {
+        let Some(line) = self.lines.next() else {
+            return Ok(None);
+        };
+
+        let values: Vec<Value> = serde_json::from_str(&line?)
+            .map_err(|err| PBaseError::InvalidArchive(format!("bad row: {err}")))?;
+        if values.len() != self.header.schema.fields.len() {
+            return Err(PBaseError::InvalidArchive(format!(
+                "row of {} values for {} fields",
+                values.len(),
+                self.header.schema.fields.len()
+            ))
+            .into());
+        }
+
+        Ok(Some(values))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use indexmap::IndexMap;
+
+    use crate::{
+        schema::{FieldSchema, TableSchema},
+        value::Value,
+    };
+
+    use super::{TableArchiveHeader, TableArchiveReader, TableArchiveWriter};
+
+    #[test]
+    fn test_table_archive_roundtrip() {
+        let header = TableArchiveHeader::new(
+            TableSchema {
+                name: "t1".into(),
+                fields: IndexMap::from([
+                    ("a".into(), FieldSchema::I32),
+                    ("b".into(), FieldSchema::U8),
+                ]),
+                ..Default::default()
+            },
+            2,
+        );
+        let rows = vec![
+            vec![Value::I32(-1), Value::U8(2)],
+            vec![Value::I32(3), Value::U8(4)],
+        ];
+
+        let mut writer = TableArchiveWriter::new(vec![], &header).unwrap();
+        for row in &rows {
+            writer.write_row(row).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+
+        let mut reader = TableArchiveReader::new(&bytes[..]).unwrap();
+        assert_eq!(&header, reader.header());
+        assert_eq!(Some(rows[0].clone()), reader.next_row().unwrap());
+        assert_eq!(Some(rows[1].clone()), reader.next_row().unwrap());
+        assert_eq!(None, reader.next_row().unwrap());
+
+        assert!(TableArchiveReader::new(&b"not an archive"[..]).is_err());
+
+        let mut writer = TableArchiveWriter::new(vec![], &header).unwrap();
+        writer.write_row(&[Value::I32(1)]).unwrap();
+        let bytes = writer.finish().unwrap();
+        assert!(TableArchiveReader::new(&bytes[..])
+            .unwrap()
+            .next_row()
+            .is_err());
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+
+use indexmap::IndexMap;
+
+use crate::{
+    common::Error,
+    schema::{FieldSchema, TablePtrType, TableSchema},
+    value::Value,
+};
+
+///
+/// Append-only table the engine records the mutations of audited tables in (see
+/// `TableSchema::audited`), created with the first of them.
+///
+/// Values are numeric only, so a mutation is recorded as one row per column it set:
+///
+/// - `lsn`: WAL LSN of the mutation, shared by its rows
+/// - `time`: unix time (in seconds) of the mutation
+/// - `actor`: id of who mutated, see `PBase::with_audit_actor`
+/// - `table_id`: the table, see `audit_table_id`
+/// - `op`: 0 insert, 1 update, 2 delete (see `AuditOp`)
+/// - `row_pos`: byte position of the row in the table data
+/// - `column`: position of the column in the table
+/// - `old_value`, `new_value`: the column's value before (0 for inserts) and after, as I32
+///
+pub const AUDIT_TABLE: &str = "_pbase_audit";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditOp {
+    Insert,
+    Update,
+    // Soft or hard delete, see `PBase::run_delete_query`. Hard deletes record no new values.
+    Delete,
+}
+
+impl AuditOp {
+    #[must_use]
+    pub const fn code(self) -> u8 {
+        match self {
+            Self::Insert => 0,
+            Self::Update => 1,
+            Self::Delete => 2,
+        }
+    }
+}
+
+///
+/// A mutation to record, with the row before (None for inserts) and after it.
+///
+pub struct AuditEntry<'a> {
+    pub lsn: i32,
+    pub time: i32,
+    pub actor: i32,
+    pub op: AuditOp,
+    pub row_pos: TablePtrType,
+    pub old_row: Option<&'a HashMap<String, Value>>,
+    pub new_row: &'a HashMap<String, Value>,
+}
+
+#[must_use]
+pub fn audit_table_schema() -> TableSchema {
+    let fields: IndexMap<String, FieldSchema> = [
+        "lsn",
+        "time",
+        "actor",
+        "table_id",
+        "op",
+        "row_pos",
+        "column",
+        "old_value",
+        "new_value",
+    ]
+    .into_iter()
+    .map(|field_name| {
+        let field_schema = if field_name == "op" {
+            FieldSchema::U8
+        } else {
+            FieldSchema::I32
+        };
+        (field_name.to_string(), field_schema)
+    })
+    .collect();
+
+    TableSchema {
+        name: AUDIT_TABLE.to_string(),
+        fields,
+        ..Default::default()
+    }
+}
+
+///
+/// Id of a table in the audit table: the 32 bit FNV-1a hash of its name, so it stays the same as
+/// tables are added and dropped.
+///
+#[must_use]
+pub fn audit_table_id(table_name: &str) -> i32 {
+    let hash = table_name.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    });
+    i32::from_ne_bytes(hash.to_ne_bytes())
+}
+
+///
+/// Audit table rows of the mutation: one per column an insert set, or an update changed.
+///
+/// # Errors
+///
+/// When the row position does not fit the I32 column (tables past 2 GiB).
+pub fn audit_rows(
+    table_schema: &TableSchema,
+    entry: &AuditEntry,
+) -> Result<Vec<HashMap<String, Value>>, Error> {
+    let as_i32 = |value: Option<&Value>| match value {
+        Some(Value::I32(v)) => *v,
+        Some(Value::U8(v)) => i32::from(*v),
+        // Decimal and custom values do not fit the I32 columns of the audit table.
+        Some(Value::NULL | Value::Decimal(_) | Value::Custom(_)) | None => 0,
+    };
+    let row_pos = i32::try_from(entry.row_pos)?;
+
+    Ok(table_schema
+        .fields
+        .keys()
+        .zip(0..)
+        .filter(|(field_name, _)| {
+            entry
+                .old_row
+                .is_none_or(|old_row| old_row.get(*field_name) != entry.new_row.get(*field_name))
+        })
+        .map(|(field_name, column)| {
+            HashMap::from([
+                ("lsn".to_string(), Value::I32(entry.lsn)),
+                ("time".to_string(), Value::I32(entry.time)),
+                ("actor".to_string(), Value::I32(entry.actor)),
+                (
+                    "table_id".to_string(),
+                    Value::I32(audit_table_id(&table_schema.name)),
+                ),
+                ("op".to_string(), Value::U8(entry.op.code())),
+                ("row_pos".to_string(), Value::I32(row_pos)),
+                ("column".to_string(), Value::I32(column)),
+                (
+                    "old_value".to_string(),
+                    Value::I32(
+                        entry
+                            .old_row
+                            .map_or(0, |old_row| as_i32(old_row.get(field_name))),
+                    ),
+                ),
+                (
+                    "new_value".to_string(),
+                    Value::I32(as_i32(entry.new_row.get(field_name))),
+                ),
+            ])
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use indexmap::IndexMap;
+
+    use crate::{
+        schema::{FieldSchema, TableSchema},
+        value::Value,
+    };
+
+    use super::{audit_rows, audit_table_id, AuditEntry, AuditOp};
+
+    #[test]
+    fn test_audit_rows() {
+        let table_schema = TableSchema {
+            name: "t1".into(),
+            fields: IndexMap::from([
+                ("a".into(), FieldSchema::U8),
+                ("b".into(), FieldSchema::I32),
+            ]),
+            ..Default::default()
+        };
+        let old_row = HashMap::from([("a".into(), Value::U8(1)), ("b".into(), Value::I32(2))]);
+        let new_row = HashMap::from([("a".into(), Value::U8(1)), ("b".into(), Value::I32(-3))]);
+        let entry = |op, old_row| AuditEntry {
+            lsn: 7,
+            time: 100,
+            actor: 42,
+            op,
+            row_pos: 5,
+            old_row,
+            new_row: &new_row,
+        };
+
+        let inserted = audit_rows(&table_schema, &entry(AuditOp::Insert, None)).unwrap();
+        assert_eq!(2, inserted.len());
+        assert_eq!(Value::I32(0), inserted[0]["column"]);
+        assert_eq!(Value::I32(0), inserted[0]["old_value"]);
+        assert_eq!(Value::I32(1), inserted[0]["new_value"]);
+
+        let updated = audit_rows(&table_schema, &entry(AuditOp::Update, Some(&old_row))).unwrap();
+        assert_eq!(
+            vec![HashMap::from([
+                ("lsn".into(), Value::I32(7)),
+                ("time".into(), Value::I32(100)),
+                ("actor".into(), Value::I32(42)),
+                ("table_id".into(), Value::I32(audit_table_id("t1"))),
+                ("op".into(), Value::U8(1)),
+                ("row_pos".into(), Value::I32(5)),
+                ("column".into(), Value::I32(1)),
+                ("old_value".into(), Value::I32(2)),
+                ("new_value".into(), Value::I32(-3)),
+            ])],
+            updated
+        );
+    }
+
+    #[test]
+    fn test_audit_table_id() {
+        assert_eq!(audit_table_id("t1"), audit_table_id("t1"));
+        assert_ne!(audit_table_id("t1"), audit_table_id("t2"));
+        // FNV-1a offset basis.
+        assert_eq!(
+            i32::from_ne_bytes(0x811c_9dc5_u32.to_ne_bytes()),
+            audit_table_id("")
+        );
+    }
+}
@@ -4,7 +4,6 @@ use pbase::common::Error;
 use pbase::pbase::*;
 use pbase::query::*;
 use pbase::schema::*;
-use std::collections::HashMap;
 use std::path::PathBuf;
 
 fn main() -> Result<(), Error> {
@@ -22,10 +21,11 @@ fn main() -> Result<(), Error> {
                     ("field3".into(), FieldSchema::I32),
                     ("field4".into(), FieldSchema::I32),
                 ]),
-                indices: HashMap::from([(
+                indices: IndexMap::from([(
                     "field_1_and_2".into(),
                     vec!["field1".into(), "field2".into()],
                 )]),
+                ..Default::default()
             },
         };
 
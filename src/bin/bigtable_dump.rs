@@ -3,7 +3,8 @@ use std::{fs::File, io::Read, path::PathBuf};
 use anyhow::Context;
 use pbase::{
     common::Error,
-    schema::{TablePtrType, TableSchema, TABLE_PTR_BYTE_SIZE},
+    schema::{TablePtrType, TABLE_PTR_BYTE_SIZE},
+    table::Table,
     table_opener::TableOpener,
 };
 
@@ -13,36 +14,21 @@ fn main() -> Result<(), Error> {
     let table_opener = TableOpener::new(current_dir);
 
     // SCHEMA
-    let schema_file_name = table_opener.table_schema_file_name(table_name);
-    let schema_file = File::open(schema_file_name).context("Failed to open schema file")?;
-    let table_schema: TableSchema =
-        serde_json::from_reader(schema_file).context("Failed parsing schema")?;
+    let table = Table::open(
+        table_opener.table_schema_file_name(table_name),
+        table_opener.table_data_file_name(table_name),
+    )?;
+    let table_schema = table.schema();
 
-    dbg!(&table_schema);
+    dbg!(table_schema);
 
     // DATA
-    let data_file_name = table_opener.table_data_file_name(table_name);
-    let mut data_file = File::open(data_file_name).context("Cannot open data file")?;
-    let mut data_buf: Vec<u8> = vec![];
-    data_file
-        .read_to_end(&mut data_buf)
-        .context("Failed reading data")?;
-
-    let mut pos = 0usize;
-    let mut row_idx = 0usize;
-    while pos < data_buf.len() {
+    for (row_idx, row) in table.scan()?.iter().enumerate() {
         println!("Row #{}:", row_idx);
 
-        let mut field_pos = 0usize;
-        for (field_name, field_schema) in &table_schema.fields {
-            let value = field_schema.value_from_bytes(&data_buf[(pos + field_pos)..]);
+        for (field_name, value) in row {
             println!("\t{} = {:?}", field_name, value);
-
-            field_pos += field_schema.byte_size();
         }
-
-        pos += table_schema.row_byte_size();
-        row_idx += 1;
     }
 
     // INDICES
@@ -1,7 +1,7 @@
 use pbase::{
     common::Error,
     pbase::PBase,
-    query::{FieldSelector, RhsValue, RowFilter, SelectQuery},
+    query::{CompareOp, FieldSelector, RhsValue, RowFilter, SelectQuery},
     value::*,
 };
 use std::path::PathBuf;
@@ -19,9 +19,10 @@ fn main() -> Result<(), Error> {
                 name: "field1".to_string(),
                 source: "bigtable".to_string(),
             },
-            op: std::cmp::Ordering::Greater,
+            op: CompareOp::Gt,
             rhs: RhsValue::Value(Value::I32(0)),
         }],
+        ..Default::default()
     };
 
     let result = db.run_select_query(query)?;
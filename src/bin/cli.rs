@@ -1,41 +1,320 @@
-use pbase::{common::Error, lexer::Lexer, parser::Parser, pbase::PBase, query::Query};
+use pbase::{
+    common::{Error, PBaseError},
+    lexer::Lexer,
+    pbase::PBase,
+    progress::ConsoleProgress,
+    query::{Query, RowCap},
+    result_set::ResultSet,
+    session::{OutputFormat, Session},
+    statement::StatementBuffer,
+};
+use rustyline::{error::ReadlineError, DefaultEditor};
 use std::{
-    io::{self, stdout, Write},
+    io::{stdout, Write},
     path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
+// How long a new session waits for another one to release the writer lock.
+const WRITER_LOCK_TIMEOUT: Duration = Duration::from_secs(3);
+// Kept in the home directory, shared by the sessions of every database directory.
+const HISTORY_FILE_NAME: &str = ".pbase_history";
+
+///
+/// Usage: `cli [--read-only]`, on the current directory.
+///
+/// Statements end with `;` and may span lines. Lines are edited with the usual readline keys
+/// (ctrl-r searches the history). Ctrl-c drops the statement being typed, `exit` or ctrl-d ends
+/// the session.
+///
+/// Only one session can write a directory. A session started while another one writes waits for
+/// it a little, then opens the directory read-only. `--read-only` never takes the writer lock.
+///
 fn main() -> Result<(), Error> {
-    let mut buffer = String::new();
-    let stdin = io::stdin();
+    let dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::new());
+    let db = open_db(dir, std::env::args().any(|arg| arg == "--read-only"))?;
+    let mut session = Session::new();
 
-    let db = PBase::new(std::env::current_dir().unwrap_or_else(|_| PathBuf::new()));
+    let mut editor = DefaultEditor::new()?;
+    let history_file_name = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(HISTORY_FILE_NAME));
+    if let Some(history_file_name) = &history_file_name {
+        // There is no history before the first session.
+        let _ = editor.load_history(history_file_name);
+    }
 
-    loop {
-        stdout().write_all(b"> ")?;
-        stdout().flush()?;
+    // The history is saved however the session ends, failures included.
+    let result = read_statements(&db, &mut session, &mut editor);
+    if let Some(history_file_name) = &history_file_name {
+        editor.save_history(history_file_name)?;
+    }
 
-        buffer.clear();
-        stdin.read_line(&mut buffer)?;
+    result
+}
 
-        if buffer.trim() == "exit" {
-            break;
+//
+// Reads and runs statements until `exit` or ctrl-d. Failing statements print their error, only
+// failures of the terminal end the session.
+//
+fn read_statements(
+    db: &PBase,
+    session: &mut Session,
+    editor: &mut DefaultEditor,
+) -> Result<(), Error> {
+    let mut statements = StatementBuffer::new();
+    loop {
+        let prompt = if statements.is_pending() {
+            "... "
         } else {
-            let Ok(tokens) = Lexer::tokenize(buffer.as_bytes()) else {
-                stdout().write_all(b"Unrecognized characters")?;
+            "> "
+        };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                statements.clear();
                 continue;
-            };
-            let mut parser = Parser::new(&tokens[..]);
-            let query = parser.parse();
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into()),
+        };
+
+        if !statements.is_pending() && line.trim() == "exit" {
+            break;
+        }
+        let completed = statements.push_line(&line);
+        if !completed.is_empty() {
+            editor.add_history_entry(format!("{};", completed.join("; ")))?;
+        }
+        for statement in completed {
+            run_statement(db, session, &statement)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn run_statement(db: &PBase, session: &mut Session, statement: &str) -> Result<(), Error> {
+    let Ok(tokens) = Lexer::tokenize(statement.as_bytes()) else {
+        stdout().write_all(b"Unrecognized characters\n")?;
+        return Ok(());
+    };
+    let query = db.parse_statement(&tokens);
+    let start = Instant::now();
 
-            match query {
-                Ok(Query::Select(select_query)) => {
-                    let result = db.run_select_query(select_query)?;
-                    dbg!(result);
+    match query {
+        Ok(Query::Select(select_query)) if select_query.into.is_some() => {
+            // Read-only sessions reject it without leaving the CLI.
+            match db.run_select_into_query(select_query) {
+                Ok(inserted) => {
+                    stdout().write_fmt(format_args!("Inserted {inserted} rows\n"))?;
+                }
+                Err(err) => {
+                    stdout().write_fmt(format_args!("{err}\n"))?;
+                    return Ok(());
+                }
+            }
+        }
+        Ok(Query::Select(select_query)) => {
+            let (result, stats) =
+                match db.run_select_query_with_stats(session.limit_select(select_query)) {
+                    Ok(result) => result,
+                    Err(err) => {
+                        stdout().write_fmt(format_args!("{err}\n"))?;
+                        return Ok(());
+                    }
+                };
+            let truncated = result.truncated;
+            print_rows(result, session)?;
+            if truncated {
+                stdout().write_all(b"Warning: result truncated at the row cap\n")?;
+            }
+            // Reports the time on its own.
+            stdout().write_fmt(format_args!("{stats}\n"))?;
+            return Ok(());
+        }
+        Ok(Query::Union(union_query)) => {
+            let mut result = match db.run_union_query(union_query) {
+                Ok(result) => result,
+                Err(err) => {
+                    stdout().write_fmt(format_args!("{err}\n"))?;
+                    return Ok(());
+                }
+            };
+            if let Some(max_rows) = session.max_rows {
+                result.rows.truncate(max_rows);
+            }
+            print_rows(result, session)?;
+        }
+        Ok(Query::Set(set_query)) => {
+            // Database settings first, the rest are the session's.
+            let result = match db.set(&set_query) {
+                Err(err)
+                    if matches!(
+                        err.downcast_ref::<PBaseError>(),
+                        Some(PBaseError::UnknownSetting(_))
+                    ) =>
+                {
+                    session.set(&set_query)
+                }
+                result => result,
+            };
+            if let Err(err) = result {
+                stdout().write_fmt(format_args!("{err}\n"))?;
+            }
+            return Ok(());
+        }
+        Ok(Query::Explain(explain_query)) => {
+            let plan = if explain_query.analyze {
+                db.explain_analyze_select_query(explain_query.select)
+            } else {
+                db.explain_select_query(explain_query.select)
+            };
+            let plan = match plan {
+                Ok(plan) => plan,
+                Err(err) => {
+                    stdout().write_fmt(format_args!("{err}\n"))?;
+                    return Ok(());
+                }
+            };
+            stdout().write_all(plan.to_ascii_tree().as_bytes())?;
+        }
+        Ok(Query::Analyze(analyze_query)) => {
+            let stats = match db.analyze_table(&analyze_query.table) {
+                Ok(stats) => stats,
+                Err(err) => {
+                    stdout().write_fmt(format_args!("{err}\n"))?;
+                    return Ok(());
+                }
+            };
+            stdout().write_fmt(format_args!("Rows: {}\n", stats.row_count))?;
+            for (field_name, histogram) in &stats.histograms {
+                stdout().write_fmt(format_args!(
+                    "{field_name}: {} buckets\n",
+                    histogram.buckets.len()
+                ))?;
+            }
+        }
+        Ok(Query::Insert(insert_statement)) => match insert_statement.returning {
+            Some(returning) => {
+                match db.run_insert_query_returning(&insert_statement.query, &returning) {
+                    Ok(inserted) => print_rows(inserted.result, session)?,
+                    Err(err) => stdout().write_fmt(format_args!("{err}\n"))?,
                 }
-                Ok(_) => unimplemented!(),
+            }
+            None => match db.run_insert_query(&insert_statement.query) {
+                Ok(inserted) => stdout().write_fmt(format_args!("Inserted {inserted} row\n"))?,
+                Err(err) => stdout().write_fmt(format_args!("{err}\n"))?,
+            },
+        },
+        Ok(Query::Delete(delete_query)) => match db.run_delete_query(&delete_query) {
+            Ok(result) => stdout().write_fmt(format_args!("{result}\n"))?,
+            Err(err) => stdout().write_fmt(format_args!("{err}\n"))?,
+        },
+        Ok(Query::Describe(describe_query)) => {
+            let columns = match db.describe_table(&describe_query.table) {
+                Ok(columns) => columns,
                 Err(err) => {
-                    stdout().write_fmt(format_args!("Unrecognized query. Error: {:?}\n\n", err))?
+                    stdout().write_fmt(format_args!("{err}\n"))?;
+                    return Ok(());
                 }
+            };
+            for column in columns {
+                stdout().write_fmt(format_args!("{}: {:?}", column.name, column.field_schema))?;
+                match column.stats {
+                    Some(stats) => stdout().write_fmt(format_args!(
+                        ", min {}, max {}, {} nulls, ~{} distinct\n",
+                        stats.min, stats.max, stats.null_count, stats.distinct_estimate
+                    ))?,
+                    None => stdout().write_all(b" (not analyzed)\n")?,
+                }
+            }
+        }
+        Ok(Query::CreateTable(create_table_query)) => {
+            match db.run_create_table_query(&create_table_query) {
+                Ok(()) => stdout().write_all(b"Table created\n")?,
+                Err(err) => stdout().write_fmt(format_args!("{err}\n"))?,
+            }
+        }
+        Ok(Query::Attach(attach_query)) => {
+            match db.attach(&attach_query.path, &attach_query.alias) {
+                Ok(()) => stdout().write_all(b"Database attached\n")?,
+                Err(err) => stdout().write_fmt(format_args!("{err}\n"))?,
+            }
+        }
+        Err(err) => {
+            stdout().write_fmt(format_args!("Unrecognized query. Error: {:?}\n\n", err))?;
+            return Ok(());
+        }
+    }
+
+    if session.timing {
+        stdout().write_fmt(format_args!("Time: {:?}\n", start.elapsed()))?;
+    }
+
+    Ok(())
+}
+
+fn open_db(dir: PathBuf, read_only: bool) -> Result<PBase, Error> {
+    // Runaway selects show their first rows rather than end the session.
+    let mut db = PBase::new(dir.clone())
+        .with_progress_reporter(Arc::new(ConsoleProgress::new()))
+        .with_row_cap(RowCap {
+            truncate: true,
+            ..RowCap::default()
+        });
+    if read_only {
+        return Ok(db.with_read_only());
+    }
+
+    if !db.try_lock_writer(Duration::ZERO)? {
+        println!(
+            "Another session is writing {}, waiting for it...",
+            dir.display()
+        );
+        if !db.try_lock_writer(WRITER_LOCK_TIMEOUT)? {
+            println!("The directory is still being written, opened it read-only.");
+            return Ok(db.with_read_only());
+        }
+    }
+
+    Ok(db)
+}
+
+fn print_rows(result: ResultSet, session: &Session) -> Result<(), Error> {
+    match session.output {
+        OutputFormat::Debug => {
+            dbg!(result.rows);
+        }
+        OutputFormat::Nested => {
+            dbg!(result.nested_rows());
+        }
+        OutputFormat::Csv => {
+            let mut out = stdout();
+            writeln!(
+                out,
+                "{}",
+                result
+                    .columns
+                    .iter()
+                    .map(|column| column.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            )?;
+            for row in &result.rows {
+                writeln!(
+                    out,
+                    "{}",
+                    result
+                        .columns
+                        .iter()
+                        .map(|column| row
+                            .get(column.name.as_str())
+                            .map_or_else(String::new, ToString::to_string))
+                        .collect::<Vec<_>>()
+                        .join(",")
+                )?;
             }
         }
     }
@@ -0,0 +1,296 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use indexmap::IndexMap;
+use pbase::{
+    common::Error,
+    copy::{CopyHeader, CopyWriter},
+    lexer::Lexer,
+    pbase::PBase,
+    query::{
+        CompareOp, CreateTableQuery, FieldSelector, InsertQuery, JoinContract, JoinType, Query,
+        RhsValue, RowFilter, SelectQuery,
+    },
+    schema::{FieldSchema, TableSchema},
+    value::Value,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const CUSTOMERS: i32 = 1_000;
+const ORDERS: i32 = 5_000;
+const ITEMS: i32 = 20_000;
+const REGIONS: u8 = 8;
+const ORDER_STATUSES: u8 = 4;
+// The dataset is the same on every run.
+const SEED: u64 = 2024;
+
+///
+/// Usage: `pbase-demo [DIR]`, in a temporary directory (removed at the end) without DIR.
+///
+/// A tour of pbase on a small shop database: creates customers, orders and order items with
+/// indices, bulk loads generated rows, then runs representative queries (point lookups, index
+/// scans, joins, samples, SQL statements) and prints their row counts and timings. DIR must not
+/// have tables of the same names.
+///
+fn main() -> Result<(), Error> {
+    let db = match std::env::args().nth(1) {
+        Some(dir) => PBase::new(PathBuf::from(dir)),
+        None => PBase::new_temp()?,
+    };
+    println!("Database: {}\n", db.dir().display());
+
+    let start = Instant::now();
+    create_tables(&db)?;
+    load_rows(&db)?;
+    for table in ["customers", "orders", "items"] {
+        db.analyze_table(table)?;
+    }
+    println!("Loaded the dataset in {:?}\n", start.elapsed());
+
+    run_queries(&db)?;
+
+    Ok(())
+}
+
+fn create_tables(db: &PBase) -> Result<(), Error> {
+    for schema in shop_schemas() {
+        if db.is_table_exist(&schema.name) {
+            return Err(format!(
+                "{} already has a {} table, run the demo in another directory",
+                db.dir().display(),
+                schema.name
+            )
+            .into());
+        }
+        db.run_create_table_query(&CreateTableQuery { schema })?;
+    }
+
+    Ok(())
+}
+
+fn shop_schemas() -> Vec<TableSchema> {
+    vec![
+        TableSchema {
+            name: "customers".into(),
+            fields: IndexMap::from([
+                ("id".into(), FieldSchema::I32),
+                ("region".into(), FieldSchema::U8),
+            ]),
+            indices: IndexMap::from([("id_index".into(), vec!["id".into()])]),
+            unique_indices: vec!["id_index".into()],
+            ..Default::default()
+        },
+        TableSchema {
+            name: "orders".into(),
+            fields: IndexMap::from([
+                ("id".into(), FieldSchema::I32),
+                ("customer_id".into(), FieldSchema::I32),
+                ("status".into(), FieldSchema::U8),
+            ]),
+            indices: IndexMap::from([
+                ("id_index".into(), vec!["id".into()]),
+                ("customer_index".into(), vec!["customer_id".into()]),
+            ]),
+            unique_indices: vec!["id_index".into()],
+            ..Default::default()
+        },
+        TableSchema {
+            name: "items".into(),
+            fields: IndexMap::from([
+                ("id".into(), FieldSchema::I32),
+                ("order_id".into(), FieldSchema::I32),
+                ("quantity".into(), FieldSchema::U8),
+                ("price".into(), FieldSchema::I32),
+            ]),
+            indices: IndexMap::from([("order_index".into(), vec!["order_id".into()])]),
+            ..Default::default()
+        },
+    ]
+}
+
+//
+// Generates the rows and bulk loads them with copy streams, the way to load more than a few rows.
+//
+fn load_rows(db: &PBase) -> Result<(), Error> {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let schemas = shop_schemas();
+
+    let customers: Vec<HashMap<String, Value>> = (0..CUSTOMERS)
+        .map(|id| {
+            HashMap::from([
+                ("id".into(), Value::I32(id)),
+                ("region".into(), Value::U8(rng.random_range(0..REGIONS))),
+            ])
+        })
+        .collect();
+    let orders: Vec<HashMap<String, Value>> = (0..ORDERS)
+        .map(|id| {
+            HashMap::from([
+                ("id".into(), Value::I32(id)),
+                (
+                    "customer_id".into(),
+                    Value::I32(rng.random_range(0..CUSTOMERS)),
+                ),
+                (
+                    "status".into(),
+                    Value::U8(rng.random_range(0..ORDER_STATUSES)),
+                ),
+            ])
+        })
+        .collect();
+    let items: Vec<HashMap<String, Value>> = (0..ITEMS)
+        .map(|id| {
+            HashMap::from([
+                ("id".into(), Value::I32(id)),
+                ("order_id".into(), Value::I32(rng.random_range(0..ORDERS))),
+                ("quantity".into(), Value::U8(rng.random_range(1..10))),
+                ("price".into(), Value::I32(rng.random_range(100..10_000))),
+            ])
+        })
+        .collect();
+
+    for (schema, rows) in schemas.iter().zip([customers, orders, items]) {
+        let mut writer = CopyWriter::new(vec![], CopyHeader::new(schema, rows.len()))?;
+        for row in &rows {
+            writer.write_row_bytes(&schema.data_row_to_bytes(row))?;
+        }
+        let loaded = db.copy_rows(&schema.name, &writer.finish()?[..])?;
+        println!("{:>10}: {loaded} rows", schema.name);
+    }
+
+    Ok(())
+}
+
+fn run_queries(db: &PBase) -> Result<(), Error> {
+    println!("{:<44} {:>8} {:>12}", "Query", "Rows", "Time");
+
+    timed("Point lookup of a customer by id", || {
+        Ok(db.run_select_query(customer_by_id(42))?.len())
+    })?;
+    timed("Same lookup, from the row cache", || {
+        Ok(db.run_select_query(customer_by_id(42))?.len())
+    })?;
+    timed("Orders of a customer (index scan)", || {
+        Ok(db.run_select_query(orders_of_customer(42))?.len())
+    })?;
+    timed("Items of the orders of a customer (join)", || {
+        Ok(db.run_select_query(items_of_customer(42))?.len())
+    })?;
+    timed("Open orders in full (scan)", || {
+        Ok(db
+            .run_select_query(SelectQuery {
+                from: "orders".into(),
+                filters: vec![filter("orders", "status", Value::U8(0))],
+                ..Default::default()
+            })?
+            .len())
+    })?;
+    timed("SQL: SELECT FROM items TABLESAMPLE 10", || {
+        run_sql(db, "SELECT FROM items TABLESAMPLE 10")
+    })?;
+    timed("SQL: same shape, from the plan cache", || {
+        run_sql(db, "SELECT FROM items TABLESAMPLE 20")
+    })?;
+    timed("Insert an order", || {
+        db.run_insert_query(&InsertQuery {
+            table: "orders".into(),
+            values: HashMap::from([
+                ("id".into(), Value::I32(ORDERS)),
+                ("customer_id".into(), Value::I32(42)),
+                ("status".into(), Value::U8(0)),
+            ]),
+        })
+    })?;
+
+    println!("\nPlan of the join:");
+    print!(
+        "{}",
+        db.explain_select_query(items_of_customer(42))?
+            .to_ascii_tree()
+    );
+
+    let stats = db.plan_cache().stats();
+    println!(
+        "\nPlan cache: {} statement hits, {} plan hits",
+        stats.statement_hits, stats.plan_hits
+    );
+
+    Ok(())
+}
+
+// Runs the query and prints its row count and timing.
+fn timed<F>(name: &str, f: F) -> Result<(), Error>
+where
+    F: FnOnce() -> Result<usize, Error>,
+{
+    let start = Instant::now();
+    let rows = f()?;
+    println!(
+        "{name:<44} {rows:>8} {:>12}",
+        format_duration(start.elapsed())
+    );
+
+    Ok(())
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.3} ms", duration.as_secs_f64() * 1000.0)
+}
+
+fn run_sql(db: &PBase, statement: &str) -> Result<usize, Error> {
+    let Query::Select(select_query) =
+        db.parse_statement(&Lexer::tokenize(statement.as_bytes())?)?
+    else {
+        return Err(format!("not a select: {statement}").into());
+    };
+
+    Ok(db.run_select_query(select_query)?.len())
+}
+
+fn filter(table: &str, field: &str, value: Value) -> RowFilter {
+    RowFilter {
+        field: FieldSelector {
+            name: field.into(),
+            source: table.into(),
+        },
+        op: CompareOp::Eq,
+        rhs: RhsValue::Value(value),
+    }
+}
+
+fn customer_by_id(id: i32) -> SelectQuery {
+    SelectQuery {
+        from: "customers".into(),
+        filters: vec![filter("customers", "id", Value::I32(id))],
+        ..Default::default()
+    }
+}
+
+fn orders_of_customer(customer_id: i32) -> SelectQuery {
+    SelectQuery {
+        from: "orders".into(),
+        filters: vec![filter("orders", "customer_id", Value::I32(customer_id))],
+        ..Default::default()
+    }
+}
+
+fn items_of_customer(customer_id: i32) -> SelectQuery {
+    SelectQuery {
+        joins: vec![JoinContract {
+            join_type: JoinType::Inner,
+            null_keys_match: false,
+            lhs: FieldSelector {
+                name: "id".into(),
+                source: "orders".into(),
+            },
+            rhs: FieldSelector {
+                name: "order_id".into(),
+                source: "items".into(),
+            },
+        }],
+        ..orders_of_customer(customer_id)
+    }
+}
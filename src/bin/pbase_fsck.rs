@@ -0,0 +1,26 @@
+use std::{io::stdout, path::PathBuf, process::ExitCode, sync::Arc};
+
+use pbase::{common::Error, pbase::PBase, progress::ConsoleProgress};
+
+///
+/// Usage: `pbase-fsck [DIR]` (defaults to the current directory).
+/// Prints a JSON report and exits with 1 when any inconsistency was found.
+///
+fn main() -> Result<ExitCode, Error> {
+    let dir = std::env::args().nth(1).map_or_else(
+        || std::env::current_dir().unwrap_or_else(|_| PathBuf::new()),
+        PathBuf::from,
+    );
+
+    let report = PBase::new(dir)
+        .with_progress_reporter(Arc::new(ConsoleProgress::new()))
+        .check_all()?;
+    serde_json::to_writer_pretty(stdout(), &report)?;
+    println!();
+
+    Ok(if report.is_ok() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
@@ -20,7 +20,8 @@ fn main() -> Result<(), Error> {
             schema: TableSchema {
                 name: "example".into(),
                 fields: IndexMap::from([("value".into(), FieldSchema::I32)]),
-                indices: HashMap::new(),
+                indices: IndexMap::new(),
+                ..Default::default()
             },
         };
 
@@ -43,7 +44,7 @@ fn main() -> Result<(), Error> {
         from: "example".into(),
         joins: vec![],
         filters: vec![],
-        // limit: None,
+        ..Default::default()
     };
     let rows = db.run_select_query(select_query)?;
     dbg!(rows);
@@ -1,4 +1,4 @@
-use std::{cmp::Ordering, fs};
+use std::{cmp::Ordering, fmt::Display, fs};
 
 use thiserror;
 
@@ -16,6 +16,180 @@ pub enum PBaseError {
     NoMoreTokens,
     #[error("Unexpected token at parsing: {0}")]
     UnexpextedToken(String),
+    #[error("Incompatible select results: {0}")]
+    IncompatibleSelects(String),
+    #[error("Table {table} read as {len} bytes, but {committed_len} bytes were committed")]
+    StaleTableRead {
+        table: String,
+        len: usize,
+        committed_len: usize,
+    },
+    #[error("Unknown field: {0}")]
+    UnknownField(String),
+    #[error("No row starts at position {0}")]
+    InvalidRowPosition(u64),
+    #[error("Index {index} has no entry for row {row_ptr}")]
+    MissingIndexEntry { index: String, row_ptr: u64 },
+    #[error("Unsupported sharded query: {0}")]
+    UnsupportedShardedQuery(String),
+    #[error("WAL record {lsn} does not follow the last applied record (expected {expected_lsn})")]
+    WalGap { expected_lsn: u64, lsn: u64 },
+    #[error("Table name is reserved for a system table: {0}")]
+    ReservedTableName(String),
+    #[error("Unknown setting: {0}")]
+    UnknownSetting(String),
+    #[error("Invalid value for setting {name}: {value}")]
+    InvalidSettingValue { name: String, value: String },
+    #[error("Unsupported query for row views: {0}")]
+    UnsupportedRowViewQuery(String),
+    #[error("Cannot insert into indexed table {0} without its database")]
+    IndexedStandaloneInsert(String),
+    #[error("Missing field: {0}")]
+    MissingField(String),
+    #[error("Value {value} does not fit field {field}")]
+    FieldTypeMismatch { field: String, value: String },
+    #[error("Row of table {table} belongs to another tenant: {tenant_id}")]
+    TenantMismatch { table: String, tenant_id: String },
+    #[error("Read of {len} bytes at {pos} is past the end of the file ({file_len} bytes)")]
+    ReadOutOfBounds {
+        pos: usize,
+        len: usize,
+        file_len: usize,
+    },
+    #[error("Numeric overflow: {0}")]
+    NumericOverflow(String),
+    #[error("Unknown function: {0}")]
+    UnknownFunction(String),
+    #[error("Unknown type: {0}")]
+    UnknownType(String),
+    #[error("Invalid table archive: {0}")]
+    InvalidArchive(String),
+    #[error("Invalid copy stream: {0}")]
+    InvalidCopyStream(String),
+    #[error("Table already exists: {0}")]
+    TableAlreadyExists(String),
+    #[error("Unsupported external table file: {0}")]
+    UnsupportedExternalFile(String),
+    #[error("Unsupported type {column_type} of external table column {column}")]
+    UnsupportedExternalColumn { column: String, column_type: String },
+    #[error("Table is not a key-value table: {0}")]
+    NotAKvTable(String),
+    #[error("Table is not a time series partition: {0}")]
+    NotATimeSeriesTable(String),
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+    #[error("Duplicate column: {0}")]
+    DuplicateColumn(String),
+    #[error(
+        "Table {0} is read more than once by the query, its columns would have the same names \
+         (read it under aliases)"
+    )]
+    AmbiguousTable(String),
+    #[error("Alias {0} cannot contain '.', which is reserved for table.field columns")]
+    InvalidAlias(String),
+    #[error("Index already exists: {0}")]
+    IndexAlreadyExists(String),
+    #[error("Duplicate key {key} of unique index {index}")]
+    DuplicateKey { index: String, key: String },
+    #[error("Name cannot be used for files on every platform: {0}")]
+    InvalidFileName(String),
+    #[error("Database is opened read-only")]
+    ReadOnly,
+    #[error("A database is already attached as {0}")]
+    DatabaseAlreadyAttached(String),
+    #[error("Table of an attached database is read-only: {0}")]
+    AttachedTableWrite(String),
+    #[error("Index {index} of table {table} is corrupt: {reason}")]
+    CorruptIndex {
+        table: String,
+        index: String,
+        reason: String,
+    },
+    #[error("Table {table} would exceed its quota of {limit} rows")]
+    RowCountQuotaExceeded { table: String, limit: usize },
+    #[error("Table {table} would exceed its quota of {limit} bytes")]
+    TableSizeQuotaExceeded { table: String, limit: u64 },
+    #[error("Unsupported storage option: {0}")]
+    UnsupportedStorageOption(String),
+    #[error("Table {table} would exceed the {limit} bytes its row pointers address")]
+    RowPointerOverflow { table: String, limit: u64 },
+    #[error(
+        "Schema of table {0} does not match the layout of its data, were its fields edited \
+         without migrating the rows? Restore the previous schema file, or create a table with \
+         the new fields and insert the rows into it"
+    )]
+    SchemaLayoutMismatch(String),
+    #[error("Database directory would exceed its quota of {limit} bytes")]
+    DirectorySizeQuotaExceeded { limit: u64 },
+    #[error("No pooled connection was released within {0:?}")]
+    PoolExhausted(std::time::Duration),
+    #[error("Row of table {table} is at version {actual}, the update expected {expected}")]
+    VersionConflict {
+        table: String,
+        expected: i32,
+        actual: i32,
+    },
+    #[error("Lock on table {table} was not granted within {timeout:?}")]
+    LockTimeout {
+        table: String,
+        timeout: std::time::Duration,
+    },
+    #[error("Transaction {transaction} waiting for table {table} would deadlock")]
+    Deadlock { table: String, transaction: u64 },
+    #[error(
+        "Table {table} was written by another handle since this one saw it (generation {seen}, \
+         now {generation}), refresh the handle before writing"
+    )]
+    StaleHandle {
+        table: String,
+        seen: u64,
+        generation: u64,
+    },
+    #[error("Invalid generation file of table {0}")]
+    InvalidGeneration(String),
+    #[error("Select returns more than {0} rows, the row cap (add a limit, or raise the cap)")]
+    RowCapExceeded(usize),
+    #[error("Fields {lhs} and {rhs} have types that cannot be compared")]
+    IncomparableFields { lhs: String, rhs: String },
+    #[error("{context}: {source}")]
+    QueryContext {
+        context: String,
+        #[source]
+        source: Error,
+    },
+}
+
+///
+/// Adds the part of the query being run to the errors of a result.
+///
+/// Errors of deep executor code then tell which fragment they failed on, eg. `filter t1.f1 = 3
+/// of select from t1: ...`. The original error is the source of the `PBaseError::QueryContext`
+/// it is wrapped in.
+///
+pub trait QueryErrorContext<T> {
+    /// # Errors
+    ///
+    /// The error of the result, in context.
+    fn query_context<C, F>(self, context: F) -> Result<T, Error>
+    where
+        C: Display,
+        F: FnOnce() -> C;
+}
+
+impl<T, E: Into<Error>> QueryErrorContext<T> for Result<T, E> {
+    fn query_context<C, F>(self, context: F) -> Result<T, Error>
+    where
+        C: Display,
+        F: FnOnce() -> C,
+    {
+        self.map_err(|source| {
+            PBaseError::QueryContext {
+                context: context().to_string(),
+                source: source.into(),
+            }
+            .into()
+        })
+    }
 }
 
 ///
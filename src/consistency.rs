@@ -0,0 +1,310 @@
+use std::{collections::HashSet, fs};
+
+use serde::Serialize;
+
+use crate::{
+    common::Error,
+    progress::{NoProgress, ProgressReporter},
+    schema::{TablePtrType, TableSchema},
+    table_opener::TableOpener,
+    value::Value,
+};
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ConsistencyIssue {
+    SchemaUnreadable {
+        error: String,
+    },
+    DataFileUnreadable {
+        error: String,
+    },
+    DataSizeNotRowMultiple {
+        data_byte_size: usize,
+        row_byte_size: usize,
+    },
+    IndexFileUnreadable {
+        index: String,
+        error: String,
+    },
+    IndexSizeNotRowMultiple {
+        index: String,
+        index_byte_size: usize,
+        index_row_byte_size: usize,
+    },
+    IndexRowCountMismatch {
+        index: String,
+        index_rows: usize,
+        table_rows: usize,
+    },
+    InvalidRowPointer {
+        index: String,
+        index_row: usize,
+        row_ptr: TablePtrType,
+    },
+    DuplicateRowPointer {
+        index: String,
+        index_row: usize,
+        row_ptr: TablePtrType,
+    },
+    // The indexed values differ from the values of the pointed row.
+    IndexValueMismatch {
+        index: String,
+        index_row: usize,
+        row_ptr: TablePtrType,
+    },
+    // The index row is smaller than the previous one.
+    IndexNotSorted {
+        index: String,
+        index_row: usize,
+    },
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct TableReport {
+    pub table: String,
+    pub rows: usize,
+    pub issues: Vec<ConsistencyIssue>,
+}
+
+///
+/// Result of checking every table of a database directory. Serializes to JSON for tooling.
+///
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct ConsistencyReport {
+    pub tables: Vec<TableReport>,
+}
+
+impl ConsistencyReport {
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.tables.iter().all(|table| table.issues.is_empty())
+    }
+}
+
+pub struct ConsistencyChecker<'a> {
+    table_opener: &'a TableOpener,
+    progress: &'a dyn ProgressReporter,
+}
+
+impl<'a> ConsistencyChecker<'a> {
+    #[must_use]
+    pub const fn new(table_opener: &'a TableOpener) -> Self {
+        Self {
+            table_opener,
+            progress: &NoProgress,
+        }
+    }
+
+    ///
+    /// Reports the rows checked, table by table.
+    ///
+    #[must_use]
+    pub const fn with_progress(mut self, progress: &'a dyn ProgressReporter) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// # Errors
+    ///
+    /// Errors when the directory cannot be listed. Problems of individual tables are reported, not
+    /// returned as errors.
+    pub fn check_all(&self) -> Result<ConsistencyReport, Error> {
+        // Table sizes are only known once their schemas are read.
+        self.progress.start("consistency check", None);
+        let mut checked_rows = 0;
+        let tables = self
+            .table_opener
+            .table_names()?
+            .into_iter()
+            .map(|table_name| {
+                let report = self.check_table(table_name);
+                checked_rows += report.rows;
+                self.progress.advance(checked_rows);
+                report
+            })
+            .collect();
+        self.progress.finish();
+
+        Ok(ConsistencyReport { tables })
+    }
+
+    fn check_table(&self, table: String) -> TableReport {
+        let mut report = TableReport {
+            table,
+            rows: 0,
+            issues: vec![],
+        };
+
+        let table_schema = match self.table_opener.open_schema(&report.table) {
+            Ok(table_schema) => table_schema,
+            Err(err) => {
+                report.issues.push(ConsistencyIssue::SchemaUnreadable {
+                    error: err.to_string(),
+                });
+                return report;
+            }
+        };
+
+        let table_bytes = match fs::read(self.table_opener.table_data_file_name(&report.table)) {
+            Ok(table_bytes) => table_bytes,
+            Err(err) => {
+                report.issues.push(ConsistencyIssue::DataFileUnreadable {
+                    error: err.to_string(),
+                });
+                return report;
+            }
+        };
+
+        let row_byte_size = table_schema.row_byte_size();
+        report.rows = table_bytes.len() / row_byte_size;
+        if table_bytes.len() % row_byte_size != 0 {
+            report
+                .issues
+                .push(ConsistencyIssue::DataSizeNotRowMultiple {
+                    data_byte_size: table_bytes.len(),
+                    row_byte_size,
+                });
+        }
+
+        for index_name in table_schema.indices.keys() {
+            check_index(
+                self.table_opener,
+                &table_schema,
+                index_name,
+                &table_bytes,
+                &mut report,
+            );
+        }
+
+        report
+    }
+}
+
+// Sorted part and delta of the index.
+fn read_index_files(
+    table_opener: &TableOpener,
+    table_schema: &TableSchema,
+    index_name: &str,
+) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    Ok((
+        fs::read(table_opener.index_file_name(&table_schema.name, index_name))?,
+        table_opener.index_delta_bytes(table_schema, index_name)?,
+    ))
+}
+
+fn check_index(
+    table_opener: &TableOpener,
+    table_schema: &TableSchema,
+    index_name: &str,
+    table_bytes: &[u8],
+    report: &mut TableReport,
+) {
+    let index_file_name = table_opener.index_file_name(&table_schema.name, index_name);
+    // Index files are created by the first insert.
+    if report.rows == 0 && !index_file_name.exists() {
+        return;
+    }
+
+    let (index_bytes, index_delta_bytes) =
+        match read_index_files(table_opener, table_schema, index_name) {
+            Ok(index_files_bytes) => index_files_bytes,
+            Err(err) => {
+                report.issues.push(ConsistencyIssue::IndexFileUnreadable {
+                    index: index_name.to_string(),
+                    error: err.to_string(),
+                });
+                return;
+            }
+        };
+
+    let index_row_byte_size = table_schema.index_row_byte_size(index_name);
+    for bytes in [&index_bytes, &index_delta_bytes] {
+        if bytes.len() % index_row_byte_size != 0 {
+            report
+                .issues
+                .push(ConsistencyIssue::IndexSizeNotRowMultiple {
+                    index: index_name.to_string(),
+                    index_byte_size: bytes.len(),
+                    index_row_byte_size,
+                });
+        }
+    }
+
+    let index_rows =
+        index_bytes.len() / index_row_byte_size + index_delta_bytes.len() / index_row_byte_size;
+    if index_rows != report.rows {
+        report.issues.push(ConsistencyIssue::IndexRowCountMismatch {
+            index: index_name.to_string(),
+            index_rows,
+            table_rows: report.rows,
+        });
+    }
+
+    let row_byte_size = table_schema.row_byte_size();
+    let ptr_byte_pos = table_schema.index_row_ptr_field_byte_pos(index_name);
+    let index_fields = &table_schema.indices[index_name];
+
+    let mut seen_row_ptrs = HashSet::new();
+    let mut prev_values: Option<Vec<Value>> = None;
+    // Delta rows are numbered after the sorted ones, and are not expected in order.
+    let sorted_rows = index_bytes
+        .chunks_exact(index_row_byte_size)
+        .map(|index_row_bytes| (index_row_bytes, true));
+    let delta_rows = index_delta_bytes
+        .chunks_exact(index_row_byte_size)
+        .map(|index_row_bytes| (index_row_bytes, false));
+    for (index_row, (index_row_bytes, sorted)) in sorted_rows.chain(delta_rows).enumerate() {
+        let values: Vec<Value> = index_fields
+            .iter()
+            .map(|index_field| {
+                table_schema.fields[index_field].value_from_bytes(
+                    &index_row_bytes[table_schema.index_field_byte_pos(index_name, index_field)..],
+                )
+            })
+            .collect();
+        if sorted && prev_values.as_ref().is_some_and(|prev| prev > &values) {
+            report.issues.push(ConsistencyIssue::IndexNotSorted {
+                index: index_name.to_string(),
+                index_row,
+            });
+        }
+
+        let row_ptr = table_schema.index_row_ptr_from_bytes(&index_row_bytes[ptr_byte_pos..]);
+        let row_pos = usize::try_from(row_ptr)
+            .ok()
+            .filter(|row_pos| row_pos % row_byte_size == 0)
+            .filter(|row_pos| row_pos + row_byte_size <= table_bytes.len());
+
+        if let Some(row_pos) = row_pos {
+            if !seen_row_ptrs.insert(row_ptr) {
+                report.issues.push(ConsistencyIssue::DuplicateRowPointer {
+                    index: index_name.to_string(),
+                    index_row,
+                    row_ptr,
+                });
+            }
+
+            let row = table_schema.parse_row_bytes(&table_bytes[row_pos..row_pos + row_byte_size]);
+            if index_fields
+                .iter()
+                .zip(&values)
+                .any(|(index_field, value)| &row[index_field] != value)
+            {
+                report.issues.push(ConsistencyIssue::IndexValueMismatch {
+                    index: index_name.to_string(),
+                    index_row,
+                    row_ptr,
+                });
+            }
+        } else {
+            report.issues.push(ConsistencyIssue::InvalidRowPointer {
+                index: index_name.to_string(),
+                index_row,
+                row_ptr,
+            });
+        }
+
+        prev_values = Some(values);
+    }
+}
@@ -0,0 +1,233 @@
+use std::io::{BufRead, Write};
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::{Error, PBaseError},
+    schema::{FieldSchema, TableSchema},
+};
+
+pub const COPY_FORMAT: &str = "pbase-copy";
+// Highest copy stream version this build reads.
+pub const COPY_VERSION: u32 = 1;
+
+///
+/// First line of a copy stream (see `CopyWriter`): the layout of the rows that follow.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CopyHeader {
+    pub format: String,
+    pub version: u32,
+    pub fields: IndexMap<String, FieldSchema>,
+    pub row_count: usize,
+}
+
+impl CopyHeader {
+    #[must_use]
+    pub fn new(table_schema: &TableSchema, row_count: usize) -> Self {
+        Self {
+            format: COPY_FORMAT.to_string(),
+            version: COPY_VERSION,
+            fields: table_schema.fields.clone(),
+            row_count,
+        }
+    }
+
+    #[must_use]
+    pub fn row_byte_size(&self) -> usize {
+        self.fields.values().map(FieldSchema::byte_size).sum()
+    }
+    ///
+    /// Fails unless the rows are laid out as the table stores them: same fields, in the same
+    /// order, of the same types. Fields with a default expression (see `TableSchema::defaults`)
+    /// may be left out, they get their default on load.
+    ///
+    /// # Errors
+    ///
+    /// With `PBaseError::InvalidCopyStream` on a mismatch.
+    pub fn check_table(&self, table_schema: &TableSchema) -> Result<(), Error> {
+        let table_fields = table_schema.fields.iter().filter(|(field_name, _)| {
+            self.fields.contains_key(*field_name)
+                || !table_schema.defaults.contains_key(*field_name)
+        });
+        // Compared in order: maps of the same fields are equal in any order.
+        if !self.fields.iter().eq(table_fields) {
+            return Err(PBaseError::InvalidCopyStream(format!(
+                "fields {:?} do not match table {} {:?}",
+                self.fields, table_schema.name, table_schema.fields
+            ))
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+///
+/// Writes a copy stream for `PBase::copy_rows`: the JSON header line, then the rows as a table
+/// without dictionary encoded fields stores them (see `TableSchema::without_dictionaries`), back
+/// to back.
+///
+/// Rows are not converted or checked beyond their length, which makes loading them close to
+/// appending bytes; the producer is responsible for the encoding.
+///
+pub struct CopyWriter<W: Write> {
+    writer: W,
+    header: CopyHeader,
+    rows_written: usize,
+}
+
+impl<W: Write> CopyWriter<W> {
+    /// # Errors
+    ///
+    /// On write errors.
+    pub fn new(mut writer: W, header: CopyHeader) -> Result<Self, Error> {
+        serde_json::to_writer(&mut writer, &header)?;
+        writer.write_all(b"\n")?;
+
+        Ok(Self {
+            writer,
+            header,
+            rows_written: 0,
+        })
+    }
+
+    /// # Errors
+    ///
+    /// On write errors, when the row is not of the header's row size, or when the header's row
+    /// count is already written.
+    pub fn write_row_bytes(&mut self, row_bytes: &[u8]) -> Result<(), Error> {
+        if row_bytes.len() != self.header.row_byte_size() {
+            return Err(PBaseError::InvalidCopyStream(format!(
+                "row of {} bytes for a row size of {}",
+                row_bytes.len(),
+                self.header.row_byte_size()
+            ))
+            .into());
+        }
+        if self.rows_written == self.header.row_count {
+            return Err(PBaseError::InvalidCopyStream(format!(
+                "more than the {} rows of the header",
+                self.header.row_count
+            ))
+            .into());
+        }
+
+        self.writer.write_all(row_bytes)?;
+        self.rows_written += 1;
+
+        Ok(())
+    }
+
+    ///
+    /// Flushes the stream and returns the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// On write errors, or when fewer rows were written than the header's row count.
+    pub fn finish(mut self) -> Result<W, Error> {
+        if self.rows_written != self.header.row_count {
+            return Err(PBaseError::InvalidCopyStream(format!(
+                "{} rows written of the {} of the header",
+                self.rows_written, self.header.row_count
+            ))
+            .into());
+        }
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+///
+/// Reads a copy stream written by `CopyWriter`: its header and all its row bytes.
+///
+/// # Errors
+///
+/// On read errors, when the input is not a copy stream (or of a newer version than
+/// `COPY_VERSION`), or when it does not hold exactly the header's row count.
+pub fn read_copy_stream<R: BufRead>(mut reader: R) -> Result<(CopyHeader, Vec<u8>), Error> {
+    let mut header_line = vec![];
+    reader.read_until(b'\n', &mut header_line)?;
+    let header: CopyHeader = serde_json::from_slice(&header_line)
+        .map_err(|err| PBaseError::InvalidCopyStream(format!("bad header: {err}")))?;
+    if header.format != COPY_FORMAT {
+        return Err(
+            PBaseError::InvalidCopyStream(format!("unknown format {}", header.format)).into(),
+        );
+    }
+    if header.version > COPY_VERSION {
+        return Err(PBaseError::InvalidCopyStream(format!(
+            "version {} is newer than supported {COPY_VERSION}",
+            header.version
+        ))
+        .into());
+    }
+
+    let rows_byte_size = header.row_count * header.row_byte_size();
+    let mut rows = Vec::with_capacity(rows_byte_size);
+    reader.read_to_end(&mut rows)?;
+    if rows.len() != rows_byte_size {
+        return Err(PBaseError::InvalidCopyStream(format!(
+            "{} bytes of rows for {} rows of {} bytes",
+            rows.len(),
+            header.row_count,
+            header.row_byte_size()
+        ))
+        .into());
+    }
+
+    Ok((header, rows))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use indexmap::IndexMap;
+
+    use crate::{
+        schema::{FieldSchema, TableSchema},
+        value::Value,
+    };
+
+    use super::{read_copy_stream, CopyHeader, CopyWriter};
+
+    #[test]
+    fn test_copy_stream_roundtrip() {
+        let table_schema = TableSchema {
+            name: "t1".into(),
+            fields: IndexMap::from([
+                ("a".into(), FieldSchema::I32),
+                ("b".into(), FieldSchema::U8),
+            ]),
+            ..Default::default()
+        };
+        let row_bytes = table_schema.data_row_to_bytes(&HashMap::from([
+            ("a".into(), Value::I32(-1)),
+            ("b".into(), Value::U8(2)),
+        ]));
+
+        let mut writer = CopyWriter::new(vec![], CopyHeader::new(&table_schema, 1)).unwrap();
+        assert!(writer.write_row_bytes(&row_bytes[1..]).is_err());
+        writer.write_row_bytes(&row_bytes).unwrap();
+        assert!(writer.write_row_bytes(&row_bytes).is_err());
+        let bytes = writer.finish().unwrap();
+
+        let (header, rows) = read_copy_stream(&bytes[..]).unwrap();
+        assert_eq!(CopyHeader::new(&table_schema, 1), header);
+        assert_eq!(row_bytes, rows);
+        header.check_table(&table_schema).unwrap();
+
+        let mut other_schema = table_schema.clone();
+        other_schema.fields.swap_indices(0, 1);
+        assert!(header.check_table(&other_schema).is_err());
+        assert!(read_copy_stream(&bytes[..bytes.len() - 1]).is_err());
+        assert!(read_copy_stream(&b"not a copy stream"[..]).is_err());
+        assert!(CopyWriter::new(vec![], CopyHeader::new(&table_schema, 1))
+            .unwrap()
+            .finish()
+            .is_err());
+    }
+}
@@ -0,0 +1,310 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt::{Debug, Display, Formatter},
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::{Error, PBaseError},
+    value::Value,
+};
+
+///
+/// A domain type (money, fixed-point, ...) stored natively in fields of its registered name (see
+/// `PBase::register_type` and `FieldSchema::Custom`).
+///
+/// Values are stored as `byte_size` bytes in the type's own encoding, and are ordered by the type:
+/// filters, sorts and indices on custom fields follow `compare`, not the bytes.
+///
+pub trait TypeDef: Send + Sync {
+    ///
+    /// Bytes of every stored value, fixed for the life of the tables using the type.
+    ///
+    fn byte_size(&self) -> usize;
+
+    ///
+    /// The stored bytes of a plain value (eg. an I32 of cents), None when the type cannot represent
+    /// it. Called for inserted values and for the values filters compare custom fields to.
+    ///
+    fn encode(&self, value: &Value) -> Option<Vec<u8>>;
+
+    ///
+    /// Orders the stored bytes of two values.
+    ///
+    fn compare(&self, lhs: &[u8], rhs: &[u8]) -> Ordering;
+
+    ///
+    /// The value as shown in results.
+    ///
+    fn format(&self, bytes: &[u8]) -> String;
+}
+
+///
+/// Types registered by the application, by name.
+///
+#[derive(Clone, Default)]
+pub struct TypeDefs {
+    type_defs: HashMap<String, Arc<dyn TypeDef>>,
+}
+
+impl TypeDefs {
+    ///
+    /// Registers the type, replacing any previous one of the same name.
+    ///
+    pub fn register<T: TypeDef + 'static>(&mut self, name: &str, type_def: T) {
+        self.type_defs.insert(name.to_string(), Arc::new(type_def));
+    }
+
+    /// # Errors
+    ///
+    /// Errors when no type is registered under the name.
+    pub fn get(&self, name: &str) -> Result<&Arc<dyn TypeDef>, Error> {
+        self.type_defs
+            .get(name)
+            .ok_or_else(|| PBaseError::UnknownType(name.to_string()).into())
+    }
+}
+
+///
+/// Type of a custom field: the registered type's name and byte size, as saved with the schema.
+///
+/// Schemas opened by a `TableOpener` have the type's definition resolved, see `resolve`.
+///
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CustomType {
+    pub name: String,
+    pub byte_size: usize,
+    #[serde(skip)]
+    type_def: Option<Arc<dyn TypeDef>>,
+}
+
+impl CustomType {
+    ///
+    /// A field of the named type, its byte size set when the table is created.
+    ///
+    #[must_use]
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            byte_size: 0,
+            type_def: None,
+        }
+    }
+
+    ///
+    /// The type with the definition registered under its name, and its byte size.
+    ///
+    /// # Errors
+    ///
+    /// Errors when the type is not registered, or when its byte size is not the size the table
+    /// was created with (its data would be read with the wrong layout).
+    pub fn resolve(&self, type_defs: &TypeDefs, created: bool) -> Result<Self, Error> {
+        let type_def = type_defs.get(&self.name)?;
+        if created && type_def.byte_size() != self.byte_size {
+            return Err(PBaseError::InvalidArgument(format!(
+                "type {} is registered with {} bytes, its fields were created with {}",
+                self.name,
+                type_def.byte_size(),
+                self.byte_size
+            ))
+            .into());
+        }
+
+        Ok(Self {
+            name: self.name.clone(),
+            byte_size: type_def.byte_size(),
+            type_def: Some(type_def.clone()),
+        })
+    }
+
+    #[must_use]
+    pub fn value_from_bytes(&self, bytes: &[u8]) -> Value {
+        Value::Custom(CustomValue {
+            type_name: self.name.clone(),
+            bytes: bytes[0..self.byte_size].to_vec(),
+            type_def: self.type_def.clone(),
+        })
+    }
+
+    ///
+    /// The value converted to this type: values of the type as they are, plain values encoded by
+    /// the type's definition. NULL and values of other custom types are not representable.
+    ///
+    #[must_use]
+    pub fn coerce(&self, value: &Value) -> Option<Value> {
+        match value {
+            Value::Custom(custom_value) if custom_value.type_name == self.name => {
+                Some(self.value_from_bytes(&custom_value.bytes))
+            }
+            Value::I32(_) | Value::U8(_) | Value::Decimal(_) => self
+                .type_def
+                .as_ref()?
+                .encode(value)
+                .filter(|bytes| bytes.len() == self.byte_size)
+                .map(|bytes| self.value_from_bytes(&bytes)),
+            Value::NULL | Value::Custom(_) => None,
+        }
+    }
+}
+
+impl PartialEq for CustomType {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.byte_size == other.byte_size
+    }
+}
+
+impl Eq for CustomType {}
+
+impl Debug for CustomType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}({} bytes)", self.name, self.byte_size)
+    }
+}
+
+///
+/// Value of a custom field: the stored bytes, ordered by the type's definition.
+///
+/// Values read back from JSON (WAL records, archives) carry no definition until coerced to their
+/// field's type, and order by their bytes meanwhile.
+///
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CustomValue {
+    pub type_name: String,
+    pub bytes: Vec<u8>,
+    #[serde(skip)]
+    type_def: Option<Arc<dyn TypeDef>>,
+}
+
+impl CustomValue {
+    ///
+    /// Orders the value against a plain value, encoded by the type.
+    ///
+    /// # Panics
+    ///
+    /// When the value has no definition, or the type cannot represent the other value.
+    #[must_use]
+    pub fn cmp_plain(&self, other: &Value) -> Ordering {
+        let encoded = self
+            .type_def
+            .as_ref()
+            .and_then(|type_def| type_def.encode(other));
+        match (&self.type_def, encoded) {
+            (Some(type_def), Some(bytes)) => type_def.compare(&self.bytes, &bytes),
+            _ => panic!("Values cannot be compared {self:?} ? {other:?}"),
+        }
+    }
+}
+
+impl PartialEq for CustomValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.type_name == other.type_name && self.bytes == other.bytes
+    }
+}
+
+impl Eq for CustomValue {}
+
+impl Hash for CustomValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.type_name.hash(state);
+        self.bytes.hash(state);
+    }
+}
+
+impl PartialOrd for CustomValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CustomValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        assert!(
+            self.type_name == other.type_name,
+            "Values cannot be compared {self:?} ? {other:?}"
+        );
+        self.type_def
+            .as_ref()
+            .or(other.type_def.as_ref())
+            .map_or_else(
+                || self.bytes.cmp(&other.bytes),
+                |type_def| type_def.compare(&self.bytes, &other.bytes),
+            )
+    }
+}
+
+impl Debug for CustomValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}({:?})", self.type_name, self.bytes)
+    }
+}
+
+impl Display for CustomValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.type_def {
+            Some(type_def) => write!(f, "{}", type_def.format(&self.bytes)),
+            None => write!(f, "{self:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cmp::Ordering;
+
+    use crate::value::Value;
+
+    use super::{CustomType, TypeDef, TypeDefs};
+
+    // Cents stored big-endian, ordered descending to tell the ordering from the bytes'.
+    struct ReversedCents;
+
+    impl TypeDef for ReversedCents {
+        fn byte_size(&self) -> usize {
+            4
+        }
+
+        fn encode(&self, value: &Value) -> Option<Vec<u8>> {
+            match value {
+                Value::I32(v) => Some(v.to_be_bytes().to_vec()),
+                _ => None,
+            }
+        }
+
+        fn compare(&self, lhs: &[u8], rhs: &[u8]) -> Ordering {
+            rhs.cmp(lhs)
+        }
+
+        fn format(&self, bytes: &[u8]) -> String {
+            let cents = i32::from_be_bytes(bytes.try_into().unwrap());
+            format!("${}.{:02}", cents / 100, cents % 100)
+        }
+    }
+
+    #[test]
+    fn test_custom_values() {
+        let mut type_defs = TypeDefs::default();
+        type_defs.register("cents", ReversedCents);
+        assert!(CustomType::new("euro").resolve(&type_defs, false).is_err());
+
+        let cents = CustomType::new("cents").resolve(&type_defs, false).unwrap();
+        assert_eq!(4, cents.byte_size);
+        assert!(cents.resolve(&type_defs, true).is_ok());
+        assert!(CustomType::new("cents").resolve(&type_defs, true).is_err());
+
+        let one = cents.coerce(&Value::I32(100)).unwrap();
+        let two = cents.coerce(&Value::I32(200)).unwrap();
+        assert_eq!("$1.00", one.to_string());
+        assert_eq!(None, cents.coerce(&Value::U8(1)));
+        assert_eq!(None, cents.coerce(&Value::NULL));
+
+        assert!(two < one);
+        assert!(Value::NULL < two);
+        assert_eq!(Ordering::Greater, one.cmp(&Value::I32(200)));
+        assert_eq!(Ordering::Equal, Value::I32(100).cmp(&one));
+        assert_eq!(Some(one.clone()), cents.coerce(&one));
+    }
+}
@@ -0,0 +1,222 @@
+use std::{
+    cmp::Ordering,
+    fmt::{Display, Formatter},
+    hash::{Hash, Hasher},
+};
+
+use serde::{Deserialize, Serialize};
+
+///
+/// Most digits of a decimal: an i128 holds any 38 digit number.
+///
+pub const MAX_DECIMAL_PRECISION: u8 = 38;
+
+// Decimals of up to this many digits are stored in an i64, larger ones in an i128.
+const I64_DECIMAL_PRECISION: u8 = 18;
+
+///
+/// Fixed-point number: `unscaled / 10^scale`, eg. 12.34 is 1234 at scale 2.
+///
+/// Arithmetic is exact: results keep every digit, or fail (None) when they would not fit. Values
+/// are equal and ordered by the number they stand for, whatever their scale (1.5 == 1.50).
+///
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Decimal {
+    pub unscaled: i128,
+    pub scale: u8,
+}
+
+impl Decimal {
+    #[must_use]
+    pub const fn new(unscaled: i128, scale: u8) -> Self {
+        Self { unscaled, scale }
+    }
+
+    ///
+    /// The same number at a larger scale, None when it does not fit or the scale is smaller.
+    ///
+    #[must_use]
+    pub fn rescaled(&self, scale: u8) -> Option<Self> {
+        let factor = 10i128.checked_pow(u32::from(scale.checked_sub(self.scale)?))?;
+        Some(Self::new(self.unscaled.checked_mul(factor)?, scale))
+    }
+
+    ///
+    /// The same number at the smallest scale representing it exactly.
+    ///
+    #[must_use]
+    pub const fn normalized(&self) -> Self {
+        let mut decimal = *self;
+        while decimal.scale > 0 && decimal.unscaled % 10 == 0 {
+            decimal.unscaled /= 10;
+            decimal.scale -= 1;
+        }
+        decimal
+    }
+
+    ///
+    /// Number of digits, at least 1 (for zero).
+    ///
+    #[must_use]
+    pub const fn digits(&self) -> u32 {
+        match self.unscaled.unsigned_abs().checked_ilog10() {
+            Some(log) => log + 1,
+            None => 1,
+        }
+    }
+
+    #[must_use]
+    pub fn checked_add(&self, other: &Self) -> Option<Self> {
+        let scale = self.scale.max(other.scale);
+        let unscaled = self
+            .rescaled(scale)?
+            .unscaled
+            .checked_add(other.rescaled(scale)?.unscaled)?;
+        Some(Self::new(unscaled, scale))
+    }
+
+    #[must_use]
+    pub fn checked_sub(&self, other: &Self) -> Option<Self> {
+        self.checked_add(&Self::new(other.unscaled.checked_neg()?, other.scale))
+    }
+
+    ///
+    /// The product, at the sum of the scales.
+    ///
+    #[must_use]
+    pub fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(Self::new(
+            self.unscaled.checked_mul(other.unscaled)?,
+            self.scale.checked_add(other.scale)?,
+        ))
+    }
+
+    ///
+    /// The quotient by an integer, truncated toward zero at the decimal's scale.
+    ///
+    #[must_use]
+    pub const fn checked_div_int(&self, divisor: i128) -> Option<Self> {
+        match self.unscaled.checked_div(divisor) {
+            Some(unscaled) => Some(Self::new(unscaled, self.scale)),
+            None => None,
+        }
+    }
+}
+
+///
+/// Bytes of a stored decimal of the precision: 8 (an i64) up to 18 digits, 16 (an i128) above.
+///
+#[must_use]
+pub const fn decimal_byte_size(precision: u8) -> usize {
+    if precision <= I64_DECIMAL_PRECISION {
+        8
+    } else {
+        16
+    }
+}
+
+impl From<i32> for Decimal {
+    fn from(value: i32) -> Self {
+        Self::new(i128::from(value), 0)
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl Hash for Decimal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let normalized = self.normalized();
+        normalized.unscaled.hash(state);
+        normalized.scale.hash(state);
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let scale = self.scale.max(other.scale);
+        match (self.rescaled(scale), other.rescaled(scale)) {
+            (Some(lhs), Some(rhs)) => lhs.unscaled.cmp(&rhs.unscaled),
+            // Out of range at the common scale: further from zero than the other one.
+            (None, _) => self.unscaled.cmp(&0),
+            (_, None) => 0.cmp(&other.unscaled),
+        }
+    }
+}
+
+impl Display for Decimal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let digits = self.unscaled.unsigned_abs().to_string();
+        let sign = if self.unscaled < 0 { "-" } else { "" };
+        let scale = usize::from(self.scale);
+        if scale == 0 {
+            return write!(f, "{sign}{digits}");
+        }
+
+        let digits = format!("{digits:0>width$}", width = scale + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - scale);
+        write!(f, "{sign}{integer}.{fraction}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::{decimal_byte_size, Decimal};
+
+    #[test]
+    fn test_decimal() {
+        assert_eq!("12.34", Decimal::new(1234, 2).to_string());
+        assert_eq!("-0.05", Decimal::new(-5, 2).to_string());
+        assert_eq!("7", Decimal::new(7, 0).to_string());
+
+        assert_eq!(Decimal::new(15, 1), Decimal::new(150, 2));
+        assert_eq!(
+            HashSet::from([Decimal::new(15, 1)]),
+            HashSet::from([Decimal::new(150, 2)])
+        );
+        assert!(Decimal::new(-1, 0) < Decimal::new(-99, 2));
+        assert!(Decimal::new(1, 2) < Decimal::new(i128::MAX, 0));
+        assert!(Decimal::new(i128::MIN, 0) < Decimal::new(-1, 2));
+
+        assert_eq!(
+            Some(Decimal::new(1254, 2)),
+            Decimal::new(1234, 2).checked_add(&Decimal::new(2, 1))
+        );
+        assert_eq!(
+            Some(Decimal::new(-66, 2)),
+            Decimal::new(34, 2).checked_sub(&Decimal::new(1, 0))
+        );
+        assert_eq!(
+            Some(Decimal::new(3_702, 3)),
+            Decimal::new(1234, 2).checked_mul(&Decimal::new(3, 1))
+        );
+        assert_eq!(
+            None,
+            Decimal::new(i128::MAX, 0).checked_add(&Decimal::new(1, 0))
+        );
+        assert_eq!(None, Decimal::new(i128::MAX, 0).rescaled(1));
+        assert_eq!(None, Decimal::new(1, 2).rescaled(1));
+        assert_eq!(
+            Some(Decimal::new(-333, 2)),
+            Decimal::new(-1000, 2).checked_div_int(3)
+        );
+
+        assert_eq!(3, Decimal::new(-123, 2).digits());
+        assert_eq!(1, Decimal::new(0, 2).digits());
+        assert_eq!(8, decimal_byte_size(18));
+        assert_eq!(16, decimal_byte_size(19));
+    }
+}
@@ -0,0 +1,132 @@
+use std::{collections::BTreeMap, collections::HashMap, fs::File, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{common::Error, value::Value};
+
+// Values a dictionary holds at most: ids are stored in one byte.
+pub const DICTIONARY_MAX_VALUES: usize = 256;
+// Distinct values up to which `PBase::compact_table` encodes a column, leaving room for the
+// values inserted later.
+pub const DICTIONARY_ENCODE_MAX_DISTINCT: u64 = 64;
+// Rows a table needs before `PBase::compact_table` encodes its columns: rewriting a smaller table
+// saves too little to be worth it.
+pub const DICTIONARY_ENCODE_MIN_ROWS: u64 = 1000;
+
+///
+/// The values of a dictionary encoded column (see `TableSchema::dictionary_columns`), by id. Rows
+/// store the one byte id of their value instead of the value.
+///
+/// Ids are given in order of first appearance, and never change: values are only added.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<Value>", into = "Vec<Value>")]
+pub struct Dictionary {
+    values: Vec<Value>,
+    ids: HashMap<Value, u8>,
+}
+
+impl Dictionary {
+    #[must_use]
+    pub fn id(&self, value: &Value) -> Option<u8> {
+        self.ids.get(value).copied()
+    }
+
+    ///
+    /// The value of the id.
+    ///
+    /// # Panics
+    ///
+    /// When the dictionary has no such id.
+    #[must_use]
+    pub fn value(&self, id: u8) -> &Value {
+        &self.values[usize::from(id)]
+    }
+
+    ///
+    /// The id of the value, added to the dictionary when new. None when the value is new and the
+    /// dictionary is full.
+    ///
+    pub fn insert(&mut self, value: &Value) -> Option<u8> {
+        if let Some(id) = self.id(value) {
+            return Some(id);
+        }
+
+        let id = u8::try_from(self.values.len()).ok()?;
+        self.values.push(value.clone());
+        self.ids.insert(value.clone(), id);
+        Some(id)
+    }
+
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    #[must_use]
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+}
+
+impl From<Vec<Value>> for Dictionary {
+    fn from(values: Vec<Value>) -> Self {
+        let mut dictionary = Self::default();
+        for value in values.iter().take(DICTIONARY_MAX_VALUES) {
+            dictionary.insert(value);
+        }
+        dictionary
+    }
+}
+
+impl From<Dictionary> for Vec<Value> {
+    fn from(dictionary: Dictionary) -> Self {
+        dictionary.values
+    }
+}
+
+///
+/// The dictionaries of a table's encoded columns, from their sidecar file (`.pbv`, see
+/// `TableOpener::table_dictionary_file_name`).
+///
+/// # Errors
+///
+/// On file operations, or when the file is invalid.
+pub fn read_dictionaries(file_name: &Path) -> Result<BTreeMap<String, Dictionary>, Error> {
+    Ok(serde_json::from_reader(File::open(file_name)?)?)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::value::Value;
+
+    use super::{Dictionary, DICTIONARY_MAX_VALUES};
+
+    #[test]
+    fn test_dictionary() {
+        let mut dictionary = Dictionary::default();
+        assert_eq!(Some(0), dictionary.insert(&Value::I32(-7)));
+        assert_eq!(Some(1), dictionary.insert(&Value::I32(3)));
+        assert_eq!(Some(0), dictionary.insert(&Value::I32(-7)));
+        assert_eq!(&Value::I32(3), dictionary.value(1));
+        assert_eq!(None, dictionary.id(&Value::I32(4)));
+
+        let json = serde_json::to_string(&dictionary).unwrap();
+        assert_eq!(dictionary, serde_json::from_str(&json).unwrap());
+
+        for i in 0..DICTIONARY_MAX_VALUES {
+            dictionary.insert(&Value::I32(i32::try_from(i).unwrap() + 100));
+        }
+        assert_eq!(DICTIONARY_MAX_VALUES, dictionary.len());
+        assert_eq!(None, dictionary.insert(&Value::I32(1000)));
+        assert_eq!(
+            Some(255),
+            dictionary.id(dictionary.values().last().unwrap())
+        );
+    }
+}
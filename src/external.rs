@@ -0,0 +1,239 @@
+use std::{collections::HashMap, fs::File, path::PathBuf};
+
+use indexmap::IndexMap;
+use parquet::{
+    basic::{ConvertedType, Type as PhysicalType},
+    file::reader::{FileReader, SerializedFileReader},
+    record::Field,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::{Error, PBaseError},
+    query::{RhsValue, RowFilter},
+    schema::{FieldSchema, TableSchema},
+    value::Value,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExternalFormat {
+    Csv,
+    Parquet,
+}
+
+impl ExternalFormat {
+    ///
+    /// The format of the file by its extension (`.csv` or `.parquet`).
+    ///
+    #[must_use]
+    pub fn of_path(path: &std::path::Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "csv" => Some(Self::Csv),
+            "parquet" => Some(Self::Parquet),
+            _ => None,
+        }
+    }
+}
+
+///
+/// A CSV or Parquet file registered as a read-only table.
+///
+/// The schema is inferred once, at registration: CSV columns are named by the header row and are
+/// I32; Parquet columns take the file's types, where `UINT_8` columns and booleans are U8 and other
+/// integers up to 32 bits are I32. Empty cells and nulls read as the type's default, like the
+/// missing fields of lenient inserts. The file is read on every select, so it may change as long
+/// as its values still fit the schema.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExternalTable {
+    pub path: PathBuf,
+    pub format: ExternalFormat,
+    pub schema: TableSchema,
+}
+
+impl ExternalTable {
+    /// # Errors
+    ///
+    /// On file operations, for unknown file formats, or when a column has no supported type.
+    pub fn infer(table_name: &str, path: PathBuf) -> Result<Self, Error> {
+        let format = ExternalFormat::of_path(&path)
+            .ok_or_else(|| PBaseError::UnsupportedExternalFile(path.display().to_string()))?;
+        let fields = match format {
+            ExternalFormat::Csv => infer_csv_fields(&path)?,
+            ExternalFormat::Parquet => infer_parquet_fields(&path)?,
+        };
+
+        Ok(Self {
+            path,
+            format,
+            schema: TableSchema {
+                name: table_name.to_string(),
+                fields,
+                ..Default::default()
+            },
+        })
+    }
+
+    ///
+    /// The rows of the file in the regular table data layout. Rows not matching the (single table,
+    /// value) filters on this table are dropped while reading, before any decoding of later stages.
+    ///
+    /// # Errors
+    ///
+    /// On file operations, or when a value does not fit its column.
+    pub fn table_bytes(&self, filters: &[&RowFilter]) -> Result<Vec<u8>, Error> {
+        let pushed_filters: Vec<(&str, &RowFilter, &Value)> = filters
+            .iter()
+            .filter(|filter| filter.field.source == self.schema.name)
+            .filter_map(|filter| match &filter.rhs {
+                RhsValue::Value(value) => {
+                    let field_schema = self.schema.fields.get(&filter.field.name)?;
+                    (*value == Value::NULL || field_schema.is_type_of(value)).then_some((
+                        filter.field.name.as_str(),
+                        *filter,
+                        value,
+                    ))
+                }
+                RhsValue::Ref(_)
+                | RhsValue::In(_)
+                | RhsValue::Range { .. }
+                | RhsValue::Interval { .. }
+                | RhsValue::Subquery { .. } => None,
+            })
+            .collect();
+
+        let rows = match self.format {
+            ExternalFormat::Csv => self.csv_rows()?,
+            ExternalFormat::Parquet => self.parquet_rows()?,
+        };
+
+        Ok(rows
+            .iter()
+            .filter(|row| {
+                pushed_filters
+                    .iter()
+                    .all(|(field_name, filter, value)| filter.op.compare(&row[*field_name], value))
+            })
+            .flat_map(|row| self.schema.data_row_to_bytes(row))
+            .collect())
+    }
+
+    fn csv_rows(&self) -> Result<Vec<HashMap<String, Value>>, Error> {
+        let mut reader = csv::Reader::from_path(&self.path)?;
+
+        let mut rows = vec![];
+        for record in reader.records() {
+            let record = record?;
+            let mut row = HashMap::new();
+            for ((field_name, field_schema), cell) in self.schema.fields.iter().zip(record.iter()) {
+                let value = if cell.is_empty() {
+                    field_schema.default_value()
+                } else {
+                    cell.parse::<i32>()
+                        .ok()
+                        .and_then(|v| field_schema.coerce(&Value::I32(v)))
+                        .ok_or_else(|| PBaseError::FieldTypeMismatch {
+                            field: field_name.clone(),
+                            value: cell.to_string(),
+                        })?
+                };
+                row.insert(field_name.clone(), value);
+            }
+            rows.push(row);
+        }
+
+        Ok(rows)
+    }
+
+    fn parquet_rows(&self) -> Result<Vec<HashMap<String, Value>>, Error> {
+        let reader = SerializedFileReader::new(File::open(&self.path)?)?;
+
+        let mut rows = vec![];
+        for record in reader.get_row_iter(None)? {
+            let record = record?;
+            let mut row = HashMap::new();
+            for (field_name, field) in record.get_column_iter() {
+                let Some(field_schema) = self.schema.fields.get(field_name) else {
+                    continue;
+                };
+                let value = match parquet_value(field) {
+                    Some(Value::NULL) => field_schema.default_value(),
+                    value => value
+                        .and_then(|value| field_schema.coerce(&value))
+                        .ok_or_else(|| PBaseError::FieldTypeMismatch {
+                            field: field_name.clone(),
+                            value: field.to_string(),
+                        })?,
+                };
+                row.insert(field_name.clone(), value);
+            }
+            rows.push(row);
+        }
+
+        Ok(rows)
+    }
+}
+
+fn infer_csv_fields(path: &std::path::Path) -> Result<IndexMap<String, FieldSchema>, Error> {
+    let mut reader = csv::Reader::from_path(path)?;
+
+    Ok(reader
+        .headers()?
+        .iter()
+        .map(|header| (header.to_string(), FieldSchema::I32))
+        .collect())
+}
+
+fn infer_parquet_fields(path: &std::path::Path) -> Result<IndexMap<String, FieldSchema>, Error> {
+    let reader = SerializedFileReader::new(File::open(path)?)?;
+    let schema_descr = reader.metadata().file_metadata().schema_descr_ptr();
+
+    schema_descr
+        .columns()
+        .iter()
+        .map(|column| {
+            let unsupported = || PBaseError::UnsupportedExternalColumn {
+                column: column.path().string(),
+                column_type: column.physical_type().to_string(),
+            };
+            // Nested columns have no flat equivalent.
+            if column.path().parts().len() != 1 {
+                return Err(unsupported().into());
+            }
+
+            let field_schema = match (column.physical_type(), column.converted_type()) {
+                (PhysicalType::INT32, ConvertedType::UINT_8) | (PhysicalType::BOOLEAN, _) => {
+                    FieldSchema::U8
+                }
+                (
+                    PhysicalType::INT32,
+                    ConvertedType::NONE
+                    | ConvertedType::INT_8
+                    | ConvertedType::INT_16
+                    | ConvertedType::INT_32
+                    | ConvertedType::UINT_16,
+                ) => FieldSchema::I32,
+                _ => return Err(unsupported().into()),
+            };
+            Ok((column.name().to_string(), field_schema))
+        })
+        .collect()
+}
+
+// A Parquet value as the closest pbase value, before fitting it to the column type. None for
+// values of types pbase has no equivalent for.
+fn parquet_value(field: &Field) -> Option<Value> {
+    let int_value = match field {
+        Field::Null => return Some(Value::NULL),
+        Field::Bool(v) => i64::from(*v),
+        Field::Byte(v) => i64::from(*v),
+        Field::Short(v) => i64::from(*v),
+        Field::Int(v) => i64::from(*v),
+        Field::UByte(v) => return Some(Value::U8(*v)),
+        Field::UShort(v) => i64::from(*v),
+        Field::UInt(v) => i64::from(*v),
+        _ => return None,
+    };
+
+    i32::try_from(int_value).ok().map(Value::I32)
+}
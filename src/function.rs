@@ -0,0 +1,55 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    common::{Error, PBaseError},
+    schema::FieldSchema,
+    value::Value,
+};
+
+///
+/// Body of a scalar function: maps the argument value of a row to the result value.
+///
+pub type ScalarFn = Arc<dyn Fn(&Value) -> Value + Send + Sync>;
+
+#[derive(Clone)]
+pub struct ScalarFunction {
+    // Type the results are reported as in result columns.
+    pub return_type: FieldSchema,
+    pub body: ScalarFn,
+}
+
+///
+/// Scalar functions registered by the application, callable by name from queries (see
+/// `ScalarCall`).
+///
+#[derive(Clone, Default)]
+pub struct ScalarFunctions {
+    functions: HashMap<String, ScalarFunction>,
+}
+
+impl ScalarFunctions {
+    ///
+    /// Registers the function, replacing any previous one of the same name.
+    ///
+    pub fn register<F>(&mut self, name: &str, return_type: FieldSchema, body: F)
+    where
+        F: Fn(&Value) -> Value + Send + Sync + 'static,
+    {
+        self.functions.insert(
+            name.to_string(),
+            ScalarFunction {
+                return_type,
+                body: Arc::new(body),
+            },
+        );
+    }
+
+    /// # Errors
+    ///
+    /// Errors when no function is registered under the name.
+    pub fn get(&self, name: &str) -> Result<&ScalarFunction, Error> {
+        self.functions
+            .get(name)
+            .ok_or_else(|| PBaseError::UnknownFunction(name.to_string()).into())
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{
+    common::Error, query::RowFilter, query_log::QueryLogEntry, stats::INDEX_SCAN_MAX_SELECTIVITY,
+    table_opener::TableOpener,
+};
+
+// Assumed fraction of the rows matching a filter on a field without a histogram.
+const DEFAULT_EQUALITY_SELECTIVITY: f64 = 0.1;
+const DEFAULT_RANGE_SELECTIVITY: f64 = 0.3;
+
+///
+/// An index suggested by `PBase::recommend_indexes`.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexRecommendation {
+    pub table: String,
+    // Equality filtered fields first (by name), then at most one range filtered field.
+    pub fields: Vec<String>,
+    // Logged accesses of the table the index would serve.
+    pub queries: usize,
+    // Estimated rows those accesses would not have read with the index.
+    pub estimated_rows_saved: usize,
+}
+
+impl IndexRecommendation {
+    #[must_use]
+    pub fn index_name(&self) -> String {
+        format!("{}_auto_index", self.fields.join("_"))
+    }
+}
+
+///
+/// Composite indices serving the logged table accesses, most beneficial first.
+///
+/// An access is served by an index on its equality filtered fields followed by one of its range
+/// filtered fields, as the index scan narrows by equal leading values and then by a range. Its
+/// benefit is the rows of the table it would skip, estimated with the ANALYZE histograms where the
+/// table has them. Accesses an existing index already serves, and those matching too many rows
+/// for the planner to pick an index, are left out.
+///
+/// # Errors
+///
+/// On file operations, or when a logged table has no valid schema.
+pub fn recommend_indexes(
+    entries: &[QueryLogEntry],
+    table_opener: &TableOpener,
+) -> Result<Vec<IndexRecommendation>, Error> {
+    let mut benefits: HashMap<(&str, Vec<String>), (usize, f64)> = HashMap::new();
+
+    for entry in entries {
+        // System and external tables cannot be indexed.
+        if !table_opener.table_schema_file_name(&entry.table).exists() {
+            continue;
+        }
+        let fields = candidate_index_fields(&entry.filters);
+        if fields.is_empty() {
+            continue;
+        }
+
+        let table_schema = table_opener.open_schema(&entry.table)?;
+        let is_served = table_schema
+            .indices
+            .values()
+            .any(|index_fields| index_fields.starts_with(&fields));
+        if is_served {
+            continue;
+        }
+
+        let table_stats = table_opener.open_stats(&entry.table)?;
+        let field_fraction = |field_name: &String| -> f64 {
+            entry
+                .filters
+                .iter()
+                .filter(|filter| filter.field.name == *field_name)
+                .map(|filter| {
+                    table_stats
+                        .as_ref()
+                        .and_then(|stats| stats.selectivity(field_name, &[filter]))
+                        .unwrap_or_else(|| {
+                            if filter.is_equality() {
+                                DEFAULT_EQUALITY_SELECTIVITY
+                            } else {
+                                DEFAULT_RANGE_SELECTIVITY
+                            }
+                        })
+                })
+                .product()
+        };
+        // The planner only weighs the leading field when choosing the index.
+        if field_fraction(&fields[0]) > INDEX_SCAN_MAX_SELECTIVITY {
+            continue;
+        }
+        let fraction: f64 = fields.iter().map(field_fraction).product();
+
+        let table_len = std::fs::metadata(table_opener.table_data_file_name(&entry.table))?.len();
+        let rows = usize::try_from(table_len)? / table_schema.row_byte_size().max(1);
+
+        let benefit = benefits
+            .entry((entry.table.as_str(), fields))
+            .or_insert((0, 0.0));
+        benefit.0 += 1;
+        benefit.1 += skipped_rows(rows, fraction);
+    }
+
+    let mut recommendations: Vec<IndexRecommendation> = benefits
+        .into_iter()
+        .map(
+            |((table, fields), (queries, rows_saved))| IndexRecommendation {
+                table: table.to_string(),
+                fields,
+                queries,
+                estimated_rows_saved: to_rows(rows_saved),
+            },
+        )
+        .collect();
+    recommendations.sort_by(|lhs, rhs| {
+        rhs.estimated_rows_saved
+            .cmp(&lhs.estimated_rows_saved)
+            .then_with(|| lhs.table.cmp(&rhs.table))
+            .then_with(|| lhs.fields.cmp(&rhs.fields))
+    });
+
+    Ok(recommendations)
+}
+
+//
+// Fields of the index serving the filters: the equality filtered ones, then the first (by name)
+// range filtered one.
+//
+fn candidate_index_fields(filters: &[RowFilter]) -> Vec<String> {
+    let equality_fields: BTreeSet<&String> = filters
+        .iter()
+        .filter(|filter| filter.is_equality())
+        .map(|filter| &filter.field.name)
+        .collect();
+    let range_field = filters
+        .iter()
+        .filter(|filter| !filter.is_equality())
+        .map(|filter| &filter.field.name)
+        .filter(|field_name| !equality_fields.contains(field_name))
+        .min();
+
+    equality_fields
+        .into_iter()
+        .chain(range_field)
+        .cloned()
+        .collect()
+}
+
+// Estimates tolerate the precision loss of large counts.
+#[allow(clippy::cast_precision_loss)]
+fn skipped_rows(rows: usize, fraction: f64) -> f64 {
+    rows as f64 * (1.0 - fraction)
+}
+
+// Estimates are non-negative and far below usize::MAX.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+const fn to_rows(estimate: f64) -> usize {
+    estimate.round() as usize
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        query::{CompareOp, FieldSelector, RhsValue, RowFilter},
+        value::Value,
+    };
+
+    use super::candidate_index_fields;
+
+    #[test]
+    fn test_candidate_index_fields() {
+        let filter = |name: &str, op| RowFilter {
+            field: FieldSelector {
+                name: name.into(),
+                source: "t1".into(),
+            },
+            op,
+            rhs: RhsValue::Value(Value::I32(1)),
+        };
+
+        assert!(candidate_index_fields(&[]).is_empty());
+        assert_eq!(
+            vec!["b", "c", "a"],
+            candidate_index_fields(&[
+                filter("d", CompareOp::Lt),
+                filter("c", CompareOp::Eq),
+                filter("a", CompareOp::Gt),
+                filter("b", CompareOp::Eq),
+                filter("c", CompareOp::Eq),
+            ])
+        );
+        assert_eq!(
+            vec!["a"],
+            candidate_index_fields(&[filter("a", CompareOp::Lt), filter("a", CompareOp::Eq)])
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use std::{
+    collections::HashMap,
+    ops::{Bound, RangeBounds},
+};
+
+use indexmap::IndexMap;
+
+use crate::{
+    common::{Error, PBaseError},
+    pbase::PBase,
+    query::{
+        CompareOp, CreateTableQuery, FieldSelector, InsertQuery, RhsValue, RowFilter, SelectQuery,
+    },
+    schema::{FieldSchema, TablePtrType, TableSchema},
+    value::Value,
+};
+
+const KEY_FIELD: &str = "key";
+const VALUE_FIELD: &str = "value";
+const DELETED_FIELD: &str = "deleted";
+const KEY_INDEX: &str = "key_index";
+
+///
+/// Ordered key-value store over a table, for callers that need no more than get, put, delete and
+/// ordered scans.
+///
+/// The table has a `key` and a `value` column, with `key` indexed. Keys are kept unique by the
+/// store: a put overwrites the key's row in place. Tables cannot delete rows, so a delete marks the
+/// row in a `deleted` column instead, and a later put of the key reuses the row.
+///
+pub struct KvStore<'a> {
+    db: &'a PBase,
+    table: String,
+}
+
+impl<'a> KvStore<'a> {
+    ///
+    /// Opens the store kept in the table, creating the table when it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// On file operations, or when the table exists but is not a key-value table.
+    pub fn open(db: &'a PBase, table: &str) -> Result<Self, Error> {
+        let table_schema = kv_table_schema(table);
+        if db.is_table_exist(table) {
+            let existing_schema = db.table_schema(table)?;
+            if existing_schema.fields != table_schema.fields
+                || existing_schema.indices != table_schema.indices
+            {
+                return Err(PBaseError::NotAKvTable(table.to_string()).into());
+            }
+        } else {
+            db.run_create_table_query(&CreateTableQuery {
+                schema: table_schema,
+            })?;
+        }
+
+        Ok(Self {
+            db,
+            table: table.to_string(),
+        })
+    }
+
+    /// # Errors
+    ///
+    /// On file operations.
+    pub fn get(&self, key: i32) -> Result<Option<i32>, Error> {
+        Ok(self.find(key)?.and_then(|(_, value)| value))
+    }
+
+    ///
+    /// Sets the key's value, returning the previous one.
+    ///
+    /// # Errors
+    ///
+    /// On file operations.
+    pub fn put(&self, key: i32, value: i32) -> Result<Option<i32>, Error> {
+        let values = HashMap::from([
+            (VALUE_FIELD.to_string(), Value::I32(value)),
+            (DELETED_FIELD.to_string(), Value::U8(0)),
+        ]);
+
+        if let Some((row_pos, previous)) = self.find(key)? {
+            self.db.update_row_at(&self.table, row_pos, &values)?;
+            return Ok(previous);
+        }
+
+        let mut values = values;
+        values.insert(KEY_FIELD.to_string(), Value::I32(key));
+        self.db.run_insert_query(&InsertQuery {
+            table: self.table.clone(),
+            values,
+        })?;
+
+        Ok(None)
+    }
+
+    ///
+    /// Removes the key, returning its value.
+    ///
+    /// # Errors
+    ///
+    /// On file operations.
+    pub fn delete(&self, key: i32) -> Result<Option<i32>, Error> {
+        let Some((row_pos, Some(previous))) = self.find(key)? else {
+            return Ok(None);
+        };
+
+        self.db.update_row_at(
+            &self.table,
+            row_pos,
+            &HashMap::from([(DELETED_FIELD.to_string(), Value::U8(1))]),
+        )?;
+
+        Ok(Some(previous))
+    }
+
+    ///
+    /// Key-value pairs with keys in the range, ordered by key.
+    ///
+    /// # Errors
+    ///
+    /// On file operations.
+    pub fn scan<R: RangeBounds<i32>>(&self, range: R) -> Result<Vec<(i32, i32)>, Error> {
+        let mut filters = vec![self.filter(DELETED_FIELD, CompareOp::Eq, Value::U8(0))];
+        match range.start_bound() {
+            Bound::Included(start) => {
+                filters.push(self.filter(KEY_FIELD, CompareOp::Ge, Value::I32(*start)));
+            }
+            Bound::Excluded(start) => {
+                filters.push(self.filter(KEY_FIELD, CompareOp::Gt, Value::I32(*start)));
+            }
+            Bound::Unbounded => {}
+        }
+        match range.end_bound() {
+            Bound::Included(end) => {
+                filters.push(self.filter(KEY_FIELD, CompareOp::Le, Value::I32(*end)));
+            }
+            Bound::Excluded(end) => {
+                filters.push(self.filter(KEY_FIELD, CompareOp::Lt, Value::I32(*end)));
+            }
+            Bound::Unbounded => {}
+        }
+
+        let mut pairs = vec![];
+        self.db.for_each_row_view(
+            SelectQuery {
+                from: self.table.clone(),
+                filters,
+                ..Default::default()
+            },
+            |row_view| {
+                if let (Some(Value::I32(key)), Some(Value::I32(value))) =
+                    (row_view.get(KEY_FIELD), row_view.get(VALUE_FIELD))
+                {
+                    pairs.push((key, value));
+                }
+            },
+        )?;
+        pairs.sort_unstable();
+
+        Ok(pairs)
+    }
+
+    ///
+    /// Key-value pairs whose keys share their bits above the lowest `suffix_bits` with
+    /// `prefix << suffix_bits`, ordered by key. Such keys are a contiguous range, so composite
+    /// keys packed with their leading part in the high bits can be scanned by that part. With 32
+    /// or more suffix bits no prefix bits are left and every key matches.
+    ///
+    /// # Errors
+    ///
+    /// On file operations.
+    pub fn scan_prefix(&self, prefix: i32, suffix_bits: u32) -> Result<Vec<(i32, i32)>, Error> {
+        if suffix_bits >= i32::BITS {
+            return self.scan(..);
+        }
+
+        let start = i64::from(prefix) << suffix_bits;
+        let end = start + (1i64 << suffix_bits) - 1;
+
+        // No key has a prefix that does not fit into the key type.
+        match (i32::try_from(start), i32::try_from(end)) {
+            (Ok(start), Ok(end)) => self.scan(start..=end),
+            _ => Ok(vec![]),
+        }
+    }
+
+    //
+    // Position and (None for deleted) value of the key's row.
+    //
+    fn find(&self, key: i32) -> Result<Option<(TablePtrType, Option<i32>)>, Error> {
+        let mut found = None;
+        self.db.for_each_row_view(
+            SelectQuery {
+                from: self.table.clone(),
+                filters: vec![self.filter(KEY_FIELD, CompareOp::Eq, Value::I32(key))],
+                ..Default::default()
+            },
+            |row_view| {
+                let value = match (row_view.get(DELETED_FIELD), row_view.get(VALUE_FIELD)) {
+                    (Some(Value::U8(0)), Some(Value::I32(value))) => Some(value),
+                    _ => None,
+                };
+                found = Some((row_view.row_pos, value));
+            },
+        )?;
+
+        found
+            .map(|(row_pos, value)| Ok((TablePtrType::try_from(row_pos)?, value)))
+            .transpose()
+    }
+
+    fn filter(&self, field_name: &str, op: CompareOp, value: Value) -> RowFilter {
+        RowFilter {
+            field: FieldSelector {
+                name: field_name.to_string(),
+                source: self.table.clone(),
+            },
+            op,
+            rhs: RhsValue::Value(value),
+        }
+    }
+}
+
+fn kv_table_schema(table: &str) -> TableSchema {
+    TableSchema {
+        name: table.to_string(),
+        fields: IndexMap::from([
+            (KEY_FIELD.to_string(), FieldSchema::I32),
+            (VALUE_FIELD.to_string(), FieldSchema::I32),
+            (DELETED_FIELD.to_string(), FieldSchema::U8),
+        ]),
+        indices: IndexMap::from([(KEY_INDEX.to_string(), vec![KEY_FIELD.to_string()])]),
+        ..Default::default()
+    }
+}
@@ -1,6 +1,7 @@
-use std::cmp::Ordering;
-
-use crate::common::{Error, PBaseError};
+use crate::{
+    common::{Error, PBaseError},
+    query::CompareOp,
+};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Token {
@@ -9,21 +10,88 @@ pub enum Token {
     Join,
     Comma,
     And,
+    Union,
+    Intersect,
+    Except,
+    All,
+    Explain,
+    Analyze,
+    Describe,
+    Set,
+    As,
+    TableSample,
+    First,
+    Into,
+    With,
+    Deleted,
+    Insert,
+    Delete,
+    Values,
+    Returning,
+    Create,
+    Table,
+    Default,
+    Distinct,
+    Order,
+    By,
+    Asc,
+    Desc,
+    Attach,
+    Where,
     Identifier(String),
-    Op(Ordering),
+    Op(CompareOp),
     Int(i32),
+    // A quoted string: `'...'`.
+    Str(String),
     Dot,
+    // `-`, of the interval filters `t2.ts - t1.ts < 3600`.
+    Minus,
+    LParen,
+    RParen,
 }
 
 const SELECT_WORD: &[u8; 6] = b"SELECT";
 const FROM_WORD: &[u8; 4] = b"FROM";
 const JOIN_WORD: &[u8; 4] = b"JOIN";
 const AND_WORD: &[u8; 3] = b"AND";
+const UNION_WORD: &[u8; 5] = b"UNION";
+const INTERSECT_WORD: &[u8; 9] = b"INTERSECT";
+const EXCEPT_WORD: &[u8; 6] = b"EXCEPT";
+const ALL_WORD: &[u8; 3] = b"ALL";
+const EXPLAIN_WORD: &[u8; 7] = b"EXPLAIN";
+const ANALYZE_WORD: &[u8; 7] = b"ANALYZE";
+const DESCRIBE_WORD: &[u8; 8] = b"DESCRIBE";
+const SET_WORD: &[u8; 3] = b"SET";
+const AS_WORD: &[u8; 2] = b"AS";
+const TABLESAMPLE_WORD: &[u8; 11] = b"TABLESAMPLE";
+const FIRST_WORD: &[u8; 5] = b"FIRST";
+const INTO_WORD: &[u8; 4] = b"INTO";
+const WITH_WORD: &[u8; 4] = b"WITH";
+const DELETED_WORD: &[u8; 7] = b"DELETED";
+const INSERT_WORD: &[u8; 6] = b"INSERT";
+const DELETE_WORD: &[u8; 6] = b"DELETE";
+const VALUES_WORD: &[u8; 6] = b"VALUES";
+const RETURNING_WORD: &[u8; 9] = b"RETURNING";
+const CREATE_WORD: &[u8; 6] = b"CREATE";
+const TABLE_WORD: &[u8; 5] = b"TABLE";
+const DEFAULT_WORD: &[u8; 7] = b"DEFAULT";
+const DISTINCT_WORD: &[u8; 8] = b"DISTINCT";
+const ORDER_WORD: &[u8; 5] = b"ORDER";
+const BY_WORD: &[u8; 2] = b"BY";
+const ASC_WORD: &[u8; 3] = b"ASC";
+const DESC_WORD: &[u8; 4] = b"DESC";
+const ATTACH_WORD: &[u8; 6] = b"ATTACH";
+const WHERE_WORD: &[u8; 5] = b"WHERE";
 const COMMA_CHAR: u8 = b',';
 const EQ_CHAR: u8 = b'=';
 const LT_CHAR: u8 = b'<';
 const GT_CHAR: u8 = b'>';
+const BANG_CHAR: u8 = b'!';
 const DOT_CHAR: u8 = b'.';
+const MINUS_CHAR: u8 = b'-';
+const LPAREN_CHAR: u8 = b'(';
+const RPAREN_CHAR: u8 = b')';
+const QUOTE_CHAR: u8 = b'\'';
 
 pub struct Lexer;
 
@@ -42,6 +110,34 @@ impl Lexer {
                     part if part == FROM_WORD => Token::From,
                     part if part == JOIN_WORD => Token::Join,
                     part if part == AND_WORD => Token::And,
+                    part if part == UNION_WORD => Token::Union,
+                    part if part == INTERSECT_WORD => Token::Intersect,
+                    part if part == EXCEPT_WORD => Token::Except,
+                    part if part == ALL_WORD => Token::All,
+                    part if part == EXPLAIN_WORD => Token::Explain,
+                    part if part == ANALYZE_WORD => Token::Analyze,
+                    part if part == DESCRIBE_WORD => Token::Describe,
+                    part if part == SET_WORD => Token::Set,
+                    part if part == AS_WORD => Token::As,
+                    part if part == TABLESAMPLE_WORD => Token::TableSample,
+                    part if part == FIRST_WORD => Token::First,
+                    part if part == INTO_WORD => Token::Into,
+                    part if part == WITH_WORD => Token::With,
+                    part if part == DELETED_WORD => Token::Deleted,
+                    part if part == INSERT_WORD => Token::Insert,
+                    part if part == DELETE_WORD => Token::Delete,
+                    part if part == VALUES_WORD => Token::Values,
+                    part if part == RETURNING_WORD => Token::Returning,
+                    part if part == CREATE_WORD => Token::Create,
+                    part if part == TABLE_WORD => Token::Table,
+                    part if part == DEFAULT_WORD => Token::Default,
+                    part if part == DISTINCT_WORD => Token::Distinct,
+                    part if part == ORDER_WORD => Token::Order,
+                    part if part == BY_WORD => Token::By,
+                    part if part == ASC_WORD => Token::Asc,
+                    part if part == DESC_WORD => Token::Desc,
+                    part if part == ATTACH_WORD => Token::Attach,
+                    part if part == WHERE_WORD => Token::Where,
                     _ => Token::Identifier(String::from_utf8_lossy(part).to_string()),
                 };
 
@@ -50,18 +146,28 @@ impl Lexer {
             } else if raw[0] == COMMA_CHAR {
                 raw = &raw[1..];
                 tokens.push(Token::Comma);
-            } else if raw[0] == EQ_CHAR {
+            } else if let Some((op, op_len)) = read_op(raw) {
+                raw = &raw[op_len..];
+                tokens.push(Token::Op(op));
+            } else if raw[0] == DOT_CHAR {
                 raw = &raw[1..];
-                tokens.push(Token::Op(Ordering::Equal));
-            } else if raw[0] == LT_CHAR {
+                tokens.push(Token::Dot);
+            } else if raw[0] == MINUS_CHAR {
                 raw = &raw[1..];
-                tokens.push(Token::Op(Ordering::Less));
-            } else if raw[0] == GT_CHAR {
+                tokens.push(Token::Minus);
+            } else if raw[0] == LPAREN_CHAR {
                 raw = &raw[1..];
-                tokens.push(Token::Op(Ordering::Greater));
-            } else if raw[0] == DOT_CHAR {
+                tokens.push(Token::LParen);
+            } else if raw[0] == RPAREN_CHAR {
                 raw = &raw[1..];
-                tokens.push(Token::Dot);
+                tokens.push(Token::RParen);
+            } else if raw[0] == QUOTE_CHAR {
+                let text = take_while(&raw[1..], |c| c != &QUOTE_CHAR);
+                if text.len() + 1 == raw.len() {
+                    return Err(PBaseError::BadToken("Unterminated string".into()).into());
+                }
+                raw = &raw[text.len() + 2..];
+                tokens.push(Token::Str(String::from_utf8_lossy(text).to_string()));
             } else if raw[0].is_ascii_whitespace() {
                 let whitespace = take_while(raw, u8::is_ascii_whitespace);
                 raw = &raw[whitespace.len()..];
@@ -94,6 +200,19 @@ fn read_keyword(raw: &[u8]) -> Option<&[u8]> {
     }
 }
 
+// A comparison operator and its length: `=`, `!=` (or `<>`), `<`, `<=`, `>` or `>=`.
+const fn read_op(raw: &[u8]) -> Option<(CompareOp, usize)> {
+    match raw {
+        [LT_CHAR, EQ_CHAR, ..] => Some((CompareOp::Le, 2)),
+        [GT_CHAR, EQ_CHAR, ..] => Some((CompareOp::Ge, 2)),
+        [BANG_CHAR, EQ_CHAR, ..] | [LT_CHAR, GT_CHAR, ..] => Some((CompareOp::Ne, 2)),
+        [EQ_CHAR, ..] => Some((CompareOp::Eq, 1)),
+        [LT_CHAR, ..] => Some((CompareOp::Lt, 1)),
+        [GT_CHAR, ..] => Some((CompareOp::Gt, 1)),
+        _ => None,
+    }
+}
+
 fn take_while<F>(raw: &[u8], cond: F) -> &[u8]
 where
     F: Fn(&u8) -> bool,
@@ -108,10 +227,8 @@ where
 
 #[cfg(test)]
 mod test {
-    use std::cmp::Ordering;
-
     use super::Lexer;
-    use crate::lexer::Token;
+    use crate::{lexer::Token, query::CompareOp};
 
     #[test]
     fn test_simple_select_query() {
@@ -152,21 +269,113 @@ mod test {
         assert_eq!(Token::Identifier("t1".into()), tokens[6]);
         assert_eq!(Token::Dot, tokens[7]);
         assert_eq!(Token::Identifier("id".into()), tokens[8]);
-        assert_eq!(Token::Op(Ordering::Equal), tokens[9]);
+        assert_eq!(Token::Op(CompareOp::Eq), tokens[9]);
         assert_eq!(Token::Identifier("t2".into()), tokens[10]);
         assert_eq!(Token::Dot, tokens[11]);
         assert_eq!(Token::Identifier("t1_id".into()), tokens[12]);
-        assert_eq!(Token::Identifier("WHERE".into()), tokens[13]);
+        assert_eq!(Token::Where, tokens[13]);
         assert_eq!(Token::Identifier("t1".into()), tokens[14]);
         assert_eq!(Token::Dot, tokens[15]);
         assert_eq!(Token::Identifier("id".into()), tokens[16]);
-        assert_eq!(Token::Op(Ordering::Equal), tokens[17]);
+        assert_eq!(Token::Op(CompareOp::Eq), tokens[17]);
         assert_eq!(Token::Int(1), tokens[18]);
         assert_eq!(Token::And, tokens[19]);
         assert_eq!(Token::Identifier("t2".into()), tokens[20]);
         assert_eq!(Token::Dot, tokens[21]);
         assert_eq!(Token::Identifier("v".into()), tokens[22]);
-        assert_eq!(Token::Op(Ordering::Less), tokens[23]);
+        assert_eq!(Token::Op(CompareOp::Lt), tokens[23]);
         assert_eq!(Token::Int(2), tokens[24]);
     }
+
+    #[test]
+    fn test_compare_ops() {
+        let tokens = Lexer::tokenize(b"= != <> < <= > >= a<=1").unwrap();
+
+        assert_eq!(
+            vec![
+                Token::Op(CompareOp::Eq),
+                Token::Op(CompareOp::Ne),
+                Token::Op(CompareOp::Ne),
+                Token::Op(CompareOp::Lt),
+                Token::Op(CompareOp::Le),
+                Token::Op(CompareOp::Gt),
+                Token::Op(CompareOp::Ge),
+                Token::Identifier("a".into()),
+                Token::Op(CompareOp::Le),
+                Token::Int(1),
+            ],
+            tokens
+        );
+        assert!(Lexer::tokenize(b"!").is_err());
+
+        assert_eq!(
+            vec![
+                Token::Identifier("ts".into()),
+                Token::Minus,
+                Token::Identifier("start".into()),
+                Token::Op(CompareOp::Lt),
+                Token::Int(3),
+            ],
+            Lexer::tokenize(b"ts-start < 3").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_scalar_call() {
+        let tokens = Lexer::tokenize(b"SELECT double(t1.a) AS d FROM t1").unwrap();
+
+        assert_eq!(
+            vec![
+                Token::Select,
+                Token::Identifier("double".into()),
+                Token::LParen,
+                Token::Identifier("t1".into()),
+                Token::Dot,
+                Token::Identifier("a".into()),
+                Token::RParen,
+                Token::As,
+                Token::Identifier("d".into()),
+                Token::From,
+                Token::Identifier("t1".into()),
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn test_union_query() {
+        let raw_query = b"SELECT FROM t1 UNION ALL SELECT FROM t2";
+
+        let tokens = Lexer::tokenize(raw_query).unwrap();
+
+        assert_eq!(
+            vec![
+                Token::Select,
+                Token::From,
+                Token::Identifier("t1".into()),
+                Token::Union,
+                Token::All,
+                Token::Select,
+                Token::From,
+                Token::Identifier("t2".into()),
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn test_create_table() {
+        let tokens =
+            Lexer::tokenize(b"CREATE TABLE t1 (a I32) WITH (layout='row', ptr_width=4)").unwrap();
+
+        assert_eq!(Token::Create, tokens[0]);
+        assert_eq!(Token::Table, tokens[1]);
+        assert_eq!(Token::Str("row".into()), tokens[11]);
+        assert_eq!(Token::Int(4), tokens[15]);
+        assert!(Lexer::tokenize(b"WITH (layout='row)").is_err());
+
+        let tokens = Lexer::tokenize(b"(id I32 DEFAULT next_id())").unwrap();
+        assert_eq!(Token::Default, tokens[3]);
+        assert_eq!(Token::Identifier("next_id".into()), tokens[4]);
+    }
 }
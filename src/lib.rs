@@ -3,13 +3,50 @@
 #![deny(clippy::nursery)]
 #![deny(clippy::cargo)]
 
+pub mod archive;
+pub mod audit;
 pub mod common;
+pub mod consistency;
+pub mod copy;
+pub mod custom_type;
+pub mod decimal;
+pub mod dictionary;
+pub mod external;
+pub mod function;
+pub mod index_advisor;
+pub mod kv;
 pub mod lexer;
-pub mod multi_table_view;
+pub mod lock;
+pub mod maintenance;
+pub mod numeric;
+pub mod operator;
 pub mod parser;
 pub mod pbase;
+pub mod plan;
+pub mod plan_cache;
+pub mod platform;
+pub mod pool;
+pub mod progress;
 pub mod query;
+pub mod query_log;
 pub mod query_tools;
+pub mod quota;
+pub mod result_set;
+pub mod row_cache;
+pub mod row_codec;
+pub mod row_view;
 pub mod schema;
+pub mod schema_diff;
+pub mod session;
+pub mod sharding;
+pub mod sketch;
+pub mod snapshot;
+pub mod statement;
+pub mod stats;
+pub mod system_tables;
+pub mod table;
 pub mod table_opener;
+pub mod tenancy;
+pub mod time_series;
 pub mod value;
+pub mod wal;
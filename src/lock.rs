@@ -0,0 +1,327 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Condvar, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use crate::common::{Error, PBaseError};
+
+// How long a lock request waits to be granted by default.
+pub const LOCK_WAIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub type TransactionId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LockMode {
+    // Held by any number of transactions at once, eg. to read a table.
+    Shared,
+    // Held by one transaction alone, eg. to write a table.
+    Exclusive,
+}
+
+///
+/// Table locks of transactions, held until the transaction releases all of them (two-phase
+/// locking, see `PBasePool::transaction`).
+///
+/// Requests of a table are granted first come, first served: a shared request waits behind an
+/// exclusive one queued before it, so writers are not starved by a stream of readers. Upgrades
+/// (an exclusive request of a shared holder) go to the front of the queue.
+///
+/// A request waits up to the wait timeout, then fails with `PBaseError::LockTimeout`. A request
+/// that would close a cycle of transactions waiting for each other fails with
+/// `PBaseError::Deadlock` right away, and the transaction is expected to release its locks and
+/// retry.
+///
+#[derive(Debug)]
+pub struct LockManager {
+    tables: Mutex<HashMap<String, TableLock>>,
+    // Signaled when a lock is released or a request leaves a queue.
+    changed: Condvar,
+    wait_timeout: Duration,
+}
+
+#[derive(Debug, Default)]
+struct TableLock {
+    holders: HashMap<TransactionId, LockMode>,
+    // Waiting requests, the next to be granted first.
+    queue: VecDeque<(TransactionId, LockMode)>,
+}
+
+impl TableLock {
+    // Whether the request is compatible with the locks held by the other transactions.
+    fn is_compatible(&self, transaction: TransactionId, mode: LockMode) -> bool {
+        self.holders.iter().all(|(holder, held_mode)| {
+            *holder == transaction || (mode == LockMode::Shared && *held_mode == LockMode::Shared)
+        })
+    }
+
+    // Transactions the queued request waits for: incompatible holders and requests ahead of it.
+    fn blockers(&self, queue_pos: usize) -> impl Iterator<Item = TransactionId> + '_ {
+        let (transaction, mode) = self.queue[queue_pos];
+        self.holders
+            .iter()
+            .filter(move |(holder, held_mode)| {
+                **holder != transaction
+                    && (mode == LockMode::Exclusive || **held_mode == LockMode::Exclusive)
+            })
+            .map(|(holder, _)| *holder)
+            .chain(self.queue.iter().take(queue_pos).map(|(ahead, _)| *ahead))
+            .filter(move |blocker| *blocker != transaction)
+    }
+}
+
+impl LockManager {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            tables: Mutex::new(HashMap::new()),
+            changed: Condvar::new(),
+            wait_timeout: LOCK_WAIT_TIMEOUT,
+        }
+    }
+
+    ///
+    /// Sets how long a request waits to be granted (`LOCK_WAIT_TIMEOUT` by default).
+    ///
+    #[must_use]
+    pub const fn with_wait_timeout(mut self, wait_timeout: Duration) -> Self {
+        self.wait_timeout = wait_timeout;
+        self
+    }
+
+    #[must_use]
+    pub const fn wait_timeout(&self) -> Duration {
+        self.wait_timeout
+    }
+
+    ///
+    /// Locks the table for the transaction, waiting for the requests queued before and for
+    /// incompatible holders. Returns right away when the transaction already holds the lock (or
+    /// an exclusive one).
+    ///
+    /// # Errors
+    ///
+    /// With `PBaseError::LockTimeout` when the lock is not granted within the wait timeout, and
+    /// with `PBaseError::Deadlock` when waiting would deadlock. The transaction keeps the locks it
+    /// holds either way.
+    pub fn lock(
+        &self,
+        transaction: TransactionId,
+        table: &str,
+        mode: LockMode,
+    ) -> Result<(), Error> {
+        let deadline = Instant::now() + self.wait_timeout;
+        let mut tables = self.tables();
+        let table_lock = tables.entry(table.to_string()).or_default();
+        let held_mode = table_lock.holders.get(&transaction).copied();
+        if held_mode >= Some(mode) {
+            return Ok(());
+        }
+        if held_mode.is_some() {
+            table_lock.queue.push_front((transaction, mode));
+        } else {
+            table_lock.queue.push_back((transaction, mode));
+        }
+
+        let err: Error = loop {
+            let table_lock = tables.entry(table.to_string()).or_default();
+            if table_lock.queue.front() == Some(&(transaction, mode))
+                && table_lock.is_compatible(transaction, mode)
+            {
+                table_lock.queue.pop_front();
+                table_lock.holders.insert(transaction, mode);
+                drop(tables);
+                // The next request may be compatible too.
+                self.changed.notify_all();
+                return Ok(());
+            }
+
+            if is_deadlocked(&tables, transaction) {
+                break PBaseError::Deadlock {
+                    table: table.to_string(),
+                    transaction,
+                }
+                .into();
+            }
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if timeout.is_zero() {
+                break PBaseError::LockTimeout {
+                    table: table.to_string(),
+                    timeout: self.wait_timeout,
+                }
+                .into();
+            }
+            tables = self
+                .changed
+                .wait_timeout(tables, timeout)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        };
+
+        // Requests behind the dropped one may be granted now.
+        tables
+            .entry(table.to_string())
+            .or_default()
+            .queue
+            .retain(|(queued, _)| *queued != transaction);
+        remove_unused(&mut tables);
+        drop(tables);
+        self.changed.notify_all();
+
+        Err(err)
+    }
+
+    ///
+    /// Releases all locks of the transaction, eg. when it ends.
+    ///
+    pub fn release_all(&self, transaction: TransactionId) {
+        let mut tables = self.tables();
+        for table_lock in tables.values_mut() {
+            table_lock.holders.remove(&transaction);
+            table_lock
+                .queue
+                .retain(|(queued, _)| *queued != transaction);
+        }
+        tables
+            .retain(|_, table_lock| !table_lock.holders.is_empty() || !table_lock.queue.is_empty());
+        drop(tables);
+        self.changed.notify_all();
+    }
+
+    ///
+    /// The lock the transaction holds on the table.
+    ///
+    #[must_use]
+    pub fn held(&self, transaction: TransactionId, table: &str) -> Option<LockMode> {
+        self.tables().get(table)?.holders.get(&transaction).copied()
+    }
+
+    ///
+    /// Requests waiting for a lock on the table.
+    ///
+    #[must_use]
+    pub fn waiting(&self, table: &str) -> usize {
+        self.tables()
+            .get(table)
+            .map_or(0, |table_lock| table_lock.queue.len())
+    }
+
+    fn tables(&self) -> MutexGuard<'_, HashMap<String, TableLock>> {
+        self.tables.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for LockManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Drops the locks nobody holds or waits for.
+fn remove_unused(tables: &mut HashMap<String, TableLock>) {
+    tables.retain(|_, table_lock| !table_lock.holders.is_empty() || !table_lock.queue.is_empty());
+}
+
+//
+// Whether the transaction waits, through a chain of waiting transactions, for itself. Only
+// requests that wait are followed: holders that wait for nothing end every chain.
+//
+fn is_deadlocked(tables: &HashMap<String, TableLock>, transaction: TransactionId) -> bool {
+    let mut waits_for: HashMap<TransactionId, Vec<TransactionId>> = HashMap::new();
+    for table_lock in tables.values() {
+        for (queue_pos, (waiting, _)) in table_lock.queue.iter().enumerate() {
+            waits_for
+                .entry(*waiting)
+                .or_default()
+                .extend(table_lock.blockers(queue_pos));
+        }
+    }
+
+    let mut visited = HashSet::new();
+    let mut stack = waits_for.get(&transaction).cloned().unwrap_or_default();
+    while let Some(blocker) = stack.pop() {
+        if blocker == transaction {
+            return true;
+        }
+        if visited.insert(blocker) {
+            stack.extend(waits_for.get(&blocker).into_iter().flatten());
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::common::PBaseError;
+
+    use super::{LockManager, LockMode};
+
+    #[test]
+    fn test_lock_queue() {
+        let locks = LockManager::new();
+        locks.lock(1, "t1", LockMode::Shared).unwrap();
+        locks.lock(2, "t1", LockMode::Shared).unwrap();
+        locks.lock(1, "t1", LockMode::Shared).unwrap();
+        assert_eq!(Some(LockMode::Shared), locks.held(2, "t1"));
+
+        std::thread::scope(|scope| {
+            let writer = scope.spawn(|| locks.lock(3, "t1", LockMode::Exclusive));
+            while locks.waiting("t1") < 1 {
+                std::thread::yield_now();
+            }
+            // Compatible with the holders, but queued behind the writer.
+            let reader = scope.spawn(|| locks.lock(4, "t1", LockMode::Shared));
+            while locks.waiting("t1") < 2 {
+                std::thread::yield_now();
+            }
+
+            locks.release_all(1);
+            locks.release_all(2);
+            writer.join().unwrap().unwrap();
+            assert_eq!(1, locks.waiting("t1"));
+            locks.release_all(3);
+            reader.join().unwrap().unwrap();
+        });
+        assert_eq!(Some(LockMode::Shared), locks.held(4, "t1"));
+        assert_eq!(None, locks.held(3, "t1"));
+
+        locks.release_all(4);
+        assert!(locks.tables().is_empty());
+
+        let locks = LockManager::new().with_wait_timeout(Duration::from_millis(10));
+        locks.lock(1, "t1", LockMode::Exclusive).unwrap();
+        let err = locks.lock(2, "t1", LockMode::Shared).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<PBaseError>(),
+            Some(PBaseError::LockTimeout { .. })
+        ));
+        assert_eq!(0, locks.waiting("t1"));
+    }
+
+    #[test]
+    fn test_lock_upgrade_deadlock() {
+        let locks = LockManager::new();
+        locks.lock(1, "t1", LockMode::Shared).unwrap();
+        locks.lock(2, "t1", LockMode::Shared).unwrap();
+
+        std::thread::scope(|scope| {
+            let upgrade = scope.spawn(|| locks.lock(1, "t1", LockMode::Exclusive));
+            while locks.waiting("t1") == 0 {
+                std::thread::yield_now();
+            }
+
+            let err = locks.lock(2, "t1", LockMode::Exclusive).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<PBaseError>(),
+                Some(PBaseError::Deadlock { transaction: 2, .. })
+            ));
+            // The victim gives up its locks, the other transaction goes on.
+            locks.release_all(2);
+            upgrade.join().unwrap().unwrap();
+        });
+        assert_eq!(Some(LockMode::Exclusive), locks.held(1, "t1"));
+    }
+}
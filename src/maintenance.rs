@@ -0,0 +1,461 @@
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use log::{debug, warn};
+
+use crate::{
+    common::Error,
+    pbase::PBase,
+    platform::{replace_file, tmp_file_name},
+};
+
+///
+/// A unit of background work (index merge, stats refresh, purge, compaction, ...).
+///
+pub trait MaintenanceTask: Send {
+    fn name(&self) -> &'static str;
+
+    /// # Errors
+    ///
+    /// Errors when the task could not complete. It is retried on its next due time.
+    fn run(&mut self, db: &PBase) -> Result<(), Error>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaintenancePolicy {
+    // Minimum time between two runs of the task.
+    pub interval: Duration,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct MaintenanceRun {
+    pub task: &'static str,
+    pub error: Option<String>,
+}
+
+struct ScheduledTask {
+    task: Box<dyn MaintenanceTask>,
+    policy: MaintenancePolicy,
+    last_run: Option<Instant>,
+}
+
+///
+/// Runs registered tasks when their policy makes them due. Drive it with explicit
+/// `run_maintenance` ticks, or hand it to a background thread with `spawn`.
+///
+#[derive(Default)]
+pub struct MaintenanceScheduler {
+    tasks: Vec<ScheduledTask>,
+}
+
+impl MaintenanceScheduler {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, task: Box<dyn MaintenanceTask>, policy: MaintenancePolicy) {
+        self.tasks.push(ScheduledTask {
+            task,
+            policy,
+            last_run: None,
+        });
+    }
+
+    ///
+    /// Runs every task that never ran or whose interval elapsed by `now`, in registration order.
+    ///
+    pub fn run_maintenance(&mut self, db: &PBase, now: Instant) -> Vec<MaintenanceRun> {
+        let mut runs = vec![];
+        for scheduled in &mut self.tasks {
+            let is_due = scheduled.last_run.is_none_or(|last_run| {
+                now.saturating_duration_since(last_run) >= scheduled.policy.interval
+            });
+            if !is_due {
+                continue;
+            }
+
+            debug!("Running maintenance task: {}", scheduled.task.name());
+            let result = scheduled.task.run(db);
+            scheduled.last_run = Some(now);
+
+            if let Err(err) = &result {
+                warn!("Maintenance task {} failed: {err}", scheduled.task.name());
+            }
+            runs.push(MaintenanceRun {
+                task: scheduled.task.name(),
+                error: result.err().map(|err| err.to_string()),
+            });
+        }
+
+        runs
+    }
+
+    ///
+    /// Ticks the scheduler every `tick` on a background thread until the handle is stopped.
+    ///
+    #[must_use]
+    pub fn spawn(mut self, db: Arc<PBase>, tick: Duration) -> MaintenanceHandle {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        let thread = std::thread::spawn(move || {
+            while !thread_stop.load(Ordering::Relaxed) {
+                self.run_maintenance(&db, Instant::now());
+                std::thread::park_timeout(tick);
+            }
+            self
+        });
+
+        MaintenanceHandle { stop, thread }
+    }
+}
+
+pub struct MaintenanceHandle {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<MaintenanceScheduler>,
+}
+
+impl MaintenanceHandle {
+    ///
+    /// Stops the background thread (after its current tick) and returns the scheduler.
+    ///
+    /// # Panics
+    ///
+    /// When a task panicked on the background thread.
+    #[must_use]
+    pub fn stop(self) -> MaintenanceScheduler {
+        self.stop.store(true, Ordering::Relaxed);
+        self.thread.thread().unpark();
+        self.thread.join().expect("Maintenance thread panicked")
+    }
+}
+
+// Bytes maintenance reads or writes between two throttle checks.
+pub const IO_THROTTLE_CHUNK_BYTES: usize = 64 * 1024;
+
+///
+/// A bytes per second budget for the IO of maintenance work (index merges), so that it does not
+/// starve foreground queries on slow disks. Unlimited by default.
+///
+/// Work reports the bytes it moves with `consume`, which sleeps as long as the work is ahead of
+/// the budget. Idle time is not saved up: work resuming after a pause is paced from then on.
+///
+#[derive(Debug, Default)]
+pub struct IoThrottle {
+    // Bytes per second, 0 for unlimited.
+    bytes_per_sec: AtomicU64,
+    // Start of the current burst of work and the bytes consumed since.
+    window: Mutex<Option<(Instant, u64)>>,
+}
+
+impl IoThrottle {
+    #[must_use]
+    pub fn new(bytes_per_sec: Option<u64>) -> Self {
+        let throttle = Self::default();
+        throttle.set_limit(bytes_per_sec);
+        throttle
+    }
+
+    pub fn set_limit(&self, bytes_per_sec: Option<u64>) {
+        self.bytes_per_sec
+            .store(bytes_per_sec.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn limit(&self) -> Option<u64> {
+        Some(self.bytes_per_sec.load(Ordering::Relaxed)).filter(|limit| *limit > 0)
+    }
+
+    ///
+    /// Records the bytes as done at `now`, and returns how long to wait for the work to get back
+    /// within the budget.
+    ///
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    pub fn delay(&self, bytes: usize, now: Instant) -> Duration {
+        let Some(limit) = self.limit() else {
+            return Duration::ZERO;
+        };
+
+        let mut window = self.window.lock().unwrap();
+        let (start, consumed) = window.get_or_insert((now, 0));
+        let budget_end = *start + budget_time(*consumed, limit);
+        if budget_end < now {
+            // Behind the budget, the work was idle.
+            *start = now;
+            *consumed = 0;
+        }
+        *consumed += u64::try_from(bytes).unwrap_or(u64::MAX);
+
+        let due = *start + budget_time(*consumed, limit);
+        drop(window);
+        due.saturating_duration_since(now)
+    }
+
+    ///
+    /// Records the bytes as done, sleeping while the work is ahead of the budget.
+    ///
+    pub fn consume(&self, bytes: usize) {
+        let delay = self.delay(bytes, Instant::now());
+        if !delay.is_zero() {
+            std::thread::sleep(delay);
+        }
+    }
+
+    ///
+    /// Reads the file in chunks of `IO_THROTTLE_CHUNK_BYTES`, within the budget.
+    ///
+    /// # Errors
+    ///
+    /// On file operations.
+    pub fn read(&self, file_name: &Path) -> Result<Vec<u8>, Error> {
+        let mut file = File::open(file_name)?;
+        let mut bytes = vec![];
+        loop {
+            let chunk_len = (&mut file)
+                .take(IO_THROTTLE_CHUNK_BYTES as u64)
+                .read_to_end(&mut bytes)?;
+            if chunk_len == 0 {
+                return Ok(bytes);
+            }
+            self.consume(chunk_len);
+        }
+    }
+
+    ///
+    /// `platform::atomic_write`, writing in chunks of `IO_THROTTLE_CHUNK_BYTES` within the budget.
+    ///
+    /// # Errors
+    ///
+    /// On file operations.
+    pub fn atomic_write(&self, file_name: &Path, bytes: &[u8]) -> Result<(), Error> {
+        let tmp_file_name = tmp_file_name(file_name);
+        let mut tmp_file = File::create(&tmp_file_name)?;
+        for chunk in bytes.chunks(IO_THROTTLE_CHUNK_BYTES) {
+            tmp_file.write_all(chunk)?;
+            self.consume(chunk.len());
+        }
+        tmp_file.sync_all()?;
+        drop(tmp_file);
+
+        replace_file(&tmp_file_name, file_name)
+    }
+}
+
+// How long moving the bytes takes at the budget.
+fn budget_time(bytes: u64, bytes_per_sec: u64) -> Duration {
+    let nanos = u128::from(bytes) * 1_000_000_000 / u128::from(bytes_per_sec);
+    Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+}
+
+///
+/// Verifies the whole directory (see `PBase::check_all`), failing when any issue is found.
+///
+pub struct ConsistencyCheckTask;
+
+impl MaintenanceTask for ConsistencyCheckTask {
+    fn name(&self) -> &'static str {
+        "consistency_check"
+    }
+
+    fn run(&mut self, db: &PBase) -> Result<(), Error> {
+        let report = db.check_all()?;
+        if report.is_ok() {
+            Ok(())
+        } else {
+            Err(serde_json::to_string(&report)?.into())
+        }
+    }
+}
+
+///
+/// Merges the index deltas of every table into their sorted indices (see
+/// `PBase::merge_index_deltas`), so reads scan fewer unsorted entries between insert batches.
+///
+pub struct IndexMergeTask;
+
+impl MaintenanceTask for IndexMergeTask {
+    fn name(&self) -> &'static str {
+        "index_merge"
+    }
+
+    fn run(&mut self, db: &PBase) -> Result<(), Error> {
+        db.merge_index_deltas()
+    }
+}
+
+// Fraction of a table's rows modified before its stats are refreshed by default.
+pub const STATS_REFRESH_CHANGED_FRACTION: f64 = 0.2;
+
+///
+/// Analyzes again the tables of which `changed_fraction` of the rows were modified since their
+/// last ANALYZE (see `PBase::refresh_stale_stats`).
+///
+pub struct StatsRefreshTask {
+    pub changed_fraction: f64,
+}
+
+impl Default for StatsRefreshTask {
+    fn default() -> Self {
+        Self {
+            changed_fraction: STATS_REFRESH_CHANGED_FRACTION,
+        }
+    }
+}
+
+impl MaintenanceTask for StatsRefreshTask {
+    fn name(&self) -> &'static str {
+        "stats_refresh"
+    }
+
+    fn run(&mut self, db: &PBase) -> Result<(), Error> {
+        let analyzed = db.refresh_stale_stats(self.changed_fraction)?;
+        if !analyzed.is_empty() {
+            debug!("Refreshed stats of: {}", analyzed.join(", "));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    };
+
+    use crate::{common::Error, pbase::PBase};
+
+    use super::{
+        IoThrottle, MaintenancePolicy, MaintenanceRun, MaintenanceScheduler, MaintenanceTask,
+    };
+
+    struct CountingTask {
+        name: &'static str,
+        runs: Arc<AtomicUsize>,
+    }
+
+    impl MaintenanceTask for CountingTask {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn run(&mut self, _db: &PBase) -> Result<(), Error> {
+            self.runs.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    fn counting_task(name: &'static str) -> (Box<CountingTask>, Arc<AtomicUsize>) {
+        let runs = Arc::new(AtomicUsize::new(0));
+        (
+            Box::new(CountingTask {
+                name,
+                runs: runs.clone(),
+            }),
+            runs,
+        )
+    }
+
+    #[test]
+    fn test_run_maintenance_respects_intervals() {
+        let db = PBase::new(PathBuf::new());
+        let mut scheduler = MaintenanceScheduler::new();
+
+        let (fast_task, fast_runs) = counting_task("fast");
+        let (slow_task, slow_runs) = counting_task("slow");
+        scheduler.register(
+            fast_task,
+            MaintenancePolicy {
+                interval: Duration::from_secs(1),
+            },
+        );
+        scheduler.register(
+            slow_task,
+            MaintenancePolicy {
+                interval: Duration::from_secs(10),
+            },
+        );
+
+        let start = Instant::now();
+        assert_eq!(
+            vec![
+                MaintenanceRun {
+                    task: "fast",
+                    error: None
+                },
+                MaintenanceRun {
+                    task: "slow",
+                    error: None
+                },
+            ],
+            scheduler.run_maintenance(&db, start)
+        );
+
+        for secs in 1..=10 {
+            scheduler.run_maintenance(&db, start + Duration::from_secs(secs));
+        }
+
+        assert_eq!(11, fast_runs.load(Ordering::Relaxed));
+        assert_eq!(2, slow_runs.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_spawned_scheduler() {
+        let mut scheduler = MaintenanceScheduler::new();
+        let (task, runs) = counting_task("task");
+        scheduler.register(
+            task,
+            MaintenancePolicy {
+                interval: Duration::ZERO,
+            },
+        );
+
+        let handle = scheduler.spawn(
+            Arc::new(PBase::new(PathBuf::new())),
+            Duration::from_millis(1),
+        );
+        while runs.load(Ordering::Relaxed) < 3 {
+            std::thread::yield_now();
+        }
+        let _scheduler = handle.stop();
+    }
+
+    #[test]
+    fn test_io_throttle_delay() {
+        let now = Instant::now();
+        assert_eq!(Duration::ZERO, IoThrottle::default().delay(1 << 30, now));
+
+        let throttle = IoThrottle::new(Some(1000));
+        assert_eq!(Duration::from_millis(500), throttle.delay(500, now));
+        assert_eq!(Duration::from_secs(1), throttle.delay(500, now));
+        assert_eq!(
+            Duration::from_millis(500),
+            throttle.delay(500, now + Duration::from_secs(1))
+        );
+        // Idle time is not saved up.
+        assert_eq!(
+            Duration::from_millis(100),
+            throttle.delay(100, now + Duration::from_secs(10))
+        );
+
+        throttle.set_limit(None);
+        assert_eq!(None, throttle.limit());
+        assert_eq!(Duration::ZERO, throttle.delay(1 << 30, now));
+    }
+}
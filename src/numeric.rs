@@ -0,0 +1,191 @@
+use crate::{
+    common::{Error, PBaseError},
+    decimal::{Decimal, MAX_DECIMAL_PRECISION},
+    value::Value,
+};
+
+///
+/// Running sum and count of numeric values, for SUM and AVG.
+///
+/// Values are accumulated in an i128, which no number of U8 or I32 values can overflow, and only
+/// the result is narrowed to the I32 result type: a sum that does not fit is an error, never a
+/// wrapped value. NULLs are skipped, and so are values of custom types, which define no arithmetic.
+///
+/// Once a decimal is added the sum is exact at the largest scale added, and the results are
+/// decimals.
+///
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SumAccumulator {
+    sum: i128,
+    scale: u8,
+    count: u64,
+    decimal: bool,
+    overflow: bool,
+}
+
+impl SumAccumulator {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            sum: 0,
+            scale: 0,
+            count: 0,
+            decimal: false,
+            overflow: false,
+        }
+    }
+
+    pub fn add(&mut self, value: &Value) {
+        let addend = match value {
+            Value::NULL | Value::Custom(_) => return,
+            Value::I32(v) => Decimal::from(*v),
+            Value::U8(v) => Decimal::from(i32::from(*v)),
+            Value::Decimal(v) => {
+                self.decimal = true;
+                *v
+            }
+        };
+        self.count += 1;
+
+        match Decimal::new(self.sum, self.scale).checked_add(&addend) {
+            Some(sum) => {
+                self.sum = sum.unscaled;
+                self.scale = sum.scale;
+            }
+            None => self.overflow = true,
+        }
+    }
+
+    ///
+    /// Number of (non NULL) values added.
+    ///
+    #[must_use]
+    pub const fn count(&self) -> u64 {
+        self.count
+    }
+
+    ///
+    /// The sum as an I32 (a decimal once decimals were added), NULL when no value was added.
+    ///
+    /// # Errors
+    ///
+    /// Errors when the sum does not fit into an I32, or into a decimal of the largest precision.
+    pub fn sum(&self) -> Result<Value, Error> {
+        if self.count == 0 {
+            return Ok(Value::NULL);
+        }
+
+        let sum = self.decimal_sum()?;
+        if self.decimal {
+            return Ok(Value::Decimal(sum));
+        }
+        i32::try_from(self.sum)
+            .map(Value::I32)
+            .map_err(|_| PBaseError::NumericOverflow(format!("sum {}", self.sum)).into())
+    }
+
+    ///
+    /// The average as an I32 truncated toward zero (a decimal truncated at the sum's scale once
+    /// decimals were added), NULL when no value was added.
+    ///
+    /// # Errors
+    ///
+    /// Errors when the average does not fit into an I32 (which cannot happen for U8 and I32
+    /// values), or when the sum of decimals overflowed.
+    pub fn avg(&self) -> Result<Value, Error> {
+        if self.count == 0 {
+            return Ok(Value::NULL);
+        }
+
+        let avg = self.decimal_sum()?.checked_div_int(i128::from(self.count));
+        match avg {
+            Some(avg) if self.decimal => Ok(Value::Decimal(avg)),
+            Some(avg) => i32::try_from(avg.unscaled)
+                .map(Value::I32)
+                .map_err(|_| PBaseError::NumericOverflow(format!("average {avg}")).into()),
+            None => Err(PBaseError::NumericOverflow("average".into()).into()),
+        }
+    }
+
+    // The sum so far, unless it overflowed the widest decimal.
+    fn decimal_sum(&self) -> Result<Decimal, Error> {
+        let sum = Decimal::new(self.sum, self.scale);
+        if self.overflow || sum.digits() > u32::from(MAX_DECIMAL_PRECISION) {
+            return Err(PBaseError::NumericOverflow("decimal sum".into()).into());
+        }
+        Ok(sum)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{decimal::Decimal, value::Value};
+
+    use super::SumAccumulator;
+
+    fn accumulate(values: &[Value]) -> SumAccumulator {
+        let mut acc = SumAccumulator::new();
+        for value in values {
+            acc.add(value);
+        }
+        acc
+    }
+
+    #[test]
+    fn test_sum_accumulator() {
+        let empty = accumulate(&[Value::NULL]);
+        assert_eq!(0, empty.count());
+        assert_eq!(Value::NULL, empty.sum().unwrap());
+        assert_eq!(Value::NULL, empty.avg().unwrap());
+
+        // U8 values do not wrap at 255.
+        let bytes = accumulate(&[Value::U8(u8::MAX), Value::U8(u8::MAX), Value::NULL]);
+        assert_eq!(2, bytes.count());
+        assert_eq!(Value::I32(510), bytes.sum().unwrap());
+        assert_eq!(Value::I32(255), bytes.avg().unwrap());
+
+        let max = accumulate(&[Value::I32(i32::MAX), Value::I32(0)]);
+        assert_eq!(Value::I32(i32::MAX), max.sum().unwrap());
+
+        let overflow = accumulate(&[Value::I32(i32::MAX), Value::I32(1)]);
+        assert!(overflow.sum().is_err());
+        assert_eq!(Value::I32(1 << 30), overflow.avg().unwrap());
+
+        let underflow = accumulate(&[Value::I32(i32::MIN), Value::I32(i32::MIN)]);
+        assert!(underflow.sum().is_err());
+        assert_eq!(Value::I32(i32::MIN), underflow.avg().unwrap());
+
+        // Back in range after overflowing on the way.
+        let recovered = accumulate(&[Value::I32(i32::MAX), Value::I32(i32::MAX), Value::I32(-3)]);
+        assert!(recovered.sum().is_err());
+        let recovered = accumulate(&[
+            Value::I32(i32::MAX),
+            Value::I32(i32::MAX),
+            Value::I32(i32::MIN),
+        ]);
+        assert_eq!(Value::I32(i32::MAX - 1), recovered.sum().unwrap());
+
+        // Truncated toward zero.
+        assert_eq!(
+            Value::I32(-1),
+            accumulate(&[Value::I32(-1), Value::I32(-2)]).avg().unwrap()
+        );
+
+        // Exact at the largest scale added.
+        let decimals = accumulate(&[
+            Value::Decimal(Decimal::new(10, 1)),
+            Value::Decimal(Decimal::new(-333, 2)),
+            Value::I32(3),
+        ]);
+        assert_eq!(Value::Decimal(Decimal::new(67, 2)), decimals.sum().unwrap());
+        assert_eq!(Value::Decimal(Decimal::new(22, 2)), decimals.avg().unwrap());
+
+        let overflow = accumulate(&[
+            Value::Decimal(Decimal::new(i128::MAX, 0)),
+            Value::Decimal(Decimal::new(1, 0)),
+            Value::Decimal(Decimal::new(-1, 0)),
+        ]);
+        assert!(overflow.sum().is_err());
+        assert!(overflow.avg().is_err());
+    }
+}
@@ -0,0 +1,1382 @@
+use std::{
+    cell::Cell,
+    cmp::Ordering,
+    collections::{HashMap, HashSet, VecDeque},
+    iter::Peekable,
+    rc::Rc,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use indexmap::IndexMap;
+use rand::{rngs::ThreadRng, Rng};
+
+use crate::{
+    common::{binary_narrow_to_range_exclusive, Error, PBaseError},
+    function::ScalarFn,
+    query::{CallFilter, JoinType, RhsValue, RowFilter, SampleSpec},
+    schema::{FieldSchema, TablePtrType, TableReader, TableRowPositionIterator, TableSchema},
+    table_opener::{BlockReader, FileBytes, BLOCK_ROWS},
+    value::Value,
+};
+
+///
+/// Key of a result row column: the full field name (`table.field`). Shared between the rows of a
+/// result instead of allocated per cell.
+///
+pub type ColumnKey = Arc<str>;
+
+///
+/// A materialized result row, in column order.
+///
+pub type Row = IndexMap<ColumnKey, Value>;
+
+///
+/// A node of the execution tree. Rows are pulled one by one from the root, which pulls from its
+/// children on demand.
+///
+pub trait Operator {
+    /// # Errors
+    ///
+    /// Errors when reading the underlying data fails.
+    fn next_row(&mut self) -> Result<Option<Row>, Error>;
+}
+
+/// # Errors
+///
+/// Errors when any operator of the tree fails.
+pub fn collect_rows(operator: &mut dyn Operator) -> Result<Vec<Row>, Error> {
+    let mut rows = vec![];
+    while let Some(row) = operator.next_row()? {
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+///
+/// Column keys of a table's fields, in schema order.
+///
+#[must_use]
+pub fn table_column_keys(table_schema: &TableSchema) -> Vec<ColumnKey> {
+    table_schema
+        .fields
+        .keys()
+        .map(|field_name| format!("{}.{field_name}", table_schema.name).into())
+        .collect()
+}
+
+fn read_table_row(table_schema: &TableSchema, column_keys: &[ColumnKey], row_bytes: &[u8]) -> Row {
+    let mut field_pos = 0;
+    table_schema
+        .fields
+        .keys()
+        .zip(column_keys)
+        .map(|(field_name, column_key)| {
+            let value = table_schema.stored_value_from_bytes(field_name, &row_bytes[field_pos..]);
+            field_pos += table_schema.stored_byte_size(field_name);
+            (column_key.clone(), value)
+        })
+        .collect()
+}
+
+///
+/// Rows from memory. Used for already materialized results.
+///
+pub struct Values {
+    rows: std::vec::IntoIter<Row>,
+}
+
+impl Values {
+    #[must_use]
+    pub fn new(rows: Vec<Row>) -> Self {
+        Self {
+            rows: rows.into_iter(),
+        }
+    }
+}
+
+impl Operator for Values {
+    fn next_row(&mut self) -> Result<Option<Row>, Error> {
+        Ok(self.rows.next())
+    }
+}
+
+///
+/// Full table scan in storage order.
+///
+pub struct Scan<'a> {
+    table_schema: &'a TableSchema,
+    table_bytes: BlockReader<&'a [u8]>,
+    column_keys: Vec<ColumnKey>,
+    positions: SampledPositions,
+}
+
+impl<'a> Scan<'a> {
+    #[must_use]
+    pub fn new(table_schema: &'a TableSchema, table_bytes: &'a [u8]) -> Self {
+        Self {
+            table_schema,
+            table_bytes: table_block_reader(table_schema, table_bytes),
+            column_keys: table_column_keys(table_schema),
+            positions: SampledPositions::new(table_schema.row_byte_size(), table_bytes.len(), None),
+        }
+    }
+
+    ///
+    /// Reads only the sampled rows.
+    ///
+    #[must_use]
+    pub fn with_sample(mut self, sample: Option<SampleSpec>) -> Self {
+        self.positions = SampledPositions::new(
+            self.table_schema.row_byte_size(),
+            self.table_bytes.len(),
+            sample,
+        );
+        self
+    }
+}
+
+///
+/// Row positions of a table, all of them or a sample (see `SampleSpec`). Rows sampled out are
+/// skipped before being read.
+///
+pub struct SampledPositions {
+    positions: TableRowPositionIterator,
+    sample: Option<SampleSpec>,
+    taken: usize,
+    rng: ThreadRng,
+}
+
+impl SampledPositions {
+    #[must_use]
+    pub fn new(row_size: usize, table_size: usize, sample: Option<SampleSpec>) -> Self {
+        Self {
+            positions: TableRowPositionIterator::new(row_size, table_size),
+            sample,
+            taken: 0,
+            rng: rand::rng(),
+        }
+    }
+}
+
+impl Iterator for SampledPositions {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.sample {
+            None => self.positions.next(),
+            Some(SampleSpec::First(count)) => {
+                if self.taken >= count {
+                    return None;
+                }
+                self.taken += 1;
+                self.positions.next()
+            }
+            Some(SampleSpec::Percent(percent)) => self
+                .positions
+                .find(|_| self.rng.random_range(0..100) < percent),
+        }
+    }
+}
+
+impl Operator for Scan<'_> {
+    fn next_row(&mut self) -> Result<Option<Row>, Error> {
+        let Some(pos) = self.positions.next() else {
+            return Ok(None);
+        };
+        let row_bytes = self
+            .table_bytes
+            .read(pos, self.table_schema.row_byte_size())?;
+
+        Ok(Some(read_table_row(
+            self.table_schema,
+            &self.column_keys,
+            &row_bytes,
+        )))
+    }
+}
+
+///
+/// Reader of table data in blocks of `BLOCK_ROWS` rows.
+///
+#[must_use]
+pub fn table_block_reader<'a>(
+    table_schema: &TableSchema,
+    table_bytes: &'a [u8],
+) -> BlockReader<&'a [u8]> {
+    BlockReader::plain(
+        table_bytes,
+        table_schema.row_byte_size().max(1) * BLOCK_ROWS,
+    )
+}
+
+///
+/// Table data positions of the rows of index ranges, in index order, or in reverse index order
+/// when descending.
+///
+/// Ranges are exclusive on both ends (line indices), as produced by the binary narrowing helpers,
+/// and read one after the other (the ranges of IN list probes are in index order). Descending
+/// reads walk the ranges backwards from their ends, without collecting the positions first. Rows
+/// of the index delta (already matched and sorted by key) are merged in, after sorted entries of
+/// the same key (before them when descending).
+///
+pub struct IndexRowPositions<'a> {
+    table_schema: &'a TableSchema,
+    table_bytes: BlockReader<&'a [u8]>,
+    index_name: String,
+    index_bytes: BlockReader<FileBytes>,
+    // The part of the current range not read yet, exclusive on both ends.
+    range: (i32, i32),
+    ranges_left: std::vec::IntoIter<(i32, i32)>,
+    descending: bool,
+    delta_rows: Peekable<std::vec::IntoIter<TablePtrType>>,
+}
+
+impl<'a> IndexRowPositions<'a> {
+    #[must_use]
+    pub fn new(
+        table_schema: &'a TableSchema,
+        table_bytes: &'a [u8],
+        index_name: String,
+        index_bytes: FileBytes,
+        mut ranges: Vec<(i32, i32)>,
+        mut delta_rows: Vec<TablePtrType>,
+        descending: bool,
+    ) -> Self {
+        if descending {
+            ranges.reverse();
+            delta_rows.reverse();
+        }
+
+        let index_row_byte_size = table_schema.index_row_byte_size(&index_name);
+        Self {
+            table_schema,
+            table_bytes: table_block_reader(table_schema, table_bytes),
+            index_name,
+            index_bytes: BlockReader::plain(index_bytes, index_row_byte_size * BLOCK_ROWS),
+            range: (-1, 0),
+            ranges_left: ranges.into_iter(),
+            descending,
+            delta_rows: delta_rows.into_iter().peekable(),
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Errors when the index range is out of bounds, or with `PBaseError::CorruptIndex` when a row
+    /// pointer is not the position of a row of the table.
+    pub fn next_pos(&mut self) -> Result<Option<usize>, Error> {
+        while self.range.1 - self.range.0 <= 1 {
+            let Some(range) = self.ranges_left.next() else {
+                break;
+            };
+            self.range = range;
+        }
+
+        let sorted_pos = if self.range.1 - self.range.0 > 1 {
+            let sorted_idx = if self.descending {
+                self.range.1 - 1
+            } else {
+                self.range.0 + 1
+            };
+            Some(self.index_row_ptr(usize::try_from(sorted_idx)?)?)
+        } else {
+            None
+        };
+        let delta_pos = self
+            .delta_rows
+            .peek()
+            .copied()
+            .map(|row_ptr| self.row_pos(row_ptr))
+            .transpose()?;
+
+        Ok(match (sorted_pos, delta_pos) {
+            (None, None) => None,
+            (Some(sorted_pos), Some(delta_pos))
+                if self.is_delta_first(delta_pos, sorted_pos)? =>
+            {
+                self.delta_rows.next();
+                Some(delta_pos)
+            }
+            (Some(sorted_pos), _) => {
+                if self.descending {
+                    self.range.1 -= 1;
+                } else {
+                    self.range.0 += 1;
+                }
+                Some(sorted_pos)
+            }
+            (None, Some(delta_pos)) => {
+                self.delta_rows.next();
+                Some(delta_pos)
+            }
+        })
+    }
+
+    fn is_delta_first(&self, delta_pos: usize, sorted_pos: usize) -> Result<bool, Error> {
+        let delta_key = self.index_key(delta_pos)?;
+        let sorted_key = self.index_key(sorted_pos)?;
+
+        Ok(if self.descending {
+            delta_key >= sorted_key
+        } else {
+            delta_key < sorted_key
+        })
+    }
+
+    fn index_row_ptr(&self, index_idx: usize) -> Result<usize, Error> {
+        let index_row_pos = index_idx * self.table_schema.index_row_byte_size(&self.index_name);
+        let ptr_pos = index_row_pos
+            + self
+                .table_schema
+                .index_row_ptr_field_byte_pos(&self.index_name);
+        let ptr_bytes = self
+            .index_bytes
+            .read(ptr_pos, self.table_schema.storage.ptr_width)?;
+
+        self.row_pos(self.table_schema.index_row_ptr_from_bytes(&ptr_bytes))
+    }
+
+    //
+    // Checks that the row pointer is the start of a row in the table data, as a garbage index can
+    // point anywhere.
+    //
+    fn row_pos(&self, row_ptr: TablePtrType) -> Result<usize, Error> {
+        let row_byte_size = self.table_schema.row_byte_size();
+        usize::try_from(row_ptr)
+            .ok()
+            .filter(|row_pos| {
+                row_pos % row_byte_size.max(1) == 0
+                    && row_pos
+                        .checked_add(row_byte_size)
+                        .is_some_and(|row_end| row_end <= self.table_bytes.len())
+            })
+            .ok_or_else(|| {
+                PBaseError::CorruptIndex {
+                    table: self.table_schema.name.clone(),
+                    index: self.index_name.clone(),
+                    reason: format!(
+                        "row pointer {row_ptr} is not a row of the table ({} bytes)",
+                        self.table_bytes.len()
+                    ),
+                }
+                .into()
+            })
+    }
+
+    fn index_key(&self, row_pos: usize) -> Result<Vec<Value>, Error> {
+        let row_bytes = self
+            .table_bytes
+            .read(row_pos, self.table_schema.row_byte_size())?;
+        let table_reader = TableReader::new(self.table_schema, &row_bytes, row_pos);
+
+        Ok(self.table_schema.indices[&self.index_name]
+            .iter()
+            .map(|index_field| table_reader.get_field_value(index_field))
+            .collect())
+    }
+}
+
+///
+/// Reads the table rows of index ranges (see `IndexRowPositions`), in index order or in reverse
+/// index order when descending.
+///
+pub struct IndexScan<'a> {
+    table_schema: &'a TableSchema,
+    table_bytes: BlockReader<&'a [u8]>,
+    column_keys: Vec<ColumnKey>,
+    positions: IndexRowPositions<'a>,
+}
+
+impl<'a> IndexScan<'a> {
+    #[must_use]
+    pub fn new(
+        table_schema: &'a TableSchema,
+        table_bytes: &'a [u8],
+        index_name: String,
+        index_bytes: FileBytes,
+        ranges: Vec<(i32, i32)>,
+        delta_rows: Vec<TablePtrType>,
+        descending: bool,
+    ) -> Self {
+        Self {
+            table_schema,
+            table_bytes: table_block_reader(table_schema, table_bytes),
+            column_keys: table_column_keys(table_schema),
+            positions: IndexRowPositions::new(
+                table_schema,
+                table_bytes,
+                index_name,
+                index_bytes,
+                ranges,
+                delta_rows,
+                descending,
+            ),
+        }
+    }
+}
+
+impl Operator for IndexScan<'_> {
+    fn next_row(&mut self) -> Result<Option<Row>, Error> {
+        let Some(row_pos) = self.positions.next_pos()? else {
+            return Ok(None);
+        };
+        let row_bytes = self
+            .table_bytes
+            .read(row_pos, self.table_schema.row_byte_size())?;
+
+        Ok(Some(read_table_row(
+            self.table_schema,
+            &self.column_keys,
+            &row_bytes,
+        )))
+    }
+}
+
+///
+/// Keeps rows matching all (AND-ed) filters.
+///
+pub struct Filter<'a> {
+    child: Box<dyn Operator + 'a>,
+    filters: Vec<RowFilter>,
+}
+
+impl<'a> Filter<'a> {
+    #[must_use]
+    pub fn new(child: Box<dyn Operator + 'a>, filters: Vec<RowFilter>) -> Self {
+        Self { child, filters }
+    }
+
+    fn is_match(&self, row: &Row) -> bool {
+        self.filters.iter().all(|filter| {
+            let lhs_value = &row[filter.field.full_name().as_str()];
+            match &filter.rhs {
+                RhsValue::Ref(reference) | RhsValue::Interval { reference, .. } => {
+                    filter.matches_values(lhs_value, &row[reference.full_name().as_str()])
+                }
+                RhsValue::Value(_)
+                | RhsValue::In(_)
+                | RhsValue::Range { .. }
+                | RhsValue::Subquery { .. } => filter.matches_value(lhs_value),
+            }
+        })
+    }
+}
+
+impl Operator for Filter<'_> {
+    fn next_row(&mut self) -> Result<Option<Row>, Error> {
+        while let Some(row) = self.child.next_row()? {
+            if self.is_match(&row) {
+                return Ok(Some(row));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+///
+/// A scalar function result column: `column` is set to the function applied to `arg`.
+///
+pub struct ComputedColumn {
+    pub column: ColumnKey,
+    pub arg: ColumnKey,
+    pub body: ScalarFn,
+}
+
+///
+/// Adds the computed columns to each row, then keeps rows whose computed values match all (AND-ed)
+/// filters.
+///
+pub struct Compute<'a> {
+    child: Box<dyn Operator + 'a>,
+    columns: Vec<ComputedColumn>,
+    filters: Vec<CallFilter>,
+}
+
+impl<'a> Compute<'a> {
+    #[must_use]
+    pub fn new(
+        child: Box<dyn Operator + 'a>,
+        columns: Vec<ComputedColumn>,
+        filters: Vec<CallFilter>,
+    ) -> Self {
+        Self {
+            child,
+            columns,
+            filters,
+        }
+    }
+}
+
+impl Operator for Compute<'_> {
+    fn next_row(&mut self) -> Result<Option<Row>, Error> {
+        while let Some(mut row) = self.child.next_row()? {
+            for computed in &self.columns {
+                let value = (computed.body)(&row[&computed.arg]);
+                row.insert(computed.column.clone(), value);
+            }
+
+            if self
+                .filters
+                .iter()
+                .all(|filter| filter.op.compare(&row[filter.alias.as_str()], &filter.rhs))
+            {
+                return Ok(Some(row));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+///
+/// Equi-join, inner unless set otherwise with `with_join_type`.
+///
+/// The right side is loaded into a hash table on the first pull, then left rows are streamed and
+/// matched. Output keeps left order, then right order among the matches.
+///
+/// With `first_match` each left row is joined to its first matching right row only.
+///
+/// Rows with a NULL key match no row, unless `null_keys_match` is set: then NULL keys match each
+/// other. Left (and full) joins pass on the left rows without a match (NULL keys included) with
+/// NULL right columns. Right (and full) joins then pass on the right rows no left row matched, in
+/// right order, with NULL left columns.
+///
+pub struct HashJoin<'a> {
+    lhs: Box<dyn Operator + 'a>,
+    rhs: Box<dyn Operator + 'a>,
+    lhs_key: String,
+    rhs_key: String,
+    join_type: JoinType,
+    // Columns of each side, NULL in the rows outer joins keep without a match on that side.
+    lhs_columns: Vec<ColumnKey>,
+    rhs_columns: Vec<ColumnKey>,
+    first_match: bool,
+    null_keys_match: bool,
+    rhs_table: Option<JoinTable>,
+    pending: VecDeque<Row>,
+}
+
+// The right rows, by key, with whether a left row matched them.
+#[derive(Default)]
+struct JoinTable {
+    rows: Vec<Row>,
+    matched: Vec<bool>,
+    positions: HashMap<Value, Vec<usize>>,
+    // Next row checked for being unmatched, once the left rows are done.
+    unmatched_pos: usize,
+}
+
+impl<'a> HashJoin<'a> {
+    #[must_use]
+    pub fn new(
+        lhs: Box<dyn Operator + 'a>,
+        rhs: Box<dyn Operator + 'a>,
+        lhs_key: String,
+        rhs_key: String,
+    ) -> Self {
+        Self {
+            lhs,
+            rhs,
+            lhs_key,
+            rhs_key,
+            join_type: JoinType::Inner,
+            lhs_columns: vec![],
+            rhs_columns: vec![],
+            first_match: false,
+            null_keys_match: false,
+            rhs_table: None,
+            pending: VecDeque::new(),
+        }
+    }
+
+    #[must_use]
+    pub const fn with_first_match(mut self, first_match: bool) -> Self {
+        self.first_match = first_match;
+        self
+    }
+
+    ///
+    /// Sets the join type, with the columns of the left and right rows (which outer joins set to
+    /// NULL in the rows without a match).
+    ///
+    #[must_use]
+    pub fn with_join_type(
+        mut self,
+        join_type: JoinType,
+        lhs_columns: Vec<ColumnKey>,
+        rhs_columns: Vec<ColumnKey>,
+    ) -> Self {
+        self.join_type = join_type;
+        self.lhs_columns = lhs_columns;
+        self.rhs_columns = rhs_columns;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_null_keys_match(mut self, null_keys_match: bool) -> Self {
+        self.null_keys_match = null_keys_match;
+        self
+    }
+
+    // Whether rows with the key can match at all.
+    fn is_joinable(&self, key: &Value) -> bool {
+        self.null_keys_match || *key != Value::NULL
+    }
+
+    fn build(&mut self) -> Result<JoinTable, Error> {
+        let mut rhs_table = JoinTable::default();
+        while let Some(rhs_row) = self.rhs.next_row()? {
+            let key = &rhs_row[self.rhs_key.as_str()];
+            if self.is_joinable(key) {
+                rhs_table
+                    .positions
+                    .entry(key.clone())
+                    .or_default()
+                    .push(rhs_table.rows.len());
+            } else if !self.join_type.keeps_unmatched_rhs() {
+                continue;
+            }
+            rhs_table.rows.push(rhs_row);
+            rhs_table.matched.push(false);
+        }
+
+        Ok(rhs_table)
+    }
+
+    // The next right row no left row matched, with NULL left columns.
+    fn next_unmatched_rhs_row(&mut self) -> Option<Row> {
+        let rhs_table = self.rhs_table.as_mut().expect("Join table is built");
+        while rhs_table.unmatched_pos < rhs_table.rows.len() {
+            let pos = rhs_table.unmatched_pos;
+            rhs_table.unmatched_pos += 1;
+            if !rhs_table.matched[pos] {
+                let mut row: Row = self
+                    .lhs_columns
+                    .iter()
+                    .map(|column| (column.clone(), Value::NULL))
+                    .collect();
+                row.extend(std::mem::take(&mut rhs_table.rows[pos]));
+                return Some(row);
+            }
+        }
+
+        None
+    }
+}
+
+impl Operator for HashJoin<'_> {
+    fn next_row(&mut self) -> Result<Option<Row>, Error> {
+        if self.rhs_table.is_none() {
+            self.rhs_table = Some(self.build()?);
+        }
+
+        loop {
+            if let Some(row) = self.pending.pop_front() {
+                return Ok(Some(row));
+            }
+
+            let Some(lhs_row) = self.lhs.next_row()? else {
+                if self.join_type.keeps_unmatched_rhs() {
+                    return Ok(self.next_unmatched_rhs_row());
+                }
+                return Ok(None);
+            };
+
+            let lhs_key = &lhs_row[self.lhs_key.as_str()];
+            let is_joinable = self.is_joinable(lhs_key);
+            let rhs_table = self.rhs_table.as_mut().expect("Join table is built");
+            let positions = Some(lhs_key)
+                .filter(|_| is_joinable)
+                .and_then(|lhs_key| rhs_table.positions.get(lhs_key));
+            if let Some(positions) = positions {
+                let match_count = if self.first_match { 1 } else { positions.len() };
+                for pos in &positions[..match_count] {
+                    rhs_table.matched[*pos] = true;
+                    let mut row = lhs_row.clone();
+                    row.extend(
+                        rhs_table.rows[*pos]
+                            .iter()
+                            .map(|(k, v)| (k.clone(), v.clone())),
+                    );
+                    self.pending.push_back(row);
+                }
+            } else if self.join_type.keeps_unmatched_lhs() {
+                let mut row = lhs_row;
+                row.extend(
+                    self.rhs_columns
+                        .iter()
+                        .map(|column| (column.clone(), Value::NULL)),
+                );
+                self.pending.push_back(row);
+            }
+        }
+    }
+}
+
+///
+/// Keeps only the listed columns.
+///
+pub struct Project<'a> {
+    child: Box<dyn Operator + 'a>,
+    columns: Vec<ColumnKey>,
+}
+
+impl<'a> Project<'a> {
+    #[must_use]
+    pub fn new(child: Box<dyn Operator + 'a>, columns: Vec<String>) -> Self {
+        Self {
+            child,
+            columns: columns.into_iter().map(ColumnKey::from).collect(),
+        }
+    }
+}
+
+impl Operator for Project<'_> {
+    fn next_row(&mut self) -> Result<Option<Row>, Error> {
+        Ok(self.child.next_row()?.map(|mut row| {
+            self.columns
+                .iter()
+                .map(|column| {
+                    row.swap_remove_entry(column.as_ref())
+                        .unwrap_or_else(|| (column.clone(), Value::NULL))
+                })
+                .collect()
+        }))
+    }
+}
+
+///
+/// Drops the rows equal (on every column, or on the one set with `with_column`) to an earlier row,
+/// keeping the first. Remembers every row passed on.
+///
+pub struct Distinct<'a> {
+    child: Box<dyn Operator + 'a>,
+    column: Option<ColumnKey>,
+    seen: HashSet<Vec<Value>>,
+}
+
+impl<'a> Distinct<'a> {
+    #[must_use]
+    pub fn new(child: Box<dyn Operator + 'a>) -> Self {
+        Self {
+            child,
+            column: None,
+            seen: HashSet::new(),
+        }
+    }
+
+    ///
+    /// Compares the rows on the column only (missing values are NULL).
+    ///
+    #[must_use]
+    pub fn with_column(mut self, column: ColumnKey) -> Self {
+        self.column = Some(column);
+        self
+    }
+}
+
+impl Operator for Distinct<'_> {
+    fn next_row(&mut self) -> Result<Option<Row>, Error> {
+        while let Some(row) = self.child.next_row()? {
+            let key = self.column.as_ref().map_or_else(
+                || row.values().cloned().collect(),
+                |column| vec![row.get(column).cloned().unwrap_or(Value::NULL)],
+            );
+            if self.seen.insert(key) {
+                return Ok(Some(row));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+///
+/// The distinct leading keys of an index, as rows of their column.
+///
+/// Keys come in index order (reverse index order when descending). Each key is found with one
+/// binary search past the entries of the previous one, so runs of duplicates are skipped rather
+/// than read. Keys of the index delta (distinct and sorted) are merged in.
+///
+pub struct IndexDistinct {
+    column: ColumnKey,
+    field_schema: FieldSchema,
+    index_row_byte_size: usize,
+    index_bytes: FileBytes,
+    // The part of the index not read yet, exclusive on both ends.
+    range: (i32, i32),
+    delta_keys: Peekable<std::vec::IntoIter<Value>>,
+    descending: bool,
+}
+
+impl IndexDistinct {
+    /// # Errors
+    ///
+    /// With `PBaseError::CorruptIndex` when the index is not made of whole entries, or has more of
+    /// them than an index range addresses.
+    pub fn new(
+        table_schema: &TableSchema,
+        index_name: &str,
+        index_bytes: FileBytes,
+        mut delta_keys: Vec<Value>,
+        descending: bool,
+    ) -> Result<Self, Error> {
+        let index_row_byte_size = table_schema.index_row_byte_size(index_name);
+        let corrupt_index = |reason: String| PBaseError::CorruptIndex {
+            table: table_schema.name.clone(),
+            index: index_name.to_string(),
+            reason,
+        };
+        if index_bytes.len() % index_row_byte_size != 0 {
+            return Err(corrupt_index(format!(
+                "size {} is not a multiple of the index row size {index_row_byte_size}",
+                index_bytes.len()
+            ))
+            .into());
+        }
+        let index_rows = i32::try_from(index_bytes.len() / index_row_byte_size)
+            .map_err(|_| corrupt_index("too many entries to read".to_string()))?;
+        if descending {
+            delta_keys.reverse();
+        }
+
+        let field_name = &table_schema.indices[index_name][0];
+        Ok(Self {
+            column: format!("{}.{field_name}", table_schema.name).into(),
+            field_schema: table_schema.fields[field_name].clone(),
+            index_row_byte_size,
+            index_bytes,
+            range: (-1, index_rows),
+            delta_keys: delta_keys.into_iter().peekable(),
+            descending,
+        })
+    }
+
+    // Leading key of the index entry.
+    fn key(&self, index_idx: i32) -> Value {
+        let index_row_pos =
+            usize::try_from(index_idx).unwrap_or_default() * self.index_row_byte_size;
+        self.field_schema
+            .value_from_bytes(&self.index_bytes[index_row_pos..])
+    }
+
+    // Next key of the sorted index in reading order.
+    fn peek_sorted_key(&self) -> Option<Value> {
+        let (lhs_idx, rhs_idx) = self.range;
+        (rhs_idx - lhs_idx > 1).then(|| {
+            self.key(if self.descending {
+                rhs_idx - 1
+            } else {
+                lhs_idx + 1
+            })
+        })
+    }
+
+    // Moves the range past the entries of the key, if it has any.
+    fn skip_sorted_key(&mut self, key: &Value) {
+        let (lhs_idx, rhs_idx) = self.range;
+        let (before_key_idx, after_key_idx) =
+            binary_narrow_to_range_exclusive(lhs_idx, rhs_idx, |i| self.key(i).cmp(key));
+        self.range = if self.descending {
+            (lhs_idx, before_key_idx + 1)
+        } else {
+            (after_key_idx - 1, rhs_idx)
+        };
+    }
+}
+
+impl Operator for IndexDistinct {
+    fn next_row(&mut self) -> Result<Option<Row>, Error> {
+        let key = match (self.peek_sorted_key(), self.delta_keys.peek().cloned()) {
+            (None, None) => return Ok(None),
+            (Some(key), None) | (None, Some(key)) => key,
+            (Some(sorted_key), Some(delta_key)) if self.descending => sorted_key.max(delta_key),
+            (Some(sorted_key), Some(delta_key)) => sorted_key.min(delta_key),
+        };
+        if self.delta_keys.peek() == Some(&key) {
+            self.delta_keys.next();
+        }
+        self.skip_sorted_key(&key);
+
+        Ok(Some(Row::from([(self.column.clone(), key)])))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+    pub column: String,
+    pub descending: bool,
+}
+
+///
+/// Sorts all rows of the child (stable, so equal keys keep the input order).
+///
+pub struct Sort<'a> {
+    child: Box<dyn Operator + 'a>,
+    keys: Vec<SortKey>,
+    sorted: Option<std::vec::IntoIter<Row>>,
+}
+
+impl<'a> Sort<'a> {
+    #[must_use]
+    pub fn new(child: Box<dyn Operator + 'a>, keys: Vec<SortKey>) -> Self {
+        Self {
+            child,
+            keys,
+            sorted: None,
+        }
+    }
+
+    fn compare(&self, lhs: &Row, rhs: &Row) -> Ordering {
+        for key in &self.keys {
+            let ordering = lhs[key.column.as_str()].cmp(&rhs[key.column.as_str()]);
+            let ordering = if key.descending {
+                ordering.reverse()
+            } else {
+                ordering
+            };
+
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+
+        Ordering::Equal
+    }
+}
+
+impl Operator for Sort<'_> {
+    fn next_row(&mut self) -> Result<Option<Row>, Error> {
+        if self.sorted.is_none() {
+            let mut rows = collect_rows(self.child.as_mut())?;
+            rows.sort_by(|lhs, rhs| self.compare(lhs, rhs));
+            self.sorted = Some(rows.into_iter());
+        }
+
+        Ok(self.sorted.as_mut().and_then(Iterator::next))
+    }
+}
+
+///
+/// Stops after `limit` rows. Children are not pulled any further after that.
+///
+pub struct Limit<'a> {
+    child: Box<dyn Operator + 'a>,
+    remaining: usize,
+}
+
+impl<'a> Limit<'a> {
+    #[must_use]
+    pub fn new(child: Box<dyn Operator + 'a>, limit: usize) -> Self {
+        Self {
+            child,
+            remaining: limit,
+        }
+    }
+}
+
+impl Operator for Limit<'_> {
+    fn next_row(&mut self) -> Result<Option<Row>, Error> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+
+        self.remaining -= 1;
+        self.child.next_row()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RuntimeStats {
+    pub rows: usize,
+    // Time spent pulling rows, children included.
+    pub elapsed: Duration,
+}
+
+///
+/// Transparent wrapper counting the rows produced by an operator and the time spent producing them.
+/// Stats can be read through the handle from `stats` while (and after) the tree is pulled.
+///
+pub struct Instrumented<'a> {
+    child: Box<dyn Operator + 'a>,
+    stats: Rc<Cell<RuntimeStats>>,
+}
+
+impl<'a> Instrumented<'a> {
+    #[must_use]
+    pub fn new(child: Box<dyn Operator + 'a>) -> Self {
+        Self {
+            child,
+            stats: Rc::new(Cell::new(RuntimeStats::default())),
+        }
+    }
+
+    #[must_use]
+    pub fn stats(&self) -> Rc<Cell<RuntimeStats>> {
+        self.stats.clone()
+    }
+}
+
+impl Operator for Instrumented<'_> {
+    fn next_row(&mut self) -> Result<Option<Row>, Error> {
+        let start = Instant::now();
+        let row = self.child.next_row()?;
+
+        let mut stats = self.stats.get();
+        stats.elapsed += start.elapsed();
+        if row.is_some() {
+            stats.rows += 1;
+        }
+        self.stats.set(stats);
+
+        Ok(row)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use indexmap::IndexMap;
+
+    use crate::{
+        query::{CallFilter, CompareOp, FieldSelector, JoinType, RhsValue, RowFilter},
+        schema::{FieldSchema, TableSchema},
+        value::Value,
+    };
+
+    use super::{
+        collect_rows, Compute, ComputedColumn, Distinct, Filter, HashJoin, Instrumented, Limit,
+        Project, Row, Scan, Sort, SortKey, Values,
+    };
+
+    fn row(values: &[(&str, i32)]) -> Row {
+        values
+            .iter()
+            .map(|(k, v)| ((*k).into(), Value::I32(*v)))
+            .collect()
+    }
+
+    #[test]
+    fn test_scan() {
+        let table_schema = TableSchema {
+            name: "t1".to_string(),
+            fields: IndexMap::from([
+                ("f1".to_string(), FieldSchema::I32),
+                ("f2".to_string(), FieldSchema::U8),
+            ]),
+            indices: IndexMap::new(),
+            ..Default::default()
+        };
+
+        #[rustfmt::skip]
+        let table_bytes: [u8; 10] = [
+            1, 0, 0, 0,   2, // Row 1
+            3, 0, 0, 0,   4, // Row 2
+        ];
+
+        let rows = collect_rows(&mut Scan::new(&table_schema, &table_bytes)).unwrap();
+
+        assert_eq!(2, rows.len());
+        assert_eq!(Value::I32(1), rows[0]["t1.f1"]);
+        assert_eq!(Value::U8(2), rows[0]["t1.f2"]);
+        assert_eq!(Value::I32(3), rows[1]["t1.f1"]);
+        assert_eq!(Value::U8(4), rows[1]["t1.f2"]);
+
+        // Column keys are shared between rows.
+        let key = |row: &Row| row.get_key_value("t1.f1").unwrap().0.clone();
+        assert!(Arc::ptr_eq(&key(&rows[0]), &key(&rows[1])));
+    }
+
+    #[test]
+    fn test_filter() {
+        let values = Values::new(vec![
+            row(&[("t.a", 1), ("t.b", 1)]),
+            row(&[("t.a", 2), ("t.b", 3)]),
+            row(&[("t.a", 3), ("t.b", 3)]),
+        ]);
+
+        let field = |name: &str| FieldSelector {
+            name: name.into(),
+            source: "t".into(),
+        };
+        let mut filter = Filter::new(
+            Box::new(values),
+            vec![
+                RowFilter {
+                    field: field("a"),
+                    op: CompareOp::Gt,
+                    rhs: RhsValue::Value(Value::I32(1)),
+                },
+                RowFilter {
+                    field: field("a"),
+                    op: CompareOp::Eq,
+                    rhs: RhsValue::Ref(field("b")),
+                },
+            ],
+        );
+
+        assert_eq!(
+            vec![row(&[("t.a", 3), ("t.b", 3)])],
+            collect_rows(&mut filter).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_compute() {
+        let values = Values::new(vec![
+            row(&[("t.a", 1)]),
+            row(&[("t.a", 2)]),
+            row(&[("t.a", 3)]),
+        ]);
+
+        let mut compute = Compute::new(
+            Box::new(values),
+            vec![ComputedColumn {
+                column: "double".into(),
+                arg: "t.a".into(),
+                body: Arc::new(|value| match value {
+                    Value::I32(v) => Value::I32(v * 2),
+                    _ => Value::NULL,
+                }),
+            }],
+            vec![CallFilter {
+                alias: "double".into(),
+                op: CompareOp::Gt,
+                rhs: Value::I32(2),
+            }],
+        );
+
+        assert_eq!(
+            vec![
+                row(&[("t.a", 2), ("double", 4)]),
+                row(&[("t.a", 3), ("double", 6)]),
+            ],
+            collect_rows(&mut compute).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_hash_join() {
+        let lhs = vec![
+            row(&[("t1.id", 0)]),
+            row(&[("t1.id", 1)]),
+            row(&[("t1.id", 2)]),
+            row(&[("t1.id", 3)]),
+        ];
+        let rhs = vec![
+            row(&[("t2.t1_id", 1), ("t2.v", 10)]),
+            row(&[("t2.t1_id", 2), ("t2.v", 20)]),
+            row(&[("t2.t1_id", 7), ("t2.v", 70)]),
+            row(&[("t2.t1_id", 1), ("t2.v", 11)]),
+        ];
+        let join = |first_match| {
+            HashJoin::new(
+                Box::new(Values::new(lhs.clone())),
+                Box::new(Values::new(rhs.clone())),
+                "t1.id".into(),
+                "t2.t1_id".into(),
+            )
+            .with_first_match(first_match)
+        };
+
+        assert_eq!(
+            vec![
+                row(&[("t1.id", 1), ("t2.t1_id", 1), ("t2.v", 10)]),
+                row(&[("t1.id", 1), ("t2.t1_id", 1), ("t2.v", 11)]),
+                row(&[("t1.id", 2), ("t2.t1_id", 2), ("t2.v", 20)]),
+            ],
+            collect_rows(&mut join(false)).unwrap()
+        );
+        assert_eq!(
+            vec![
+                row(&[("t1.id", 1), ("t2.t1_id", 1), ("t2.v", 10)]),
+                row(&[("t1.id", 2), ("t2.t1_id", 2), ("t2.v", 20)]),
+            ],
+            collect_rows(&mut join(true)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_hash_join_null_keys() {
+        let null_key = |key: &str, v| {
+            let mut row = row(&[(v, 0)]);
+            row.insert(key.into(), Value::NULL);
+            row
+        };
+        let lhs = vec![row(&[("t1.id", 1)]), null_key("t1.id", "t1.v")];
+        let rhs = vec![null_key("t2.t1_id", "t2.v"), row(&[("t2.t1_id", 1)])];
+        let join = |null_keys_match| {
+            HashJoin::new(
+                Box::new(Values::new(lhs.clone())),
+                Box::new(Values::new(rhs.clone())),
+                "t1.id".into(),
+                "t2.t1_id".into(),
+            )
+            .with_null_keys_match(null_keys_match)
+        };
+
+        assert_eq!(
+            vec![row(&[("t1.id", 1), ("t2.t1_id", 1)])],
+            collect_rows(&mut join(false)).unwrap()
+        );
+        let mut null_match = null_key("t1.id", "t1.v");
+        null_match.extend(null_key("t2.t1_id", "t2.v"));
+        assert_eq!(
+            vec![row(&[("t1.id", 1), ("t2.t1_id", 1)]), null_match],
+            collect_rows(&mut join(true)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_left_hash_join() {
+        let mut null_key = row(&[("t1.v", 4)]);
+        null_key.insert("t1.id".into(), Value::NULL);
+        let lhs = vec![row(&[("t1.id", 1), ("t1.v", 1)]), null_key.clone()];
+        let rhs = vec![row(&[("t2.t1_id", 1), ("t2.v", 10)])];
+        let mut join = HashJoin::new(
+            Box::new(Values::new(lhs)),
+            Box::new(Values::new(rhs)),
+            "t1.id".into(),
+            "t2.t1_id".into(),
+        )
+        .with_join_type(
+            JoinType::Left,
+            vec!["t1.id".into(), "t1.v".into()],
+            vec!["t2.t1_id".into(), "t2.v".into()],
+        );
+
+        let mut unmatched = null_key;
+        unmatched.insert("t2.t1_id".into(), Value::NULL);
+        unmatched.insert("t2.v".into(), Value::NULL);
+        assert_eq!(
+            vec![
+                row(&[("t1.id", 1), ("t1.v", 1), ("t2.t1_id", 1), ("t2.v", 10)]),
+                unmatched,
+            ],
+            collect_rows(&mut join).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_right_and_full_hash_joins() {
+        let with_nulls = |values: &[(&str, i32)], null_columns: &[&str]| {
+            let mut row = row(values);
+            for column in null_columns {
+                row.insert((*column).into(), Value::NULL);
+            }
+            row
+        };
+        let join = |join_type| {
+            let lhs = vec![
+                row(&[("t1.id", 1), ("t1.v", 1)]),
+                with_nulls(&[("t1.v", 4)], &["t1.id"]),
+            ];
+            let rhs = vec![
+                row(&[("t2.t1_id", 1), ("t2.v", 10)]),
+                row(&[("t2.t1_id", 2), ("t2.v", 20)]),
+                with_nulls(&[("t2.v", 30)], &["t2.t1_id"]),
+            ];
+            let mut join = HashJoin::new(
+                Box::new(Values::new(lhs)),
+                Box::new(Values::new(rhs)),
+                "t1.id".into(),
+                "t2.t1_id".into(),
+            )
+            .with_join_type(
+                join_type,
+                vec!["t1.id".into(), "t1.v".into()],
+                vec!["t2.t1_id".into(), "t2.v".into()],
+            );
+            collect_rows(&mut join).unwrap()
+        };
+
+        let matched = row(&[("t1.id", 1), ("t1.v", 1), ("t2.t1_id", 1), ("t2.v", 10)]);
+        let unmatched_lhs = with_nulls(&[("t1.v", 4)], &["t1.id", "t2.t1_id", "t2.v"]);
+        let unmatched_rhs = vec![
+            with_nulls(&[("t2.t1_id", 2), ("t2.v", 20)], &["t1.id", "t1.v"]),
+            with_nulls(&[("t2.v", 30)], &["t1.id", "t1.v", "t2.t1_id"]),
+        ];
+        let right_rows = join(JoinType::Right);
+        assert_eq!(
+            [vec![matched.clone()], unmatched_rhs.clone()].concat(),
+            right_rows
+        );
+        // Left columns come first in the rows without a left match too.
+        assert_eq!(
+            vec!["t1.id", "t1.v", "t2.t1_id", "t2.v"],
+            right_rows[1]
+                .keys()
+                .map(AsRef::as_ref)
+                .collect::<Vec<&str>>()
+        );
+        assert_eq!(
+            [vec![matched, unmatched_lhs], unmatched_rhs].concat(),
+            join(JoinType::Full)
+        );
+    }
+
+    #[test]
+    fn test_distinct() {
+        let values = Values::new(vec![
+            row(&[("t.a", 1), ("t.b", 2)]),
+            row(&[("t.a", 1), ("t.b", 3)]),
+            row(&[("t.a", 1), ("t.b", 2)]),
+            row(&[("t.a", 2), ("t.b", 2)]),
+        ]);
+        let mut limit = Limit::new(Box::new(Distinct::new(Box::new(values))), 3);
+
+        assert_eq!(
+            vec![
+                row(&[("t.a", 1), ("t.b", 2)]),
+                row(&[("t.a", 1), ("t.b", 3)]),
+                row(&[("t.a", 2), ("t.b", 2)]),
+            ],
+            collect_rows(&mut limit).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_project_sort_limit() {
+        let values = Values::new(vec![
+            row(&[("t.a", 1), ("t.b", 2)]),
+            row(&[("t.a", 3), ("t.b", 1)]),
+            row(&[("t.a", 2), ("t.b", 1)]),
+        ]);
+
+        let sort = Sort::new(
+            Box::new(values),
+            vec![
+                SortKey {
+                    column: "t.b".into(),
+                    descending: false,
+                },
+                SortKey {
+                    column: "t.a".into(),
+                    descending: true,
+                },
+            ],
+        );
+        let project = Project::new(Box::new(sort), vec!["t.a".into()]);
+        let mut limit = Limit::new(Box::new(project), 2);
+
+        assert_eq!(
+            vec![row(&[("t.a", 3)]), row(&[("t.a", 2)])],
+            collect_rows(&mut limit).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_instrumented() {
+        let values = Values::new(vec![row(&[("t.a", 1)]), row(&[("t.a", 2)])]);
+        let mut instrumented = Instrumented::new(Box::new(values));
+        let stats = instrumented.stats();
+
+        assert_eq!(0, stats.get().rows);
+        assert_eq!(2, collect_rows(&mut instrumented).unwrap().len());
+        assert_eq!(2, stats.get().rows);
+    }
+}
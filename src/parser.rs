@@ -1,18 +1,69 @@
+use std::collections::{BTreeMap, HashMap};
+
+use indexmap::IndexMap;
+
 use crate::{
     common::{Error, PBaseError},
+    custom_type::CustomType,
     lexer::Token,
-    query::{Query, SelectQuery},
+    query::{
+        AnalyzeQuery, AttachQuery, CompareOp, CreateTableQuery, DeleteQuery, DescribeQuery,
+        ExplainQuery, FieldSelector, InsertQuery, InsertStatement, OrderBy, Query, RhsValue,
+        RowFilter, SampleSpec, ScalarCall, SelectQuery, SetOperation, SetQuery, SettingValue,
+        UnionQuery,
+    },
+    schema::{DefaultExpr, FieldSchema, StorageOptions, TableSchema},
+    value::{Interval, Value},
 };
 
+///
+/// Where a literal (`Token::Int`) of a statement ends up in its query, see `Parser::literal_slots`.
+///
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum LiteralSlot {
+    // The value of a field of an INSERT.
+    InsertValue(String),
+    // The value of a SET.
+    SettingValue,
+    // The value of a storage option of a CREATE TABLE.
+    StorageOption(String),
+    // The constant, or function argument, of the default expression of a field of a CREATE TABLE.
+    DefaultValue(String),
+    // The precision, or the scale, of a DECIMAL field of a CREATE TABLE.
+    DecimalPrecision(String),
+    DecimalScale(String),
+    // The sample size of the nth select of the statement (selects of a UNION are counted in
+    // order, an EXPLAIN has one).
+    SampleSize(usize),
+    // The value, or the interval, of a filter of the nth select of the statement: its position
+    // among the select's filters.
+    FilterValue(usize, usize),
+}
+
 pub struct Parser<'a> {
     __tokens: &'a [Token],
     i: usize,
+    literal_slots: Vec<LiteralSlot>,
+    selects: usize,
 }
 
 impl<'a> Parser<'a> {
     #[must_use]
     pub const fn new(__tokens: &'a [Token]) -> Self {
-        Self { __tokens, i: 0 }
+        Self {
+            __tokens,
+            i: 0,
+            literal_slots: vec![],
+            selects: 0,
+        }
+    }
+
+    ///
+    /// Where the literals of the parsed statement went, in statement order.
+    ///
+    #[must_use]
+    pub fn literal_slots(&self) -> &[LiteralSlot] {
+        &self.literal_slots
     }
 
     #[must_use]
@@ -33,7 +84,15 @@ impl<'a> Parser<'a> {
     /// Returns error when token stream cannot be parsed.
     pub fn parse(&mut self) -> Result<Query, Error> {
         match self.head() {
-            Some(&Token::Select) => Ok(Query::Select(self.parse_select_query()?)),
+            Some(&Token::Select) => self.parse_select_or_union_query(),
+            Some(&Token::Explain) => self.parse_explain_query(),
+            Some(&Token::Set) => self.parse_set_query(),
+            Some(&Token::Analyze) => self.parse_analyze_query(),
+            Some(&Token::Describe) => self.parse_describe_query(),
+            Some(&Token::Insert) => self.parse_insert_statement(),
+            Some(&Token::Delete) => self.parse_delete_query(),
+            Some(&Token::Create) => self.parse_create_table_query(),
+            Some(&Token::Attach) => self.parse_attach_query(),
             head => Err(format!("Not yet implemented for token: {head:?}",).into()),
         }
     }
@@ -58,31 +117,606 @@ impl<'a> Parser<'a> {
         .into()
     }
 
+    fn parse_explain_query(&mut self) -> Result<Query, Error> {
+        self.must_swallow(&Token::Explain)?;
+
+        let analyze = self.head() == Some(&Token::Analyze);
+        if analyze {
+            self.advance();
+        }
+
+        let select = self.parse_select_query()?;
+
+        Ok(Query::Explain(ExplainQuery { select, analyze }))
+    }
+
+    fn parse_analyze_query(&mut self) -> Result<Query, Error> {
+        self.must_swallow(&Token::Analyze)?;
+
+        let Some(Token::Identifier(table)) = self.head().cloned() else {
+            return Err(self.bail("expected table name"));
+        };
+        self.advance();
+
+        Ok(Query::Analyze(AnalyzeQuery { table }))
+    }
+
+    fn parse_describe_query(&mut self) -> Result<Query, Error> {
+        self.must_swallow(&Token::Describe)?;
+        let table = self.parse_table_name()?;
+
+        Ok(Query::Describe(DescribeQuery { table }))
+    }
+
+    //
+    // `ATTACH 'path' AS alias`.
+    //
+    fn parse_attach_query(&mut self) -> Result<Query, Error> {
+        self.must_swallow(&Token::Attach)?;
+        let Some(Token::Str(path)) = self.head().cloned() else {
+            return Err(self.bail("expected quoted database path"));
+        };
+        self.advance();
+        self.must_swallow(&Token::As)?;
+        let alias = self.parse_identifier("expected alias")?;
+
+        Ok(Query::Attach(AttachQuery { path, alias }))
+    }
+
+    //
+    // `INSERT INTO table (field, ...) VALUES (value, ...) [RETURNING [field, ...]]`.
+    //
+    fn parse_insert_statement(&mut self) -> Result<Query, Error> {
+        self.must_swallow(&Token::Insert)?;
+        self.must_swallow(&Token::Into)?;
+        let table = self.parse_identifier("expected table name")?;
+
+        self.must_swallow(&Token::LParen)?;
+        let mut fields = vec![];
+        while self.head() != Some(&Token::RParen) {
+            if !fields.is_empty() {
+                self.must_swallow(&Token::Comma)?;
+            }
+            fields.push(self.parse_identifier("expected field name")?);
+        }
+        self.advance();
+
+        self.must_swallow(&Token::Values)?;
+        self.must_swallow(&Token::LParen)?;
+        let mut values = HashMap::new();
+        for (i, field) in fields.into_iter().enumerate() {
+            if i > 0 {
+                self.must_swallow(&Token::Comma)?;
+            }
+            let Some(Token::Int(value)) = self.head().cloned() else {
+                return Err(self.bail("expected value"));
+            };
+            self.advance();
+            self.literal_slots
+                .push(LiteralSlot::InsertValue(field.clone()));
+            values.insert(field, Value::I32(value));
+        }
+        self.must_swallow(&Token::RParen)?;
+
+        let returning = if self.head() == Some(&Token::Returning) {
+            self.advance();
+            let mut returned_fields = vec![];
+            while self.head().is_some() {
+                if !returned_fields.is_empty() {
+                    self.must_swallow(&Token::Comma)?;
+                }
+                returned_fields.push(self.parse_identifier("expected field name")?);
+            }
+            Some(returned_fields)
+        } else {
+            None
+        };
+
+        Ok(Query::Insert(InsertStatement {
+            query: InsertQuery { table, values },
+            returning,
+        }))
+    }
+
+    //
+    // `DELETE FROM table`. Filters are not parsed yet, all rows are deleted.
+    //
+    fn parse_delete_query(&mut self) -> Result<Query, Error> {
+        self.must_swallow(&Token::Delete)?;
+        self.must_swallow(&Token::From)?;
+        let table = self.parse_identifier("expected table name")?;
+
+        Ok(Query::Delete(DeleteQuery {
+            table,
+            ..Default::default()
+        }))
+    }
+
+    //
+    // `CREATE TABLE table (field type, ...) [WITH (option=value, ...)]`, types are `I32`, `U8`,
+    // `DECIMAL(precision[, scale])` and the names of custom types.
+    // Options are the table's storage options (see `StorageOptions::set`), string values quoted.
+    //
+    fn parse_create_table_query(&mut self) -> Result<Query, Error> {
+        self.must_swallow(&Token::Create)?;
+        self.must_swallow(&Token::Table)?;
+        let name = self.parse_identifier("expected table name")?;
+
+        self.must_swallow(&Token::LParen)?;
+        let mut fields = IndexMap::new();
+        let mut defaults = IndexMap::new();
+        while self.head() != Some(&Token::RParen) {
+            if !fields.is_empty() {
+                self.must_swallow(&Token::Comma)?;
+            }
+            let field = self.parse_identifier("expected field name")?;
+            if fields.contains_key(&field) {
+                return Err(PBaseError::DuplicateColumn(field).into());
+            }
+            // Other names are of custom types, checked to be registered when the table is created.
+            let field_schema = match self.parse_identifier("expected field type")?.as_str() {
+                "I32" => FieldSchema::I32,
+                "U8" => FieldSchema::U8,
+                "DECIMAL" => self.parse_decimal_type(&field)?,
+                type_name => FieldSchema::Custom(CustomType::new(type_name)),
+            };
+            if self.head() == Some(&Token::Default) {
+                self.advance();
+                defaults.insert(field.clone(), self.parse_default_expr(&field)?);
+            }
+            fields.insert(field, field_schema);
+        }
+        self.advance();
+
+        let mut storage = StorageOptions::default();
+        if self.head() == Some(&Token::With) {
+            self.advance();
+            self.must_swallow(&Token::LParen)?;
+            let mut first = true;
+            while self.head() != Some(&Token::RParen) {
+                if !first {
+                    self.must_swallow(&Token::Comma)?;
+                }
+                first = false;
+
+                let option = self.parse_identifier("expected storage option")?;
+                self.must_swallow(&Token::Op(CompareOp::Eq))?;
+                let value = match self.head().cloned() {
+                    Some(Token::Int(v)) => {
+                        self.literal_slots
+                            .push(LiteralSlot::StorageOption(option.clone()));
+                        SettingValue::Int(v)
+                    }
+                    Some(Token::Str(word)) => SettingValue::Word(word),
+                    _ => return Err(self.bail("expected storage option value")),
+                };
+                self.advance();
+                storage.set(&option, &value)?;
+            }
+            self.advance();
+        }
+
+        Ok(Query::CreateTable(CreateTableQuery {
+            schema: TableSchema {
+                name,
+                fields,
+                storage,
+                defaults,
+                ..Default::default()
+            },
+        }))
+    }
+
+    // A constant `7`, `now()`, `next_id()`, or a scalar function called with a constant `f(7)` or
+    // with NULL `f()`.
+    //
+    // `(precision[, scale])` of a DECIMAL field, the scale 0 when omitted. Whether the digits add up
+    // is checked when the table is created.
+    //
+    fn parse_decimal_type(&mut self, field: &str) -> Result<FieldSchema, Error> {
+        self.must_swallow(&Token::LParen)?;
+        let precision = self.parse_type_arg(LiteralSlot::DecimalPrecision(field.to_string()))?;
+        let scale = if self.head() == Some(&Token::Comma) {
+            self.advance();
+            self.parse_type_arg(LiteralSlot::DecimalScale(field.to_string()))?
+        } else {
+            0
+        };
+        self.must_swallow(&Token::RParen)?;
+
+        Ok(FieldSchema::Decimal { precision, scale })
+    }
+
+    fn parse_type_arg(&mut self, literal_slot: LiteralSlot) -> Result<u8, Error> {
+        let Some(arg) = self.head().and_then(|token| match token {
+            Token::Int(v) => u8::try_from(*v).ok(),
+            _ => None,
+        }) else {
+            return Err(self.bail("expected type argument between 0 and 255"));
+        };
+        self.advance();
+        self.literal_slots.push(literal_slot);
+
+        Ok(arg)
+    }
+
+    fn parse_default_expr(&mut self, field: &str) -> Result<DefaultExpr, Error> {
+        if let Some(Token::Int(v)) = self.head().cloned() {
+            self.advance();
+            self.literal_slots
+                .push(LiteralSlot::DefaultValue(field.to_string()));
+            return Ok(DefaultExpr::Value(Value::I32(v)));
+        }
+
+        let function = self.parse_identifier("expected default value or function")?;
+        self.must_swallow(&Token::LParen)?;
+        let arg = if let Some(Token::Int(v)) = self.head().cloned() {
+            self.advance();
+            self.literal_slots
+                .push(LiteralSlot::DefaultValue(field.to_string()));
+            Value::I32(v)
+        } else {
+            Value::NULL
+        };
+        self.must_swallow(&Token::RParen)?;
+
+        Ok(match (function.as_str(), &arg) {
+            ("now", Value::NULL) => DefaultExpr::Now,
+            ("next_id", Value::NULL) => DefaultExpr::NextId,
+            _ => DefaultExpr::Scalar { function, arg },
+        })
+    }
+
+    fn parse_set_query(&mut self) -> Result<Query, Error> {
+        self.must_swallow(&Token::Set)?;
+
+        let Some(Token::Identifier(name)) = self.head().cloned() else {
+            return Err(self.bail("expected setting name"));
+        };
+        self.advance();
+
+        self.must_swallow(&Token::Op(CompareOp::Eq))?;
+
+        let value = match self.head().cloned() {
+            Some(Token::Int(v)) => {
+                self.literal_slots.push(LiteralSlot::SettingValue);
+                SettingValue::Int(v)
+            }
+            Some(Token::Identifier(word)) => SettingValue::Word(word),
+            _ => return Err(self.bail("expected setting value")),
+        };
+        self.advance();
+
+        Ok(Query::Set(SetQuery { name, value }))
+    }
+
+    //
+    // A select, or selects combined by one set operation: `select UNION [ALL] select ...`, and the
+    // same with INTERSECT or EXCEPT. Mixing operations would need precedence rules, so it is not
+    // supported.
+    //
+    fn parse_select_or_union_query(&mut self) -> Result<Query, Error> {
+        let select_query = self.parse_select_query()?;
+        if self.set_operation().is_none() {
+            return Ok(Query::Select(select_query));
+        }
+
+        let mut selects = vec![select_query];
+        let mut combination = None;
+        while let Some(op) = self.set_operation() {
+            self.advance();
+
+            let all = self.head() == Some(&Token::All);
+            if all {
+                self.advance();
+            }
+            let (first_op, first_all) = *combination.get_or_insert((op, all));
+            if first_op != op {
+                return Err(self.bail(&format!("mixing {first_op} and {op} is not supported")));
+            }
+            if first_all != all {
+                return Err(self.bail(&format!("mixing {op} and {op} ALL is not supported")));
+            }
+
+            selects.push(self.parse_select_query()?);
+        }
+
+        let (op, all) = combination.unwrap_or_default();
+        Ok(Query::Union(UnionQuery { selects, op, all }))
+    }
+
+    fn set_operation(&self) -> Option<SetOperation> {
+        match self.head()? {
+            Token::Union => Some(SetOperation::Union),
+            Token::Intersect => Some(SetOperation::Intersect),
+            Token::Except => Some(SetOperation::Except),
+            _ => None,
+        }
+    }
+
+    //
+    // `SELECT [DISTINCT] [function(table.field) AS alias, ...] [INTO table] FROM table [AS alias]
+    // [TABLESAMPLE percent | TABLESAMPLE FIRST count] [WITH DELETED] [WHERE filter AND ...]
+    // [ORDER BY table.field [ASC | DESC]]`. All fields of the table are selected, scalar calls add
+    // columns. `SELECT DISTINCT table.field FROM ...` selects the distinct values of the field
+    // alone. Fields of an aliased table are `alias.field`. The table can be a derived table,
+    // `FROM (SELECT ...) AS name`, whose fields are `name.field`.
+    //
     fn parse_select_query(&mut self) -> Result<SelectQuery, Error> {
         self.must_swallow(&Token::Select)?;
+        let distinct = self.head() == Some(&Token::Distinct);
+        if distinct {
+            self.advance();
+        }
+
+        // A field rather than a function call.
+        let distinct_field = if distinct && self.tokens().get(1) == Some(&Token::Dot) {
+            Some(self.parse_field_selector()?)
+        } else {
+            None
+        };
+
+        let mut scalar_calls = vec![];
+        while distinct_field.is_none()
+            && self.head() != Some(&Token::From)
+            && self.head() != Some(&Token::Into)
+        {
+            if !scalar_calls.is_empty() {
+                self.must_swallow(&Token::Comma)?;
+            }
+            scalar_calls.push(self.parse_scalar_call()?);
+        }
+
+        let into = if self.head() == Some(&Token::Into) {
+            self.advance();
+            Some(self.parse_identifier("expected table name after INTO")?)
+        } else {
+            None
+        };
         self.must_swallow(&Token::From)?;
 
-        let Some(Token::Identifier(table_name)) = self.head().cloned() else {
-            return Err(self.bail("expected table name"));
+        let mut derived_tables = BTreeMap::new();
+        let mut table_name = if self.head() == Some(&Token::LParen) {
+            let (name, derived_table) = self.parse_derived_table()?;
+            derived_tables.insert(name.clone(), derived_table);
+            name
+        } else {
+            self.parse_table_name()?
+        };
+        let mut aliases = BTreeMap::new();
+        if derived_tables.is_empty() && self.head() == Some(&Token::As) {
+            self.advance();
+            let alias = self.parse_identifier("expected table alias")?;
+            aliases.insert(alias.clone(), table_name);
+            table_name = alias;
+        }
+
+        let sample = if self.head() == Some(&Token::TableSample) {
+            self.advance();
+            Some(self.parse_sample_spec()?)
+        } else {
+            None
+        };
+        self.selects += 1;
+
+        let with_deleted = self.head() == Some(&Token::With);
+        if with_deleted {
+            self.advance();
+            self.must_swallow(&Token::Deleted)?;
+        }
+
+        let mut filters = vec![];
+        if self.head() == Some(&Token::Where) {
+            self.advance();
+            loop {
+                filters.push(self.parse_filter(filters.len())?);
+                if self.head() != Some(&Token::And) {
+                    break;
+                }
+                self.advance();
+            }
+        }
+
+        let order_by = if self.head() == Some(&Token::Order) {
+            self.advance();
+            Some(self.parse_order_by()?)
+        } else {
+            None
         };
-        self.advance();
 
         Ok(SelectQuery {
             from: table_name,
             joins: vec![],
-            filters: vec![],
+            filters,
+            scalar_calls,
+            sample,
+            into,
+            with_deleted,
+            distinct,
+            distinct_field,
+            order_by,
+            aliases,
+            derived_tables,
+            ..Default::default()
         })
     }
+
+    //
+    // `(SELECT ...) AS name`, a derived table. Its select is not counted: the sample size slots
+    // of a statement are bound to its top-level selects, so derived tables take no sample. Nor
+    // do they insert INTO a table.
+    //
+    fn parse_derived_table(&mut self) -> Result<(String, SelectQuery), Error> {
+        self.must_swallow(&Token::LParen)?;
+        let selects = self.selects;
+        let derived_table = self.parse_select_query()?;
+        self.selects = selects;
+        if derived_table.sample.is_some()
+            || derived_table.into.is_some()
+            || !derived_table.filters.is_empty()
+        {
+            return Err(
+                self.bail("TABLESAMPLE, INTO and WHERE are not supported in a derived table")
+            );
+        }
+        self.must_swallow(&Token::RParen)?;
+        self.must_swallow(&Token::As)?;
+        let name = self.parse_identifier("expected derived table name")?;
+
+        Ok((name, derived_table))
+    }
+
+    //
+    // `table.field op value`, `table.field op table.field`, or the interval filter
+    // `table.field - table.field op value`, eg. `t2.ts - t1.ts < 3600` (see `RhsValue::Interval`).
+    // Values are integers.
+    //
+    fn parse_filter(&mut self, filter_pos: usize) -> Result<RowFilter, Error> {
+        let field = self.parse_field_selector()?;
+        let since = if self.head() == Some(&Token::Minus) {
+            self.advance();
+            Some(self.parse_field_selector()?)
+        } else {
+            None
+        };
+        let Some(Token::Op(op)) = self.head().cloned() else {
+            return Err(self.bail("expected comparison operator"));
+        };
+        self.advance();
+
+        let rhs = match (self.head().cloned(), since) {
+            (Some(Token::Int(value)), None) => RhsValue::Value(Value::I32(value)),
+            (Some(Token::Int(value)), Some(reference)) => RhsValue::Interval {
+                reference,
+                interval: Interval(i64::from(value)),
+            },
+            (Some(Token::Identifier(_)), None) => {
+                let rhs = RhsValue::Ref(self.parse_field_selector()?);
+                return Ok(RowFilter { field, op, rhs });
+            }
+            (_, None) => return Err(self.bail("expected value or field")),
+            (_, Some(_)) => return Err(self.bail("expected interval")),
+        };
+        self.advance();
+        self.literal_slots
+            .push(LiteralSlot::FilterValue(self.selects - 1, filter_pos));
+
+        Ok(RowFilter { field, op, rhs })
+    }
+
+    fn parse_order_by(&mut self) -> Result<OrderBy, Error> {
+        self.must_swallow(&Token::By)?;
+        let field = self.parse_field_selector()?;
+
+        let descending = self.head() == Some(&Token::Desc);
+        if descending || self.head() == Some(&Token::Asc) {
+            self.advance();
+        }
+
+        Ok(OrderBy { field, descending })
+    }
+
+    fn parse_sample_spec(&mut self) -> Result<SampleSpec, Error> {
+        let is_first = self.head() == Some(&Token::First);
+        if is_first {
+            self.advance();
+        }
+
+        let Some(Token::Int(value)) = self.head().cloned() else {
+            return Err(self.bail("expected sample size"));
+        };
+        self.advance();
+        self.literal_slots
+            .push(LiteralSlot::SampleSize(self.selects));
+
+        if is_first {
+            usize::try_from(value)
+                .map(SampleSpec::First)
+                .map_err(|_| self.bail("expected non-negative row count"))
+        } else {
+            u8::try_from(value)
+                .ok()
+                .filter(|percent| *percent <= 100)
+                .map(SampleSpec::Percent)
+                .ok_or_else(|| self.bail("expected percent between 0 and 100"))
+        }
+    }
+
+    fn parse_scalar_call(&mut self) -> Result<ScalarCall, Error> {
+        let function = self.parse_identifier("expected function name")?;
+        self.must_swallow(&Token::LParen)?;
+        let arg = self.parse_field_selector()?;
+        self.must_swallow(&Token::RParen)?;
+        self.must_swallow(&Token::As)?;
+        let alias = self.parse_identifier("expected alias")?;
+
+        Ok(ScalarCall {
+            alias,
+            function,
+            arg,
+        })
+    }
+
+    //
+    // `table`, or `alias.table` for a table of an attached database.
+    //
+    fn parse_table_name(&mut self) -> Result<String, Error> {
+        let table = self.parse_identifier("expected table name")?;
+        if self.head() != Some(&Token::Dot) {
+            return Ok(table);
+        }
+        self.advance();
+        let attached_table = self.parse_identifier("expected table name after alias")?;
+
+        Ok(format!("{table}.{attached_table}"))
+    }
+
+    //
+    // `table.field`, or `alias.table.field` for a field of a table of an attached database.
+    //
+    fn parse_field_selector(&mut self) -> Result<FieldSelector, Error> {
+        let mut source = self.parse_identifier("expected table name")?;
+        self.must_swallow(&Token::Dot)?;
+        let mut name = self.parse_identifier("expected field name")?;
+        if self.head() == Some(&Token::Dot) {
+            self.advance();
+            source = format!("{source}.{name}");
+            name = self.parse_identifier("expected field name")?;
+        }
+
+        Ok(FieldSelector { name, source })
+    }
+
+    fn parse_identifier(&mut self, message: &str) -> Result<String, Error> {
+        let Some(Token::Identifier(identifier)) = self.head().cloned() else {
+            return Err(self.bail(message));
+        };
+        self.advance();
+
+        Ok(identifier)
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use std::collections::{BTreeMap, HashMap};
+
+    use indexmap::IndexMap;
+
     use crate::{
+        custom_type::CustomType,
         lexer::Lexer,
-        query::{Query, SelectQuery},
+        query::{
+            AnalyzeQuery, AttachQuery, CompareOp, CreateTableQuery, DeleteQuery, DescribeQuery,
+            ExplainQuery, FieldSelector, InsertQuery, InsertStatement, OrderBy, Query, RhsValue,
+            RowFilter, SampleSpec, ScalarCall, SelectQuery, SetOperation, SetQuery, SettingValue,
+            UnionQuery,
+        },
+        schema::{Compression, DefaultExpr, FieldSchema, StorageOptions, TableLayout, TableSchema},
+        value::{Interval, Value},
     };
 
-    use super::Parser;
+    use super::{LiteralSlot, Parser};
 
     #[test]
     fn test_minimal_select_query() {
@@ -102,9 +736,669 @@ mod test {
             Query::Select(SelectQuery {
                 from: "t1".into(),
                 joins: vec![],
-                filters: vec![]
+                filters: vec![],
+                ..Default::default()
+            }),
+            query,
+        );
+    }
+
+    #[test]
+    fn test_select_table_alias() {
+        let parse = |raw: &[u8]| Parser::new(&Lexer::tokenize(raw).unwrap()[..]).parse();
+
+        assert_eq!(
+            Query::Select(SelectQuery {
+                from: "e".into(),
+                aliases: BTreeMap::from([("e".into(), "employees".into())]),
+                ..Default::default()
+            }),
+            parse(b"SELECT FROM employees AS e").unwrap(),
+        );
+        assert!(parse(b"SELECT FROM employees AS").is_err());
+    }
+
+    #[test]
+    fn test_select_derived_table() {
+        let parse = |raw: &[u8]| Parser::new(&Lexer::tokenize(raw).unwrap()[..]).parse();
+
+        assert_eq!(
+            Query::Select(SelectQuery {
+                from: "d".into(),
+                derived_tables: BTreeMap::from([(
+                    "d".into(),
+                    SelectQuery {
+                        from: "e".into(),
+                        aliases: BTreeMap::from([("e".into(), "employees".into())]),
+                        distinct: true,
+                        ..Default::default()
+                    }
+                )]),
+                sample: Some(SampleSpec::Percent(10)),
+                ..Default::default()
+            }),
+            parse(b"SELECT FROM (SELECT DISTINCT FROM employees AS e) AS d TABLESAMPLE 10")
+                .unwrap(),
+        );
+        assert!(parse(b"SELECT FROM (SELECT FROM employees)").is_err());
+        assert!(parse(b"SELECT FROM (SELECT FROM employees AS d").is_err());
+        assert!(parse(b"SELECT FROM (SELECT FROM employees TABLESAMPLE 10) AS d").is_err());
+    }
+
+    #[test]
+    fn test_union_query() {
+        let query = Parser::new(
+            &Lexer::tokenize(b"SELECT FROM t1 UNION SELECT FROM t2 UNION SELECT FROM t3")
+                .expect("failed to tokenize")[..],
+        )
+        .parse()
+        .expect("failed to parse");
+
+        assert_eq!(
+            Query::Union(UnionQuery {
+                selects: vec![
+                    SelectQuery {
+                        from: "t1".into(),
+                        joins: vec![],
+                        filters: vec![],
+                        ..Default::default()
+                    },
+                    SelectQuery {
+                        from: "t2".into(),
+                        joins: vec![],
+                        filters: vec![],
+                        ..Default::default()
+                    },
+                    SelectQuery {
+                        from: "t3".into(),
+                        joins: vec![],
+                        filters: vec![],
+                        ..Default::default()
+                    },
+                ],
+                op: SetOperation::Union,
+                all: false,
+            }),
+            query,
+        );
+    }
+
+    #[test]
+    fn test_intersect_and_except_queries() {
+        let parse = |raw: &[u8]| Parser::new(&Lexer::tokenize(raw).unwrap()[..]).parse();
+        let select = |from: &str| SelectQuery {
+            from: from.into(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Query::Union(UnionQuery {
+                selects: vec![select("t1"), select("t2")],
+                op: SetOperation::Intersect,
+                all: false,
+            }),
+            parse(b"SELECT FROM t1 INTERSECT SELECT FROM t2").unwrap(),
+        );
+        assert_eq!(
+            Query::Union(UnionQuery {
+                selects: vec![select("t1"), select("t2"), select("t3")],
+                op: SetOperation::Except,
+                all: true,
+            }),
+            parse(b"SELECT FROM t1 EXCEPT ALL SELECT FROM t2 EXCEPT ALL SELECT FROM t3").unwrap(),
+        );
+        assert!(parse(b"SELECT FROM t1 UNION SELECT FROM t2 EXCEPT SELECT FROM t3").is_err());
+    }
+
+    #[test]
+    fn test_mixed_union_query_fails() {
+        let tokens =
+            Lexer::tokenize(b"SELECT FROM t1 UNION SELECT FROM t2 UNION ALL SELECT FROM t3")
+                .expect("failed to tokenize");
+
+        assert!(Parser::new(&tokens[..]).parse().is_err());
+    }
+
+    #[test]
+    fn test_explain_query() {
+        let query = Parser::new(
+            &Lexer::tokenize(b"EXPLAIN SELECT FROM t1").expect("failed to tokenize")[..],
+        )
+        .parse()
+        .expect("failed to parse");
+
+        assert_eq!(
+            Query::Explain(ExplainQuery {
+                select: SelectQuery {
+                    from: "t1".into(),
+                    ..Default::default()
+                },
+                analyze: false,
+            }),
+            query,
+        );
+    }
+
+    #[test]
+    fn test_explain_analyze_query() {
+        let query = Parser::new(
+            &Lexer::tokenize(b"EXPLAIN ANALYZE SELECT FROM t1").expect("failed to tokenize")[..],
+        )
+        .parse()
+        .expect("failed to parse");
+
+        assert_eq!(
+            Query::Explain(ExplainQuery {
+                select: SelectQuery {
+                    from: "t1".into(),
+                    ..Default::default()
+                },
+                analyze: true,
             }),
             query,
         );
     }
+
+    #[test]
+    fn test_set_query() {
+        let parse = |raw: &[u8]| {
+            Parser::new(&Lexer::tokenize(raw).expect("failed to tokenize")[..])
+                .parse()
+                .expect("failed to parse")
+        };
+
+        assert_eq!(
+            Query::Set(SetQuery {
+                name: "max_rows".into(),
+                value: SettingValue::Int(100),
+            }),
+            parse(b"SET max_rows = 100"),
+        );
+        assert_eq!(
+            Query::Set(SetQuery {
+                name: "output".into(),
+                value: SettingValue::Word("csv".into()),
+            }),
+            parse(b"SET output = csv"),
+        );
+
+        let tokens = Lexer::tokenize(b"SET timing on").expect("failed to tokenize");
+        assert!(Parser::new(&tokens[..]).parse().is_err());
+    }
+
+    #[test]
+    fn test_analyze_query() {
+        let tokens = Lexer::tokenize(b"ANALYZE t1").expect("failed to tokenize");
+        assert_eq!(
+            Query::Analyze(AnalyzeQuery { table: "t1".into() }),
+            Parser::new(&tokens[..]).parse().expect("failed to parse"),
+        );
+
+        let tokens = Lexer::tokenize(b"ANALYZE").expect("failed to tokenize");
+        assert!(Parser::new(&tokens[..]).parse().is_err());
+    }
+
+    #[test]
+    fn test_insert_statement() {
+        let tokens = Lexer::tokenize(b"INSERT INTO t1 (a, b) VALUES (1, 200) RETURNING b")
+            .expect("failed to tokenize");
+        assert_eq!(
+            Query::Insert(InsertStatement {
+                query: InsertQuery {
+                    table: "t1".into(),
+                    values: HashMap::from([
+                        ("a".into(), Value::I32(1)),
+                        ("b".into(), Value::I32(200)),
+                    ]),
+                },
+                returning: Some(vec!["b".into()]),
+            }),
+            Parser::new(&tokens[..]).parse().expect("failed to parse"),
+        );
+
+        let tokens =
+            Lexer::tokenize(b"INSERT INTO t1 () VALUES () RETURNING").expect("failed to tokenize");
+        assert_eq!(
+            Query::Insert(InsertStatement {
+                query: InsertQuery {
+                    table: "t1".into(),
+                    values: HashMap::new(),
+                },
+                returning: Some(vec![]),
+            }),
+            Parser::new(&tokens[..]).parse().expect("failed to parse"),
+        );
+
+        for invalid in [
+            &b"INSERT INTO t1 (a) VALUES (1, 2)"[..],
+            b"INSERT INTO t1 (a, b) VALUES (1)",
+            b"INSERT t1 (a) VALUES (1)",
+        ] {
+            let tokens = Lexer::tokenize(invalid).expect("failed to tokenize");
+            assert!(Parser::new(&tokens[..]).parse().is_err());
+        }
+    }
+
+    #[test]
+    fn test_delete_query() {
+        let tokens = Lexer::tokenize(b"DELETE FROM t1").expect("failed to tokenize");
+        assert_eq!(
+            Query::Delete(DeleteQuery {
+                table: "t1".into(),
+                filters: vec![],
+            }),
+            Parser::new(&tokens[..]).parse().expect("failed to parse"),
+        );
+    }
+
+    #[test]
+    fn test_describe_query() {
+        let tokens = Lexer::tokenize(b"DESCRIBE t1").expect("failed to tokenize");
+        assert_eq!(
+            Query::Describe(DescribeQuery { table: "t1".into() }),
+            Parser::new(&tokens[..]).parse().expect("failed to parse"),
+        );
+    }
+
+    #[test]
+    fn test_attach_query() {
+        let parse = |raw: &[u8]| {
+            Parser::new(&Lexer::tokenize(raw).expect("failed to tokenize")[..]).parse()
+        };
+
+        assert_eq!(
+            Query::Attach(AttachQuery {
+                path: "/tmp/other db".into(),
+                alias: "other".into(),
+            }),
+            parse(b"ATTACH '/tmp/other db' AS other").unwrap()
+        );
+        assert!(parse(b"ATTACH other AS other").is_err());
+        assert!(parse(b"ATTACH '/tmp/other'").is_err());
+
+        assert_eq!(
+            Query::Select(SelectQuery {
+                from: "other.t1".into(),
+                order_by: Some(OrderBy {
+                    field: FieldSelector {
+                        name: "a".into(),
+                        source: "other.t1".into(),
+                    },
+                    descending: false,
+                }),
+                ..Default::default()
+            }),
+            parse(b"SELECT FROM other.t1 ORDER BY other.t1.a").unwrap()
+        );
+        assert_eq!(
+            Query::Describe(DescribeQuery {
+                table: "other.t1".into()
+            }),
+            parse(b"DESCRIBE other.t1").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_create_table_query() {
+        let parse = |raw: &[u8]| {
+            let tokens = Lexer::tokenize(raw).expect("failed to tokenize");
+            let mut parser = Parser::new(&tokens[..]);
+            parser
+                .parse()
+                .map(|query| (query, parser.literal_slots().to_vec()))
+        };
+
+        let (query, literal_slots) = parse(
+            b"CREATE TABLE t1 (a I32, b U8) WITH (layout='columnar', compression='lz4', ptr_width=4)",
+        )
+        .unwrap();
+        assert_eq!(
+            Query::CreateTable(CreateTableQuery {
+                schema: TableSchema {
+                    name: "t1".into(),
+                    fields: IndexMap::from([
+                        ("a".into(), FieldSchema::I32),
+                        ("b".into(), FieldSchema::U8),
+                    ]),
+                    storage: StorageOptions {
+                        layout: TableLayout::Columnar,
+                        compression: Compression::Lz4,
+                        ptr_width: 4,
+                    },
+                    ..Default::default()
+                },
+            }),
+            query
+        );
+        assert_eq!(
+            vec![LiteralSlot::StorageOption("ptr_width".into())],
+            literal_slots
+        );
+
+        let (Query::CreateTable(query), _) = parse(b"CREATE TABLE t1 (a I32)").unwrap() else {
+            panic!("expected a create table query");
+        };
+        assert_eq!(StorageOptions::default(), query.schema.storage);
+
+        // Unknown types are rejected by the database, unless registered.
+        let (Query::CreateTable(query), _) = parse(b"CREATE TABLE t1 (a I64)").unwrap() else {
+            panic!("expected a create table query");
+        };
+        assert_eq!(
+            FieldSchema::Custom(CustomType::new("I64")),
+            query.schema.fields["a"]
+        );
+        assert!(parse(b"CREATE TABLE t1 (a 7)").is_err());
+        assert!(parse(b"CREATE TABLE t1 (a I32, a U8)").is_err());
+        assert!(parse(b"CREATE TABLE t1 (a I32) WITH (ptr_width=2)").is_err());
+        assert!(parse(b"CREATE TABLE t1 (a I32) WITH (layout='diagonal')").is_err());
+        assert!(parse(b"CREATE TABLE t1 (a I32) WITH (page_size=4)").is_err());
+
+        let (Query::CreateTable(query), literal_slots) = parse(
+            b"CREATE TABLE t1 (id I32 DEFAULT next_id(), at I32 DEFAULT now(), b U8 DEFAULT 3, \
+              c I32 DEFAULT double(4), d I32 DEFAULT random())",
+        )
+        .unwrap() else {
+            panic!("expected a create table query");
+        };
+        assert_eq!(
+            IndexMap::from([
+                ("id".into(), DefaultExpr::NextId),
+                ("at".into(), DefaultExpr::Now),
+                ("b".into(), DefaultExpr::Value(Value::I32(3))),
+                (
+                    "c".into(),
+                    DefaultExpr::Scalar {
+                        function: "double".into(),
+                        arg: Value::I32(4),
+                    }
+                ),
+                (
+                    "d".into(),
+                    DefaultExpr::Scalar {
+                        function: "random".into(),
+                        arg: Value::NULL,
+                    }
+                ),
+            ]),
+            query.schema.defaults
+        );
+        assert_eq!(5, query.schema.fields.len());
+        assert_eq!(
+            vec![
+                LiteralSlot::DefaultValue("b".into()),
+                LiteralSlot::DefaultValue("c".into())
+            ],
+            literal_slots
+        );
+        assert!(parse(b"CREATE TABLE t1 (a I32 DEFAULT)").is_err());
+        assert!(parse(b"CREATE TABLE t1 (a I32 DEFAULT now)").is_err());
+    }
+
+    #[test]
+    fn test_create_table_decimal() {
+        let parse = |raw: &[u8]| {
+            let tokens = Lexer::tokenize(raw).expect("failed to tokenize");
+            let mut parser = Parser::new(&tokens[..]);
+            parser
+                .parse()
+                .map(|query| (query, parser.literal_slots().to_vec()))
+        };
+
+        let (Query::CreateTable(query), literal_slots) =
+            parse(b"CREATE TABLE t1 (a DECIMAL(10, 2), b DECIMAL(4))").unwrap()
+        else {
+            panic!("expected a create table query");
+        };
+        assert_eq!(
+            IndexMap::from([
+                (
+                    "a".into(),
+                    FieldSchema::Decimal {
+                        precision: 10,
+                        scale: 2
+                    }
+                ),
+                (
+                    "b".into(),
+                    FieldSchema::Decimal {
+                        precision: 4,
+                        scale: 0
+                    }
+                ),
+            ]),
+            query.schema.fields
+        );
+        assert_eq!(
+            vec![
+                LiteralSlot::DecimalPrecision("a".into()),
+                LiteralSlot::DecimalScale("a".into()),
+                LiteralSlot::DecimalPrecision("b".into()),
+            ],
+            literal_slots
+        );
+        assert!(parse(b"CREATE TABLE t1 (a DECIMAL)").is_err());
+        assert!(parse(b"CREATE TABLE t1 (a DECIMAL(300))").is_err());
+    }
+
+    #[test]
+    fn test_scalar_calls() {
+        let tokens = Lexer::tokenize(b"SELECT double(t1.a) AS d, negate(t1.b) AS n FROM t1")
+            .expect("failed to tokenize");
+        let call = |function: &str, field: &str, alias: &str| ScalarCall {
+            alias: alias.into(),
+            function: function.into(),
+            arg: FieldSelector {
+                name: field.into(),
+                source: "t1".into(),
+            },
+        };
+
+        assert_eq!(
+            Query::Select(SelectQuery {
+                from: "t1".into(),
+                scalar_calls: vec![call("double", "a", "d"), call("negate", "b", "n")],
+                ..Default::default()
+            }),
+            Parser::new(&tokens[..]).parse().expect("failed to parse"),
+        );
+
+        for raw in [
+            &b"SELECT double(t1.a) FROM t1"[..],
+            b"SELECT double(t1.a) AS d negate(t1.b) AS n FROM t1",
+        ] {
+            let tokens = Lexer::tokenize(raw).expect("failed to tokenize");
+            assert!(Parser::new(&tokens[..]).parse().is_err());
+        }
+    }
+
+    #[test]
+    fn test_table_sample() {
+        let parse = |raw: &[u8]| {
+            Parser::new(&Lexer::tokenize(raw).expect("failed to tokenize")[..]).parse()
+        };
+        let sampled = |sample| {
+            Query::Select(SelectQuery {
+                from: "t1".into(),
+                sample: Some(sample),
+                ..Default::default()
+            })
+        };
+
+        assert_eq!(
+            sampled(SampleSpec::Percent(10)),
+            parse(b"SELECT FROM t1 TABLESAMPLE 10").unwrap()
+        );
+        assert_eq!(
+            sampled(SampleSpec::First(5)),
+            parse(b"SELECT FROM t1 TABLESAMPLE FIRST 5").unwrap()
+        );
+        assert!(parse(b"SELECT FROM t1 TABLESAMPLE 101").is_err());
+        assert!(parse(b"SELECT FROM t1 TABLESAMPLE").is_err());
+    }
+
+    #[test]
+    fn test_select_into() {
+        let parse = |raw: &[u8]| {
+            Parser::new(&Lexer::tokenize(raw).expect("failed to tokenize")[..]).parse()
+        };
+
+        assert_eq!(
+            Query::Select(SelectQuery {
+                from: "t1".into(),
+                into: Some("t2".into()),
+                ..Default::default()
+            }),
+            parse(b"SELECT INTO t2 FROM t1").unwrap()
+        );
+        let Query::Select(select_query) =
+            parse(b"SELECT double(t1.a) AS d INTO t2 FROM t1").unwrap()
+        else {
+            panic!("expected a select query");
+        };
+        assert_eq!(1, select_query.scalar_calls.len());
+        assert_eq!(Some("t2".into()), select_query.into);
+
+        assert!(parse(b"SELECT INTO FROM t1").is_err());
+    }
+
+    #[test]
+    fn test_with_deleted() {
+        let parse = |raw: &[u8]| {
+            Parser::new(&Lexer::tokenize(raw).expect("failed to tokenize")[..]).parse()
+        };
+
+        assert_eq!(
+            Query::Select(SelectQuery {
+                from: "t1".into(),
+                sample: Some(SampleSpec::Percent(50)),
+                with_deleted: true,
+                ..Default::default()
+            }),
+            parse(b"SELECT FROM t1 TABLESAMPLE 50 WITH DELETED").unwrap()
+        );
+        assert!(parse(b"SELECT FROM t1 WITH").is_err());
+    }
+
+    #[test]
+    fn test_distinct() {
+        let parse = |raw: &[u8]| {
+            Parser::new(&Lexer::tokenize(raw).expect("failed to tokenize")[..]).parse()
+        };
+
+        assert_eq!(
+            Query::Select(SelectQuery {
+                from: "t1".into(),
+                distinct: true,
+                ..Default::default()
+            }),
+            parse(b"SELECT DISTINCT FROM t1").unwrap()
+        );
+        assert_eq!(
+            Query::Select(SelectQuery {
+                from: "t1".into(),
+                distinct: true,
+                distinct_field: Some(FieldSelector {
+                    name: "f1".into(),
+                    source: "t1".into(),
+                }),
+                ..Default::default()
+            }),
+            parse(b"SELECT DISTINCT t1.f1 FROM t1").unwrap()
+        );
+        assert!(parse(b"SELECT FROM DISTINCT t1").is_err());
+        assert!(parse(b"SELECT DISTINCT t1.f1, t1.f2 FROM t1").is_err());
+    }
+
+    #[test]
+    fn test_order_by() {
+        let parse = |raw: &[u8]| {
+            Parser::new(&Lexer::tokenize(raw).expect("failed to tokenize")[..]).parse()
+        };
+        let ordered = |descending| {
+            Query::Select(SelectQuery {
+                from: "t1".into(),
+                order_by: Some(OrderBy {
+                    field: FieldSelector {
+                        name: "a".into(),
+                        source: "t1".into(),
+                    },
+                    descending,
+                }),
+                ..Default::default()
+            })
+        };
+
+        assert_eq!(
+            ordered(false),
+            parse(b"SELECT FROM t1 ORDER BY t1.a").unwrap()
+        );
+        assert_eq!(
+            ordered(false),
+            parse(b"SELECT FROM t1 ORDER BY t1.a ASC").unwrap()
+        );
+        assert_eq!(
+            ordered(true),
+            parse(b"SELECT FROM t1 ORDER BY t1.a DESC").unwrap()
+        );
+        assert!(parse(b"SELECT FROM t1 ORDER t1.a").is_err());
+        assert!(parse(b"SELECT FROM t1 ORDER BY a").is_err());
+    }
+
+    #[test]
+    fn test_where() {
+        let parse = |raw: &[u8]| Parser::new(&Lexer::tokenize(raw).unwrap()[..]).parse();
+        let field = |source: &str, name: &str| FieldSelector {
+            name: name.into(),
+            source: source.into(),
+        };
+
+        assert_eq!(
+            Query::Select(SelectQuery {
+                from: "t2".into(),
+                filters: vec![
+                    RowFilter {
+                        field: field("t2", "ts"),
+                        op: CompareOp::Lt,
+                        rhs: RhsValue::Interval {
+                            reference: field("t2", "start"),
+                            interval: Interval(3600),
+                        },
+                    },
+                    RowFilter {
+                        field: field("t2", "a"),
+                        op: CompareOp::Ne,
+                        rhs: RhsValue::Value(Value::I32(1)),
+                    },
+                    RowFilter {
+                        field: field("t2", "a"),
+                        op: CompareOp::Le,
+                        rhs: RhsValue::Ref(field("t2", "b")),
+                    },
+                ],
+                order_by: Some(OrderBy {
+                    field: field("t2", "ts"),
+                    descending: false,
+                }),
+                ..Default::default()
+            }),
+            parse(
+                b"SELECT FROM t2 WHERE t2.ts - t2.start < 3600 AND t2.a != 1 AND t2.a <= t2.b \
+                  ORDER BY t2.ts"
+            )
+            .unwrap(),
+        );
+
+        let tokens = Lexer::tokenize(b"SELECT FROM t2 WHERE t2.a = t2.b AND t2.a > 5").unwrap();
+        let mut parser = Parser::new(&tokens[..]);
+        parser.parse().unwrap();
+        assert_eq!(&[LiteralSlot::FilterValue(0, 1)], parser.literal_slots());
+
+        assert!(parse(b"SELECT FROM t2 WHERE").is_err());
+        assert!(parse(b"SELECT FROM t2 WHERE t2.a 1").is_err());
+        assert!(parse(b"SELECT FROM t2 WHERE t2.a = 1 AND").is_err());
+        // An interval is a number.
+        assert!(parse(b"SELECT FROM t2 WHERE t2.ts - t2.start < t2.b").is_err());
+        assert!(parse(b"SELECT FROM (SELECT FROM t2 WHERE t2.a = 1) AS d").is_err());
+    }
 }
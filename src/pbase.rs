@@ -1,152 +1,3212 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::{Display, Formatter},
     fs::{File, OpenOptions},
-    io::Write,
-    path::PathBuf,
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+    ops::AddAssign,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError, RwLock, RwLockReadGuard,
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
 use crate::{
+    archive::{TableArchiveHeader, TableArchiveReader, TableArchiveWriter},
+    audit::{audit_rows, audit_table_schema, AuditEntry, AuditOp, AUDIT_TABLE},
     common::{Error, PBaseError},
-    query::{CreateTableQuery, InsertQuery, SelectQuery},
-    query_tools::{find_insert_pos_in_index, SelectQueryExecutor},
-    schema::{TablePtrType, TableSchema},
-    table_opener::TableOpener,
+    consistency::{ConsistencyChecker, ConsistencyReport},
+    copy::{read_copy_stream, CopyHeader},
+    custom_type::TypeDef,
+    dictionary::{Dictionary, DICTIONARY_ENCODE_MAX_DISTINCT, DICTIONARY_ENCODE_MIN_ROWS},
+    external::ExternalTable,
+    function::ScalarFunctions,
+    index_advisor::IndexRecommendation,
+    lexer::Token,
+    lock::LockManager,
+    maintenance::IoThrottle,
+    operator::Row,
+    plan::{QueryPlan, QueryStats},
+    plan_cache::PlanCache,
+    platform::{atomic_write, validate_file_stem, FileLock, TempDir},
+    pool::{run_transaction, Transaction, TRANSACTION_ATTEMPTS},
+    progress::{NoProgress, ProgressReporter, PROGRESS_STEP_ROWS},
+    query::{
+        CompareOp, CreateTableQuery, DeleteQuery, FieldSelector, InsertQuery, MutationQuery, Query,
+        RhsValue, RowCap, RowFilter, SelectQuery, SetQuery, SettingValue, UnionQuery,
+    },
+    query_log::QueryLog,
+    query_tools::{
+        find_insert_pos_in_index, find_row_pos_in_index, SelectQueryExecutor, UnionQueryExecutor,
+    },
+    quota::Quota,
+    result_set::{ColumnInfo, ResultSet},
+    row_cache::{RowCache, RowCacheKey},
+    row_view::RowView,
+    schema::{DefaultExpr, FieldSchema, InsertMode, TablePtrType, TableSchema, VERSION_FIELD},
+    schema_diff::SchemaDiff,
+    snapshot::{query_tables, ReadSnapshot, SnapshotPins},
+    stats::{ColumnDescription, ColumnStats, Histogram, TableStats, HISTOGRAM_BUCKETS},
+    system_tables::is_system_table,
+    table_opener::{IoStrategy, TableOpener},
     value::Value,
+    wal::{Lsn, Wal, WalOp, WalRecord},
 };
 
 use anyhow::Context;
+use indexmap::IndexMap;
+
+// Values of a table row by field name.
+type FieldValues = HashMap<String, Value>;
+
+///
+/// What a mutation would change, as reported by `PBase::dry_run`.
+///
+#[derive(Debug, PartialEq, Eq)]
+pub struct DryRunReport {
+    pub table: String,
+    pub affected_rows: usize,
+    // Indices that would get entries added or moved, in declaration order.
+    pub changed_indices: Vec<String>,
+}
+
+///
+/// What an update or delete did, row by row summed up.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MutationResult {
+    // Rows the mutation selected.
+    pub matched: usize,
+    // Matched rows with a value changed (versioned rows always change).
+    pub modified: usize,
+    // Index entries moved to a new position, or removed by a delete.
+    pub index_entries: usize,
+    // Bytes written to the table data and indices (not counting index delta merges).
+    pub bytes_written: usize,
+}
+
+impl AddAssign for MutationResult {
+    fn add_assign(&mut self, other: Self) {
+        self.matched += other.matched;
+        self.modified += other.modified;
+        self.index_entries += other.index_entries;
+        self.bytes_written += other.bytes_written;
+    }
+}
+
+impl Display for MutationResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} rows matched, {} modified, {} index entries moved, {} bytes written",
+            self.matched, self.modified, self.index_entries, self.bytes_written
+        )
+    }
+}
+
+// The outcome of a row update.
+struct RowUpdate {
+    table_schema: TableSchema,
+    // Position of the row after the update, see `moved_row_pos`.
+    row_pos: TablePtrType,
+    old_row: FieldValues,
+    new_row: FieldValues,
+    result: MutationResult,
+}
+
+///
+/// A row inserted by `PBase::run_insert_query_returning`.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InsertedRow {
+    // Byte position of the row in the table data, as `update_row_at` takes it.
+    pub row_pos: TablePtrType,
+    // The returned fields of the row, as a single row result.
+    pub result: ResultSet,
+}
+
+// Columns of the edge tables walked by `PBase::traverse`.
+pub const EDGE_SRC_FIELD: &str = "src";
+pub const EDGE_DST_FIELD: &str = "dst";
+
+// Index entries appended to an index delta before it is merged into the sorted index.
+pub const INDEX_DELTA_MERGE_ROWS: usize = 1024;
+
+// How often a handle waiting for the writer lock checks whether it was released.
+const WRITER_LOCK_POLL_INTERVAL: Duration = Duration::from_millis(50);
 
 pub struct PBase {
     table_opener: TableOpener,
+    wal: Wal,
+    insert_mode: InsertMode,
+    functions: ScalarFunctions,
+    query_log: QueryLog,
+    row_cache: RowCache,
+    plan_cache: PlanCache,
+    // Held while this handle is the directory's writer, see `try_lock_writer`.
+    writer_lock: Option<FileLock>,
+    read_only: bool,
+    progress: Arc<dyn ProgressReporter>,
+    quota: Quota,
+    row_cap: RowCap,
+    audit_actor: i32,
+    // Budget of the file IO of maintenance, see `with_maintenance_io_limit`.
+    maintenance_throttle: IoThrottle,
+    // Shared by writes, taken exclusively while a read snapshot is captured, see `read_snapshot`.
+    snapshot_gate: RwLock<()>,
+    // Tables with live read snapshots, see `write_row_bytes`.
+    snapshot_pins: SnapshotPins,
+    // Table locks of the transactions of this handle, see `with_transaction`.
+    locks: LockManager,
+    next_transaction_id: AtomicU64,
+    // Rows written per table through this handle since the table was last analyzed, see
+    // `refresh_stale_stats`.
+    modified_rows: Mutex<HashMap<String, usize>>,
+    // The directory of a `new_temp` handle, removed with it. Declared last so that it is dropped
+    // after the files above are closed.
+    temp_dir: Option<TempDir>,
 }
 
-impl PBase {
-    #[must_use]
-    pub const fn new(current_dir: PathBuf) -> Self {
-        Self {
-            table_opener: TableOpener::new(current_dir),
+impl PBase {
+    #[must_use]
+    pub fn new(current_dir: PathBuf) -> Self {
+        let table_opener = TableOpener::new(current_dir);
+        let wal = Wal::new(table_opener.wal_file_name());
+
+        Self {
+            table_opener,
+            wal,
+            insert_mode: InsertMode::default(),
+            functions: ScalarFunctions::default(),
+            query_log: QueryLog::new(),
+            row_cache: RowCache::default(),
+            plan_cache: PlanCache::default(),
+            writer_lock: None,
+            read_only: false,
+            progress: Arc::new(NoProgress),
+            quota: Quota::default(),
+            row_cap: RowCap::default(),
+            audit_actor: 0,
+            maintenance_throttle: IoThrottle::default(),
+            snapshot_gate: RwLock::new(()),
+            snapshot_pins: SnapshotPins::default(),
+            locks: LockManager::new(),
+            next_transaction_id: AtomicU64::new(1),
+            modified_rows: Mutex::new(HashMap::new()),
+            temp_dir: None,
+        }
+    }
+
+    ///
+    /// A handle of a new, empty directory under the system temp directory, removed when the handle
+    /// is dropped. Tests and apps get isolated tables without prefixing their names.
+    ///
+    /// # Errors
+    ///
+    /// When the directory cannot be created.
+    pub fn new_temp() -> Result<Self, Error> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Self::new(temp_dir.path().to_path_buf());
+        db.temp_dir = Some(temp_dir);
+
+        Ok(db)
+    }
+
+    ///
+    /// The directory of the database.
+    ///
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.table_opener.dir
+    }
+
+    ///
+    /// Attaches the database in the directory under the alias (`ATTACH '/path' AS alias`), so that
+    /// queries read its tables as `alias.table`, eg. to join them with the tables of this database.
+    /// Attached tables are read-only, and stay attached as long as the handle lives.
+    ///
+    /// # Errors
+    ///
+    /// When the path is not a directory, the alias is not a valid name, or a database is already
+    /// attached under it.
+    pub fn attach<P: AsRef<Path>>(&self, path: P, alias: &str) -> Result<(), Error> {
+        self.table_opener.attach(path.as_ref().to_path_buf(), alias)
+    }
+
+    ///
+    /// Sets how inserts treat values not matching the table schema (lenient by default).
+    ///
+    #[must_use]
+    pub const fn with_insert_mode(mut self, insert_mode: InsertMode) -> Self {
+        self.insert_mode = insert_mode;
+        self
+    }
+
+    ///
+    /// Sets how the files of tables without their own IO strategy are read (mapped by default).
+    ///
+    #[must_use]
+    pub fn with_io_strategy(self, io_strategy: IoStrategy) -> Self {
+        Self {
+            table_opener: self.table_opener.with_io_strategy(io_strategy),
+            ..self
+        }
+    }
+
+    ///
+    /// Sets how many point lookup results the row cache keeps (`ROW_CACHE_ROWS` by default, 0
+    /// disables the cache).
+    ///
+    #[must_use]
+    pub fn with_row_cache_capacity(mut self, rows: usize) -> Self {
+        self.row_cache = RowCache::new(rows);
+        self
+    }
+
+    ///
+    /// Sets how many statements and logical plans the plan cache keeps (`PLAN_CACHE_ENTRIES` of
+    /// each by default, 0 disables the cache).
+    ///
+    #[must_use]
+    pub fn with_plan_cache_capacity(mut self, entries: usize) -> Self {
+        self.plan_cache = PlanCache::new(entries);
+        self
+    }
+
+    ///
+    /// Rejects every write through this handle with `PBaseError::ReadOnly`, for readers of a
+    /// directory another process writes.
+    ///
+    #[must_use]
+    pub const fn with_read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    #[must_use]
+    pub const fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    ///
+    /// Sets who the audit table records as the actor of this handle's mutations (0 by default),
+    /// eg. an application user id.
+    ///
+    #[must_use]
+    pub const fn with_audit_actor(mut self, actor: i32) -> Self {
+        self.audit_actor = actor;
+        self
+    }
+
+    ///
+    /// Sets the caps inserts and imports are checked against (none by default).
+    ///
+    #[must_use]
+    pub const fn with_quota(mut self, quota: Quota) -> Self {
+        self.quota = quota;
+        self
+    }
+
+    #[must_use]
+    pub const fn quota(&self) -> &Quota {
+        &self.quota
+    }
+
+    ///
+    /// Sets the cap on the rows selects return, unless they set their own (`DEFAULT_ROW_CAP` rows,
+    /// past which selects fail, by default).
+    ///
+    #[must_use]
+    pub const fn with_row_cap(mut self, row_cap: RowCap) -> Self {
+        self.row_cap = row_cap;
+        self
+    }
+
+    ///
+    /// Caps the file IO of maintenance (`merge_index_deltas`, eg. run by an `IndexMergeTask`) at
+    /// the bytes per second, so it does not starve foreground queries on slow disks (unlimited by
+    /// default). Merges inserts trigger themselves are not capped. Also set with
+    /// `SET maintenance_io_limit = bytes_per_sec | off`, see `set`.
+    ///
+    #[must_use]
+    pub fn with_maintenance_io_limit(self, bytes_per_sec: u64) -> Self {
+        self.maintenance_throttle.set_limit(Some(bytes_per_sec));
+        self
+    }
+
+    ///
+    /// Applies a database setting of a `SET` statement. `maintenance_io_limit` is the only one:
+    /// the bytes per second maintenance may read and write (see `with_maintenance_io_limit`), or
+    /// `off`. Takes effect from the next chunk of IO, also for maintenance already running.
+    ///
+    /// # Errors
+    ///
+    /// `UnknownSetting` for other names (eg. session settings, see `Session::set`), and
+    /// `InvalidSettingValue` for values that are not a positive number or `off`.
+    pub fn set(&self, query: &SetQuery) -> Result<(), Error> {
+        if query.name != "maintenance_io_limit" {
+            return Err(PBaseError::UnknownSetting(query.name.clone()).into());
+        }
+
+        let limit = match &query.value {
+            SettingValue::Int(v) if *v > 0 => Some(u64::from(v.unsigned_abs())),
+            SettingValue::Word(word) if word == "off" => None,
+            _ => {
+                return Err(PBaseError::InvalidSettingValue {
+                    name: query.name.clone(),
+                    value: query.value.to_string(),
+                }
+                .into())
+            }
+        };
+        self.maintenance_throttle.set_limit(limit);
+
+        Ok(())
+    }
+
+    ///
+    /// The bytes per second maintenance may read and write, None when unlimited.
+    ///
+    #[must_use]
+    pub fn maintenance_io_limit(&self) -> Option<u64> {
+        self.maintenance_throttle.limit()
+    }
+
+    ///
+    /// Sets where bulk operations (index builds, index delta merges, archive imports, ANALYZE,
+    /// `SELECT ... INTO` and consistency checks) report their progress (nowhere by default).
+    ///
+    #[must_use]
+    pub fn with_progress_reporter(mut self, progress: Arc<dyn ProgressReporter>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    ///
+    /// Takes the directory's writer lock (an advisory lock on `pbase.lock`), waiting up to
+    /// `timeout` for another process to release it. Returns whether the lock was taken; it is held
+    /// until this handle is dropped.
+    ///
+    /// Handles only respect each other's lock when they take it: a directory must only be written
+    /// by one process, and processes that may share it should lock before writing.
+    ///
+    /// # Errors
+    ///
+    /// On file operations.
+    pub fn try_lock_writer(&mut self, timeout: Duration) -> Result<bool, Error> {
+        if self.writer_lock.is_some() {
+            return Ok(true);
+        }
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(lock) = FileLock::try_acquire(&self.table_opener.writer_lock_file_name())? {
+                self.writer_lock = Some(lock);
+                return Ok(true);
+            }
+            if Instant::now() >= deadline {
+                return Ok(false);
+            }
+            std::thread::sleep(WRITER_LOCK_POLL_INTERVAL);
+        }
+    }
+
+    fn check_writable(&self) -> Result<(), Error> {
+        if self.read_only {
+            return Err(PBaseError::ReadOnly.into());
+        }
+
+        Ok(())
+    }
+
+    // Attached databases are only read, see `attach`.
+    fn check_not_attached_table(&self, table: &str) -> Result<(), Error> {
+        if self.table_opener.is_attached_table(table) {
+            return Err(PBaseError::AttachedTableWrite(table.to_string()).into());
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Accepts the tables as other handles left them. Until then, writing a table that another
+    /// handle wrote since this one last wrote or checked it fails with `PBaseError::StaleHandle`
+    /// (see `TableOpener::check_generation`). Drops the lengths of the tables committed through
+    /// this handle, and the cached plans and row lookups, which may be stale too.
+    ///
+    pub fn refresh(&self) {
+        self.table_opener.refresh_generations();
+        self.plan_cache.clear();
+        self.row_cache.clear();
+    }
+
+    ///
+    /// Runs the closure in a transaction of this handle, committing the writes it buffers (see
+    /// `Transaction`) when it succeeds, and running it again on conflicts, up to
+    /// `TRANSACTION_ATTEMPTS` times in all. See `PBasePool::with_transaction`, which does the same
+    /// for the transactions of a pool.
+    ///
+    /// # Errors
+    ///
+    /// The closure's error when it is not a conflict, or when it conflicted on every attempt, and
+    /// the commit's error.
+    pub fn with_transaction<T, F>(&self, body: F) -> Result<T, Error>
+    where
+        F: FnMut(&Transaction<'_>) -> Result<T, Error>,
+    {
+        run_transaction(
+            TRANSACTION_ATTEMPTS,
+            || {
+                Ok(Transaction::new(
+                    self,
+                    &self.locks,
+                    self.next_transaction_id.fetch_add(1, Ordering::Relaxed),
+                ))
+            },
+            body,
+        )
+    }
+
+    ///
+    /// Registers a scalar function callable by name from queries (see `ScalarCall`), replacing any
+    /// previous one of the same name. Results are reported with `return_type` in result columns.
+    ///
+    /// Example: `db.register_scalar("double", FieldSchema::I32, |v| ...)`.
+    ///
+    pub fn register_scalar<F>(&mut self, name: &str, return_type: FieldSchema, body: F)
+    where
+        F: Fn(&Value) -> Value + Send + Sync + 'static,
+    {
+        self.functions.register(name, return_type, body);
+    }
+
+    ///
+    /// Registers a type for fields to be declared of by name (see `FieldSchema::Custom`),
+    /// replacing any previous one of the same name. Tables with fields of the type can only be
+    /// opened while it is registered, with the byte size they were created with.
+    ///
+    /// Example: `db.register_type("money", Cents)`, then `CREATE TABLE t (price money)`.
+    ///
+    pub fn register_type<T: TypeDef + 'static>(&mut self, name: &str, type_def: T) {
+        self.table_opener.register_type(name, type_def);
+        self.plan_cache.clear();
+    }
+
+    #[must_use]
+    pub fn is_table_exist(&self, table_name: &str) -> bool {
+        self.table_opener
+            .table_schema_file_name(table_name)
+            .exists()
+    }
+
+    ///
+    /// Names of the regular tables, sorted.
+    ///
+    /// # Errors
+    ///
+    /// On file operations.
+    pub fn table_names(&self) -> Result<Vec<String>, Error> {
+        self.table_opener.table_names()
+    }
+
+    /// # Errors
+    ///
+    /// Errors when the table does not exist or its schema cannot be read.
+    pub fn table_schema(&self, table_name: &str) -> Result<TableSchema, Error> {
+        if let Some(external_table) = self.table_opener.open_external_table(table_name)? {
+            return Ok(external_table.schema);
+        }
+
+        self.table_opener.open_schema(table_name)
+    }
+
+    ///
+    /// The changes turning this database's schemas into the other's: of all tables of the other
+    /// database directory, or of the one table of a schema file (`.pbs`) compared to the table of
+    /// its name here. Generated ALTER statements come with it, see `SchemaDiff`.
+    ///
+    /// Meant for drift detection at deploy time, against the schemas a release expects.
+    ///
+    /// # Errors
+    ///
+    /// On file operations, or when a schema file is invalid.
+    pub fn schema_diff(&self, other: &Path) -> Result<SchemaDiff, Error> {
+        let open_schemas = |table_opener: &TableOpener| -> Result<Vec<TableSchema>, Error> {
+            table_opener
+                .table_names()?
+                .iter()
+                .map(|table_name| table_opener.open_schema(table_name))
+                .collect()
+        };
+
+        if other.is_dir() {
+            let other_schemas = open_schemas(&TableOpener::new(other.to_path_buf()))?;
+            return Ok(SchemaDiff::between(
+                &open_schemas(&self.table_opener)?,
+                &other_schemas,
+            ));
+        }
+
+        let other_schema: TableSchema = serde_json::from_reader(File::open(other)?)?;
+        let schemas = if self.is_table_exist(&other_schema.name) {
+            vec![self.table_opener.open_schema(&other_schema.name)?]
+        } else {
+            vec![]
+        };
+        Ok(SchemaDiff::between(&schemas, &[other_schema]))
+    }
+
+    ///
+    /// The columns of the table, in order, with their stats from the last `analyze_table` (None
+    /// before the first one, or for columns added since).
+    ///
+    /// # Errors
+    ///
+    /// Errors when the table does not exist, or its schema or stats cannot be read.
+    pub fn describe_table(&self, table_name: &str) -> Result<Vec<ColumnDescription>, Error> {
+        let table_schema = self.table_schema(table_name)?;
+        let mut column_stats = self
+            .table_opener
+            .open_stats(table_name)?
+            .map(|table_stats| table_stats.columns)
+            .unwrap_or_default();
+
+        Ok(table_schema
+            .fields
+            .into_iter()
+            .map(|(name, field_schema)| ColumnDescription {
+                stats: column_stats.remove(&name),
+                name,
+                field_schema,
+            })
+            .collect())
+    }
+
+    ///
+    /// Registers a CSV or Parquet file (by its `.csv` or `.parquet` extension) as a read-only table
+    /// that can be selected and joined like the regular ones. See `ExternalTable` for the inferred
+    /// schema, which is returned.
+    ///
+    /// # Errors
+    ///
+    /// On file operations, when the name is taken, or when the file cannot be read as a table.
+    pub fn register_external_table<P: AsRef<Path>>(
+        &self,
+        table_name: &str,
+        path: P,
+    ) -> Result<TableSchema, Error> {
+        self.check_writable()?;
+        self.check_not_attached_table(table_name)?;
+        if is_system_table(table_name) {
+            return Err(PBaseError::ReservedTableName(table_name.to_string()).into());
+        }
+        validate_file_stem(table_name)?;
+        if self.is_table_exist(table_name)
+            || self.table_opener.open_external_table(table_name)?.is_some()
+        {
+            return Err(PBaseError::TableAlreadyExists(table_name.to_string()).into());
+        }
+
+        let external_table = ExternalTable::infer(table_name, std::fs::canonicalize(path)?)?;
+        let external_file = File::create(self.table_opener.external_table_file_name(table_name))?;
+        serde_json::to_writer(external_file, &external_table)?;
+        self.plan_cache.clear();
+
+        Ok(external_table.schema)
+    }
+
+    ///
+    /// Removes the registration of an external table. The file itself is left untouched.
+    ///
+    /// # Errors
+    ///
+    /// On file operations, or when no such external table is registered.
+    pub fn unregister_external_table(&self, table_name: &str) -> Result<(), Error> {
+        self.check_writable()?;
+        self.check_not_attached_table(table_name)?;
+        Ok(std::fs::remove_file(
+            self.table_opener.external_table_file_name(table_name),
+        )?)
+    }
+
+    ///
+    /// Verifies every table of the directory: schema, data size, and each index's row pointers,
+    /// values and ordering.
+    ///
+    /// # Errors
+    ///
+    /// Errors when the directory cannot be listed.
+    pub fn check_all(&self) -> Result<ConsistencyReport, Error> {
+        ConsistencyChecker::new(&self.table_opener)
+            .with_progress(self.progress.as_ref())
+            .check_all()
+    }
+
+    ///
+    /// Collects the statistics of the table: its row count, a summary of every field and an
+    /// equi-depth histogram of every indexed field. They are stored next to the table, and the
+    /// planner uses them to choose between an index scan and a full scan. Stats are not updated by
+    /// later writes, see `refresh_stale_stats`.
+    ///
+    /// Only reads the table: dictionary encoding the fields the stats find few values in is left
+    /// to `compact_table`.
+    ///
+    /// # Errors
+    ///
+    /// On file operations, or when the table data is invalid.
+    ///
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    pub fn analyze_table(&self, table_name: &str) -> Result<TableStats, Error> {
+        self.check_writable()?;
+        self.check_not_attached_table(table_name)?;
+        let table_schema = self.table_opener.open_schema(table_name)?;
+        let table_bytes = std::fs::read(self.table_opener.table_data_file_name(table_name))?;
+        let row_byte_size = table_schema.row_byte_size();
+        if table_bytes.len() % row_byte_size != 0 {
+            return Err(PBaseError::InvalidTableSizeError.into());
+        }
+
+        let row_count = table_bytes.len() / row_byte_size;
+        let indexed_fields: BTreeSet<&String> = table_schema.indices.values().flatten().collect();
+        // Every field is a pass over the rows.
+        self.progress.start(
+            &format!("analyze {table_name}"),
+            Some(row_count * table_schema.fields.len()),
+        );
+        let mut table_stats = TableStats {
+            row_count,
+            ..Default::default()
+        };
+        for (field_i, (field_name, field_schema)) in table_schema.fields.iter().enumerate() {
+            // Stats are read back without the ordering of custom types, see `CustomValue`.
+            if matches!(field_schema, FieldSchema::Custom(_)) {
+                self.progress.advance((field_i + 1) * row_count);
+                continue;
+            }
+            let field_pos = table_schema.field_byte_pos(field_name);
+            let values: Vec<Value> = table_bytes
+                .chunks_exact(row_byte_size)
+                .map(|row_bytes| {
+                    table_schema.stored_value_from_bytes(field_name, &row_bytes[field_pos..])
+                })
+                .collect();
+            self.progress.advance((field_i + 1) * row_count);
+
+            table_stats
+                .columns
+                .insert(field_name.clone(), ColumnStats::build(&values));
+            if indexed_fields.contains(field_name) {
+                table_stats.histograms.insert(
+                    field_name.clone(),
+                    Histogram::build(values, HISTOGRAM_BUCKETS),
+                );
+            }
+        }
+
+        // Replaced rather than rewritten: exported snapshots share the file, see `snapshot`.
+        atomic_write(
+            &self.table_opener.table_stats_file_name(table_name),
+            &serde_json::to_vec(&table_stats)?,
+        )?;
+        self.modified_rows.lock().unwrap().remove(table_name);
+        self.progress.finish();
+
+        Ok(table_stats)
+    }
+
+    ///
+    /// Rewrites the table to store its I32 fields with few distinct values dictionary encoded, one
+    /// byte per row (see `TableSchema::dictionary_columns`). Fields are chosen by the table's
+    /// stats, which are collected first when it was never analyzed. Tables under
+    /// `DICTIONARY_ENCODE_MIN_ROWS` rows are left as they are. Returns the fields encoded.
+    ///
+    /// Fields that take new values by design, row versions and deletion times, are left alone (U8
+    /// ones are a byte already).
+    ///
+    /// Encoding a field changes the row positions of the table: positions read before, eg. of
+    /// `run_insert_query_returning`, no longer point to their rows. Cached lookups and plans of
+    /// the table are dropped.
+    ///
+    /// # Errors
+    ///
+    /// On file operations, or when the table data is invalid.
+    pub fn compact_table(&self, table_name: &str) -> Result<Vec<String>, Error> {
+        self.check_writable()?;
+        self.check_not_attached_table(table_name)?;
+        let table_stats = match self.table_opener.open_stats(table_name)? {
+            Some(table_stats) => table_stats,
+            None => self.analyze_table(table_name)?,
+        };
+        if u64::try_from(table_stats.row_count)? < DICTIONARY_ENCODE_MIN_ROWS {
+            return Ok(vec![]);
+        }
+
+        let _gate = self.hold_off_snapshots();
+        let _table_lock = self.table_opener.lock_table(table_name)?;
+        let mut table_schema = self.table_opener.open_schema(table_name)?;
+        let mut encoded = vec![];
+        let field_names: Vec<String> = table_schema.fields.keys().cloned().collect();
+        for field_name in field_names {
+            let low_cardinality = table_stats
+                .columns
+                .get(&field_name)
+                .is_some_and(|column| column.distinct_estimate <= DICTIONARY_ENCODE_MAX_DISTINCT);
+            if table_schema.fields[&field_name] != FieldSchema::I32
+                || table_schema.is_dictionary_column(&field_name)
+                || (table_schema.versioned && field_name == VERSION_FIELD)
+                || table_schema.soft_delete_column.as_ref() == Some(&field_name)
+                || !low_cardinality
+            {
+                continue;
+            }
+
+            table_schema = self.recode_column(&table_schema, &field_name, true)?;
+            if table_schema.is_dictionary_column(&field_name) {
+                self.wal.append(WalOp::EncodeDictionaryColumn {
+                    table: table_schema.name.clone(),
+                    field: field_name.clone(),
+                })?;
+                encoded.push(field_name);
+            }
+        }
+
+        Ok(encoded)
+    }
+
+    //
+    // Rewrites the table with the field dictionary encoded, or decoded: the dictionaries, the data,
+    // the row pointers of the indices, then the schema. Rows move as their size changes, and
+    // cached lookups are dropped. Not crash safe: an interruption before the schema is written
+    // leaves data the schema does not describe.
+    //
+    // Encoding returns the schema as it is when the field has more distinct values than a
+    // dictionary holds.
+    //
+    fn recode_column(
+        &self,
+        table_schema: &TableSchema,
+        field_name: &str,
+        encode: bool,
+    ) -> Result<TableSchema, Error> {
+        let table = &table_schema.name;
+        let table_bytes = std::fs::read(self.table_opener.table_data_file_name(table))?;
+        let old_row_byte_size = table_schema.row_byte_size();
+        let rows: Vec<FieldValues> = table_bytes
+            .chunks_exact(old_row_byte_size)
+            .map(|row_bytes| table_schema.parse_row_bytes(row_bytes))
+            .collect();
+
+        let mut new_schema = table_schema.clone();
+        if encode {
+            let mut dictionary = Dictionary::default();
+            if rows.iter().any(|row| {
+                dictionary
+                    .insert(&new_schema.dictionary_value(field_name, row))
+                    .is_none()
+            }) {
+                return Ok(new_schema);
+            }
+            new_schema.dictionary_columns.push(field_name.to_string());
+            new_schema
+                .dictionaries
+                .insert(field_name.to_string(), dictionary);
+        } else {
+            new_schema
+                .dictionary_columns
+                .retain(|column| column != field_name);
+            new_schema.dictionaries.remove(field_name);
+        }
+        new_schema.set_layout_hash();
+
+        let new_row_byte_size = new_schema.row_byte_size();
+        let new_table_bytes: Vec<u8> = rows
+            .iter()
+            .flat_map(|row| new_schema.data_row_to_bytes(row))
+            .collect();
+        self.write_dictionaries(&new_schema)?;
+        atomic_write(
+            &self.table_opener.table_data_file_name(table),
+            &new_table_bytes,
+        )?;
+        self.table_opener.forget_table(table);
+        self.table_opener
+            .commit_table_len(table, new_table_bytes.len());
+
+        self.rewrite_index_ptrs(&new_schema, |row_pos| {
+            Some(moved_row_pos(row_pos, old_row_byte_size, new_row_byte_size))
+        })?;
+        atomic_write(
+            &self.table_opener.table_schema_file_name(table),
+            &serde_json::to_vec(&new_schema)?,
+        )?;
+        self.row_cache.invalidate_table(table);
+        self.plan_cache.clear();
+        self.table_opener.bump_generation(table)?;
+
+        Ok(new_schema)
+    }
+
+    //
+    // Rewrites the row pointers of every index entry of the table (sorted and delta), dropping the
+    // entries without a new pointer. Keys stay in order as long as the pointers keep theirs.
+    // Returns the dropped entries and the bytes written.
+    //
+    fn rewrite_index_ptrs(
+        &self,
+        table_schema: &TableSchema,
+        new_row_pos: impl Fn(TablePtrType) -> Option<TablePtrType>,
+    ) -> Result<(usize, usize), Error> {
+        let mut dropped_entries = 0;
+        let mut bytes_written = 0;
+        for index_name in table_schema.indices.keys() {
+            let ptr_byte_pos = table_schema.index_row_ptr_field_byte_pos(index_name);
+            let index_row_size = table_schema.index_row_byte_size(index_name);
+            for index_file_name in [
+                self.table_opener
+                    .index_file_name(&table_schema.name, index_name),
+                self.table_opener
+                    .index_delta_file_name(&table_schema.name, index_name),
+            ] {
+                if !index_file_name.exists() {
+                    continue;
+                }
+                let index_bytes = std::fs::read(&index_file_name)?;
+                let mut new_index_bytes = Vec::with_capacity(index_bytes.len());
+                for index_row in index_bytes.chunks_exact(index_row_size) {
+                    let row_pos = table_schema.index_row_ptr_from_bytes(&index_row[ptr_byte_pos..]);
+                    let Some(row_pos) = new_row_pos(row_pos) else {
+                        dropped_entries += 1;
+                        continue;
+                    };
+                    new_index_bytes.extend_from_slice(&index_row[..ptr_byte_pos]);
+                    new_index_bytes.extend_from_slice(
+                        &row_pos.to_le_bytes()[..table_schema.storage.ptr_width],
+                    );
+                }
+                atomic_write(&index_file_name, &new_index_bytes)?;
+                bytes_written += new_index_bytes.len();
+            }
+        }
+
+        Ok((dropped_entries, bytes_written))
+    }
+
+    //
+    // Adds the values of the rows to the dictionaries of the table's encoded fields, written before
+    // the rows that refer to them. A field whose dictionary would overflow is decoded instead (see
+    // `recode_column`). Returns the schema to write the rows with.
+    //
+    fn extend_dictionaries(
+        &self,
+        mut table_schema: TableSchema,
+        rows: &[FieldValues],
+    ) -> Result<TableSchema, Error> {
+        let mut extended = false;
+        for field_name in table_schema.dictionary_columns.clone() {
+            let mut dictionary = table_schema.dictionaries[&field_name].clone();
+            let len = dictionary.len();
+            if rows.iter().any(|row| {
+                dictionary
+                    .insert(&table_schema.dictionary_value(&field_name, row))
+                    .is_none()
+            }) {
+                table_schema = self.recode_column(&table_schema, &field_name, false)?;
+            } else if dictionary.len() > len {
+                table_schema.dictionaries.insert(field_name, dictionary);
+                extended = true;
+            }
+        }
+        if extended {
+            self.write_dictionaries(&table_schema)?;
+        }
+
+        Ok(table_schema)
+    }
+
+    // Fails when the rows would grow the table past what the row pointers of its index entries
+    // address (see `StorageOptions::ptr_width`).
+    fn check_row_ptrs(&self, table_schema: &TableSchema, rows: usize) -> Result<(), Error> {
+        let limit = table_schema.storage.max_data_bytes();
+        let table_bytes =
+            std::fs::metadata(self.table_opener.table_data_file_name(&table_schema.name))
+                .map_or(0, |metadata| metadata.len());
+        let new_table_bytes = u64::try_from(rows * table_schema.row_byte_size())?;
+        if table_bytes.saturating_add(new_table_bytes) > limit {
+            return Err(PBaseError::RowPointerOverflow {
+                table: table_schema.name.clone(),
+                limit,
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    //
+    // Fills the fields the row leaves out or sets to NULL that have a default expression (see
+    // `TableSchema::defaults`), with values of the field's type. `next_ids` carries the next value
+    // of `next_id()` fields from row to row of a bulk load, the table is scanned for it once.
+    //
+    fn fill_defaults(
+        &self,
+        table_schema: &TableSchema,
+        values: &mut FieldValues,
+        next_ids: &mut HashMap<String, i64>,
+    ) -> Result<(), Error> {
+        for (field_name, default_expr) in &table_schema.defaults {
+            match values.get(field_name) {
+                None | Some(Value::NULL) => {}
+                Some(value) => {
+                    // Rows setting the id themselves move the next one past theirs.
+                    if let Some(next_id) = next_ids.get_mut(field_name) {
+                        *next_id = (*next_id).max(int_value(value).map_or(0, |v| v + 1));
+                    }
+                    continue;
+                }
+            }
+
+            let value = match default_expr {
+                DefaultExpr::Value(value) => value.clone(),
+                DefaultExpr::Now => Value::I32(unix_time()?),
+                DefaultExpr::NextId => {
+                    let next_id = match next_ids.get(field_name) {
+                        Some(next_id) => *next_id,
+                        None => self
+                            .max_field_value(table_schema, field_name)?
+                            .map_or(0, |max| max + 1),
+                    };
+                    next_ids.insert(field_name.clone(), next_id + 1);
+                    Value::I32(i32::try_from(next_id).map_err(|_| {
+                        PBaseError::NumericOverflow(format!("next id of {field_name}"))
+                    })?)
+                }
+                DefaultExpr::Scalar { function, arg } => (self.functions.get(function)?.body)(arg),
+            };
+            let value = table_schema.fields[field_name]
+                .coerce(&value)
+                .ok_or_else(|| PBaseError::FieldTypeMismatch {
+                    field: field_name.clone(),
+                    value: value.to_string(),
+                })?;
+            values.insert(field_name.clone(), value);
+        }
+
+        Ok(())
+    }
+
+    // The highest value of the field in the table, read from every row. None when the table is
+    // empty.
+    fn max_field_value(
+        &self,
+        table_schema: &TableSchema,
+        field_name: &str,
+    ) -> Result<Option<i64>, Error> {
+        let table_bytes =
+            std::fs::read(self.table_opener.table_data_file_name(&table_schema.name))?;
+        let field_byte_pos = table_schema.field_byte_pos(field_name);
+
+        Ok(table_bytes
+            .chunks_exact(table_schema.row_byte_size())
+            .filter_map(|row_bytes| {
+                int_value(
+                    &table_schema.stored_value_from_bytes(field_name, &row_bytes[field_byte_pos..]),
+                )
+            })
+            .max())
+    }
+
+    // Writes the table's dictionaries, or removes them when it has no encoded fields.
+    fn write_dictionaries(&self, table_schema: &TableSchema) -> Result<(), Error> {
+        let dictionary_file_name = self
+            .table_opener
+            .table_dictionary_file_name(&table_schema.name);
+        if !table_schema.dictionary_columns.is_empty() {
+            atomic_write(
+                &dictionary_file_name,
+                &serde_json::to_vec(&table_schema.dictionaries)?,
+            )?;
+        } else if dictionary_file_name.exists() {
+            std::fs::remove_file(dictionary_file_name)?;
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// The table accesses of the selects run through this handle, most recent last.
+    ///
+    #[must_use]
+    pub const fn query_log(&self) -> &QueryLog {
+        &self.query_log
+    }
+
+    ///
+    /// Suggests composite indices for the filters of the logged selects, with the rows each would
+    /// save (see `index_advisor::recommend_indexes`). Run `analyze_table` first for estimates based
+    /// on the actual value distribution.
+    ///
+    /// # Errors
+    ///
+    /// On file operations.
+    pub fn recommend_indexes(&self) -> Result<Vec<IndexRecommendation>, Error> {
+        crate::index_advisor::recommend_indexes(&self.query_log.entries(), &self.table_opener)
+    }
+
+    ///
+    /// Creates the recommended indices estimated to save at least `min_rows_saved` rows, and
+    /// returns their recommendations.
+    ///
+    /// # Errors
+    ///
+    /// On file operations.
+    pub fn create_recommended_indexes(
+        &self,
+        min_rows_saved: usize,
+    ) -> Result<Vec<IndexRecommendation>, Error> {
+        let recommendations: Vec<IndexRecommendation> = self
+            .recommend_indexes()?
+            .into_iter()
+            .filter(|recommendation| recommendation.estimated_rows_saved >= min_rows_saved)
+            .collect();
+        for recommendation in &recommendations {
+            self.run_create_index_query(
+                &recommendation.table,
+                &recommendation.index_name(),
+                &recommendation.fields,
+            )?;
+        }
+
+        Ok(recommendations)
+    }
+
+    ///
+    /// Adds an index on the fields to an existing table, built from the rows already in it.
+    ///
+    /// # Errors
+    ///
+    /// Errors on file operations, unknown fields, or when the table already has an index of the
+    /// name.
+    pub fn run_create_index_query(
+        &self,
+        table: &str,
+        index_name: &str,
+        fields: &[String],
+    ) -> Result<(), Error> {
+        self.check_writable()?;
+        self.check_not_attached_table(table)?;
+        let _gate = self.hold_off_snapshots();
+        self.create_index(table, index_name, fields)?;
+        self.wal.append(WalOp::CreateIndex {
+            table: table.to_string(),
+            index_name: index_name.to_string(),
+            fields: fields.to_vec(),
+        })?;
+
+        Ok(())
+    }
+
+    fn create_index(&self, table: &str, index_name: &str, fields: &[String]) -> Result<(), Error> {
+        let mut table_schema = self.table_opener.open_schema(table)?;
+        if table_schema.indices.contains_key(index_name) {
+            return Err(PBaseError::IndexAlreadyExists(index_name.to_string()).into());
+        }
+        validate_file_stem(&format!("{table}__{index_name}"))?;
+        if let Some(unknown_field) = fields
+            .iter()
+            .find(|field_name| !table_schema.fields.contains_key(*field_name))
+        {
+            return Err(PBaseError::UnknownField(unknown_field.clone()).into());
+        }
+        table_schema
+            .indices
+            .insert(index_name.to_string(), fields.to_vec());
+
+        let table_bytes = std::fs::read(self.table_opener.table_data_file_name(table))?;
+        let row_byte_size = table_schema.row_byte_size();
+        if table_bytes.len() % row_byte_size != 0 {
+            return Err(PBaseError::InvalidTableSizeError.into());
+        }
+
+        self.progress.start(
+            &format!("index build {table}.{index_name}"),
+            Some(table_bytes.len() / row_byte_size),
+        );
+        // Stable: entries of the same key stay in row order, as if inserted one by one.
+        let mut index_rows: Vec<(Vec<Value>, Vec<u8>)> = table_bytes
+            .chunks_exact(row_byte_size)
+            .zip((0..).step_by(row_byte_size))
+            .enumerate()
+            .map(|(row_i, (row_bytes, row_pos))| {
+                if row_i % PROGRESS_STEP_ROWS == 0 {
+                    self.progress.advance(row_i);
+                }
+                let index_row = table_schema.index_row_to_bytes(
+                    index_name,
+                    &table_schema.parse_row_bytes(row_bytes),
+                    row_pos,
+                );
+                (
+                    table_schema.parse_index_row_bytes(index_name, &index_row).0,
+                    index_row,
+                )
+            })
+            .collect();
+        index_rows.sort_by(|(lhs_key, _), (rhs_key, _)| lhs_key.cmp(rhs_key));
+
+        atomic_write(
+            &self.table_opener.index_file_name(table, index_name),
+            &index_rows
+                .into_iter()
+                .flat_map(|(_, index_row)| index_row)
+                .collect::<Vec<u8>>(),
+        )?;
+
+        // The schema goes last: readers only use the index once it is complete.
+        table_schema.set_layout_hash();
+        atomic_write(
+            &self.table_opener.table_schema_file_name(table),
+            &serde_json::to_vec(&table_schema)?,
+        )?;
+        self.plan_cache.clear();
+        self.progress.finish();
+
+        Ok(())
+    }
+
+    ///
+    /// Writes the table's schema and rows into a single compressed archive file (see
+    /// `TableArchiveWriter`), readable by `import_table_archive` on any machine.
+    ///
+    /// # Errors
+    ///
+    /// On file operations, or when the table data is invalid.
+    pub fn export_table_archive<P: AsRef<Path>>(
+        &self,
+        table_name: &str,
+        path: P,
+    ) -> Result<TableArchiveHeader, Error> {
+        let table_schema = self.table_opener.open_schema(table_name)?;
+        let table_bytes = std::fs::read(self.table_opener.table_data_file_name(table_name))?;
+        let row_byte_size = table_schema.row_byte_size();
+        if table_bytes.len() % row_byte_size != 0 {
+            return Err(PBaseError::InvalidTableSizeError.into());
+        }
+
+        // Archives hold values: the importing table starts without dictionaries.
+        let header = TableArchiveHeader::new(
+            table_schema.without_dictionaries(),
+            table_bytes.len() / row_byte_size,
+        );
+        let mut writer = TableArchiveWriter::new(File::create(path)?, &header)?;
+        for row_bytes in table_bytes.chunks_exact(row_byte_size) {
+            let mut row = table_schema.parse_row_bytes(row_bytes);
+            let values: Vec<Value> = header
+                .schema
+                .fields
+                .keys()
+                .map(|field_name| row.remove(field_name).unwrap_or(Value::NULL))
+                .collect();
+            writer.write_row(&values)?;
+        }
+        writer.finish()?.sync_all()?;
+
+        Ok(header)
+    }
+
+    ///
+    /// Creates the table of an archive written by `export_table_archive` and inserts its rows
+    /// (building its indices and logging to the WAL like any insert).
+    ///
+    /// # Errors
+    ///
+    /// On file operations, for invalid archives, when the table already exists, or when its rows
+    /// exceed the quota.
+    pub fn import_table_archive<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<TableArchiveHeader, Error> {
+        self.check_writable()?;
+        let mut reader = TableArchiveReader::new(File::open(path)?)?;
+        let header = reader.header().clone();
+        if self.is_table_exist(&header.schema.name) {
+            return Err(PBaseError::TableAlreadyExists(header.schema.name).into());
+        }
+        // Checked up front, not to leave a partial table behind.
+        self.quota
+            .check_rows(&self.table_opener, &header.schema, header.row_count)?;
+        self.check_row_ptrs(&header.schema, header.row_count)?;
+
+        self.run_create_table_query(&CreateTableQuery {
+            schema: header.schema.clone(),
+        })?;
+        self.progress.start(
+            &format!("import {}", header.schema.name),
+            Some(header.row_count),
+        );
+        let mut imported_rows = 0;
+        while let Some(values) = reader.next_row()? {
+            self.run_insert_query(&InsertQuery {
+                table: header.schema.name.clone(),
+                values: header.schema.fields.keys().cloned().zip(values).collect(),
+            })?;
+            imported_rows += 1;
+            if imported_rows % PROGRESS_STEP_ROWS == 0 {
+                self.progress.advance(imported_rows);
+            }
+        }
+        self.progress.finish();
+
+        Ok(header)
+    }
+
+    /// # Errors
+    ///
+    /// Errors on file operations, for `SELECT ... INTO` queries (see `run_select_into_query`), or
+    /// when the rows exceed the row cap and it does not truncate (see `with_row_cap`).
+    pub fn run_select_query(&self, query: SelectQuery) -> Result<ResultSet, Error> {
+        Ok(self.select(query, false)?.0)
+    }
+
+    ///
+    /// Runs the select like `run_select_query`, measuring it: rows, time, indices used and rows
+    /// read (see `QueryStats`). Each operator is timed, which costs a little on every row.
+    ///
+    /// # Errors
+    ///
+    /// Errors like `run_select_query`.
+    ///
+    /// # Panics
+    ///
+    /// Never: measured selects always have stats.
+    pub fn run_select_query_with_stats(
+        &self,
+        query: SelectQuery,
+    ) -> Result<(ResultSet, QueryStats), Error> {
+        let (result, stats) = self.select(query, true)?;
+        Ok((result, stats.expect("Measured select has stats")))
+    }
+
+    // Runs the select, with its stats when measured.
+    fn select(
+        &self,
+        query: SelectQuery,
+        measure: bool,
+    ) -> Result<(ResultSet, Option<QueryStats>), Error> {
+        if let Some(into) = &query.into {
+            return Err(PBaseError::InvalidArgument(format!(
+                "SELECT INTO {into} has no result, run it with run_select_into_query"
+            ))
+            .into());
+        }
+
+        let start = Instant::now();
+        let call = |executor: SelectQueryExecutor| {
+            if measure {
+                executor
+                    .call_with_stats()
+                    .map(|(result, stats)| (result, Some(stats)))
+            } else {
+                executor.call().map(|result| (result, None))
+            }
+        };
+
+        self.query_log.record(&query);
+        // Reads of several tables see them as of the same moment.
+        let tables = self.regular_tables(query_tables(&query));
+        if tables.len() > 1 {
+            let snapshot = self.capture_snapshot(&tables)?;
+            return call(
+                SelectQueryExecutor::new(&self.table_opener, query)
+                    .with_functions(&self.functions)
+                    .with_plan_cache(&self.plan_cache)
+                    .with_row_cap(self.row_cap)
+                    .with_snapshot(&snapshot),
+            );
+        }
+
+        let cache_key = self.point_lookup_key(&query)?;
+        if let Some(result) = cache_key
+            .as_ref()
+            .and_then(|(key, generation)| self.row_cache.get(key, *generation))
+        {
+            let stats = measure.then(|| QueryStats {
+                rows: result.len(),
+                elapsed: start.elapsed(),
+                row_cache_hit: true,
+                ..Default::default()
+            });
+            return Ok((result, stats));
+        }
+
+        let (result, stats) = call(
+            SelectQueryExecutor::new(&self.table_opener, query)
+                .with_functions(&self.functions)
+                .with_plan_cache(&self.plan_cache)
+                .with_row_cap(self.row_cap),
+        )?;
+        if let Some((key, generation)) = cache_key.filter(|_| !result.truncated) {
+            self.row_cache.put(key, generation, &result);
+        }
+
+        Ok((result, stats))
+    }
+
+    ///
+    /// Captures the regular tables (system and external tables are skipped), so that queries run
+    /// with the snapshot (see `run_select_query_in`) see all of them as of the same moment, however
+    /// the tables are written through this handle meanwhile. Writes through this handle wait for
+    /// the capture to finish.
+    ///
+    /// The snapshot maps the tables' files rather than copying them (see `ReadSnapshot`), and
+    /// updates of a snapshotted table write a copy of its data instead of changing rows in place:
+    /// drop snapshots once read. Updates in place through other handles are not held off.
+    /// `run_select_query` snapshots the tables of joins and subqueries by itself.
+    ///
+    /// # Errors
+    ///
+    /// Errors on file operations, or when a table does not exist.
+    pub fn read_snapshot(&self, tables: &[&str]) -> Result<ReadSnapshot, Error> {
+        let tables = self.regular_tables(tables.iter().map(ToString::to_string).collect());
+        self.capture_snapshot(&tables)
+    }
+
+    ///
+    /// Runs the select reading its tables from the snapshot (tables missing from it are read
+    /// as they are now). The row cache is bypassed, it holds current rows.
+    ///
+    /// # Errors
+    ///
+    /// Errors on file operations, or for `SELECT ... INTO` queries.
+    pub fn run_select_query_in(
+        &self,
+        snapshot: &ReadSnapshot,
+        query: SelectQuery,
+    ) -> Result<ResultSet, Error> {
+        if let Some(into) = &query.into {
+            return Err(PBaseError::InvalidArgument(format!(
+                "SELECT INTO {into} has no result, run it with run_select_into_query"
+            ))
+            .into());
+        }
+
+        self.query_log.record(&query);
+        SelectQueryExecutor::new(&self.table_opener, query)
+            .with_functions(&self.functions)
+            .with_plan_cache(&self.plan_cache)
+            .with_row_cap(self.row_cap)
+            .with_snapshot(snapshot)
+            .call()
+    }
+
+    ///
+    /// Exports the regular tables to `dest`, a new directory, as of the moment of the call, for
+    /// analytics jobs to read while writes here continue (eg. opened with
+    /// `PBase::new(dest).with_read_only()`). Writes through this handle wait for the export.
+    ///
+    /// Files that writes replace rather than modify (schemas, sorted indices, stats, dictionaries
+    /// and generations) are hard linked: they share their space until replaced here. Table data
+    /// and index deltas are modified in place, so they are copied, which filesystems cloning
+    /// copies (btrfs, XFS) do copy-on-write. Files that cannot be linked, eg. to another
+    /// filesystem, are copied too.
+    ///
+    /// # Errors
+    ///
+    /// Errors on file operations, or when `dest` exists.
+    pub fn snapshot<P: AsRef<Path>>(&self, dest: P) -> Result<(), Error> {
+        std::fs::create_dir(dest.as_ref())?;
+        let dest_opener = TableOpener::new(dest.as_ref().to_path_buf());
+
+        let _gate = self
+            .snapshot_gate
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        for table in self.regular_tables(self.table_opener.table_names()?) {
+            let table_schema = self.table_opener.open_schema(&table)?;
+            let file_names = |table_opener: &TableOpener| {
+                let mut linked = vec![
+                    table_opener.table_schema_file_name(&table),
+                    table_opener.table_stats_file_name(&table),
+                    table_opener.table_dictionary_file_name(&table),
+                    table_opener.table_generation_file_name(&table),
+                ];
+                let mut copied = vec![table_opener.table_data_file_name(&table)];
+                for index_name in table_schema.indices.keys() {
+                    linked.push(table_opener.index_file_name(&table, index_name));
+                    copied.push(table_opener.index_delta_file_name(&table, index_name));
+                }
+                (linked, copied)
+            };
+
+            let (linked, copied) = file_names(&self.table_opener);
+            let (dest_linked, dest_copied) = file_names(&dest_opener);
+            for (from_file, to_file) in linked.iter().zip(&dest_linked) {
+                if from_file.exists() && std::fs::hard_link(from_file, to_file).is_err() {
+                    std::fs::copy(from_file, to_file)?;
+                }
+            }
+            for (from_file, to_file) in copied.iter().zip(&dest_copied) {
+                if from_file.exists() {
+                    std::fs::copy(from_file, to_file)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn capture_snapshot(&self, tables: &[String]) -> Result<ReadSnapshot, Error> {
+        let _gate = self
+            .snapshot_gate
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        ReadSnapshot::capture(&self.table_opener, &self.snapshot_pins, tables)
+    }
+
+    // Held by writes so that no snapshot captures the tables halfway through them.
+    fn hold_off_snapshots(&self) -> RwLockReadGuard<'_, ()> {
+        self.snapshot_gate
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // The tables stored in the directory, without duplicates.
+    fn regular_tables(&self, mut tables: Vec<String>) -> Vec<String> {
+        tables.sort();
+        tables.dedup();
+        tables.retain(|table| {
+            !is_system_table(table)
+                && self
+                    .table_opener
+                    .open_external_table(table)
+                    .is_ok_and(|external_table| external_table.is_none())
+        });
+
+        tables
+    }
+
+    //
+    // The row cache key of point lookups on unique indices of regular tables, with the generation
+    // of the table.
+    //
+    fn point_lookup_key(&self, query: &SelectQuery) -> Result<Option<(RowCacheKey, u64)>, Error> {
+        if query.filters.is_empty() || !self.is_table_exist(&query.from) {
+            return Ok(None);
+        }
+
+        let table_schema = self.table_opener.open_schema(&query.from)?;
+        let Some(key) = RowCacheKey::of_point_lookup(query, &table_schema) else {
+            return Ok(None);
+        };
+        // Read before the lookup: a write landing during it moves the generation past the result.
+        Ok(Some((
+            key,
+            self.table_opener.table_generation(&query.from)?,
+        )))
+    }
+
+    ///
+    /// The cache of point lookup results on unique indices.
+    ///
+    #[must_use]
+    pub const fn row_cache(&self) -> &RowCache {
+        &self.row_cache
+    }
+
+    ///
+    /// The cache of parsed statements and logical plans, see `parse_statement`. Its `stats` tell
+    /// how often it was hit.
+    ///
+    #[must_use]
+    pub const fn plan_cache(&self) -> &PlanCache {
+        &self.plan_cache
+    }
+
+    ///
+    /// Parses a tokenized statement, reusing the parse of an earlier statement of the same shape
+    /// (same statement but for its literals, see `PlanCache`). Selects run through this handle
+    /// reuse their logical plans the same way.
+    ///
+    /// # Errors
+    ///
+    /// When the statement cannot be parsed.
+    pub fn parse_statement(&self, tokens: &[Token]) -> Result<Query, Error> {
+        self.plan_cache.statement(tokens)
+    }
+
+    ///
+    /// Runs a `SELECT ... INTO table`, the INSERT ... SELECT of pbase: result rows are inserted
+    /// into the table one by one as they are produced, without collecting the result. Each column
+    /// goes into the field of its name (the alias of computed columns). A missing table is created
+    /// with the result's columns. Returns the number of rows inserted.
+    ///
+    /// # Errors
+    ///
+    /// Errors on file operations, when the query has no `into` table, when two columns have the
+    /// same name, or when the rows do not fit an existing table.
+    pub fn run_select_into_query(&self, mut query: SelectQuery) -> Result<usize, Error> {
+        self.check_writable()?;
+        let Some(into) = query.into.take() else {
+            return Err(PBaseError::InvalidArgument("SELECT has no INTO table".into()).into());
+        };
+        self.query_log.record(&query);
+        let executor =
+            SelectQueryExecutor::new(&self.table_opener, query).with_functions(&self.functions);
+
+        // Result column name and the field it is inserted into.
+        let mut target_fields: IndexMap<String, (String, FieldSchema)> = IndexMap::new();
+        for column in executor.output_columns()? {
+            let field_name = column
+                .name
+                .rsplit_once('.')
+                .map_or(column.name.as_str(), |(_, field_name)| field_name)
+                .to_string();
+            if target_fields
+                .values()
+                .any(|(target_field, _)| *target_field == field_name)
+            {
+                return Err(PBaseError::DuplicateColumn(field_name).into());
+            }
+            target_fields.insert(column.name, (field_name, column.field_schema));
+        }
+
+        if !self.is_table_exist(&into) {
+            self.run_create_table_query(&CreateTableQuery {
+                schema: TableSchema {
+                    name: into.clone(),
+                    fields: target_fields.values().cloned().collect(),
+                    ..Default::default()
+                },
+            })?;
+        }
+
+        // The result size is unknown until it is produced.
+        self.progress.start(&format!("select into {into}"), None);
+        let mut inserted_rows = 0;
+        let inserted_rows_total = executor.for_each_row(|row| {
+            let values = target_fields
+                .iter()
+                .filter_map(|(column_name, (field_name, _))| {
+                    Some((field_name.clone(), row.get(column_name.as_str())?.clone()))
+                })
+                .collect();
+            self.run_insert_query(&InsertQuery {
+                table: into.clone(),
+                values,
+            })?;
+            inserted_rows += 1;
+            if inserted_rows % PROGRESS_STEP_ROWS == 0 {
+                self.progress.advance(inserted_rows);
+            }
+            Ok(())
+        })?;
+        self.progress.finish();
+
+        Ok(inserted_rows_total)
+    }
+
+    ///
+    /// Streams the rows of a single table select as borrowed views (see
+    /// `SelectQueryExecutor::for_each_row_view`). Returns the number of rows visited.
+    ///
+    /// # Errors
+    ///
+    /// Errors on file operations, or for joins and scalar subqueries.
+    pub fn for_each_row_view<F>(&self, query: SelectQuery, f: F) -> Result<usize, Error>
+    where
+        F: FnMut(&RowView<'_>),
+    {
+        self.query_log.record(&query);
+        SelectQueryExecutor::new(&self.table_opener, query)
+            .with_functions(&self.functions)
+            .with_plan_cache(&self.plan_cache)
+            .for_each_row_view(f)
+    }
+
+    ///
+    /// Nodes reachable from the start values in at most `depth` hops along the edges of
+    /// `edge_table` (a table with `src` and `dst` columns of the same type), each with the fewest
+    /// hops it takes to reach (0 for the start values).
+    ///
+    /// Every hop joins the nodes reached in the previous hop with the edge table in a single scan.
+    /// Nodes already reached are not expanded again, so cycles do not grow the result.
+    ///
+    /// # Errors
+    ///
+    /// Errors on file operations, when the table has no `src` and `dst` columns of the same type,
+    /// or when a start value does not fit the `src` column.
+    pub fn traverse(
+        &self,
+        edge_table: &str,
+        start_values: &[Value],
+        depth: usize,
+    ) -> Result<BTreeMap<Value, usize>, Error> {
+        let table_schema = self.table_schema(edge_table)?;
+        let node_schema = table_schema
+            .fields
+            .get(EDGE_SRC_FIELD)
+            .ok_or_else(|| PBaseError::UnknownField(EDGE_SRC_FIELD.to_string()))?;
+        if table_schema.fields.get(EDGE_DST_FIELD) != Some(node_schema) {
+            return Err(PBaseError::UnknownField(EDGE_DST_FIELD.to_string()).into());
+        }
+
+        let mut reached: BTreeMap<Value, usize> = BTreeMap::new();
+        let mut frontier: HashSet<Value> = HashSet::new();
+        for start_value in start_values {
+            let node =
+                node_schema
+                    .coerce(start_value)
+                    .ok_or_else(|| PBaseError::FieldTypeMismatch {
+                        field: EDGE_SRC_FIELD.to_string(),
+                        value: start_value.to_string(),
+                    })?;
+            reached.insert(node.clone(), 0);
+            frontier.insert(node);
+        }
+
+        for hop in 1..=depth {
+            if frontier.is_empty() {
+                break;
+            }
+
+            let mut next_frontier = HashSet::new();
+            self.for_each_row_view(
+                SelectQuery {
+                    from: edge_table.to_string(),
+                    ..Default::default()
+                },
+                |row_view| {
+                    let (Some(src), Some(dst)) =
+                        (row_view.get(EDGE_SRC_FIELD), row_view.get(EDGE_DST_FIELD))
+                    else {
+                        return;
+                    };
+                    if frontier.contains(&src) && !reached.contains_key(&dst) {
+                        reached.insert(dst.clone(), hop);
+                        next_frontier.insert(dst);
+                    }
+                },
+            )?;
+            frontier = next_frontier;
+        }
+
+        Ok(reached)
+    }
+
+    /// # Errors
+    ///
+    /// Errors on file operations.
+    pub fn explain_select_query(&self, query: SelectQuery) -> Result<QueryPlan, Error> {
+        SelectQueryExecutor::new(&self.table_opener, query)
+            .with_functions(&self.functions)
+            .with_plan_cache(&self.plan_cache)
+            .explain()
+    }
+
+    /// # Errors
+    ///
+    /// Errors on file operations.
+    pub fn explain_analyze_select_query(&self, query: SelectQuery) -> Result<QueryPlan, Error> {
+        SelectQueryExecutor::new(&self.table_opener, query)
+            .with_functions(&self.functions)
+            .with_plan_cache(&self.plan_cache)
+            .explain_analyze()
+    }
+
+    /// # Errors
+    ///
+    /// Errors on file operations or when the selects' columns are incompatible.
+    pub fn run_union_query(&self, query: UnionQuery) -> Result<ResultSet, Error> {
+        let tables = self.regular_tables(query.selects.iter().flat_map(query_tables).collect());
+        let snapshot = if tables.len() > 1 {
+            Some(self.capture_snapshot(&tables)?)
+        } else {
+            None
+        };
+        let mut executor =
+            UnionQueryExecutor::new(&self.table_opener, query).with_functions(&self.functions);
+        if let Some(snapshot) = &snapshot {
+            executor = executor.with_snapshot(snapshot);
+        }
+
+        executor.call()
+    }
+
+    ///
+    /// Inserts a row, with its values checked or converted according to the insert mode (see
+    /// `InsertMode`) after the fields it leaves out get their default expression (see
+    /// `TableSchema::defaults`). The WAL records the row as stored.
+    ///
+    /// # Errors
+    ///
+    /// Errors on file operations, on fields and values the insert mode does not accept, when the
+    /// row exceeds the quota (see `with_quota`), or for the audit table (only the engine writes
+    /// it).
+    pub fn run_insert_query(&self, query: &InsertQuery) -> Result<usize, Error> {
+        self.insert_row(query)?;
+
+        Ok(1)
+    }
+
+    ///
+    /// Inserts a row like `run_insert_query`, and returns it as stored: with defaults filled and
+    /// values converted by the insert mode, and the version of versioned tables. Only the
+    /// `returning` fields are returned (all of them when empty), keyed like select rows.
+    ///
+    /// # Errors
+    ///
+    /// As `run_insert_query`, or with `PBaseError::UnknownField` for a returned field the table
+    /// does not have (before inserting).
+    pub fn run_insert_query_returning(
+        &self,
+        query: &InsertQuery,
+        returning: &[String],
+    ) -> Result<InsertedRow, Error> {
+        let table_schema = self.table_opener.open_schema(&query.table)?;
+        if let Some(unknown_field) = returning
+            .iter()
+            .find(|field_name| !table_schema.fields.contains_key(*field_name))
+        {
+            return Err(PBaseError::UnknownField(unknown_field.clone()).into());
+        }
+
+        let (row_pos, mut values) = self.insert_row(query)?;
+        // Fields in table order, as selects return them.
+        let (columns, row): (Vec<ColumnInfo>, Row) = table_schema
+            .fields
+            .iter()
+            .filter(|(field_name, _)| returning.is_empty() || returning.contains(field_name))
+            .map(|(field_name, field_schema)| {
+                let name = format!("{}.{field_name}", table_schema.name);
+                let value = values.remove(field_name).unwrap_or(Value::NULL);
+                let column = ColumnInfo {
+                    name: name.clone(),
+                    source: Some(table_schema.name.clone()),
+                    field_schema: field_schema.clone(),
+                };
+                (column, (name.into(), value))
+            })
+            .unzip();
+
+        Ok(InsertedRow {
+            row_pos,
+            result: ResultSet {
+                columns,
+                rows: vec![row],
+                truncated: false,
+            },
+        })
+    }
+
+    // Inserts the conformed row, returning its position and values.
+    fn insert_row(&self, query: &InsertQuery) -> Result<(TablePtrType, FieldValues), Error> {
+        self.check_writable()?;
+        let _gate = self.hold_off_snapshots();
+        check_not_audit_table(&query.table)?;
+        self.check_not_attached_table(&query.table)?;
+        let _table_lock = self.table_opener.lock_table(&query.table)?;
+        self.table_opener.check_generation(&query.table)?;
+        let table_schema = self.table_opener.open_schema(&query.table)?;
+        self.quota
+            .check_rows(&self.table_opener, &table_schema, 1)?;
+        self.check_row_ptrs(&table_schema, 1)?;
+        let mut values = query.values.clone();
+        self.fill_defaults(&table_schema, &mut values, &mut HashMap::new())?;
+        let query = InsertQuery {
+            table: query.table.clone(),
+            values: table_schema.conform_row(&values, self.insert_mode)?,
+        };
+
+        let row_pos = self.insert(&query)?;
+        let values = query.values.clone();
+        let lsn = self.wal.append(WalOp::Insert(query))?;
+        self.audit(&table_schema, lsn, AuditOp::Insert, row_pos, None, &values)?;
+
+        Ok((row_pos, values))
+    }
+
+    ///
+    /// Bulk loads a copy stream (see `CopyWriter`) into the table: the rows are appended in one
+    /// write, and each index gets all their entries in one merge, instead of converting and
+    /// indexing row by row. Returns the number of rows loaded.
+    ///
+    /// The stream's header must describe the table's row layout, fields with a default expression
+    /// may be left out (see `TableSchema::defaults`). Row bytes are taken as encoded by the
+    /// producer: no insert mode or tenant checks apply. Unique indices are checked.
+    ///
+    /// # Errors
+    ///
+    /// Errors on file operations, when the stream is invalid or does not match the table, when
+    /// the rows exceed the quota, or on a duplicate key of a unique index (nothing is loaded then).
+    pub fn copy_rows<R: Read>(&self, table: &str, reader: R) -> Result<usize, Error> {
+        self.check_writable()?;
+        check_not_audit_table(table)?;
+        self.check_not_attached_table(table)?;
+        let (header, rows) = read_copy_stream(BufReader::new(reader))?;
+
+        let _gate = self.hold_off_snapshots();
+        let _table_lock = self.table_opener.lock_table(table)?;
+        self.table_opener.check_generation(table)?;
+        let table_schema = self.table_opener.open_schema(table)?;
+        header.check_table(&table_schema)?;
+        self.quota
+            .check_rows(&self.table_opener, &table_schema, header.row_count)?;
+        self.check_row_ptrs(&table_schema, header.row_count)?;
+        let (parsed_rows, rows) = if header.fields.len() < table_schema.fields.len() {
+            self.fill_copied_rows(&table_schema, &header, &rows)?
+        } else {
+            (parse_copied_rows(&table_schema, &rows), rows)
+        };
+        let audited = table_schema.audited;
+        let (table_schema, first_row_pos) = self.append_rows(table_schema, &parsed_rows)?;
+        let lsn = self.wal.append(WalOp::CopyRows {
+            table: table.to_string(),
+            rows,
+        })?;
+        if audited {
+            let row_positions = (first_row_pos..).step_by(table_schema.row_byte_size());
+            for (row_pos, values) in row_positions.zip(&parsed_rows) {
+                self.audit(&table_schema, lsn, AuditOp::Insert, row_pos, None, values)?;
+            }
+        }
+
+        Ok(header.row_count)
+    }
+
+    //
+    // Parses the rows of a copy stream leaving out defaulted fields, and fills them. Returns the
+    // rows, and their bytes with every field (as logged to the WAL).
+    //
+    fn fill_copied_rows(
+        &self,
+        table_schema: &TableSchema,
+        header: &CopyHeader,
+        rows: &[u8],
+    ) -> Result<(Vec<FieldValues>, Vec<u8>), Error> {
+        let stream_schema = TableSchema {
+            fields: header.fields.clone(),
+            ..Default::default()
+        };
+        let mut next_ids = HashMap::new();
+        let parsed_rows = rows
+            .chunks_exact(header.row_byte_size())
+            .map(|row_bytes| {
+                let mut values = stream_schema.parse_row_bytes(row_bytes);
+                self.fill_defaults(table_schema, &mut values, &mut next_ids)?;
+                Ok(values)
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let plain_schema = table_schema.without_dictionaries();
+        let rows = parsed_rows
+            .iter()
+            .flat_map(|row| plain_schema.data_row_to_bytes(row))
+            .collect();
+
+        Ok((parsed_rows, rows))
+    }
+
+    //
+    // Appends rows to the table and merges their entries into its indices. Returns the schema the
+    // rows were stored with and the position of the first row.
+    //
+    fn append_rows(
+        &self,
+        table_schema: TableSchema,
+        parsed_rows: &[FieldValues],
+    ) -> Result<(TableSchema, TablePtrType), Error> {
+        for index_name in &table_schema.unique_indices {
+            let mut keys = HashSet::new();
+            for row in parsed_rows {
+                self.check_unique_keys(&table_schema, row, std::slice::from_ref(index_name))?;
+                let key = unique_key(&table_schema, index_name, row).values;
+                if keys.contains(&key) {
+                    return Err(PBaseError::DuplicateKey {
+                        index: index_name.clone(),
+                        key: key
+                            .iter()
+                            .map(ToString::to_string)
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                    }
+                    .into());
+                }
+                keys.insert(key);
+            }
+        }
+
+        let table_schema = self.extend_dictionaries(table_schema, parsed_rows)?;
+        let row_byte_size = table_schema.row_byte_size();
+        let rows: Vec<u8> = parsed_rows
+            .iter()
+            .flat_map(|row| table_schema.data_row_to_bytes(row))
+            .collect();
+        let mut table_data_file = self
+            .table_opener
+            .table_file_for_insert(&table_schema.name)?;
+        let first_row_pos = table_data_file.metadata()?.len();
+        table_data_file.write_all(&rows)?;
+        self.table_opener.commit_table_len(
+            &table_schema.name,
+            usize::try_from(first_row_pos)? + rows.len(),
+        );
+
+        for index_name in table_schema.indices.keys() {
+            let index_rows: Vec<u8> = parsed_rows
+                .iter()
+                .zip((first_row_pos..).step_by(row_byte_size))
+                .flat_map(|(row, row_pos)| {
+                    table_schema.index_row_to_bytes(index_name, row, row_pos)
+                })
+                .collect();
+            OpenOptions::new().create(true).append(true).open(
+                self.table_opener
+                    .index_file_name(&table_schema.name, index_name),
+            )?;
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(
+                    self.table_opener
+                        .index_delta_file_name(&table_schema.name, index_name),
+                )?
+                .write_all(&index_rows)?;
+            self.merge_index_delta(&table_schema, index_name, &IoThrottle::default())?;
+        }
+        self.table_opener.bump_generation(&table_schema.name)?;
+        self.record_modified_rows(&table_schema.name, parsed_rows.len());
+
+        Ok((table_schema, first_row_pos))
+    }
+
+    // Returns the position of the inserted row.
+    fn insert(&self, query: &InsertQuery) -> Result<TablePtrType, Error> {
+        let table_schema = self.table_opener.open_schema(&query.table)?;
+        self.check_unique_keys(&table_schema, &query.values, &table_schema.unique_indices)?;
+        let table_schema =
+            self.extend_dictionaries(table_schema, std::slice::from_ref(&query.values))?;
+        let bytes = table_schema.data_row_to_bytes(&query.values);
+        let mut table_data_file = self.table_opener.table_file_for_insert(&query.table)?;
+        let new_row_pos = table_data_file
+            .metadata()
+            .context("Failed reading table file size")?
+            .len();
+        let written_bytes_len = table_data_file.write(&bytes)?;
+        if written_bytes_len != bytes.len() {
+            return Err(PBaseError::BadFileWriteLength.into());
+        }
+        self.table_opener
+            .commit_table_len(&query.table, usize::try_from(new_row_pos)? + bytes.len());
+
+        for index_name in table_schema.indices.keys() {
+            self.insert_to_index(index_name, query, &table_schema, new_row_pos)?;
+        }
+        self.table_opener.bump_generation(&query.table)?;
+        self.record_modified_rows(&query.table, 1);
+
+        Ok(new_row_pos)
+    }
+
+    /// # Errors
+    ///
+    /// Errors on file operations, when the name is reserved for a system or the audit table, when
+    /// the storage layer does not support the table's storage options, or for default
+    /// expressions of unknown fields or of values not of the field's type.
+    pub fn run_create_table_query(&self, query: &CreateTableQuery) -> Result<(), Error> {
+        self.check_writable()?;
+        check_not_audit_table(&query.schema.name)?;
+        self.check_not_attached_table(&query.schema.name)?;
+        self.create_table(query)?;
+        self.wal
+            .append(WalOp::CreateTable(Box::new(query.clone())))?;
+
+        Ok(())
+    }
+
+    ///
+    /// Renames a regular table with its data, indices and stats. Writing the schema under the new
+    /// name commits the rename: the other files are hard linked to their new names before it and
+    /// the old names removed after it, so a rename interrupted before the commit leaves the table
+    /// as it was.
+    ///
+    /// # Errors
+    ///
+    /// Errors on file operations, when the table does not exist, or when the new name is taken,
+    /// reserved or not a valid file name.
+    pub fn rename_table(&self, from: &str, to: &str) -> Result<(), Error> {
+        self.check_writable()?;
+        check_not_audit_table(from)?;
+        check_not_audit_table(to)?;
+        self.check_not_attached_table(from)?;
+        self.check_not_attached_table(to)?;
+        let _gate = self.hold_off_snapshots();
+        self.copy_table(from, to, true)?;
+        self.wal.append(WalOp::RenameTable {
+            from: from.to_string(),
+            to: to.to_string(),
+        })?;
+
+        Ok(())
+    }
+
+    ///
+    /// Copies a regular table with its data, indices and stats to a new table, eg. to migrate the
+    /// copy while the source is still served. The copy is committed by writing its schema last.
+    ///
+    /// # Errors
+    ///
+    /// Errors on file operations, when the source table does not exist, or when the target name is
+    /// taken, reserved or not a valid file name.
+    pub fn clone_table(&self, source: &str, target: &str) -> Result<(), Error> {
+        self.check_writable()?;
+        check_not_audit_table(target)?;
+        self.check_not_attached_table(target)?;
+        let _gate = self.hold_off_snapshots();
+        self.copy_table(source, target, false)?;
+        self.wal.append(WalOp::CloneTable {
+            source: source.to_string(),
+            target: target.to_string(),
+        })?;
+
+        Ok(())
+    }
+
+    //
+    // Copies the files of a table to the new name (hard links when renaming), the schema last. A
+    // rename then removes the old files, the schema first.
+    //
+    fn copy_table(&self, from: &str, to: &str, rename: bool) -> Result<(), Error> {
+        let mut table_schema = self.table_opener.open_schema(from)?;
+        if is_system_table(to) {
+            return Err(PBaseError::ReservedTableName(to.to_string()).into());
+        }
+        validate_file_stem(to)?;
+        for index_name in table_schema.indices.keys() {
+            validate_file_stem(&format!("{to}__{index_name}"))?;
+        }
+        if self.is_table_exist(to) || self.table_opener.open_external_table(to)?.is_some() {
+            return Err(PBaseError::TableAlreadyExists(to.to_string()).into());
+        }
+
+        let mut files = vec![
+            (
+                self.table_opener.table_data_file_name(from),
+                self.table_opener.table_data_file_name(to),
+            ),
+            (
+                self.table_opener.table_stats_file_name(from),
+                self.table_opener.table_stats_file_name(to),
+            ),
+            (
+                self.table_opener.table_dictionary_file_name(from),
+                self.table_opener.table_dictionary_file_name(to),
+            ),
+            (
+                self.table_opener.table_generation_file_name(from),
+                self.table_opener.table_generation_file_name(to),
+            ),
+        ];
+        for index_name in table_schema.indices.keys() {
+            files.push((
+                self.table_opener.index_file_name(from, index_name),
+                self.table_opener.index_file_name(to, index_name),
+            ));
+            files.push((
+                self.table_opener.index_delta_file_name(from, index_name),
+                self.table_opener.index_delta_file_name(to, index_name),
+            ));
+        }
+
+        for (from_file, to_file) in &files {
+            if !from_file.exists() {
+                continue;
+            }
+            // Left over by an interrupted copy, the table did not exist.
+            if to_file.exists() {
+                std::fs::remove_file(to_file)?;
+            }
+            if rename {
+                std::fs::hard_link(from_file, to_file)?;
+            } else {
+                std::fs::copy(from_file, to_file)?;
+            }
+        }
+        table_schema.name = to.to_string();
+        table_schema.set_layout_hash();
+        atomic_write(
+            &self.table_opener.table_schema_file_name(to),
+            &serde_json::to_vec(&table_schema)?,
+        )?;
+
+        if rename {
+            std::fs::remove_file(self.table_opener.table_schema_file_name(from))?;
+            for (from_file, _) in &files {
+                if from_file.exists() {
+                    std::fs::remove_file(from_file)?;
+                }
+            }
+            self.table_opener.forget_table(from);
+            self.row_cache.invalidate_table(from);
         }
+        self.plan_cache.clear();
+
+        Ok(())
     }
 
-    #[must_use]
-    pub fn is_table_exist(&self, table_name: &str) -> bool {
-        self.table_opener
-            .table_schema_file_name(table_name)
-            .exists()
+    fn create_table(&self, query: &CreateTableQuery) -> Result<(), Error> {
+        if is_system_table(&query.schema.name) {
+            return Err(PBaseError::ReservedTableName(query.schema.name.clone()).into());
+        }
+        validate_file_stem(&query.schema.name)?;
+        for index_name in query.schema.indices.keys() {
+            validate_file_stem(&format!("{}__{index_name}", query.schema.name))?;
+        }
+        if self
+            .table_opener
+            .open_external_table(&query.schema.name)?
+            .is_some()
+        {
+            return Err(PBaseError::TableAlreadyExists(query.schema.name.clone()).into());
+        }
+        if let Some(index_name) = query
+            .schema
+            .unique_indices
+            .iter()
+            .find(|index_name| !query.schema.indices.contains_key(*index_name))
+        {
+            return Err(PBaseError::InvalidArgument(format!(
+                "unique index {index_name} is not an index"
+            ))
+            .into());
+        }
+
+        if let Some(column) = &query.schema.soft_delete_column {
+            match query.schema.fields.get(column) {
+                None => {
+                    return Err(PBaseError::InvalidArgument(format!(
+                        "soft delete column {column} is not a field"
+                    ))
+                    .into())
+                }
+                Some(FieldSchema::Decimal { .. } | FieldSchema::Custom(_)) => {
+                    return Err(PBaseError::InvalidArgument(format!(
+                        "soft delete column {column} is not U8 or I32"
+                    ))
+                    .into())
+                }
+                Some(_) => {}
+            }
+        }
+
+        query.schema.storage.check_supported()?;
+
+        // Tables start with every field stored as its type, see `analyze_table`.
+        let mut table_schema = query.schema.without_dictionaries();
+        table_schema.resolve_types(self.table_opener.type_defs(), false)?;
+        table_schema.check_defaults()?;
+        if table_schema.versioned {
+            match table_schema.fields.get(VERSION_FIELD) {
+                None => {
+                    table_schema
+                        .fields
+                        .insert(VERSION_FIELD.to_string(), FieldSchema::I32);
+                }
+                Some(FieldSchema::I32) => {}
+                Some(_) => {
+                    return Err(PBaseError::InvalidArgument(format!(
+                        "version field {VERSION_FIELD} must be I32"
+                    ))
+                    .into())
+                }
+            }
+        }
+
+        table_schema.set_layout_hash();
+        let mut schema_file =
+            File::create(self.table_opener.table_schema_file_name(&table_schema.name))?;
+        serde_json::to_writer(&mut schema_file, &table_schema)?;
+
+        File::create(self.table_opener.table_data_file_name(&query.schema.name))?;
+        self.plan_cache.clear();
+
+        Ok(())
     }
 
+    ///
+    /// Deletes the matching rows of a table, found like the rows of a select with the filters.
+    ///
+    /// Tables without a soft delete column lose the rows: they are removed from the data and their
+    /// entries from the indices. Rows are addressed by position, so the rows after a deleted one
+    /// move up (see `update_row_at`), and positions read before the delete are stale after it.
+    ///
+    /// Tables with a soft delete column (see `TableSchema::soft_delete_column`) keep the rows, and
+    /// the column is set: to 1 for U8 columns, to the current unix time (in seconds) for I32 ones.
+    /// The rows are hidden from selects not run `with_deleted`. Rows already deleted are left as
+    /// they are, and not matched. Each row is updated like by `update_row_at` (versioned rows get
+    /// a new version), the result sums up their updates.
+    ///
     /// # Errors
     ///
-    /// Errors on file operations.
-    pub fn run_select_query(
+    /// Errors on file operations, unknown fields, or when the table is the audit table.
+    pub fn run_delete_query(&self, query: &DeleteQuery) -> Result<MutationResult, Error> {
+        self.check_writable()?;
+        check_not_audit_table(&query.table)?;
+        self.check_not_attached_table(&query.table)?;
+        let _gate = self.hold_off_snapshots();
+        let _table_lock = self.table_opener.lock_table(&query.table)?;
+        self.table_opener.check_generation(&query.table)?;
+        let table_schema = self.table_opener.open_schema(&query.table)?;
+
+        // Positions and versions first: the deletion must not move rows under the scan.
+        let mut deleted_rows: Vec<(TablePtrType, Option<Value>)> = vec![];
+        SelectQueryExecutor::new(
+            &self.table_opener,
+            SelectQuery {
+                from: query.table.clone(),
+                filters: query.filters.clone(),
+                ..Default::default()
+            },
+        )
+        .for_each_row_view(|row_view| {
+            deleted_rows.push((
+                row_view.row_pos as TablePtrType,
+                row_view.get(VERSION_FIELD),
+            ));
+        })?;
+
+        let Some(column) = &table_schema.soft_delete_column else {
+            let mut row_positions: Vec<TablePtrType> = deleted_rows
+                .into_iter()
+                .map(|(row_pos, _)| row_pos)
+                .collect();
+            // Index scans find rows in key order.
+            row_positions.sort_unstable();
+            return self.hard_delete(&table_schema, &row_positions);
+        };
+        let deleted_value = match table_schema.fields[column] {
+            FieldSchema::U8 => Value::U8(1),
+            FieldSchema::I32 => Value::I32(unix_time()?),
+            FieldSchema::Decimal { .. } | FieldSchema::Custom(_) => {
+                unreachable!("Soft delete columns are U8 or I32")
+            }
+        };
+        let mut result = MutationResult::default();
+        for (row_pos, version) in &deleted_rows {
+            let mut values = HashMap::from([(column.clone(), deleted_value.clone())]);
+            if table_schema.versioned {
+                values.extend(
+                    version
+                        .clone()
+                        .map(|version| (VERSION_FIELD.to_string(), version)),
+                );
+            }
+            result += self.logged_update(&query.table, *row_pos, &values, AuditOp::Delete)?;
+        }
+
+        Ok(result)
+    }
+
+    // Deletes the rows of a table without a soft delete column, logged and audited.
+    fn hard_delete(
         &self,
-        query: SelectQuery,
-    ) -> Result<Vec<HashMap<String, Value>>, Error> {
-        SelectQueryExecutor::new(&self.table_opener, query).call()
+        table_schema: &TableSchema,
+        row_positions: &[TablePtrType],
+    ) -> Result<MutationResult, Error> {
+        let (result, deleted_rows) = self.delete_rows(table_schema, row_positions)?;
+        let lsn = self.wal.append(WalOp::DeleteRows {
+            table: table_schema.name.clone(),
+            row_positions: row_positions.to_vec(),
+        })?;
+        for (row_pos, deleted_row) in row_positions.iter().zip(&deleted_rows) {
+            self.audit(
+                table_schema,
+                lsn,
+                AuditOp::Delete,
+                *row_pos,
+                Some(deleted_row),
+                &FieldValues::new(),
+            )?;
+        }
+
+        Ok(result)
+    }
+
+    //
+    // Removes the rows at the positions (ascending) from the table data, moving the rows after them
+    // up, and their entries from the indices, whose pointers follow the moved rows. Returns the
+    // removed rows. Not crash safe: an interruption between the files leaves indices pointing at
+    // the wrong rows.
+    //
+    fn delete_rows(
+        &self,
+        table_schema: &TableSchema,
+        row_positions: &[TablePtrType],
+    ) -> Result<(MutationResult, Vec<FieldValues>), Error> {
+        let table = &table_schema.name;
+        let table_bytes = std::fs::read(self.table_opener.table_data_file_name(table))?;
+        let row_byte_size = table_schema.row_byte_size();
+        let mut deleted_rows = vec![];
+        for row_pos in row_positions {
+            let row_start = usize::try_from(*row_pos)?;
+            if row_start % row_byte_size != 0 || row_start + row_byte_size > table_bytes.len() {
+                return Err(PBaseError::InvalidRowPosition(*row_pos).into());
+            }
+            deleted_rows.push(
+                table_schema.parse_row_bytes(&table_bytes[row_start..row_start + row_byte_size]),
+            );
+        }
+        if deleted_rows.is_empty() {
+            return Ok((MutationResult::default(), deleted_rows));
+        }
+
+        let is_deleted = |row_pos: TablePtrType| row_positions.binary_search(&row_pos).is_ok();
+        let new_table_bytes: Vec<u8> = table_bytes
+            .chunks_exact(row_byte_size)
+            .zip((0..).step_by(row_byte_size))
+            .filter(|(_, row_pos)| !is_deleted(*row_pos))
+            .flat_map(|(row_bytes, _)| row_bytes)
+            .copied()
+            .collect();
+        atomic_write(
+            &self.table_opener.table_data_file_name(table),
+            &new_table_bytes,
+        )?;
+        self.table_opener.forget_table(table);
+        self.table_opener
+            .commit_table_len(table, new_table_bytes.len());
+
+        let (index_entries, index_bytes_written) =
+            self.rewrite_index_ptrs(table_schema, |row_pos| {
+                if is_deleted(row_pos) {
+                    return None;
+                }
+                let deleted_before = row_positions.partition_point(|deleted| *deleted < row_pos);
+                Some(row_pos - (deleted_before * row_byte_size) as TablePtrType)
+            })?;
+        self.row_cache.invalidate_table(table);
+        self.table_opener.bump_generation(table)?;
+        self.record_modified_rows(table, deleted_rows.len());
+
+        Ok((
+            MutationResult {
+                matched: deleted_rows.len(),
+                modified: deleted_rows.len(),
+                index_entries,
+                bytes_written: new_table_bytes.len() + index_bytes_written,
+            },
+            deleted_rows,
+        ))
     }
 
+    ///
+    /// Overwrites the given fields of the row starting at byte `row_pos` of the table data, and
+    /// moves the row's entries in the indices whose values changed. Meant for repair and replay
+    /// tooling that knows the exact row. The row is matched, and modified when a value changed.
+    ///
+    /// Updates of versioned tables pass the version of the row they read in `VERSION_FIELD`: the
+    /// update only happens when the row is still at that version, and it increments the version.
+    ///
     /// # Errors
     ///
-    /// Errors on file operations.
-    pub fn run_insert_query(&self, query: &InsertQuery) -> Result<usize, Error> {
-        let table_schema = self.table_opener.open_schema(&query.table)?;
-        let bytes = table_schema.data_row_to_bytes(&query.values);
-        let mut table_data_file = self.table_opener.table_file_for_insert(&query.table)?;
-        let new_row_pos = table_data_file
-            .metadata()
-            .context("Failed reading table file size")?
-            .len();
-        let written_bytes_len = table_data_file.write(&bytes)?;
-        if written_bytes_len != bytes.len() {
-            return Err(PBaseError::BadFileWriteLength.into());
+    /// Errors on file operations, unknown fields, when no row starts at `row_pos`, or when an
+    /// affected index has no entry for the row. Updates of versioned tables fail with
+    /// `PBaseError::VersionConflict` when the row has changed since it was read.
+    pub fn update_row_at(
+        &self,
+        table: &str,
+        row_pos: TablePtrType,
+        values: &HashMap<String, Value>,
+    ) -> Result<MutationResult, Error> {
+        self.update_row_as(table, row_pos, values, AuditOp::Update)
+    }
+
+    // Updates the row, recorded in the audit table as the operation.
+    fn update_row_as(
+        &self,
+        table: &str,
+        row_pos: TablePtrType,
+        values: &HashMap<String, Value>,
+        op: AuditOp,
+    ) -> Result<MutationResult, Error> {
+        self.check_writable()?;
+        check_not_audit_table(table)?;
+        self.check_not_attached_table(table)?;
+        let _gate = self.hold_off_snapshots();
+        let _table_lock = self.table_opener.lock_table(table)?;
+        self.table_opener.check_generation(table)?;
+        self.logged_update(table, row_pos, values, op)
+    }
+
+    // Updates the row, logged and audited as the operation. The caller holds off snapshots and
+    // holds the table's lock.
+    fn logged_update(
+        &self,
+        table: &str,
+        row_pos: TablePtrType,
+        values: &HashMap<String, Value>,
+        op: AuditOp,
+    ) -> Result<MutationResult, Error> {
+        let update = self.update_row(table, row_pos, values)?;
+        let lsn = self.wal.append(WalOp::UpdateRowAt {
+            table: table.to_string(),
+            row_pos,
+            values: values.clone(),
+        })?;
+        self.audit(
+            &update.table_schema,
+            lsn,
+            op,
+            update.row_pos,
+            Some(&update.old_row),
+            &update.new_row,
+        )?;
+
+        Ok(update.result)
+    }
+
+    //
+    // Records the mutation of an audited table in the audit table, creating it on first use. The
+    // audit rows are logged in the WAL right after the mutation, replicas replay them as is.
+    //
+    fn audit(
+        &self,
+        table_schema: &TableSchema,
+        lsn: Lsn,
+        op: AuditOp,
+        row_pos: TablePtrType,
+        old_row: Option<&FieldValues>,
+        new_row: &FieldValues,
+    ) -> Result<(), Error> {
+        if !table_schema.audited {
+            return Ok(());
         }
 
-        for (index_name, index_fields) in &table_schema.indices {
-            self.insert_to_index(index_name, index_fields, query, &table_schema, new_row_pos)?;
+        if !self.is_table_exist(AUDIT_TABLE) {
+            let query = CreateTableQuery {
+                schema: audit_table_schema(),
+            };
+            self.create_table(&query)?;
+            self.wal.append(WalOp::CreateTable(Box::new(query)))?;
         }
 
-        Ok(1)
+        let entry = AuditEntry {
+            lsn: i32::try_from(lsn)?,
+            time: unix_time()?,
+            actor: self.audit_actor,
+            op,
+            row_pos,
+            old_row,
+            new_row,
+        };
+        let _audit_lock = self.table_opener.lock_table(AUDIT_TABLE)?;
+        for values in audit_rows(table_schema, &entry)? {
+            let query = InsertQuery {
+                table: AUDIT_TABLE.to_string(),
+                values,
+            };
+            self.insert(&query)?;
+            self.wal.append(WalOp::Insert(query))?;
+        }
+
+        Ok(())
+    }
+
+    fn update_row(
+        &self,
+        table: &str,
+        row_pos: TablePtrType,
+        values: &HashMap<String, Value>,
+    ) -> Result<RowUpdate, Error> {
+        let table_schema = self.table_opener.open_schema(table)?;
+        let (old_row, new_row) = self.updated_row(&table_schema, row_pos, values)?;
+        let changed_indices: Vec<String> = changed_indices(&table_schema, &old_row, &new_row)
+            .into_iter()
+            .cloned()
+            .collect();
+        self.check_unique_keys(&table_schema, &new_row, &changed_indices)?;
+
+        let old_row_byte_size = table_schema.row_byte_size();
+        let table_schema =
+            self.extend_dictionaries(table_schema, std::slice::from_ref(&new_row))?;
+        // The row moves when a dictionary overflowed and its field got decoded.
+        let row_pos = moved_row_pos(row_pos, old_row_byte_size, table_schema.row_byte_size());
+
+        for index_name in &table_schema.unique_indices {
+            self.row_cache
+                .invalidate(&unique_key(&table_schema, index_name, &old_row));
+        }
+
+        let mut result = MutationResult {
+            matched: 1,
+            modified: usize::from(old_row != new_row),
+            ..Default::default()
+        };
+        let row_bytes = table_schema.data_row_to_bytes(&new_row);
+        result.bytes_written += self.write_row_bytes(table, row_pos, &row_bytes)?;
+
+        for index_name in &changed_indices {
+            self.merge_index_delta(&table_schema, index_name, &IoThrottle::default())?;
+            result.bytes_written +=
+                self.move_index_entry(index_name, &table_schema, &old_row, &new_row, row_pos)?;
+            result.index_entries += 1;
+        }
+        self.table_opener.bump_generation(table)?;
+        self.record_modified_rows(table, result.modified);
+
+        Ok(RowUpdate {
+            table_schema,
+            row_pos,
+            old_row,
+            new_row,
+            result,
+        })
+    }
+
+    //
+    // Overwrites the row starting at byte `row_pos` of the table data. In place, unless a read
+    // snapshot maps the table: the table is then written to a copy replacing the file, so that the
+    // snapshot keeps reading the row as it was. Returns the bytes written.
+    //
+    fn write_row_bytes(
+        &self,
+        table: &str,
+        row_pos: TablePtrType,
+        row_bytes: &[u8],
+    ) -> Result<usize, Error> {
+        let table_data_file_name = self.table_opener.table_data_file_name(table);
+        if self.snapshot_pins.is_pinned(table) {
+            let mut table_bytes = std::fs::read(&table_data_file_name)?;
+            let row_start = usize::try_from(row_pos)?;
+            table_bytes[row_start..row_start + row_bytes.len()].copy_from_slice(row_bytes);
+            atomic_write(&table_data_file_name, &table_bytes)?;
+            return Ok(table_bytes.len());
+        }
+
+        let mut table_data_file = OpenOptions::new().write(true).open(table_data_file_name)?;
+        table_data_file.seek(SeekFrom::Start(row_pos))?;
+        table_data_file.write_all(row_bytes)?;
+
+        Ok(row_bytes.len())
+    }
+
+    //
+    // The row starting at byte `row_pos` of the table data, before and after applying the values.
+    //
+    fn updated_row(
+        &self,
+        table_schema: &TableSchema,
+        row_pos: TablePtrType,
+        values: &HashMap<String, Value>,
+    ) -> Result<(FieldValues, FieldValues), Error> {
+        if let Some(field_name) = values
+            .keys()
+            .find(|field_name| !table_schema.fields.contains_key(*field_name))
+        {
+            return Err(PBaseError::UnknownField(field_name.clone()).into());
+        }
+
+        let mut table_data_file =
+            File::open(self.table_opener.table_data_file_name(&table_schema.name))?;
+        let row_byte_size = table_schema.row_byte_size();
+        let row_start = usize::try_from(row_pos)?;
+        let table_byte_size = usize::try_from(table_data_file.metadata()?.len())?;
+        if row_start % row_byte_size != 0 || row_start + row_byte_size > table_byte_size {
+            return Err(PBaseError::InvalidRowPosition(row_pos).into());
+        }
+
+        let mut old_row_bytes = vec![0; row_byte_size];
+        table_data_file.seek(SeekFrom::Start(row_pos))?;
+        table_data_file.read_exact(&mut old_row_bytes)?;
+        let old_row = table_schema.parse_row_bytes(&old_row_bytes);
+
+        let mut new_row = old_row.clone();
+        new_row.extend(values.iter().map(|(k, v)| (k.clone(), v.clone())));
+        if table_schema.versioned {
+            new_row.insert(
+                VERSION_FIELD.to_string(),
+                Value::I32(next_version(table_schema, &old_row, values)?),
+            );
+        }
+
+        Ok((old_row, new_row))
     }
 
+    ///
+    /// Validates a mutation the way running it would, and reports what it would change, without
+    /// writing anything (not even the WAL). Meant for migration scripts and admin tooling.
+    ///
     /// # Errors
     ///
-    /// Errors on file operations.
-    pub fn run_create_table_query(&self, query: &CreateTableQuery) -> Result<(), Error> {
-        let mut schema_file =
-            File::create(self.table_opener.table_schema_file_name(&query.schema.name))?;
-        serde_json::to_writer(&mut schema_file, &query.schema)?;
+    /// Errors when the mutation would fail: on unknown tables, and on fields, values or row
+    /// positions the mutation does not accept.
+    pub fn dry_run(&self, query: &MutationQuery) -> Result<DryRunReport, Error> {
+        match query {
+            MutationQuery::Insert(insert_query) => {
+                let table_schema = self.table_opener.open_schema(&insert_query.table)?;
+                let mut values = insert_query.values.clone();
+                self.fill_defaults(&table_schema, &mut values, &mut HashMap::new())?;
+                let row = table_schema.conform_row(&values, self.insert_mode)?;
+                self.check_unique_keys(&table_schema, &row, &table_schema.unique_indices)?;
 
-        File::create(self.table_opener.table_data_file_name(&query.schema.name))?;
+                let changed_indices: Vec<String> = table_schema.indices.keys().cloned().collect();
+
+                Ok(DryRunReport {
+                    table: insert_query.table.clone(),
+                    affected_rows: 1,
+                    changed_indices,
+                })
+            }
+            MutationQuery::UpdateRowAt {
+                table,
+                row_pos,
+                values,
+            } => {
+                let table_schema = self.table_opener.open_schema(table)?;
+                let (old_row, new_row) = self.updated_row(&table_schema, *row_pos, values)?;
+                let changed_indices = changed_indices(&table_schema, &old_row, &new_row);
+                self.check_unique_keys(&table_schema, &new_row, changed_indices.iter().copied())?;
+
+                Ok(DryRunReport {
+                    table: table.clone(),
+                    affected_rows: 1,
+                    changed_indices: changed_indices.into_iter().cloned().collect(),
+                })
+            }
+        }
+    }
+
+    ///
+    /// LSN of the last mutation committed (or applied from a primary) in this directory.
+    ///
+    /// # Errors
+    ///
+    /// Errors when the WAL cannot be read.
+    pub fn last_lsn(&self) -> Result<Lsn, Error> {
+        self.wal.last_lsn()
+    }
+
+    ///
+    /// WAL records from `from_lsn` (inclusive) on, to be applied by a replica. A replica asks for
+    /// the records after its own `last_lsn`.
+    ///
+    /// # Errors
+    ///
+    /// Errors when the WAL cannot be read.
+    pub fn stream_wal(&self, from_lsn: Lsn) -> Result<Vec<WalRecord>, Error> {
+        self.wal.read_from(from_lsn)
+    }
+
+    ///
+    /// Replays records of a primary's WAL, skipping the ones already applied, and records them in
+    /// this directory's WAL under the same LSNs. Returns the last applied LSN.
+    ///
+    /// # Errors
+    ///
+    /// Errors when a mutation fails or the batch skips records.
+    pub fn apply_wal(&self, batch: &[WalRecord]) -> Result<Lsn, Error> {
+        self.check_writable()?;
+        let _gate = self.hold_off_snapshots();
+        let mut last_lsn = self.wal.last_lsn()?;
+        for record in batch {
+            if record.lsn <= last_lsn {
+                continue;
+            }
+            if record.lsn != last_lsn + 1 {
+                return Err(PBaseError::WalGap {
+                    expected_lsn: last_lsn + 1,
+                    lsn: record.lsn,
+                }
+                .into());
+            }
+
+            let _table_lock = written_table(&record.op)
+                .map(|table| self.table_opener.lock_table(table))
+                .transpose()?;
+            match &record.op {
+                WalOp::CreateTable(query) => self.create_table(query)?,
+                WalOp::Insert(query) => {
+                    self.insert(query)?;
+                }
+                WalOp::UpdateRowAt {
+                    table,
+                    row_pos,
+                    values,
+                } => {
+                    self.update_row(table, *row_pos, values)?;
+                }
+                WalOp::CreateIndex {
+                    table,
+                    index_name,
+                    fields,
+                } => self.create_index(table, index_name, fields)?,
+                WalOp::RenameTable { from, to } => self.copy_table(from, to, true)?,
+                WalOp::CloneTable { source, target } => self.copy_table(source, target, false)?,
+                WalOp::CopyRows { table, rows } => {
+                    let table_schema = self.table_opener.open_schema(table)?;
+                    let rows = parse_copied_rows(&table_schema, rows);
+                    self.append_rows(table_schema, &rows)?;
+                }
+                WalOp::DeleteRows {
+                    table,
+                    row_positions,
+                } => {
+                    self.delete_rows(&self.table_opener.open_schema(table)?, row_positions)?;
+                }
+                WalOp::EncodeDictionaryColumn { table, field } => {
+                    self.recode_column(&self.table_opener.open_schema(table)?, field, true)?;
+                }
+            }
+            self.wal.append_record(record)?;
+            last_lsn = record.lsn;
+        }
+
+        Ok(last_lsn)
+    }
+
+    //
+    // Fails when a row of the table already has the row's key in one of the given indices that
+    // is unique.
+    //
+    fn check_unique_keys<'a>(
+        &self,
+        table_schema: &TableSchema,
+        row: &FieldValues,
+        index_names: impl IntoIterator<Item = &'a String>,
+    ) -> Result<(), Error> {
+        for index_name in index_names {
+            if !table_schema.unique_indices.contains(index_name) {
+                continue;
+            }
+
+            let filters = table_schema.indices[index_name]
+                .iter()
+                .map(|index_field| RowFilter {
+                    field: FieldSelector {
+                        name: index_field.clone(),
+                        source: table_schema.name.clone(),
+                    },
+                    op: CompareOp::Eq,
+                    rhs: RhsValue::Value(row[index_field].clone()),
+                })
+                .collect();
+            let matching_rows = SelectQueryExecutor::new(
+                &self.table_opener,
+                SelectQuery {
+                    from: table_schema.name.clone(),
+                    filters,
+                    limit: Some(1),
+                    ..Default::default()
+                },
+            )
+            .for_each_row_view(|_| {})?;
+            if matching_rows > 0 {
+                let key = unique_key(table_schema, index_name, row);
+                return Err(PBaseError::DuplicateKey {
+                    index: index_name.clone(),
+                    key: key
+                        .values
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                }
+                .into());
+            }
+        }
 
         Ok(())
     }
 
+    // Returns the bytes written: the index is rewritten as a whole.
+    fn move_index_entry(
+        &self,
+        index_name: &str,
+        table_schema: &TableSchema,
+        old_row: &HashMap<String, Value>,
+        new_row: &HashMap<String, Value>,
+        row_ptr: TablePtrType,
+    ) -> Result<usize, Error> {
+        let index_file_name = self
+            .table_opener
+            .index_file_name(&table_schema.name, index_name);
+        let mut index_bytes = std::fs::read(&index_file_name)?;
+        let index_row_size = table_schema.index_row_byte_size(index_name);
+        let index_values = |row: &HashMap<String, Value>| -> Vec<Value> {
+            table_schema.indices[index_name]
+                .iter()
+                .map(|index_field| row[index_field].clone())
+                .collect()
+        };
+
+        let old_values = index_values(old_row);
+        let old_pos = find_row_pos_in_index(
+            index_name,
+            &index_bytes,
+            &table_schema.index_row_to_bytes(index_name, old_row, row_ptr),
+            &old_values.iter().collect::<Vec<_>>(),
+            table_schema,
+        )
+        .ok_or_else(|| PBaseError::MissingIndexEntry {
+            index: index_name.to_string(),
+            row_ptr,
+        })?;
+        index_bytes.drain(old_pos * index_row_size..(old_pos + 1) * index_row_size);
+
+        let new_values = index_values(new_row);
+        let new_pos = find_insert_pos_in_index(
+            index_name,
+            &index_bytes,
+            &new_values.iter().collect::<Vec<_>>(),
+            table_schema,
+        );
+        index_bytes.splice(
+            new_pos * index_row_size..new_pos * index_row_size,
+            table_schema.index_row_to_bytes(index_name, new_row, row_ptr),
+        );
+
+        atomic_write(&index_file_name, &index_bytes)?;
+
+        Ok(index_bytes.len())
+    }
+
+    //
+    // Appends the index entry of a new row to the index delta, merging the delta into the sorted
+    // index once it is large enough. Keeps inserts from rewriting the whole index each time.
+    //
     fn insert_to_index(
         &self,
         index_name: &str,
-        index_fields: &[String],
         query: &InsertQuery,
         table_schema: &TableSchema,
         row_ptr: TablePtrType,
     ) -> Result<(), Error> {
-        // TODO:
-        // - experiment with memory mapped vs raw file mode.
+        // The sorted part must exist even when everything is still in the delta.
+        let index_file_name = self.table_opener.index_file_name(&query.table, index_name);
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&index_file_name)
+            .context(format!("Cannot open index file: {:?}", &index_file_name))?;
+
+        let index_row_bytes = table_schema.index_row_to_bytes(index_name, &query.values, row_ptr);
+        let mut index_delta_file = OpenOptions::new().create(true).append(true).open(
+            self.table_opener
+                .index_delta_file_name(&query.table, index_name),
+        )?;
+        index_delta_file.write_all(&index_row_bytes)?;
+
+        let delta_rows =
+            usize::try_from(index_delta_file.metadata()?.len())? / index_row_bytes.len();
+        if delta_rows >= INDEX_DELTA_MERGE_ROWS {
+            self.merge_index_delta(table_schema, index_name, &IoThrottle::default())?;
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Rows inserted, updated or deleted through this handle since the table was last analyzed.
+    ///
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    #[must_use]
+    pub fn modified_rows(&self, table: &str) -> usize {
+        self.modified_rows
+            .lock()
+            .unwrap()
+            .get(table)
+            .copied()
+            .unwrap_or_default()
+    }
 
-        // Extract exact fields in exact order.
-        let index_values: Vec<&Value> = index_fields
+    ///
+    /// Analyzes again the tables of which at least `changed_fraction` of the rows (as counted by
+    /// their stats) were modified through this handle since their last ANALYZE, so the planner
+    /// does not estimate from stale stats. Tables that were never analyzed are left alone.
+    /// Returns the names of the analyzed tables. See `maintenance::StatsRefreshTask` to run it in
+    /// the background.
+    ///
+    /// # Errors
+    ///
+    /// On file operations, or when the table data is invalid (see `analyze_table`).
+    ///
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    pub fn refresh_stale_stats(&self, changed_fraction: f64) -> Result<Vec<String>, Error> {
+        let modified_rows: Vec<(String, usize)> = self
+            .modified_rows
+            .lock()
+            .unwrap()
             .iter()
-            .map(|index_field_name| query.values.get(index_field_name).unwrap_or(&Value::NULL))
+            .map(|(table, rows)| (table.clone(), *rows))
             .collect();
 
-        let index_row_bytes =
-            &table_schema.index_row_to_bytes(index_name, &query.values, row_ptr)[..];
+        let mut analyzed = vec![];
+        for (table, rows) in modified_rows {
+            let Some(table_stats) = self.table_opener.open_stats(&table)? else {
+                // Never analyzed, or dropped or renamed since.
+                self.modified_rows.lock().unwrap().remove(&table);
+                continue;
+            };
+            if is_changed_fraction(rows, table_stats.row_count, changed_fraction) {
+                self.analyze_table(&table)?;
+                analyzed.push(table);
+            }
+        }
+        analyzed.sort();
 
-        // Find position.
-        let index_file_name = self.table_opener.index_file_name(&query.table, index_name);
-        let mut index_file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .truncate(false)
-            .open(&index_file_name)
-            .context(format!("Cannot open index file: {:?}", &index_file_name))?;
+        Ok(analyzed)
+    }
 
-        if index_file.metadata()?.len() == 0 {
-            // Short circuit. We cannot map "nothing" to memory.
-            index_file.write_all(index_row_bytes)?;
-            return Ok(());
+    // Counts the rows towards the next stats refresh of the table.
+    fn record_modified_rows(&self, table: &str, rows: usize) {
+        if rows > 0 {
+            *self
+                .modified_rows
+                .lock()
+                .unwrap()
+                .entry(table.to_string())
+                .or_default() += rows;
         }
+    }
 
-        let index_file_mmap = unsafe {
-            memmap::MmapOptions::new()
-                .map(&index_file)
-                .context("Convert index file to memory mapped file.")?
-        };
-        let insert_pos =
-            find_insert_pos_in_index(index_name, &index_file_mmap, &index_values, table_schema);
+    ///
+    /// Merges the appended index entries of every table into the sorted indices. Inserts do this
+    /// in batches of `INDEX_DELTA_MERGE_ROWS`; reads consult the deltas, so merging is only needed
+    /// before handing index files to tools that expect them complete.
+    ///
+    /// # Errors
+    ///
+    /// On file operations.
+    pub fn merge_index_deltas(&self) -> Result<(), Error> {
+        self.check_writable()?;
+        let _gate = self.hold_off_snapshots();
+        let table_schemas = self
+            .table_opener
+            .table_names()?
+            .iter()
+            .map(|table_name| self.table_opener.open_schema(table_name))
+            .collect::<Result<Vec<TableSchema>, Error>>()?;
+
+        // Delta entries to merge, the total of the progress.
+        let mut delta_rows = 0;
+        for table_schema in &table_schemas {
+            for index_name in table_schema.indices.keys() {
+                let index_delta_file_name = self
+                    .table_opener
+                    .index_delta_file_name(&table_schema.name, index_name);
+                if let Ok(metadata) = std::fs::metadata(index_delta_file_name) {
+                    delta_rows += usize::try_from(metadata.len())?
+                        / table_schema.index_row_byte_size(index_name);
+                }
+            }
+        }
+
+        self.progress.start("index merge", Some(delta_rows));
+        let mut merged_rows = 0;
+        for table_schema in &table_schemas {
+            for index_name in table_schema.indices.keys() {
+                merged_rows +=
+                    self.merge_index_delta(table_schema, index_name, &self.maintenance_throttle)?;
+                self.progress.advance(merged_rows);
+            }
+        }
+        self.progress.finish();
+
+        Ok(())
+    }
+
+    // Returns the number of delta entries merged. The file IO is kept within the throttle's budget.
+    fn merge_index_delta(
+        &self,
+        table_schema: &TableSchema,
+        index_name: &str,
+        throttle: &IoThrottle,
+    ) -> Result<usize, Error> {
+        let index_delta_bytes = self
+            .table_opener
+            .index_delta_bytes(table_schema, index_name)?;
+        if index_delta_bytes.is_empty() {
+            return Ok(0);
+        }
+        throttle.consume(index_delta_bytes.len());
 
-        // Divide index list + insert + merge
         let index_row_size = table_schema.index_row_byte_size(index_name);
-        let lhs_bytes = &index_file_mmap[0..insert_pos * index_row_size];
-        let rhs_bytes = &index_file_mmap[insert_pos * index_row_size..];
+        let index_file_name = self
+            .table_opener
+            .index_file_name(&table_schema.name, index_name);
+        let index_bytes = throttle.read(&index_file_name)?;
+        let index_key =
+            |index_row: &[u8]| table_schema.parse_index_row_bytes(index_name, index_row).0;
 
-        // Save
-        let tmp_file_path = index_file_name.with_extension("tmp");
-        let mut tmp_file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(&tmp_file_path)?;
+        // Stable: delta entries of the same key stay in insertion order, after the sorted ones.
+        let mut delta_rows: Vec<(Vec<Value>, &[u8])> = index_delta_bytes
+            .chunks_exact(index_row_size)
+            .map(|index_row| (index_key(index_row), index_row))
+            .collect();
+        delta_rows.sort_by(|(lhs_key, _), (rhs_key, _)| lhs_key.cmp(rhs_key));
+
+        let mut merged = Vec::with_capacity(index_bytes.len() + index_delta_bytes.len());
+        let mut delta_rows = delta_rows.into_iter().peekable();
+        for index_row in index_bytes.chunks_exact(index_row_size) {
+            let key = index_key(index_row);
+            while let Some((_, delta_row)) = delta_rows.next_if(|(delta_key, _)| *delta_key < key) {
+                merged.extend_from_slice(delta_row);
+            }
+            merged.extend_from_slice(index_row);
+        }
+        for (_, delta_row) in delta_rows {
+            merged.extend_from_slice(delta_row);
+        }
 
-        tmp_file.write_all(lhs_bytes)?;
-        tmp_file.write_all(index_row_bytes)?;
-        tmp_file.write_all(rhs_bytes)?;
+        throttle.atomic_write(&index_file_name, &merged)?;
+        std::fs::remove_file(
+            self.table_opener
+                .index_delta_file_name(&table_schema.name, index_name),
+        )?;
 
-        drop(index_file_mmap);
-        drop(index_file);
+        Ok(index_delta_bytes.len() / index_row_size)
+    }
+}
 
-        std::fs::rename(tmp_file_path, index_file_name)?;
+//
+// Whether the modified rows make up the fraction of the analyzed ones. The fraction tolerates the
+// precision loss of large counts.
+//
+#[allow(clippy::cast_precision_loss)]
+fn is_changed_fraction(modified_rows: usize, analyzed_rows: usize, changed_fraction: f64) -> bool {
+    modified_rows > 0 && modified_rows as f64 >= analyzed_rows as f64 * changed_fraction
+}
 
-        Ok(())
+//
+// Position of the row at `row_pos` once rows are rewritten in another size.
+//
+const fn moved_row_pos(
+    row_pos: TablePtrType,
+    old_row_byte_size: usize,
+    new_row_byte_size: usize,
+) -> TablePtrType {
+    if old_row_byte_size == new_row_byte_size {
+        return row_pos;
+    }
+    row_pos / old_row_byte_size as TablePtrType * new_row_byte_size as TablePtrType
+}
+
+//
+// Rows of a copy stream, laid out as the table's fields (not dictionary encoded, see `CopyHeader`).
+//
+fn parse_copied_rows(table_schema: &TableSchema, rows: &[u8]) -> Vec<FieldValues> {
+    let plain_schema = table_schema.without_dictionaries();
+    rows.chunks_exact(plain_schema.row_byte_size())
+        .map(|row_bytes| plain_schema.parse_row_bytes(row_bytes))
+        .collect()
+}
+
+//
+// The version a versioned row gets from an update, when the update expects its current version.
+//
+//
+// The table whose rows the logged operation writes, none for schema changes.
+//
+const fn written_table(op: &WalOp) -> Option<&String> {
+    match op {
+        WalOp::Insert(InsertQuery { table, .. })
+        | WalOp::UpdateRowAt { table, .. }
+        | WalOp::CopyRows { table, .. }
+        | WalOp::DeleteRows { table, .. }
+        | WalOp::EncodeDictionaryColumn { table, .. } => Some(table),
+        WalOp::CreateTable(_)
+        | WalOp::CreateIndex { .. }
+        | WalOp::RenameTable { .. }
+        | WalOp::CloneTable { .. } => None,
+    }
+}
+
+fn next_version(
+    table_schema: &TableSchema,
+    old_row: &FieldValues,
+    values: &FieldValues,
+) -> Result<i32, Error> {
+    let expected = values
+        .get(VERSION_FIELD)
+        .ok_or_else(|| PBaseError::MissingField(VERSION_FIELD.to_string()))?;
+    let Some(Value::I32(expected)) = FieldSchema::I32.coerce(expected) else {
+        return Err(PBaseError::FieldTypeMismatch {
+            field: VERSION_FIELD.to_string(),
+            value: expected.to_string(),
+        }
+        .into());
+    };
+    let Value::I32(actual) = old_row[VERSION_FIELD] else {
+        unreachable!("Version field is I32");
+    };
+    if expected != actual {
+        return Err(PBaseError::VersionConflict {
+            table: table_schema.name.clone(),
+            expected,
+            actual,
+        }
+        .into());
     }
+
+    actual.checked_add(1).ok_or_else(|| {
+        PBaseError::NumericOverflow(format!("version of table {}", table_schema.name)).into()
+    })
+}
+
+// The audit table is only written by the engine.
+fn check_not_audit_table(table: &str) -> Result<(), Error> {
+    if table == AUDIT_TABLE {
+        return Err(PBaseError::ReservedTableName(table.to_string()).into());
+    }
+
+    Ok(())
+}
+
+fn int_value(value: &Value) -> Option<i64> {
+    match value {
+        Value::I32(v) => Some(i64::from(*v)),
+        Value::U8(v) => Some(i64::from(*v)),
+        Value::NULL | Value::Decimal(_) | Value::Custom(_) => None,
+    }
+}
+
+// Current unix time in seconds.
+fn unix_time() -> Result<i32, Error> {
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+    Ok(i32::try_from(now.as_secs())?)
+}
+
+fn unique_key(table_schema: &TableSchema, index_name: &str, row: &FieldValues) -> RowCacheKey {
+    RowCacheKey {
+        table: table_schema.name.clone(),
+        index: index_name.to_string(),
+        values: table_schema.indices[index_name]
+            .iter()
+            .map(|index_field| row[index_field].clone())
+            .collect(),
+    }
+}
+
+//
+// Indices (in declaration order) whose values differ between the old and the new version of a row.
+//
+fn changed_indices<'a>(
+    table_schema: &'a TableSchema,
+    old_row: &HashMap<String, Value>,
+    new_row: &HashMap<String, Value>,
+) -> Vec<&'a String> {
+    table_schema
+        .indices
+        .iter()
+        .filter(|(_, index_fields)| {
+            index_fields
+                .iter()
+                .any(|index_field| old_row[index_field] != new_row[index_field])
+        })
+        .map(|(index_name, _)| index_name)
+        .collect()
 }
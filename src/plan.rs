@@ -0,0 +1,1667 @@
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fmt::{Display, Formatter, Write},
+    time::Duration,
+};
+
+use log::debug;
+
+use crate::{
+    operator::{RuntimeStats, SortKey},
+    query::{
+        CallFilter, CompareOp, JoinContract, JoinType, RhsValue, RowFilter, SampleSpec, ScalarCall,
+        SelectQuery,
+    },
+    schema::TablePtrType,
+    value::Value,
+};
+
+///
+/// What running a select took, see `PBase::run_select_query_with_stats`. Displayed as the CLI's
+/// footer: `N rows in X ms (index: idx_name, scanned M rows)`.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryStats {
+    pub rows: usize,
+    pub elapsed: Duration,
+    // Indices the query read, in plan order.
+    pub indices: Vec<String>,
+    // Rows read from the tables (by scans and index scans), before filters and joins.
+    pub scanned_rows: usize,
+    // Answered from the row cache, without reading the table.
+    pub row_cache_hit: bool,
+}
+
+impl QueryStats {
+    ///
+    /// Stats of a query executed with its plan, from the plan's runtime stats.
+    ///
+    #[must_use]
+    pub fn of_plan(plan: &QueryPlan, rows: usize, elapsed: Duration) -> Self {
+        let mut stats = Self {
+            rows,
+            elapsed,
+            ..Default::default()
+        };
+        stats.add_reads(plan);
+        stats
+    }
+
+    fn add_reads(&mut self, plan: &QueryPlan) {
+        match &plan.node {
+            PlanNode::Scan { .. } => {}
+            PlanNode::IndexScan { index, .. } => {
+                if !self.indices.contains(index) {
+                    self.indices.push(index.clone());
+                }
+            }
+            _ => {
+                for child in &plan.children {
+                    self.add_reads(child);
+                }
+                return;
+            }
+        }
+        self.scanned_rows += plan.runtime.map_or(0, |runtime| runtime.rows);
+    }
+}
+
+impl Display for QueryStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} row{} in {:.3} ms (",
+            self.rows,
+            if self.rows == 1 { "" } else { "s" },
+            self.elapsed.as_secs_f64() * 1000.0
+        )?;
+        if self.row_cache_hit {
+            return write!(f, "row cache)");
+        }
+        if self.indices.is_empty() {
+            write!(f, "no index")?;
+        } else {
+            write!(f, "index: {}", self.indices.join(", "))?;
+        }
+        write!(f, ", scanned {} rows)", self.scanned_rows)
+    }
+}
+
+///
+/// Logical query plan: what to compute, independent of access paths (index or full scan) and of
+/// the operators executing it.
+///
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum LogicalPlan {
+    // Known to produce no rows.
+    Empty,
+    Scan {
+        table: String,
+    },
+    Filter {
+        input: Box<Self>,
+        // List of AND-ed filters.
+        filters: Vec<RowFilter>,
+    },
+    Join {
+        lhs: Box<Self>,
+        rhs: Box<Self>,
+        contract: JoinContract,
+    },
+    Limit {
+        input: Box<Self>,
+        limit: usize,
+    },
+}
+
+impl LogicalPlan {
+    ///
+    /// Tables whose fields are available in the output rows of this node.
+    ///
+    #[must_use]
+    pub fn sources(&self) -> HashSet<&str> {
+        match self {
+            Self::Empty => HashSet::new(),
+            Self::Scan { table } => HashSet::from([table.as_str()]),
+            Self::Filter { input, .. } | Self::Limit { input, .. } => input.sources(),
+            Self::Join { lhs, rhs, .. } => {
+                let mut sources = lhs.sources();
+                sources.extend(rhs.sources());
+                sources
+            }
+        }
+    }
+
+    ///
+    /// Filters the scans of the tables the filters are on right above them, below any join. Unlike
+    /// filters of the query, these also hold on the right side of left joins: rows they reject are
+    /// not there to match.
+    ///
+    #[must_use]
+    pub fn filter_scans(self, filters: &[RowFilter]) -> Self {
+        match self {
+            Self::Empty => self,
+            Self::Scan { ref table } => {
+                let scan_filters = filters
+                    .iter()
+                    .filter(|filter| filter_sources(filter) == HashSet::from([table.as_str()]))
+                    .cloned()
+                    .collect();
+                Self::filter(self, scan_filters)
+            }
+            Self::Filter {
+                input,
+                filters: input_filters,
+            } => Self::Filter {
+                input: Box::new(input.filter_scans(filters)),
+                filters: input_filters,
+            },
+            Self::Join { lhs, rhs, contract } => Self::Join {
+                lhs: Box::new(lhs.filter_scans(filters)),
+                rhs: Box::new(rhs.filter_scans(filters)),
+                contract,
+            },
+            Self::Limit { input, limit } => Self::Limit {
+                input: Box::new(input.filter_scans(filters)),
+                limit,
+            },
+        }
+    }
+
+    fn filter(input: Self, filters: Vec<RowFilter>) -> Self {
+        if filters.is_empty() {
+            input
+        } else {
+            Self::Filter {
+                input: Box::new(input),
+                filters,
+            }
+        }
+    }
+}
+
+///
+/// The naive plan of a select: joins in query order, all filters on top of them, limit last.
+///
+impl From<&SelectQuery> for LogicalPlan {
+    fn from(query: &SelectQuery) -> Self {
+        let mut plan = Self::Scan {
+            table: query.from.clone(),
+        };
+
+        for join_contract in &query.joins {
+            plan = Self::Join {
+                lhs: Box::new(plan),
+                rhs: Box::new(Self::Scan {
+                    table: join_contract.rhs.source.clone(),
+                }),
+                contract: join_contract.clone(),
+            };
+        }
+
+        plan = Self::filter(plan, query.filters.clone());
+
+        if let Some(limit) = query.limit {
+            plan = Self::Limit {
+                input: Box::new(plan),
+                limit,
+            };
+        }
+
+        plan
+    }
+}
+
+fn filter_sources(filter: &RowFilter) -> HashSet<&str> {
+    let mut sources = HashSet::from([filter.field.source.as_str()]);
+    if let Some(reference) = filter.rhs.reference() {
+        sources.insert(reference.source.as_str());
+    }
+    sources
+}
+
+///
+/// A logical-to-logical transformation that must not change the result set (row order may change).
+///
+pub trait RewriteRule {
+    fn name(&self) -> &'static str;
+
+    fn rewrite(&self, plan: LogicalPlan) -> LogicalPlan;
+}
+
+///
+/// Evaluates what the filters decide without reading any rows.
+///
+/// Filters comparing a field to itself: `x = x` always holds and is dropped, `x < x` and `x > x`
+/// never hold and turn the plan empty. Value filters on the same field are narrowed to their
+/// tightest bounds (`x > 5 AND x > 3` keeps `x > 5`, `x = 4 AND x < 9` keeps `x = 4`), and bounds
+/// that cannot all hold (`x > 5 AND x < 3`, `x = 1 AND x = 2`, `x < NULL`) turn the plan empty, as
+/// do an empty IN list and a range with its bounds the wrong way around (`5 <= x <= 3`). Empty
+/// inputs are propagated up the tree.
+///
+pub struct ConstantFolding;
+
+impl RewriteRule for ConstantFolding {
+    fn name(&self) -> &'static str {
+        "constant_folding"
+    }
+
+    fn rewrite(&self, plan: LogicalPlan) -> LogicalPlan {
+        match plan {
+            LogicalPlan::Empty | LogicalPlan::Scan { .. } => plan,
+            LogicalPlan::Filter { input, filters } => {
+                let input = self.rewrite(*input);
+                if input == LogicalPlan::Empty {
+                    return LogicalPlan::Empty;
+                }
+
+                let mut filters_left = vec![];
+                for filter in filters {
+                    if is_unsatisfiable(&filter) {
+                        return LogicalPlan::Empty;
+                    }
+
+                    let is_self_comparison = match &filter.rhs {
+                        RhsValue::Ref(reference) => reference == &filter.field,
+                        RhsValue::Value(_)
+                        | RhsValue::In(_)
+                        | RhsValue::Range { .. }
+                        | RhsValue::Interval { .. }
+                        | RhsValue::Subquery { .. } => false,
+                    };
+
+                    // A field is equal to itself: `a <= a` always passes, `a < a` never.
+                    if !is_self_comparison {
+                        filters_left.push(filter);
+                    } else if !filter.op.matches(Ordering::Equal) {
+                        return LogicalPlan::Empty;
+                    }
+                }
+
+                let Some(filters_left) = fold_value_filters(filters_left) else {
+                    return LogicalPlan::Empty;
+                };
+
+                LogicalPlan::filter(input, filters_left)
+            }
+            LogicalPlan::Join { lhs, rhs, contract } => {
+                let lhs = self.rewrite(*lhs);
+                let rhs = self.rewrite(*rhs);
+                // Outer joins keep the rows of one side without rows on the other.
+                let join_type = contract.join_type;
+                if (lhs == LogicalPlan::Empty && !join_type.keeps_unmatched_rhs())
+                    || (rhs == LogicalPlan::Empty && !join_type.keeps_unmatched_lhs())
+                {
+                    return LogicalPlan::Empty;
+                }
+
+                LogicalPlan::Join {
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
+                    contract,
+                }
+            }
+            LogicalPlan::Limit { input, limit } => {
+                let input = self.rewrite(*input);
+                if input == LogicalPlan::Empty || limit == 0 {
+                    return LogicalPlan::Empty;
+                }
+
+                LogicalPlan::Limit {
+                    input: Box::new(input),
+                    limit,
+                }
+            }
+        }
+    }
+}
+
+//
+// Whether no value passes the filter: a comparison against NULL, an IN list without non NULL
+// values, or a range with its bounds (of the same type) the wrong way around.
+//
+fn is_unsatisfiable(filter: &RowFilter) -> bool {
+    match &filter.rhs {
+        RhsValue::Value(value) => *value == Value::NULL,
+        RhsValue::In(values) => values.iter().all(|value| *value == Value::NULL),
+        RhsValue::Range {
+            low,
+            high,
+            inclusive,
+        } => {
+            *low == Value::NULL
+                || *high == Value::NULL
+                || (std::mem::discriminant(low) == std::mem::discriminant(high)
+                    && if *inclusive { low > high } else { low >= high })
+        }
+        RhsValue::Ref(_) | RhsValue::Interval { .. } | RhsValue::Subquery { .. } => false,
+    }
+}
+
+// Position of a value filter among the filters, its comparison and value.
+type ValueBound<'a> = (usize, CompareOp, &'a Value);
+
+//
+// Keeps the tightest value filters of each field (an equality, or a lower and an upper bound), in
+// their original order. None when the filters of a field contradict each other. Fields compared to
+// values of different types are left as they are, and so are not-equal filters.
+//
+fn fold_value_filters(filters: Vec<RowFilter>) -> Option<Vec<RowFilter>> {
+    let mut field_filters: HashMap<String, Vec<ValueBound>> = HashMap::new();
+    for (filter_idx, filter) in filters.iter().enumerate() {
+        if let (RhsValue::Value(value), false) = (&filter.rhs, filter.op == CompareOp::Ne) {
+            field_filters
+                .entry(filter.field.full_name())
+                .or_default()
+                .push((filter_idx, filter.op, value));
+        }
+    }
+
+    let mut dropped = HashSet::new();
+    for bounds in field_filters.values() {
+        let mut kinds = bounds
+            .iter()
+            .filter(|(_, _, value)| **value != Value::NULL)
+            .map(|(_, _, value)| std::mem::discriminant(*value));
+        let first_kind = kinds.next();
+        if kinds.any(|kind| Some(kind) != first_kind) {
+            continue;
+        }
+
+        let tightest = |ops: [CompareOp; 2], is_tighter: fn(&ValueBound, &ValueBound) -> bool| {
+            bounds.iter().filter(|(_, op, _)| ops.contains(op)).fold(
+                None,
+                |tightest: Option<&ValueBound>, bound| match tightest {
+                    Some(current) if !is_tighter(bound, current) => Some(current),
+                    _ => Some(bound),
+                },
+            )
+        };
+        // Of two bounds at the same value, the exclusive one is tighter.
+        let lower = tightest([CompareOp::Gt, CompareOp::Ge], |bound, current| {
+            bound.2 > current.2 || (bound.2 == current.2 && current.1 == CompareOp::Ge)
+        });
+        let upper = tightest([CompareOp::Lt, CompareOp::Le], |bound, current| {
+            bound.2 < current.2 || (bound.2 == current.2 && current.1 == CompareOp::Le)
+        });
+        let equal = tightest([CompareOp::Eq, CompareOp::Eq], |_, _| false);
+
+        let passes = |bound: Option<&ValueBound>, value: &Value| {
+            bound.is_none_or(|bound| bound.1.matches(value.cmp(bound.2)))
+        };
+        let kept = if let Some(equal) = equal {
+            let is_consistent = bounds
+                .iter()
+                .filter(|(_, op, _)| *op == CompareOp::Eq)
+                .all(|(_, _, value)| *value == equal.2);
+            if !is_consistent || !passes(lower, equal.2) || !passes(upper, equal.2) {
+                return None;
+            }
+            vec![equal.0]
+        } else {
+            // Nothing is below NULL, which orders first.
+            let is_empty = upper.is_some_and(|upper| {
+                (*upper.2 == Value::NULL && upper.1 == CompareOp::Lt)
+                    || lower.is_some_and(|lower| match upper.2.cmp(lower.2) {
+                        Ordering::Less => true,
+                        Ordering::Equal => lower.1 == CompareOp::Gt || upper.1 == CompareOp::Lt,
+                        Ordering::Greater => false,
+                    })
+            });
+            if is_empty {
+                return None;
+            }
+            lower
+                .into_iter()
+                .chain(upper)
+                .map(|bound| bound.0)
+                .collect()
+        };
+
+        dropped.extend(
+            bounds
+                .iter()
+                .map(|bound| bound.0)
+                .filter(|filter_idx| !kept.contains(filter_idx)),
+        );
+    }
+
+    Some(
+        filters
+            .into_iter()
+            .enumerate()
+            .filter(|(filter_idx, _)| !dropped.contains(filter_idx))
+            .map(|(_, filter)| filter)
+            .collect(),
+    )
+}
+
+///
+/// Moves each filter down to the lowest node that provides all the tables it references, so
+/// single table filters end up right above their scan (where indexes can serve them).
+///
+/// Filters on the side an outer join fills with NULLs (the right side of a left join, the left side
+/// of a right join, both sides of a full join) stay above it: below, they would turn the rows they
+/// reject into unmatched rows of the other side instead of dropping them.
+///
+pub struct FilterPushdown;
+
+impl FilterPushdown {
+    fn push(plan: LogicalPlan, filters: Vec<RowFilter>) -> LogicalPlan {
+        match plan {
+            LogicalPlan::Empty => LogicalPlan::Empty,
+            LogicalPlan::Scan { .. } => LogicalPlan::filter(plan, filters),
+            LogicalPlan::Filter {
+                input,
+                filters: mut input_filters,
+            } => {
+                input_filters.extend(filters);
+                Self::push(*input, input_filters)
+            }
+            LogicalPlan::Join { lhs, rhs, contract } => {
+                let mut lhs_filters = vec![];
+                let mut rhs_filters = vec![];
+                let mut join_filters = vec![];
+                {
+                    let lhs_sources = lhs.sources();
+                    let rhs_sources = rhs.sources();
+                    for filter in filters {
+                        let sources = filter_sources(&filter);
+                        if sources.is_subset(&lhs_sources)
+                            && !contract.join_type.keeps_unmatched_rhs()
+                        {
+                            lhs_filters.push(filter);
+                        } else if sources.is_subset(&rhs_sources)
+                            && !contract.join_type.keeps_unmatched_lhs()
+                        {
+                            rhs_filters.push(filter);
+                        } else {
+                            join_filters.push(filter);
+                        }
+                    }
+                }
+
+                let join = LogicalPlan::Join {
+                    lhs: Box::new(Self::push(*lhs, lhs_filters)),
+                    rhs: Box::new(Self::push(*rhs, rhs_filters)),
+                    contract,
+                };
+                LogicalPlan::filter(join, join_filters)
+            }
+            // Filtering before the limit would let different rows through.
+            LogicalPlan::Limit { input, limit } => LogicalPlan::filter(
+                LogicalPlan::Limit {
+                    input: Box::new(Self::push(*input, vec![])),
+                    limit,
+                },
+                filters,
+            ),
+        }
+    }
+}
+
+impl RewriteRule for FilterPushdown {
+    fn name(&self) -> &'static str {
+        "filter_pushdown"
+    }
+
+    fn rewrite(&self, plan: LogicalPlan) -> LogicalPlan {
+        Self::push(plan, vec![])
+    }
+}
+
+///
+/// Reorders a left-deep chain of joins so filtered (hence likely smaller) tables are joined first,
+/// shrinking the intermediate results. A join is only moved after the join providing its left side.
+///
+/// Chains with right or full joins are kept in order: a join moved before one of them would no
+/// longer drop the unmatched right rows it adds, whose left columns are NULL.
+///
+pub struct JoinReordering;
+
+impl JoinReordering {
+    fn reorder_chain(&self, plan: LogicalPlan) -> LogicalPlan {
+        // Unfold the chain: `((base JOIN a) JOIN b) JOIN c` -> base, [a, b, c].
+        let mut joins = vec![];
+        let mut base = plan;
+        while let LogicalPlan::Join { lhs, rhs, contract } = base {
+            joins.push((self.rewrite(*rhs), contract));
+            base = *lhs;
+        }
+        joins.reverse();
+        let base = self.rewrite(base);
+        if joins
+            .iter()
+            .any(|(_, contract)| contract.join_type.keeps_unmatched_rhs())
+        {
+            return joins
+                .into_iter()
+                .fold(base, |lhs, (rhs, contract)| LogicalPlan::Join {
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
+                    contract,
+                });
+        }
+
+        let mut available: HashSet<String> =
+            base.sources().into_iter().map(str::to_string).collect();
+        let mut plan = base;
+        while !joins.is_empty() {
+            let is_joinable = |(_, contract): &(LogicalPlan, JoinContract)| {
+                available.contains(&contract.lhs.source)
+            };
+            let next = joins
+                .iter()
+                .position(|join| is_joinable(join) && matches!(join.0, LogicalPlan::Filter { .. }))
+                .or_else(|| joins.iter().position(is_joinable))
+                // Joins referring to unknown tables are kept in place.
+                .unwrap_or(0);
+
+            let (rhs, contract) = joins.remove(next);
+            available.extend(rhs.sources().into_iter().map(str::to_string));
+            plan = LogicalPlan::Join {
+                lhs: Box::new(plan),
+                rhs: Box::new(rhs),
+                contract,
+            };
+        }
+
+        plan
+    }
+}
+
+impl RewriteRule for JoinReordering {
+    fn name(&self) -> &'static str {
+        "join_reordering"
+    }
+
+    fn rewrite(&self, plan: LogicalPlan) -> LogicalPlan {
+        match plan {
+            LogicalPlan::Empty | LogicalPlan::Scan { .. } => plan,
+            LogicalPlan::Filter { input, filters } => LogicalPlan::Filter {
+                input: Box::new(self.rewrite(*input)),
+                filters,
+            },
+            LogicalPlan::Join { .. } => self.reorder_chain(plan),
+            LogicalPlan::Limit { input, limit } => LogicalPlan::Limit {
+                input: Box::new(self.rewrite(*input)),
+                limit,
+            },
+        }
+    }
+}
+
+///
+/// Applies rewrite rules in order, each on the result of the previous one.
+///
+pub struct Optimizer {
+    rules: Vec<Box<dyn RewriteRule>>,
+}
+
+impl Optimizer {
+    #[must_use]
+    pub fn new(rules: Vec<Box<dyn RewriteRule>>) -> Self {
+        Self { rules }
+    }
+
+    #[must_use]
+    pub fn optimize(&self, plan: LogicalPlan) -> LogicalPlan {
+        self.rules.iter().fold(plan, |plan, rule| {
+            let plan = rule.rewrite(plan);
+            debug!("Plan after {}: {plan:?}", rule.name());
+            plan
+        })
+    }
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        // Join reordering relies on filters already being pushed down to the joined tables.
+        Self::new(vec![
+            Box::new(ConstantFolding),
+            Box::new(FilterPushdown),
+            Box::new(JoinReordering),
+        ])
+    }
+}
+
+///
+/// A physical plan operator: the access path or algorithm chosen for a logical node.
+///
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum PlanNode {
+    Empty,
+    Scan {
+        table: String,
+        sample: Option<SampleSpec>,
+    },
+    IndexScan {
+        table: String,
+        index: String,
+        // Exclusive line index ranges of the narrowed index, in index order: one per probed value of
+        // an IN list, a single one otherwise.
+        ranges: Vec<(i32, i32)>,
+        // Matching rows of the index delta, sorted by key.
+        delta_rows: Vec<TablePtrType>,
+        // The ranges are read backwards, in reverse index order.
+        descending: bool,
+    },
+    // The distinct leading keys of an index, without reading the table.
+    IndexDistinct {
+        table: String,
+        index: String,
+        // Distinct leading keys of the index delta, sorted.
+        delta_keys: Vec<Value>,
+        // The keys are read in reverse index order.
+        descending: bool,
+    },
+    Filter {
+        filters: Vec<RowFilter>,
+    },
+    HashJoin {
+        lhs_key: String,
+        rhs_key: String,
+        join_type: JoinType,
+        // Only the first matching right row is joined to each left row.
+        first_match: bool,
+        // NULL keys match each other, see `JoinContract::null_keys_match`.
+        null_keys_match: bool,
+    },
+    Compute {
+        calls: Vec<ScalarCall>,
+        filters: Vec<CallFilter>,
+    },
+    Distinct,
+    // Keeps the first row of each value of the column.
+    DistinctValues {
+        column: String,
+    },
+    Sort {
+        keys: Vec<SortKey>,
+    },
+    Limit {
+        limit: usize,
+    },
+}
+
+impl Display for PlanNode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "Empty"),
+            Self::Scan { table, sample } => {
+                write!(f, "Scan {table}")?;
+                if let Some(sample) = sample {
+                    write!(f, " TABLESAMPLE {sample}")?;
+                }
+                Ok(())
+            }
+            Self::IndexScan {
+                table,
+                index,
+                descending,
+                ..
+            } => {
+                write!(f, "IndexScan {table} using {index}")?;
+                if *descending {
+                    write!(f, " DESC")?;
+                }
+                Ok(())
+            }
+            Self::IndexDistinct {
+                table,
+                index,
+                descending,
+                ..
+            } => {
+                write!(f, "IndexDistinct {table} using {index}")?;
+                if *descending {
+                    write!(f, " DESC")?;
+                }
+                Ok(())
+            }
+            Self::Filter { filters } => {
+                let filters: Vec<String> = filters.iter().map(ToString::to_string).collect();
+                write!(f, "Filter {}", filters.join(" AND "))
+            }
+            Self::HashJoin {
+                lhs_key,
+                rhs_key,
+                join_type,
+                first_match,
+                null_keys_match,
+            } => {
+                write!(f, "HashJoin {lhs_key} = {rhs_key}")?;
+                match join_type {
+                    JoinType::Inner => {}
+                    JoinType::Left => write!(f, " (left outer)")?,
+                    JoinType::Right => write!(f, " (right outer)")?,
+                    JoinType::Full => write!(f, " (full outer)")?,
+                }
+                if *first_match {
+                    write!(f, " (first match)")?;
+                }
+                if *null_keys_match {
+                    write!(f, " (NULL keys match)")?;
+                }
+                Ok(())
+            }
+            Self::Compute { calls, filters } => {
+                let calls: Vec<String> = calls.iter().map(ToString::to_string).collect();
+                write!(f, "Compute {}", calls.join(", "))?;
+                if !filters.is_empty() {
+                    let filters: Vec<String> = filters.iter().map(ToString::to_string).collect();
+                    write!(f, " WHERE {}", filters.join(" AND "))?;
+                }
+                Ok(())
+            }
+            Self::Distinct => write!(f, "Distinct"),
+            Self::DistinctValues { column } => write!(f, "Distinct {column}"),
+            Self::Sort { keys } => {
+                let keys: Vec<String> = keys
+                    .iter()
+                    .map(|key| {
+                        if key.descending {
+                            format!("{} DESC", key.column)
+                        } else {
+                            key.column.clone()
+                        }
+                    })
+                    .collect();
+                write!(f, "Sort {}", keys.join(", "))
+            }
+            Self::Limit { limit } => write!(f, "Limit {limit}"),
+        }
+    }
+}
+
+// Rows estimated to pass filters with the given comparisons.
+fn filtered_rows(rows: usize, ops: impl Iterator<Item = CompareOp>) -> usize {
+    ops.fold(rows, |rows, op| match op {
+        CompareOp::Eq => rows.div_ceil(10),
+        CompareOp::Ne => rows - rows / 10,
+        CompareOp::Lt | CompareOp::Le | CompareOp::Gt | CompareOp::Ge => rows.div_ceil(3),
+    })
+}
+
+///
+/// Physical plan tree with row count estimates. This is what gets executed (see
+/// `SelectQueryExecutor`) and what EXPLAIN shows.
+///
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct QueryPlan {
+    pub node: PlanNode,
+    pub estimated_rows: usize,
+    // Measured by EXPLAIN ANALYZE.
+    pub runtime: Option<RuntimeStats>,
+    pub children: Vec<Self>,
+}
+
+impl QueryPlan {
+    ///
+    /// Tables read by the plan, in plan order.
+    ///
+    #[must_use]
+    pub fn tables(&self) -> Vec<&str> {
+        match &self.node {
+            PlanNode::Scan { table, .. }
+            | PlanNode::IndexScan { table, .. }
+            | PlanNode::IndexDistinct { table, .. } => vec![table.as_str()],
+            _ => self.children.iter().flat_map(Self::tables).collect(),
+        }
+    }
+
+    #[must_use]
+    pub const fn leaf(node: PlanNode, estimated_rows: usize) -> Self {
+        Self {
+            node,
+            estimated_rows,
+            runtime: None,
+            children: vec![],
+        }
+    }
+
+    ///
+    /// Without column statistics every equality is assumed to keep 1/10 of the rows (and every
+    /// not-equal the rest), and every range 1/3 of them.
+    ///
+    #[must_use]
+    pub fn filter(input: Self, filters: Vec<RowFilter>) -> Self {
+        let estimated_rows =
+            filtered_rows(input.estimated_rows, filters.iter().map(|filter| filter.op));
+
+        Self {
+            node: PlanNode::Filter { filters },
+            estimated_rows,
+            runtime: None,
+            children: vec![input],
+        }
+    }
+
+    ///
+    /// Filters on computed values are estimated like filters on fields.
+    ///
+    #[must_use]
+    pub fn compute(input: Self, calls: Vec<ScalarCall>, filters: Vec<CallFilter>) -> Self {
+        let estimated_rows =
+            filtered_rows(input.estimated_rows, filters.iter().map(|filter| filter.op));
+
+        Self {
+            node: PlanNode::Compute { calls, filters },
+            estimated_rows,
+            runtime: None,
+            children: vec![input],
+        }
+    }
+
+    ///
+    /// Joins are assumed to follow a foreign key, matching each row of the bigger side once. First
+    /// match joins keep at most the left rows (and left joins at least them), unless right rows
+    /// are kept unmatched.
+    ///
+    #[must_use]
+    pub fn hash_join(
+        lhs: Self,
+        rhs: Self,
+        lhs_key: String,
+        rhs_key: String,
+        join_type: JoinType,
+        first_match: bool,
+        null_keys_match: bool,
+    ) -> Self {
+        let estimated_rows = if first_match && !join_type.keeps_unmatched_rhs() {
+            lhs.estimated_rows
+        } else {
+            lhs.estimated_rows.max(rhs.estimated_rows)
+        };
+
+        Self {
+            node: PlanNode::HashJoin {
+                lhs_key,
+                rhs_key,
+                join_type,
+                first_match,
+                null_keys_match,
+            },
+            estimated_rows,
+            runtime: None,
+            children: vec![lhs, rhs],
+        }
+    }
+
+    ///
+    /// Without statistics on the combined columns no row is assumed to be a duplicate.
+    ///
+    #[must_use]
+    pub fn distinct(input: Self) -> Self {
+        Self {
+            node: PlanNode::Distinct,
+            estimated_rows: input.estimated_rows,
+            runtime: None,
+            children: vec![input],
+        }
+    }
+
+    #[must_use]
+    pub fn distinct_values(input: Self, column: String) -> Self {
+        Self {
+            node: PlanNode::DistinctValues { column },
+            estimated_rows: input.estimated_rows,
+            runtime: None,
+            children: vec![input],
+        }
+    }
+
+    #[must_use]
+    pub fn sort(input: Self, keys: Vec<SortKey>) -> Self {
+        Self {
+            node: PlanNode::Sort { keys },
+            estimated_rows: input.estimated_rows,
+            runtime: None,
+            children: vec![input],
+        }
+    }
+
+    ///
+    /// A limit right above an index scan (no filters left in between) narrows the scan to its
+    /// first `limit` entries (its last ones when descending): no more of the index is read than
+    /// the limit lets through.
+    ///
+    #[must_use]
+    pub fn limit(mut input: Self, limit: usize) -> Self {
+        if let PlanNode::IndexScan {
+            ranges,
+            delta_rows,
+            descending,
+            ..
+        } = &mut input.node
+        {
+            // The first entries in reading order are among the first of both the sorted ranges
+            // and the (sorted) delta.
+            let mut entries_left = i32::try_from(limit).unwrap_or(i32::MAX);
+            let narrow = |(lhs_idx, rhs_idx): &mut (i32, i32)| {
+                let is_kept = entries_left > 0;
+                if *descending {
+                    *lhs_idx =
+                        (*lhs_idx).max(rhs_idx.saturating_sub(1).saturating_sub(entries_left));
+                } else {
+                    *rhs_idx =
+                        (*rhs_idx).min(lhs_idx.saturating_add(1).saturating_add(entries_left));
+                }
+                entries_left -= *rhs_idx - *lhs_idx - 1;
+                is_kept
+            };
+            if *descending {
+                ranges.reverse();
+                ranges.retain_mut(narrow);
+                ranges.reverse();
+                delta_rows.drain(..delta_rows.len().saturating_sub(limit));
+            } else {
+                ranges.retain_mut(narrow);
+                delta_rows.truncate(limit);
+            }
+            input.estimated_rows = input.estimated_rows.min(limit);
+        }
+
+        Self {
+            node: PlanNode::Limit { limit },
+            estimated_rows: input.estimated_rows.min(limit),
+            runtime: None,
+            children: vec![input],
+        }
+    }
+
+    ///
+    /// Attaches measured stats, given in post-order (children first, left to right, then the
+    /// node itself) as the executor builds the operator tree.
+    ///
+    pub fn set_runtime_stats<I>(&mut self, stats: &mut I)
+    where
+        I: Iterator<Item = RuntimeStats>,
+    {
+        for child in &mut self.children {
+            child.set_runtime_stats(stats);
+        }
+        self.runtime = stats.next();
+    }
+
+    fn stats_label(&self) -> String {
+        self.runtime.map_or_else(
+            || format!("rows: {}", self.estimated_rows),
+            |runtime| {
+                format!(
+                    "rows: {}, actual rows: {}, time: {:?}",
+                    self.estimated_rows, runtime.rows, runtime.elapsed
+                )
+            },
+        )
+    }
+
+    ///
+    /// Graphviz representation. Render with: `dot -Tpng plan.dot -o plan.png`.
+    ///
+    #[must_use]
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph plan {\n    node [shape=box];\n");
+        self.write_dot(&mut out, &mut 0);
+        out.push_str("}\n");
+        out
+    }
+
+    fn write_dot(&self, out: &mut String, next_id: &mut usize) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+
+        let label = self.node.to_string().replace('"', "\\\"");
+        let _ = writeln!(
+            out,
+            "    n{id} [label=\"{label}\\n{}\"];",
+            self.stats_label()
+        );
+        for child in &self.children {
+            let child_id = child.write_dot(out, next_id);
+            let _ = writeln!(out, "    n{id} -> n{child_id};");
+        }
+
+        id
+    }
+
+    ///
+    /// Indented tree, one operator per line, root first:
+    ///
+    /// ```text
+    /// HashJoin t1.id = t2.t1_id (rows: 4)
+    /// ├── Scan t1 (rows: 4)
+    /// └── Scan t2 (rows: 4)
+    /// ```
+    ///
+    #[must_use]
+    pub fn to_ascii_tree(&self) -> String {
+        let mut out = String::new();
+        self.write_ascii_tree(&mut out, "", "");
+        out
+    }
+
+    fn write_ascii_tree(&self, out: &mut String, prefix: &str, child_prefix: &str) {
+        let _ = writeln!(out, "{prefix}{} ({})", self.node, self.stats_label());
+
+        for (i, child) in self.children.iter().enumerate() {
+            if i + 1 == self.children.len() {
+                child.write_ascii_tree(
+                    out,
+                    &format!("{child_prefix}└── "),
+                    &format!("{child_prefix}    "),
+                );
+            } else {
+                child.write_ascii_tree(
+                    out,
+                    &format!("{child_prefix}├── "),
+                    &format!("{child_prefix}│   "),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::{
+        operator::RuntimeStats,
+        query::{
+            CompareOp, FieldSelector, JoinContract, JoinType, RhsValue, RowFilter, SelectQuery,
+        },
+        value::Value,
+    };
+
+    use super::{
+        ConstantFolding, FilterPushdown, JoinReordering, LogicalPlan, Optimizer, PlanNode,
+        QueryPlan, QueryStats, RewriteRule,
+    };
+
+    fn field(source: &str, name: &str) -> FieldSelector {
+        FieldSelector {
+            name: name.into(),
+            source: source.into(),
+        }
+    }
+
+    fn value_filter(source: &str, name: &str, value: i32) -> RowFilter {
+        RowFilter {
+            field: field(source, name),
+            op: CompareOp::Eq,
+            rhs: RhsValue::Value(Value::I32(value)),
+        }
+    }
+
+    fn join(lhs_source: &str, rhs_source: &str) -> JoinContract {
+        JoinContract {
+            join_type: JoinType::Inner,
+            null_keys_match: false,
+            lhs: field(lhs_source, "id"),
+            rhs: field(rhs_source, format!("{lhs_source}_id").as_str()),
+        }
+    }
+
+    fn scan(table: &str) -> LogicalPlan {
+        LogicalPlan::Scan {
+            table: table.into(),
+        }
+    }
+
+    #[test]
+    fn test_from_select_query() {
+        let query = SelectQuery {
+            from: "t1".into(),
+            joins: vec![join("t1", "t2")],
+            filters: vec![value_filter("t2", "a", 1)],
+            limit: Some(3),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            LogicalPlan::Limit {
+                input: Box::new(LogicalPlan::Filter {
+                    input: Box::new(LogicalPlan::Join {
+                        lhs: Box::new(scan("t1")),
+                        rhs: Box::new(scan("t2")),
+                        contract: join("t1", "t2"),
+                    }),
+                    filters: vec![value_filter("t2", "a", 1)],
+                }),
+                limit: 3,
+            },
+            LogicalPlan::from(&query)
+        );
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        let filter = |op| RowFilter {
+            field: field("t1", "a"),
+            op,
+            rhs: RhsValue::Ref(field("t1", "a")),
+        };
+        let plan = |op| LogicalPlan::Filter {
+            input: Box::new(scan("t1")),
+            filters: vec![filter(op), value_filter("t1", "b", 1)],
+        };
+
+        assert_eq!(
+            LogicalPlan::Filter {
+                input: Box::new(scan("t1")),
+                filters: vec![value_filter("t1", "b", 1)],
+            },
+            ConstantFolding.rewrite(plan(CompareOp::Eq))
+        );
+        assert_eq!(
+            ConstantFolding.rewrite(plan(CompareOp::Eq)),
+            ConstantFolding.rewrite(plan(CompareOp::Ge))
+        );
+        assert_eq!(
+            LogicalPlan::Empty,
+            ConstantFolding.rewrite(plan(CompareOp::Ne))
+        );
+
+        assert_eq!(
+            LogicalPlan::Empty,
+            ConstantFolding.rewrite(LogicalPlan::Join {
+                lhs: Box::new(plan(CompareOp::Lt)),
+                rhs: Box::new(scan("t2")),
+                contract: join("t1", "t2"),
+            })
+        );
+    }
+
+    #[test]
+    fn test_constant_folding_value_bounds() {
+        let bound = |op, value| RowFilter {
+            op,
+            ..value_filter("t1", "a", value)
+        };
+        let fold = |filters: Vec<RowFilter>| {
+            ConstantFolding.rewrite(LogicalPlan::Filter {
+                input: Box::new(scan("t1")),
+                filters,
+            })
+        };
+        let filtered = |filters| LogicalPlan::Filter {
+            input: Box::new(scan("t1")),
+            filters,
+        };
+
+        // Implied bounds are dropped, other fields are untouched.
+        assert_eq!(
+            filtered(vec![
+                bound(CompareOp::Gt, 5),
+                value_filter("t1", "b", 1),
+                bound(CompareOp::Lt, 9),
+            ]),
+            fold(vec![
+                bound(CompareOp::Gt, 3),
+                bound(CompareOp::Gt, 5),
+                value_filter("t1", "b", 1),
+                bound(CompareOp::Lt, 9),
+                bound(CompareOp::Gt, 5),
+            ])
+        );
+        assert_eq!(
+            filtered(vec![bound(CompareOp::Eq, 4)]),
+            fold(vec![bound(CompareOp::Lt, 9), bound(CompareOp::Eq, 4)])
+        );
+        // Exclusive bounds are tighter than inclusive ones at the same value, not-equal filters
+        // are kept.
+        assert_eq!(
+            filtered(vec![
+                bound(CompareOp::Ne, 6),
+                bound(CompareOp::Gt, 5),
+                bound(CompareOp::Le, 9),
+            ]),
+            fold(vec![
+                bound(CompareOp::Ge, 5),
+                bound(CompareOp::Ne, 6),
+                bound(CompareOp::Gt, 5),
+                bound(CompareOp::Le, 9),
+                bound(CompareOp::Lt, 10),
+            ])
+        );
+        assert_eq!(
+            filtered(vec![bound(CompareOp::Eq, 5)]),
+            fold(vec![bound(CompareOp::Ge, 5), bound(CompareOp::Eq, 5)])
+        );
+        assert_eq!(
+            filtered(vec![bound(CompareOp::Ge, 5), bound(CompareOp::Le, 5)]),
+            fold(vec![bound(CompareOp::Ge, 5), bound(CompareOp::Le, 5)])
+        );
+
+        // Contradictions.
+        for filters in [
+            vec![bound(CompareOp::Gt, 5), bound(CompareOp::Lt, 3)],
+            vec![bound(CompareOp::Gt, 5), bound(CompareOp::Lt, 5)],
+            vec![bound(CompareOp::Eq, 1), bound(CompareOp::Eq, 2)],
+            vec![bound(CompareOp::Eq, 1), bound(CompareOp::Gt, 1)],
+            vec![bound(CompareOp::Ge, 5), bound(CompareOp::Lt, 5)],
+            vec![bound(CompareOp::Ge, 6), bound(CompareOp::Le, 5)],
+            vec![RowFilter {
+                rhs: RhsValue::Value(Value::NULL),
+                ..bound(CompareOp::Lt, 0)
+            }],
+            vec![RowFilter {
+                rhs: RhsValue::In(vec![]),
+                ..bound(CompareOp::Eq, 0)
+            }],
+            vec![RowFilter {
+                rhs: RhsValue::Range {
+                    low: Value::I32(5),
+                    high: Value::I32(3),
+                    inclusive: true,
+                },
+                ..bound(CompareOp::Eq, 0)
+            }],
+            vec![RowFilter {
+                rhs: RhsValue::Range {
+                    low: Value::I32(5),
+                    high: Value::I32(5),
+                    inclusive: false,
+                },
+                ..bound(CompareOp::Eq, 0)
+            }],
+        ] {
+            assert_eq!(LogicalPlan::Empty, fold(filters));
+        }
+
+        // Values of different types are not compared.
+        let mixed = vec![
+            bound(CompareOp::Gt, 5),
+            RowFilter {
+                rhs: RhsValue::Value(Value::U8(3)),
+                ..bound(CompareOp::Lt, 0)
+            },
+        ];
+        assert_eq!(filtered(mixed.clone()), fold(mixed));
+    }
+
+    #[test]
+    fn test_filter_pushdown() {
+        let cross_filter = RowFilter {
+            field: field("t1", "a"),
+            op: CompareOp::Lt,
+            rhs: RhsValue::Ref(field("t2", "a")),
+        };
+        let plan = LogicalPlan::Filter {
+            input: Box::new(LogicalPlan::Join {
+                lhs: Box::new(scan("t1")),
+                rhs: Box::new(scan("t2")),
+                contract: join("t1", "t2"),
+            }),
+            filters: vec![
+                value_filter("t1", "a", 1),
+                cross_filter.clone(),
+                value_filter("t2", "b", 2),
+            ],
+        };
+
+        assert_eq!(
+            LogicalPlan::Filter {
+                input: Box::new(LogicalPlan::Join {
+                    lhs: Box::new(LogicalPlan::Filter {
+                        input: Box::new(scan("t1")),
+                        filters: vec![value_filter("t1", "a", 1)],
+                    }),
+                    rhs: Box::new(LogicalPlan::Filter {
+                        input: Box::new(scan("t2")),
+                        filters: vec![value_filter("t2", "b", 2)],
+                    }),
+                    contract: join("t1", "t2"),
+                }),
+                filters: vec![cross_filter],
+            },
+            FilterPushdown.rewrite(plan)
+        );
+    }
+
+    #[test]
+    fn test_left_join_filters() {
+        let left_join = JoinContract {
+            join_type: JoinType::Left,
+            ..join("t1", "t2")
+        };
+        let plan = |rhs| LogicalPlan::Filter {
+            input: Box::new(LogicalPlan::Join {
+                lhs: Box::new(scan("t1")),
+                rhs: Box::new(rhs),
+                contract: left_join.clone(),
+            }),
+            filters: vec![value_filter("t1", "a", 1), value_filter("t2", "b", 2)],
+        };
+
+        // Right side filters would keep the left rows they reject.
+        assert_eq!(
+            LogicalPlan::Filter {
+                input: Box::new(LogicalPlan::Join {
+                    lhs: Box::new(LogicalPlan::Filter {
+                        input: Box::new(scan("t1")),
+                        filters: vec![value_filter("t1", "a", 1)],
+                    }),
+                    rhs: Box::new(scan("t2")),
+                    contract: left_join.clone(),
+                }),
+                filters: vec![value_filter("t2", "b", 2)],
+            },
+            FilterPushdown.rewrite(plan(scan("t2")))
+        );
+
+        // Scan filters stay below the join.
+        let scan_filtered = LogicalPlan::Join {
+            lhs: Box::new(scan("t1")),
+            rhs: Box::new(scan("t2")),
+            contract: left_join.clone(),
+        }
+        .filter_scans(&[value_filter("t2", "c", 0)]);
+        assert_eq!(
+            LogicalPlan::Join {
+                lhs: Box::new(scan("t1")),
+                rhs: Box::new(LogicalPlan::Filter {
+                    input: Box::new(scan("t2")),
+                    filters: vec![value_filter("t2", "c", 0)],
+                }),
+                contract: left_join.clone(),
+            },
+            FilterPushdown.rewrite(scan_filtered)
+        );
+
+        // No right rows leave the left ones.
+        let folded = ConstantFolding.rewrite(plan(LogicalPlan::Empty));
+        assert!(
+            matches!(&folded, LogicalPlan::Filter { input, .. } if matches!(input.as_ref(), LogicalPlan::Join { .. })),
+            "{folded:?}"
+        );
+    }
+
+    #[test]
+    fn test_join_reordering() {
+        // t1 JOIN t2 JOIN t3 (filtered) JOIN t4 (joined on t3, filtered)
+        let filtered = |table: &str| {
+            Box::new(LogicalPlan::Filter {
+                input: Box::new(scan(table)),
+                filters: vec![value_filter(table, "a", 1)],
+            })
+        };
+        let plan = LogicalPlan::Join {
+            lhs: Box::new(LogicalPlan::Join {
+                lhs: Box::new(LogicalPlan::Join {
+                    lhs: Box::new(scan("t1")),
+                    rhs: Box::new(scan("t2")),
+                    contract: join("t1", "t2"),
+                }),
+                rhs: filtered("t3"),
+                contract: join("t1", "t3"),
+            }),
+            rhs: filtered("t4"),
+            contract: join("t3", "t4"),
+        };
+
+        assert_eq!(
+            LogicalPlan::Join {
+                lhs: Box::new(LogicalPlan::Join {
+                    lhs: Box::new(LogicalPlan::Join {
+                        lhs: Box::new(scan("t1")),
+                        rhs: filtered("t3"),
+                        contract: join("t1", "t3"),
+                    }),
+                    rhs: filtered("t4"),
+                    contract: join("t3", "t4"),
+                }),
+                rhs: Box::new(scan("t2")),
+                contract: join("t1", "t2"),
+            },
+            JoinReordering.rewrite(plan)
+        );
+    }
+
+    #[test]
+    fn test_right_and_full_join_rewrites() {
+        let outer_join = |join_type| JoinContract {
+            join_type,
+            ..join("t1", "t2")
+        };
+        let plan = |join_type, lhs| LogicalPlan::Filter {
+            input: Box::new(LogicalPlan::Join {
+                lhs: Box::new(lhs),
+                rhs: Box::new(scan("t2")),
+                contract: outer_join(join_type),
+            }),
+            filters: vec![value_filter("t1", "a", 1), value_filter("t2", "b", 2)],
+        };
+
+        // Only the side not filled with NULLs is filtered below the join.
+        assert_eq!(
+            LogicalPlan::Filter {
+                input: Box::new(LogicalPlan::Join {
+                    lhs: Box::new(scan("t1")),
+                    rhs: Box::new(LogicalPlan::Filter {
+                        input: Box::new(scan("t2")),
+                        filters: vec![value_filter("t2", "b", 2)],
+                    }),
+                    contract: outer_join(JoinType::Right),
+                }),
+                filters: vec![value_filter("t1", "a", 1)],
+            },
+            FilterPushdown.rewrite(plan(JoinType::Right, scan("t1")))
+        );
+        assert_eq!(
+            plan(JoinType::Full, scan("t1")),
+            FilterPushdown.rewrite(plan(JoinType::Full, scan("t1")))
+        );
+
+        // No left rows leave the right ones.
+        for join_type in [JoinType::Right, JoinType::Full] {
+            let folded = ConstantFolding.rewrite(plan(join_type, LogicalPlan::Empty));
+            assert!(
+                matches!(&folded, LogicalPlan::Filter { input, .. } if matches!(input.as_ref(), LogicalPlan::Join { .. })),
+                "{folded:?}"
+            );
+        }
+
+        // t1 RIGHT JOIN t2 JOIN t3 (filtered) keeps its order.
+        let chain = LogicalPlan::Join {
+            lhs: Box::new(LogicalPlan::Join {
+                lhs: Box::new(scan("t1")),
+                rhs: Box::new(scan("t2")),
+                contract: outer_join(JoinType::Right),
+            }),
+            rhs: Box::new(LogicalPlan::Filter {
+                input: Box::new(scan("t3")),
+                filters: vec![value_filter("t3", "a", 1)],
+            }),
+            contract: join("t1", "t3"),
+        };
+        assert_eq!(chain.clone(), JoinReordering.rewrite(chain));
+    }
+
+    #[test]
+    fn test_optimizer_pushes_filters_before_reordering() {
+        let query = SelectQuery {
+            from: "t1".into(),
+            joins: vec![join("t1", "t2"), join("t1", "t3")],
+            filters: vec![value_filter("t3", "a", 1)],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            LogicalPlan::Join {
+                lhs: Box::new(LogicalPlan::Join {
+                    lhs: Box::new(scan("t1")),
+                    rhs: Box::new(LogicalPlan::Filter {
+                        input: Box::new(scan("t3")),
+                        filters: vec![value_filter("t3", "a", 1)],
+                    }),
+                    contract: join("t1", "t3"),
+                }),
+                rhs: Box::new(scan("t2")),
+                contract: join("t1", "t2"),
+            },
+            Optimizer::default().optimize(LogicalPlan::from(&query))
+        );
+    }
+
+    fn example_query_plan() -> QueryPlan {
+        QueryPlan::limit(
+            QueryPlan::hash_join(
+                QueryPlan::leaf(
+                    PlanNode::IndexScan {
+                        table: "t1".into(),
+                        index: "idx".into(),
+                        ranges: vec![(-1, 5)],
+                        delta_rows: vec![],
+                        descending: false,
+                    },
+                    5,
+                ),
+                QueryPlan::filter(
+                    QueryPlan::leaf(
+                        PlanNode::Scan {
+                            table: "t2".into(),
+                            sample: None,
+                        },
+                        100,
+                    ),
+                    vec![value_filter("t2", "a", 1)],
+                ),
+                "t1.id".into(),
+                "t2.t1_id".into(),
+                JoinType::Inner,
+                false,
+                false,
+            ),
+            3,
+        )
+    }
+
+    #[test]
+    fn test_query_plan_ascii_tree() {
+        assert_eq!(
+            "Limit 3 (rows: 3)\n\
+             └── HashJoin t1.id = t2.t1_id (rows: 10)\n\
+             \x20   ├── IndexScan t1 using idx (rows: 5)\n\
+             \x20   └── Filter t2.a = 1 (rows: 10)\n\
+             \x20       └── Scan t2 (rows: 100)\n",
+            example_query_plan().to_ascii_tree()
+        );
+    }
+
+    #[test]
+    fn test_query_plan_dot() {
+        assert_eq!(
+            "digraph plan {\n\
+             \x20   node [shape=box];\n\
+             \x20   n0 [label=\"Limit 3\\nrows: 3\"];\n\
+             \x20   n1 [label=\"HashJoin t1.id = t2.t1_id\\nrows: 10\"];\n\
+             \x20   n2 [label=\"IndexScan t1 using idx\\nrows: 5\"];\n\
+             \x20   n1 -> n2;\n\
+             \x20   n3 [label=\"Filter t2.a = 1\\nrows: 10\"];\n\
+             \x20   n4 [label=\"Scan t2\\nrows: 100\"];\n\
+             \x20   n3 -> n4;\n\
+             \x20   n1 -> n3;\n\
+             \x20   n0 -> n1;\n\
+             }\n",
+            example_query_plan().to_dot()
+        );
+    }
+
+    #[test]
+    fn test_query_stats() {
+        let mut plan = example_query_plan();
+        // Post-order: index scan, scan, filter, join, limit.
+        plan.set_runtime_stats(&mut [4, 50, 8, 8, 3].into_iter().map(|rows| RuntimeStats {
+            rows,
+            ..Default::default()
+        }));
+
+        let stats = QueryStats::of_plan(&plan, 3, Duration::from_micros(1500));
+        assert_eq!(vec!["idx".to_string()], stats.indices);
+        assert_eq!(54, stats.scanned_rows);
+        assert_eq!(
+            "3 rows in 1.500 ms (index: idx, scanned 54 rows)",
+            stats.to_string()
+        );
+
+        let stats = QueryStats {
+            rows: 1,
+            row_cache_hit: true,
+            ..Default::default()
+        };
+        assert_eq!("1 row in 0.000 ms (row cache)", stats.to_string());
+    }
+
+    #[test]
+    fn test_limit_narrows_index_scan() {
+        let index_scan = |ranges: Vec<(i32, i32)>, delta_rows: Vec<u64>, descending| {
+            let estimated_rows = ranges
+                .iter()
+                .map(|(lhs_idx, rhs_idx)| usize::try_from(rhs_idx - lhs_idx - 1).unwrap())
+                .sum::<usize>()
+                + delta_rows.len();
+            QueryPlan::leaf(
+                PlanNode::IndexScan {
+                    table: "t1".into(),
+                    index: "idx".into(),
+                    ranges,
+                    delta_rows,
+                    descending,
+                },
+                estimated_rows,
+            )
+        };
+
+        let plan = QueryPlan::limit(index_scan(vec![(3, 40)], vec![8, 16, 24], false), 2);
+        assert_eq!(
+            index_scan(vec![(3, 6)], vec![8, 16], false).node,
+            plan.children[0].node
+        );
+        assert_eq!(2, plan.children[0].estimated_rows);
+
+        // The ranges of IN list probes are taken in order until the limit.
+        let plan = QueryPlan::limit(
+            index_scan(vec![(3, 6), (10, 20), (30, 40)], vec![], false),
+            4,
+        );
+        assert_eq!(
+            index_scan(vec![(3, 6), (10, 13)], vec![], false).node,
+            plan.children[0].node
+        );
+
+        // Descending scans keep their last entries.
+        let plan = QueryPlan::limit(
+            index_scan(vec![(3, 6), (10, 20), (30, 40)], vec![8, 16, 24], true),
+            12,
+        );
+        assert_eq!(
+            index_scan(vec![(16, 20), (30, 40)], vec![8, 16, 24], true).node,
+            plan.children[0].node
+        );
+        let plan = QueryPlan::limit(index_scan(vec![(3, 40)], vec![8, 16, 24], true), 2);
+        assert_eq!(
+            index_scan(vec![(37, 40)], vec![16, 24], true).node,
+            plan.children[0].node
+        );
+
+        // Ranges shorter than the limit are kept.
+        let plan = QueryPlan::limit(index_scan(vec![(-1, 2)], vec![], false), 10);
+        assert_eq!(index_scan(vec![(-1, 2)], vec![], false), plan.children[0]);
+
+        // Filters in between may drop rows, the scan is read in full.
+        let plan = QueryPlan::limit(
+            QueryPlan::filter(
+                index_scan(vec![(3, 40)], vec![], false),
+                vec![value_filter("t1", "a", 1)],
+            ),
+            2,
+        );
+        assert_eq!(
+            index_scan(vec![(3, 40)], vec![], false),
+            plan.children[0].children[0]
+        );
+    }
+}
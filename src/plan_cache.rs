@@ -0,0 +1,468 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering as AtomicOrdering},
+        Mutex,
+    },
+};
+
+use crate::{
+    common::{Error, PBaseError},
+    lexer::Token,
+    parser::{LiteralSlot, Parser},
+    plan::LogicalPlan,
+    query::{Query, RhsValue, SampleSpec, SelectQuery, SettingValue},
+    schema::{DefaultExpr, FieldSchema},
+    value::{Interval, Value},
+};
+
+// Statements (and, separately, logical plans) a plan cache keeps by default.
+pub const PLAN_CACHE_ENTRIES: usize = 256;
+
+///
+/// The normalized text of a statement, its shape: its tokens with the literals replaced by `?`.
+/// Statements differing only in literals or whitespace have the same shape.
+///
+#[must_use]
+pub fn statement_shape(tokens: &[Token]) -> String {
+    tokens
+        .iter()
+        .map(|token| match token {
+            Token::Int(_) => "?".to_string(),
+            token => format!("{token:?}"),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+///
+/// Counters of a plan cache, see `PlanCache::stats`.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PlanCacheStats {
+    // Statements whose shape was parsed before.
+    pub statement_hits: u64,
+    pub statement_misses: u64,
+    // Selects whose logical plan was optimized before.
+    pub plan_hits: u64,
+    pub plan_misses: u64,
+    pub statements: usize,
+    pub plans: usize,
+}
+
+#[derive(Debug)]
+struct CachedStatement {
+    shape: String,
+    // The first statement of the shape parsed, its literals are overwritten on reuse.
+    query: Query,
+    literal_slots: Vec<LiteralSlot>,
+}
+
+///
+/// LRU caches of the work done before running a statement, for applications running the same
+/// statements over and over:
+/// - parsed statements, by shape (see `statement_shape`): a statement of a known shape gets the
+///   query parsed before with its own literals,
+/// - optimized logical plans of selects, by query. The sample size is left out, as it is not part
+///   of the logical plan, so selects parsed from one shape share their plan.
+///
+/// Logical plans depend on the schemas of the tables read: the cache is cleared on schema changes
+/// (see `clear`).
+///
+#[derive(Debug)]
+pub struct PlanCache {
+    capacity: usize,
+    // Most recently used first.
+    statements: Mutex<VecDeque<CachedStatement>>,
+    plans: Mutex<VecDeque<(SelectQuery, LogicalPlan)>>,
+    statement_hits: AtomicU64,
+    statement_misses: AtomicU64,
+    plan_hits: AtomicU64,
+    plan_misses: AtomicU64,
+}
+
+impl PlanCache {
+    #[must_use]
+    pub const fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            statements: Mutex::new(VecDeque::new()),
+            plans: Mutex::new(VecDeque::new()),
+            statement_hits: AtomicU64::new(0),
+            statement_misses: AtomicU64::new(0),
+            plan_hits: AtomicU64::new(0),
+            plan_misses: AtomicU64::new(0),
+        }
+    }
+
+    ///
+    /// The statement's query: the cached query of its shape with the statement's literals, or
+    /// parsed (and cached) when the shape is new.
+    ///
+    /// # Errors
+    ///
+    /// When the statement cannot be parsed, or a literal is out of its range (eg. a sample
+    /// percent over 100).
+    ///
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    pub fn statement(&self, tokens: &[Token]) -> Result<Query, Error> {
+        let shape = statement_shape(tokens);
+        let literals: Vec<i32> = tokens
+            .iter()
+            .filter_map(|token| match token {
+                Token::Int(literal) => Some(*literal),
+                _ => None,
+            })
+            .collect();
+
+        let mut statements = self.statements.lock().unwrap();
+        if let Some(cache_pos) = statements.iter().position(|cached| cached.shape == shape) {
+            let cached = statements.remove(cache_pos).unwrap();
+            let mut query = cached.query.clone();
+            let literal_slots = cached.literal_slots.clone();
+            statements.push_front(cached);
+            drop(statements);
+            self.statement_hits.fetch_add(1, AtomicOrdering::Relaxed);
+
+            bind_literals(&mut query, &literal_slots, &literals)?;
+            return Ok(query);
+        }
+        drop(statements);
+        self.statement_misses.fetch_add(1, AtomicOrdering::Relaxed);
+
+        let mut parser = Parser::new(tokens);
+        let query = parser.parse()?;
+        if self.capacity > 0 {
+            let mut statements = self.statements.lock().unwrap();
+            statements.truncate(self.capacity - 1);
+            statements.push_front(CachedStatement {
+                shape,
+                query: query.clone(),
+                literal_slots: parser.literal_slots().to_vec(),
+            });
+        }
+
+        Ok(query)
+    }
+
+    ///
+    /// The logical plan of the select, built with `plan` (and cached) when not cached yet.
+    ///
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    pub fn logical_plan<F>(&self, query: &SelectQuery, plan: F) -> LogicalPlan
+    where
+        F: FnOnce() -> LogicalPlan,
+    {
+        let key = SelectQuery {
+            sample: None,
+            ..query.clone()
+        };
+
+        let mut plans = self.plans.lock().unwrap();
+        if let Some(cache_pos) = plans.iter().position(|(cached_key, _)| *cached_key == key) {
+            let entry = plans.remove(cache_pos).unwrap();
+            let logical_plan = entry.1.clone();
+            plans.push_front(entry);
+            drop(plans);
+            self.plan_hits.fetch_add(1, AtomicOrdering::Relaxed);
+            return logical_plan;
+        }
+        drop(plans);
+        self.plan_misses.fetch_add(1, AtomicOrdering::Relaxed);
+
+        let logical_plan = plan();
+        if self.capacity > 0 {
+            let mut plans = self.plans.lock().unwrap();
+            plans.truncate(self.capacity - 1);
+            plans.push_front((key, logical_plan.clone()));
+        }
+
+        logical_plan
+    }
+
+    ///
+    /// Drops every cached statement and plan. The counters are kept.
+    ///
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    pub fn clear(&self) {
+        self.statements.lock().unwrap().clear();
+        self.plans.lock().unwrap().clear();
+    }
+
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    #[must_use]
+    pub fn stats(&self) -> PlanCacheStats {
+        PlanCacheStats {
+            statement_hits: self.statement_hits.load(AtomicOrdering::Relaxed),
+            statement_misses: self.statement_misses.load(AtomicOrdering::Relaxed),
+            plan_hits: self.plan_hits.load(AtomicOrdering::Relaxed),
+            plan_misses: self.plan_misses.load(AtomicOrdering::Relaxed),
+            statements: self.statements.lock().unwrap().len(),
+            plans: self.plans.lock().unwrap().len(),
+        }
+    }
+}
+
+impl Default for PlanCache {
+    fn default() -> Self {
+        Self::new(PLAN_CACHE_ENTRIES)
+    }
+}
+
+//
+// Writes the literals into the query parsed from a statement of the same shape, checking them like
+// the parser does.
+//
+fn bind_literals(
+    query: &mut Query,
+    literal_slots: &[LiteralSlot],
+    literals: &[i32],
+) -> Result<(), Error> {
+    for (literal_slot, literal) in literal_slots.iter().zip(literals) {
+        match (literal_slot, &mut *query) {
+            (LiteralSlot::InsertValue(field), Query::Insert(insert_statement)) => {
+                insert_statement
+                    .query
+                    .values
+                    .insert(field.clone(), Value::I32(*literal));
+            }
+            (LiteralSlot::SettingValue, Query::Set(set_query)) => {
+                set_query.value = SettingValue::Int(*literal);
+            }
+            (LiteralSlot::StorageOption(option), Query::CreateTable(create_table_query)) => {
+                create_table_query
+                    .schema
+                    .storage
+                    .set(option, &SettingValue::Int(*literal))?;
+            }
+            (LiteralSlot::DefaultValue(field), Query::CreateTable(create_table_query)) => {
+                if let Some(DefaultExpr::Value(value) | DefaultExpr::Scalar { arg: value, .. }) =
+                    create_table_query.schema.defaults.get_mut(field)
+                {
+                    *value = Value::I32(*literal);
+                }
+            }
+            (
+                LiteralSlot::DecimalPrecision(field) | LiteralSlot::DecimalScale(field),
+                Query::CreateTable(create_table_query),
+            ) => {
+                let arg = u8::try_from(*literal).map_err(|_| {
+                    PBaseError::InvalidArgument(format!(
+                        "expected type argument between 0 and 255, got {literal}"
+                    ))
+                })?;
+                if let Some(FieldSchema::Decimal { precision, scale }) =
+                    create_table_query.schema.fields.get_mut(field)
+                {
+                    if matches!(literal_slot, LiteralSlot::DecimalPrecision(_)) {
+                        *precision = arg;
+                    } else {
+                        *scale = arg;
+                    }
+                }
+            }
+            (LiteralSlot::SampleSize(select_pos), query) => {
+                if let Some(sample) = nth_select(query, *select_pos)
+                    .and_then(|select_query| select_query.sample.as_mut())
+                {
+                    *sample = bind_sample_size(*sample, *literal)?;
+                }
+            }
+            (LiteralSlot::FilterValue(select_pos, filter_pos), query) => {
+                match nth_select(query, *select_pos)
+                    .and_then(|select_query| select_query.filters.get_mut(*filter_pos))
+                    .map(|filter| &mut filter.rhs)
+                {
+                    Some(RhsValue::Value(value)) => *value = Value::I32(*literal),
+                    Some(RhsValue::Interval { interval, .. }) => {
+                        *interval = Interval(i64::from(*literal));
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+// The nth select of a statement (selects of a UNION are counted in order, an EXPLAIN has one).
+fn nth_select(query: &mut Query, select_pos: usize) -> Option<&mut SelectQuery> {
+    match query {
+        Query::Select(select_query) => Some(select_query),
+        Query::Explain(explain_query) => Some(&mut explain_query.select),
+        Query::Union(union_query) => union_query.selects.get_mut(select_pos),
+        _ => None,
+    }
+}
+
+fn bind_sample_size(sample: SampleSpec, literal: i32) -> Result<SampleSpec, Error> {
+    match sample {
+        SampleSpec::First(_) => usize::try_from(literal)
+            .map(SampleSpec::First)
+            .map_err(|_| {
+                PBaseError::InvalidArgument(format!(
+                    "expected non-negative row count, got {literal}"
+                ))
+                .into()
+            }),
+        SampleSpec::Percent(_) => u8::try_from(literal)
+            .ok()
+            .filter(|percent| *percent <= 100)
+            .map(SampleSpec::Percent)
+            .ok_or_else(|| {
+                PBaseError::InvalidArgument(format!(
+                    "expected percent between 0 and 100, got {literal}"
+                ))
+                .into()
+            }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        lexer::Lexer,
+        plan::LogicalPlan,
+        query::{Query, RhsValue, SampleSpec, SelectQuery},
+        schema::FieldSchema,
+        value::{Interval, Value},
+    };
+
+    use super::{statement_shape, PlanCache};
+
+    #[test]
+    fn test_statement_cache() {
+        let plan_cache = PlanCache::new(2);
+        let statement = |raw: &[u8]| plan_cache.statement(&Lexer::tokenize(raw).unwrap());
+
+        let Query::Insert(first) = statement(b"INSERT INTO t1 (a, b) VALUES (1, 2)").unwrap()
+        else {
+            panic!("expected an insert");
+        };
+        let Query::Insert(second) = statement(b"INSERT INTO t1 (a,b)  VALUES (3, 4)").unwrap()
+        else {
+            panic!("expected an insert");
+        };
+        assert_eq!(Value::I32(1), first.query.values["a"]);
+        assert_eq!(Value::I32(3), second.query.values["a"]);
+        assert_eq!(Value::I32(4), second.query.values["b"]);
+
+        let Query::Union(union_query) =
+            statement(b"SELECT FROM t1 TABLESAMPLE 10 UNION SELECT FROM t2 TABLESAMPLE FIRST 5")
+                .unwrap()
+        else {
+            panic!("expected a union");
+        };
+        assert_eq!(Some(SampleSpec::First(5)), union_query.selects[1].sample);
+        let Query::Union(union_query) =
+            statement(b"SELECT FROM t1 TABLESAMPLE 20 UNION SELECT FROM t2 TABLESAMPLE FIRST 7")
+                .unwrap()
+        else {
+            panic!("expected a union");
+        };
+        assert_eq!(Some(SampleSpec::Percent(20)), union_query.selects[0].sample);
+        assert_eq!(Some(SampleSpec::First(7)), union_query.selects[1].sample);
+        assert!(statement(
+            b"SELECT FROM t1 TABLESAMPLE 200 UNION SELECT FROM t2 TABLESAMPLE FIRST 7"
+        )
+        .is_err());
+
+        let stats = plan_cache.stats();
+        assert_eq!(3, stats.statement_hits);
+        assert_eq!(2, stats.statement_misses);
+        assert_eq!(2, stats.statements);
+
+        // Capacity is 2, the insert was the least recently used.
+        statement(b"SELECT FROM t1").unwrap();
+        statement(b"INSERT INTO t1 (a, b) VALUES (5, 6)").unwrap();
+        assert_eq!(4, plan_cache.stats().statement_misses);
+
+        assert!(statement(b"SELECT t1").is_err());
+        plan_cache.clear();
+        assert_eq!(0, plan_cache.stats().statements);
+
+        // Type arguments are bound like the other literals.
+        statement(b"CREATE TABLE t1 (a DECIMAL(10, 2))").unwrap();
+        let Query::CreateTable(create_table_query) =
+            statement(b"CREATE TABLE t1 (a DECIMAL(20, 4))").unwrap()
+        else {
+            panic!("expected a create table");
+        };
+        assert_eq!(
+            FieldSchema::Decimal {
+                precision: 20,
+                scale: 4
+            },
+            create_table_query.schema.fields["a"]
+        );
+        assert!(statement(b"CREATE TABLE t1 (a DECIMAL(10, 300))").is_err());
+
+        // So are filter values and intervals.
+        statement(b"SELECT FROM t1 WHERE t1.a = 1 AND t1.b - t1.a < 10").unwrap();
+        let Query::Select(select_query) =
+            statement(b"SELECT FROM t1 WHERE t1.a = 2 AND t1.b - t1.a < 20").unwrap()
+        else {
+            panic!("expected a select");
+        };
+        assert_eq!(RhsValue::Value(Value::I32(2)), select_query.filters[0].rhs);
+        assert!(matches!(
+            select_query.filters[1].rhs,
+            RhsValue::Interval {
+                interval: Interval(20),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_statement_shape() {
+        let shape = |raw: &[u8]| statement_shape(&Lexer::tokenize(raw).unwrap());
+        assert_eq!(
+            shape(b"SELECT FROM t1 TABLESAMPLE 10"),
+            shape(b"SELECT  FROM t1\nTABLESAMPLE 90")
+        );
+        assert_ne!(
+            shape(b"SELECT FROM t1 TABLESAMPLE 10"),
+            shape(b"SELECT FROM t1 TABLESAMPLE FIRST 10")
+        );
+    }
+
+    #[test]
+    fn test_plan_cache() {
+        let plan_cache = PlanCache::default();
+        let query = SelectQuery {
+            from: "t1".into(),
+            sample: Some(SampleSpec::First(1)),
+            ..Default::default()
+        };
+        let plan = || LogicalPlan::Scan { table: "t1".into() };
+
+        assert_eq!(plan(), plan_cache.logical_plan(&query, plan));
+        let resampled = SelectQuery {
+            sample: None,
+            ..query.clone()
+        };
+        assert_eq!(
+            plan(),
+            plan_cache.logical_plan(&resampled, || panic!("expected a cached plan"))
+        );
+        let limited = SelectQuery {
+            limit: Some(1),
+            ..query
+        };
+        plan_cache.logical_plan(&limited, plan);
+
+        let stats = plan_cache.stats();
+        assert_eq!((1, 2, 2), (stats.plan_hits, stats.plan_misses, stats.plans));
+    }
+}
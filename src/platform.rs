@@ -0,0 +1,281 @@
+use std::{
+    ffi::OsString,
+    fs::{File, OpenOptions, TryLockError},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::common::{Error, PBaseError};
+
+// Attempts of a replace that the destination being open (on Windows) makes fail.
+const REPLACE_ATTEMPTS: u32 = 5;
+
+// Characters Windows does not allow in file names. `/` is the Unix separator as well.
+const RESERVED_CHARS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+// Device names Windows reserves regardless of the extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+///
+/// Checks that the name can be the stem of a file on every supported platform, as tables and
+/// indices name their files.
+///
+/// # Errors
+///
+/// When the name is empty, has path separators, characters or a trailing dot or space Windows does
+/// not allow, or is a reserved device name (`CON`, `NUL`, ...).
+pub fn validate_file_stem(name: &str) -> Result<(), Error> {
+    let device_name = name.split('.').next().unwrap_or_default();
+    let is_valid = !name.is_empty()
+        && !name.ends_with(['.', ' '])
+        && !name
+            .chars()
+            .any(|c| c.is_control() || RESERVED_CHARS.contains(&c))
+        && !RESERVED_NAMES
+            .iter()
+            .any(|reserved| reserved.eq_ignore_ascii_case(device_name.trim_end()));
+
+    if is_valid {
+        Ok(())
+    } else {
+        Err(PBaseError::InvalidFileName(name.to_string()).into())
+    }
+}
+
+///
+/// The temporary file a replacement of the file is written to: the file name with `.tmp` appended,
+/// in the same directory (renames are only atomic within a filesystem).
+///
+#[must_use]
+pub fn tmp_file_name(file_name: &Path) -> PathBuf {
+    let mut tmp_name: OsString = file_name.file_name().unwrap_or_default().to_owned();
+    tmp_name.push(".tmp");
+    file_name.with_file_name(tmp_name)
+}
+
+///
+/// Replaces the file's contents atomically.
+///
+/// Readers see either the old or the new bytes, and a crash leaves one of them in place. The bytes
+/// are written to a temporary file, flushed to disk, then renamed over the file.
+///
+/// # Errors
+///
+/// On file operations.
+pub fn atomic_write(file_name: &Path, bytes: &[u8]) -> Result<(), Error> {
+    let tmp_file_name = tmp_file_name(file_name);
+    let mut tmp_file = File::create(&tmp_file_name)?;
+    tmp_file.write_all(bytes)?;
+    tmp_file.sync_all()?;
+    drop(tmp_file);
+
+    replace_file(&tmp_file_name, file_name)
+}
+
+///
+/// Renames `from` over `to`, replacing it.
+///
+/// Unix replaces open files; Windows refuses while another handle (eg. a reader's map) has the
+/// destination open, so there the rename is retried a few times with a short backoff before
+/// giving up.
+///
+/// # Errors
+///
+/// On file operations, or when the destination stays in use.
+pub fn replace_file(from: &Path, to: &Path) -> Result<(), Error> {
+    let mut attempt = 0;
+    loop {
+        match std::fs::rename(from, to) {
+            Ok(()) => return Ok(()),
+            Err(err)
+                if cfg!(windows)
+                    && err.kind() == ErrorKind::PermissionDenied
+                    && attempt + 1 < REPLACE_ATTEMPTS =>
+            {
+                attempt += 1;
+                std::thread::sleep(std::time::Duration::from_millis(10 << attempt));
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+///
+/// An exclusive advisory lock on a file, released when dropped.
+///
+/// Uses the platform's file locks (`flock` on Unix, `LockFileEx` on Windows). They are advisory:
+/// they only keep out other processes taking the same lock, not plain reads and writes.
+///
+#[derive(Debug)]
+pub struct FileLock {
+    file: File,
+    file_name: PathBuf,
+}
+
+impl FileLock {
+    ///
+    /// Takes the lock, creating the lock file when missing. Returns None when another handle
+    /// holds it.
+    ///
+    /// # Errors
+    ///
+    /// On file operations.
+    pub fn try_acquire(file_name: &Path) -> Result<Option<Self>, Error> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(file_name)?;
+
+        match file.try_lock() {
+            Ok(()) => Ok(Some(Self {
+                file,
+                file_name: file_name.to_path_buf(),
+            })),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(err)) => Err(err.into()),
+        }
+    }
+
+    ///
+    /// Takes the lock, creating the lock file when missing, waiting while another handle holds it.
+    ///
+    /// # Errors
+    ///
+    /// On file operations.
+    pub fn acquire(file_name: &Path) -> Result<Self, Error> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(file_name)?;
+        file.lock()?;
+
+        Ok(Self {
+            file,
+            file_name: file_name.to_path_buf(),
+        })
+    }
+
+    #[must_use]
+    pub fn file_name(&self) -> &Path {
+        &self.file_name
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // Closing the file releases the lock as well.
+        let _ = self.file.unlock();
+    }
+}
+
+///
+/// A fresh, empty directory under the system temp directory, removed with its files when dropped.
+///
+/// Names are unique per process, call and time, so concurrent tests and processes never share one.
+///
+#[derive(Debug)]
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    ///
+    /// # Errors
+    ///
+    /// When the directory cannot be created.
+    pub fn new() -> Result<Self, Error> {
+        static CREATED: AtomicUsize = AtomicUsize::new(0);
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let path = std::env::temp_dir().join(format!(
+            "pbase_{}_{nanos}_{}",
+            std::process::id(),
+            CREATED.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir(&path)?;
+
+        Ok(Self { path })
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use super::{atomic_write, tmp_file_name, validate_file_stem, FileLock};
+
+    #[test]
+    fn test_validate_file_stem() {
+        for valid in [
+            "t1",
+            "user_events",
+            "t1__ts-3",
+            "con_log",
+            "nul1",
+            "table.v2",
+        ] {
+            assert!(validate_file_stem(valid).is_ok(), "{valid}");
+        }
+        for invalid in [
+            "", "a/b", "a\\b", "c:", "what?", "pipe|", "star*", "quote\"", "t1.", "t1 ", "tab\t",
+            "CON", "con", "Nul.txt", "com1", "LPT9 ",
+        ] {
+            assert!(validate_file_stem(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_tmp_file_name() {
+        assert_eq!(
+            PathBuf::from("dir").join("t1__index.pbi.tmp"),
+            tmp_file_name(&Path::new("dir").join("t1__index.pbi"))
+        );
+        // Files of different extensions get different temporary files.
+        assert_ne!(
+            tmp_file_name(Path::new("t1.pbs")),
+            tmp_file_name(Path::new("t1.pbd"))
+        );
+    }
+
+    #[test]
+    fn test_atomic_write_and_lock() {
+        let dir = std::env::temp_dir().join("pbase_platform_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let file_name = dir.join("t1.pbs");
+        atomic_write(&file_name, b"old").unwrap();
+        atomic_write(&file_name, b"new").unwrap();
+        assert_eq!(b"new", &std::fs::read(&file_name).unwrap()[..]);
+        assert!(!tmp_file_name(&file_name).exists());
+
+        let lock_file_name = dir.join("pbase.lock");
+        let lock = FileLock::try_acquire(&lock_file_name).unwrap().unwrap();
+        assert_eq!(lock_file_name, lock.file_name());
+        assert!(FileLock::try_acquire(&lock_file_name).unwrap().is_none());
+        drop(lock);
+        assert!(FileLock::try_acquire(&lock_file_name).unwrap().is_some());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
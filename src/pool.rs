@@ -0,0 +1,533 @@
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+    time::{Duration, Instant},
+};
+
+use log::debug;
+
+use crate::{
+    common::{Error, PBaseError},
+    lock::{LockManager, LockMode, TransactionId},
+    pbase::PBase,
+    query::{InsertQuery, MutationQuery},
+    schema::TablePtrType,
+    value::Value,
+};
+
+// Connections a pool hands out at once by default.
+pub const POOL_MAX_CONNECTIONS: usize = 16;
+// How long a checkout waits for a connection by default.
+pub const POOL_ACQUIRE_TIMEOUT: Duration = Duration::from_secs(5);
+// Times `with_transaction` runs its closure at most by default.
+pub const TRANSACTION_ATTEMPTS: u32 = 5;
+// Wait before the second attempt of `with_transaction`, doubled before each further one.
+const TRANSACTION_RETRY_BACKOFF: Duration = Duration::from_millis(5);
+
+///
+/// Shares a database between the threads of a server, eg. as the app state of a web framework.
+///
+/// Clones are cheap and share the database. Every request checks out a connection: a
+/// `ReadTransaction` for reads or a `WriteTransaction` for writes, released when dropped. Read
+/// transactions run concurrently and see no writes of write transactions until they end; write
+/// transactions run one at a time, without readers. At most `max_connections` transactions are open, further checkouts wait
+/// for one to end.
+///
+/// A `Transaction` instead locks the tables it uses one by one, so transactions of other tables
+/// run alongside it (see `LockManager`).
+///
+/// All connections use the one `PBase` handle: handles of a directory do not share their caches
+/// and write positions, so several of them would see each other's writes late.
+///
+/// Transactions are blocking, async servers check them out on a blocking thread (eg.
+/// `tokio::task::spawn_blocking`) and do not hold them across awaits.
+///
+#[derive(Clone)]
+pub struct PBasePool {
+    db: Arc<PBase>,
+    connections: Arc<Connections>,
+    // Readers share it, a writer holds it alone.
+    isolation: Arc<RwLock<()>>,
+    locks: Arc<LockManager>,
+    next_transaction_id: Arc<AtomicU64>,
+    max_connections: usize,
+    acquire_timeout: Duration,
+    transaction_attempts: u32,
+}
+
+// Checked out connection count, with a signal when one is released.
+#[derive(Default)]
+struct Connections {
+    in_use: Mutex<usize>,
+    released: Condvar,
+}
+
+impl PBasePool {
+    #[must_use]
+    pub fn new(db: PBase) -> Self {
+        Self {
+            db: Arc::new(db),
+            connections: Arc::new(Connections::default()),
+            isolation: Arc::new(RwLock::new(())),
+            locks: Arc::new(LockManager::new()),
+            next_transaction_id: Arc::new(AtomicU64::new(1)),
+            max_connections: POOL_MAX_CONNECTIONS,
+            acquire_timeout: POOL_ACQUIRE_TIMEOUT,
+            transaction_attempts: TRANSACTION_ATTEMPTS,
+        }
+    }
+
+    ///
+    /// Sets how many transactions may be open at once (`POOL_MAX_CONNECTIONS` by default).
+    ///
+    #[must_use]
+    pub const fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+
+    ///
+    /// Sets how long a checkout waits for a connection (`POOL_ACQUIRE_TIMEOUT` by default).
+    ///
+    #[must_use]
+    pub const fn with_acquire_timeout(mut self, acquire_timeout: Duration) -> Self {
+        self.acquire_timeout = acquire_timeout;
+        self
+    }
+
+    ///
+    /// Sets how long a transaction waits for a table lock (`LOCK_WAIT_TIMEOUT` by default).
+    ///
+    #[must_use]
+    pub fn with_lock_wait_timeout(mut self, wait_timeout: Duration) -> Self {
+        self.locks = Arc::new(LockManager::new().with_wait_timeout(wait_timeout));
+        self
+    }
+
+    ///
+    /// Sets how many times `with_transaction` runs its closure at most (`TRANSACTION_ATTEMPTS` by
+    /// default).
+    ///
+    #[must_use]
+    pub const fn with_transaction_attempts(mut self, attempts: u32) -> Self {
+        self.transaction_attempts = attempts;
+        self
+    }
+
+    ///
+    /// Checks out a connection for reads, waiting for running writes to end.
+    ///
+    /// # Errors
+    ///
+    /// With `PBaseError::PoolExhausted` when no connection was released within the timeout.
+    pub fn read(&self) -> Result<ReadTransaction<'_>, Error> {
+        let connection = self.checkout()?;
+        let guard = self
+            .isolation
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        Ok(ReadTransaction {
+            db: &self.db,
+            _guard: guard,
+            _connection: connection,
+        })
+    }
+
+    ///
+    /// Checks out a connection for writes, waiting for running reads and writes to end.
+    ///
+    /// # Errors
+    ///
+    /// With `PBaseError::PoolExhausted` when no connection was released within the timeout.
+    pub fn write(&self) -> Result<WriteTransaction<'_>, Error> {
+        let connection = self.checkout()?;
+        let guard = self
+            .isolation
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        Ok(WriteTransaction {
+            db: &self.db,
+            _guard: guard,
+            _connection: connection,
+        })
+    }
+
+    ///
+    /// Checks out a connection for a transaction locking its tables (see `Transaction`), waiting
+    /// for running write transactions to end.
+    ///
+    /// # Errors
+    ///
+    /// With `PBaseError::PoolExhausted` when no connection was released within the timeout.
+    pub fn transaction(&self) -> Result<Transaction<'_>, Error> {
+        let connection = self.checkout()?;
+        let guard = self
+            .isolation
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        Ok(Transaction::checked_out(
+            &self.db,
+            &self.locks,
+            self.next_transaction_id.fetch_add(1, Ordering::Relaxed),
+            Some((guard, connection)),
+        ))
+    }
+
+    ///
+    /// Runs the closure in a transaction (see `transaction`), which ends when the closure returns.
+    /// The writes the closure buffers (see `Transaction::insert`) are committed when it succeeds.
+    ///
+    /// When the closure fails with a conflict with another transaction or handle
+    /// (`PBaseError::Deadlock`, `PBaseError::LockTimeout`, `PBaseError::VersionConflict` or
+    /// `PBaseError::StaleHandle`), its transaction is dropped, releasing the locks and discarding
+    /// the buffered writes, and after a short backoff the closure runs again in a new one, up to
+    /// `with_transaction_attempts` times in all. So is a commit failing with a conflict on its
+    /// first write. A stale handle is refreshed (see `PBase::refresh`) before the next attempt.
+    ///
+    /// # Errors
+    ///
+    /// The closure's error when it is not a conflict, or when it conflicted on every attempt, the
+    /// commit's error, and `PBaseError::PoolExhausted` when no connection was released within the
+    /// timeout.
+    pub fn with_transaction<T, F>(&self, body: F) -> Result<T, Error>
+    where
+        F: FnMut(&Transaction<'_>) -> Result<T, Error>,
+    {
+        run_transaction(self.transaction_attempts, || self.transaction(), body)
+    }
+
+    ///
+    /// The table locks of the pool's transactions.
+    ///
+    #[must_use]
+    pub fn locks(&self) -> &LockManager {
+        &self.locks
+    }
+
+    ///
+    /// Transactions open right now.
+    ///
+    #[must_use]
+    pub fn in_use(&self) -> usize {
+        *self
+            .connections
+            .in_use
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    #[must_use]
+    pub const fn max_connections(&self) -> usize {
+        self.max_connections
+    }
+
+    fn checkout(&self) -> Result<Connection<'_>, Error> {
+        let deadline = Instant::now() + self.acquire_timeout;
+        let mut in_use = self
+            .connections
+            .in_use
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        while *in_use >= self.max_connections {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if timeout.is_zero() {
+                return Err(PBaseError::PoolExhausted(self.acquire_timeout).into());
+            }
+            in_use = self
+                .connections
+                .released
+                .wait_timeout(in_use, timeout)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+        *in_use += 1;
+        drop(in_use);
+
+        Ok(Connection {
+            connections: &self.connections,
+        })
+    }
+}
+
+///
+/// Runs the closure in transactions from `begin` until it succeeds or fails with other than a
+/// conflict, committing the writes it buffered, see `PBasePool::with_transaction`.
+///
+/// # Errors
+///
+/// The closure's error when it is not a conflict, or when it conflicted on every attempt, the
+/// commit's error, and the error of `begin`.
+pub fn run_transaction<'a, T, B, F>(attempts: u32, mut begin: B, mut body: F) -> Result<T, Error>
+where
+    B: FnMut() -> Result<Transaction<'a>, Error>,
+    F: FnMut(&Transaction<'_>) -> Result<T, Error>,
+{
+    let mut attempt = 1;
+    loop {
+        let transaction = begin()?;
+        let db = transaction.db;
+        let (result, applied) = match body(&transaction) {
+            Ok(value) => {
+                let (applied, result) = transaction.apply_writes();
+                (result.map(|()| value), applied)
+            }
+            Err(err) => (Err(err), 0),
+        };
+        drop(transaction);
+
+        match result {
+            // A commit failing after a write is not run again, it would repeat the write.
+            Err(err) if applied == 0 && is_conflict(&err) && attempt < attempts => {
+                debug!("Transaction attempt {attempt} conflicted, retrying: {err}");
+                if matches!(
+                    err.downcast_ref::<PBaseError>(),
+                    Some(PBaseError::StaleHandle { .. })
+                ) {
+                    db.refresh();
+                }
+                std::thread::sleep(TRANSACTION_RETRY_BACKOFF * 2_u32.saturating_pow(attempt - 1));
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+// Errors of a transaction caused by other transactions (or handles), which a new attempt may not
+// run into.
+fn is_conflict(err: &Error) -> bool {
+    matches!(
+        err.downcast_ref::<PBaseError>(),
+        Some(
+            PBaseError::Deadlock { .. }
+                | PBaseError::LockTimeout { .. }
+                | PBaseError::VersionConflict { .. }
+                | PBaseError::StaleHandle { .. }
+        )
+    )
+}
+
+// A checked out connection, returned to the pool when dropped.
+struct Connection<'a> {
+    connections: &'a Connections,
+}
+
+impl Drop for Connection<'_> {
+    fn drop(&mut self) {
+        *self
+            .connections
+            .in_use
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) -= 1;
+        self.connections.released.notify_one();
+    }
+}
+
+///
+/// A request-scoped read of the pooled database (see `PBasePool::read`), dereferencing to it.
+///
+/// Only reads are isolated: writes run through it are not kept from concurrent readers.
+///
+pub struct ReadTransaction<'a> {
+    db: &'a PBase,
+    // Dropped in order: the lock before the connection, so a waiting checkout finds it free.
+    _guard: RwLockReadGuard<'a, ()>,
+    _connection: Connection<'a>,
+}
+
+impl Deref for ReadTransaction<'_> {
+    type Target = PBase;
+
+    fn deref(&self) -> &PBase {
+        self.db
+    }
+}
+
+///
+/// A request-scoped write of the pooled database (see `PBasePool::write`), dereferencing to it.
+///
+pub struct WriteTransaction<'a> {
+    db: &'a PBase,
+    _guard: RwLockWriteGuard<'a, ()>,
+    _connection: Connection<'a>,
+}
+
+impl Deref for WriteTransaction<'_> {
+    type Target = PBase;
+
+    fn deref(&self) -> &PBase {
+        self.db
+    }
+}
+
+///
+/// A request-scoped transaction of the pooled database (see `PBasePool::transaction`), or of a
+/// handle (see `PBase::with_transaction`), dereferencing to it.
+///
+/// Before using a table the transaction locks it, shared to read and exclusive to write. The locks
+/// are held until the transaction is dropped.
+///
+/// Writes through `insert` and `update_row_at` are buffered, and applied in order by `commit`:
+/// dropping the transaction discards them, so an attempt failing halfway leaves nothing behind.
+/// Reads in the transaction do not see its buffered writes. Writes through the dereferenced
+/// database are applied at once.
+///
+/// Locks only order transactions taking them: reads and writes of the database are not checked
+/// against them. A transaction failing with `PBaseError::Deadlock` is dropped (releasing its
+/// locks) and retried, see `PBasePool::with_transaction`.
+///
+pub struct Transaction<'a> {
+    db: &'a PBase,
+    id: TransactionId,
+    locks: &'a LockManager,
+    // Buffered by `insert` and `update_row_at`, applied by `commit`.
+    writes: Mutex<Vec<MutationQuery>>,
+    // The pool's isolation lock and connection, none for transactions of a handle. Dropped in
+    // order: the lock before the connection.
+    _checkout: Option<(RwLockReadGuard<'a, ()>, Connection<'a>)>,
+}
+
+impl<'a> Transaction<'a> {
+    ///
+    /// A transaction of the database taking its table locks in `locks`, outside of any pool.
+    ///
+    #[must_use]
+    pub const fn new(db: &'a PBase, locks: &'a LockManager, id: TransactionId) -> Self {
+        Self::checked_out(db, locks, id, None)
+    }
+
+    #[must_use]
+    pub const fn id(&self) -> TransactionId {
+        self.id
+    }
+
+    ///
+    /// Locks the table for writes and buffers the insert, validated as running it would be (see
+    /// `PBase::dry_run`) against the table as it is now.
+    ///
+    /// # Errors
+    ///
+    /// With `PBaseError::LockTimeout` or `PBaseError::Deadlock`, or when the insert would fail.
+    pub fn insert(&self, query: InsertQuery) -> Result<(), Error> {
+        self.lock_exclusive(&query.table)?;
+        self.buffer(MutationQuery::Insert(query))
+    }
+
+    ///
+    /// Locks the table for writes and buffers the update of the row starting at byte `row_pos`
+    /// (see `PBase::update_row_at`), validated as running it would be.
+    ///
+    /// # Errors
+    ///
+    /// With `PBaseError::LockTimeout` or `PBaseError::Deadlock`, or when the update would fail.
+    pub fn update_row_at(
+        &self,
+        table: &str,
+        row_pos: TablePtrType,
+        values: HashMap<String, Value>,
+    ) -> Result<(), Error> {
+        self.lock_exclusive(table)?;
+        self.buffer(MutationQuery::UpdateRowAt {
+            table: table.to_string(),
+            row_pos,
+            values,
+        })
+    }
+
+    ///
+    /// Applies the buffered writes in order, and ends the transaction.
+    ///
+    /// # Errors
+    ///
+    /// The error of the first failing write. The writes before it stay applied.
+    pub fn commit(self) -> Result<(), Error> {
+        self.apply_writes().1
+    }
+
+    const fn checked_out(
+        db: &'a PBase,
+        locks: &'a LockManager,
+        id: TransactionId,
+        checkout: Option<(RwLockReadGuard<'a, ()>, Connection<'a>)>,
+    ) -> Self {
+        Self {
+            db,
+            id,
+            locks,
+            writes: Mutex::new(vec![]),
+            _checkout: checkout,
+        }
+    }
+
+    fn buffer(&self, query: MutationQuery) -> Result<(), Error> {
+        self.db.dry_run(&query)?;
+        self.writes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(query);
+        Ok(())
+    }
+
+    // Applies the buffered writes in order, stopping at the first failing one. Returns the writes
+    // applied.
+    fn apply_writes(&self) -> (usize, Result<(), Error>) {
+        let writes =
+            std::mem::take(&mut *self.writes.lock().unwrap_or_else(PoisonError::into_inner));
+        for (applied, write) in writes.iter().enumerate() {
+            let result = match write {
+                MutationQuery::Insert(query) => self.db.run_insert_query(query).map(|_| ()),
+                MutationQuery::UpdateRowAt {
+                    table,
+                    row_pos,
+                    values,
+                } => self.db.update_row_at(table, *row_pos, values).map(|_| ()),
+            };
+            if result.is_err() {
+                return (applied, result);
+            }
+        }
+
+        (writes.len(), Ok(()))
+    }
+
+    ///
+    /// Locks the table for reads, see `LockManager::lock`.
+    ///
+    /// # Errors
+    ///
+    /// With `PBaseError::LockTimeout` or `PBaseError::Deadlock`.
+    pub fn lock_shared(&self, table: &str) -> Result<(), Error> {
+        self.locks.lock(self.id, table, LockMode::Shared)
+    }
+
+    ///
+    /// Locks the table for writes (upgrading a shared lock), see `LockManager::lock`.
+    ///
+    /// # Errors
+    ///
+    /// With `PBaseError::LockTimeout` or `PBaseError::Deadlock`.
+    pub fn lock_exclusive(&self, table: &str) -> Result<(), Error> {
+        self.locks.lock(self.id, table, LockMode::Exclusive)
+    }
+}
+
+impl Deref for Transaction<'_> {
+    type Target = PBase;
+
+    fn deref(&self) -> &PBase {
+        self.db
+    }
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        self.locks.release_all(self.id);
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    io::Write,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// Rows a bulk operation processes between progress reports.
+pub const PROGRESS_STEP_ROWS: usize = 4096;
+// Shortest time between redraws of a console progress bar.
+const CONSOLE_REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+const CONSOLE_BAR_WIDTH: usize = 30;
+
+///
+/// Receives the progress of bulk operations: index builds, index delta merges, archive imports,
+/// ANALYZE, `SELECT ... INTO` and consistency checks.
+///
+/// An operation calls `start` once, `advance` with its processed rows every `PROGRESS_STEP_ROWS`
+/// rows or so, and `finish` when it completed (not when it failed). Operations may run on any
+/// thread, and several may report to the same reporter one after the other.
+///
+pub trait ProgressReporter: Send + Sync {
+    // `total` is an estimate, None when unknown up front.
+    fn start(&self, operation: &str, total: Option<usize>);
+    fn advance(&self, processed: usize);
+    fn finish(&self);
+}
+
+///
+/// Ignores all progress, the default of `PBase`.
+///
+pub struct NoProgress;
+
+impl ProgressReporter for NoProgress {
+    fn start(&self, _operation: &str, _total: Option<usize>) {}
+    fn advance(&self, _processed: usize) {}
+    fn finish(&self) {}
+}
+
+///
+/// Draws a progress bar of the running operation on stderr, redrawn in place.
+///
+#[derive(Default)]
+pub struct ConsoleProgress {
+    // Running operation, its total and the last redraw.
+    state: Mutex<Option<(String, Option<usize>, Instant)>>,
+}
+
+impl ConsoleProgress {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ProgressReporter for ConsoleProgress {
+    fn start(&self, operation: &str, total: Option<usize>) {
+        if let Ok(mut state) = self.state.lock() {
+            *state = Some((operation.to_string(), total, Instant::now()));
+        }
+        draw(&progress_line(operation, 0, total));
+    }
+
+    fn advance(&self, processed: usize) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        let Some((operation, total, last_draw)) = state.as_mut() else {
+            return;
+        };
+        if last_draw.elapsed() >= CONSOLE_REDRAW_INTERVAL {
+            *last_draw = Instant::now();
+            draw(&progress_line(operation, processed, *total));
+        }
+    }
+
+    fn finish(&self) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        if let Some((operation, total, _)) = state.take() {
+            draw(&format!(
+                "{}\n",
+                progress_line(&operation, total.unwrap_or(0), total)
+            ));
+        }
+    }
+}
+
+// Progress is best effort, a failing stderr does not fail the operation.
+fn draw(line: &str) {
+    let mut stderr = std::io::stderr();
+    let _ = write!(stderr, "\r{line}");
+    let _ = stderr.flush();
+}
+
+//
+// The progress bar of an operation, eg. `index build [=======>      ] 2000/8000 rows (25%)`.
+// Without a total only the processed rows are shown.
+//
+fn progress_line(operation: &str, processed: usize, total: Option<usize>) -> String {
+    let Some(total) = total.filter(|total| *total > 0) else {
+        return format!("{operation}: {processed} rows");
+    };
+
+    let processed = processed.min(total);
+    let filled = processed * CONSOLE_BAR_WIDTH / total;
+    let bar: String = (0..CONSOLE_BAR_WIDTH)
+        .map(|i| match i.cmp(&filled) {
+            std::cmp::Ordering::Less => '=',
+            std::cmp::Ordering::Equal => '>',
+            std::cmp::Ordering::Greater => ' ',
+        })
+        .collect();
+
+    format!(
+        "{operation} [{bar}] {processed}/{total} rows ({}%)",
+        processed * 100 / total
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::progress_line;
+
+    #[test]
+    fn test_progress_line() {
+        assert_eq!("import: 12 rows", progress_line("import", 12, None));
+        assert_eq!("import: 12 rows", progress_line("import", 12, Some(0)));
+        assert_eq!(
+            "build [>                             ] 0/60 rows (0%)",
+            progress_line("build", 0, Some(60))
+        );
+        assert_eq!(
+            "build [=======>                      ] 15/60 rows (25%)",
+            progress_line("build", 15, Some(60))
+        );
+        // Estimates may be exceeded.
+        assert_eq!(
+            "build [==============================] 60/60 rows (100%)",
+            progress_line("build", 61, Some(60))
+        );
+    }
+}
@@ -1,6 +1,15 @@
-use std::{cmp::Ordering, collections::HashMap};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    fmt::{Display, Formatter},
+};
 
-use crate::{schema::TableSchema, value::Value};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    schema::{TablePtrType, TableSchema},
+    value::{Interval, Value},
+};
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct FieldSelector {
@@ -18,6 +27,12 @@ impl FieldSelector {
     }
 }
 
+impl Display for FieldSelector {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.source, self.name)
+    }
+}
+
 #[derive(Hash, PartialEq, Eq)]
 pub enum FilterSource {
     Single(String),
@@ -49,6 +64,29 @@ impl FilterSource {
 pub enum RhsValue {
     Value(Value),
     Ref(FieldSelector),
+    // `IN (...)`: equal to any of the values (the filter's op is `CompareOp::Eq`).
+    In(Vec<Value>),
+    // `BETWEEN low AND high` when inclusive, `low < field < high` otherwise (the filter's op is
+    // `CompareOp::Eq`).
+    Range {
+        low: Value,
+        high: Value,
+        inclusive: bool,
+    },
+    // `field - reference op interval`: the time from the other field's timestamp to the field's
+    // compared to the interval, eg. `t2.ts - t1.ts < 3600000`. Never passes for NULLs.
+    Interval {
+        reference: FieldSelector,
+        interval: Interval,
+    },
+    // `field op (SELECT aggregate FROM ...)`: compared to the aggregate of an uncorrelated
+    // subquery, eg. `t1.value > (SELECT MAX(t2.v) FROM t2)`. The executor runs the subquery once
+    // and filters by its value as by any value (index scans included); a NULL value (the MIN/MAX
+    // of no rows) passes no row.
+    Subquery {
+        aggregate: Aggregate,
+        query: Box<SelectQuery>,
+    },
 }
 
 impl RhsValue {
@@ -59,7 +97,12 @@ impl RhsValue {
     pub fn as_value(&self) -> &Value {
         match self {
             Self::Value(v) => v,
-            Self::Ref(_) => panic!("Unexpected reference value in single index filtering"),
+            Self::Ref(_) | Self::Interval { .. } => {
+                panic!("Unexpected reference value in single index filtering")
+            }
+            Self::In(_) => panic!("Unexpected value list in single index filtering"),
+            Self::Range { .. } => panic!("Unexpected value range in single index filtering"),
+            Self::Subquery { .. } => panic!("Unexpected subquery in single index filtering"),
         }
     }
 
@@ -70,15 +113,111 @@ impl RhsValue {
     pub fn as_field_selector(&self) -> &FieldSelector {
         match self {
             Self::Ref(field_selector) => field_selector,
-            Self::Value(_) => panic!("Unexpected regular value in single index filtering"),
+            Self::Value(_)
+            | Self::In(_)
+            | Self::Range { .. }
+            | Self::Interval { .. }
+            | Self::Subquery { .. } => {
+                panic!("Unexpected regular value in single index filtering")
+            }
+        }
+    }
+
+    ///
+    /// The other field compared to, of field references and intervals.
+    ///
+    #[must_use]
+    pub const fn reference(&self) -> Option<&FieldSelector> {
+        match self {
+            Self::Ref(reference) | Self::Interval { reference, .. } => Some(reference),
+            Self::Value(_) | Self::In(_) | Self::Range { .. } | Self::Subquery { .. } => None,
+        }
+    }
+
+    ///
+    /// Where the value is relative to the range: `Less` below it, `Greater` above it, `Equal` in
+    /// it.
+    ///
+    /// # Panics
+    ///
+    /// Caller is reponsible for ensuring it's the range variant.
+    #[must_use]
+    pub fn range_position(&self, value: &Value) -> Ordering {
+        let Self::Range {
+            low,
+            high,
+            inclusive,
+        } = self
+        else {
+            panic!("Unexpected non range value in range filtering")
+        };
+
+        match (value.cmp(low), value.cmp(high)) {
+            (Ordering::Less, _) => Ordering::Less,
+            (Ordering::Equal, _) if !inclusive => Ordering::Less,
+            (_, Ordering::Greater) => Ordering::Greater,
+            (_, Ordering::Equal) if !inclusive => Ordering::Greater,
+            _ => Ordering::Equal,
+        }
+    }
+}
+
+///
+/// How a filter compares the value of its field (on the left) to its right hand side.
+///
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    ///
+    /// Whether a left hand side ordered so to the right hand side passes the comparison.
+    ///
+    #[must_use]
+    pub const fn matches(self, ordering: Ordering) -> bool {
+        match self {
+            Self::Eq => ordering.is_eq(),
+            Self::Ne => ordering.is_ne(),
+            Self::Lt => ordering.is_lt(),
+            Self::Le => ordering.is_le(),
+            Self::Gt => ordering.is_gt(),
+            Self::Ge => ordering.is_ge(),
         }
     }
+
+    ///
+    /// Whether the values pass the comparison. NULL compares to nothing, NULL included: no
+    /// comparison against it passes, not even `!=`.
+    ///
+    #[must_use]
+    pub fn compare(self, lhs: &Value, rhs: &Value) -> bool {
+        *lhs != Value::NULL && *rhs != Value::NULL && self.matches(lhs.cmp(rhs))
+    }
+}
+
+impl Display for CompareOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Eq => "=",
+            Self::Ne => "!=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+        })
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct RowFilter {
     pub field: FieldSelector,
-    pub op: Ordering,
+    pub op: CompareOp,
     pub rhs: RhsValue,
 }
 
@@ -86,8 +225,11 @@ impl RowFilter {
     #[must_use]
     pub fn filter_source(&self) -> FilterSource {
         match &self.rhs {
-            RhsValue::Value(_) => FilterSource::Single(self.field.source.clone()),
-            RhsValue::Ref(reference) => {
+            RhsValue::Value(_)
+            | RhsValue::In(_)
+            | RhsValue::Range { .. }
+            | RhsValue::Subquery { .. } => FilterSource::Single(self.field.source.clone()),
+            RhsValue::Ref(reference) | RhsValue::Interval { reference, .. } => {
                 FilterSource::new_multi(self.field.source.clone(), reference.source.clone())
             }
         }
@@ -96,8 +238,11 @@ impl RowFilter {
     #[must_use]
     pub const fn is_multi_table(&self) -> bool {
         match self.rhs {
-            RhsValue::Value(_) => false,
-            RhsValue::Ref(_) => true,
+            RhsValue::Value(_)
+            | RhsValue::In(_)
+            | RhsValue::Range { .. }
+            | RhsValue::Subquery { .. } => false,
+            RhsValue::Ref(_) | RhsValue::Interval { .. } => true,
         }
     }
 
@@ -109,49 +254,536 @@ impl RowFilter {
     #[must_use]
     pub fn is_multi_same_table(&self) -> bool {
         match &self.rhs {
-            RhsValue::Value(_) => false,
-            RhsValue::Ref(reference) => reference.source == self.field.source,
+            RhsValue::Value(_)
+            | RhsValue::In(_)
+            | RhsValue::Range { .. }
+            | RhsValue::Subquery { .. } => false,
+            RhsValue::Ref(reference) | RhsValue::Interval { reference, .. } => {
+                reference.source == self.field.source
+            }
         }
     }
+
+    ///
+    /// Equality with a value or any value of an IN list: index scans narrow on the next index
+    /// field after these only.
+    ///
+    #[must_use]
+    pub const fn is_equality(&self) -> bool {
+        matches!(self.op, CompareOp::Eq) && !matches!(self.rhs, RhsValue::Range { .. })
+    }
+
+    ///
+    /// Whether the value of the field passes the filter: compares it to the filter's value, looks
+    /// it up in the IN list, or checks it is in the range. A NULL value, or a NULL to compare to,
+    /// never passes (see `CompareOp::compare`).
+    ///
+    /// # Panics
+    ///
+    /// When the filter compares to another field.
+    #[must_use]
+    pub fn matches_value(&self, value: &Value) -> bool {
+        match &self.rhs {
+            RhsValue::Value(rhs_value) => self.op.compare(value, rhs_value),
+            // Compared rather than equal: a decimal field is in a list of integers.
+            RhsValue::In(values) => values
+                .iter()
+                .any(|rhs_value| CompareOp::Eq.compare(value, rhs_value)),
+            RhsValue::Range { low, high, .. } => {
+                ![value, low, high].contains(&&Value::NULL)
+                    && self.rhs.range_position(value) == Ordering::Equal
+            }
+            RhsValue::Ref(_) | RhsValue::Interval { .. } => {
+                panic!("Unexpected reference value in value filtering")
+            }
+            RhsValue::Subquery { .. } => {
+                panic!("Unexpected subquery in value filtering, it is evaluated before the scan")
+            }
+        }
+    }
+
+    ///
+    /// Whether the values of the field and of the field it refers to pass the filter.
+    ///
+    /// # Panics
+    ///
+    /// When the filter does not compare to another field.
+    #[must_use]
+    pub fn matches_values(&self, value: &Value, reference_value: &Value) -> bool {
+        match &self.rhs {
+            RhsValue::Ref(_) => self.op.compare(value, reference_value),
+            RhsValue::Interval { interval, .. } => value
+                .interval_since(reference_value)
+                .is_some_and(|since| self.op.matches(since.cmp(interval))),
+            RhsValue::Value(_)
+            | RhsValue::In(_)
+            | RhsValue::Range { .. }
+            | RhsValue::Subquery { .. } => {
+                panic!("Unexpected value in reference filtering")
+            }
+        }
+    }
+}
+
+impl Display for RowFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let op = self.op;
+
+        match &self.rhs {
+            RhsValue::Value(value) => write!(f, "{} {op} {value}", self.field),
+            RhsValue::Ref(reference) => write!(f, "{} {op} {reference}", self.field),
+            RhsValue::Interval {
+                reference,
+                interval,
+            } => write!(f, "{} - {reference} {op} {interval}", self.field),
+            RhsValue::In(values) => {
+                let values: Vec<String> = values.iter().map(ToString::to_string).collect();
+                write!(f, "{} IN ({})", self.field, values.join(", "))
+            }
+            RhsValue::Range {
+                low,
+                high,
+                inclusive,
+            } => {
+                let op = if *inclusive { "<=" } else { "<" };
+                write!(f, "{low} {op} {} {op} {high}", self.field)
+            }
+            RhsValue::Subquery { aggregate, query } => {
+                write!(
+                    f,
+                    "{} {op} (SELECT {aggregate} FROM {})",
+                    self.field, query.from
+                )
+            }
+        }
+    }
+}
+
+///
+/// `function(arg) AS alias`: a registered scalar function (see `PBase::register_scalar`) applied
+/// to a field of every row, projected as an extra column.
+///
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ScalarCall {
+    pub alias: String,
+    pub function: String,
+    pub arg: FieldSelector,
+}
+
+impl Display for ScalarCall {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}({}) AS {}", self.function, self.arg, self.alias)
+    }
+}
+
+///
+/// Compares the result of a scalar call, referred to by its alias, to a value.
+///
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CallFilter {
+    pub alias: String,
+    pub op: CompareOp,
+    pub rhs: Value,
+}
+
+impl Display for CallFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.alias, self.op, self.rhs)
+    }
+}
+
+///
+/// `ORDER BY field [DESC]`: sorts the result rows by a field. Rows of equal values come in data
+/// order when sorted, in index order when read from an index.
+///
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct OrderBy {
+    pub field: FieldSelector,
+    pub descending: bool,
+}
+
+impl Display for OrderBy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.field)?;
+        if self.descending {
+            write!(f, " DESC")?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// `TABLESAMPLE`: which rows of a table are read, for a quick look at large tables.
+///
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SampleSpec {
+    // Each row is read with the given probability (in percent, 0 to 100).
+    Percent(u8),
+    // The first rows, in data order.
+    First(usize),
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl SampleSpec {
+    #[must_use]
+    pub fn sampled_rows(self, rows: usize) -> usize {
+        match self {
+            Self::Percent(percent) => rows * usize::from(percent) / 100,
+            Self::First(count) => rows.min(count),
+        }
+    }
+}
+
+impl Display for SampleSpec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Percent(percent) => write!(f, "{percent}%"),
+            Self::First(count) => write!(f, "FIRST {count}"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum JoinType {
     Inner,
-    // Left,
-    // Rigt,
-    // Outer,
+    // Also keeps the left rows without a matching right row, their right columns NULL.
+    Left,
+    // Also keeps the right rows without a matching left row, their left columns NULL.
+    Right,
+    // Keeps the unmatched rows of both sides, as left and right joins do.
+    Full,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl JoinType {
+    ///
+    /// Whether left rows without a matching right row are kept, with NULL right columns.
+    ///
+    #[must_use]
+    pub const fn keeps_unmatched_lhs(self) -> bool {
+        matches!(self, Self::Left | Self::Full)
+    }
+
+    ///
+    /// Whether right rows without a matching left row are kept, with NULL left columns.
+    ///
+    #[must_use]
+    pub const fn keeps_unmatched_rhs(self) -> bool {
+        matches!(self, Self::Right | Self::Full)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct JoinContract {
     pub join_type: JoinType,
+    // Whether rows with NULL keys on both sides are joined. They are not by default, as in SQL
+    // where NULL equals nothing, NULL included.
+    pub null_keys_match: bool,
     pub lhs: FieldSelector,
     pub rhs: FieldSelector,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Query {
     Select(SelectQuery),
-    Insert(InsertQuery),
+    Union(UnionQuery),
+    Explain(ExplainQuery),
+    Set(SetQuery),
+    Insert(InsertStatement),
+    Delete(DeleteQuery),
     CreateTable(CreateTableQuery),
+    Analyze(AnalyzeQuery),
+    Describe(DescribeQuery),
+    Attach(AttachQuery),
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
 pub struct SelectQuery {
     pub from: String,
     pub joins: Vec<JoinContract>,
     // List of AND-ed filters.
     pub filters: Vec<RowFilter>,
+    // Extra per-row columns computed by subqueries.
+    pub scalar_subqueries: Vec<ScalarSubquery>,
+    // Extra per-row columns computed by registered scalar functions, evaluated as rows are
+    // produced (before the limit).
+    pub scalar_calls: Vec<ScalarCall>,
+    // List of AND-ed filters on the scalar call results.
+    pub call_filters: Vec<CallFilter>,
+    // Reads only a sample of the `from` table (joined tables are read in full).
+    pub sample: Option<SampleSpec>,
+    pub limit: Option<usize>,
+    // Each join keeps only the first matching row (in data order), so every driving (`from`)
+    // row appears at most once. Meant for joins used as filters. Filters comparing fields of
+    // different tables are applied to the first match only.
+    pub first_match: bool,
+    // SELECT ... INTO: the result rows are inserted into this table instead of being returned,
+    // see `PBase::run_select_into_query`.
+    pub into: Option<String>,
+    // WITH DELETED: includes the soft deleted rows (see `TableSchema::soft_delete_column`).
+    pub with_deleted: bool,
+    // SELECT DISTINCT: drops the rows equal to an earlier row on every column, before the limit.
+    pub distinct: bool,
+    // SELECT DISTINCT table.field: the distinct values of the field, as the only result column,
+    // instead of distinct rows. Single table selects of the leading field of an index walk the
+    // index keys instead of reading the rows.
+    pub distinct_field: Option<FieldSelector>,
+    // ORDER BY: sorts the result rows, before the limit. Single table selects ordered by the
+    // leading field of an index read the index (backwards when descending) instead of sorting.
+    pub order_by: Option<OrderBy>,
+    // Overrides the handle's row cap for this select, see `RowCap`.
+    pub row_cap: Option<RowCap>,
+    // Table aliases (`FROM employees AS managers`), alias to table. The `from` and joined sources,
+    // and the fields, name a table by its alias, so that a table can be joined to itself: its
+    // columns are then keyed `alias.field`.
+    pub aliases: BTreeMap<String, String>,
+    // Derived tables (`FROM (SELECT ...) AS name`), name to query. The `from` or a joined source
+    // names one like a table: its query is run first, and its result rows are read as the rows of
+    // a table with a field per result column (see `SelectQueryExecutor`).
+    pub derived_tables: BTreeMap<String, Self>,
+}
+
+impl SelectQuery {
+    ///
+    /// The table read under the source: the aliased table, or the source itself.
+    ///
+    #[must_use]
+    pub fn table_name<'a>(&'a self, source: &'a str) -> &'a str {
+        self.aliases.get(source).map_or(source, String::as_str)
+    }
+
+    ///
+    /// Whether the source is a derived table: rows of a query rather than of a stored table.
+    ///
+    #[must_use]
+    pub fn is_derived_table(&self, source: &str) -> bool {
+        self.derived_tables.contains_key(source)
+    }
+}
+
+// Rows a select may return by default, see `RowCap`.
+pub const DEFAULT_ROW_CAP: usize = 1_000_000;
+
+///
+/// Safety cap on the rows a select materializes, against runaway joins in interactive use.
+///
+/// Past `max_rows` rows the select fails, or when truncating returns its first `max_rows` rows
+/// with `ResultSet::truncated` set. Rows are counted before scalar subqueries are applied, and
+/// the select stops reading at the cap either way.
+///
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RowCap {
+    pub max_rows: usize,
+    pub truncate: bool,
+}
+
+impl Default for RowCap {
+    fn default() -> Self {
+        Self {
+            max_rows: DEFAULT_ROW_CAP,
+            truncate: false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Aggregate {
+    Count,
+    Min(FieldSelector),
+    Max(FieldSelector),
+    // SUM and AVG give I32 results (AVG truncated toward zero), or decimals of decimal fields, see
+    // `SumAccumulator`.
+    Sum(FieldSelector),
+    Avg(FieldSelector),
+    // APPROX_COUNT_DISTINCT: estimated number of distinct non NULL values, see `HyperLogLog`.
+    ApproxCountDistinct(FieldSelector),
+}
+
+impl Aggregate {
+    ///
+    /// The aggregated field, None for COUNT(*).
+    ///
+    #[must_use]
+    pub const fn field(&self) -> Option<&FieldSelector> {
+        match self {
+            Self::Count => None,
+            Self::Min(field)
+            | Self::Max(field)
+            | Self::Sum(field)
+            | Self::Avg(field)
+            | Self::ApproxCountDistinct(field) => Some(field),
+        }
+    }
+}
+
+impl Display for Aggregate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Count => write!(f, "COUNT(*)"),
+            Self::Min(field) => write!(f, "MIN({field})"),
+            Self::Max(field) => write!(f, "MAX({field})"),
+            Self::Sum(field) => write!(f, "SUM({field})"),
+            Self::Avg(field) => write!(f, "AVG({field})"),
+            Self::ApproxCountDistinct(field) => write!(f, "APPROX_COUNT_DISTINCT({field})"),
+        }
+    }
+}
+
+///
+/// Equality correlation between a subquery and its outer (driving) query:
+/// `inner = outer` for each driving row.
+///
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Correlation {
+    pub outer: FieldSelector,
+    pub inner: FieldSelector,
+}
+
+///
+/// A subquery projected as an extra column (under `alias`) of every result row.
+///
+/// Example: `SELECT *, (SELECT COUNT(*) FROM t2 WHERE t2.t1_id = t1.id) AS t2_count FROM t1`.
+///
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ScalarSubquery {
+    pub alias: String,
+    pub query: SelectQuery,
+    pub aggregate: Aggregate,
+    // Without correlation the subquery yields the same value for every row.
+    pub correlation: Option<Correlation>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ExplainQuery {
+    pub select: SelectQuery,
+    // EXPLAIN ANALYZE executes the query and reports actual row counts and timings.
+    pub analyze: bool,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+///
+/// `ANALYZE table`: collects the table's statistics (see `PBase::analyze_table`).
+///
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AnalyzeQuery {
+    pub table: String,
+}
+
+///
+/// `DESCRIBE table`: shows the table's columns with their stats (see `PBase::describe_table`).
+///
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DescribeQuery {
+    pub table: String,
+}
+
+///
+/// `ATTACH 'path' AS alias`: attaches another database directory, whose tables are then read as
+/// `alias.table` (see `PBase::attach`).
+///
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AttachQuery {
+    pub path: String,
+    pub alias: String,
+}
+
+///
+/// `SET name = value`: changes a database setting (see `PBase::set`) or a session setting (see
+/// `Session`).
+///
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SetQuery {
+    pub name: String,
+    pub value: SettingValue,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SettingValue {
+    Int(i32),
+    Word(String),
+}
+
+impl Display for SettingValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Int(v) => write!(f, "{v}"),
+            Self::Word(word) => write!(f, "{word}"),
+        }
+    }
+}
+
+///
+/// `SELECT ... UNION | INTERSECT | EXCEPT [ALL] SELECT ...`: the rows of the selects combined by
+/// the set operation, in the columns of the first select. The rest are matched by position.
+///
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct UnionQuery {
+    pub selects: Vec<SelectQuery>,
+    pub op: SetOperation,
+    // With ALL duplicate rows are kept (as many times as the operation leaves them), without it
+    // the result is distinct rows.
+    pub all: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum SetOperation {
+    // Rows of any select.
+    #[default]
+    Union,
+    // Rows of the first select also in every other one.
+    Intersect,
+    // Rows of the first select in none of the others.
+    Except,
+}
+
+impl Display for SetOperation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Union => write!(f, "UNION"),
+            Self::Intersect => write!(f, "INTERSECT"),
+            Self::Except => write!(f, "EXCEPT"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct InsertQuery {
     pub table: String,
     pub values: HashMap<String, Value>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+///
+/// `INSERT INTO table (field, ...) VALUES (value, ...) [RETURNING [field, ...]]`: inserts a row,
+/// and with RETURNING returns it as stored (see `PBase::run_insert_query_returning`).
+///
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct InsertStatement {
+    pub query: InsertQuery,
+    // Fields to return, all of them when empty. None without RETURNING.
+    pub returning: Option<Vec<String>>,
+}
+
+///
+/// Deletes the rows of the table matching all (AND-ed) filters, see `PBase::run_delete_query`.
+///
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct DeleteQuery {
+    pub table: String,
+    pub filters: Vec<RowFilter>,
+}
+
+///
+/// A data changing query, as accepted by `PBase::dry_run`.
+///
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum MutationQuery {
+    Insert(InsertQuery),
+    // Update of the row starting at byte `row_pos` of the table data (see `PBase::update_row_at`).
+    UpdateRowAt {
+        table: String,
+        row_pos: TablePtrType,
+        values: HashMap<String, Value>,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct CreateTableQuery {
     pub schema: TableSchema,
 }
@@ -0,0 +1,149 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use crate::query::{RhsValue, RowFilter, SelectQuery};
+
+// Table accesses kept by the query log, older ones are dropped.
+pub const QUERY_LOG_CAPACITY: usize = 1024;
+
+///
+/// A table read by a logged select, with the select's value filters on that table.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryLogEntry {
+    pub table: String,
+    pub filters: Vec<RowFilter>,
+}
+
+///
+/// The most recent table accesses of the selects run, for workload analysis such as
+/// `PBase::recommend_indexes`. Each select adds an entry for its main table and for every joined
+/// table.
+///
+#[derive(Debug, Default)]
+pub struct QueryLog {
+    entries: Mutex<VecDeque<QueryLogEntry>>,
+}
+
+impl QueryLog {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    pub fn record(&self, query: &SelectQuery) {
+        // Derived tables and subqueries of filters are logged as the selects they are.
+        for derived_table in query.derived_tables.values() {
+            self.record(derived_table);
+        }
+        for filter in &query.filters {
+            if let RhsValue::Subquery { query, .. } = &filter.rhs {
+                self.record(query);
+            }
+        }
+        let sources = std::iter::once(&query.from)
+            .chain(
+                query
+                    .joins
+                    .iter()
+                    .map(|join_contract| &join_contract.rhs.source),
+            )
+            .filter(|source| !query.is_derived_table(source));
+
+        let mut entries = self.entries.lock().unwrap();
+        for source in sources {
+            let filters = query
+                .filters
+                .iter()
+                .filter(|filter| filter.field.source == *source && filter.rhs.reference().is_none())
+                .cloned()
+                .collect();
+            if entries.len() == QUERY_LOG_CAPACITY {
+                entries.pop_front();
+            }
+            entries.push_back(QueryLogEntry {
+                table: query.table_name(source).to_string(),
+                filters,
+            });
+        }
+    }
+
+    ///
+    /// The logged entries, oldest first.
+    ///
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    #[must_use]
+    pub fn entries(&self) -> Vec<QueryLogEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        query::{
+            CompareOp, FieldSelector, JoinContract, JoinType, RhsValue, RowFilter, SelectQuery,
+        },
+        value::Value,
+    };
+
+    use super::{QueryLog, QUERY_LOG_CAPACITY};
+
+    #[test]
+    fn test_query_log() {
+        let field = |source: &str, name: &str| FieldSelector {
+            name: name.into(),
+            source: source.into(),
+        };
+        let value_filter = RowFilter {
+            field: field("t1", "a"),
+            op: CompareOp::Eq,
+            rhs: RhsValue::Value(Value::I32(1)),
+        };
+        let query = SelectQuery {
+            from: "t1".into(),
+            joins: vec![JoinContract {
+                join_type: JoinType::Inner,
+                null_keys_match: false,
+                lhs: field("t1", "id"),
+                rhs: field("t2", "t1_id"),
+            }],
+            filters: vec![
+                value_filter.clone(),
+                RowFilter {
+                    field: field("t2", "b"),
+                    op: CompareOp::Lt,
+                    rhs: RhsValue::Ref(field("t1", "b")),
+                },
+            ],
+            ..Default::default()
+        };
+
+        let log = QueryLog::new();
+        log.record(&query);
+        let entries = log.entries();
+        assert_eq!(2, entries.len());
+        assert_eq!(vec![value_filter], entries[0].filters);
+        assert_eq!("t2", entries[1].table);
+        assert!(entries[1].filters.is_empty());
+
+        for _ in 0..QUERY_LOG_CAPACITY {
+            log.record(&query);
+        }
+        assert_eq!(QUERY_LOG_CAPACITY, log.entries().len());
+
+        log.clear();
+        assert!(log.entries().is_empty());
+    }
+}
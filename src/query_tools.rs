@@ -1,26 +1,52 @@
 use std::{
+    borrow::Cow,
+    cell::Cell,
     cmp::Ordering,
     collections::{HashMap, HashSet},
+    rc::Rc,
+    time::Instant,
 };
 
+use indexmap::IndexMap;
 use log::debug;
-use memmap::Mmap;
 
 use crate::{
     common::{
         binary_narrow_to_lower_range_exclusive, binary_narrow_to_range_exclusive,
-        binary_narrow_to_upper_range_exclusive, Error, Selection, SelectionIterator,
+        binary_narrow_to_upper_range_exclusive, Error, PBaseError, QueryErrorContext,
     },
-    multi_table_view::MultiTableView,
-    query::{FieldSelector, FilterSource, RhsValue, RowFilter, SelectQuery},
-    schema::{TablePtrType, TableSchema, TABLE_PTR_BYTE_SIZE},
-    table_opener::TableOpener,
+    decimal::MAX_DECIMAL_PRECISION,
+    function::{ScalarFunction, ScalarFunctions},
+    numeric::SumAccumulator,
+    operator::{
+        collect_rows, table_column_keys, ColumnKey, Compute, ComputedColumn, Distinct, Filter,
+        HashJoin, IndexDistinct, IndexRowPositions, IndexScan, Instrumented, Limit, Operator,
+        Project, Row, RuntimeStats, SampledPositions, Scan, Sort, SortKey, Values,
+    },
+    plan::{LogicalPlan, Optimizer, PlanNode, QueryPlan, QueryStats},
+    plan_cache::PlanCache,
+    query::{
+        Aggregate, CompareOp, FieldSelector, FilterSource, OrderBy, RhsValue, RowCap, RowFilter,
+        ScalarSubquery, SelectQuery, SetOperation, UnionQuery,
+    },
+    result_set::{ColumnInfo, ResultSet},
+    row_view::{RowMatcher, RowView},
+    schema::{FieldSchema, TablePtrType, TableSchema},
+    sketch::HyperLogLog,
+    snapshot::ReadSnapshot,
+    stats::INDEX_SCAN_MAX_SELECTIVITY,
+    system_tables::{is_system_table, system_table_bytes, system_table_schema},
+    table_opener::{FileBytes, Prefetcher, TableOpener, PREFETCH_MIN_BYTES},
     value::Value,
 };
 
 pub struct SelectQueryExecutor<'a> {
     table_opener: &'a TableOpener,
     query: SelectQuery,
+    functions: Option<&'a ScalarFunctions>,
+    snapshot: Option<&'a ReadSnapshot>,
+    plan_cache: Option<&'a PlanCache>,
+    row_cap: Option<RowCap>,
 }
 
 impl<'a> SelectQueryExecutor<'a> {
@@ -29,104 +55,1034 @@ impl<'a> SelectQueryExecutor<'a> {
         Self {
             table_opener,
             query,
+            functions: None,
+            snapshot: None,
+            plan_cache: None,
+            row_cap: None,
         }
     }
 
+    ///
+    /// Functions the query's scalar calls are resolved from. Without them any call is unknown.
+    ///
+    #[must_use]
+    pub const fn with_functions(mut self, functions: &'a ScalarFunctions) -> Self {
+        self.functions = Some(functions);
+        self
+    }
+
+    ///
+    /// Snapshot the query reads its tables from. Tables missing from it are read from disk.
+    ///
+    #[must_use]
+    pub const fn with_snapshot(mut self, snapshot: &'a ReadSnapshot) -> Self {
+        self.snapshot = Some(snapshot);
+        self
+    }
+
+    ///
+    /// Cache the optimized logical plan is looked up in, and added to when missing.
+    ///
+    #[must_use]
+    pub const fn with_plan_cache(mut self, plan_cache: &'a PlanCache) -> Self {
+        self.plan_cache = Some(plan_cache);
+        self
+    }
+
+    ///
+    /// Cap on the rows `call` returns, unless the query has its own (uncapped by default).
+    ///
+    #[must_use]
+    pub const fn with_row_cap(mut self, row_cap: RowCap) -> Self {
+        self.row_cap = Some(row_cap);
+        self
+    }
+
+    /// # Errors
+    ///
+    /// Errors on file operations, or when the rows exceed a row cap that does not truncate.
+    pub fn call(&self) -> Result<ResultSet, Error> {
+        if let Some(executor) = self.resolved()? {
+            return executor.call();
+        }
+        let mut rows = vec![];
+        let (table_schema_map, truncated) = self.capped_rows(&mut rows, None)?;
+
+        self.apply_scalar_subqueries(&mut rows)?;
+
+        Ok(ResultSet {
+            columns: self.columns(&table_schema_map)?,
+            rows,
+            truncated,
+        })
+    }
+
+    ///
+    /// Passes the result rows to `f` one by one as they are produced, without collecting them.
+    /// Queries with scalar subqueries are the exception: those need all rows at once, so their
+    /// result is collected first. Returns the number of rows.
+    ///
+    /// # Errors
+    ///
+    /// Errors on file operations, or when `f` fails (which stops the query).
+    pub fn for_each_row<F>(&self, mut f: F) -> Result<usize, Error>
+    where
+        F: FnMut(Row) -> Result<(), Error>,
+    {
+        if let Some(executor) = self.resolved()? {
+            return executor.for_each_row(f);
+        }
+        let mut count = 0;
+        if self.query.scalar_subqueries.is_empty() {
+            self.stream_rows(
+                |row| {
+                    count += 1;
+                    f(row)
+                },
+                None,
+                None,
+            )?;
+        } else {
+            for row in self.call()?.rows {
+                count += 1;
+                f(row)?;
+            }
+        }
+
+        Ok(count)
+    }
+
+    ///
+    /// Runs the query like `call`, measuring it (see `QueryStats`). Each operator is timed, which
+    /// costs a little on every row.
+    ///
     /// # Errors
     ///
     /// Errors on file operations.
-    pub fn call(&self) -> Result<Vec<HashMap<String, Value>>, Error> {
+    ///
+    /// # Panics
+    ///
+    /// Never: measured rows are streamed with an analyzed plan.
+    pub fn call_with_stats(&self) -> Result<(ResultSet, QueryStats), Error> {
+        if let Some(executor) = self.resolved()? {
+            return executor.call_with_stats();
+        }
+        let start = Instant::now();
+        let mut rows = vec![];
+        let mut analyzed_plan = None;
+        let (table_schema_map, truncated) =
+            self.capped_rows(&mut rows, Some(&mut analyzed_plan))?;
+
+        self.apply_scalar_subqueries(&mut rows)?;
+
+        let result = ResultSet {
+            columns: self.columns(&table_schema_map)?,
+            rows,
+            truncated,
+        };
+        let stats = QueryStats::of_plan(
+            &analyzed_plan.expect("Measured query has an analyzed plan"),
+            result.len(),
+            start.elapsed(),
+        );
+
+        Ok((result, stats))
+    }
+
+    //
+    // Collects the rows of the query (without its scalar subqueries) up to its row cap. Returns
+    // the schemas of the tables read, and whether rows past the cap were dropped.
+    //
+    fn capped_rows(
+        &self,
+        rows: &mut Vec<Row>,
+        analyzed_plan: Option<&mut Option<QueryPlan>>,
+    ) -> Result<(HashMap<&str, TableSchema>, bool), Error> {
+        let row_cap = self.query.row_cap.or(self.row_cap);
+        let (table_schema_map, capped) = self.stream_rows(
+            |row| {
+                rows.push(row);
+                Ok(())
+            },
+            row_cap.map(|row_cap| row_cap.max_rows),
+            analyzed_plan,
+        )?;
+
+        match row_cap {
+            Some(row_cap) if capped && !row_cap.truncate => {
+                Err(PBaseError::RowCapExceeded(row_cap.max_rows).into())
+            }
+            _ => Ok((table_schema_map, capped)),
+        }
+    }
+
+    //
+    // Runs the query (without its scalar subqueries), passing each projected row to `f`, up to
+    // `max_rows` rows. Returns the schemas of the tables read, and whether the query stopped at
+    // `max_rows` with rows left. With `analyzed_plan` the operators are measured, and the plan
+    // annotated with their stats is set.
+    //
+    fn stream_rows<F>(
+        &self,
+        mut f: F,
+        max_rows: Option<usize>,
+        analyzed_plan: Option<&mut Option<QueryPlan>>,
+    ) -> Result<(HashMap<&str, TableSchema>, bool), Error>
+    where
+        F: FnMut(Row) -> Result<(), Error>,
+    {
         let table_schema_map = self.collect_table_schemas_from_query()?;
 
         // Preloading memory mapped table files for main table and all join tables.
-        let table_bytes_mmap_map: HashMap<&str, Mmap> = self.collect_table_bytes_map()?;
+        let logical_plan = self.logical_plan(&table_schema_map);
+        let table_bytes_mmap_map: HashMap<&str, FileBytes> =
+            self.collect_table_bytes_map(&logical_plan)?;
         let table_bytes_map: HashMap<&str, &[u8]> = table_bytes_mmap_map
             .iter()
             .map(|(k, v)| (*k, &v[..]))
             .collect();
 
-        let mut filters_left: Vec<&RowFilter> = self.query.filters.iter().collect();
+        let mut plan = self.physical_plan(&logical_plan, &table_schema_map, &table_bytes_map)?;
+        let _prefetchers = self.prefetch_scans(&plan, &table_bytes_mmap_map);
+        let mut stats = analyzed_plan.is_some().then(Vec::new);
+        let root = self.build(&plan, &table_schema_map, &table_bytes_map, stats.as_mut())?;
 
-        // Reducing table search spaces using single table filters.
-        let mut selections: HashMap<&str, Selection> = HashMap::new();
-        selections.insert(
-            self.query.from.as_str(),
-            self.execute_filters_on_single_tables(
-                table_bytes_map[self.query.from.as_str()],
-                &table_schema_map[self.query.from.as_str()],
-                &mut filters_left,
-            )?,
-        );
+        // Joins may be reordered, the result keeps the query's column order.
+        let output_columns = self
+            .output_fields(&table_schema_map)
+            .iter()
+            .map(FieldSelector::full_name)
+            .chain(
+                self.query
+                    .scalar_calls
+                    .iter()
+                    .map(|call| call.alias.clone()),
+            )
+            .collect();
+        let mut project = Project::new(root, output_columns);
+        let mut count = 0;
+        let mut capped = false;
+        while let Some(row) = project.next_row()? {
+            if max_rows == Some(count) {
+                capped = true;
+                break;
+            }
+            count += 1;
+            f(row)?;
+        }
+        // The operators borrow the schemas.
+        drop(project);
+
+        if let (Some(analyzed_plan), Some(stats)) = (analyzed_plan, stats) {
+            plan.set_runtime_stats(&mut stats.iter().map(|node_stats| node_stats.get()));
+            *analyzed_plan = Some(plan);
+        }
+
+        Ok((table_schema_map, capped))
+    }
+
+    ///
+    /// The plan `call` executes: chosen access paths and join order, with row estimates.
+    ///
+    /// # Errors
+    ///
+    /// Errors on file operations.
+    pub fn explain(&self) -> Result<QueryPlan, Error> {
+        if let Some(executor) = self.resolved()? {
+            return executor.explain();
+        }
+        let table_schema_map = self.collect_table_schemas_from_query()?;
+        let logical_plan = self.logical_plan(&table_schema_map);
+        let table_bytes_mmap_map: HashMap<&str, FileBytes> =
+            self.collect_table_bytes_map(&logical_plan)?;
+        let table_bytes_map: HashMap<&str, &[u8]> = table_bytes_mmap_map
+            .iter()
+            .map(|(k, v)| (*k, &v[..]))
+            .collect();
+
+        self.physical_plan(&logical_plan, &table_schema_map, &table_bytes_map)
+    }
+
+    ///
+    /// Executes the query (without scalar subqueries) and returns its plan annotated with the
+    /// rows each node actually produced and the time it took.
+    ///
+    /// # Errors
+    ///
+    /// Errors on file operations.
+    pub fn explain_analyze(&self) -> Result<QueryPlan, Error> {
+        if let Some(executor) = self.resolved()? {
+            return executor.explain_analyze();
+        }
+        let table_schema_map = self.collect_table_schemas_from_query()?;
+        let logical_plan = self.logical_plan(&table_schema_map);
+        let table_bytes_mmap_map: HashMap<&str, FileBytes> =
+            self.collect_table_bytes_map(&logical_plan)?;
+        let table_bytes_map: HashMap<&str, &[u8]> = table_bytes_mmap_map
+            .iter()
+            .map(|(k, v)| (*k, &v[..]))
+            .collect();
+
+        let mut plan = self.physical_plan(&logical_plan, &table_schema_map, &table_bytes_map)?;
+        let _prefetchers = self.prefetch_scans(&plan, &table_bytes_mmap_map);
+        let mut stats = vec![];
+        let mut root = self.build(&plan, &table_schema_map, &table_bytes_map, Some(&mut stats))?;
+        collect_rows(root.as_mut())?;
+
+        plan.set_runtime_stats(&mut stats.iter().map(|node_stats| node_stats.get()));
+
+        Ok(plan)
+    }
+
+    ///
+    /// Passes each result row to `f` as a view borrowing the table data, without materializing it.
+    /// Meant for single table reads (no joins or scalar subqueries) that only aggregate or forward
+    /// values. Returns the number of rows visited.
+    ///
+    /// # Errors
+    ///
+    /// Errors on file operations, or for joins, scalar subqueries, scalar calls, distinct and
+    /// ordered selects.
+    pub fn for_each_row_view<F>(&self, mut f: F) -> Result<usize, Error>
+    where
+        F: FnMut(&RowView<'_>),
+    {
+        if let Some(executor) = self.resolved()? {
+            return executor.for_each_row_view(f);
+        }
+        if !self.query.joins.is_empty()
+            || !self.query.scalar_subqueries.is_empty()
+            || !self.query.scalar_calls.is_empty()
+            || !self.query.call_filters.is_empty()
+            || self.query.distinct
+            || self.query.distinct_field.is_some()
+            || self.query.order_by.is_some()
+        {
+            return Err(PBaseError::UnsupportedRowViewQuery(
+                "joins, scalar subqueries, scalar calls, distinct and order by need materialized rows"
+                    .into(),
+            )
+            .into());
+        }
+
+        let table_schema_map = self.collect_table_schemas_from_query()?;
+        let logical_plan = self.logical_plan(&table_schema_map);
+        let table_bytes_mmap_map: HashMap<&str, FileBytes> =
+            self.collect_table_bytes_map(&logical_plan)?;
+        let table_bytes_map: HashMap<&str, &[u8]> = table_bytes_mmap_map
+            .iter()
+            .map(|(k, v)| (*k, &v[..]))
+            .collect();
+
+        let plan = self.physical_plan(&logical_plan, &table_schema_map, &table_bytes_map)?;
+        let _prefetchers = self.prefetch_scans(&plan, &table_bytes_mmap_map);
+        let row_views = self.row_views(
+            &plan,
+            &table_schema_map[self.query.from.as_str()],
+            table_bytes_map
+                .get(self.query.from.as_str())
+                .copied()
+                .unwrap_or_default(),
+        )?;
+
+        let mut count = 0;
+        for row_view in row_views {
+            f(&row_view);
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    //
+    // Advises sequential reads of the tables the plan scans in full, and prefetches the large
+    // ones. Prefetching stops when the returned prefetchers are dropped.
+    //
+    fn prefetch_scans(
+        &self,
+        plan: &QueryPlan,
+        table_bytes_mmap_map: &HashMap<&str, FileBytes>,
+    ) -> Vec<Prefetcher> {
+        let mut prefetchers = vec![];
+        let mut nodes = vec![plan];
+        while let Some(plan) = nodes.pop() {
+            nodes.extend(plan.children.iter());
+
+            let PlanNode::Scan {
+                table,
+                sample: None,
+            } = &plan.node
+            else {
+                continue;
+            };
+            let Some(table_bytes @ FileBytes::Mapped(_)) = table_bytes_mmap_map.get(table.as_str())
+            else {
+                continue;
+            };
+
+            table_bytes.advise_sequential();
+            if table_bytes.len() >= PREFETCH_MIN_BYTES {
+                prefetchers.push(Prefetcher::spawn(
+                    self.table_opener
+                        .table_data_file_name(self.query.table_name(table)),
+                    table_bytes.len(),
+                ));
+            }
+        }
+
+        prefetchers
+    }
+
+    //
+    // Walks a single table physical plan, producing views instead of materialized rows.
+    //
+    fn row_views<'b>(
+        &self,
+        plan: &QueryPlan,
+        table_schema: &'b TableSchema,
+        table_bytes: &'b [u8],
+    ) -> Result<Box<dyn Iterator<Item = RowView<'b>> + 'b>, Error> {
+        let child = || {
+            self.row_views(
+                plan.children.first().expect("Plan node has its child"),
+                table_schema,
+                table_bytes,
+            )
+        };
+
+        Ok(match &plan.node {
+            PlanNode::Empty => Box::new(std::iter::empty()),
+            PlanNode::Scan { sample, .. } => Box::new(
+                SampledPositions::new(table_schema.row_byte_size(), table_bytes.len(), *sample)
+                    .map(move |pos| RowView::new(table_schema, table_bytes, pos)),
+            ),
+            PlanNode::IndexScan {
+                index,
+                ranges,
+                delta_rows,
+                descending,
+                ..
+            } => {
+                let mut index_row_positions = IndexRowPositions::new(
+                    table_schema,
+                    table_bytes,
+                    index.clone(),
+                    self.index_bytes(table_schema, index)?,
+                    ranges.clone(),
+                    delta_rows.clone(),
+                    *descending,
+                );
+                let mut positions = vec![];
+                while let Some(pos) = index_row_positions.next_pos()? {
+                    positions.push(pos);
+                }
+
+                Box::new(
+                    positions
+                        .into_iter()
+                        .map(move |pos| RowView::new(table_schema, table_bytes, pos)),
+                )
+            }
+            PlanNode::Filter { filters } => {
+                let row_matcher = RowMatcher::new(table_schema, filters.clone());
+                Box::new(child()?.filter(move |row_view| row_matcher.matches(row_view)))
+            }
+            PlanNode::Limit { limit } => Box::new(child()?.take(*limit)),
+            PlanNode::HashJoin { .. } => unreachable!("Single table plans have no joins"),
+            PlanNode::Compute { .. } => unreachable!("Row view plans have no scalar calls"),
+            PlanNode::IndexDistinct { .. }
+            | PlanNode::Distinct
+            | PlanNode::DistinctValues { .. } => {
+                unreachable!("Row view plans are not distinct")
+            }
+            PlanNode::Sort { .. } => unreachable!("Row view plans are not ordered"),
+        })
+    }
+
+    fn logical_plan(&self, table_schema_map: &HashMap<&str, TableSchema>) -> LogicalPlan {
+        self.plan_cache.map_or_else(
+            || self.optimized_logical_plan(table_schema_map),
+            |plan_cache| {
+                plan_cache.logical_plan(&self.query, || {
+                    self.optimized_logical_plan(table_schema_map)
+                })
+            },
+        )
+    }
+
+    //
+    // Soft deleted rows of the tables outer joins fill with NULLs are skipped before the join, rows
+    // matching only those are then unmatched rather than dropped.
+    //
+    fn optimized_logical_plan(&self, table_schema_map: &HashMap<&str, TableSchema>) -> LogicalPlan {
+        let deleted_row_filters = self.deleted_row_filters(table_schema_map);
+        if deleted_row_filters.is_empty() {
+            return Optimizer::default().optimize(LogicalPlan::from(&self.query));
+        }
+
+        let mut joined_tables = vec![self.query.from.as_str()];
+        let mut null_filled_tables: HashSet<&str> = HashSet::new();
         for join_contract in &self.query.joins {
-            selections.insert(
-                join_contract.rhs.source.as_str(),
-                self.execute_filters_on_single_tables(
-                    table_bytes_map[join_contract.rhs.source.as_str()],
-                    &table_schema_map[join_contract.rhs.source.as_str()],
-                    &mut filters_left,
-                )?,
+            if join_contract.join_type.keeps_unmatched_rhs() {
+                null_filled_tables.extend(&joined_tables);
+            }
+            if join_contract.join_type.keeps_unmatched_lhs() {
+                null_filled_tables.insert(&join_contract.rhs.source);
+            }
+            joined_tables.push(&join_contract.rhs.source);
+        }
+        let (scan_filters, query_filters): (Vec<RowFilter>, Vec<RowFilter>) = deleted_row_filters
+            .into_iter()
+            .partition(|filter| null_filled_tables.contains(filter.field.source.as_str()));
+
+        let mut query = self.query.clone();
+        query.filters.extend(query_filters);
+        Optimizer::default().optimize(LogicalPlan::from(&query).filter_scans(&scan_filters))
+    }
+
+    //
+    // Filters skipping the soft deleted rows of the tables read, unless the query includes them.
+    //
+    fn deleted_row_filters(&self, table_schema_map: &HashMap<&str, TableSchema>) -> Vec<RowFilter> {
+        if self.query.with_deleted {
+            return vec![];
+        }
+
+        let mut table_names: Vec<&&str> = table_schema_map.keys().collect();
+        table_names.sort();
+        table_names
+            .into_iter()
+            .filter_map(|table_name| {
+                let table_schema = &table_schema_map[*table_name];
+                let column = table_schema.soft_delete_column.as_ref()?;
+                Some(RowFilter {
+                    field: FieldSelector {
+                        name: column.clone(),
+                        source: table_schema.name.clone(),
+                    },
+                    op: CompareOp::Eq,
+                    rhs: RhsValue::Value(table_schema.fields[column].default_value()),
+                })
+            })
+            .collect()
+    }
+
+    //
+    // Lowers the logical plan, ordering the joined and filtered rows, computing the scalar calls
+    // (and their filters) over them, and dropping duplicate rows of distinct selects, under the
+    // limit.
+    //
+    fn physical_plan(
+        &self,
+        logical_plan: &LogicalPlan,
+        table_schema_map: &HashMap<&str, TableSchema>,
+        table_bytes_map: &HashMap<&str, &[u8]>,
+    ) -> Result<QueryPlan, Error> {
+        if let Some(distinct_field) = &self.query.distinct_field {
+            return self.distinct_field_plan(
+                distinct_field,
+                logical_plan,
+                table_schema_map,
+                table_bytes_map,
             );
         }
 
-        // Compile joined view. (Assuming we will need all to present/filter.)
-        let multi_table_view =
-            self.generate_multi_table_view(&selections, &table_bytes_map, &table_schema_map);
+        let has_calls = !self.query.scalar_calls.is_empty() || !self.query.call_filters.is_empty();
+        if !has_calls && !self.query.distinct && self.query.order_by.is_none() {
+            return self.lower(logical_plan, table_schema_map, table_bytes_map);
+        }
 
-        // todo!("Execute multi table (different table) filters and generate a selection. Leftover filters are in `filters_left`.");
-        let view_selection = Self::execute_filters_on_multi_view(
-            &multi_table_view,
-            &table_bytes_map,
-            &table_schema_map,
-            &mut filters_left,
-        );
+        let (input, limit) = match logical_plan {
+            LogicalPlan::Limit { input, limit } => (input.as_ref(), Some(*limit)),
+            _ => (logical_plan, None),
+        };
+        let mut plan = self.lower(input, table_schema_map, table_bytes_map)?;
+        if let Some(order_by) = &self.query.order_by {
+            plan = self.ordered(plan, order_by, table_schema_map)?;
+        }
+        if has_calls {
+            self.check_scalar_calls(table_schema_map)?;
+            plan = QueryPlan::compute(
+                plan,
+                self.query.scalar_calls.clone(),
+                self.query.call_filters.clone(),
+            );
+        }
+        if self.query.distinct {
+            plan = QueryPlan::distinct(plan);
+        }
+        if let Some(limit) = limit {
+            plan = QueryPlan::limit(plan, limit);
+        }
 
-        // Materialize the selection and return.
-        Ok(self.materialize_view(
-            &multi_table_view,
-            &view_selection,
-            &table_bytes_map,
-            &table_schema_map,
-        ))
+        Ok(plan)
     }
 
-    fn generate_multi_table_view(
+    //
+    // Plan of a SELECT DISTINCT of a field. A select reading a whole table (no filters, joins or
+    // sample) walks the keys of an index leading with the field, in the ORDER BY direction when
+    // ordered by the field. Others keep the first row of each value of the field, after ordering.
+    //
+    fn distinct_field_plan(
         &self,
-        selections: &HashMap<&str, Selection>,
+        distinct_field: &FieldSelector,
+        logical_plan: &LogicalPlan,
+        table_schema_map: &HashMap<&str, TableSchema>,
         table_bytes_map: &HashMap<&str, &[u8]>,
+    ) -> Result<QueryPlan, Error> {
+        let (input, limit) = match logical_plan {
+            LogicalPlan::Limit { input, limit } => (input.as_ref(), Some(*limit)),
+            _ => (logical_plan, None),
+        };
+        let descending = match &self.query.order_by {
+            None => Some(false),
+            Some(order_by) if order_by.field == *distinct_field => Some(order_by.descending),
+            Some(_) => None,
+        };
+        let table_schema = &table_schema_map[distinct_field.source.as_str()];
+        let index_name = table_schema
+            .indices
+            .iter()
+            .find(|(_, index_fields)| index_fields.first() == Some(&distinct_field.name))
+            .map(|(index_name, _)| index_name.clone());
+
+        let mut plan = match (input, index_name, descending) {
+            (LogicalPlan::Scan { .. }, Some(index_name), Some(descending))
+                if self.query.sample.is_none() =>
+            {
+                self.index_distinct(table_schema, index_name, descending)?
+            }
+            _ => {
+                let mut plan = self.lower(input, table_schema_map, table_bytes_map)?;
+                if let Some(order_by) = &self.query.order_by {
+                    plan = self.ordered(plan, order_by, table_schema_map)?;
+                }
+                QueryPlan::distinct_values(plan, distinct_field.full_name())
+            }
+        };
+        if let Some(limit) = limit {
+            plan = QueryPlan::limit(plan, limit);
+        }
+
+        Ok(plan)
+    }
+
+    //
+    // Walks the distinct leading keys of the index, merging in those of its delta. Estimated by the
+    // ANALYZE distinct count of the field when there is one, by the index entries otherwise.
+    //
+    fn index_distinct(
+        &self,
+        table_schema: &TableSchema,
+        index_name: String,
+        descending: bool,
+    ) -> Result<QueryPlan, Error> {
+        let index_row_byte_len = table_schema.index_row_byte_size(&index_name);
+        let index_byte_len = self.index_bytes(table_schema, &index_name)?.len();
+        let index_delta_bytes = self.index_delta_bytes(table_schema, &index_name)?;
+        check_index_file_size(
+            table_schema,
+            &index_name,
+            index_delta_bytes.len(),
+            "index delta",
+        )?;
+
+        let mut delta_keys: Vec<Value> = index_delta_bytes
+            .chunks_exact(index_row_byte_len)
+            .map(|index_row| {
+                let (mut values, _) = table_schema.parse_index_row_bytes(&index_name, index_row);
+                values.swap_remove(0)
+            })
+            .collect();
+        delta_keys.sort();
+        delta_keys.dedup();
+
+        let field_name = &table_schema.indices[&index_name][0];
+        let estimated_rows = self
+            .table_opener
+            .open_stats(self.query.table_name(&table_schema.name))?
+            .and_then(|table_stats| {
+                let column_stats = table_stats.columns.get(field_name)?;
+                usize::try_from(column_stats.distinct_estimate).ok()
+            })
+            .unwrap_or(index_byte_len / index_row_byte_len + delta_keys.len());
+
+        Ok(QueryPlan::leaf(
+            PlanNode::IndexDistinct {
+                table: table_schema.name.clone(),
+                index: index_name,
+                delta_keys,
+                descending,
+            },
+            estimated_rows,
+        ))
+    }
+
+    //
+    // Sorts the rows by the ORDER BY field. The index scan of a single table select reads its
+    // index in the order asked for instead, when the index leads with the field (filters on top
+    // keep the order).
+    //
+    fn ordered(
+        &self,
+        mut plan: QueryPlan,
+        order_by: &OrderBy,
         table_schema_map: &HashMap<&str, TableSchema>,
-    ) -> MultiTableView {
-        let mut view = MultiTableView::new_from_table_bytes_and_selection(
-            table_bytes_map[self.query.from.as_str()],
-            &table_schema_map[self.query.from.as_str()],
-            &selections[self.query.from.as_str()],
-        );
+    ) -> Result<QueryPlan, Error> {
+        field_schema(table_schema_map, &order_by.field)
+            .query_context(|| format!("order by {} of {}", order_by.field, self.fragment()))?;
 
-        for join_contract in &self.query.joins {
-            view.join(
-                &join_contract.join_type,
-                &selections[join_contract.rhs.source.as_str()],
-                &join_contract.lhs.source,
-                &join_contract.rhs.source,
-                &join_contract.lhs.name,
-                &join_contract.rhs.name,
-                table_bytes_map,
+        let mut access = &mut plan;
+        while matches!(access.node, PlanNode::Filter { .. }) {
+            access = &mut access.children[0];
+        }
+        if let PlanNode::IndexScan {
+            table,
+            index,
+            descending,
+            ..
+        } = &mut access.node
+        {
+            let is_index_order = self.query.joins.is_empty()
+                && *table == order_by.field.source
+                && table_schema_map[table.as_str()].indices[index.as_str()].first()
+                    == Some(&order_by.field.name);
+            if is_index_order {
+                *descending = order_by.descending;
+                return Ok(plan);
+            }
+        }
+
+        Ok(QueryPlan::sort(
+            plan,
+            vec![SortKey {
+                column: order_by.field.full_name(),
+                descending: order_by.descending,
+            }],
+        ))
+    }
+
+    fn check_scalar_calls(
+        &self,
+        table_schema_map: &HashMap<&str, TableSchema>,
+    ) -> Result<(), Error> {
+        for call in &self.query.scalar_calls {
+            self.scalar_function(&call.function)
+                .and_then(|_| field_schema(table_schema_map, &call.arg))
+                .query_context(|| {
+                    format!("{}({}) of {}", call.function, call.arg, self.fragment())
+                })?;
+        }
+
+        for filter in &self.query.call_filters {
+            if !self
+                .query
+                .scalar_calls
+                .iter()
+                .any(|call| call.alias == filter.alias)
+            {
+                return Err(PBaseError::UnknownField(filter.alias.clone()).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn scalar_function(&self, name: &str) -> Result<&'a ScalarFunction, Error> {
+        self.functions
+            .ok_or_else(|| PBaseError::UnknownFunction(name.to_string()))?
+            .get(name)
+    }
+
+    //
+    // Rows a scalar subquery aggregates: those of its query, or the one row deciding an indexed
+    // MIN/MAX.
+    //
+    fn scalar_subquery_rows(&self, scalar_subquery: &ScalarSubquery) -> Result<Vec<Row>, Error> {
+        let table_schema =
+            self.open_source_schema(&scalar_subquery.query, &scalar_subquery.query.from)?;
+        let inner_query = index_endpoint_query(scalar_subquery, &table_schema)
+            .unwrap_or_else(|| scalar_subquery.query.clone());
+        let mut inner_executor = SelectQueryExecutor::new(self.table_opener, inner_query);
+        inner_executor.functions = self.functions;
+        inner_executor.snapshot = self.snapshot;
+        Ok(inner_executor.call()?.rows)
+    }
+
+    //
+    // The executor of the query with its subquery filters evaluated, None without any. Each
+    // subquery runs once, its filter then compares to the value like any value filter (index
+    // scans included). A NULL value compares to nothing: its filter becomes an empty IN list.
+    //
+    fn resolved(&self) -> Result<Option<Self>, Error> {
+        let has_subquery_filters = self
+            .query
+            .filters
+            .iter()
+            .any(|filter| matches!(filter.rhs, RhsValue::Subquery { .. }));
+        if !has_subquery_filters {
+            return Ok(None);
+        }
+
+        let mut query = self.query.clone();
+        for filter in &mut query.filters {
+            let RhsValue::Subquery {
+                aggregate,
+                query: subquery,
+            } = &filter.rhs
+            else {
+                continue;
+            };
+            let scalar_subquery = ScalarSubquery {
+                alias: String::new(),
+                query: subquery.as_ref().clone(),
+                aggregate: aggregate.clone(),
+                correlation: None,
+            };
+            let value = self
+                .check_subquery_fields(&scalar_subquery)
+                .and_then(|()| self.scalar_subquery_rows(&scalar_subquery))
+                .and_then(|rows| aggregate_rows(aggregate, rows.iter()))
+                .query_context(|| format!("filter {filter} of {}", self.fragment()))?;
+            if value == Value::NULL {
+                filter.op = CompareOp::Eq;
+                filter.rhs = RhsValue::In(vec![]);
+            } else {
+                filter.rhs = RhsValue::Value(value);
+            }
+        }
+
+        Ok(Some(Self { query, ..*self }))
+    }
+
+    //
+    // Projects each scalar subquery as an extra column. Correlated subqueries are executed once and
+    // pre-aggregated into a hash lookup keyed by the correlated value, instead of re-running them
+    // for every driving row.
+    //
+    fn apply_scalar_subqueries(&self, rows: &mut [Row]) -> Result<(), Error> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        for scalar_subquery in &self.query.scalar_subqueries {
+            let inner_rows = self.scalar_subquery_rows(scalar_subquery)?;
+            let aggregate = &scalar_subquery.aggregate;
+            let alias = ColumnKey::from(scalar_subquery.alias.as_str());
+
+            let Some(correlation) = &scalar_subquery.correlation else {
+                let value = aggregate_rows(aggregate, inner_rows.iter())?;
+                for row in rows.iter_mut() {
+                    row.insert(alias.clone(), value.clone());
+                }
+                continue;
+            };
+
+            let inner_key = correlation.inner.full_name();
+            let mut groups: HashMap<&Value, Vec<&Row>> = HashMap::new();
+            for inner_row in &inner_rows {
+                let key = &inner_row[inner_key.as_str()];
+                // NULL is never equal to anything, not even to NULL.
+                if key != &Value::NULL {
+                    groups.entry(key).or_default().push(inner_row);
+                }
+            }
+            let lookup: HashMap<&Value, Value> = groups
+                .into_iter()
+                .map(|(key, group)| Ok((key, aggregate_rows(aggregate, group.into_iter())?)))
+                .collect::<Result<_, Error>>()?;
+            let empty_value = aggregate_rows(aggregate, std::iter::empty::<&Row>())?;
+
+            let outer_key = correlation.outer.full_name();
+            for row in rows.iter_mut() {
+                let value = lookup
+                    .get(&row[outer_key.as_str()])
+                    .cloned()
+                    .unwrap_or_else(|| empty_value.clone());
+                row.insert(alias.clone(), value);
+            }
+        }
+
+        Ok(())
+    }
+
+    //
+    // Turns the logical plan into a physical one. Filters right above a scan are served by an
+    // index when possible.
+    //
+    fn lower(
+        &self,
+        plan: &LogicalPlan,
+        table_schema_map: &HashMap<&str, TableSchema>,
+        table_bytes_map: &HashMap<&str, &[u8]>,
+    ) -> Result<QueryPlan, Error> {
+        Ok(match plan {
+            LogicalPlan::Empty => QueryPlan::leaf(PlanNode::Empty, 0),
+            LogicalPlan::Scan { table } => self.table_access(
+                table_bytes_map[table.as_str()],
+                &table_schema_map[table.as_str()],
+                &mut vec![],
+            )?,
+            LogicalPlan::Filter { input, filters } => {
+                let mut filters_left: Vec<&RowFilter> = filters.iter().collect();
+                let access = if let LogicalPlan::Scan { table } = input.as_ref() {
+                    self.table_access(
+                        table_bytes_map[table.as_str()],
+                        &table_schema_map[table.as_str()],
+                        &mut filters_left,
+                    )?
+                } else {
+                    self.lower(input, table_schema_map, table_bytes_map)?
+                };
+
+                if filters_left.is_empty() {
+                    access
+                } else {
+                    QueryPlan::filter(access, filters_left.into_iter().cloned().collect())
+                }
+            }
+            LogicalPlan::Join { lhs, rhs, contract } => QueryPlan::hash_join(
+                self.lower(lhs, table_schema_map, table_bytes_map)?,
+                self.lower(rhs, table_schema_map, table_bytes_map)?,
+                contract.lhs.full_name(),
+                contract.rhs.full_name(),
+                contract.join_type,
+                self.query.first_match,
+                contract.null_keys_match,
+            ),
+            LogicalPlan::Limit { input, limit } => QueryPlan::limit(
+                self.lower(input, table_schema_map, table_bytes_map)?,
+                *limit,
+            ),
+        })
+    }
+
+    //
+    // Instantiates the operators of a physical plan. With `stats` each operator is instrumented
+    // and its stats handle is collected in post-order.
+    //
+    fn build<'b>(
+        &self,
+        plan: &QueryPlan,
+        table_schema_map: &'b HashMap<&str, TableSchema>,
+        table_bytes_map: &HashMap<&str, &'b [u8]>,
+        mut stats: Option<&mut Vec<Rc<Cell<RuntimeStats>>>>,
+    ) -> Result<Box<dyn Operator + 'b>, Error> {
+        let mut children = vec![];
+        for child in &plan.children {
+            children.push(self.build(
+                child,
                 table_schema_map,
-            );
+                table_bytes_map,
+                stats.as_deref_mut(),
+            )?);
         }
+        let mut children = children.into_iter();
+        let mut child = || children.next().expect("Plan node has all its children");
+
+        let operator: Box<dyn Operator + 'b> = match &plan.node {
+            PlanNode::Empty => Box::new(Values::new(vec![])),
+            PlanNode::Scan { table, sample } => Box::new(
+                Scan::new(
+                    &table_schema_map[table.as_str()],
+                    table_bytes_map[table.as_str()],
+                )
+                .with_sample(*sample),
+            ),
+            PlanNode::IndexScan {
+                table,
+                index,
+                ranges,
+                delta_rows,
+                descending,
+            } => {
+                let table_schema = &table_schema_map[table.as_str()];
+                Box::new(IndexScan::new(
+                    table_schema,
+                    table_bytes_map[table.as_str()],
+                    index.clone(),
+                    self.index_bytes(table_schema, index)?,
+                    ranges.clone(),
+                    delta_rows.clone(),
+                    *descending,
+                ))
+            }
+            PlanNode::Filter { filters } => Box::new(Filter::new(child(), filters.clone())),
+            PlanNode::HashJoin {
+                lhs_key,
+                rhs_key,
+                join_type,
+                first_match,
+                null_keys_match,
+            } => Box::new(
+                HashJoin::new(child(), child(), lhs_key.clone(), rhs_key.clone())
+                    .with_join_type(
+                        *join_type,
+                        plan_columns(&plan.children[0], table_schema_map),
+                        plan_columns(&plan.children[1], table_schema_map),
+                    )
+                    .with_first_match(*first_match)
+                    .with_null_keys_match(*null_keys_match),
+            ),
+            PlanNode::Compute { calls, filters } => {
+                let columns = calls
+                    .iter()
+                    .map(|call| {
+                        Ok(ComputedColumn {
+                            column: call.alias.as_str().into(),
+                            arg: call.arg.full_name().into(),
+                            body: self.scalar_function(&call.function)?.body.clone(),
+                        })
+                    })
+                    .collect::<Result<_, Error>>()?;
+                Box::new(Compute::new(child(), columns, filters.clone()))
+            }
+            PlanNode::IndexDistinct {
+                table,
+                index,
+                delta_keys,
+                descending,
+            } => {
+                let table_schema = &table_schema_map[table.as_str()];
+                Box::new(IndexDistinct::new(
+                    table_schema,
+                    index,
+                    self.index_bytes(table_schema, index)?,
+                    delta_keys.clone(),
+                    *descending,
+                )?)
+            }
+            PlanNode::Distinct => Box::new(Distinct::new(child())),
+            PlanNode::DistinctValues { column } => {
+                Box::new(Distinct::new(child()).with_column(column.as_str().into()))
+            }
+            PlanNode::Sort { keys } => Box::new(Sort::new(child(), keys.clone())),
+            PlanNode::Limit { limit } => Box::new(Limit::new(child(), *limit)),
+        };
 
-        view
+        Ok(match stats {
+            Some(stats) => {
+                let instrumented = Instrumented::new(operator);
+                stats.push(instrumented.stats());
+                Box::new(instrumented)
+            }
+            None => operator,
+        })
     }
 
     //
-    // Applies all single table filters on a table and returns the selection.
+    // Builds the access path of a single table: an index scan when an index can narrow on the
+    // table's filters, a full scan otherwise, with the rest of the table's filters on top.
     //
-    fn execute_filters_on_single_tables(
+    fn table_access(
         &self,
         table_bytes: &[u8],
         table_schema: &TableSchema,
         filters_left: &mut Vec<&RowFilter>,
-    ) -> Result<Selection, Error> {
-        let mut selection = Selection::All;
+    ) -> Result<QueryPlan, Error> {
+        let table_byte_len = table_bytes.len();
+        let row_byte_len = table_schema.row_byte_size();
+        assert!(table_byte_len % row_byte_len == 0, "Invalid table size. Table byte size ({table_byte_len}) is not multiple of row byte size ({row_byte_len}).");
 
         // TODO: Greedy algorithm for index selection might not be the best.
         // Example:
@@ -142,120 +1098,594 @@ impl<'a> SelectQueryExecutor<'a> {
         // Establish current subset.
         let index_filterable_fields: HashSet<&String> = filters_left
             .iter()
+            .filter(|row_filter| row_filter.field.source == table_schema.name)
             .filter_map(|row_filter| match row_filter.rhs {
-                RhsValue::Value(_) => Some(&row_filter.field.name),
-                RhsValue::Ref(_) => None,
+                RhsValue::Value(_) | RhsValue::In(_) | RhsValue::Range { .. } => {
+                    Some(&row_filter.field.name)
+                }
+                RhsValue::Ref(_) | RhsValue::Interval { .. } | RhsValue::Subquery { .. } => None,
             })
             .collect();
 
-        if let Some(index_name) = index_for_query(table_schema, &index_filterable_fields) {
-            debug!("Using index: {}", &index_name);
+        // Samples are taken of the table data, the index is not used.
+        let sample = self
+            .query
+            .sample
+            .filter(|_| table_schema.name == self.query.from);
+        let index_name = match index_for_query(table_schema, &index_filterable_fields) {
+            Some(_) if sample.is_some() => None,
+            Some(index_name)
+                if self.is_index_selective(table_schema, &index_name, filters_left)? =>
+            {
+                Some(index_name)
+            }
+            Some(index_name) => {
+                debug!("Index {index_name} matches too many rows, scanning instead");
+                None
+            }
+            None if sample.is_none() => self.order_index(table_schema),
+            None => None,
+        };
 
-            // Index lookup.
-            selection = self.index_filter(&index_name, filters_left, table_schema)?;
-            debug!("Index filter result selection: {:?}", &selection);
+        let mut access = if let Some(index_name) = index_name {
+            debug!("Using index: {}", &index_name);
+            self.index_filter(index_name, filters_left, table_schema)?
         } else {
             debug!("No index found");
-        }
+            let rows = table_byte_len / row_byte_len;
+            QueryPlan::leaf(
+                PlanNode::Scan {
+                    table: table_schema.name.clone(),
+                    sample,
+                },
+                sample.map_or(rows, |sample| sample.sampled_rows(rows)),
+            )
+        };
 
         // Linear scan the rest.
-        if !filters_left.is_empty() {
-            selection = Self::scan_filter(&selection, filters_left, table_bytes, table_schema);
+        let table_filters: Vec<RowFilter> = filters_left
+            .iter()
+            .filter(|row_filter| match row_filter.filter_source() {
+                FilterSource::Single(source) => source == table_schema.name,
+                FilterSource::Multi(source_lhs, source_rhs) => {
+                    source_lhs == table_schema.name && source_rhs == table_schema.name
+                }
+            })
+            .map(|row_filter| (*row_filter).clone())
+            .collect();
+        if !table_filters.is_empty() {
+            filters_left.retain(|row_filter| !table_filters.contains(row_filter));
+            access = QueryPlan::filter(access, table_filters);
         }
 
-        Ok(selection)
+        Ok(access)
     }
 
-    fn execute_filters_on_multi_view(
-        multi_table_view: &MultiTableView,
-        table_bytes_map: &HashMap<&str, &[u8]>,
+    //
+    // An index leading with the ORDER BY field of a limited single table select: read in order, a
+    // limit right above it reads only the first entries (see `QueryPlan::limit`) instead of the
+    // whole table being sorted.
+    //
+    fn order_index(&self, table_schema: &TableSchema) -> Option<String> {
+        let order_by = self.query.order_by.as_ref()?;
+        if self.query.limit.is_none()
+            || !self.query.joins.is_empty()
+            || order_by.field.source != table_schema.name
+        {
+            return None;
+        }
+
+        table_schema
+            .indices
+            .iter()
+            .find(|(_, index_fields)| index_fields.first() == Some(&order_by.field.name))
+            .map(|(index_name, _)| index_name.clone())
+    }
+
+    fn collect_table_schemas_from_query(&self) -> Result<HashMap<&str, TableSchema>, Error> {
+        self.check_column_names()?;
+        let mut table_schemas = HashMap::new();
+
+        // Main table schema.
+        table_schemas.insert(
+            self.query.from.as_str(),
+            self.open_source_schema(&self.query, &self.query.from)?,
+        );
+
+        // Join table schemas.
+        for join_contract in &self.query.joins {
+            table_schemas.insert(
+                join_contract.rhs.source.as_str(),
+                self.open_source_schema(&self.query, &join_contract.rhs.source)?,
+            );
+        }
+
+        if let Some(distinct_field) = &self.query.distinct_field {
+            field_schema(&table_schemas, distinct_field)
+                .query_context(|| format!("distinct {distinct_field} of {}", self.fragment()))?;
+        }
+        self.check_filters(&table_schemas)?;
+        self.check_joins(&table_schemas)?;
+        self.check_scalar_subqueries(&table_schemas)?;
+
+        Ok(table_schemas)
+    }
+
+    //
+    // Fails on scalar subqueries naming fields their rows do not have: the fields of the subquery
+    // (see `check_subquery_fields`), and the outer field of a correlation among the query's tables.
+    //
+    fn check_scalar_subqueries(
+        &self,
         table_schema_map: &HashMap<&str, TableSchema>,
-        filters_left: &mut Vec<&RowFilter>,
-    ) -> Selection {
-        if filters_left.is_empty() {
-            return Selection::All;
+    ) -> Result<(), Error> {
+        for scalar_subquery in &self.query.scalar_subqueries {
+            let check = || -> Result<(), Error> {
+                self.check_subquery_fields(scalar_subquery)?;
+                if let Some(correlation) = &scalar_subquery.correlation {
+                    field_schema(table_schema_map, &correlation.outer)?;
+                }
+                Ok(())
+            };
+            check().query_context(|| {
+                format!("subquery {} of {}", scalar_subquery.alias, self.fragment())
+            })?;
         }
 
-        let mut positions = vec![];
+        Ok(())
+    }
 
-        for view_row_reader in
-            multi_table_view.iter(table_bytes_map, table_schema_map, &Selection::All)
-        {
-            let mut is_match = true;
+    //
+    // Fails on the aggregated field or the inner field of a correlation when the subquery's tables
+    // do not have it: both are read from its rows by column.
+    //
+    fn check_subquery_fields(&self, scalar_subquery: &ScalarSubquery) -> Result<(), Error> {
+        let subquery = &scalar_subquery.query;
+        let sources = std::iter::once(&subquery.from).chain(
+            subquery
+                .joins
+                .iter()
+                .map(|join_contract| &join_contract.rhs.source),
+        );
+        let mut table_schemas = HashMap::new();
+        for source in sources {
+            table_schemas.insert(source.as_str(), self.open_source_schema(subquery, source)?);
+        }
 
-            for filter in filters_left.iter() {
-                assert!(filter.is_multi_table() && !filter.is_multi_same_table());
+        let inner = scalar_subquery
+            .correlation
+            .as_ref()
+            .map(|correlation| &correlation.inner);
+        for field in scalar_subquery.aggregate.field().into_iter().chain(inner) {
+            field_schema(&table_schemas, field)?;
+        }
 
-                let lhs_table_reader = view_row_reader.table_reader(filter.field.source.as_str());
-                let lhs_value = lhs_table_reader.get_field_value(filter.field.name.as_str());
+        Ok(())
+    }
 
-                let rhs_table_reader =
-                    view_row_reader.table_reader(filter.rhs.as_field_selector().source.as_str());
-                let rhs_value =
-                    rhs_table_reader.get_field_value(filter.rhs.as_field_selector().name.as_str());
+    //
+    // Fails on filters the operators could not evaluate: of fields not in their table, or
+    // comparing values of types that do not compare (which would panic mid-scan).
+    //
+    fn check_filters(&self, table_schema_map: &HashMap<&str, TableSchema>) -> Result<(), Error> {
+        for filter in &self.query.filters {
+            let check = || -> Result<(), Error> {
+                let lhs = field_schema(table_schema_map, &filter.field)?;
+                let values = match &filter.rhs {
+                    RhsValue::Value(value) => vec![value],
+                    RhsValue::In(values) => values.iter().collect(),
+                    RhsValue::Range { low, high, .. } => vec![low, high],
+                    RhsValue::Ref(reference) => {
+                        let rhs = field_schema(table_schema_map, reference)?;
+                        if !lhs.is_comparable_to(&rhs.default_value()) {
+                            return Err(PBaseError::IncomparableFields {
+                                lhs: filter.field.full_name(),
+                                rhs: reference.full_name(),
+                            }
+                            .into());
+                        }
+                        vec![]
+                    }
+                    RhsValue::Interval { reference, .. } => {
+                        field_schema(table_schema_map, reference)?;
+                        vec![]
+                    }
+                    // Checked once evaluated, see `resolved`.
+                    RhsValue::Subquery { .. } => vec![],
+                };
+                values
+                    .into_iter()
+                    .find(|value| !lhs.is_comparable_to(value))
+                    .map_or(Ok(()), |value| {
+                        Err(PBaseError::FieldTypeMismatch {
+                            field: filter.field.full_name(),
+                            value: value.to_string(),
+                        }
+                        .into())
+                    })
+            };
+            check().query_context(|| format!("filter {filter} of {}", self.fragment()))?;
+        }
 
-                if lhs_value.cmp(&rhs_value) != filter.op {
-                    is_match = false;
-                    break;
+        Ok(())
+    }
+
+    //
+    // Fails on join keys not in their table, and on keys of different types: their values would
+    // never be equal (a U8 1 is not an I32 1), leaving the join silently empty.
+    //
+    fn check_joins(&self, table_schema_map: &HashMap<&str, TableSchema>) -> Result<(), Error> {
+        for join_contract in &self.query.joins {
+            let check = || -> Result<(), Error> {
+                let lhs = field_schema(table_schema_map, &join_contract.lhs)?;
+                let rhs = field_schema(table_schema_map, &join_contract.rhs)?;
+                let is_same_type = match (lhs, rhs) {
+                    (FieldSchema::Decimal { .. }, FieldSchema::Decimal { .. }) => true,
+                    (lhs, rhs) => lhs == rhs,
+                };
+                if !is_same_type {
+                    return Err(PBaseError::IncomparableFields {
+                        lhs: join_contract.lhs.full_name(),
+                        rhs: join_contract.rhs.full_name(),
+                    }
+                    .into());
                 }
+                Ok(())
+            };
+            check().query_context(|| {
+                format!(
+                    "join {} = {} of {}",
+                    join_contract.lhs,
+                    join_contract.rhs,
+                    self.fragment()
+                )
+            })?;
+        }
+
+        Ok(())
+    }
+
+    //
+    // The query as named in error contexts: its main table, and the table it aliases.
+    //
+    fn fragment(&self) -> String {
+        let table_name = self.query.table_name(&self.query.from);
+        if table_name == self.query.from {
+            format!("select from {table_name}")
+        } else {
+            format!("select from {table_name} as {}", self.query.from)
+        }
+    }
+
+    //
+    // Fails unless every result column has its own key: each table is read once (its columns are
+    // keyed `table.field`), and computed columns have distinct aliases without a '.', which can
+    // then not collide with table columns. A SELECT DISTINCT of a field has no computed columns.
+    //
+    fn check_column_names(&self) -> Result<(), Error> {
+        let has_computed_columns =
+            !self.query.scalar_calls.is_empty() || !self.query.scalar_subqueries.is_empty();
+        if self.query.distinct_field.is_some() && has_computed_columns {
+            return Err(PBaseError::InvalidArgument(
+                "DISTINCT of a field selects no other columns".to_string(),
+            )
+            .into());
+        }
+
+        for alias in self
+            .query
+            .aliases
+            .keys()
+            .chain(self.query.derived_tables.keys())
+        {
+            if alias.contains('.') {
+                return Err(PBaseError::InvalidAlias(alias.clone()).into());
             }
+        }
+        let mut tables = HashSet::from([self.query.from.as_str()]);
+        for join_contract in &self.query.joins {
+            if !tables.insert(join_contract.rhs.source.as_str()) {
+                return Err(PBaseError::AmbiguousTable(join_contract.rhs.source.clone()).into());
+            }
+        }
+
+        let mut aliases = HashSet::new();
+        let call_aliases = self.query.scalar_calls.iter().map(|call| &call.alias);
+        let subquery_aliases = self
+            .query
+            .scalar_subqueries
+            .iter()
+            .map(|scalar_subquery| &scalar_subquery.alias);
+        for alias in call_aliases.chain(subquery_aliases) {
+            if alias.contains('.') {
+                return Err(PBaseError::InvalidAlias(alias.clone()).into());
+            }
+            if !aliases.insert(alias) {
+                return Err(PBaseError::DuplicateColumn(alias.clone()).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    //
+    // Table data of the main and joined tables. Nothing is read for plans known to be empty.
+    //
+    fn collect_table_bytes_map(
+        &self,
+        logical_plan: &LogicalPlan,
+    ) -> Result<HashMap<&str, FileBytes>, Error> {
+        let mut table_bytes_map: HashMap<&str, FileBytes> = HashMap::new();
+        if *logical_plan == LogicalPlan::Empty {
+            return Ok(table_bytes_map);
+        }
+
+        table_bytes_map.insert(
+            self.query.from.as_str(),
+            self.table_bytes(&self.query.from)?,
+        );
+        for join_contract in &self.query.joins {
+            table_bytes_map.insert(
+                &join_contract.rhs.source,
+                self.table_bytes(&join_contract.rhs.source)?,
+            );
+        }
+
+        Ok(table_bytes_map)
+    }
 
-            if is_match {
-                positions.push(view_row_reader.view_idx);
+    //
+    // Schema of the table the query reads under the source, named by the source: its columns are
+    // keyed `source.field`.
+    //
+    fn open_source_schema(&self, query: &SelectQuery, source: &str) -> Result<TableSchema, Error> {
+        if let Some(derived_table) = query.derived_tables.get(source) {
+            return self.derived_table_schema(source, derived_table);
+        }
+
+        let mut table_schema = self.open_schema(query.table_name(source))?;
+        table_schema.name = source.to_string();
+        Ok(table_schema)
+    }
+
+    // Schema of a regular, external or system table.
+    fn open_schema(&self, table_name: &str) -> Result<TableSchema, Error> {
+        if let Some(table_schema) = self
+            .snapshot
+            .and_then(|snapshot| snapshot.table_schema(table_name))
+        {
+            return Ok(table_schema.clone());
+        }
+        if let Some(table_schema) = system_table_schema(table_name) {
+            return Ok(table_schema);
+        }
+        if let Some(external_table) = self.table_opener.open_external_table(table_name)? {
+            return Ok(external_table.schema);
+        }
+
+        self.table_opener.open_schema(table_name)
+    }
+
+    // Data of the table read under the source.
+    fn table_bytes(&self, source: &str) -> Result<FileBytes, Error> {
+        if let Some(derived_table) = self.query.derived_tables.get(source) {
+            return Ok(FileBytes::Owned(
+                self.derived_table_bytes(source, derived_table)?,
+            ));
+        }
+        let table_name = self.query.table_name(source);
+        if is_system_table(table_name) {
+            return Ok(FileBytes::Owned(system_table_bytes(
+                self.table_opener,
+                table_name,
+            )?));
+        }
+        if let Some(external_table) = self.table_opener.open_external_table(table_name)? {
+            // Filters are pushed into the read of the main table only: dropping rows of a joined
+            // table early could change outer joins.
+            let pushed_filters: Vec<&RowFilter> = if source == self.query.from {
+                self.query.filters.iter().collect()
+            } else {
+                vec![]
+            };
+            return Ok(FileBytes::Owned(
+                external_table.table_bytes(&pushed_filters)?,
+            ));
+        }
+
+        if let Some(table_bytes) = self
+            .snapshot
+            .and_then(|snapshot| snapshot.table_bytes(table_name))
+        {
+            return Ok(table_bytes);
+        }
+
+        self.table_opener
+            .table_bytes(&self.table_opener.open_schema(table_name)?)
+    }
+
+    //
+    // Schema of a derived table: a field per result column of its query, named by the column's
+    // field (`t1.a` is `a`) or alias. It has no indices, its rows are scanned.
+    //
+    fn derived_table_schema(
+        &self,
+        name: &str,
+        derived_table: &SelectQuery,
+    ) -> Result<TableSchema, Error> {
+        let mut fields = IndexMap::new();
+        for column in self
+            .derived_table_executor(derived_table)
+            .output_columns()?
+        {
+            let field_name = derived_field_name(&column).to_string();
+            if fields
+                .insert(field_name.clone(), column.field_schema)
+                .is_some()
+            {
+                // Eg. `a` of both tables of a join.
+                return Err(PBaseError::DuplicateColumn(format!("{name}.{field_name}")).into());
             }
         }
 
-        filters_left.clear();
-
-        Selection::List(positions)
+        Ok(TableSchema {
+            name: name.to_string(),
+            fields,
+            ..Default::default()
+        })
     }
 
-    fn collect_table_schemas_from_query(&self) -> Result<HashMap<&str, TableSchema>, Error> {
-        let mut table_schemas = HashMap::new();
+    //
+    // Rows of a derived table, materialized by running its query. NULLs (of outer joins) are
+    // stored as zero bytes, as in tables.
+    //
+    fn derived_table_bytes(
+        &self,
+        name: &str,
+        derived_table: &SelectQuery,
+    ) -> Result<Vec<u8>, Error> {
+        let table_schema = self.derived_table_schema(name, derived_table)?;
+        let result = self.derived_table_executor(derived_table).call()?;
+
+        Ok(result
+            .rows
+            .iter()
+            .flat_map(|row| {
+                let values = result
+                    .columns
+                    .iter()
+                    .map(|column| {
+                        (
+                            derived_field_name(column).to_string(),
+                            row[column.name.as_str()].clone(),
+                        )
+                    })
+                    .collect();
+                table_schema.data_row_to_bytes(&values)
+            })
+            .collect())
+    }
 
-        // Main table schema.
-        table_schemas.insert(
-            self.query.from.as_str(),
-            self.table_opener.open_schema(&self.query.from)?,
-        );
+    //
+    // Executor of a derived table's query. A row cap fails the query rather than truncate it: the
+    // rows of a truncated derived table would be silently missing from the result.
+    //
+    fn derived_table_executor(&self, derived_table: &SelectQuery) -> Self {
+        let mut executor = SelectQueryExecutor::new(self.table_opener, derived_table.clone());
+        executor.functions = self.functions;
+        executor.snapshot = self.snapshot;
+        executor.row_cap = self.row_cap.map(|row_cap| RowCap {
+            truncate: false,
+            ..row_cap
+        });
+        executor
+    }
 
-        // Join table schemas.
-        for join_contract in &self.query.joins {
-            table_schemas.insert(
-                join_contract.rhs.source.as_str(),
-                self.table_opener.open_schema(&join_contract.rhs.source)?,
-            );
+    fn index_bytes(
+        &self,
+        table_schema: &TableSchema,
+        index_name: &str,
+    ) -> Result<FileBytes, Error> {
+        let table_schema = self.unaliased(table_schema);
+        if let Some(index_bytes) = self
+            .snapshot
+            .and_then(|snapshot| snapshot.index_bytes(&table_schema.name, index_name))
+        {
+            return Ok(index_bytes);
         }
 
-        Ok(table_schemas)
+        self.table_opener.index_bytes(&table_schema, index_name)
     }
 
-    fn collect_table_bytes_map(&self) -> Result<HashMap<&str, Mmap>, Error> {
-        let mut table_bytes_map: HashMap<&str, Mmap> = HashMap::new();
-        table_bytes_map.insert(
-            self.query.from.as_str(),
-            self.table_opener.table_mmap(&self.query.from)?,
-        );
-        for join_contract in &self.query.joins {
-            table_bytes_map.insert(
-                &join_contract.rhs.source,
-                self.table_opener.table_mmap(&join_contract.rhs.source)?,
-            );
+    fn index_delta_bytes(
+        &self,
+        table_schema: &TableSchema,
+        index_name: &str,
+    ) -> Result<Vec<u8>, Error> {
+        let table_schema = self.unaliased(table_schema);
+        if let Some(index_delta_bytes) = self
+            .snapshot
+            .and_then(|snapshot| snapshot.index_delta_bytes(&table_schema.name, index_name))
+        {
+            return Ok(index_delta_bytes);
         }
 
-        Ok(table_bytes_map)
+        self.table_opener
+            .index_delta_bytes(&table_schema, index_name)
     }
 
-    fn index_filter(
+    //
+    // The schema of a source named by its table again, whose files have the table's name.
+    //
+    fn unaliased<'s>(&self, table_schema: &'s TableSchema) -> Cow<'s, TableSchema> {
+        self.query.aliases.get(&table_schema.name).map_or(
+            Cow::Borrowed(table_schema),
+            |table_name| {
+                Cow::Owned(TableSchema {
+                    name: table_name.clone(),
+                    ..table_schema.clone()
+                })
+            },
+        )
+    }
+
+    //
+    // Whether the filters on the index's leading field are estimated (by the ANALYZE histogram)
+    // to match few enough rows for the index to beat a full scan. Without stats the index is
+    // used.
+    //
+    fn is_index_selective(
         &self,
+        table_schema: &TableSchema,
         index_name: &str,
+        filters_left: &[&RowFilter],
+    ) -> Result<bool, Error> {
+        let Some(table_stats) = self
+            .table_opener
+            .open_stats(self.query.table_name(&table_schema.name))?
+        else {
+            return Ok(true);
+        };
+        let leading_field = &table_schema.indices[index_name][0];
+        let leading_filters: Vec<&RowFilter> = filters_left
+            .iter()
+            .filter(|row_filter| {
+                row_filter.field.source == table_schema.name
+                    && &row_filter.field.name == leading_field
+            })
+            .copied()
+            .collect();
+
+        Ok(table_stats
+            .selectivity(leading_field, &leading_filters)
+            .is_none_or(|selectivity| {
+                debug!("Estimated selectivity of {index_name}: {selectivity:.3}");
+                selectivity <= INDEX_SCAN_MAX_SELECTIVITY
+            }))
+    }
+
+    //
+    // Narrows the index to the range matching the filters on its leading fields (removing those
+    // filters) and returns a scan over that range.
+    //
+    fn index_filter(
+        &self,
+        index_name: String,
         filters_left: &mut Vec<&RowFilter>,
         table_schema: &TableSchema,
-    ) -> Result<Selection, Error> {
-        let index_row_byte_len = table_schema.index_row_byte_size(index_name);
-        let index_mmap = self.table_opener.index_mmap(table_schema, index_name)?;
-        let index_bytes = &index_mmap[..];
-        let index_fields = &table_schema.indices[index_name];
+    ) -> Result<QueryPlan, Error> {
+        let index_row_byte_len = table_schema.index_row_byte_size(&index_name);
+        let index_bytes = &self.index_bytes(table_schema, &index_name)?[..];
+        let index_delta_bytes = self.index_delta_bytes(table_schema, &index_name)?;
+        let index_fields = &table_schema.indices[&index_name];
+        // A truncated index would have the narrowing read partial rows.
+        check_index_file_size(table_schema, &index_name, index_bytes.len(), "index")?;
+        check_index_file_size(
+            table_schema,
+            &index_name,
+            index_delta_bytes.len(),
+            "index delta",
+        )?;
 
         let mut filter_by_field_map: HashMap<&String, Vec<RowFilter>> = HashMap::new();
         for filter in filters_left.iter() {
@@ -278,162 +1708,83 @@ impl<'a> SelectQueryExecutor<'a> {
         // Get crossection ordered
         // Iterate the crossection in order
         // Narrow down the index ranges
-        let mut lhs_idx = -1i32; // Line index.
-        let mut rhs_idx = i32::try_from(index_bytes.len() / index_row_byte_len).unwrap(); // Line index.
-        for index_field in index_fields {
+        // Line index ranges, one per probed value of an IN list.
+        let mut ranges = vec![(
+            -1i32,
+            i32::try_from(index_bytes.len() / index_row_byte_len)?,
+        )];
+        let mut index_filters: Vec<(usize, &RowFilter)> = vec![];
+        for (index_field_idx, index_field) in index_fields.iter().enumerate() {
             if !filter_by_field_map.contains_key(index_field) {
                 // No more filters to leverage the index columns.
                 break;
             }
 
-            let index_field_byte_pos = table_schema.index_field_byte_pos(index_name, index_field);
+            let index_field_byte_pos = table_schema.index_field_byte_pos(&index_name, index_field);
             let index_field_schema = &table_schema.fields[index_field];
+            let index_value = |i: i32| {
+                let index_row_pos = index_row_byte_len * usize::try_from(i).unwrap();
+                let index_value_pos = index_row_pos + index_field_byte_pos;
+                index_field_schema.value_from_bytes(&index_bytes[index_value_pos..])
+            };
 
-            for filter in &filter_by_field_map[index_field] {
-                let rhs_value = filter.rhs.as_value();
-
-                // Narrow the range.
-                match filter.op {
-                    Ordering::Equal => {
-                        (lhs_idx, rhs_idx) =
-                            binary_narrow_to_range_exclusive(lhs_idx, rhs_idx, |i| {
-                                let index_row_pos =
-                                    index_row_byte_len * usize::try_from(i).unwrap();
-                                let index_value_pos = index_row_pos + index_field_byte_pos;
-                                let index_value = index_field_schema
-                                    .value_from_bytes(&index_bytes[index_value_pos..]);
-
-                                index_value.cmp(rhs_value)
-                            });
-                    }
-                    Ordering::Greater => {
-                        lhs_idx = binary_narrow_to_upper_range_exclusive(lhs_idx, rhs_idx, |i| {
-                            let index_row_pos = index_row_byte_len * usize::try_from(i).unwrap();
-                            let index_value_pos = index_row_pos + index_field_byte_pos;
-                            let index_value = index_field_schema
-                                .value_from_bytes(&index_bytes[index_value_pos..]);
-
-                            index_value.cmp(rhs_value)
-                        });
-                    }
-                    Ordering::Less => {
-                        rhs_idx = binary_narrow_to_lower_range_exclusive(lhs_idx, rhs_idx, |i| {
-                            let index_row_pos = index_row_byte_len * usize::try_from(i).unwrap();
-                            let index_value_pos = index_row_pos + index_field_byte_pos;
-                            let index_value = index_field_schema
-                                .value_from_bytes(&index_bytes[index_value_pos..]);
-
-                            index_value.cmp(rhs_value)
-                        });
-                    }
-                }
+            let field_filters = &filter_by_field_map[index_field];
+            for filter in field_filters {
+                ranges = narrow_index_ranges(ranges, filter, index_value);
 
                 filters_left.retain(|row_filter| row_filter != &filter);
+                index_filters.push((index_field_idx, filter));
             }
-        }
-
-        debug!("Index narrowing result range: ({lhs_idx}..{rhs_idx})");
 
-        // 3:
-        // Collect positions from final range.
-        let mut i = lhs_idx + 1;
-        let mut out_positions = vec![];
-        while i < rhs_idx {
-            let index_row_pos = usize::try_from(i).unwrap() * index_row_byte_len;
-            let index_row_row_idx_field_pos =
-                index_row_pos + table_schema.index_row_ptr_field_byte_pos(index_name);
-
-            let table_row_ptr = TablePtrType::from_le_bytes(
-                index_bytes[index_row_row_idx_field_pos
-                    ..index_row_row_idx_field_pos + TABLE_PTR_BYTE_SIZE]
-                    .try_into()?,
-            );
-            out_positions.push(usize::try_from(table_row_ptr).unwrap());
-
-            i += 1;
+            if !field_filters.iter().all(RowFilter::is_equality) {
+                // Entries in a range of values are not sorted by the next index fields.
+                break;
+            }
         }
 
-        // 4:
-        // Return.
-        Ok(Selection::List(out_positions))
-    }
-
-    //
-    // Filters a selection on a table using row-fitlers line by line (no index use).
-    //
-    fn scan_filter(
-        current_selection: &Selection,
-        filters: &mut Vec<&RowFilter>,
-        table_bytes: &[u8],
-        table_schema: &TableSchema,
-    ) -> Selection {
-        let table_byte_len = table_bytes.len();
-        let row_byte_len = table_schema.row_byte_size();
-        assert!(table_byte_len % row_byte_len == 0, "Invalid table size. Table byte size ({table_byte_len}) is not multiple of row byte size ({row_byte_len}).");
-        assert!(!filters.is_empty());
+        debug!("Index narrowing result ranges: {ranges:?}");
 
-        let table_filters: Vec<RowFilter> = filters
-            .iter()
-            .filter(|row_filter| match row_filter.filter_source() {
-                FilterSource::Single(source) => source == table_schema.name,
-                FilterSource::Multi(source_lhs, source_rhs) => {
-                    source_lhs == table_schema.name && source_rhs == table_schema.name
-                }
+        // The unsorted delta is checked entry by entry against the same filters.
+        let mut delta_entries: Vec<(Vec<Value>, TablePtrType)> = index_delta_bytes
+            .chunks_exact(index_row_byte_len)
+            .map(|index_row| table_schema.parse_index_row_bytes(&index_name, index_row))
+            .filter(|(values, _)| {
+                index_filters.iter().all(|(index_field_idx, filter)| {
+                    filter.matches_value(&values[*index_field_idx])
+                })
             })
-            .map(|row_filter| (*row_filter).clone())
             .collect();
-
-        let selection_it = SelectionIterator::new(current_selection, row_byte_len, table_byte_len);
-        let mut filtered_positions = vec![];
-        for pos in selection_it {
-            let row_bytes = &table_bytes[pos..pos + row_byte_len];
-            let mut is_match = true;
-
-            // We need to go through all filters.
-            for filter in &table_filters {
-                // Skip if not match.
-                let filter_field_pos = table_schema.field_byte_pos(&filter.field.name);
-                let field_schema = &table_schema.fields[&filter.field.name];
-                let value = field_schema.value_from_bytes(&row_bytes[filter_field_pos..]);
-
-                let is_satisfy = match &filter.rhs {
-                    RhsValue::Value(rhs_value) => value.cmp(rhs_value) == filter.op,
-                    RhsValue::Ref(rhs_reference) => {
-                        let rhs_filter_field_pos = table_schema.field_byte_pos(&rhs_reference.name);
-                        let rhs_field_schema = &table_schema.fields[&rhs_reference.name];
-                        let rhs_value =
-                            rhs_field_schema.value_from_bytes(&row_bytes[rhs_filter_field_pos..]);
-
-                        value.cmp(&rhs_value) == filter.op
-                    }
-                };
-
-                if !is_satisfy {
-                    is_match = false;
-                }
-            }
-
-            if is_match {
-                // Add to filtered positions.
-                filtered_positions.push(pos);
-            }
+        delta_entries.sort_by(|(lhs_values, _), (rhs_values, _)| lhs_values.cmp(rhs_values));
+        let delta_rows: Vec<TablePtrType> = delta_entries
+            .into_iter()
+            .map(|(_, row_ptr)| row_ptr)
+            .collect();
+        let mut estimated_rows = delta_rows.len();
+        for (lhs_idx, rhs_idx) in &ranges {
+            estimated_rows += usize::try_from(rhs_idx - lhs_idx - 1)?;
         }
 
-        filters.retain(|row_filter| !table_filters.contains(row_filter));
-
-        Selection::List(filtered_positions)
+        Ok(QueryPlan::leaf(
+            PlanNode::IndexScan {
+                table: table_schema.name.clone(),
+                index: index_name,
+                ranges,
+                delta_rows,
+                descending: false,
+            },
+            estimated_rows,
+        ))
     }
 
-    fn materialize_view(
-        &self,
-        view: &MultiTableView,
-        selection: &Selection,
-        table_bytes_map: &HashMap<&str, &[u8]>,
-        table_schema_map: &HashMap<&str, TableSchema>,
-    ) -> Vec<HashMap<String, Value>> {
-        let mut out = vec![];
+    //
+    // Output fields in result order: main table fields first, then each joined table's fields. The
+    // field of a SELECT DISTINCT of a field alone.
+    //
+    fn output_fields(&self, table_schema_map: &HashMap<&str, TableSchema>) -> Vec<FieldSelector> {
+        if let Some(distinct_field) = &self.query.distinct_field {
+            return vec![distinct_field.clone()];
+        }
 
-        // Collecting output fields.
         let mut output_fields = vec![];
         for main_table_field in table_schema_map[self.query.from.as_str()].fields.keys() {
             output_fields.push(FieldSelector {
@@ -454,21 +1805,382 @@ impl<'a> SelectQueryExecutor<'a> {
             }
         }
 
-        for view_reader in view.iter(table_bytes_map, table_schema_map, selection) {
-            let mut out_row = HashMap::new();
-            for output_field in &output_fields {
-                let table_reader = view_reader.table_reader(&output_field.source);
-                let value = table_reader.get_field_value(&output_field.name);
-                out_row.insert(output_field.full_name(), value);
+        output_fields
+    }
+
+    ///
+    /// Result columns, in result order.
+    ///
+    /// # Errors
+    ///
+    /// Errors on file operations.
+    pub fn output_columns(&self) -> Result<Vec<ColumnInfo>, Error> {
+        let table_schema_map = self.collect_table_schemas_from_query()?;
+        self.columns(&table_schema_map)
+    }
+
+    fn columns(
+        &self,
+        table_schema_map: &HashMap<&str, TableSchema>,
+    ) -> Result<Vec<ColumnInfo>, Error> {
+        let mut columns: Vec<ColumnInfo> = self
+            .output_fields(table_schema_map)
+            .into_iter()
+            .map(|field| ColumnInfo {
+                name: field.full_name(),
+                field_schema: table_schema_map[field.source.as_str()].fields[field.name.as_str()]
+                    .clone(),
+                source: Some(field.source),
+            })
+            .collect();
+
+        for call in &self.query.scalar_calls {
+            columns.push(ColumnInfo {
+                name: call.alias.clone(),
+                source: None,
+                field_schema: self.scalar_function(&call.function)?.return_type.clone(),
+            });
+        }
+
+        for scalar_subquery in &self.query.scalar_subqueries {
+            let field_schema = match &scalar_subquery.aggregate {
+                Aggregate::Count | Aggregate::ApproxCountDistinct(_) => FieldSchema::I32,
+                Aggregate::Sum(field) | Aggregate::Avg(field) => {
+                    match self
+                        .open_source_schema(&scalar_subquery.query, &field.source)?
+                        .fields[field.name.as_str()]
+                    {
+                        FieldSchema::Decimal { scale, .. } => FieldSchema::Decimal {
+                            precision: MAX_DECIMAL_PRECISION,
+                            scale,
+                        },
+                        _ => FieldSchema::I32,
+                    }
+                }
+                Aggregate::Min(field) | Aggregate::Max(field) => self
+                    .open_source_schema(&scalar_subquery.query, &field.source)?
+                    .fields[field.name.as_str()]
+                .clone(),
+            };
+            columns.push(ColumnInfo {
+                name: scalar_subquery.alias.clone(),
+                source: None,
+                field_schema,
+            });
+        }
+
+        Ok(columns)
+    }
+}
+
+pub struct UnionQueryExecutor<'a> {
+    table_opener: &'a TableOpener,
+    query: UnionQuery,
+    functions: Option<&'a ScalarFunctions>,
+    snapshot: Option<&'a ReadSnapshot>,
+}
+
+impl<'a> UnionQueryExecutor<'a> {
+    #[must_use]
+    pub const fn new(table_opener: &'a TableOpener, query: UnionQuery) -> Self {
+        Self {
+            table_opener,
+            query,
+            functions: None,
+            snapshot: None,
+        }
+    }
+
+    ///
+    /// Functions the selects' scalar calls are resolved from.
+    ///
+    #[must_use]
+    pub const fn with_functions(mut self, functions: &'a ScalarFunctions) -> Self {
+        self.functions = Some(functions);
+        self
+    }
+
+    ///
+    /// Snapshot all selects read their tables from.
+    ///
+    #[must_use]
+    pub const fn with_snapshot(mut self, snapshot: &'a ReadSnapshot) -> Self {
+        self.snapshot = Some(snapshot);
+        self
+    }
+
+    /// # Errors
+    ///
+    /// Errors on file operations or when the selects have incompatible columns.
+    pub fn call(self) -> Result<ResultSet, Error> {
+        let mut executors = self.query.selects.into_iter().map(|select_query| {
+            let mut executor = SelectQueryExecutor::new(self.table_opener, select_query);
+            executor.functions = self.functions;
+            executor.snapshot = self.snapshot;
+            executor
+        });
+
+        let Some(first_executor) = executors.next() else {
+            return Err(PBaseError::IncompatibleSelects("union without selects".into()).into());
+        };
+
+        // Result columns are named after the first select, the rest is matched by position.
+        let mut out = first_executor.call()?;
+        let result_keys: Vec<ColumnKey> = out
+            .columns
+            .iter()
+            .map(|column| column.name.as_str().into())
+            .collect();
+
+        for executor in executors {
+            let columns = executor.output_columns()?;
+            check_columns_compatible(&out.columns, &columns)?;
+
+            let rows = executor.call()?.rows.into_iter().map(|mut row| {
+                let mut out_row = Row::new();
+                for (result_key, column) in result_keys.iter().zip(&columns) {
+                    let value = row.swap_remove(column.name.as_str()).unwrap_or(Value::NULL);
+                    out_row.insert(result_key.clone(), value);
+                }
+                out_row
+            });
+            if self.query.op == SetOperation::Union {
+                out.rows.extend(rows);
+            } else {
+                out.rows = match_rows(self.query.op, self.query.all, out.rows, rows, &out.columns);
             }
+        }
+
+        if !self.query.all {
+            out.rows = dedup_rows(out.rows, &out.columns);
+        }
+
+        Ok(out)
+    }
+}
+
+//
+// INTERSECT or EXCEPT of the rows: those with a match among the other rows, or those without.
+// With ALL each of the other rows matches one row at most, so that of `m` equal rows `min(m, n)`
+// are kept by INTERSECT and `m - n` by EXCEPT.
+//
+fn match_rows(
+    op: SetOperation,
+    all: bool,
+    rows: Vec<Row>,
+    other_rows: impl Iterator<Item = Row>,
+    columns: &[ColumnInfo],
+) -> Vec<Row> {
+    let mut other_counts: HashMap<Vec<Value>, usize> = HashMap::new();
+    for other_row in other_rows {
+        *other_counts
+            .entry(row_key(&other_row, columns))
+            .or_default() += 1;
+    }
+
+    rows.into_iter()
+        .filter(|row| {
+            let matched = match other_counts.get_mut(&row_key(row, columns)) {
+                Some(count) if *count > 0 => {
+                    if all {
+                        *count -= 1;
+                    }
+                    true
+                }
+                _ => false,
+            };
+            matched == (op == SetOperation::Intersect)
+        })
+        .collect()
+}
+
+//
+// Columns of the rows the plan produces, of the tables it reads.
+//
+//
+// Field of a derived table for a result column of its query: the column's field, or its alias.
+//
+fn derived_field_name(column: &ColumnInfo) -> &str {
+    column
+        .source
+        .as_ref()
+        .and_then(|source| column.name.strip_prefix(source.as_str())?.strip_prefix('.'))
+        .unwrap_or(&column.name)
+}
+
+//
+// Schema of the selected field, with `PBaseError::UnknownField` when its table is not read by the
+// query or has no such field.
+//
+fn field_schema<'s>(
+    table_schema_map: &'s HashMap<&str, TableSchema>,
+    field: &FieldSelector,
+) -> Result<&'s FieldSchema, Error> {
+    table_schema_map
+        .get(field.source.as_str())
+        .and_then(|table_schema| table_schema.fields.get(&field.name))
+        .ok_or_else(|| PBaseError::UnknownField(field.full_name()).into())
+}
 
-            out.push(out_row);
+fn plan_columns(plan: &QueryPlan, table_schema_map: &HashMap<&str, TableSchema>) -> Vec<ColumnKey> {
+    plan.tables()
+        .into_iter()
+        .flat_map(|table| table_column_keys(&table_schema_map[table]))
+        .collect()
+}
+
+fn check_columns_compatible(lhs: &[ColumnInfo], rhs: &[ColumnInfo]) -> Result<(), Error> {
+    if lhs.len() != rhs.len() {
+        return Err(PBaseError::IncompatibleSelects(format!(
+            "column count mismatch: {} vs {}",
+            lhs.len(),
+            rhs.len()
+        ))
+        .into());
+    }
+
+    for (lhs_column, rhs_column) in lhs.iter().zip(rhs) {
+        if lhs_column.field_schema != rhs_column.field_schema {
+            return Err(PBaseError::IncompatibleSelects(format!(
+                "column type mismatch: {} ({:?}) vs {} ({:?})",
+                lhs_column.name, lhs_column.field_schema, rhs_column.name, rhs_column.field_schema
+            ))
+            .into());
         }
+    }
+
+    Ok(())
+}
 
-        out
+//
+// Removes duplicate rows keeping the first occurrence (and so the original order).
+//
+fn dedup_rows(rows: Vec<Row>, columns: &[ColumnInfo]) -> Vec<Row> {
+    let mut seen: HashSet<Vec<Value>> = HashSet::new();
+
+    rows.into_iter()
+        .filter(|row| seen.insert(row_key(row, columns)))
+        .collect()
+}
+
+//
+// Values of the row in the columns, missing ones NULL. Rows with equal keys (NULLs included) are
+// the same row to set operations.
+//
+fn row_key(row: &Row, columns: &[ColumnInfo]) -> Vec<Value> {
+    columns
+        .iter()
+        .map(|column| {
+            row.get(column.name.as_str())
+                .cloned()
+                .unwrap_or(Value::NULL)
+        })
+        .collect()
+}
+
+//
+// The shortcut of an uncorrelated MIN/MAX of the leading field of an index over a single table: the
+// one row deciding it, read from the matching end of the index (see `QueryPlan::limit`) instead of
+// aggregating all rows. Stored fields are never NULL, so the first row in index order is the MIN.
+// None for other subqueries.
+//
+fn index_endpoint_query(
+    scalar_subquery: &ScalarSubquery,
+    table_schema: &TableSchema,
+) -> Option<SelectQuery> {
+    let (field, descending) = match &scalar_subquery.aggregate {
+        Aggregate::Min(field) => (field, false),
+        Aggregate::Max(field) => (field, true),
+        _ => return None,
+    };
+    let query = &scalar_subquery.query;
+    let is_single_table = scalar_subquery.correlation.is_none()
+        && field.source == query.from
+        && query.joins.is_empty()
+        && query.scalar_subqueries.is_empty()
+        && query.scalar_calls.is_empty()
+        && query.call_filters.is_empty()
+        && query.sample.is_none()
+        && query.limit.is_none()
+        && query.order_by.is_none();
+    let is_index_leading = table_schema
+        .indices
+        .values()
+        .any(|index_fields| index_fields.first() == Some(&field.name));
+    if !is_single_table || !is_index_leading {
+        return None;
     }
+
+    Some(SelectQuery {
+        order_by: Some(OrderBy {
+            field: field.clone(),
+            descending,
+        }),
+        limit: Some(1),
+        ..query.clone()
+    })
+}
+
+//
+// Aggregates a group of rows into a single value. MIN/MAX/SUM/AVG ignore NULLs, an empty group
+// gives 0 for COUNT and NULL otherwise.
+//
+fn aggregate_rows<'r, I>(aggregate: &Aggregate, rows: I) -> Result<Value, Error>
+where
+    I: Iterator<Item = &'r Row>,
+{
+    Ok(match aggregate {
+        Aggregate::Count => Value::I32(
+            i32::try_from(rows.count())
+                .map_err(|_| PBaseError::NumericOverflow("count does not fit into I32".into()))?,
+        ),
+        Aggregate::Min(field) => {
+            let key = field.full_name();
+            rows.map(|row| &row[key.as_str()])
+                .filter(|value| **value != Value::NULL)
+                .min()
+                .cloned()
+                .unwrap_or(Value::NULL)
+        }
+        Aggregate::Max(field) => {
+            // NULL orders lowest, so it only wins when there is nothing else.
+            let key = field.full_name();
+            rows.map(|row| &row[key.as_str()])
+                .max()
+                .cloned()
+                .unwrap_or(Value::NULL)
+        }
+        Aggregate::Sum(field) | Aggregate::Avg(field) => {
+            let key = field.full_name();
+            let mut acc = SumAccumulator::new();
+            for row in rows {
+                acc.add(&row[key.as_str()]);
+            }
+
+            if matches!(aggregate, Aggregate::Sum(_)) {
+                acc.sum()?
+            } else {
+                acc.avg()?
+            }
+        }
+        Aggregate::ApproxCountDistinct(field) => {
+            let key = field.full_name();
+            let mut sketch = HyperLogLog::new();
+            for row in rows {
+                sketch.add(&row[key.as_str()]);
+            }
+
+            Value::I32(i32::try_from(sketch.estimate()).map_err(|_| {
+                PBaseError::NumericOverflow("distinct count does not fit into I32".into())
+            })?)
+        }
+    })
 }
 
+///
+/// The index whose leading fields are covered the furthest by the filtered fields. Ties go to the
+/// index declared first, so plans do not change between runs.
+///
 #[must_use]
 pub fn index_for_query<S>(
     table_schema: &TableSchema,
@@ -543,18 +2255,160 @@ pub fn find_insert_pos_in_index(
     usize::try_from(rhs_idx).unwrap()
 }
 
+//
+// Narrows the line index ranges of an index to the entries passing a value filter on one of its
+// fields, `index_value` reading the field's value of an index line. A range filter is a single
+// narrowing pass. An IN list takes one binary search probe per value, splitting each range into
+// the ranges of the values found, in index order. A not-equal filter splits each range around
+// the value, dropping the sides left empty. No entry passes a comparison against NULL.
+//
+fn narrow_index_ranges<F>(
+    ranges: Vec<(i32, i32)>,
+    filter: &RowFilter,
+    index_value: F,
+) -> Vec<(i32, i32)>
+where
+    F: Fn(i32) -> Value,
+{
+    let index_value = &index_value;
+    match &filter.rhs {
+        RhsValue::In(values) => {
+            let mut values = values.clone();
+            values.retain(|value| *value != Value::NULL);
+            values.sort();
+            values.dedup_by(|lhs, rhs| (*lhs).cmp(rhs).is_eq());
+            return ranges
+                .into_iter()
+                .flat_map(|(lhs_idx, rhs_idx)| {
+                    values.iter().map(move |rhs_value| {
+                        binary_narrow_to_range_exclusive(lhs_idx, rhs_idx, |i| {
+                            index_value(i).cmp(rhs_value)
+                        })
+                    })
+                })
+                .filter(|(lhs_idx, rhs_idx)| rhs_idx - lhs_idx > 1)
+                .collect();
+        }
+        RhsValue::Range { low, high, .. } => {
+            if *low == Value::NULL || *high == Value::NULL {
+                return vec![];
+            }
+            return ranges
+                .into_iter()
+                .map(|(lhs_idx, rhs_idx)| {
+                    binary_narrow_to_range_exclusive(lhs_idx, rhs_idx, |i| {
+                        filter.rhs.range_position(&index_value(i))
+                    })
+                })
+                .collect();
+        }
+        RhsValue::Value(_)
+        | RhsValue::Ref(_)
+        | RhsValue::Interval { .. }
+        | RhsValue::Subquery { .. } => {}
+    }
+
+    let rhs_value = filter.rhs.as_value();
+    if *rhs_value == Value::NULL {
+        return vec![];
+    }
+    // Inclusive bounds place the entries equal to the value on the side they keep.
+    let above = |lhs_idx, rhs_idx, equal| {
+        binary_narrow_to_upper_range_exclusive(lhs_idx, rhs_idx, |i| {
+            index_value(i).cmp(rhs_value).then(equal)
+        })
+    };
+    let below = |lhs_idx, rhs_idx, equal| {
+        binary_narrow_to_lower_range_exclusive(lhs_idx, rhs_idx, |i| {
+            index_value(i).cmp(rhs_value).then(equal)
+        })
+    };
+    ranges
+        .into_iter()
+        .flat_map(|(lhs_idx, rhs_idx)| match filter.op {
+            CompareOp::Eq => vec![binary_narrow_to_range_exclusive(lhs_idx, rhs_idx, |i| {
+                index_value(i).cmp(rhs_value)
+            })],
+            CompareOp::Ne => vec![
+                (lhs_idx, below(lhs_idx, rhs_idx, Ordering::Equal)),
+                (above(lhs_idx, rhs_idx, Ordering::Equal), rhs_idx),
+            ],
+            CompareOp::Gt => vec![(above(lhs_idx, rhs_idx, Ordering::Equal), rhs_idx)],
+            CompareOp::Ge => vec![(above(lhs_idx, rhs_idx, Ordering::Greater), rhs_idx)],
+            CompareOp::Lt => vec![(lhs_idx, below(lhs_idx, rhs_idx, Ordering::Equal))],
+            CompareOp::Le => vec![(lhs_idx, below(lhs_idx, rhs_idx, Ordering::Less))],
+        })
+        .filter(|(lhs_idx, rhs_idx)| filter.op != CompareOp::Ne || rhs_idx - lhs_idx > 1)
+        .collect()
+}
+
+fn check_index_file_size(
+    table_schema: &TableSchema,
+    index_name: &str,
+    file_len: usize,
+    file: &str,
+) -> Result<(), Error> {
+    let index_row_byte_len = table_schema.index_row_byte_size(index_name);
+    if file_len % index_row_byte_len != 0 {
+        return Err(PBaseError::CorruptIndex {
+            table: table_schema.name.clone(),
+            index: index_name.to_string(),
+            reason: format!(
+                "{file} size {file_len} is not a multiple of the index row size {index_row_byte_len}"
+            ),
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+///
+/// Position (in index rows) of the given index row (values and row pointer), if present.
+///
+#[must_use]
+pub fn find_row_pos_in_index(
+    index_name: &str,
+    index_bytes: &[u8],
+    index_row_bytes: &[u8],
+    index_values: &[&Value],
+    table_schema: &TableSchema,
+) -> Option<usize> {
+    let index_row_size = table_schema.index_row_byte_size(index_name);
+    let values_byte_size = table_schema.index_row_ptr_field_byte_pos(index_name);
+
+    // Rows with the same values end right before the insert position, row pointers are unordered.
+    let end = find_insert_pos_in_index(index_name, index_bytes, index_values, table_schema);
+    (0..end)
+        .rev()
+        .map(|pos| {
+            (
+                pos,
+                &index_bytes[pos * index_row_size..(pos + 1) * index_row_size],
+            )
+        })
+        .take_while(|(_, row_bytes)| {
+            row_bytes[..values_byte_size] == index_row_bytes[..values_byte_size]
+        })
+        .find(|(_, row_bytes)| *row_bytes == index_row_bytes)
+        .map(|(pos, _)| pos)
+}
+
 #[cfg(test)]
 mod test {
-    use std::collections::{HashMap, HashSet};
+    use std::collections::HashSet;
     use std::hash::{DefaultHasher, Hash, Hasher};
 
     use indexmap::IndexMap;
 
+    use crate::query::{
+        Aggregate, Correlation, FieldSelector, OrderBy, ScalarSubquery, SelectQuery,
+    };
     use crate::query_tools::{find_insert_pos_in_index, index_score, FilterSource};
     use crate::schema::{FieldSchema, TableSchema};
     use crate::value::Value;
 
-    use super::index_for_query;
+    use super::{index_endpoint_query, index_for_query};
 
     #[test]
     fn test_index_score() {
@@ -594,13 +2448,14 @@ mod test {
                 ("C".to_string(), FieldSchema::I32),
                 ("D".to_string(), FieldSchema::I32),
             ]),
-            indices: HashMap::from([
+            indices: IndexMap::from([
                 ("index1".to_string(), vec!["A".to_string(), "B".to_string()]),
                 (
                     "index2".to_string(),
                     vec!["B".to_string(), "C".to_string(), "D".to_string()],
                 ),
             ]),
+            ..Default::default()
         };
 
         let index_name = index_for_query(
@@ -610,12 +2465,76 @@ mod test {
         assert_eq!(Some("index1".to_string()), index_name);
     }
 
+    #[test]
+    fn test_index_endpoint_query() {
+        let table_schema = TableSchema {
+            name: "t".to_string(),
+            fields: IndexMap::from([
+                ("a".to_string(), FieldSchema::I32),
+                ("b".to_string(), FieldSchema::I32),
+            ]),
+            indices: IndexMap::from([("a_index".to_string(), vec!["a".to_string()])]),
+            ..Default::default()
+        };
+        let field = |name: &str| FieldSelector {
+            name: name.to_string(),
+            source: "t".to_string(),
+        };
+        let subquery = |aggregate| ScalarSubquery {
+            alias: "x".to_string(),
+            query: SelectQuery {
+                from: "t".to_string(),
+                ..Default::default()
+            },
+            aggregate,
+            correlation: None,
+        };
+        let endpoint = |descending, filters| SelectQuery {
+            from: "t".to_string(),
+            filters,
+            order_by: Some(OrderBy {
+                field: field("a"),
+                descending,
+            }),
+            limit: Some(1),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Some(endpoint(true, vec![])),
+            index_endpoint_query(&subquery(Aggregate::Max(field("a"))), &table_schema)
+        );
+        assert_eq!(
+            Some(endpoint(false, vec![])),
+            index_endpoint_query(&subquery(Aggregate::Min(field("a"))), &table_schema)
+        );
+
+        // Not an index's leading field, not MIN/MAX, or correlated.
+        assert_eq!(
+            None,
+            index_endpoint_query(&subquery(Aggregate::Max(field("b"))), &table_schema)
+        );
+        assert_eq!(
+            None,
+            index_endpoint_query(&subquery(Aggregate::Count), &table_schema)
+        );
+        let correlated = ScalarSubquery {
+            correlation: Some(Correlation {
+                outer: field("b"),
+                inner: field("b"),
+            }),
+            ..subquery(Aggregate::Max(field("a")))
+        };
+        assert_eq!(None, index_endpoint_query(&correlated, &table_schema));
+    }
+
     #[test]
     fn test_find_insert_pos_in_index_single_field_index() {
         let table_schema = TableSchema {
             name: "fake_table".to_string(),
             fields: IndexMap::from([("col1".to_string(), FieldSchema::I32)]),
-            indices: HashMap::from([("fake_index".to_string(), vec!["col1".to_string()])]),
+            indices: IndexMap::from([("fake_index".to_string(), vec!["col1".to_string()])]),
+            ..Default::default()
         };
 
         assert_find_insert_pos_in_index(&[[0], [0], [1], [1], [3], [3]], &[2], 4, &table_schema);
@@ -634,10 +2553,11 @@ mod test {
                 ("col1".to_string(), FieldSchema::I32),
                 ("col2".to_string(), FieldSchema::I32),
             ]),
-            indices: HashMap::from([(
+            indices: IndexMap::from([(
                 "fake_index".to_string(),
                 vec!["col1".to_string(), "col2".to_string()],
             )]),
+            ..Default::default()
         };
 
         #[rustfmt::skip]
@@ -0,0 +1,97 @@
+use std::path::Path;
+
+use crate::{
+    common::{Error, PBaseError},
+    schema::TableSchema,
+    table_opener::TableOpener,
+};
+
+///
+/// Caps on how far inserts and imports may grow a database directory.
+///
+/// Embedded deployments on small devices get an error instead of a full disk. Each cap is
+/// unlimited when None, as by default. Only added rows are checked, updates do not grow tables.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quota {
+    // Bytes of the data file of a table.
+    pub max_table_bytes: Option<u64>,
+    // Bytes of all files in the directory: data, indices, WAL, stats, ...
+    pub max_dir_bytes: Option<u64>,
+    pub max_table_rows: Option<usize>,
+}
+
+impl Quota {
+    ///
+    /// Checks that `rows` more rows fit the table. The directory grows by their data and index
+    /// entries (the WAL record is not counted).
+    ///
+    /// The directory size is summed from its files on every check, so a directory cap costs a
+    /// directory listing per insert.
+    ///
+    /// # Errors
+    ///
+    /// On file operations, or with the error of the first cap the rows would exceed.
+    pub fn check_rows(
+        &self,
+        table_opener: &TableOpener,
+        table_schema: &TableSchema,
+        rows: usize,
+    ) -> Result<(), Error> {
+        if *self == Self::default() {
+            return Ok(());
+        }
+
+        let row_byte_size = table_schema.row_byte_size();
+        let table_bytes = std::fs::metadata(table_opener.table_data_file_name(&table_schema.name))
+            .map_or(0, |metadata| metadata.len());
+        let new_table_bytes = u64::try_from(rows * row_byte_size)?;
+
+        if let Some(limit) = self.max_table_rows {
+            if usize::try_from(table_bytes)? / row_byte_size + rows > limit {
+                return Err(PBaseError::RowCountQuotaExceeded {
+                    table: table_schema.name.clone(),
+                    limit,
+                }
+                .into());
+            }
+        }
+
+        if let Some(limit) = self.max_table_bytes {
+            if table_bytes + new_table_bytes > limit {
+                return Err(PBaseError::TableSizeQuotaExceeded {
+                    table: table_schema.name.clone(),
+                    limit,
+                }
+                .into());
+            }
+        }
+
+        if let Some(limit) = self.max_dir_bytes {
+            let index_row_byte_size: usize = table_schema
+                .indices
+                .keys()
+                .map(|index_name| table_schema.index_row_byte_size(index_name))
+                .sum();
+            let new_dir_bytes = new_table_bytes + u64::try_from(rows * index_row_byte_size)?;
+            if dir_bytes(&table_opener.dir)? + new_dir_bytes > limit {
+                return Err(PBaseError::DirectorySizeQuotaExceeded { limit }.into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Total size of the files in the directory (tables keep no subdirectories).
+fn dir_bytes(dir: &Path) -> Result<u64, Error> {
+    let mut bytes = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let metadata = entry?.metadata()?;
+        if metadata.is_file() {
+            bytes += metadata.len();
+        }
+    }
+
+    Ok(bytes)
+}
@@ -0,0 +1,94 @@
+use indexmap::IndexMap;
+
+use crate::{operator::Row, schema::FieldSchema, value::Value};
+
+// Table key of the computed columns (scalar calls and subqueries) in nested rows, see
+// `ResultSet::nested_rows`.
+pub const COMPUTED_COLUMNS: &str = "";
+
+///
+/// A result row as the values of each table by field name, see `ResultSet::nested_rows`.
+///
+pub type NestedRow = IndexMap<String, IndexMap<String, Value>>;
+
+///
+/// A result column. `name` is the key of the column in the rows.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnInfo {
+    pub name: String,
+    // Table the column is read from, `None` for computed columns (scalar subqueries).
+    pub source: Option<String>,
+    pub field_schema: FieldSchema,
+}
+
+///
+/// Rows of a query with their columns, in result order (row keys follow the same order). The
+/// columns are known even when there are no rows.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ResultSet {
+    pub columns: Vec<ColumnInfo>,
+    pub rows: Vec<Row>,
+    // Whether rows past the select's row cap were dropped, see `RowCap`.
+    pub truncated: bool,
+}
+
+impl ResultSet {
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    #[must_use]
+    pub fn column(&self, name: &str) -> Option<&ColumnInfo> {
+        self.columns.iter().find(|column| column.name == name)
+    }
+
+    ///
+    /// The rows with a map of values per table instead of flat `table.field` keys, eg.
+    /// `{"users": {"id": 1}, "orders": {"id": 7}}`, tables and fields in result order. Computed
+    /// columns are under `COMPUTED_COLUMNS`, by alias.
+    ///
+    #[must_use]
+    pub fn nested_rows(&self) -> Vec<NestedRow> {
+        // Table and field name of each column.
+        let column_keys: Vec<(&str, &str)> = self
+            .columns
+            .iter()
+            .map(|column| {
+                column
+                    .source
+                    .as_ref()
+                    .map_or((COMPUTED_COLUMNS, column.name.as_str()), |table| {
+                        let field = column
+                            .name
+                            .strip_prefix(table.as_str())
+                            .and_then(|name| name.strip_prefix('.'));
+                        (table.as_str(), field.unwrap_or(&column.name))
+                    })
+            })
+            .collect();
+
+        self.rows
+            .iter()
+            .map(|row| {
+                let mut nested_row = NestedRow::new();
+                for ((table, field), column) in column_keys.iter().zip(&self.columns) {
+                    nested_row.entry((*table).to_string()).or_default().insert(
+                        (*field).to_string(),
+                        row.get(column.name.as_str())
+                            .cloned()
+                            .unwrap_or(Value::NULL),
+                    );
+                }
+                nested_row
+            })
+            .collect()
+    }
+}
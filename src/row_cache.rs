@@ -0,0 +1,400 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering as AtomicOrdering},
+        Mutex,
+    },
+};
+
+use crate::{
+    query::{CompareOp, RhsValue, SelectQuery},
+    result_set::ResultSet,
+    schema::TableSchema,
+    value::Value,
+};
+
+// Rows a row cache keeps by default.
+pub const ROW_CACHE_ROWS: usize = 256;
+
+///
+/// A unique index key of a table: table, unique index and the key's values in index field order.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RowCacheKey {
+    pub table: String,
+    pub index: String,
+    pub values: Vec<Value>,
+}
+
+impl RowCacheKey {
+    ///
+    /// The key a select looks up, when it is a point lookup: a plain select of a single table
+    /// with equality filters on exactly the fields of one of its unique indices.
+    ///
+    #[must_use]
+    pub fn of_point_lookup(query: &SelectQuery, table_schema: &TableSchema) -> Option<Self> {
+        if !is_plain_single_table_select(query) {
+            return None;
+        }
+
+        table_schema.unique_indices.iter().find_map(|index_name| {
+            let index_fields = table_schema.indices.get(index_name)?;
+            if index_fields.len() != query.filters.len() {
+                return None;
+            }
+
+            let values = index_fields
+                .iter()
+                .map(|index_field| {
+                    let filter = query
+                        .filters
+                        .iter()
+                        .find(|filter| filter.field.name == *index_field)?;
+                    let RhsValue::Value(value) = &filter.rhs else {
+                        return None;
+                    };
+                    table_schema.fields[index_field].coerce(value)
+                })
+                .collect::<Option<Vec<Value>>>()?;
+
+            Some(Self {
+                table: table_schema.name.clone(),
+                index: index_name.clone(),
+                values,
+            })
+        })
+    }
+}
+
+//
+// Whether the select returns whole rows of its table filtered by value equalities only, so its
+// result depends on nothing but the filter values. DISTINCT is ruled out with the rest: with a
+// field it returns that column alone, and the plain lookup of the key must not be served it.
+//
+fn is_plain_single_table_select(query: &SelectQuery) -> bool {
+    query.joins.is_empty()
+        && query.aliases.is_empty()
+        && query.derived_tables.is_empty()
+        && query.scalar_subqueries.is_empty()
+        && query.scalar_calls.is_empty()
+        && query.call_filters.is_empty()
+        && query.sample.is_none()
+        && !query.distinct
+        && query.distinct_field.is_none()
+        && query.limit != Some(0)
+        && query.into.is_none()
+        && !query.with_deleted
+        && query.filters.iter().all(|filter| {
+            filter.field.source == query.from
+                && filter.op == CompareOp::Eq
+                && matches!(filter.rhs, RhsValue::Value(_))
+        })
+}
+
+///
+/// LRU cache of the results of point lookups on unique indices, for read patterns that fetch the
+/// same few rows by key over and over.
+///
+/// Only found rows are cached, a lookup of a missing key always reads the table. Writes through
+/// the database invalidate the keys of every row they overwrite; inserted rows have new keys, so
+/// they cannot be cached yet. Each result is cached with the generation of its table (see
+/// `TableOpener::table_generation`), and only served at that generation: writes of other handles
+/// bump it too.
+///
+#[derive(Debug)]
+pub struct RowCache {
+    capacity: usize,
+    // Most recently used first, with the table generation the result was read at.
+    entries: Mutex<VecDeque<(RowCacheKey, u64, ResultSet)>>,
+    hits: AtomicU64,
+}
+
+impl RowCache {
+    #[must_use]
+    pub const fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(VecDeque::new()),
+            hits: AtomicU64::new(0),
+        }
+    }
+
+    ///
+    /// The cached result of the lookup, when it was read at the table's current generation. Results
+    /// of other generations are dropped.
+    ///
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    pub fn get(&self, key: &RowCacheKey, generation: u64) -> Option<ResultSet> {
+        let mut entries = self.entries.lock().unwrap();
+        let cache_pos = entries
+            .iter()
+            .position(|(cached_key, _, _)| cached_key == key)?;
+        let entry = entries.remove(cache_pos)?;
+        if entry.1 != generation {
+            return None;
+        }
+        let result = entry.2.clone();
+        entries.push_front(entry);
+        drop(entries);
+        self.hits.fetch_add(1, AtomicOrdering::Relaxed);
+
+        Some(result)
+    }
+
+    ///
+    /// Caches the result of the lookup, read at the table generation, unless it found no row.
+    ///
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    pub fn put(&self, key: RowCacheKey, generation: u64, result: &ResultSet) {
+        if self.capacity == 0 || result.len() != 1 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|(cached_key, _, _)| *cached_key != key);
+        entries.truncate(self.capacity - 1);
+        entries.push_front((key, generation, result.clone()));
+    }
+
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    pub fn invalidate(&self, key: &RowCacheKey) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|(cached_key, _, _)| cached_key != key);
+    }
+
+    ///
+    /// Drops the cached lookups of the table.
+    ///
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    pub fn invalidate_table(&self, table: &str) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|(cached_key, _, _)| cached_key.table != table);
+    }
+
+    ///
+    /// Drops every cached lookup.
+    ///
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    ///
+    /// Lookups answered from the cache so far.
+    ///
+    #[must_use]
+    pub fn hits(&self) -> u64 {
+        self.hits.load(AtomicOrdering::Relaxed)
+    }
+}
+
+impl Default for RowCache {
+    fn default() -> Self {
+        Self::new(ROW_CACHE_ROWS)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use indexmap::IndexMap;
+
+    use crate::{
+        pbase::PBase,
+        query::{
+            CompareOp, CreateTableQuery, FieldSelector, InsertQuery, RhsValue, RowFilter,
+            SelectQuery,
+        },
+        result_set::ResultSet,
+        schema::{FieldSchema, TableSchema},
+        value::Value,
+    };
+
+    use super::{RowCache, RowCacheKey};
+
+    #[test]
+    fn test_row_cache_key_of_point_lookup() {
+        let table_schema = TableSchema {
+            name: "t1".into(),
+            fields: IndexMap::from([
+                ("a".into(), FieldSchema::U8),
+                ("b".into(), FieldSchema::I32),
+            ]),
+            indices: IndexMap::from([
+                ("ab_index".into(), vec!["a".into(), "b".into()]),
+                ("b_index".into(), vec!["b".into()]),
+            ]),
+            unique_indices: vec!["ab_index".into()],
+            ..Default::default()
+        };
+        let filter = |name: &str, op, value| RowFilter {
+            field: FieldSelector {
+                name: name.into(),
+                source: "t1".into(),
+            },
+            op,
+            rhs: RhsValue::Value(value),
+        };
+        let query = |filters| SelectQuery {
+            from: "t1".into(),
+            filters,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Some(RowCacheKey {
+                table: "t1".into(),
+                index: "ab_index".into(),
+                values: vec![Value::U8(1), Value::I32(2)],
+            }),
+            RowCacheKey::of_point_lookup(
+                &query(vec![
+                    filter("b", CompareOp::Eq, Value::I32(2)),
+                    filter("a", CompareOp::Eq, Value::I32(1)),
+                ]),
+                &table_schema
+            )
+        );
+        // Not unique.
+        assert!(RowCacheKey::of_point_lookup(
+            &query(vec![filter("b", CompareOp::Eq, Value::I32(2))]),
+            &table_schema
+        )
+        .is_none());
+        assert!(RowCacheKey::of_point_lookup(
+            &query(vec![
+                filter("b", CompareOp::Lt, Value::I32(2)),
+                filter("a", CompareOp::Eq, Value::I32(1)),
+            ]),
+            &table_schema
+        )
+        .is_none());
+        // Out of the field's range.
+        assert!(RowCacheKey::of_point_lookup(
+            &query(vec![
+                filter("b", CompareOp::Eq, Value::I32(2)),
+                filter("a", CompareOp::Eq, Value::I32(300)),
+            ]),
+            &table_schema
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_row_cache() {
+        let key = |value| RowCacheKey {
+            table: "t1".into(),
+            index: "a_index".into(),
+            values: vec![Value::I32(value)],
+        };
+        let result = |value| ResultSet {
+            columns: vec![],
+            rows: vec![IndexMap::from([("t1.a".into(), Value::I32(value))])],
+            truncated: false,
+        };
+
+        let cache = RowCache::new(2);
+        cache.put(key(1), 0, &result(1));
+        cache.put(key(2), 0, &result(2));
+        cache.put(key(3), 0, &ResultSet::default());
+        assert_eq!(2, cache.len());
+
+        assert_eq!(Some(result(1)), cache.get(&key(1), 0));
+        cache.put(key(4), 0, &result(4));
+        assert_eq!(None, cache.get(&key(2), 0));
+        assert_eq!(Some(result(4)), cache.get(&key(4), 0));
+        assert_eq!(2, cache.hits());
+
+        cache.invalidate(&key(1));
+        assert_eq!(None, cache.get(&key(1), 0));
+        assert_eq!(1, cache.len());
+
+        // Results of another table generation are dropped.
+        assert_eq!(None, cache.get(&key(4), 1));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_distinct_lookup_is_not_cached() {
+        let db = PBase::new_temp().unwrap();
+        db.run_create_table_query(&CreateTableQuery {
+            schema: TableSchema {
+                name: "users".into(),
+                fields: IndexMap::from([
+                    ("id".into(), FieldSchema::I32),
+                    ("age".into(), FieldSchema::U8),
+                ]),
+                indices: IndexMap::from([("id_index".into(), vec!["id".into()])]),
+                unique_indices: vec!["id_index".into()],
+                ..Default::default()
+            },
+        })
+        .unwrap();
+        db.run_insert_query(&InsertQuery {
+            table: "users".into(),
+            values: [("id".into(), Value::I32(3)), ("age".into(), Value::U8(20))].into(),
+        })
+        .unwrap();
+
+        let lookup = SelectQuery {
+            from: "users".into(),
+            filters: vec![RowFilter {
+                field: FieldSelector {
+                    name: "id".into(),
+                    source: "users".into(),
+                },
+                op: CompareOp::Eq,
+                rhs: RhsValue::Value(Value::I32(3)),
+            }],
+            ..Default::default()
+        };
+        let distinct = db
+            .run_select_query(SelectQuery {
+                distinct_field: Some(FieldSelector {
+                    name: "age".into(),
+                    source: "users".into(),
+                }),
+                ..lookup.clone()
+            })
+            .unwrap();
+        assert_eq!(1, distinct.columns.len());
+        assert!(db.row_cache().is_empty());
+
+        let result = db.run_select_query(lookup).unwrap();
+        assert_eq!(2, result.columns.len());
+        assert_eq!(
+            vec![Value::I32(3), Value::U8(20)],
+            result.rows[0].values().cloned().collect::<Vec<_>>()
+        );
+        assert_eq!(0, db.row_cache().hits());
+    }
+}
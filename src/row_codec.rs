@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use crate::{schema::TableSchema, value::Value};
+
+///
+/// Encoding of table rows into the bytes of the data file.
+///
+/// Rows are addressed by their byte position (index entries point at them), so a codec encodes
+/// every row of a table to the same size, `row_byte_size`. Within that, the layout is the codec's:
+/// bit-packed fields, dictionary ids, and so on.
+///
+/// The database stores tables with `FixedWidthCodec`; other codecs are read and written through
+/// `TableReader`, `TableRowIterator` and `Table`.
+///
+pub trait RowCodec {
+    fn row_byte_size(&self, table_schema: &TableSchema) -> usize;
+
+    ///
+    /// The row's bytes. Missing fields are encoded as the codec's zero value.
+    ///
+    fn encode_row(&self, table_schema: &TableSchema, values: &HashMap<String, Value>) -> Vec<u8>;
+
+    ///
+    /// A field's value out of the row's bytes.
+    ///
+    /// # Panics
+    ///
+    /// When the table has no such field, or the bytes are shorter than a row.
+    fn decode_field(&self, table_schema: &TableSchema, row_bytes: &[u8], field: &str) -> Value;
+
+    ///
+    /// All values of the row, by field name.
+    ///
+    /// # Panics
+    ///
+    /// When the bytes are shorter than a row.
+    fn decode_row(&self, table_schema: &TableSchema, row_bytes: &[u8]) -> HashMap<String, Value> {
+        table_schema
+            .fields
+            .keys()
+            .map(|field| {
+                (
+                    field.clone(),
+                    self.decode_field(table_schema, row_bytes, field),
+                )
+            })
+            .collect()
+    }
+}
+
+///
+/// The layout tables are stored in: fields in schema order, each in the little endian bytes of
+/// its type.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FixedWidthCodec;
+
+impl RowCodec for FixedWidthCodec {
+    fn row_byte_size(&self, table_schema: &TableSchema) -> usize {
+        table_schema.row_byte_size()
+    }
+
+    fn encode_row(&self, table_schema: &TableSchema, values: &HashMap<String, Value>) -> Vec<u8> {
+        table_schema.data_row_to_bytes(values)
+    }
+
+    fn decode_field(&self, table_schema: &TableSchema, row_bytes: &[u8], field: &str) -> Value {
+        table_schema
+            .stored_value_from_bytes(field, &row_bytes[table_schema.field_byte_pos(field)..])
+    }
+
+    fn decode_row(&self, table_schema: &TableSchema, row_bytes: &[u8]) -> HashMap<String, Value> {
+        table_schema.parse_row_bytes(row_bytes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use indexmap::IndexMap;
+
+    use crate::{
+        common::Selection,
+        schema::{FieldSchema, TableRowIterator, TableSchema},
+        value::Value,
+    };
+
+    use super::{FixedWidthCodec, RowCodec};
+
+    // Every field in one byte, for tables of small values.
+    struct ByteCodec;
+
+    impl RowCodec for ByteCodec {
+        fn row_byte_size(&self, table_schema: &TableSchema) -> usize {
+            table_schema.fields.len()
+        }
+
+        fn encode_row(
+            &self,
+            table_schema: &TableSchema,
+            values: &HashMap<String, Value>,
+        ) -> Vec<u8> {
+            table_schema
+                .fields
+                .keys()
+                .map(|field| match values.get(field) {
+                    Some(Value::I32(value)) => u8::try_from(*value).unwrap(),
+                    Some(Value::U8(value)) => *value,
+                    _ => 0,
+                })
+                .collect()
+        }
+
+        fn decode_field(&self, table_schema: &TableSchema, row_bytes: &[u8], field: &str) -> Value {
+            let field_pos = table_schema.fields.get_index_of(field).unwrap();
+            match table_schema.fields[field] {
+                FieldSchema::I32 => Value::I32(i32::from(row_bytes[field_pos])),
+                FieldSchema::U8 => Value::U8(row_bytes[field_pos]),
+                FieldSchema::Decimal { .. } | FieldSchema::Custom(_) => Value::NULL,
+            }
+        }
+    }
+
+    #[test]
+    fn test_row_codecs() {
+        let table_schema = TableSchema {
+            name: "t1".into(),
+            fields: IndexMap::from([
+                ("a".into(), FieldSchema::I32),
+                ("b".into(), FieldSchema::U8),
+            ]),
+            ..Default::default()
+        };
+        let rows = [
+            HashMap::from([("a".into(), Value::I32(1)), ("b".into(), Value::U8(2))]),
+            HashMap::from([("a".into(), Value::I32(3)), ("b".into(), Value::U8(4))]),
+        ];
+
+        let encode = |codec: &dyn RowCodec| -> Vec<u8> {
+            rows.iter()
+                .flat_map(|row| codec.encode_row(&table_schema, row))
+                .collect()
+        };
+        assert_eq!(vec![1, 2, 3, 4], encode(&ByteCodec));
+        assert_eq!(10, encode(&FixedWidthCodec).len());
+
+        let table_bytes = encode(&ByteCodec);
+        let decoded: Vec<HashMap<String, Value>> =
+            TableRowIterator::with_codec(&table_schema, &ByteCodec, &table_bytes, &Selection::All)
+                .map(|table_reader| table_reader.values())
+                .collect();
+        assert_eq!(rows.to_vec(), decoded);
+
+        let selection = Selection::List(vec![2]);
+        let mut it =
+            TableRowIterator::with_codec(&table_schema, &ByteCodec, &table_bytes, &selection);
+        assert_eq!(Value::U8(4), it.next().unwrap().get_field_value("b"));
+        assert!(it.next().is_none());
+
+        let table_bytes = encode(&FixedWidthCodec);
+        let reader = TableRowIterator::new(&table_schema, &table_bytes, &Selection::All)
+            .nth(1)
+            .unwrap();
+        assert_eq!(rows[1], reader.values());
+        assert_eq!(5, reader.absolute_pos);
+    }
+}
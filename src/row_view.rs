@@ -0,0 +1,212 @@
+use crate::{
+    operator::Row,
+    query::{CompareOp, RhsValue, RowFilter},
+    schema::TableSchema,
+    value::Value,
+};
+
+///
+/// A table row read in place from the table data. Unlike a materialized `Row` it owns nothing:
+/// field names are borrowed from the schema and values are decoded on access.
+///
+#[derive(Debug, Clone, Copy)]
+pub struct RowView<'a> {
+    table_schema: &'a TableSchema,
+    row_bytes: &'a [u8],
+    // Byte position of the row in the table data.
+    pub row_pos: usize,
+}
+
+impl<'a> RowView<'a> {
+    /// # Panics
+    ///
+    /// When no full row starts at `row_pos`.
+    #[must_use]
+    pub fn new(table_schema: &'a TableSchema, table_bytes: &'a [u8], row_pos: usize) -> Self {
+        Self {
+            table_schema,
+            row_bytes: &table_bytes[row_pos..row_pos + table_schema.row_byte_size()],
+            row_pos,
+        }
+    }
+
+    #[must_use]
+    pub fn table(&self) -> &'a str {
+        &self.table_schema.name
+    }
+
+    #[must_use]
+    pub fn get(&self, field_name: &str) -> Option<Value> {
+        self.table_schema.fields.get(field_name).map(|_| {
+            let field_pos = self.table_schema.field_byte_pos(field_name);
+            self.table_schema
+                .stored_value_from_bytes(field_name, &self.row_bytes[field_pos..])
+        })
+    }
+
+    ///
+    /// Field names and values in schema order.
+    ///
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, Value)> + '_ {
+        let mut field_pos = 0;
+        self.table_schema.fields.keys().map(move |field_name| {
+            let value = self
+                .table_schema
+                .stored_value_from_bytes(field_name, &self.row_bytes[field_pos..]);
+            field_pos += self.table_schema.stored_byte_size(field_name);
+            (field_name.as_str(), value)
+        })
+    }
+
+    ///
+    /// Materializes the row, keyed by full field names like query results.
+    ///
+    #[must_use]
+    pub fn to_row(&self) -> Row {
+        self.iter()
+            .map(|(field_name, value)| (format!("{}.{field_name}", self.table()).into(), value))
+            .collect()
+    }
+
+    ///
+    /// Whether the row matches all (AND-ed) filters. Fields of other tables read as NULL.
+    ///
+    #[must_use]
+    pub fn matches(&self, filters: &[RowFilter]) -> bool {
+        let value_of = |source: &str, name: &str| {
+            if source == self.table() {
+                self.get(name).unwrap_or(Value::NULL)
+            } else {
+                Value::NULL
+            }
+        };
+
+        filters.iter().all(|filter| {
+            let lhs_value = value_of(&filter.field.source, &filter.field.name);
+            match &filter.rhs {
+                RhsValue::Ref(reference) | RhsValue::Interval { reference, .. } => {
+                    filter.matches_values(&lhs_value, &value_of(&reference.source, &reference.name))
+                }
+                RhsValue::Value(_)
+                | RhsValue::In(_)
+                | RhsValue::Range { .. }
+                | RhsValue::Subquery { .. } => filter.matches_value(&lhs_value),
+            }
+        })
+    }
+}
+
+///
+/// Filters prepared for the rows of a table.
+///
+/// Equality with a value on a dictionary encoded field (see `TableSchema::dictionary_columns`)
+/// compares the stored id of rows to the value's, without decoding them. Other filters are matched
+/// as in `RowView::matches`.
+///
+pub struct RowMatcher {
+    filters: Vec<RowFilter>,
+    // Byte position of the field in rows and the value's id, of the filters comparing ids. No id
+    // when the value is not in the dictionary: no row matches.
+    id_filters: Vec<(usize, Option<u8>)>,
+}
+
+impl RowMatcher {
+    #[must_use]
+    pub fn new(table_schema: &TableSchema, filters: Vec<RowFilter>) -> Self {
+        let (id_filters, filters) = filters.into_iter().partition::<Vec<_>, _>(|filter| {
+            filter.op == CompareOp::Eq
+                && filter.field.source == table_schema.name
+                && matches!(filter.rhs, RhsValue::Value(_))
+                && table_schema.is_dictionary_column(&filter.field.name)
+        });
+        let id_filters = id_filters
+            .into_iter()
+            .map(|filter| {
+                let RhsValue::Value(value) = &filter.rhs else {
+                    unreachable!("Id filters compare to values")
+                };
+                let field_name = &filter.field.name;
+                let id = table_schema.fields[field_name]
+                    .coerce(value)
+                    .and_then(|value| table_schema.dictionaries[field_name].id(&value));
+                (table_schema.field_byte_pos(field_name), id)
+            })
+            .collect();
+
+        Self {
+            filters,
+            id_filters,
+        }
+    }
+
+    ///
+    /// Whether the row, of the table the matcher was prepared for, matches all (AND-ed) filters.
+    ///
+    #[must_use]
+    pub fn matches(&self, row_view: &RowView<'_>) -> bool {
+        self.id_filters
+            .iter()
+            .all(|(field_pos, id)| *id == Some(row_view.row_bytes[*field_pos]))
+            && row_view.matches(&self.filters)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use indexmap::IndexMap;
+
+    use crate::{
+        query::{CompareOp, FieldSelector, RhsValue, RowFilter},
+        schema::{FieldSchema, TableSchema},
+        value::Value,
+    };
+
+    use super::RowView;
+
+    #[test]
+    fn test_row_view() {
+        let table_schema = TableSchema {
+            name: "t1".to_string(),
+            fields: IndexMap::from([
+                ("f1".to_string(), FieldSchema::I32),
+                ("f2".to_string(), FieldSchema::U8),
+            ]),
+            indices: IndexMap::new(),
+            ..Default::default()
+        };
+
+        #[rustfmt::skip]
+        let table_bytes: [u8; 10] = [
+            1, 0, 0, 0,   2, // Row 1
+            3, 0, 0, 0,   3, // Row 2
+        ];
+
+        let row_view = RowView::new(&table_schema, &table_bytes, 5);
+        assert_eq!(Some(Value::I32(3)), row_view.get("f1"));
+        assert_eq!(None, row_view.get("f3"));
+        assert_eq!(
+            vec![("f1", Value::I32(3)), ("f2", Value::U8(3))],
+            row_view.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            IndexMap::from([
+                ("t1.f1".into(), Value::I32(3)),
+                ("t1.f2".into(), Value::U8(3)),
+            ]),
+            row_view.to_row()
+        );
+
+        let field = |name: &str| FieldSelector {
+            name: name.into(),
+            source: "t1".into(),
+        };
+        let f1_greater_than = |value| RowFilter {
+            field: field("f1"),
+            op: CompareOp::Gt,
+            rhs: RhsValue::Value(Value::I32(value)),
+        };
+        assert!(row_view.matches(&[f1_greater_than(2)]));
+        assert!(!row_view.matches(&[f1_greater_than(2), f1_greater_than(3)]));
+        assert!(!RowView::new(&table_schema, &table_bytes, 0).matches(&[f1_greater_than(2)]));
+    }
+}
@@ -1,16 +1,38 @@
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-
-use crate::{common::Selection, value::Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{Display, Formatter},
+};
+
+use log::debug;
+
+use crate::{
+    common::{Error, PBaseError, Selection},
+    custom_type::{CustomType, TypeDefs},
+    decimal::{decimal_byte_size, Decimal, MAX_DECIMAL_PRECISION},
+    dictionary::Dictionary,
+    query::SettingValue,
+    row_codec::{FixedWidthCodec, RowCodec},
+    table_opener::IoStrategy,
+    value::Value,
+};
 
 pub type TablePtrType = u64;
 pub const TABLE_PTR_BYTE_SIZE: usize = std::mem::size_of::<TablePtrType>();
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+// Row version of versioned tables, maintained by the database (see `TableSchema::versioned`).
+pub const VERSION_FIELD: &str = "_version";
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub enum FieldSchema {
     U8,
     I32,
+    // Fixed-point numbers of `precision` digits, `scale` of them after the point (`DECIMAL(10, 2)`
+    // holds up to 99999999.99), stored exactly as integers (see `decimal_byte_size`).
+    Decimal { precision: u8, scale: u8 },
+    // A type registered by the application, see `PBase::register_type`.
+    Custom(CustomType),
 }
 
 impl FieldSchema {
@@ -19,6 +41,8 @@ impl FieldSchema {
         match self {
             Self::U8 => 1,
             Self::I32 => 4,
+            Self::Decimal { precision, .. } => decimal_byte_size(*precision),
+            Self::Custom(custom_type) => custom_type.byte_size,
         }
     }
 
@@ -36,21 +60,506 @@ impl FieldSchema {
                 );
                 Value::I32(value)
             }
+            Self::Decimal { scale, .. } => {
+                let unscaled = if value_bytes.len() == 8 {
+                    i128::from(i64::from_le_bytes(
+                        value_bytes.try_into().expect("slice with incorrect length"),
+                    ))
+                } else {
+                    i128::from_le_bytes(
+                        value_bytes.try_into().expect("slice with incorrect length"),
+                    )
+                };
+                Value::Decimal(Decimal::new(unscaled, *scale))
+            }
+            Self::Custom(custom_type) => custom_type.value_from_bytes(value_bytes),
+        }
+    }
+}
+
+impl FieldSchema {
+    ///
+    /// Value stored for missing fields by lenient inserts.
+    ///
+    #[must_use]
+    pub fn default_value(&self) -> Value {
+        match self {
+            Self::U8 => Value::U8(0),
+            Self::I32 => Value::I32(0),
+            Self::Decimal { scale, .. } => Value::Decimal(Decimal::new(0, *scale)),
+            Self::Custom(custom_type) => {
+                custom_type.value_from_bytes(&vec![0; custom_type.byte_size])
+            }
+        }
+    }
+
+    ///
+    /// The value converted to this type, if it is representable (NULL is not). Numbers convert
+    /// exactly or not at all: decimals to integers only when whole, and to decimals only when the
+    /// digits fit the precision and scale.
+    ///
+    #[must_use]
+    pub fn coerce(&self, value: &Value) -> Option<Value> {
+        let whole = |v: &Decimal| {
+            let v = v.normalized();
+            (v.scale == 0).then_some(v.unscaled)
+        };
+        match (self, value) {
+            (Self::U8, Value::U8(v)) => Some(Value::U8(*v)),
+            (Self::U8, Value::I32(v)) => u8::try_from(*v).ok().map(Value::U8),
+            (Self::U8, Value::Decimal(v)) => u8::try_from(whole(v)?).ok().map(Value::U8),
+            (Self::I32, Value::I32(v)) => Some(Value::I32(*v)),
+            (Self::I32, Value::U8(v)) => Some(Value::I32(i32::from(*v))),
+            (Self::I32, Value::Decimal(v)) => i32::try_from(whole(v)?).ok().map(Value::I32),
+            (
+                Self::Decimal { precision, scale },
+                Value::I32(_) | Value::U8(_) | Value::Decimal(_),
+            ) => {
+                let decimal = match value {
+                    Value::I32(v) => Decimal::from(*v),
+                    Value::U8(v) => Decimal::from(i32::from(*v)),
+                    Value::Decimal(v) => v.normalized(),
+                    _ => unreachable!("Matched numbers only"),
+                };
+                decimal
+                    .rescaled(*scale)
+                    .filter(|decimal| decimal.digits() <= u32::from(*precision))
+                    .map(Value::Decimal)
+            }
+            (Self::Custom(custom_type), value) => custom_type.coerce(value),
+            (_, Value::NULL | Value::Custom(_)) => None,
+        }
+    }
+
+    ///
+    /// Whether filters can compare values of the type to the value: NULL, values of the type,
+    /// numbers to decimals, and custom types to the plain values they encode.
+    ///
+    #[must_use]
+    pub fn is_comparable_to(&self, value: &Value) -> bool {
+        match (self, value) {
+            (_, Value::NULL)
+            | (Self::U8, Value::U8(_))
+            | (Self::I32, Value::I32(_))
+            | (Self::U8 | Self::I32 | Self::Decimal { .. }, Value::Decimal(_))
+            | (Self::Decimal { .. }, Value::I32(_) | Value::U8(_)) => true,
+            (Self::Custom(custom_type), value) => custom_type.coerce(value).is_some(),
+            _ => false,
+        }
+    }
+
+    #[must_use]
+    pub fn is_type_of(&self, value: &Value) -> bool {
+        match (self, value) {
+            (Self::U8, Value::U8(_)) | (Self::I32, Value::I32(_)) => true,
+            (Self::Decimal { precision, scale }, Value::Decimal(v)) => {
+                v.scale == *scale && v.digits() <= u32::from(*precision)
+            }
+            (Self::Custom(custom_type), Value::Custom(custom_value)) => {
+                custom_value.type_name == custom_type.name
+            }
+            _ => false,
+        }
+    }
+
+    ///
+    /// The field schema with the definition of its custom type resolved (see
+    /// `CustomType::resolve`), other types as they are.
+    ///
+    /// # Errors
+    ///
+    /// Errors when the custom type is not registered, or no longer has the byte size the field was
+    /// created with, and for decimals of more than `MAX_DECIMAL_PRECISION` digits or of a scale
+    /// over their precision.
+    pub fn resolve(&self, type_defs: &TypeDefs, created: bool) -> Result<Self, Error> {
+        match self {
+            Self::Custom(custom_type) => Ok(Self::Custom(custom_type.resolve(type_defs, created)?)),
+            Self::Decimal { precision, scale }
+                if *precision == 0 || *precision > MAX_DECIMAL_PRECISION || scale > precision =>
+            {
+                Err(PBaseError::InvalidArgument(format!(
+                    "DECIMAL({precision}, {scale}) is not a supported precision and scale"
+                ))
+                .into())
+            }
+            _ => Ok(self.clone()),
+        }
+    }
+}
+
+///
+/// How inserts treat values that do not match the table schema.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InsertMode {
+    /// Rejects unknown fields, missing fields and values not of the field's type (NULL included).
+    Strict,
+    /// Drops unknown fields, fills missing and NULL fields with the type's default and converts
+    /// values that fit the field's type (eg. an I32 of 7 into a U8 field).
+    #[default]
+    Lenient,
+}
+
+///
+/// How rows are laid out in the data file.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TableLayout {
+    // Rows back to back, each field in the bytes of its type (see `FixedWidthCodec`).
+    #[default]
+    Row,
+    // The values of each field stored together.
+    Columnar,
+}
+
+///
+/// How the data and index files are compressed.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Lz4,
+}
+
+///
+/// Storage options of a table, set on create with `CREATE TABLE ... WITH (option=value, ...)`.
+///
+/// The storage layer writes tables in the `Row` layout, uncompressed: creating a table with
+/// another layout or compression fails (see `check_supported`), the options are there for the
+/// schema files of storage engines that support them.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageOptions {
+    #[serde(default)]
+    pub layout: TableLayout,
+    #[serde(default)]
+    pub compression: Compression,
+    // Bytes of the row pointers of index entries, 4 or 8. Tables with 4 byte pointers have
+    // smaller indices, and data files of at most 4 GiB.
+    #[serde(default = "default_ptr_width")]
+    pub ptr_width: usize,
+}
+
+const fn default_ptr_width() -> usize {
+    TABLE_PTR_BYTE_SIZE
+}
+
+impl Default for StorageOptions {
+    fn default() -> Self {
+        Self {
+            layout: TableLayout::default(),
+            compression: Compression::default(),
+            ptr_width: default_ptr_width(),
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+impl StorageOptions {
+    ///
+    /// Sets an option from its SQL name and value: `layout` ('row' or 'columnar'), `compression`
+    /// ('none' or 'lz4') or `ptr_width` (4 or 8).
+    ///
+    /// # Errors
+    ///
+    /// On unknown options, and values not valid for the option.
+    pub fn set(&mut self, name: &str, value: &SettingValue) -> Result<(), Error> {
+        let invalid = || PBaseError::InvalidSettingValue {
+            name: name.to_string(),
+            value: value.to_string(),
+        };
+        match (name, value) {
+            ("layout", SettingValue::Word(word)) => {
+                self.layout =
+                    serde_json::from_value(word.as_str().into()).map_err(|_| invalid())?;
+            }
+            ("compression", SettingValue::Word(word)) => {
+                self.compression =
+                    serde_json::from_value(word.as_str().into()).map_err(|_| invalid())?;
+            }
+            ("ptr_width", SettingValue::Int(width @ (4 | 8))) => {
+                self.ptr_width = usize::try_from(*width)?;
+            }
+            ("layout" | "compression" | "ptr_width", _) => return Err(invalid().into()),
+            _ => return Err(PBaseError::UnknownSetting(name.to_string()).into()),
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Fails for the options the storage layer does not implement.
+    ///
+    /// # Errors
+    ///
+    /// With `PBaseError::UnsupportedStorageOption` for a layout other than `Row`, or compressed
+    /// tables.
+    pub fn check_supported(&self) -> Result<(), Error> {
+        if self.layout != TableLayout::Row {
+            return Err(
+                PBaseError::UnsupportedStorageOption(format!("layout {:?}", self.layout)).into(),
+            );
+        }
+        if self.compression != Compression::None {
+            return Err(PBaseError::UnsupportedStorageOption(format!(
+                "compression {:?}",
+                self.compression
+            ))
+            .into());
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Bytes the data file can grow to with rows the row pointers can address.
+    ///
+    #[must_use]
+    pub const fn max_data_bytes(&self) -> u64 {
+        if self.ptr_width >= TABLE_PTR_BYTE_SIZE {
+            u64::MAX
+        } else {
+            1 << (self.ptr_width * 8)
+        }
+    }
+
+    #[must_use]
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+///
+/// Value an insert gives a field it leaves out (or sets to NULL), instead of the type's default.
+/// Evaluated for each inserted row, see `PBase::run_insert_query` and `PBase::copy_rows`.
+///
+/// In SQL: `CREATE TABLE t (id I32 DEFAULT next_id(), created I32 DEFAULT now(), ...)`, a scalar
+/// function as `f(7)` or `f()` (called with NULL), a constant as `7`.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DefaultExpr {
+    Value(Value),
+    // Unix time of the insert in seconds, for I32 fields.
+    Now,
+    // One more than the highest value of the field in the table, 0 in an empty table.
+    NextId,
+    // A scalar function registered with the database (see `PBase::register_scalar`), called
+    // with the argument.
+    Scalar { function: String, arg: Value },
+}
+
+impl DefaultExpr {
+    ///
+    /// Fails when the expression cannot produce values of the field's type.
+    ///
+    /// # Errors
+    ///
+    /// With `PBaseError::FieldTypeMismatch` for constants not of the type, and `now()` on fields
+    /// other than I32.
+    pub fn check_type(&self, field_name: &str, field_schema: &FieldSchema) -> Result<(), Error> {
+        let mismatch = |value: String| PBaseError::FieldTypeMismatch {
+            field: field_name.to_string(),
+            value,
+        };
+        match self {
+            Self::Value(value) if field_schema.coerce(value).is_none() => {
+                Err(mismatch(value.to_string()).into())
+            }
+            Self::Now if *field_schema != FieldSchema::I32 => {
+                Err(mismatch(self.to_string()).into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Display for DefaultExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Value(value) => write!(f, "{value}"),
+            Self::Now => write!(f, "now()"),
+            Self::NextId => write!(f, "next_id()"),
+            Self::Scalar {
+                function,
+                arg: Value::NULL,
+            } => write!(f, "{function}()"),
+            Self::Scalar { function, arg } => write!(f, "{function}({arg})"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
 pub struct TableSchema {
     pub name: String,
     pub fields: IndexMap<String, FieldSchema>,
-    pub indices: HashMap<String, Vec<String>>,
+    pub indices: IndexMap<String, Vec<String>>,
+    // Indices whose keys identify at most one row, enforced on writes. Point lookups on them are
+    // cached (see `RowCache`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unique_indices: Vec<String>,
+    // Field holding the owning tenant's id, for tables shared by tenants (see
+    // `TenantScopedPBase`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant_column: Option<String>,
+    // How the table's files are read, the database's strategy when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub io_strategy: Option<IoStrategy>,
+    // Whether rows have a `VERSION_FIELD` (I32, added on create) for optimistic concurrency: rows
+    // are inserted with version 1, and an update must pass the row's current version, which it
+    // increments (see `PBase::update_row_at`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub versioned: bool,
+    // U8 or I32 field marking deleted rows: `PBase::run_delete_query` sets it (to 1, or the
+    // deletion's unix time) instead of removing the row, and selects skip rows where it is not 0
+    // unless they are `with_deleted`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub soft_delete_column: Option<String>,
+    // Whether the engine records the table's mutations in the audit table (see `AUDIT_TABLE`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub audited: bool,
+    // Fields whose rows store a one byte id into the field's dictionary instead of the value,
+    // chosen by `PBase::compact_table` for low cardinality fields.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dictionary_columns: Vec<String>,
+    // The dictionaries of `dictionary_columns`, kept in the table's sidecar file and loaded with
+    // the schema (see `TableOpener::open_schema`).
+    #[serde(skip)]
+    pub dictionaries: BTreeMap<String, Dictionary>,
+    #[serde(default, skip_serializing_if = "StorageOptions::is_default")]
+    pub storage: StorageOptions,
+    // Expressions giving the fields inserts leave out their value, by field.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub defaults: IndexMap<String, DefaultExpr>,
+    // Fingerprint of the row layout the data is stored with (see `row_layout_hash`), set whenever
+    // the database writes the schema. Fields edited by hand without migrating the data no longer
+    // match it, see `check_row_layout`. Not set in schemas written before it existed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout_hash: Option<u64>,
 }
 
 impl TableSchema {
     #[must_use]
     pub fn row_byte_size(&self) -> usize {
-        self.fields.values().map(FieldSchema::byte_size).sum()
+        self.fields
+            .keys()
+            .map(|field_name| self.stored_byte_size(field_name))
+            .sum()
+    }
+
+    ///
+    /// Bytes of the field in rows: one for dictionary encoded fields, the size of the type
+    /// otherwise.
+    ///
+    /// # Panics
+    ///
+    /// When field is not found.
+    #[must_use]
+    pub fn stored_byte_size(&self, field_name: &str) -> usize {
+        if self.is_dictionary_column(field_name) {
+            1
+        } else {
+            self.fields[field_name].byte_size()
+        }
+    }
+
+    ///
+    /// Fingerprint of the row layout: the stored type and size of each field, in row order. Field
+    /// names are not part of it, renaming a field keeps the data readable.
+    ///
+    #[must_use]
+    pub fn row_layout_hash(&self) -> u64 {
+        // FNV-1a: unlike `DefaultHasher`, stable across builds, the hash is stored.
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for (field_name, field_schema) in &self.fields {
+            for byte_size in [field_schema.byte_size(), self.stored_byte_size(field_name)] {
+                hash = (hash ^ byte_size as u64).wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+        hash
+    }
+
+    ///
+    /// Records the current row layout, done by the database before writing the schema.
+    ///
+    pub fn set_layout_hash(&mut self) {
+        self.layout_hash = Some(self.row_layout_hash());
+    }
+
+    ///
+    /// Verifies that the fields still have the layout the data was written with.
+    ///
+    /// # Errors
+    ///
+    /// `PBaseError::SchemaLayoutMismatch` when the fields changed since the database last wrote
+    /// the schema, eg. a field was added to the schema file by hand.
+    pub fn check_row_layout(&self) -> Result<(), PBaseError> {
+        match self.layout_hash {
+            Some(layout_hash) if layout_hash != self.row_layout_hash() => {
+                Err(PBaseError::SchemaLayoutMismatch(self.name.clone()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    ///
+    /// Resolves the custom types of the fields (see `FieldSchema::resolve`): to their registered
+    /// byte sizes for new tables, to their definitions for created ones.
+    ///
+    /// # Errors
+    ///
+    /// Errors when a custom type is not registered, or no longer has the byte size the table was
+    /// created with.
+    pub fn resolve_types(&mut self, type_defs: &TypeDefs, created: bool) -> Result<(), Error> {
+        for field_schema in self.fields.values_mut() {
+            *field_schema = field_schema.resolve(type_defs, created)?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// Verifies the default expressions: they are of fields of the table, and produce values of
+    /// the field's type.
+    ///
+    /// # Errors
+    ///
+    /// With `PBaseError::UnknownField` for a default of a field not in the table, as
+    /// `DefaultExpr::check_type` otherwise.
+    pub fn check_defaults(&self) -> Result<(), Error> {
+        for (field_name, default_expr) in &self.defaults {
+            let field_schema = self
+                .fields
+                .get(field_name)
+                .ok_or_else(|| PBaseError::UnknownField(field_name.clone()))?;
+            default_expr.check_type(field_name, field_schema)?;
+        }
+
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn is_dictionary_column(&self, field_name: &str) -> bool {
+        self.dictionary_columns
+            .iter()
+            .any(|column| column == field_name)
+    }
+
+    ///
+    /// The field's value from the bytes at its position in a row, looked up in the dictionary of
+    /// encoded fields.
+    ///
+    /// # Panics
+    ///
+    /// When field is not found, the bytes are too short, or the id is not in the dictionary.
+    #[must_use]
+    pub fn stored_value_from_bytes(&self, field_name: &str, bytes: &[u8]) -> Value {
+        if self.is_dictionary_column(field_name) {
+            self.dictionaries[field_name].value(bytes[0]).clone()
+        } else {
+            self.fields[field_name].value_from_bytes(bytes)
+        }
     }
 
     /// # Panics
@@ -59,12 +568,12 @@ impl TableSchema {
     #[must_use]
     pub fn field_byte_pos(&self, field_name: &str) -> usize {
         let mut pos = 0usize;
-        for (schema_field_name, field_schema) in &self.fields {
+        for schema_field_name in self.fields.keys() {
             if schema_field_name == field_name {
                 return pos;
             }
 
-            pos += field_schema.byte_size();
+            pos += self.stored_byte_size(schema_field_name);
         }
 
         panic!("Field '{}' not found in table '{}'", field_name, self.name)
@@ -86,20 +595,121 @@ impl TableSchema {
             .map(|index_field_name| self.fields[index_field_name].byte_size())
             .sum();
 
-        fields_total_byte_len + TABLE_PTR_BYTE_SIZE
+        fields_total_byte_len + self.storage.ptr_width
+    }
+
+    ///
+    /// The insert values as a full row of the table, checked or converted according to the mode.
+    /// The version of versioned tables is set to 1, regardless of the values.
+    ///
+    /// # Errors
+    ///
+    /// Errors on fields or values the mode does not accept.
+    pub fn conform_row(
+        &self,
+        values: &HashMap<String, Value>,
+        mode: InsertMode,
+    ) -> Result<HashMap<String, Value>, Error> {
+        if let Some(field_name) = values
+            .keys()
+            .find(|field_name| !self.fields.contains_key(*field_name))
+        {
+            match mode {
+                InsertMode::Strict => {
+                    return Err(PBaseError::UnknownField(field_name.clone()).into())
+                }
+                InsertMode::Lenient => debug!("Dropping unknown insert field: {field_name}"),
+            }
+        }
+
+        let mut row = HashMap::new();
+        for (field_name, field_schema) in &self.fields {
+            if self.versioned && field_name == VERSION_FIELD {
+                row.insert(field_name.clone(), Value::I32(1));
+                continue;
+            }
+
+            let mismatch = |value: &Value| PBaseError::FieldTypeMismatch {
+                field: field_name.clone(),
+                value: value.to_string(),
+            };
+            let value = match (values.get(field_name), mode) {
+                (Some(value), InsertMode::Strict) if field_schema.is_type_of(value) => {
+                    value.clone()
+                }
+                (Some(value), InsertMode::Strict) => return Err(mismatch(value).into()),
+                (None, InsertMode::Strict) => {
+                    return Err(PBaseError::MissingField(field_name.clone()).into())
+                }
+                (None | Some(Value::NULL), InsertMode::Lenient) => field_schema.default_value(),
+                (Some(value), InsertMode::Lenient) => {
+                    field_schema.coerce(value).ok_or_else(|| mismatch(value))?
+                }
+            };
+            row.insert(field_name.clone(), value);
+        }
+
+        Ok(row)
     }
 
+    ///
+    /// The row as stored. Missing fields are stored as zero bytes, or as the id of the type's
+    /// default value for dictionary encoded fields.
+    ///
+    /// # Panics
+    ///
+    /// When a value of a dictionary encoded field (or its default) is not in its dictionary, see
+    /// `PBase::insert_row` for how dictionaries are extended before writes.
     #[must_use]
     pub fn data_row_to_bytes(&self, values: &HashMap<String, Value>) -> Vec<u8> {
         let mut bytes = vec![0; self.row_byte_size()];
 
         for (field_name, field_value) in values {
-            field_value.copy_bytes_to(&mut bytes[self.field_byte_pos(field_name)..]);
+            if !self.is_dictionary_column(field_name) {
+                let pos = self.field_byte_pos(field_name);
+                field_value.copy_bytes_to(&mut bytes[pos..pos + self.stored_byte_size(field_name)]);
+            }
+        }
+        for field_name in &self.dictionary_columns {
+            let value = self.dictionary_value(field_name, values);
+            bytes[self.field_byte_pos(field_name)] =
+                self.dictionaries[field_name].id(&value).unwrap_or_else(|| {
+                    panic!("Value {value} of {field_name} is not in the dictionary")
+                });
         }
 
         bytes
     }
 
+    ///
+    /// The value a row stores in the dictionary encoded field: its value as the field's type, or
+    /// the default of the type when missing, NULL or not representable.
+    ///
+    /// # Panics
+    ///
+    /// When field is not found.
+    #[must_use]
+    pub fn dictionary_value(&self, field_name: &str, values: &HashMap<String, Value>) -> Value {
+        let field_schema = &self.fields[field_name];
+        values
+            .get(field_name)
+            .and_then(|value| field_schema.coerce(value))
+            .unwrap_or_else(|| field_schema.default_value())
+    }
+
+    ///
+    /// The schema with every field stored as its type: the layout of copy streams and of tables
+    /// before `PBase::compact_table` encodes them.
+    ///
+    #[must_use]
+    pub fn without_dictionaries(&self) -> Self {
+        Self {
+            dictionary_columns: vec![],
+            dictionaries: BTreeMap::new(),
+            ..self.clone()
+        }
+    }
+
     #[must_use]
     pub fn index_row_to_bytes(
         &self,
@@ -113,13 +723,14 @@ impl TableSchema {
         for index_field in &self.indices[index_name] {
             let field_byte_size = self.fields[index_field].byte_size();
             if let Some(value) = values.get(index_field) {
-                value.copy_bytes_to(&mut out[pos..]);
+                value.copy_bytes_to(&mut out[pos..pos + field_byte_size]);
             }
 
             pos += field_byte_size;
         }
 
-        out[pos..pos + TABLE_PTR_BYTE_SIZE].copy_from_slice(&row_ptr.to_le_bytes());
+        let ptr_width = self.storage.ptr_width;
+        out[pos..pos + ptr_width].copy_from_slice(&row_ptr.to_le_bytes()[..ptr_width]);
 
         out
     }
@@ -140,7 +751,45 @@ impl TableSchema {
 
     #[must_use]
     pub fn index_row_ptr_field_byte_pos(&self, index_name: &str) -> usize {
-        self.index_row_byte_size(index_name) - TABLE_PTR_BYTE_SIZE
+        self.index_row_byte_size(index_name) - self.storage.ptr_width
+    }
+
+    ///
+    /// The row pointer at the start of the bytes, of the table's pointer width.
+    ///
+    /// # Panics
+    ///
+    /// When the bytes are shorter than a pointer.
+    #[must_use]
+    pub fn index_row_ptr_from_bytes(&self, bytes: &[u8]) -> TablePtrType {
+        let mut ptr_bytes = [0; TABLE_PTR_BYTE_SIZE];
+        ptr_bytes[..self.storage.ptr_width].copy_from_slice(&bytes[..self.storage.ptr_width]);
+        TablePtrType::from_le_bytes(ptr_bytes)
+    }
+
+    ///
+    /// Values (in index field order) and row pointer of an index row.
+    ///
+    /// # Panics
+    ///
+    /// When the bytes are shorter than an index row.
+    #[must_use]
+    pub fn parse_index_row_bytes(
+        &self,
+        index_name: &str,
+        bytes: &[u8],
+    ) -> (Vec<Value>, TablePtrType) {
+        let mut values = vec![];
+        let mut pos = 0usize;
+        for index_field in &self.indices[index_name] {
+            let field_schema = &self.fields[index_field];
+            values.push(field_schema.value_from_bytes(&bytes[pos..]));
+            pos += field_schema.byte_size();
+        }
+
+        let row_ptr = self.index_row_ptr_from_bytes(&bytes[pos..]);
+
+        (values, row_ptr)
     }
 
     #[must_use]
@@ -148,20 +797,24 @@ impl TableSchema {
         let mut out = HashMap::new();
 
         let mut pos = 0usize;
-        for (field_name, field_schema) in &self.fields {
+        for field_name in self.fields.keys() {
             out.insert(
                 field_name.clone(),
-                field_schema.value_from_bytes(&bytes[pos..]),
+                self.stored_value_from_bytes(field_name, &bytes[pos..]),
             );
-            pos += field_schema.byte_size();
+            pos += self.stored_byte_size(field_name);
         }
 
         out
     }
 }
 
-pub struct TableReader<'a> {
+///
+/// A row of table data, decoded field by field with the codec.
+///
+pub struct TableReader<'a, C: RowCodec = FixedWidthCodec> {
     table_schema: &'a TableSchema,
+    codec: &'a C,
     row_bytes: &'a [u8],
     pub absolute_pos: usize,
 }
@@ -172,9 +825,22 @@ impl<'a> TableReader<'a> {
         table_schema: &'a TableSchema,
         row_bytes: &'a [u8],
         absolute_pos: usize,
+    ) -> Self {
+        Self::with_codec(table_schema, &FixedWidthCodec, row_bytes, absolute_pos)
+    }
+}
+
+impl<'a, C: RowCodec> TableReader<'a, C> {
+    #[must_use]
+    pub const fn with_codec(
+        table_schema: &'a TableSchema,
+        codec: &'a C,
+        row_bytes: &'a [u8],
+        absolute_pos: usize,
     ) -> Self {
         Self {
             table_schema,
+            codec,
             row_bytes,
             absolute_pos,
         }
@@ -182,13 +848,22 @@ impl<'a> TableReader<'a> {
 
     #[must_use]
     pub fn get_field_value(&self, field: &str) -> Value {
-        let field_pos = self.table_schema.field_byte_pos(field);
-        self.table_schema.fields[field].value_from_bytes(&self.row_bytes[field_pos..])
+        self.codec
+            .decode_field(self.table_schema, self.row_bytes, field)
+    }
+
+    ///
+    /// All values of the row, by field name.
+    ///
+    #[must_use]
+    pub fn values(&self) -> HashMap<String, Value> {
+        self.codec.decode_row(self.table_schema, self.row_bytes)
     }
 }
 
-pub struct TableRowIterator<'a> {
+pub struct TableRowIterator<'a, C: RowCodec = FixedWidthCodec> {
     table_schema: &'a TableSchema,
+    codec: &'a C,
     table_bytes: &'a [u8],
     selection: &'a Selection,
     current_pos: usize,
@@ -200,48 +875,69 @@ impl<'a> TableRowIterator<'a> {
         table_schema: &'a TableSchema,
         table_bytes: &'a [u8],
         selection: &'a Selection,
+    ) -> Self {
+        Self::with_codec(table_schema, &FixedWidthCodec, table_bytes, selection)
+    }
+}
+
+impl<'a, C: RowCodec> TableRowIterator<'a, C> {
+    ///
+    /// Iterates rows encoded with the codec. Positions of the selection are byte positions of
+    /// encoded rows.
+    ///
+    #[must_use]
+    pub const fn with_codec(
+        table_schema: &'a TableSchema,
+        codec: &'a C,
+        table_bytes: &'a [u8],
+        selection: &'a Selection,
     ) -> Self {
         Self {
             table_schema,
+            codec,
             table_bytes,
             selection,
             current_pos: 0,
         }
     }
 
-    fn next_with_all_selection(&mut self) -> Option<TableReader<'a>> {
+    fn next_with_all_selection(&mut self) -> Option<TableReader<'a, C>> {
+        let row_byte_size = self.codec.row_byte_size(self.table_schema);
         if self.current_pos >= self.table_bytes.len() {
             None
         } else {
             let pos = self.current_pos;
-            self.current_pos += self.table_schema.row_byte_size();
+            self.current_pos += row_byte_size;
 
-            Some(TableReader::new(
+            Some(TableReader::with_codec(
                 self.table_schema,
-                &self.table_bytes[pos..pos + self.table_schema.row_byte_size()],
+                self.codec,
+                &self.table_bytes[pos..pos + row_byte_size],
                 pos,
             ))
         }
     }
 
-    fn next_with_positions(&mut self, positions: &[usize]) -> Option<TableReader<'a>> {
+    fn next_with_positions(&mut self, positions: &[usize]) -> Option<TableReader<'a, C>> {
         if self.current_pos >= positions.len() {
             None
         } else {
             let current_pos = positions[self.current_pos];
             self.current_pos += 1;
 
-            Some(TableReader::new(
+            Some(TableReader::with_codec(
                 self.table_schema,
-                &self.table_bytes[current_pos..current_pos + self.table_schema.row_byte_size()],
+                self.codec,
+                &self.table_bytes
+                    [current_pos..current_pos + self.codec.row_byte_size(self.table_schema)],
                 current_pos,
             ))
         }
     }
 }
 
-impl<'a> Iterator for TableRowIterator<'a> {
-    type Item = TableReader<'a>;
+impl<'a, C: RowCodec> Iterator for TableRowIterator<'a, C> {
+    type Item = TableReader<'a, C>;
 
     fn next(&mut self) -> Option<Self::Item> {
         match self.selection {
@@ -294,14 +990,15 @@ mod test {
 
     use crate::{schema::FieldSchema, value::Value};
 
-    use super::{TableRowIterator, TableSchema};
+    use super::{InsertMode, TableRowIterator, TableSchema};
 
     #[test]
     fn test_empty_table_schema() {
         let table_schema = TableSchema {
             name: "t1".to_string(),
             fields: IndexMap::from([]),
-            indices: HashMap::from([]),
+            indices: IndexMap::from([]),
+            ..Default::default()
         };
 
         assert_eq!(0, table_schema.row_byte_size());
@@ -313,7 +1010,8 @@ mod test {
         let table_schema = TableSchema {
             name: "t1".to_string(),
             fields: IndexMap::from([]),
-            indices: HashMap::from([]),
+            indices: IndexMap::from([]),
+            ..Default::default()
         };
 
         let _ = table_schema.field_byte_pos("missing");
@@ -325,7 +1023,8 @@ mod test {
         let table_schema = TableSchema {
             name: "t1".to_string(),
             fields: IndexMap::from([]),
-            indices: HashMap::from([]),
+            indices: IndexMap::from([]),
+            ..Default::default()
         };
         let _ = table_schema.index_row_byte_size("missing");
     }
@@ -339,10 +1038,11 @@ mod test {
                 ("f2".to_string(), FieldSchema::I32),
                 ("f3".to_string(), FieldSchema::I32),
             ]),
-            indices: HashMap::from([
+            indices: IndexMap::from([
                 ("i1".to_string(), vec!["f1".to_string(), "f2".to_string()]),
                 ("i2".to_string(), vec!["f3".to_string()]),
             ]),
+            ..Default::default()
         };
 
         assert_eq!(12, table_schema.row_byte_size());
@@ -400,10 +1100,11 @@ mod test {
                 ("f2".to_string(), FieldSchema::I32),
                 ("f3".to_string(), FieldSchema::I32),
             ]),
-            indices: HashMap::from([
+            indices: IndexMap::from([
                 ("i1".to_string(), vec!["f1".to_string(), "f2".to_string()]),
                 ("i2".to_string(), vec!["f3".to_string()]),
             ]),
+            ..Default::default()
         };
 
         let bytes: [u8; 12] = [1, 2, 3, 4, 5, 5, 5, 5, 6, 7, 8, 9];
@@ -422,7 +1123,8 @@ mod test {
                 ("f1".to_string(), FieldSchema::I32),
                 ("f2".to_string(), FieldSchema::I32),
             ]),
-            indices: HashMap::new(),
+            indices: IndexMap::new(),
+            ..Default::default()
         };
 
         #[rustfmt::skip]
@@ -453,7 +1155,8 @@ mod test {
                 ("f1".to_string(), FieldSchema::I32),
                 ("f2".to_string(), FieldSchema::I32),
             ]),
-            indices: HashMap::new(),
+            indices: IndexMap::new(),
+            ..Default::default()
         };
 
         #[rustfmt::skip]
@@ -478,4 +1181,121 @@ mod test {
 
         assert!(it.next().is_none());
     }
+
+    #[test]
+    fn test_conform_row() {
+        let table_schema = TableSchema {
+            name: "t1".to_string(),
+            fields: IndexMap::from([
+                ("f1".to_string(), FieldSchema::I32),
+                ("f2".to_string(), FieldSchema::U8),
+            ]),
+            indices: IndexMap::new(),
+            ..Default::default()
+        };
+        let values = |pairs: &[(&str, Value)]| -> HashMap<String, Value> {
+            pairs
+                .iter()
+                .map(|(name, value)| ((*name).to_string(), value.clone()))
+                .collect()
+        };
+
+        let full_row = values(&[("f1", Value::I32(-1)), ("f2", Value::U8(2))]);
+        assert_eq!(
+            full_row,
+            table_schema
+                .conform_row(&full_row, InsertMode::Strict)
+                .unwrap()
+        );
+        for invalid_row in [
+            values(&[("f1", Value::I32(-1))]),
+            values(&[("f1", Value::I32(-1)), ("f2", Value::I32(2))]),
+            values(&[("f1", Value::I32(-1)), ("f2", Value::NULL)]),
+            values(&[
+                ("f1", Value::I32(-1)),
+                ("f2", Value::U8(2)),
+                ("f3", Value::U8(3)),
+            ]),
+        ] {
+            assert!(table_schema
+                .conform_row(&invalid_row, InsertMode::Strict)
+                .is_err());
+        }
+
+        assert_eq!(
+            values(&[("f1", Value::I32(7)), ("f2", Value::U8(0))]),
+            table_schema
+                .conform_row(
+                    &values(&[("f1", Value::U8(7)), ("f3", Value::U8(3))]),
+                    InsertMode::Lenient
+                )
+                .unwrap()
+        );
+        assert_eq!(
+            values(&[("f1", Value::I32(0)), ("f2", Value::U8(200))]),
+            table_schema
+                .conform_row(
+                    &values(&[("f1", Value::NULL), ("f2", Value::I32(200))]),
+                    InsertMode::Lenient
+                )
+                .unwrap()
+        );
+        assert!(table_schema
+            .conform_row(&values(&[("f2", Value::I32(256))]), InsertMode::Lenient)
+            .is_err());
+    }
+
+    #[test]
+    fn test_indices_keep_declaration_order() {
+        let table_schema = TableSchema {
+            name: "t1".into(),
+            fields: IndexMap::from([
+                ("a".into(), FieldSchema::I32),
+                ("b".into(), FieldSchema::I32),
+            ]),
+            indices: IndexMap::from([
+                ("z_index".into(), vec!["b".into()]),
+                ("a_index".into(), vec!["a".into()]),
+            ]),
+            ..Default::default()
+        };
+
+        let json = serde_json::to_string(&table_schema).unwrap();
+        assert!(json.find("z_index").unwrap() < json.find("a_index").unwrap());
+        let parsed: TableSchema = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            vec!["z_index", "a_index"],
+            parsed.indices.keys().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_row_layout() {
+        let mut table_schema = TableSchema {
+            name: "t1".into(),
+            fields: IndexMap::from([
+                ("a".into(), FieldSchema::I32),
+                ("b".into(), FieldSchema::U8),
+            ]),
+            ..Default::default()
+        };
+        // Schemas written before the layout was recorded are trusted.
+        assert!(table_schema.check_row_layout().is_ok());
+
+        table_schema.set_layout_hash();
+        let json = serde_json::to_string(&table_schema).unwrap();
+        let mut parsed: TableSchema = serde_json::from_str(&json).unwrap();
+        assert!(parsed.check_row_layout().is_ok());
+
+        let (_, field_schema) = parsed.fields.shift_remove_entry("b").unwrap();
+        parsed.fields.insert("renamed".into(), field_schema);
+        assert!(parsed.check_row_layout().is_ok());
+
+        parsed.fields.insert("added".into(), FieldSchema::U8);
+        assert!(parsed.check_row_layout().is_err());
+
+        parsed.fields.shift_remove("added");
+        parsed.fields.insert("renamed".into(), FieldSchema::I32);
+        assert!(parsed.check_row_layout().is_err());
+    }
 }
@@ -0,0 +1,395 @@
+use std::fmt::{Display, Formatter};
+
+use crate::schema::{FieldSchema, TableSchema};
+
+///
+/// A difference between two schemas, as the change turning the first into the second. Displays as
+/// the ALTER (or CREATE, DROP) statement making the change.
+///
+/// Statements describe the change for a human applying it: pbase does not run them, and changes
+/// of stored fields mean rewriting the table data.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaChange {
+    CreateTable(Box<TableSchema>),
+    DropTable(String),
+    AddField {
+        table: String,
+        field: String,
+        field_schema: FieldSchema,
+    },
+    DropField {
+        table: String,
+        field: String,
+    },
+    ChangeFieldType {
+        table: String,
+        field: String,
+        from: FieldSchema,
+        to: FieldSchema,
+    },
+    // Same fields, stored in another order.
+    ReorderFields {
+        table: String,
+        fields: Vec<String>,
+    },
+    CreateIndex {
+        table: String,
+        index: String,
+        fields: Vec<String>,
+    },
+    DropIndex {
+        table: String,
+        index: String,
+    },
+    AddUnique {
+        table: String,
+        index: String,
+    },
+    DropUnique {
+        table: String,
+        index: String,
+    },
+    // A table option (`versioned`, `audited`, `soft_delete_column`, `tenant_column`,
+    // `io_strategy`) with its new value, NONE when unset.
+    SetOption {
+        table: String,
+        option: String,
+        value: String,
+    },
+}
+
+impl Display for SchemaChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::CreateTable(table_schema) => write!(
+                f,
+                "CREATE TABLE {} ({})",
+                table_schema.name,
+                table_schema
+                    .fields
+                    .iter()
+                    .map(|(field, field_schema)| format!("{field} {field_schema:?}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Self::DropTable(table) => write!(f, "DROP TABLE {table}"),
+            Self::AddField {
+                table,
+                field,
+                field_schema,
+            } => write!(f, "ALTER TABLE {table} ADD COLUMN {field} {field_schema:?}"),
+            Self::DropField { table, field } => {
+                write!(f, "ALTER TABLE {table} DROP COLUMN {field}")
+            }
+            Self::ChangeFieldType {
+                table, field, to, ..
+            } => write!(f, "ALTER TABLE {table} ALTER COLUMN {field} TYPE {to:?}"),
+            Self::ReorderFields { table, fields } => write!(
+                f,
+                "ALTER TABLE {table} REORDER COLUMNS ({})",
+                fields.join(", ")
+            ),
+            Self::CreateIndex {
+                table,
+                index,
+                fields,
+            } => write!(f, "CREATE INDEX {index} ON {table} ({})", fields.join(", ")),
+            Self::DropIndex { table, index } => write!(f, "DROP INDEX {index} ON {table}"),
+            Self::AddUnique { table, index } => {
+                write!(f, "ALTER TABLE {table} ADD UNIQUE ({index})")
+            }
+            Self::DropUnique { table, index } => {
+                write!(f, "ALTER TABLE {table} DROP UNIQUE ({index})")
+            }
+            Self::SetOption {
+                table,
+                option,
+                value,
+            } => write!(f, "ALTER TABLE {table} SET ({option} = {value})"),
+        }
+    }
+}
+
+///
+/// The changes turning one set of table schemas into another, see `PBase::schema_diff`. Empty when
+/// they match.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchemaDiff {
+    pub changes: Vec<SchemaChange>,
+}
+
+impl SchemaDiff {
+    ///
+    /// Diffs the tables by name. `to` tables missing from `from` are created (then indexed and
+    /// configured), `from` tables missing from `to` dropped.
+    ///
+    #[must_use]
+    pub fn between(from: &[TableSchema], to: &[TableSchema]) -> Self {
+        let mut changes = vec![];
+        for from_schema in from {
+            match to
+                .iter()
+                .find(|to_schema| to_schema.name == from_schema.name)
+            {
+                Some(to_schema) => changes.extend(table_changes(from_schema, to_schema)),
+                None => changes.push(SchemaChange::DropTable(from_schema.name.clone())),
+            }
+        }
+        for to_schema in to {
+            if !from
+                .iter()
+                .any(|from_schema| from_schema.name == to_schema.name)
+            {
+                changes.push(SchemaChange::CreateTable(Box::new(to_schema.clone())));
+                let created = TableSchema {
+                    name: to_schema.name.clone(),
+                    fields: to_schema.fields.clone(),
+                    ..Default::default()
+                };
+                changes.extend(table_changes(&created, to_schema));
+            }
+        }
+
+        Self { changes }
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    ///
+    /// The statements making the changes, in order.
+    ///
+    #[must_use]
+    pub fn alter_statements(&self) -> Vec<String> {
+        self.changes.iter().map(ToString::to_string).collect()
+    }
+}
+
+//
+// Changes of a table kept by name: indices are dropped before the fields they cover, and created
+// after them.
+//
+fn table_changes(from: &TableSchema, to: &TableSchema) -> Vec<SchemaChange> {
+    let table = || to.name.clone();
+    let mut changes = vec![];
+
+    for index in from.unique_indices.iter().filter(|index| {
+        !to.unique_indices.contains(index) || to.indices.get(*index) != from.indices.get(*index)
+    }) {
+        changes.push(SchemaChange::DropUnique {
+            table: table(),
+            index: index.clone(),
+        });
+    }
+    for index in from
+        .indices
+        .keys()
+        .filter(|index| to.indices.get(*index) != from.indices.get(*index))
+    {
+        changes.push(SchemaChange::DropIndex {
+            table: table(),
+            index: index.clone(),
+        });
+    }
+
+    for (field, from_field_schema) in &from.fields {
+        match to.fields.get(field) {
+            None => changes.push(SchemaChange::DropField {
+                table: table(),
+                field: field.clone(),
+            }),
+            Some(to_field_schema) if to_field_schema != from_field_schema => {
+                changes.push(SchemaChange::ChangeFieldType {
+                    table: table(),
+                    field: field.clone(),
+                    from: from_field_schema.clone(),
+                    to: to_field_schema.clone(),
+                });
+            }
+            Some(_) => {}
+        }
+    }
+    for (field, field_schema) in &to.fields {
+        if !from.fields.contains_key(field) {
+            changes.push(SchemaChange::AddField {
+                table: table(),
+                field: field.clone(),
+                field_schema: field_schema.clone(),
+            });
+        }
+    }
+    // Once the fields match, their order still decides the row layout.
+    let kept_order = |schema: &TableSchema, other: &TableSchema| -> Vec<String> {
+        schema
+            .fields
+            .keys()
+            .filter(|field| other.fields.contains_key(*field))
+            .cloned()
+            .collect()
+    };
+    if kept_order(from, to) != kept_order(to, from) {
+        changes.push(SchemaChange::ReorderFields {
+            table: table(),
+            fields: to.fields.keys().cloned().collect(),
+        });
+    }
+
+    for (index, fields) in &to.indices {
+        if from.indices.get(index) != Some(fields) {
+            changes.push(SchemaChange::CreateIndex {
+                table: table(),
+                index: index.clone(),
+                fields: fields.clone(),
+            });
+        }
+    }
+    for index in to.unique_indices.iter().filter(|index| {
+        !from.unique_indices.contains(index) || to.indices.get(*index) != from.indices.get(*index)
+    }) {
+        changes.push(SchemaChange::AddUnique {
+            table: table(),
+            index: index.clone(),
+        });
+    }
+
+    changes.extend(option_changes(from, to));
+
+    changes
+}
+
+// Changes of the table options, see `SchemaChange::SetOption`.
+fn option_changes(from: &TableSchema, to: &TableSchema) -> Vec<SchemaChange> {
+    let options = |schema: &TableSchema| {
+        let or_none = |value: Option<String>| value.unwrap_or_else(|| "NONE".to_string());
+        [
+            ("versioned", schema.versioned.to_string()),
+            ("audited", schema.audited.to_string()),
+            (
+                "soft_delete_column",
+                or_none(schema.soft_delete_column.clone()),
+            ),
+            ("tenant_column", or_none(schema.tenant_column.clone())),
+            (
+                "io_strategy",
+                or_none(
+                    schema
+                        .io_strategy
+                        .map(|io_strategy| format!("{io_strategy:?}")),
+                ),
+            ),
+        ]
+    };
+
+    options(from)
+        .into_iter()
+        .zip(options(to))
+        .filter(|((_, from_value), (_, to_value))| from_value != to_value)
+        .map(|((option, _), (_, value))| SchemaChange::SetOption {
+            table: to.name.clone(),
+            option: option.to_string(),
+            value,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use indexmap::IndexMap;
+
+    use crate::schema::{FieldSchema, TableSchema};
+
+    use super::{SchemaChange, SchemaDiff};
+
+    fn table_schema(fields: &[(&str, FieldSchema)], indices: &[(&str, &[&str])]) -> TableSchema {
+        TableSchema {
+            name: "t1".into(),
+            fields: fields
+                .iter()
+                .map(|(field, field_schema)| ((*field).to_string(), field_schema.clone()))
+                .collect(),
+            indices: indices
+                .iter()
+                .map(|(index, fields)| {
+                    (
+                        (*index).to_string(),
+                        fields.iter().map(ToString::to_string).collect(),
+                    )
+                })
+                .collect::<IndexMap<_, _>>(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_schema_diff() {
+        let from = table_schema(
+            &[
+                ("a", FieldSchema::I32),
+                ("b", FieldSchema::U8),
+                ("c", FieldSchema::I32),
+            ],
+            &[("c_index", &["c"]), ("a_index", &["a"])],
+        );
+        let mut to = table_schema(
+            &[
+                ("a", FieldSchema::I32),
+                ("b", FieldSchema::I32),
+                ("d", FieldSchema::U8),
+            ],
+            &[("a_index", &["a", "b"])],
+        );
+        to.unique_indices = vec!["a_index".into()];
+        to.versioned = true;
+
+        assert!(
+            SchemaDiff::between(std::slice::from_ref(&from), std::slice::from_ref(&from))
+                .is_empty()
+        );
+        assert_eq!(
+            vec![
+                "DROP INDEX c_index ON t1",
+                "DROP INDEX a_index ON t1",
+                "ALTER TABLE t1 ALTER COLUMN b TYPE I32",
+                "ALTER TABLE t1 DROP COLUMN c",
+                "ALTER TABLE t1 ADD COLUMN d U8",
+                "CREATE INDEX a_index ON t1 (a, b)",
+                "ALTER TABLE t1 ADD UNIQUE (a_index)",
+                "ALTER TABLE t1 SET (versioned = true)",
+            ],
+            SchemaDiff::between(std::slice::from_ref(&from), &[to]).alter_statements()
+        );
+
+        let reordered = table_schema(
+            &[
+                ("b", FieldSchema::U8),
+                ("a", FieldSchema::I32),
+                ("c", FieldSchema::I32),
+            ],
+            &[("a_index", &["a"]), ("c_index", &["c"])],
+        );
+        assert_eq!(
+            vec![SchemaChange::ReorderFields {
+                table: "t1".into(),
+                fields: vec!["b".into(), "a".into(), "c".into()],
+            }],
+            SchemaDiff::between(std::slice::from_ref(&from), &[reordered]).changes
+        );
+
+        assert_eq!(
+            vec!["DROP TABLE t1"],
+            SchemaDiff::between(std::slice::from_ref(&from), &[]).alter_statements()
+        );
+        assert_eq!(
+            vec![
+                "CREATE TABLE t1 (a I32, b U8, c I32)",
+                "CREATE INDEX c_index ON t1 (c)",
+                "CREATE INDEX a_index ON t1 (a)",
+            ],
+            SchemaDiff::between(&[], &[from]).alter_statements()
+        );
+    }
+}
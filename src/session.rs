@@ -0,0 +1,158 @@
+use crate::{
+    common::{Error, PBaseError},
+    query::{SelectQuery, SetQuery, SettingValue},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Debug,
+    Csv,
+    // Rows as maps of values per table, see `ResultSet::nested_rows`.
+    Nested,
+}
+
+///
+/// Settings of a client session, changed with `SET` statements:
+///
+/// - `max_rows`: cap on the rows a select returns (`SET max_rows = 100`, `SET max_rows = off`)
+/// - `output`: result format of the CLI (`debug`, `csv`, or `nested` for values grouped by table)
+/// - `timing`: whether the CLI reports query times (`on` or `off`); selects always report theirs,
+///   with their row count and index use
+///
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Session {
+    pub max_rows: Option<usize>,
+    pub output: OutputFormat,
+    pub timing: bool,
+}
+
+impl Session {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// # Errors
+    ///
+    /// Errors on unknown settings and values not valid for the setting.
+    pub fn set(&mut self, query: &SetQuery) -> Result<(), Error> {
+        let invalid_value = || PBaseError::InvalidSettingValue {
+            name: query.name.clone(),
+            value: query.value.to_string(),
+        };
+
+        match query.name.as_str() {
+            "max_rows" => {
+                self.max_rows = match &query.value {
+                    SettingValue::Int(v) => Some(usize::try_from(*v).map_err(|_| invalid_value())?),
+                    SettingValue::Word(word) if word == "off" => None,
+                    SettingValue::Word(_) => return Err(invalid_value().into()),
+                };
+            }
+            "output" => {
+                self.output = match &query.value {
+                    SettingValue::Word(word) if word == "debug" => OutputFormat::Debug,
+                    SettingValue::Word(word) if word == "csv" => OutputFormat::Csv,
+                    SettingValue::Word(word) if word == "nested" => OutputFormat::Nested,
+                    _ => return Err(invalid_value().into()),
+                };
+            }
+            "timing" => {
+                self.timing = match &query.value {
+                    SettingValue::Word(word) if word == "on" => true,
+                    SettingValue::Word(word) if word == "off" => false,
+                    _ => return Err(invalid_value().into()),
+                };
+            }
+            name => return Err(PBaseError::UnknownSetting(name.to_string()).into()),
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// The select with its limit tightened to `max_rows`.
+    ///
+    #[must_use]
+    pub fn limit_select(&self, mut query: SelectQuery) -> SelectQuery {
+        if let Some(max_rows) = self.max_rows {
+            query.limit = Some(query.limit.map_or(max_rows, |limit| limit.min(max_rows)));
+        }
+
+        query
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::query::{SelectQuery, SetQuery, SettingValue};
+
+    use super::{OutputFormat, Session};
+
+    fn set_query(name: &str, value: SettingValue) -> SetQuery {
+        SetQuery {
+            name: name.into(),
+            value,
+        }
+    }
+
+    #[test]
+    fn test_set() {
+        let mut session = Session::new();
+
+        session
+            .set(&set_query("max_rows", SettingValue::Int(100)))
+            .unwrap();
+        session
+            .set(&set_query("output", SettingValue::Word("csv".into())))
+            .unwrap();
+        session
+            .set(&set_query("timing", SettingValue::Word("on".into())))
+            .unwrap();
+        assert_eq!(
+            Session {
+                max_rows: Some(100),
+                output: OutputFormat::Csv,
+                timing: true,
+            },
+            session
+        );
+
+        session
+            .set(&set_query("max_rows", SettingValue::Word("off".into())))
+            .unwrap();
+        assert_eq!(None, session.max_rows);
+
+        session
+            .set(&set_query("output", SettingValue::Word("nested".into())))
+            .unwrap();
+        assert_eq!(OutputFormat::Nested, session.output);
+
+        assert!(session
+            .set(&set_query("timing", SettingValue::Int(1)))
+            .is_err());
+        assert!(session
+            .set(&set_query("colors", SettingValue::Word("on".into())))
+            .is_err());
+        assert!(session.timing);
+    }
+
+    #[test]
+    fn test_limit_select() {
+        let session = Session {
+            max_rows: Some(10),
+            ..Default::default()
+        };
+
+        let query = |limit| SelectQuery {
+            from: "t1".into(),
+            limit,
+            ..Default::default()
+        };
+        assert_eq!(Some(10), session.limit_select(query(None)).limit);
+        assert_eq!(Some(5), session.limit_select(query(Some(5))).limit);
+        assert_eq!(Some(10), session.limit_select(query(Some(50))).limit);
+        assert_eq!(None, Session::new().limit_select(query(None)).limit);
+    }
+}
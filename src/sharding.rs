@@ -0,0 +1,238 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+use crate::{
+    common::{Error, PBaseError},
+    pbase::PBase,
+    query::{CompareOp, CreateTableQuery, InsertQuery, RhsValue, SelectQuery},
+    result_set::ResultSet,
+    value::Value,
+};
+
+///
+/// Spreads tables over multiple directories (shards), each being a regular `PBase`.
+///
+/// Rows of a sharded table go to the shard picked by their shard key value. Tables without a shard
+/// key are reference tables, copied to every shard, so they can be joined to sharded tables.
+/// Joining two sharded tables is only correct when they are co-located: sharded on the join fields.
+///
+pub struct ShardedPBase {
+    shards: Vec<PBase>,
+    // Table name -> shard key field name.
+    shard_keys: HashMap<String, String>,
+}
+
+impl ShardedPBase {
+    /// # Panics
+    ///
+    /// When no shard directory is given.
+    #[must_use]
+    pub fn new(shard_dirs: Vec<PathBuf>, shard_keys: HashMap<String, String>) -> Self {
+        assert!(!shard_dirs.is_empty(), "At least one shard is required");
+
+        Self {
+            shards: shard_dirs.into_iter().map(PBase::new).collect(),
+            shard_keys,
+        }
+    }
+
+    #[must_use]
+    pub fn shards(&self) -> &[PBase] {
+        &self.shards
+    }
+
+    ///
+    /// The shard of a shard key value. Stable (does not depend on hashing), so data placed by one
+    /// build is found by another. NULL goes to the first shard, decimals by their digits (1.5 and
+    /// 1.50 alike), custom values by the sum of their bytes.
+    ///
+    /// # Panics
+    ///
+    /// When there are more shards than `i32::MAX`.
+    #[must_use]
+    pub fn shard_for(&self, value: &Value) -> usize {
+        let shard_count = self.shards.len();
+        match value {
+            Value::NULL => 0,
+            Value::I32(v) => {
+                let shard_count = i32::try_from(shard_count).expect("Shard count fits in i32");
+                usize::try_from(v.rem_euclid(shard_count)).expect("Remainder is not negative")
+            }
+            Value::U8(v) => usize::from(*v) % shard_count,
+            Value::Decimal(v) => {
+                let shard_count = i128::try_from(shard_count).expect("Shard count fits in i128");
+                usize::try_from(v.normalized().unscaled.rem_euclid(shard_count))
+                    .expect("Remainder is a shard")
+            }
+            Value::Custom(v) => {
+                v.bytes.iter().map(|b| usize::from(*b)).sum::<usize>() % shard_count
+            }
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Errors on file operations of any shard.
+    pub fn run_create_table_query(&self, query: &CreateTableQuery) -> Result<(), Error> {
+        for shard in &self.shards {
+            shard.run_create_table_query(query)?;
+        }
+
+        Ok(())
+    }
+
+    /// # Errors
+    ///
+    /// Errors on file operations.
+    pub fn run_insert_query(&self, query: &InsertQuery) -> Result<usize, Error> {
+        let Some(shard_key) = self.shard_keys.get(&query.table) else {
+            for shard in &self.shards {
+                shard.run_insert_query(query)?;
+            }
+            return Ok(1);
+        };
+
+        let shard_key_value = query.values.get(shard_key).unwrap_or(&Value::NULL);
+        self.shards[self.shard_for(shard_key_value)].run_insert_query(query)
+    }
+
+    ///
+    /// Runs the select on every shard holding relevant rows and concatenates the results (in shard
+    /// order). Filters and the limit are applied by each shard; the limit once more on the merge.
+    /// Ordered selects merge the (ordered) shard results by the ORDER BY field before the limit.
+    /// DISTINCT drops the rows (values) equal to one of an earlier shard, also before the limit.
+    ///
+    /// # Errors
+    ///
+    /// Errors on file operations, or for subqueries and derived tables (they would aggregate or be
+    /// read per shard), and for DISTINCT of a field ordered by another field (the order of the
+    /// distinct values is lost on the shards).
+    pub fn run_select_query(&self, query: &SelectQuery) -> Result<ResultSet, Error> {
+        if !query.scalar_subqueries.is_empty() {
+            return Err(PBaseError::UnsupportedShardedQuery(
+                "scalar subqueries cannot be merged across shards".into(),
+            )
+            .into());
+        }
+        let has_subquery_filters = query
+            .filters
+            .iter()
+            .any(|filter| matches!(filter.rhs, RhsValue::Subquery { .. }));
+        if has_subquery_filters {
+            return Err(PBaseError::UnsupportedShardedQuery(
+                "subqueries of filters would aggregate per shard".into(),
+            )
+            .into());
+        }
+        if !query.derived_tables.is_empty() {
+            return Err(PBaseError::UnsupportedShardedQuery(
+                "derived tables would be read per shard".into(),
+            )
+            .into());
+        }
+
+        let is_distinct_field_ordered_by_other = query
+            .distinct_field
+            .as_ref()
+            .zip(query.order_by.as_ref())
+            .is_some_and(|(distinct_field, order_by)| order_by.field != *distinct_field);
+        if is_distinct_field_ordered_by_other {
+            return Err(PBaseError::UnsupportedShardedQuery(
+                "distinct field ordered by another field cannot be merged across shards".into(),
+            )
+            .into());
+        }
+
+        let is_distinct = query.distinct || query.distinct_field.is_some();
+        let mut seen: HashSet<Vec<Value>> = HashSet::new();
+        let mut result = ResultSet::default();
+        for shard_idx in self.shards_for_select(query) {
+            // Rows of a later shard can order before the ones read already.
+            if query.order_by.is_none() && query.limit.is_some_and(|limit| result.len() >= limit) {
+                break;
+            }
+
+            // Every shard has the same schemas, so the same columns.
+            let shard_result = self.shards[shard_idx].run_select_query(query.clone())?;
+            result.columns = shard_result.columns;
+            if is_distinct {
+                // Shards only drop their own duplicates.
+                let columns = &result.columns;
+                result
+                    .rows
+                    .extend(shard_result.rows.into_iter().filter(|row| {
+                        seen.insert(
+                            columns
+                                .iter()
+                                .map(|column| {
+                                    row.get(column.name.as_str())
+                                        .cloned()
+                                        .unwrap_or(Value::NULL)
+                                })
+                                .collect(),
+                        )
+                    }));
+            } else {
+                result.rows.extend(shard_result.rows);
+            }
+        }
+
+        if let Some(order_by) = &query.order_by {
+            // A stable sort of the concatenated shard results merges them: each is ordered already,
+            // and rows with equal keys stay in shard order.
+            let column = order_by.field.full_name();
+            let null = Value::NULL;
+            result.rows.sort_by(|lhs, rhs| {
+                let ordering = lhs
+                    .get(column.as_str())
+                    .unwrap_or(&null)
+                    .cmp(rhs.get(column.as_str()).unwrap_or(&null));
+                if order_by.descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            });
+        }
+        if let Some(limit) = query.limit {
+            result.rows.truncate(limit);
+        }
+
+        Ok(result)
+    }
+
+    fn shards_for_select(&self, query: &SelectQuery) -> Vec<usize> {
+        let Some(shard_key) = self.shard_keys.get(query.table_name(&query.from)) else {
+            let is_any_join_sharded = query.joins.iter().any(|join_contract| {
+                self.shard_keys
+                    .contains_key(query.table_name(&join_contract.rhs.source))
+            });
+
+            // Reference tables are complete on each shard.
+            return if is_any_join_sharded {
+                (0..self.shards.len()).collect()
+            } else {
+                vec![0]
+            };
+        };
+
+        // An equality on the shard key pins the only shard that can have matching rows.
+        let pinned_value = query.filters.iter().find_map(|filter| match &filter.rhs {
+            RhsValue::Value(value)
+                if filter.op == CompareOp::Eq
+                    && filter.field.source == query.from
+                    && &filter.field.name == shard_key =>
+            {
+                Some(value)
+            }
+            _ => None,
+        });
+
+        pinned_value.map_or_else(
+            || (0..self.shards.len()).collect(),
+            |value| vec![self.shard_for(value)],
+        )
+    }
+}
@@ -0,0 +1,117 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use crate::value::Value;
+
+// Bits of the hash selecting the register: 2^12 registers, about 1.6% standard error.
+const HLL_PRECISION: u32 = 12;
+const HLL_REGISTERS: u32 = 1 << HLL_PRECISION;
+
+///
+/// `HyperLogLog` sketch estimating the number of distinct values added, in constant memory (one
+/// byte per register) instead of a set of all values. NULLs are skipped.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HyperLogLog {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            registers: vec![0; HLL_REGISTERS as usize],
+        }
+    }
+
+    pub fn add(&mut self, value: &Value) {
+        if *value == Value::NULL {
+            return;
+        }
+
+        // The default hasher is keyed the same way in every run, so sketches are reproducible.
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let register = usize::try_from(hash >> (u64::BITS - HLL_PRECISION)).unwrap_or_default();
+        // Position of the first set bit of the rest of the hash.
+        let rank = (hash << HLL_PRECISION)
+            .leading_zeros()
+            .min(u64::BITS - HLL_PRECISION)
+            + 1;
+        let rank = u8::try_from(rank).unwrap_or(u8::MAX);
+        self.registers[register] = self.registers[register].max(rank);
+    }
+
+    ///
+    /// Estimated number of distinct values. Small cardinalities, where most registers are still
+    /// empty, are counted from the empty registers instead (linear counting).
+    ///
+    #[must_use]
+    pub fn estimate(&self) -> u64 {
+        let registers = f64::from(HLL_REGISTERS);
+        let alpha = 0.7213 / (1.0 + 1.079 / registers);
+        let harmonic_sum: f64 = self
+            .registers
+            .iter()
+            .map(|rank| 2f64.powi(-i32::from(*rank)))
+            .sum();
+        let raw_estimate = alpha * registers * registers / harmonic_sum;
+
+        let empty_registers: u32 = self
+            .registers
+            .iter()
+            .map(|rank| u32::from(*rank == 0))
+            .sum();
+        let empty_registers = f64::from(empty_registers);
+        if raw_estimate <= 2.5 * registers && empty_registers > 0.0 {
+            to_count(registers * (registers / empty_registers).ln())
+        } else {
+            to_count(raw_estimate)
+        }
+    }
+}
+
+// Estimates are non-negative and far below u64::MAX.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+const fn to_count(estimate: f64) -> u64 {
+    estimate.round() as u64
+}
+
+#[cfg(test)]
+mod test {
+    use crate::value::Value;
+
+    use super::HyperLogLog;
+
+    #[test]
+    fn test_hyper_log_log() {
+        let mut sketch = HyperLogLog::new();
+        assert_eq!(0, sketch.estimate());
+
+        sketch.add(&Value::NULL);
+        for _ in 0..3 {
+            sketch.add(&Value::U8(7));
+        }
+        assert_eq!(1, sketch.estimate());
+
+        for (distinct, tolerance) in [(1_000, 20), (100_000, 5_000)] {
+            let mut sketch = HyperLogLog::new();
+            // Every value twice.
+            for i in (0..distinct).chain(0..distinct) {
+                sketch.add(&Value::I32(i));
+            }
+            let estimate = i64::try_from(sketch.estimate()).unwrap();
+            assert!(
+                (estimate - i64::from(distinct)).abs() < tolerance,
+                "{estimate} for {distinct}"
+            );
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    common::Error,
+    query::{RhsValue, SelectQuery},
+    schema::TableSchema,
+    table_opener::{FileBytes, TableOpener},
+};
+
+///
+/// Regular tables (schema, data and indices) taken together, so that a query reading them sees
+/// every table as of the same moment, see `PBase::read_snapshot`.
+///
+/// Table data and sorted indices are not copied: the snapshot keeps the maps of the files taken
+/// at the capture, bounded by their lengths then. Rows appended later are past those lengths, and
+/// files replaced later (deletes, index merges) stay mapped as they were. Updates in place through
+/// the handle write a copy of the table instead while it is snapshotted, see `SnapshotPins`. Index
+/// deltas are copied: they are short (merged every `INDEX_DELTA_MERGE_ROWS` entries), and merges
+/// remove them. Tables read with `IoStrategy::Buffered` are read into memory, as by any query.
+///
+#[derive(Debug, Default)]
+pub struct ReadSnapshot {
+    tables: HashMap<String, TableSnapshot>,
+}
+
+#[derive(Debug)]
+struct TableSnapshot {
+    schema: TableSchema,
+    // Generation of the table at the capture, see `TableOpener::table_generation`.
+    generation: u64,
+    data: Arc<FileBytes>,
+    // Length of the table data at the capture, the snapshot reads no further.
+    data_len: usize,
+    // Sorted index and index delta by index name.
+    indices: HashMap<String, (Arc<FileBytes>, Vec<u8>)>,
+    // Marks the table snapshotted while the snapshot lives.
+    _pin: Arc<()>,
+}
+
+impl ReadSnapshot {
+    ///
+    /// Maps the tables, pinning them (see `SnapshotPins`). Writes through the directory's handle
+    /// must be held off meanwhile (see `PBase::read_snapshot`), or the tables may not match.
+    ///
+    /// # Errors
+    ///
+    /// On file operations.
+    pub fn capture(
+        table_opener: &TableOpener,
+        pins: &SnapshotPins,
+        tables: &[String],
+    ) -> Result<Self, Error> {
+        let mut snapshot = Self::default();
+        for table in tables {
+            if snapshot.tables.contains_key(table) {
+                continue;
+            }
+
+            let schema = table_opener.open_schema(table)?;
+            let generation = table_opener.table_generation(table)?;
+            let data = table_opener.table_bytes(&schema)?;
+            let data_len = data.len();
+            let indices = schema
+                .indices
+                .keys()
+                .map(|index_name| -> Result<_, Error> {
+                    Ok((
+                        index_name.clone(),
+                        (
+                            Arc::new(table_opener.index_bytes(&schema, index_name)?),
+                            table_opener.index_delta_bytes(&schema, index_name)?,
+                        ),
+                    ))
+                })
+                .collect::<Result<_, Error>>()?;
+            snapshot.tables.insert(
+                table.clone(),
+                TableSnapshot {
+                    schema,
+                    generation,
+                    data: Arc::new(data),
+                    data_len,
+                    indices,
+                    _pin: pins.pin(table),
+                },
+            );
+        }
+
+        Ok(snapshot)
+    }
+
+    ///
+    /// Generation of the table at the capture: the snapshot misses the writes of later
+    /// generations.
+    ///
+    #[must_use]
+    pub fn generation(&self, table: &str) -> Option<u64> {
+        Some(self.tables.get(table)?.generation)
+    }
+
+    #[must_use]
+    pub fn contains(&self, table: &str) -> bool {
+        self.tables.contains_key(table)
+    }
+
+    #[must_use]
+    pub fn table_schema(&self, table: &str) -> Option<&TableSchema> {
+        Some(&self.tables.get(table)?.schema)
+    }
+
+    #[must_use]
+    pub fn table_bytes(&self, table: &str) -> Option<FileBytes> {
+        let table_snapshot = self.tables.get(table)?;
+        Some(FileBytes::Shared(
+            table_snapshot.data.clone(),
+            table_snapshot.data_len,
+        ))
+    }
+
+    #[must_use]
+    pub fn index_bytes(&self, table: &str, index_name: &str) -> Option<FileBytes> {
+        let (index_bytes, _) = self.tables.get(table)?.indices.get(index_name)?;
+        Some(FileBytes::Shared(index_bytes.clone(), index_bytes.len()))
+    }
+
+    #[must_use]
+    pub fn index_delta_bytes(&self, table: &str, index_name: &str) -> Option<Vec<u8>> {
+        let (_, index_delta_bytes) = self.tables.get(table)?.indices.get(index_name)?;
+        Some(index_delta_bytes.clone())
+    }
+}
+
+///
+/// The tables of a handle with live read snapshots. A snapshot maps the table data as it is, so
+/// writes changing rows in place must leave the mapped file alone while the table is pinned.
+///
+#[derive(Debug, Default)]
+pub struct SnapshotPins {
+    // A pin is held by each live snapshot of the table, and here.
+    pins: Mutex<HashMap<String, Arc<()>>>,
+}
+
+impl SnapshotPins {
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    #[must_use]
+    pub fn is_pinned(&self, table: &str) -> bool {
+        self.pins
+            .lock()
+            .unwrap()
+            .get(table)
+            .is_some_and(|pin| Arc::strong_count(pin) > 1)
+    }
+
+    fn pin(&self, table: &str) -> Arc<()> {
+        self.pins
+            .lock()
+            .unwrap()
+            .entry(table.to_string())
+            .or_default()
+            .clone()
+    }
+}
+
+///
+/// The tables a select reads: its table, the joined ones and those of its derived tables and
+/// subqueries.
+///
+#[must_use]
+pub fn query_tables(query: &SelectQuery) -> Vec<String> {
+    let sources = std::iter::once(&query.from).chain(
+        query
+            .joins
+            .iter()
+            .map(|join_contract| &join_contract.rhs.source),
+    );
+    let mut tables = vec![];
+    for source in sources {
+        tables.extend(
+            query
+                .derived_tables
+                .get(source)
+                .map_or_else(|| vec![query.table_name(source).to_string()], query_tables),
+        );
+    }
+    for scalar_subquery in &query.scalar_subqueries {
+        tables.extend(query_tables(&scalar_subquery.query));
+    }
+    for filter in &query.filters {
+        if let RhsValue::Subquery { query, .. } = &filter.rhs {
+            tables.extend(query_tables(query));
+        }
+    }
+    tables.dedup();
+
+    tables
+}
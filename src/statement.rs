@@ -0,0 +1,94 @@
+// Ends a statement in interactive input.
+pub const STATEMENT_TERMINATOR: char = ';';
+
+///
+/// Collects lines of interactive input into statements terminated by `;`, so a statement can span
+/// lines and a line can hold several statements.
+///
+#[derive(Debug, Default)]
+pub struct StatementBuffer {
+    // Text of the unfinished statement.
+    pending: String,
+}
+
+impl StatementBuffer {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// Adds a line of input and returns the statements it completed (trimmed, without their
+    /// terminators). Empty statements are skipped.
+    ///
+    pub fn push_line(&mut self, line: &str) -> Vec<String> {
+        if !self.pending.is_empty() {
+            self.pending.push('\n');
+        }
+        self.pending.push_str(line);
+
+        let Some(last_terminator) = self.pending.rfind(STATEMENT_TERMINATOR) else {
+            return vec![];
+        };
+        let rest = self.pending.split_off(last_terminator + 1);
+        let statements = self
+            .pending
+            .split(STATEMENT_TERMINATOR)
+            .map(str::trim)
+            .filter(|statement| !statement.is_empty())
+            .map(ToString::to_string)
+            .collect();
+        self.pending = if rest.trim().is_empty() {
+            String::new()
+        } else {
+            rest
+        };
+
+        statements
+    }
+
+    ///
+    /// Whether a statement is being continued, ie. the next line needs a continuation prompt.
+    ///
+    #[must_use]
+    pub fn is_pending(&self) -> bool {
+        !self.pending.trim().is_empty()
+    }
+
+    ///
+    /// Drops the unfinished statement.
+    ///
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::StatementBuffer;
+
+    #[test]
+    fn test_statement_buffer() {
+        let mut buffer = StatementBuffer::new();
+        assert!(!buffer.is_pending());
+
+        assert!(buffer.push_line("SELECT").is_empty());
+        assert!(buffer.is_pending());
+        assert_eq!(vec!["SELECT\nFROM t1"], buffer.push_line("FROM t1 ;  "));
+        assert!(!buffer.is_pending());
+
+        assert_eq!(
+            vec!["SET timing = on", "SELECT FROM t1"],
+            buffer.push_line("SET timing = on;; SELECT FROM t1; SELECT")
+        );
+        assert!(buffer.is_pending());
+        assert_eq!(vec!["SELECT\n FROM t2"], buffer.push_line(" FROM t2;"));
+
+        assert!(buffer.push_line("").is_empty());
+        assert!(!buffer.is_pending());
+        buffer.push_line("SELECT");
+        buffer.clear();
+        assert!(!buffer.is_pending());
+        assert_eq!(vec!["SELECT FROM t3"], buffer.push_line("SELECT FROM t3;"));
+    }
+}
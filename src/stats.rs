@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    query::{CompareOp, RhsValue, RowFilter},
+    schema::FieldSchema,
+    sketch::HyperLogLog,
+    value::Value,
+};
+
+// Buckets of a column histogram built by ANALYZE.
+pub const HISTOGRAM_BUCKETS: usize = 16;
+// An index is only used when its leading column filters are estimated to match at most this
+// fraction of the rows; above it reading the table in order is cheaper than hopping through it.
+pub const INDEX_SCAN_MAX_SELECTIVITY: f64 = 0.3;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistogramBucket {
+    pub lower: Value,
+    pub upper: Value,
+    pub rows: usize,
+}
+
+///
+/// Equi-depth histogram of a column.
+///
+/// Sorted buckets hold (about) the same number of rows, each with the lowest and highest value it
+/// holds. Values inside a bucket are assumed to be spread evenly over its range.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Histogram {
+    pub buckets: Vec<HistogramBucket>,
+}
+
+impl Histogram {
+    #[must_use]
+    pub fn build(mut values: Vec<Value>, bucket_count: usize) -> Self {
+        values.sort();
+        let bucket_count = bucket_count.min(values.len());
+
+        let buckets = (0..bucket_count)
+            .map(|bucket_idx| {
+                let start = bucket_idx * values.len() / bucket_count;
+                let end = (bucket_idx + 1) * values.len() / bucket_count;
+                HistogramBucket {
+                    lower: values[start].clone(),
+                    upper: values[end - 1].clone(),
+                    rows: end - start,
+                }
+            })
+            .collect();
+
+        Self { buckets }
+    }
+
+    #[must_use]
+    pub fn rows(&self) -> usize {
+        self.buckets.iter().map(|bucket| bucket.rows).sum()
+    }
+
+    ///
+    /// Estimated fraction of the rows whose value compares to `value` as `op`. None for values
+    /// the histogram cannot place (NULL).
+    ///
+    #[must_use]
+    pub fn fraction(&self, op: CompareOp, value: &Value) -> Option<f64> {
+        let value = numeric(value)?;
+        if self.buckets.is_empty() {
+            return Some(0.0);
+        }
+
+        let equal = || self.fraction_up_to(value) - self.fraction_up_to(value - 1);
+        Some(match op {
+            CompareOp::Eq => equal(),
+            CompareOp::Ne => 1.0 - equal(),
+            CompareOp::Lt => self.fraction_up_to(value - 1),
+            CompareOp::Le => self.fraction_up_to(value),
+            CompareOp::Gt => 1.0 - self.fraction_up_to(value),
+            CompareOp::Ge => 1.0 - self.fraction_up_to(value - 1),
+        })
+    }
+
+    // Estimated fraction of the rows with a value of at most `value`.
+    fn fraction_up_to(&self, value: i64) -> f64 {
+        let rows_up_to: f64 = self
+            .buckets
+            .iter()
+            .filter_map(|bucket| Some((numeric(&bucket.lower)?, numeric(&bucket.upper)?, bucket)))
+            .map(|(lower, upper, bucket)| {
+                if upper <= value {
+                    ratio(bucket.rows, 1)
+                } else if lower <= value {
+                    ratio(bucket.rows, 1) * ratio(value - lower + 1, upper - lower + 1)
+                } else {
+                    0.0
+                }
+            })
+            .sum();
+
+        rows_up_to / ratio(self.rows(), 1)
+    }
+}
+
+///
+/// Statistics of a table, collected by ANALYZE and stored next to the table data.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableStats {
+    pub row_count: usize,
+    // Histograms of the indexed columns, by field name.
+    pub histograms: BTreeMap<String, Histogram>,
+    // Summaries of every column, by field name. Missing from stats files of older versions.
+    #[serde(default)]
+    pub columns: BTreeMap<String, ColumnStats>,
+}
+
+impl TableStats {
+    ///
+    /// Estimated fraction of the rows matching all (AND-ed, assumed independent) value filters on
+    /// the field. None when the field has no histogram.
+    ///
+    #[must_use]
+    pub fn selectivity(&self, field_name: &str, filters: &[&RowFilter]) -> Option<f64> {
+        let histogram = self.histograms.get(field_name)?;
+
+        Some(
+            filters
+                .iter()
+                .filter_map(|filter| match &filter.rhs {
+                    RhsValue::Value(value) => histogram.fraction(filter.op, value),
+                    // Rows equal to different values of the list are different rows.
+                    RhsValue::In(values) => values
+                        .iter()
+                        .map(|value| histogram.fraction(CompareOp::Eq, value))
+                        .sum::<Option<f64>>()
+                        .map(|fraction| fraction.min(1.0)),
+                    RhsValue::Range {
+                        low,
+                        high,
+                        inclusive,
+                    } => {
+                        let (below, above) = if *inclusive {
+                            (CompareOp::Lt, CompareOp::Gt)
+                        } else {
+                            (CompareOp::Le, CompareOp::Ge)
+                        };
+                        let outside =
+                            histogram.fraction(below, low)? + histogram.fraction(above, high)?;
+                        Some((1.0 - outside).max(0.0))
+                    }
+                    RhsValue::Ref(_) | RhsValue::Interval { .. } | RhsValue::Subquery { .. } => {
+                        None
+                    }
+                })
+                .product(),
+        )
+    }
+}
+
+///
+/// Summary of a column's values, collected by ANALYZE.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColumnStats {
+    // NULL for a column without (non-NULL) values.
+    pub min: Value,
+    pub max: Value,
+    pub null_count: usize,
+    // `HyperLogLog` estimate, see `sketch::HyperLogLog`.
+    pub distinct_estimate: u64,
+}
+
+impl ColumnStats {
+    #[must_use]
+    pub fn build(values: &[Value]) -> Self {
+        let mut sketch = HyperLogLog::new();
+        for value in values {
+            sketch.add(value);
+        }
+        let non_null = || values.iter().filter(|value| **value != Value::NULL);
+
+        Self {
+            min: non_null().min().cloned().unwrap_or(Value::NULL),
+            max: non_null().max().cloned().unwrap_or(Value::NULL),
+            null_count: values.len() - non_null().count(),
+            distinct_estimate: sketch.estimate(),
+        }
+    }
+}
+
+///
+/// A column as `DESCRIBE table` shows it: its type, and its stats when the table was analyzed.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnDescription {
+    pub name: String,
+    pub field_schema: FieldSchema,
+    pub stats: Option<ColumnStats>,
+}
+
+fn numeric(value: &Value) -> Option<i64> {
+    match value {
+        Value::NULL | Value::Decimal(_) | Value::Custom(_) => None,
+        Value::I32(v) => Some(i64::from(*v)),
+        Value::U8(v) => Some(i64::from(*v)),
+    }
+}
+
+// Estimates tolerate the precision loss of large counts.
+#[allow(clippy::cast_precision_loss)]
+fn ratio<N: TryInto<i64>>(numerator: N, denominator: N) -> f64 {
+    let as_f64 = |n: N| n.try_into().map_or(f64::MAX, |n: i64| n as f64);
+    as_f64(numerator) / as_f64(denominator)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{query::CompareOp, value::Value};
+
+    use super::{ColumnStats, Histogram, HistogramBucket};
+
+    #[test]
+    fn test_histogram() {
+        let values: Vec<Value> = (0..100).rev().map(Value::I32).collect();
+        let histogram = Histogram::build(values, 4);
+        assert_eq!(
+            HistogramBucket {
+                lower: Value::I32(25),
+                upper: Value::I32(49),
+                rows: 25,
+            },
+            histogram.buckets[1]
+        );
+        assert_eq!(100, histogram.rows());
+
+        let fraction = |op, value| histogram.fraction(op, &Value::I32(value)).unwrap();
+        assert!((fraction(CompareOp::Lt, 10) - 0.1).abs() < 1e-9);
+        assert!((fraction(CompareOp::Gt, 89) - 0.1).abs() < 1e-9);
+        assert!((fraction(CompareOp::Eq, 42) - 0.01).abs() < 1e-9);
+        assert!((fraction(CompareOp::Gt, -5) - 1.0).abs() < 1e-9);
+        assert!(fraction(CompareOp::Gt, 1000).abs() < 1e-9);
+        assert!((fraction(CompareOp::Le, 9) - 0.1).abs() < 1e-9);
+        assert!((fraction(CompareOp::Ge, 90) - 0.1).abs() < 1e-9);
+        assert!((fraction(CompareOp::Ne, 42) - 0.99).abs() < 1e-9);
+        assert_eq!(None, histogram.fraction(CompareOp::Lt, &Value::NULL));
+
+        // A heavy value fills whole buckets.
+        let mut values = vec![Value::U8(7); 90];
+        values.extend((0..10).map(Value::U8));
+        let histogram = Histogram::build(values, 10);
+        let fraction = histogram.fraction(CompareOp::Eq, &Value::U8(7)).unwrap();
+        assert!(fraction > 0.8 && fraction < 0.95);
+    }
+
+    #[test]
+    fn test_column_stats() {
+        let mut values: Vec<Value> = (0..100).map(|v| Value::I32(v % 10 - 3)).collect();
+        values.push(Value::NULL);
+        assert_eq!(
+            ColumnStats {
+                min: Value::I32(-3),
+                max: Value::I32(6),
+                null_count: 1,
+                distinct_estimate: 10,
+            },
+            ColumnStats::build(&values)
+        );
+
+        let empty = ColumnStats::build(&[]);
+        assert_eq!(Value::NULL, empty.min);
+        assert_eq!(0, empty.distinct_estimate);
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+
+use indexmap::IndexMap;
+
+use crate::{
+    common::Error,
+    schema::{FieldSchema, TableSchema},
+    table_opener::TableOpener,
+    value::Value,
+};
+
+pub const PBASE_TABLES: &str = "pbase_tables";
+pub const PBASE_COLUMNS: &str = "pbase_columns";
+pub const PBASE_INDICES: &str = "pbase_indices";
+
+///
+/// Virtual, read-only tables describing the directory. They are generated on each read and can be
+/// selected and joined like regular tables.
+///
+/// Values are numeric only, so tables and indices are referred to by ids: a table's id is its
+/// position in `TableOpener::table_names`, an index's id its position among the table's indices
+/// in declaration order. Columns are referred to by their position in the table.
+///
+/// - `pbase_tables`: `id`, `row_count`, `row_byte_size`, `column_count`, `index_count`
+/// - `pbase_columns`: `table_id`, `position`, `type` (0: U8, 1: I32, 2: custom, 3: decimal),
+///   `byte_size`, `byte_pos`
+/// - `pbase_indices`: `table_id`, `index_id`, `position`, `column_position` (-1 for an unknown
+///   column; one row per indexed column, in index order)
+///
+#[must_use]
+pub fn is_system_table(table_name: &str) -> bool {
+    [PBASE_TABLES, PBASE_COLUMNS, PBASE_INDICES].contains(&table_name)
+}
+
+#[must_use]
+pub fn system_table_schema(table_name: &str) -> Option<TableSchema> {
+    let field_names: &[&str] = match table_name {
+        PBASE_TABLES => &[
+            "id",
+            "row_count",
+            "row_byte_size",
+            "column_count",
+            "index_count",
+        ],
+        PBASE_COLUMNS => &["table_id", "position", "type", "byte_size", "byte_pos"],
+        PBASE_INDICES => &["table_id", "index_id", "position", "column_position"],
+        _ => return None,
+    };
+
+    let fields = field_names
+        .iter()
+        .map(|field_name| {
+            let field_schema = if *field_name == "type" {
+                FieldSchema::U8
+            } else {
+                FieldSchema::I32
+            };
+            ((*field_name).to_string(), field_schema)
+        })
+        .collect::<IndexMap<_, _>>();
+
+    Some(TableSchema {
+        name: table_name.to_string(),
+        fields,
+        indices: IndexMap::new(),
+        ..Default::default()
+    })
+}
+
+///
+/// Generates the data of a system table, in the regular table data layout.
+///
+/// # Errors
+///
+/// On file operations.
+///
+/// # Panics
+///
+/// When `table_name` is not a system table, or when a table is too large to describe.
+pub fn system_table_bytes(table_opener: &TableOpener, table_name: &str) -> Result<Vec<u8>, Error> {
+    let table_schema = system_table_schema(table_name).expect("Not a system table");
+
+    let mut rows: Vec<HashMap<String, Value>> = vec![];
+    for (table_id, name) in table_opener.table_names()?.iter().enumerate() {
+        let schema = table_opener.open_schema(name)?;
+        let index_names: Vec<&String> = schema.indices.keys().collect();
+
+        match table_name {
+            PBASE_TABLES => {
+                let data_len = std::fs::metadata(table_opener.table_data_file_name(name))
+                    .map_or(0, |metadata| metadata.len());
+                let row_byte_size = schema.row_byte_size();
+                rows.push(HashMap::from([
+                    ("id".into(), id_value(table_id)),
+                    (
+                        "row_count".into(),
+                        id_value(
+                            usize::try_from(data_len).expect("Table length fits in usize")
+                                / row_byte_size.max(1),
+                        ),
+                    ),
+                    ("row_byte_size".into(), id_value(row_byte_size)),
+                    ("column_count".into(), id_value(schema.fields.len())),
+                    ("index_count".into(), id_value(index_names.len())),
+                ]));
+            }
+            PBASE_COLUMNS => {
+                for (position, (field_name, field_schema)) in schema.fields.iter().enumerate() {
+                    let field_type = match field_schema {
+                        FieldSchema::U8 => 0,
+                        FieldSchema::I32 => 1,
+                        FieldSchema::Custom(_) => 2,
+                        FieldSchema::Decimal { .. } => 3,
+                    };
+                    rows.push(HashMap::from([
+                        ("table_id".into(), id_value(table_id)),
+                        ("position".into(), id_value(position)),
+                        ("type".into(), Value::U8(field_type)),
+                        (
+                            "byte_size".into(),
+                            id_value(schema.stored_byte_size(field_name)),
+                        ),
+                        (
+                            "byte_pos".into(),
+                            id_value(schema.field_byte_pos(field_name)),
+                        ),
+                    ]));
+                }
+            }
+            _ => {
+                for (index_id, index_name) in index_names.iter().enumerate() {
+                    for (position, field_name) in schema.indices[*index_name].iter().enumerate() {
+                        rows.push(HashMap::from([
+                            ("table_id".into(), id_value(table_id)),
+                            ("index_id".into(), id_value(index_id)),
+                            ("position".into(), id_value(position)),
+                            (
+                                "column_position".into(),
+                                schema
+                                    .fields
+                                    .get_index_of(field_name)
+                                    .map_or(Value::I32(-1), id_value),
+                            ),
+                        ]));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(rows
+        .iter()
+        .flat_map(|row| table_schema.data_row_to_bytes(row))
+        .collect())
+}
+
+// Panics: ids and sizes of a toy database fit in an i32.
+fn id_value(id: usize) -> Value {
+    Value::I32(i32::try_from(id).expect("Id fits in i32"))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use indexmap::IndexMap;
+
+    use crate::{
+        schema::{FieldSchema, TableSchema},
+        value::Value,
+    };
+
+    use super::{is_system_table, system_table_schema};
+
+    #[test]
+    fn test_system_table_schema() {
+        assert!(is_system_table("pbase_columns"));
+        assert!(!is_system_table("columns"));
+        assert_eq!(None, system_table_schema("columns"));
+
+        let schema = system_table_schema("pbase_columns").unwrap();
+        assert_eq!(
+            TableSchema {
+                name: "pbase_columns".into(),
+                fields: IndexMap::from([
+                    ("table_id".into(), FieldSchema::I32),
+                    ("position".into(), FieldSchema::I32),
+                    ("type".into(), FieldSchema::U8),
+                    ("byte_size".into(), FieldSchema::I32),
+                    ("byte_pos".into(), FieldSchema::I32),
+                ]),
+                indices: IndexMap::new(),
+                ..Default::default()
+            },
+            schema
+        );
+
+        let row = HashMap::from([
+            ("table_id".into(), Value::I32(1)),
+            ("position".into(), Value::I32(0)),
+            ("type".into(), Value::U8(1)),
+            ("byte_size".into(), Value::I32(4)),
+            ("byte_pos".into(), Value::I32(0)),
+        ]);
+        assert_eq!(row, schema.parse_row_bytes(&schema.data_row_to_bytes(&row)));
+    }
+}
@@ -0,0 +1,223 @@
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::{
+    common::{Error, PBaseError},
+    dictionary::read_dictionaries,
+    operator::{collect_rows, table_column_keys, ColumnKey, Filter, Operator, Row},
+    query::{RhsValue, RowFilter},
+    row_codec::{FixedWidthCodec, RowCodec},
+    schema::{TablePtrType, TableRowPositionIterator, TableSchema},
+    table_opener::{BlockReader, FileBytes, BLOCK_ROWS},
+    value::Value,
+};
+
+///
+/// A single table opened from its schema and data files, without a database directory. Meant for
+/// tools and ETL jobs working on individual files.
+///
+/// Index files are not consulted nor maintained: reads scan the data, and inserts are refused for
+/// tables with indices (their index files would go stale).
+///
+/// Rows are read and written with the table's row codec, the stored layout unless set with
+/// `with_codec` (eg. for data files written by another encoder).
+///
+pub struct Table<C: RowCodec = FixedWidthCodec> {
+    schema: TableSchema,
+    data_path: PathBuf,
+    codec: C,
+}
+
+impl Table {
+    /// # Errors
+    ///
+    /// Errors when the schema cannot be read, its fields do not have the layout of the data (see
+    /// `TableSchema::check_row_layout`), or the data file does not exist.
+    pub fn open<S, D>(schema_path: S, data_path: D) -> Result<Self, Error>
+    where
+        S: AsRef<Path>,
+        D: AsRef<Path>,
+    {
+        let schema_file = File::open(&schema_path).context("Failed to open schema file")?;
+        let mut schema: TableSchema =
+            serde_json::from_reader(schema_file).context("Failed parsing schema")?;
+        schema.check_row_layout()?;
+        if !schema.dictionary_columns.is_empty() {
+            // The dictionary sidecar lives next to the schema, see `TableOpener`.
+            schema.dictionaries = read_dictionaries(&schema_path.as_ref().with_extension("pbv"))?;
+        }
+
+        let data_path = data_path.as_ref().to_path_buf();
+        std::fs::metadata(&data_path).context("Cannot open data file")?;
+
+        Ok(Self {
+            schema,
+            data_path,
+            codec: FixedWidthCodec,
+        })
+    }
+}
+
+impl<C: RowCodec> Table<C> {
+    ///
+    /// Reads and writes the rows with the codec.
+    ///
+    #[must_use]
+    pub fn with_codec<D: RowCodec>(self, codec: D) -> Table<D> {
+        Table {
+            schema: self.schema,
+            data_path: self.data_path,
+            codec,
+        }
+    }
+
+    #[must_use]
+    pub const fn schema(&self) -> &TableSchema {
+        &self.schema
+    }
+
+    ///
+    /// All rows, in data order, keyed by full field names like query results.
+    ///
+    /// # Errors
+    ///
+    /// On file operations.
+    pub fn scan(&self) -> Result<Vec<Row>, Error> {
+        self.filter(&[])
+    }
+
+    ///
+    /// Rows matching all (AND-ed) filters, in data order. Filters refer to the table by its
+    /// schema name, and compare fields to values: without a database there are no subqueries to
+    /// run, and field references are not supported. Rows are read from the mapped data a block at
+    /// a time, only the matching ones are kept.
+    ///
+    /// # Errors
+    ///
+    /// On file operations, when a filter refers to a field not in the table, or when it does not
+    /// compare to values.
+    pub fn filter(&self, filters: &[RowFilter]) -> Result<Vec<Row>, Error> {
+        for filter in filters {
+            self.check_field(&filter.field.source, &filter.field.name)?;
+            match filter.rhs {
+                RhsValue::Value(_) | RhsValue::In(_) | RhsValue::Range { .. } => {}
+                RhsValue::Ref(_) | RhsValue::Interval { .. } | RhsValue::Subquery { .. } => {
+                    return Err(PBaseError::InvalidArgument(format!(
+                        "filter {filter} of a standalone table does not compare to values"
+                    ))
+                    .into());
+                }
+            }
+        }
+
+        let data_file = File::open(&self.data_path)?;
+        // Empty files cannot be mapped.
+        let table_bytes = if data_file.metadata()?.len() == 0 {
+            FileBytes::Owned(vec![])
+        } else {
+            FileBytes::Mapped(unsafe { memmap::MmapOptions::new().map(&data_file)? })
+        };
+        let row_byte_size = self.codec.row_byte_size(&self.schema).max(1);
+        if table_bytes.len() % row_byte_size != 0 {
+            return Err(PBaseError::InvalidTableSizeError.into());
+        }
+
+        let scan = CodecScan {
+            table_schema: &self.schema,
+            codec: &self.codec,
+            table_bytes: BlockReader::plain(&table_bytes, row_byte_size * BLOCK_ROWS),
+            column_keys: table_column_keys(&self.schema),
+            positions: TableRowPositionIterator::new(row_byte_size, table_bytes.len()),
+        };
+        let mut filter = Filter::new(Box::new(scan), filters.to_vec());
+        let rows = collect_rows(&mut filter)?;
+
+        Ok(rows)
+    }
+
+    ///
+    /// Appends a row (missing fields are zeroed) and returns its byte position in the data.
+    ///
+    /// # Errors
+    ///
+    /// On file operations, unknown fields, when the table has indices, or when a value of a
+    /// dictionary encoded field is not in its dictionary (the sidecar is not maintained here).
+    pub fn insert(&self, values: &HashMap<String, Value>) -> Result<TablePtrType, Error> {
+        if !self.schema.indices.is_empty() {
+            return Err(PBaseError::IndexedStandaloneInsert(self.schema.name.clone()).into());
+        }
+        if let Some(field_name) = values
+            .keys()
+            .find(|field_name| !self.schema.fields.contains_key(*field_name))
+        {
+            return Err(PBaseError::UnknownField(field_name.clone()).into());
+        }
+        if let Some(field_name) = self.schema.dictionary_columns.iter().find(|field_name| {
+            self.schema.dictionaries[*field_name]
+                .id(&self.schema.dictionary_value(field_name, values))
+                .is_none()
+        }) {
+            return Err(PBaseError::InvalidArgument(format!(
+                "value of {field_name} is not in its dictionary"
+            ))
+            .into());
+        }
+
+        let mut data_file = OpenOptions::new().append(true).open(&self.data_path)?;
+        let row_pos = data_file.metadata()?.len();
+        data_file.write_all(&self.codec.encode_row(&self.schema, values))?;
+
+        Ok(row_pos)
+    }
+
+    fn check_field(&self, source: &str, name: &str) -> Result<(), Error> {
+        if source == self.schema.name && self.schema.fields.contains_key(name) {
+            Ok(())
+        } else {
+            Err(PBaseError::UnknownField(format!("{source}.{name}")).into())
+        }
+    }
+}
+
+//
+// Full scan of a standalone table, decoding the rows with the table's codec.
+//
+struct CodecScan<'a, C: RowCodec> {
+    table_schema: &'a TableSchema,
+    codec: &'a C,
+    table_bytes: BlockReader<&'a [u8]>,
+    column_keys: Vec<ColumnKey>,
+    positions: TableRowPositionIterator,
+}
+
+impl<C: RowCodec> Operator for CodecScan<'_, C> {
+    fn next_row(&mut self) -> Result<Option<Row>, Error> {
+        let Some(pos) = self.positions.next() else {
+            return Ok(None);
+        };
+        let row_bytes = self
+            .table_bytes
+            .read(pos, self.codec.row_byte_size(self.table_schema))?;
+        let mut values = self.codec.decode_row(self.table_schema, &row_bytes);
+
+        Ok(Some(
+            self.table_schema
+                .fields
+                .keys()
+                .zip(&self.column_keys)
+                .map(|(field_name, column_key)| {
+                    (
+                        column_key.clone(),
+                        values.remove(field_name).unwrap_or(Value::NULL),
+                    )
+                })
+                .collect(),
+        ))
+    }
+}
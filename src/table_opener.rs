@@ -1,65 +1,910 @@
 use std::{
+    cell::RefCell,
+    collections::{hash_map::Entry, HashMap, VecDeque},
     fs::{File, OpenOptions},
+    io::{ErrorKind, Read},
+    ops::{Deref, Range},
     path::PathBuf,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::Duration,
 };
 
+use log::debug;
 use memmap::Mmap;
+use serde::{Deserialize, Serialize};
 
-use crate::{common::Error, schema::TableSchema};
+use crate::{
+    common::{Error, PBaseError},
+    custom_type::{TypeDef, TypeDefs},
+    dictionary::read_dictionaries,
+    external::ExternalTable,
+    platform::{atomic_write, validate_file_stem, FileLock},
+    schema::TableSchema,
+    stats::TableStats,
+};
+
+///
+/// How table data and index files are read.
+///
+/// Mapping is the cheapest for large, mostly cached files. Some filesystems (network mounts, some
+/// FUSE ones) map poorly, and maps of index files that are rewritten on every update are
+/// invalidated all the time, so reads can go through plain buffered file reads instead.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum IoStrategy {
+    #[default]
+    Mmap,
+    Buffered,
+}
+
+// Mapping a table shorter than its committed length is tried this many times before failing.
+const STALE_TABLE_MAP_ATTEMPTS: u32 = 3;
+// Wait before mapping a stale table again, doubled before each further attempt.
+const STALE_TABLE_MAP_BACKOFF: Duration = Duration::from_millis(10);
+
+///
+/// Contents of a data or index file: mapped, or in memory (empty files cannot be mapped).
+///
+#[derive(Debug)]
+pub enum FileBytes {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+    // Bytes of a read snapshot up to the length captured, shared by the queries reading it.
+    Shared(Arc<Self>, usize),
+}
+
+impl Deref for FileBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Mapped(mmap) => mmap,
+            Self::Owned(bytes) => bytes,
+            Self::Shared(bytes, len) => &bytes[..*len],
+        }
+    }
+}
+
+impl FileBytes {
+    ///
+    /// Advises the OS that the bytes will be read front to back, so it reads ahead more
+    /// aggressively and drops pages behind the reader sooner. Does nothing for bytes in memory and
+    /// on non-Unix systems.
+    ///
+    pub fn advise_sequential(&self) {
+        #[cfg(unix)]
+        if let Self::Mapped(mmap) = self {
+            // Only a hint: failing leaves the default readahead in place.
+            let result = unsafe {
+                libc::madvise(
+                    mmap.as_ptr().cast_mut().cast::<libc::c_void>(),
+                    mmap.len(),
+                    libc::MADV_SEQUENTIAL,
+                )
+            };
+            if result != 0 {
+                debug!("madvise failed: {}", std::io::Error::last_os_error());
+            }
+        }
+    }
+}
+
+// Table data smaller than this is scanned without prefetching, it is likely cached already.
+pub const PREFETCH_MIN_BYTES: usize = 4 << 20;
+// Bytes a prefetcher reads at a time.
+pub const PREFETCH_BLOCK_BYTES: usize = 1 << 20;
+
+///
+/// Reads a file front to back in a background thread, warming the page cache for a scan.
+///
+/// The pages the scan is about to reach are then already in memory. The file is read through its
+/// own handle (the bytes are dropped), so the prefetcher does not borrow the scan's map. Reading
+/// stops at the end of the range, or when the prefetcher is dropped.
+///
+pub struct Prefetcher {
+    cancelled: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Prefetcher {
+    #[must_use]
+    pub fn spawn(file_name: PathBuf, len: usize) -> Self {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let thread_cancelled = cancelled.clone();
+        let handle = std::thread::spawn(move || {
+            if let Err(err) = prefetch(&file_name, len, &thread_cancelled) {
+                debug!("Prefetching {file_name:?} stopped: {err}");
+            }
+        });
+
+        Self {
+            cancelled,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for Prefetcher {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn prefetch(file_name: &PathBuf, len: usize, cancelled: &AtomicBool) -> Result<(), Error> {
+    let mut file = File::open(file_name)?;
+    let mut block = vec![0; PREFETCH_BLOCK_BYTES];
+    let mut read_len = 0;
+    while read_len < len && !cancelled.load(Ordering::Relaxed) {
+        let block_len = file.read(&mut block)?;
+        if block_len == 0 {
+            break;
+        }
+        read_len += block_len;
+    }
+
+    Ok(())
+}
+
+// Rows per block of a block reader.
+pub const BLOCK_ROWS: usize = 1024;
+// Decoded blocks a block reader keeps around.
+pub const BLOCK_CACHE_BLOCKS: usize = 16;
+
+///
+/// Decodes the blocks of an encoded (compressed, encrypted, ...) data or index file. The codec
+/// owns the framing of the encoded bytes; blocks are numbered in decoded order.
+///
+pub trait BlockCodec {
+    /// # Errors
+    ///
+    /// Errors when the encoded bytes are invalid.
+    fn decoded_len(&self, bytes: &[u8]) -> Result<usize, Error>;
+
+    ///
+    /// The decoded bytes of the block: `block_byte_size` long, shorter for the last block.
+    ///
+    /// # Errors
+    ///
+    /// Errors when the encoded bytes are invalid.
+    fn decode_block(
+        &self,
+        bytes: &[u8],
+        block_idx: usize,
+        block_byte_size: usize,
+    ) -> Result<Vec<u8>, Error>;
+}
+
+///
+/// Bytes read through a `BlockReader`: borrowed from a plain file, or from a decoded block.
+///
+pub enum Block<'a> {
+    Borrowed(&'a [u8]),
+    Decoded(Rc<[u8]>, Range<usize>),
+    // A read spanning decoded blocks.
+    Owned(Vec<u8>),
+}
+
+impl Deref for Block<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Borrowed(bytes) => bytes,
+            Self::Decoded(block, range) => &block[range.clone()],
+            Self::Owned(bytes) => bytes,
+        }
+    }
+}
+
+///
+/// Reads ranges of a data or index file.
+///
+/// Plain files are sliced in place; encoded files are decoded a block at a time, keeping the most
+/// recently used blocks in an LRU cache. Readers are meant for a single query (the cache is not
+/// shared between threads).
+///
+pub struct BlockReader<B> {
+    bytes: B,
+    block_byte_size: usize,
+    codec: Option<Box<dyn BlockCodec>>,
+    decoded_len: usize,
+    cache_capacity: usize,
+    // Most recently used first.
+    cache: RefCell<VecDeque<(usize, Rc<[u8]>)>>,
+}
+
+impl<B: Deref<Target = [u8]>> BlockReader<B> {
+    ///
+    /// Reader of a plain (not encoded) file.
+    ///
+    #[must_use]
+    pub fn plain(bytes: B, block_byte_size: usize) -> Self {
+        let decoded_len = bytes.len();
+        Self {
+            bytes,
+            block_byte_size,
+            codec: None,
+            decoded_len,
+            cache_capacity: 0,
+            cache: RefCell::new(VecDeque::new()),
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Errors when the codec cannot read the encoded length.
+    pub fn encoded(
+        bytes: B,
+        block_byte_size: usize,
+        codec: Box<dyn BlockCodec>,
+        cache_capacity: usize,
+    ) -> Result<Self, Error> {
+        let decoded_len = codec.decoded_len(&bytes)?;
+        Ok(Self {
+            bytes,
+            block_byte_size,
+            codec: Some(codec),
+            decoded_len,
+            cache_capacity,
+            cache: RefCell::new(VecDeque::new()),
+        })
+    }
+
+    ///
+    /// Decoded length of the file.
+    ///
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.decoded_len
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.decoded_len == 0
+    }
+
+    ///
+    /// The decoded bytes at `pos..pos + len`.
+    ///
+    /// # Errors
+    ///
+    /// Errors when the range is out of bounds or a block cannot be decoded.
+    pub fn read(&self, pos: usize, len: usize) -> Result<Block<'_>, Error> {
+        let Some(end) = pos.checked_add(len).filter(|end| *end <= self.decoded_len) else {
+            return Err(PBaseError::ReadOutOfBounds {
+                pos,
+                len,
+                file_len: self.decoded_len,
+            }
+            .into());
+        };
+
+        let Some(codec) = &self.codec else {
+            return Ok(Block::Borrowed(&self.bytes[pos..end]));
+        };
+
+        let first_block_idx = pos / self.block_byte_size;
+        let last_block_idx = (end.max(1) - 1) / self.block_byte_size;
+        let block_start = first_block_idx * self.block_byte_size;
+        if first_block_idx == last_block_idx {
+            let block = self.decoded_block(codec.as_ref(), first_block_idx)?;
+            return Ok(Block::Decoded(block, pos - block_start..end - block_start));
+        }
+
+        let mut out = Vec::with_capacity(len);
+        for block_idx in first_block_idx..=last_block_idx {
+            out.extend_from_slice(&self.decoded_block(codec.as_ref(), block_idx)?);
+        }
+        out.drain(..pos - block_start);
+        out.truncate(len);
+
+        Ok(Block::Owned(out))
+    }
+
+    ///
+    /// Number of decoded blocks held by the cache.
+    ///
+    /// # Panics
+    ///
+    /// When the cache is being updated (readers are single threaded, so never).
+    #[must_use]
+    pub fn cached_blocks(&self) -> usize {
+        self.cache.borrow().len()
+    }
+
+    fn decoded_block(&self, codec: &dyn BlockCodec, block_idx: usize) -> Result<Rc<[u8]>, Error> {
+        let mut cache = self.cache.borrow_mut();
+        if let Some(cache_pos) = cache.iter().position(|(idx, _)| *idx == block_idx) {
+            let entry = cache.remove(cache_pos).expect("Position is in the cache");
+            let block = entry.1.clone();
+            cache.push_front(entry);
+            return Ok(block);
+        }
+
+        let block: Rc<[u8]> = codec
+            .decode_block(&self.bytes, block_idx, self.block_byte_size)?
+            .into();
+        if self.cache_capacity > 0 {
+            cache.truncate(self.cache_capacity - 1);
+            cache.push_front((block_idx, block.clone()));
+        }
+
+        Ok(block)
+    }
+}
 
 pub struct TableOpener {
     pub dir: PathBuf,
+    // Used for the tables whose schema selects no strategy.
+    io_strategy: IoStrategy,
+    // Table data lengths written through this handle. Table maps are guaranteed to cover them.
+    committed_table_lens: Mutex<HashMap<String, usize>>,
+    // Directories of other databases by alias, their tables are read as `alias.table`.
+    attached_dirs: Mutex<HashMap<String, PathBuf>>,
+    // Table generations this handle saw last, writes check the table was not written since.
+    seen_generations: Mutex<HashMap<String, u64>>,
+    // Definitions of the custom field types, resolved in the schemas opened.
+    type_defs: TypeDefs,
 }
 
 impl TableOpener {
     #[must_use]
-    pub const fn new(dir: PathBuf) -> Self {
-        Self { dir }
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            io_strategy: IoStrategy::default(),
+            committed_table_lens: Mutex::new(HashMap::new()),
+            attached_dirs: Mutex::new(HashMap::new()),
+            seen_generations: Mutex::new(HashMap::new()),
+            type_defs: TypeDefs::default(),
+        }
+    }
+
+    ///
+    /// Registers a custom field type, see `PBase::register_type`.
+    ///
+    pub fn register_type<T: TypeDef + 'static>(&mut self, name: &str, type_def: T) {
+        self.type_defs.register(name, type_def);
+    }
+
+    #[must_use]
+    pub const fn type_defs(&self) -> &TypeDefs {
+        &self.type_defs
+    }
+
+    ///
+    /// Sets how the files of tables without their own IO strategy are read.
+    ///
+    #[must_use]
+    pub const fn with_io_strategy(mut self, io_strategy: IoStrategy) -> Self {
+        self.io_strategy = io_strategy;
+        self
+    }
+
+    ///
+    /// The IO strategy of the table: its own, or the opener's.
+    ///
+    #[must_use]
+    pub fn io_strategy(&self, table_schema: &TableSchema) -> IoStrategy {
+        table_schema.io_strategy.unwrap_or(self.io_strategy)
     }
 
     #[must_use]
     pub fn table_data_file_name(&self, table_name: &str) -> PathBuf {
-        let mut out = self.dir.clone();
-        out.push(format!("{table_name}.pbd"));
-        out
+        self.table_file_name(table_name, ".pbd")
     }
 
     #[must_use]
     pub fn table_schema_file_name(&self, table_name: &str) -> PathBuf {
+        self.table_file_name(table_name, ".pbs")
+    }
+
+    ///
+    /// Dictionaries of the table's dictionary encoded columns (see
+    /// `TableSchema::dictionary_columns`).
+    ///
+    #[must_use]
+    pub fn table_dictionary_file_name(&self, table_name: &str) -> PathBuf {
+        self.table_file_name(table_name, ".pbv")
+    }
+
+    #[must_use]
+    pub fn wal_file_name(&self) -> PathBuf {
         let mut out = self.dir.clone();
-        out.push(format!("{table_name}.pbs"));
+        out.push("pbase.wal");
         out
     }
 
     #[must_use]
     pub fn index_file_name(&self, table_name: &str, index_name: &str) -> PathBuf {
+        self.table_file_name(table_name, &format!("__{index_name}.pbi"))
+    }
+
+    ///
+    /// Advisory lock file taken by the directory's writer (see `PBase::try_lock_writer`).
+    ///
+    #[must_use]
+    pub fn writer_lock_file_name(&self) -> PathBuf {
         let mut out = self.dir.clone();
-        out.push(format!("{table_name}__{index_name}.pbi"));
+        out.push("pbase.lock");
         out
     }
 
+    ///
+    /// Statistics sidecar of the table, written by ANALYZE.
+    ///
+    #[must_use]
+    pub fn table_stats_file_name(&self, table_name: &str) -> PathBuf {
+        self.table_file_name(table_name, ".pbt")
+    }
+
+    ///
+    /// Generation counter of the table, bumped on every committed write.
+    ///
+    #[must_use]
+    pub fn table_generation_file_name(&self, table_name: &str) -> PathBuf {
+        self.table_file_name(table_name, ".pbg")
+    }
+
+    ///
+    /// Advisory lock file of the table's writes, see `lock_table`.
+    ///
+    #[must_use]
+    pub fn table_lock_file_name(&self, table_name: &str) -> PathBuf {
+        self.table_file_name(table_name, ".pbk")
+    }
+
+    ///
+    /// Registration of an external (CSV or Parquet) table, in place of a schema file.
+    ///
+    #[must_use]
+    pub fn external_table_file_name(&self, table_name: &str) -> PathBuf {
+        self.table_file_name(table_name, ".pbe")
+    }
+
+    ///
+    /// Unsorted index entries appended since the index was last merged.
+    ///
+    #[must_use]
+    pub fn index_delta_file_name(&self, table_name: &str, index_name: &str) -> PathBuf {
+        self.table_file_name(table_name, &format!("__{index_name}.pbx"))
+    }
+
+    ///
+    /// Attaches the database in `dir` under the alias: its tables are then opened as
+    /// `alias.table`.
+    ///
+    /// # Errors
+    ///
+    /// When `dir` is not a directory, the alias is not a valid name (it cannot have dots either),
+    /// or a database is already attached under it.
+    ///
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    pub fn attach(&self, dir: PathBuf, alias: &str) -> Result<(), Error> {
+        validate_file_stem(alias)?;
+        if alias.contains('.') {
+            return Err(PBaseError::InvalidFileName(alias.to_string()).into());
+        }
+        if !dir.is_dir() {
+            return Err(PBaseError::InvalidArgument(format!(
+                "{} is not a directory",
+                dir.display()
+            ))
+            .into());
+        }
+
+        match self.attached_dirs.lock().unwrap().entry(alias.to_string()) {
+            Entry::Occupied(_) => {
+                Err(PBaseError::DatabaseAlreadyAttached(alias.to_string()).into())
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(dir);
+                Ok(())
+            }
+        }
+    }
+
+    ///
+    /// Whether the table is one of an attached database (named `alias.table`, see `attach`).
+    ///
+    #[must_use]
+    pub fn is_attached_table(&self, table_name: &str) -> bool {
+        self.attached_table(table_name).is_some()
+    }
+
+    //
+    // The directory of the attached database of the table and its name in there, None for tables
+    // of this directory. Local table names may have dots as well: only a prefix naming an attached
+    // database qualifies.
+    //
+    fn attached_table<'a>(&self, table_name: &'a str) -> Option<(PathBuf, &'a str)> {
+        let (alias, name) = table_name.split_once('.')?;
+        let attached_dirs = self.attached_dirs.lock().unwrap();
+        attached_dirs.get(alias).map(|dir| (dir.clone(), name))
+    }
+
+    //
+    // A file of the table: its name followed by the suffix, in the directory of the table's
+    // database.
+    //
+    fn table_file_name(&self, table_name: &str, suffix: &str) -> PathBuf {
+        let (mut out, name) = self
+            .attached_table(table_name)
+            .unwrap_or_else(|| (self.dir.clone(), table_name));
+        out.push(format!("{name}{suffix}"));
+        out
+    }
+
+    ///
+    /// Names of all tables (having a schema file) in the directory, sorted.
+    ///
+    /// # Errors
+    ///
+    /// On file operations.
+    pub fn table_names(&self) -> Result<Vec<String>, Error> {
+        let mut table_names = vec![];
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "pbs") {
+                if let Some(table_name) = path.file_stem() {
+                    table_names.push(table_name.to_string_lossy().to_string());
+                }
+            }
+        }
+        table_names.sort();
+
+        Ok(table_names)
+    }
+
+    ///
+    /// Records that the table data is at least `len` bytes long, after a write through this handle.
+    ///
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    pub fn commit_table_len(&self, table_name: &str, len: usize) {
+        self.committed_table_lens
+            .lock()
+            .unwrap()
+            .entry(table_name.to_string())
+            .and_modify(|committed_len| *committed_len = (*committed_len).max(len))
+            .or_insert(len);
+    }
+
+    ///
+    /// Drops what this handle recorded of the table, once it is renamed away or rewritten.
+    ///
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    pub fn forget_table(&self, table_name: &str) {
+        self.committed_table_lens.lock().unwrap().remove(table_name);
+        self.seen_generations.lock().unwrap().remove(table_name);
+    }
+
+    ///
+    /// Writes committed to the table so far, by any handle (0 for tables never written).
+    ///
+    /// # Errors
+    ///
+    /// On file operations, or when the generation file is invalid.
+    pub fn table_generation(&self, table_name: &str) -> Result<u64, Error> {
+        let bytes = match std::fs::read(self.table_generation_file_name(table_name)) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err.into()),
+        };
+        let bytes: [u8; 8] = bytes
+            .try_into()
+            .map_err(|_| PBaseError::InvalidGeneration(table_name.to_string()))?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    ///
+    /// Takes the table's write lock, waiting while a write of any handle of the directory holds it.
+    /// Writes hold it from `check_generation` to `bump_generation`, so that no other handle writes
+    /// the table in between: two handles cannot both pass the check and write over each other.
+    ///
     /// # Errors
     ///
     /// On file operations.
+    pub fn lock_table(&self, table_name: &str) -> Result<FileLock, Error> {
+        FileLock::acquire(&self.table_lock_file_name(table_name))
+    }
+
+    ///
+    /// Checks that no other handle wrote the table since this one last saw it. The first check
+    /// of a table (or the first since `refresh_generations`) records its generation. Writes check
+    /// under the table's lock, see `lock_table`.
+    ///
+    /// # Errors
+    ///
+    /// `PBaseError::StaleHandle` when the table was written by another handle, or on reading
+    /// the generation.
+    ///
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    pub fn check_generation(&self, table_name: &str) -> Result<(), Error> {
+        let generation = self.table_generation(table_name)?;
+        match self
+            .seen_generations
+            .lock()
+            .unwrap()
+            .entry(table_name.to_string())
+        {
+            Entry::Occupied(seen) if *seen.get() != generation => Err(PBaseError::StaleHandle {
+                table: table_name.to_string(),
+                seen: *seen.get(),
+                generation,
+            }
+            .into()),
+            Entry::Occupied(_) => Ok(()),
+            Entry::Vacant(seen) => {
+                seen.insert(generation);
+                Ok(())
+            }
+        }
+    }
+
+    ///
+    /// Bumps the generation of the table after a committed write through this handle, which
+    /// keeps seeing the table as current. Writes bump under the table's lock, see `lock_table`.
+    ///
+    /// # Errors
+    ///
+    /// On file operations, or when the generation file is invalid.
+    ///
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    pub fn bump_generation(&self, table_name: &str) -> Result<(), Error> {
+        let generation = self.table_generation(table_name)? + 1;
+        atomic_write(
+            &self.table_generation_file_name(table_name),
+            &generation.to_le_bytes(),
+        )?;
+        self.seen_generations
+            .lock()
+            .unwrap()
+            .insert(table_name.to_string(), generation);
+        Ok(())
+    }
+
+    ///
+    /// Forgets the table generations seen and the lengths committed, so that reads and writes
+    /// accept the tables as they are now.
+    ///
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    pub fn refresh_generations(&self) {
+        self.seen_generations.lock().unwrap().clear();
+        self.committed_table_lens.lock().unwrap().clear();
+    }
+
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    #[must_use]
+    pub fn committed_table_len(&self, table_name: &str) -> usize {
+        self.committed_table_lens
+            .lock()
+            .unwrap()
+            .get(table_name)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    //
+    // The length a read of the table must cover, now that its file is `len` bytes long: what this
+    // handle committed. Unless another handle wrote the table since (its generation moved past the
+    // one seen here), eg. deleted rows: the file is then the table as that handle left it, and its
+    // length is what is committed from now on.
+    //
+    fn required_table_len(&self, table_name: &str, len: usize) -> Result<usize, Error> {
+        let committed_len = self.committed_table_len(table_name);
+        if len >= committed_len {
+            return Ok(committed_len);
+        }
+
+        let seen = self
+            .seen_generations
+            .lock()
+            .unwrap()
+            .get(table_name)
+            .copied();
+        if seen.is_none() || seen == Some(self.table_generation(table_name)?) {
+            return Ok(committed_len);
+        }
+        self.committed_table_lens
+            .lock()
+            .unwrap()
+            .insert(table_name.to_string(), len);
+
+        Ok(len)
+    }
+
+    ///
+    /// Maps the table data. The map covers every write committed through this handle: when the file
+    /// is seen shorter than that (eg. file sizes cached by a network filesystem), it is mapped
+    /// again after a short backoff, against the committed length as of then. A file another handle
+    /// shortened since is read as it is, see `required_table_len`.
+    ///
+    /// # Errors
+    ///
+    /// On file operations, or when the file stays shorter than the committed length.
     pub fn table_mmap(&self, table_name: &str) -> Result<Mmap, Error> {
-        let table_file = File::open(self.table_data_file_name(table_name))?;
-        Ok(unsafe { memmap::MmapOptions::new().map(&table_file)? })
+        let mut attempt = 1;
+        loop {
+            let table_file = File::open(self.table_data_file_name(table_name))?;
+            let table_mmap = unsafe { memmap::MmapOptions::new().map(&table_file)? };
+            let committed_len = self.required_table_len(table_name, table_mmap.len())?;
+            if table_mmap.len() >= committed_len {
+                return Ok(table_mmap);
+            }
+
+            let table_len = table_mmap.len();
+            debug!("Stale table map of {table_name}: {table_len} < {committed_len} bytes");
+            if attempt == STALE_TABLE_MAP_ATTEMPTS {
+                return Err(PBaseError::StaleTableRead {
+                    table: table_name.to_string(),
+                    len: table_len,
+                    committed_len,
+                }
+                .into());
+            }
+
+            drop(table_mmap);
+            std::thread::sleep(STALE_TABLE_MAP_BACKOFF * 2_u32.pow(attempt - 1));
+            attempt += 1;
+        }
     }
 
+    ///
+    /// The table data, read with the table's IO strategy. Covers every write committed through
+    /// this handle.
+    ///
+    /// # Errors
+    ///
+    /// On file operations, or when the file stays shorter than the committed length.
+    pub fn table_bytes(&self, table_schema: &TableSchema) -> Result<FileBytes, Error> {
+        let table_name = &table_schema.name;
+
+        match self.io_strategy(table_schema) {
+            IoStrategy::Mmap => {
+                // Empty files cannot be mapped.
+                let table_len = std::fs::metadata(self.table_data_file_name(table_name))?.len();
+                if table_len == 0 && self.required_table_len(table_name, 0)? == 0 {
+                    Ok(FileBytes::Owned(vec![]))
+                } else {
+                    Ok(FileBytes::Mapped(self.table_mmap(table_name)?))
+                }
+            }
+            IoStrategy::Buffered => {
+                let table_bytes = std::fs::read(self.table_data_file_name(table_name))?;
+                let committed_len = self.required_table_len(table_name, table_bytes.len())?;
+                if table_bytes.len() < committed_len {
+                    return Err(PBaseError::StaleTableRead {
+                        table: table_name.clone(),
+                        len: table_bytes.len(),
+                        committed_len,
+                    }
+                    .into());
+                }
+
+                Ok(FileBytes::Owned(table_bytes))
+            }
+        }
+    }
+
+    ///
+    /// The sorted part of the index. Empty when all entries are still in the delta, or when
+    /// nothing was inserted into the table yet (there is no index file).
+    ///
     /// # Errors
     ///
     /// On file operations.
-    pub fn index_mmap(&self, table_schema: &TableSchema, index_name: &str) -> Result<Mmap, Error> {
-        let index_file = File::open(self.index_file_name(&table_schema.name, index_name))?;
-        Ok(unsafe { memmap::MmapOptions::new().map(&index_file)? })
+    pub fn index_bytes(
+        &self,
+        table_schema: &TableSchema,
+        index_name: &str,
+    ) -> Result<FileBytes, Error> {
+        let index_file = match File::open(self.index_file_name(&table_schema.name, index_name)) {
+            Ok(index_file) => index_file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(FileBytes::Owned(vec![])),
+            Err(err) => return Err(err.into()),
+        };
+        if index_file.metadata()?.len() == 0 {
+            return Ok(FileBytes::Owned(vec![]));
+        }
+        if self.io_strategy(table_schema) == IoStrategy::Buffered {
+            let mut index_bytes = vec![];
+            (&index_file).read_to_end(&mut index_bytes)?;
+            return Ok(FileBytes::Owned(index_bytes));
+        }
+
+        Ok(FileBytes::Mapped(unsafe {
+            memmap::MmapOptions::new().map(&index_file)?
+        }))
     }
 
+    ///
+    /// The unsorted delta of the index, in insertion order. Empty when there is no delta file.
+    ///
     /// # Errors
     ///
     /// On file operations.
+    pub fn index_delta_bytes(
+        &self,
+        table_schema: &TableSchema,
+        index_name: &str,
+    ) -> Result<Vec<u8>, Error> {
+        match std::fs::read(self.index_delta_file_name(&table_schema.name, index_name)) {
+            Ok(bytes) => Ok(bytes),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(vec![]),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    ///
+    /// Statistics of the table, if it was analyzed.
+    ///
+    /// # Errors
+    ///
+    /// On file operations, or when the stats file is invalid.
+    pub fn open_stats(&self, table_name: &str) -> Result<Option<TableStats>, Error> {
+        match File::open(self.table_stats_file_name(table_name)) {
+            Ok(stats_file) => Ok(Some(serde_json::from_reader(stats_file)?)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    ///
+    /// The external table registered under the name, if any.
+    ///
+    /// # Errors
+    ///
+    /// On file operations, or when the registration file is invalid.
+    pub fn open_external_table(&self, table_name: &str) -> Result<Option<ExternalTable>, Error> {
+        match File::open(self.external_table_file_name(table_name)) {
+            Ok(external_file) => Ok(Some(serde_json::from_reader(external_file)?)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    ///
+    /// The schema of the table. Tables of attached databases are named by their qualified name
+    /// (`alias.table`), as queries refer to them.
+    ///
+    /// # Errors
+    ///
+    /// On file operations, when the fields do not have the layout of the data (see
+    /// `TableSchema::check_row_layout`), or when a custom field type is not registered.
     pub fn open_schema(&self, table_name: &str) -> Result<TableSchema, Error> {
         let schema_file = File::open(self.table_schema_file_name(table_name))?;
-        let table_schema = serde_json::from_reader(schema_file)?;
+        let mut table_schema: TableSchema = serde_json::from_reader(schema_file)?;
+        if self.is_attached_table(table_name) {
+            table_schema.name = table_name.to_string();
+        }
+        table_schema.check_row_layout()?;
+        table_schema.resolve_types(&self.type_defs, true)?;
+        if !table_schema.dictionary_columns.is_empty() {
+            table_schema.dictionaries =
+                read_dictionaries(&self.table_dictionary_file_name(table_name))?;
+        }
         Ok(table_schema)
     }
 
@@ -74,3 +919,76 @@ impl TableOpener {
         Ok(file)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::common::Error;
+
+    use std::sync::atomic::AtomicBool;
+
+    use super::{prefetch, Block, BlockCodec, BlockReader, Prefetcher, PREFETCH_BLOCK_BYTES};
+
+    // Stores every byte inverted, in blocks of the decoded size.
+    struct InvertCodec;
+
+    impl BlockCodec for InvertCodec {
+        fn decoded_len(&self, bytes: &[u8]) -> Result<usize, Error> {
+            Ok(bytes.len())
+        }
+
+        fn decode_block(
+            &self,
+            bytes: &[u8],
+            block_idx: usize,
+            block_byte_size: usize,
+        ) -> Result<Vec<u8>, Error> {
+            let start = block_idx * block_byte_size;
+            let end = (start + block_byte_size).min(bytes.len());
+            Ok(bytes[start..end].iter().map(|byte| !byte).collect())
+        }
+    }
+
+    #[test]
+    fn test_block_reader() {
+        let bytes: Vec<u8> = (0..10).collect();
+        let plain = BlockReader::plain(&bytes[..], 4);
+        assert!(matches!(
+            plain.read(3, 2).unwrap(),
+            Block::Borrowed(&[3, 4])
+        ));
+        assert!(plain.read(8, 3).is_err());
+
+        let encoded_bytes: Vec<u8> = bytes.iter().map(|byte| !byte).collect();
+        let encoded =
+            BlockReader::encoded(&encoded_bytes[..], 4, Box::new(InvertCodec), 2).unwrap();
+        assert_eq!(10, encoded.len());
+        assert_eq!(&[1, 2], &*encoded.read(1, 2).unwrap());
+        assert_eq!(1, encoded.cached_blocks());
+
+        // Spans all three blocks, the last one shorter.
+        assert_eq!(&[2, 3, 4, 5, 6, 7, 8, 9], &*encoded.read(2, 8).unwrap());
+        assert_eq!(2, encoded.cached_blocks());
+        assert_eq!(&[9], &*encoded.read(9, 1).unwrap());
+        assert!(encoded.read(9, 2).is_err());
+    }
+
+    #[test]
+    fn test_prefetch() {
+        let file_name = std::env::temp_dir().join("pbase_prefetch_test.pbd");
+        std::fs::write(&file_name, vec![1u8; PREFETCH_BLOCK_BYTES * 2 + 1]).unwrap();
+
+        assert!(prefetch(&file_name, usize::MAX, &AtomicBool::new(false)).is_ok());
+        assert!(prefetch(&file_name, usize::MAX, &AtomicBool::new(true)).is_ok());
+        assert!(prefetch(
+            &file_name.with_extension("missing"),
+            1,
+            &AtomicBool::new(false)
+        )
+        .is_err());
+
+        // Dropping stops and joins the thread.
+        drop(Prefetcher::spawn(file_name.clone(), usize::MAX));
+
+        std::fs::remove_file(file_name).unwrap();
+    }
+}
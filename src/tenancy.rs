@@ -0,0 +1,157 @@
+use crate::{
+    common::{Error, PBaseError},
+    pbase::PBase,
+    query::{CompareOp, FieldSelector, InsertQuery, RhsValue, RowFilter, SelectQuery, UnionQuery},
+    result_set::ResultSet,
+    schema::FieldSchema,
+    system_tables::is_system_table,
+    value::Value,
+};
+
+///
+/// A view of the database for a single tenant.
+///
+/// Tables with a `tenant_column` in their schema are shared by tenants: selects only see the tenant's rows (a `tenant_column = tenant_id` filter is
+/// added for each such table, joined and subqueried ones included), and inserts get the tenant id
+/// set. Other tables are accessed as is.
+///
+pub struct TenantScopedPBase<'a> {
+    db: &'a PBase,
+    tenant_id: Value,
+}
+
+impl<'a> TenantScopedPBase<'a> {
+    #[must_use]
+    pub const fn new(db: &'a PBase, tenant_id: Value) -> Self {
+        Self { db, tenant_id }
+    }
+
+    #[must_use]
+    pub const fn tenant_id(&self) -> &Value {
+        &self.tenant_id
+    }
+
+    /// # Errors
+    ///
+    /// Errors on file operations, or when the tenant id does not fit a tenant column.
+    pub fn run_select_query(&self, query: SelectQuery) -> Result<ResultSet, Error> {
+        self.db.run_select_query(self.scope_select(query)?)
+    }
+
+    /// # Errors
+    ///
+    /// Errors on file operations, when the tenant id does not fit a tenant column, or when the
+    /// selects' columns are incompatible.
+    pub fn run_union_query(&self, query: UnionQuery) -> Result<ResultSet, Error> {
+        let selects = query
+            .selects
+            .into_iter()
+            .map(|select| self.scope_select(select))
+            .collect::<Result<_, Error>>()?;
+
+        self.db.run_union_query(UnionQuery { selects, ..query })
+    }
+
+    ///
+    /// Inserts the row with the tenant id set. A row already carrying another tenant's id is
+    /// rejected.
+    ///
+    /// # Errors
+    ///
+    /// Errors on file operations, when the row belongs to another tenant, or when the tenant id
+    /// does not fit the tenant column.
+    pub fn run_insert_query(&self, query: &InsertQuery) -> Result<usize, Error> {
+        let Some((tenant_column, field_schema, tenant_id)) = self.tenant_column(&query.table)?
+        else {
+            return self.db.run_insert_query(query);
+        };
+
+        let mut query = query.clone();
+        if let Some(value) = query.values.get(&tenant_column) {
+            if *value != Value::NULL && field_schema.coerce(value).as_ref() != Some(&tenant_id) {
+                return Err(PBaseError::TenantMismatch {
+                    table: query.table,
+                    tenant_id: value.to_string(),
+                }
+                .into());
+            }
+        }
+        query.values.insert(tenant_column, tenant_id);
+
+        self.db.run_insert_query(&query)
+    }
+
+    fn scope_select(&self, mut query: SelectQuery) -> Result<SelectQuery, Error> {
+        let mut sources = vec![query.from.clone()];
+        sources.extend(
+            query
+                .joins
+                .iter()
+                .map(|join_contract| join_contract.rhs.source.clone()),
+        );
+
+        for source in sources {
+            if query.is_derived_table(&source) {
+                continue;
+            }
+            if let Some((tenant_column, _, tenant_id)) =
+                self.tenant_column(query.table_name(&source))?
+            {
+                query.filters.push(RowFilter {
+                    field: FieldSelector {
+                        name: tenant_column,
+                        source,
+                    },
+                    op: CompareOp::Eq,
+                    rhs: RhsValue::Value(tenant_id),
+                });
+            }
+        }
+
+        for derived_table in query.derived_tables.values_mut() {
+            *derived_table = self.scope_select(std::mem::take(derived_table))?;
+        }
+        for filter in &mut query.filters {
+            if let RhsValue::Subquery { query, .. } = &mut filter.rhs {
+                **query = self.scope_select(std::mem::take(query.as_mut()))?;
+            }
+        }
+        for scalar_subquery in &mut query.scalar_subqueries {
+            scalar_subquery.query =
+                self.scope_select(std::mem::take(&mut scalar_subquery.query))?;
+        }
+
+        Ok(query)
+    }
+
+    //
+    // The table's tenant column, its type and the tenant id converted to it, for tenant scoped
+    // tables.
+    //
+    fn tenant_column(
+        &self,
+        table_name: &str,
+    ) -> Result<Option<(String, FieldSchema, Value)>, Error> {
+        if is_system_table(table_name) {
+            return Ok(None);
+        }
+
+        let table_schema = self.db.table_schema(table_name)?;
+        let Some(tenant_column) = table_schema.tenant_column else {
+            return Ok(None);
+        };
+
+        let mismatch = || PBaseError::FieldTypeMismatch {
+            field: tenant_column.clone(),
+            value: self.tenant_id.to_string(),
+        };
+        let field_schema = table_schema
+            .fields
+            .get(&tenant_column)
+            .ok_or_else(mismatch)?
+            .clone();
+        let tenant_id = field_schema.coerce(&self.tenant_id).ok_or_else(mismatch)?;
+
+        Ok(Some((tenant_column, field_schema, tenant_id)))
+    }
+}
@@ -0,0 +1,232 @@
+use std::{collections::BTreeMap, collections::HashMap};
+
+use indexmap::IndexMap;
+
+use crate::{
+    common::{Error, PBaseError},
+    numeric::SumAccumulator,
+    pbase::PBase,
+    query::{
+        CompareOp, CreateTableQuery, FieldSelector, InsertQuery, RhsValue, RowFilter, SelectQuery,
+    },
+    schema::{FieldSchema, TableSchema},
+    value::Value,
+};
+
+const TS_FIELD: &str = "ts";
+const KEY_FIELD: &str = "key";
+const VALUE_FIELD: &str = "value";
+const TS_INDEX: &str = "ts_index";
+
+///
+/// Aggregates of a key's points within one bucket of a range query.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeSeriesBucket {
+    // Lowest timestamp of the bucket.
+    pub start: i64,
+    pub key: i32,
+    pub count: u64,
+    pub min: i32,
+    pub max: i32,
+    // Truncated toward zero.
+    pub avg: i32,
+}
+
+///
+/// Time series of (timestamp, key, value) points, all I32.
+///
+/// Points are partitioned by time: each `partition_span` long range of timestamps is stored in its
+/// own table (`<name>__ts<partition number>`, indexed by timestamp), created on the first point
+/// falling into it. Range queries only read the partitions overlapping the range, and dropping old
+/// data is dropping whole partition tables.
+///
+pub struct TimeSeriesTable<'a> {
+    db: &'a PBase,
+    name: String,
+    partition_span: i32,
+}
+
+impl<'a> TimeSeriesTable<'a> {
+    ///
+    /// The time series stored in the partition tables of `name`. The same span must be used every
+    /// time the series is opened.
+    ///
+    /// # Errors
+    ///
+    /// When the span is not positive, or when an existing partition is not a time series table.
+    pub fn open(db: &'a PBase, name: &str, partition_span: i32) -> Result<Self, Error> {
+        if partition_span <= 0 {
+            return Err(
+                PBaseError::InvalidArgument(format!("partition span {partition_span}")).into(),
+            );
+        }
+
+        let time_series = Self {
+            db,
+            name: name.to_string(),
+            partition_span,
+        };
+        for (partition, _) in time_series.partitions()? {
+            let partition_table = time_series.partition_table(partition);
+            let table_schema = db.table_schema(&partition_table)?;
+            let expected_schema = partition_schema(&partition_table);
+            if table_schema.fields != expected_schema.fields
+                || table_schema.indices != expected_schema.indices
+            {
+                return Err(PBaseError::NotATimeSeriesTable(partition_table).into());
+            }
+        }
+
+        Ok(time_series)
+    }
+
+    /// # Errors
+    ///
+    /// On file operations.
+    pub fn insert(&self, ts: i32, key: i32, value: i32) -> Result<(), Error> {
+        let partition_table = self.partition_table(ts.div_euclid(self.partition_span));
+        if !self.db.is_table_exist(&partition_table) {
+            self.db.run_create_table_query(&CreateTableQuery {
+                schema: partition_schema(&partition_table),
+            })?;
+        }
+
+        self.db.run_insert_query(&InsertQuery {
+            table: partition_table,
+            values: HashMap::from([
+                (TS_FIELD.to_string(), Value::I32(ts)),
+                (KEY_FIELD.to_string(), Value::I32(key)),
+                (VALUE_FIELD.to_string(), Value::I32(value)),
+            ]),
+        })?;
+
+        Ok(())
+    }
+
+    ///
+    /// Points with timestamps in `start..end`, aggregated per key into `downsample` long buckets
+    /// (aligned to multiples of it), ordered by bucket start and key. Without downsampling each
+    /// timestamp is its own bucket.
+    ///
+    /// # Errors
+    ///
+    /// On file operations, or when `downsample` is not positive.
+    pub fn query_range(
+        &self,
+        start: i32,
+        end: i32,
+        downsample: Option<i32>,
+    ) -> Result<Vec<TimeSeriesBucket>, Error> {
+        let bucket_span = i64::from(downsample.unwrap_or(1));
+        if bucket_span <= 0 {
+            return Err(PBaseError::InvalidArgument(format!("downsample {bucket_span}")).into());
+        }
+
+        let mut buckets: BTreeMap<(i64, i32), (i32, i32, SumAccumulator)> = BTreeMap::new();
+        for (partition, partition_table) in self.partitions()? {
+            let partition_start = i64::from(partition) * i64::from(self.partition_span);
+            let partition_end = partition_start + i64::from(self.partition_span);
+            if partition_end <= i64::from(start) || partition_start >= i64::from(end) {
+                continue;
+            }
+
+            self.db.for_each_row_view(
+                SelectQuery {
+                    from: partition_table.clone(),
+                    filters: vec![
+                        ts_filter(&partition_table, CompareOp::Ge, start),
+                        ts_filter(&partition_table, CompareOp::Lt, end),
+                    ],
+                    ..Default::default()
+                },
+                |row_view| {
+                    let (Some(Value::I32(ts)), Some(Value::I32(key)), Some(Value::I32(value))) = (
+                        row_view.get(TS_FIELD),
+                        row_view.get(KEY_FIELD),
+                        row_view.get(VALUE_FIELD),
+                    ) else {
+                        return;
+                    };
+
+                    let bucket_start = i64::from(ts).div_euclid(bucket_span) * bucket_span;
+                    let (min, max, sum) = buckets.entry((bucket_start, key)).or_insert((
+                        value,
+                        value,
+                        SumAccumulator::new(),
+                    ));
+                    *min = (*min).min(value);
+                    *max = (*max).max(value);
+                    sum.add(&Value::I32(value));
+                },
+            )?;
+        }
+
+        buckets
+            .into_iter()
+            .map(|((start, key), (min, max, sum))| {
+                // Never NULL, buckets have at least one value.
+                let avg = match sum.avg()? {
+                    Value::I32(avg) => avg,
+                    _ => 0,
+                };
+                Ok(TimeSeriesBucket {
+                    start,
+                    key,
+                    count: sum.count(),
+                    min,
+                    max,
+                    avg,
+                })
+            })
+            .collect()
+    }
+
+    //
+    // Existing partitions with their tables, by partition number.
+    //
+    fn partitions(&self) -> Result<Vec<(i32, String)>, Error> {
+        let prefix = format!("{}__ts", self.name);
+
+        let mut partitions: Vec<(i32, String)> = self
+            .db
+            .table_names()?
+            .into_iter()
+            .filter_map(|table_name| {
+                let partition = table_name.strip_prefix(&prefix)?.parse().ok()?;
+                Some((partition, table_name))
+            })
+            .collect();
+        partitions.sort_unstable();
+
+        Ok(partitions)
+    }
+
+    fn partition_table(&self, partition: i32) -> String {
+        format!("{}__ts{partition}", self.name)
+    }
+}
+
+fn partition_schema(partition_table: &str) -> TableSchema {
+    TableSchema {
+        name: partition_table.to_string(),
+        fields: IndexMap::from([
+            (TS_FIELD.to_string(), FieldSchema::I32),
+            (KEY_FIELD.to_string(), FieldSchema::I32),
+            (VALUE_FIELD.to_string(), FieldSchema::I32),
+        ]),
+        indices: IndexMap::from([(TS_INDEX.to_string(), vec![TS_FIELD.to_string()])]),
+        ..Default::default()
+    }
+}
+
+fn ts_filter(partition_table: &str, op: CompareOp, ts: i32) -> RowFilter {
+    RowFilter {
+        field: FieldSelector {
+            name: TS_FIELD.to_string(),
+            source: partition_table.to_string(),
+        },
+        op,
+        rhs: RhsValue::Value(Value::I32(ts)),
+    }
+}
@@ -1,18 +1,76 @@
-use std::cmp::Ordering;
+use std::{
+    cmp::Ordering,
+    fmt::{Display, Formatter},
+};
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+use serde::{Deserialize, Serialize};
+
+use crate::{custom_type::CustomValue, decimal::Decimal};
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
 pub enum Value {
     NULL,
     I32(i32),
     U8(u8),
+    // Value of a DECIMAL field, see `FieldSchema::Decimal`.
+    Decimal(Decimal),
+    // Value of a type registered by the application, see `TypeDef`.
+    Custom(CustomValue),
 }
 
 impl Value {
+    ///
+    /// The interval from the earlier timestamp to this one, negative when this one is earlier.
+    /// Timestamps are I32 (or U8) values in the application's unit, None for other values.
+    ///
+    #[must_use]
+    pub fn interval_since(&self, earlier: &Self) -> Option<Interval> {
+        let timestamp = |value: &Self| match value {
+            Self::I32(v) => Some(i64::from(*v)),
+            Self::U8(v) => Some(i64::from(*v)),
+            Self::NULL | Self::Decimal(_) | Self::Custom(_) => None,
+        };
+        Some(Interval(timestamp(self)? - timestamp(earlier)?))
+    }
+
+    ///
+    /// Writes the stored bytes of the value. Decimals fill the buffer, the 8 or 16 bytes of their
+    /// field (see `decimal_byte_size`).
+    ///
     pub fn copy_bytes_to(&self, buf: &mut [u8]) {
         match self {
             Self::NULL => {} // Noop.
             Self::I32(v) => buf[0..4].copy_from_slice(&v.to_le_bytes()),
             Self::U8(v) => buf[0] = *v,
+            Self::Decimal(v) => {
+                let len = buf.len().min(16);
+                buf[..len].copy_from_slice(&v.unscaled.to_le_bytes()[..len]);
+            }
+            Self::Custom(v) => buf[0..v.bytes.len()].copy_from_slice(&v.bytes),
+        }
+    }
+}
+
+///
+/// Time between two timestamps, in their unit (eg. milliseconds), see `Value::interval_since`.
+///
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct Interval(pub i64);
+
+impl Display for Interval {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NULL => write!(f, "NULL"),
+            Self::I32(v) => write!(f, "{v}"),
+            Self::U8(v) => write!(f, "{v}"),
+            Self::Decimal(v) => write!(f, "{v}"),
+            Self::Custom(v) => write!(f, "{v}"),
         }
     }
 }
@@ -28,12 +86,23 @@ impl Ord for Value {
         match (self, other) {
             (Self::NULL, Self::NULL) => Ordering::Equal,
 
-            (Self::NULL, Self::I32(_) | Self::U8(_)) => Ordering::Less,
-            (Self::I32(_) | Self::U8(_), Self::NULL) => Ordering::Greater,
+            (Self::NULL, _) => Ordering::Less,
+            (_, Self::NULL) => Ordering::Greater,
 
             (Self::I32(lhs), Self::I32(rhs)) => lhs.cmp(rhs),
             (Self::U8(lhs), Self::U8(rhs)) => lhs.cmp(rhs),
 
+            (Self::Decimal(lhs), Self::Decimal(rhs)) => lhs.cmp(rhs),
+            (Self::Decimal(lhs), Self::I32(rhs)) => lhs.cmp(&Decimal::from(*rhs)),
+            (Self::Decimal(lhs), Self::U8(rhs)) => lhs.cmp(&Decimal::from(i32::from(*rhs))),
+            (Self::I32(lhs), Self::Decimal(rhs)) => Decimal::from(*lhs).cmp(rhs),
+            (Self::U8(lhs), Self::Decimal(rhs)) => Decimal::from(i32::from(*lhs)).cmp(rhs),
+
+            // Plain values are compared as the custom type, eg. filter values of custom fields.
+            (Self::Custom(lhs), Self::Custom(rhs)) => lhs.cmp(rhs),
+            (Self::Custom(lhs), rhs) => lhs.cmp_plain(rhs),
+            (lhs, Self::Custom(rhs)) => rhs.cmp_plain(lhs).reverse(),
+
             _ => panic!("Values cannot be compared {self:?} ? {other:?  }"),
         }
     }
@@ -75,4 +144,18 @@ mod test {
 
         assert_eq!(vec![0, 1, 2, 3, 4, 0], buf);
     }
+
+    #[test]
+    fn test_interval_since() {
+        assert_eq!(
+            Some(Interval(-3_600_000)),
+            Value::I32(0).interval_since(&Value::I32(3_600_000))
+        );
+        // Differences of I32 timestamps do not overflow.
+        assert_eq!(
+            Some(Interval(i64::from(i32::MAX) - i64::from(i32::MIN))),
+            Value::I32(i32::MAX).interval_since(&Value::I32(i32::MIN))
+        );
+        assert_eq!(None, Value::NULL.interval_since(&Value::I32(1)));
+    }
 }
@@ -0,0 +1,195 @@
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::{Error, PBaseError},
+    query::{CreateTableQuery, InsertQuery},
+    schema::TablePtrType,
+    value::Value,
+};
+
+///
+/// Log sequence number. Records are numbered from 1, without gaps.
+///
+pub type Lsn = u64;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WalOp {
+    // Boxed: schemas are much larger than the other operations.
+    CreateTable(Box<CreateTableQuery>),
+    Insert(InsertQuery),
+    UpdateRowAt {
+        table: String,
+        row_pos: TablePtrType,
+        values: HashMap<String, Value>,
+    },
+    CreateIndex {
+        table: String,
+        index_name: String,
+        fields: Vec<String>,
+    },
+    RenameTable {
+        from: String,
+        to: String,
+    },
+    CloneTable {
+        source: String,
+        target: String,
+    },
+    // Rows appended as stored, see `PBase::copy_rows`.
+    CopyRows {
+        table: String,
+        rows: Vec<u8>,
+    },
+    // Rows removed by `PBase::run_delete_query`, by their positions before the delete.
+    DeleteRows {
+        table: String,
+        row_positions: Vec<TablePtrType>,
+    },
+    // Field dictionary encoded by `PBase::compact_table`.
+    EncodeDictionaryColumn {
+        table: String,
+        field: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalRecord {
+    pub lsn: Lsn,
+    pub op: WalOp,
+}
+
+///
+/// Log of the mutations committed in a directory, one JSON record per line. A record is appended
+/// once its mutation succeeded, so replaying the log in order reproduces the directory.
+///
+/// A directory must only be written through a single `PBase` handle, which owns the numbering.
+///
+pub struct Wal {
+    file_name: PathBuf,
+    // Loaded from the file on first use.
+    last_lsn: Mutex<Option<Lsn>>,
+}
+
+impl Wal {
+    #[must_use]
+    pub const fn new(file_name: PathBuf) -> Self {
+        Self {
+            file_name,
+            last_lsn: Mutex::new(None),
+        }
+    }
+
+    /// LSN of the last record, 0 for an empty log.
+    ///
+    /// # Errors
+    ///
+    /// On file operations or unreadable records.
+    ///
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    pub fn last_lsn(&self) -> Result<Lsn, Error> {
+        let mut last_lsn = self.last_lsn.lock().unwrap();
+        self.loaded_last_lsn(&mut last_lsn)
+    }
+
+    /// # Errors
+    ///
+    /// On file operations.
+    ///
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    pub fn append(&self, op: WalOp) -> Result<Lsn, Error> {
+        let mut last_lsn = self.last_lsn.lock().unwrap();
+        let lsn = self.loaded_last_lsn(&mut last_lsn)? + 1;
+
+        self.write_record(&WalRecord { lsn, op })?;
+        // Held across the write, so records are appended in LSN order.
+        *last_lsn = Some(lsn);
+        drop(last_lsn);
+
+        Ok(lsn)
+    }
+
+    ///
+    /// Appends a record numbered elsewhere (by the primary a replica follows).
+    ///
+    /// # Errors
+    ///
+    /// On file operations, or when the record does not directly follow the last one.
+    ///
+    /// # Panics
+    ///
+    /// When the lock is poisoned.
+    pub fn append_record(&self, record: &WalRecord) -> Result<(), Error> {
+        let mut last_lsn = self.last_lsn.lock().unwrap();
+        let expected_lsn = self.loaded_last_lsn(&mut last_lsn)? + 1;
+        if record.lsn != expected_lsn {
+            return Err(PBaseError::WalGap {
+                expected_lsn,
+                lsn: record.lsn,
+            }
+            .into());
+        }
+
+        self.write_record(record)?;
+        *last_lsn = Some(record.lsn);
+        drop(last_lsn);
+
+        Ok(())
+    }
+
+    fn loaded_last_lsn(&self, last_lsn: &mut Option<Lsn>) -> Result<Lsn, Error> {
+        if let Some(lsn) = *last_lsn {
+            return Ok(lsn);
+        }
+
+        let lsn = self.read_from(0)?.last().map_or(0, |record| record.lsn);
+        *last_lsn = Some(lsn);
+        Ok(lsn)
+    }
+
+    fn write_record(&self, record: &WalRecord) -> Result<(), Error> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.file_name)?
+            .write_all(&line)?;
+
+        Ok(())
+    }
+
+    ///
+    /// Records with LSN `from_lsn` and after, in order.
+    ///
+    /// # Errors
+    ///
+    /// On file operations or unreadable records.
+    pub fn read_from(&self, from_lsn: Lsn) -> Result<Vec<WalRecord>, Error> {
+        if !self.file_name.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut records = vec![];
+        for line in BufReader::new(File::open(&self.file_name)?).lines() {
+            let record: WalRecord = serde_json::from_str(&line?)?;
+            if record.lsn >= from_lsn {
+                records.push(record);
+            }
+        }
+
+        Ok(records)
+    }
+}
@@ -0,0 +1,69 @@
+use indexmap::IndexMap;
+use pbase::{
+    kv::KvStore,
+    pbase::PBase,
+    query::{CreateTableQuery, SelectQuery},
+    schema::{FieldSchema, TableSchema},
+};
+
+#[test]
+fn test_kv_store() {
+    let dir = std::env::temp_dir().join("pbase_kv_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+    let kv = KvStore::open(&db, "kv").unwrap();
+    assert_eq!(None, kv.get(1).unwrap());
+
+    for key in [5, -3, 1 << 8, (1 << 8) + 7, i32::MAX, 2 << 8] {
+        assert_eq!(None, kv.put(key, key / 2).unwrap());
+    }
+    assert_eq!(Some(2), kv.put(5, 50).unwrap());
+    assert_eq!(Some(50), kv.get(5).unwrap());
+    assert_eq!(Some(-1), kv.get(-3).unwrap());
+
+    assert_eq!(Some(50), kv.delete(5).unwrap());
+    assert_eq!(None, kv.delete(5).unwrap());
+    assert_eq!(None, kv.get(5).unwrap());
+    assert_eq!(vec![(-3, -1)], kv.scan(..256).unwrap());
+
+    // A put after a delete reuses the key's row.
+    assert_eq!(None, kv.put(5, 51).unwrap());
+    assert_eq!(Some(51), kv.get(5).unwrap());
+    assert_eq!(
+        6,
+        db.run_select_query(SelectQuery {
+            from: "kv".into(),
+            ..Default::default()
+        })
+        .unwrap()
+        .len()
+    );
+
+    assert_eq!(
+        vec![(5, 51), (256, 128), (263, 131)],
+        kv.scan(0..=263).unwrap()
+    );
+    assert_eq!(vec![(256, 128), (263, 131)], kv.scan_prefix(1, 8).unwrap());
+    assert_eq!(vec![(-3, -1)], kv.scan_prefix(-1, 8).unwrap());
+    assert_eq!(vec![(i32::MAX, i32::MAX / 2)], kv.scan(513..).unwrap());
+    assert!(kv.scan_prefix(1, 31).unwrap().is_empty());
+    assert_eq!(6, kv.scan_prefix(3, 32).unwrap().len());
+
+    // Reopened from the table.
+    let kv = KvStore::open(&db, "kv").unwrap();
+    assert_eq!(Some(51), kv.get(5).unwrap());
+
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "other".into(),
+            fields: IndexMap::from([("key".into(), FieldSchema::I32)]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    assert!(KvStore::open(&db, "other").is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
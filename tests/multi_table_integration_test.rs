@@ -1,25 +1,36 @@
-use std::{collections::HashMap, fs, path::PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+};
 
 use indexmap::IndexMap;
 use pbase::{
+    common::PBaseError,
+    lexer::Lexer,
     pbase::PBase,
+    plan::{PlanNode, QueryPlan},
+    platform::TempDir,
     query::{
-        CreateTableQuery, FieldSelector, InsertQuery, JoinContract, RhsValue, RowFilter,
-        SelectQuery,
+        Aggregate, CompareOp, Correlation, CreateTableQuery, DeleteQuery, FieldSelector,
+        InsertQuery, JoinContract, JoinType, Query, RhsValue, RowFilter, ScalarSubquery,
+        SelectQuery, SetOperation, UnionQuery,
     },
+    result_set::COMPUTED_COLUMNS,
     schema::{FieldSchema, TableSchema},
-    value::Value,
+    table_opener::TableOpener,
+    value::{Interval, Value},
 };
 
 #[test]
 fn test_single_tables() {
-    let db = setup_multi_tables("qqq");
+    let db = setup_multi_tables();
 
     // Total t1 query.
     let query = SelectQuery {
-        from: "qqq_t1".into(),
+        from: "t1".into(),
         joins: vec![],
         filters: vec![],
+        ..Default::default()
     };
 
     let query_result = db.run_select_query(query);
@@ -28,9 +39,10 @@ fn test_single_tables() {
 
     // Total t2 query.
     let query = SelectQuery {
-        from: "qqq_t2".into(),
+        from: "t2".into(),
         joins: vec![],
         filters: vec![],
+        ..Default::default()
     };
 
     let query_result = db.run_select_query(query);
@@ -40,26 +52,28 @@ fn test_single_tables() {
 
 #[test]
 fn test_join_table_all() {
-    let db = setup_multi_tables("www");
+    let db = setup_multi_tables();
 
     // Join query:
     // SELECT *
     // FROM t1
     // JOIN t2 ON t2.t1_id = t1.id
     let query = SelectQuery {
-        from: "www_t1".into(),
+        from: "t1".into(),
         joins: vec![JoinContract {
             join_type: pbase::query::JoinType::Inner,
+            null_keys_match: false,
             lhs: FieldSelector {
                 name: "id".into(),
-                source: "www_t1".into(),
+                source: "t1".into(),
             },
             rhs: FieldSelector {
                 name: "t1_id".into(),
-                source: "www_t2".into(),
+                source: "t2".into(),
             },
         }],
         filters: vec![],
+        ..Default::default()
     };
     let query_result = db.run_select_query(query);
     assert!(query_result.is_ok());
@@ -76,66 +90,85 @@ fn test_join_table_all() {
     // └──┴─────┘   └─────┴─────┘
 
     assert_eq!(
-        HashMap::from([
-            ("www_t1.id".to_string(), Value::I32(0)),
-            ("www_t1.value".to_string(), Value::I32(100)),
-            ("www_t2.t1_id".to_string(), Value::I32(0)),
-            ("www_t2.value".to_string(), Value::I32(1000)),
-            ("www_t2.v2".to_string(), Value::I32(555)),
+        IndexMap::from([
+            ("t1.id".into(), Value::I32(0)),
+            ("t1.value".into(), Value::I32(100)),
+            ("t2.t1_id".into(), Value::I32(0)),
+            ("t2.value".into(), Value::I32(1000)),
+            ("t2.v2".into(), Value::I32(555)),
         ]),
-        query_result[0],
+        query_result.rows[0],
     );
     assert_eq!(
-        HashMap::from([
-            ("www_t1.id".to_string(), Value::I32(0)),
-            ("www_t1.value".to_string(), Value::I32(100)),
-            ("www_t2.t1_id".to_string(), Value::I32(0)),
-            ("www_t2.value".to_string(), Value::I32(2000)),
-            ("www_t2.v2".to_string(), Value::I32(101)),
+        IndexMap::from([
+            ("t1.id".into(), Value::I32(0)),
+            ("t1.value".into(), Value::I32(100)),
+            ("t2.t1_id".into(), Value::I32(0)),
+            ("t2.value".into(), Value::I32(2000)),
+            ("t2.v2".into(), Value::I32(101)),
         ]),
-        query_result[1],
+        query_result.rows[1],
     );
     assert_eq!(
-        HashMap::from([
-            ("www_t1.id".to_string(), Value::I32(2)),
-            ("www_t1.value".to_string(), Value::I32(102)),
-            ("www_t2.t1_id".to_string(), Value::I32(2)),
-            ("www_t2.value".to_string(), Value::I32(3002)),
-            ("www_t2.v2".to_string(), Value::I32(102)),
+        IndexMap::from([
+            ("t1.id".into(), Value::I32(2)),
+            ("t1.value".into(), Value::I32(102)),
+            ("t2.t1_id".into(), Value::I32(2)),
+            ("t2.value".into(), Value::I32(3002)),
+            ("t2.v2".into(), Value::I32(102)),
         ]),
-        query_result[2],
+        query_result.rows[2],
+    );
+
+    // Row keys follow the column order: main table fields, then joined table fields.
+    let column_names: Vec<&str> = query_result
+        .columns
+        .iter()
+        .map(|column| column.name.as_str())
+        .collect();
+    assert_eq!(
+        vec!["t1.id", "t1.value", "t2.t1_id", "t2.value", "t2.v2"],
+        column_names
     );
+    for row in &query_result.rows {
+        assert_eq!(
+            column_names,
+            row.keys().map(AsRef::as_ref).collect::<Vec<_>>()
+        );
+    }
 }
 
 #[test]
 fn test_join_table_filtered() {
-    let db = setup_multi_tables("eee");
+    let db = setup_multi_tables();
 
     // Join query:
     // SELECT *
     // FROM t1
     // JOIN t2 ON t2.t1_id = t1.id
     let query = SelectQuery {
-        from: "eee_t1".into(),
+        from: "t1".into(),
         joins: vec![JoinContract {
             join_type: pbase::query::JoinType::Inner,
+            null_keys_match: false,
             lhs: FieldSelector {
                 name: "id".into(),
-                source: "eee_t1".into(),
+                source: "t1".into(),
             },
             rhs: FieldSelector {
                 name: "t1_id".into(),
-                source: "eee_t2".into(),
+                source: "t2".into(),
             },
         }],
         filters: vec![RowFilter {
             field: FieldSelector {
                 name: "value".to_string(),
-                source: "eee_t2".to_string(),
+                source: "t2".to_string(),
             },
-            op: std::cmp::Ordering::Greater,
+            op: CompareOp::Gt,
             rhs: RhsValue::Value(Value::I32(1500)),
         }],
+        ..Default::default()
     };
     let query_result = db.run_select_query(query);
     assert!(query_result.is_ok());
@@ -152,55 +185,57 @@ fn test_join_table_filtered() {
     // └──┴─────┘   └─────┴─────┴───┘
 
     assert_eq!(
-        HashMap::from([
-            ("eee_t1.id".to_string(), Value::I32(0)),
-            ("eee_t1.value".to_string(), Value::I32(100)),
-            ("eee_t2.t1_id".to_string(), Value::I32(0)),
-            ("eee_t2.value".to_string(), Value::I32(2000)),
-            ("eee_t2.v2".to_string(), Value::I32(101)),
+        IndexMap::from([
+            ("t1.id".into(), Value::I32(0)),
+            ("t1.value".into(), Value::I32(100)),
+            ("t2.t1_id".into(), Value::I32(0)),
+            ("t2.value".into(), Value::I32(2000)),
+            ("t2.v2".into(), Value::I32(101)),
         ]),
-        query_result[0],
+        query_result.rows[0],
     );
     assert_eq!(
-        HashMap::from([
-            ("eee_t1.id".to_string(), Value::I32(2)),
-            ("eee_t1.value".to_string(), Value::I32(102)),
-            ("eee_t2.t1_id".to_string(), Value::I32(2)),
-            ("eee_t2.value".to_string(), Value::I32(3002)),
-            ("eee_t2.v2".to_string(), Value::I32(102)),
+        IndexMap::from([
+            ("t1.id".into(), Value::I32(2)),
+            ("t1.value".into(), Value::I32(102)),
+            ("t2.t1_id".into(), Value::I32(2)),
+            ("t2.value".into(), Value::I32(3002)),
+            ("t2.v2".into(), Value::I32(102)),
         ]),
-        query_result[1],
+        query_result.rows[1],
     );
 }
 
 #[test]
 fn test_multi_table_cross_table_ref_filter() {
-    let db = setup_multi_tables("fff");
+    let db = setup_multi_tables();
 
     let query = SelectQuery {
-        from: "fff_t1".into(),
+        from: "t1".into(),
         joins: vec![JoinContract {
             join_type: pbase::query::JoinType::Inner,
+            null_keys_match: false,
             lhs: FieldSelector {
                 name: "id".into(),
-                source: "fff_t1".into(),
+                source: "t1".into(),
             },
             rhs: FieldSelector {
                 name: "t1_id".into(),
-                source: "fff_t2".into(),
+                source: "t2".into(),
             },
         }],
         filters: vec![RowFilter {
             field: FieldSelector {
                 name: "value".to_string(),
-                source: "fff_t1".to_string(),
+                source: "t1".to_string(),
             },
-            op: std::cmp::Ordering::Equal,
+            op: CompareOp::Eq,
             rhs: RhsValue::Ref(FieldSelector {
                 name: "v2".into(),
-                source: "fff_t2".into(),
+                source: "t2".into(),
             }),
         }],
+        ..Default::default()
     };
 
     // ┌──┬─────┐   ┌─────┬─────┬───┐
@@ -212,29 +247,1611 @@ fn test_multi_table_cross_table_ref_filter() {
     // │3 │103  │   │4    │4004 │99 │
     // └──┴─────┘   └─────┴─────┴───┘
 
-    let result = db.run_select_query(query).unwrap();
+    let result = db.run_select_query(query).unwrap().rows;
     assert_eq!(1, result.len());
 
     assert_eq!(
-        HashMap::from([
-            ("fff_t1.id".to_string(), Value::I32(2)),
-            ("fff_t1.value".to_string(), Value::I32(102)),
-            ("fff_t2.t1_id".to_string(), Value::I32(2)),
-            ("fff_t2.value".to_string(), Value::I32(3002)),
-            ("fff_t2.v2".to_string(), Value::I32(102)),
+        IndexMap::from([
+            ("t1.id".into(), Value::I32(2)),
+            ("t1.value".into(), Value::I32(102)),
+            ("t2.t1_id".into(), Value::I32(2)),
+            ("t2.value".into(), Value::I32(3002)),
+            ("t2.v2".into(), Value::I32(102)),
         ]),
         result[0],
     );
 }
 
-fn setup_multi_tables(prefix: &str) -> PBase {
-    let t1_name = format!("{prefix}_t1");
-    let t2_name = format!("{prefix}_t2");
+#[test]
+fn test_multi_table_interval_filter() {
+    let db = setup_multi_tables();
+
+    // The values taken as timestamps: rows of t2 less than 1000 after their t1 row.
+    let interval_query = |op: CompareOp, interval: i64| SelectQuery {
+        from: "t1".into(),
+        joins: vec![JoinContract {
+            join_type: JoinType::Inner,
+            null_keys_match: false,
+            lhs: FieldSelector {
+                name: "id".into(),
+                source: "t1".into(),
+            },
+            rhs: FieldSelector {
+                name: "t1_id".into(),
+                source: "t2".into(),
+            },
+        }],
+        filters: vec![RowFilter {
+            field: FieldSelector {
+                name: "value".into(),
+                source: "t2".into(),
+            },
+            op,
+            rhs: RhsValue::Interval {
+                reference: FieldSelector {
+                    name: "value".into(),
+                    source: "t1".into(),
+                },
+                interval: Interval(interval),
+            },
+        }],
+        ..Default::default()
+    };
+
+    // ┌──┬─────┐   ┌─────┬─────┬───┐
+    // │id│value│   │t1_id│value│v2 │
+    // ├──┼─────┤   ├─────┼─────┼───┤
+    // │0 │100  │   │0    │1000 │555│
+    // │1 │101  │   │0    │2000 │101│
+    // │2 │102  │   │2    │3002 │102│
+    // │3 │103  │   │4    │4004 │99 │
+    // └──┴─────┘   └─────┴─────┴───┘
+
+    let result = db
+        .run_select_query(interval_query(CompareOp::Lt, 1000))
+        .unwrap()
+        .rows;
+    assert_eq!(1, result.len());
+    assert_eq!(Value::I32(1000), result[0]["t2.value"]);
+
+    let result = db
+        .run_select_query(interval_query(CompareOp::Ge, 1900))
+        .unwrap()
+        .rows;
+    assert_eq!(
+        vec![Value::I32(2000), Value::I32(3002)],
+        result
+            .iter()
+            .map(|row| row["t2.value"].clone())
+            .collect::<Vec<_>>()
+    );
+
+    assert!(db
+        .explain_select_query(interval_query(CompareOp::Lt, 1000))
+        .unwrap()
+        .to_ascii_tree()
+        .contains("t2.value - t1.value < 1000"));
+}
+
+#[test]
+fn test_self_join_with_alias() {
+    let db = PBase::new_temp().unwrap();
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "employees".into(),
+            fields: IndexMap::from([
+                ("id".into(), FieldSchema::I32),
+                ("manager_id".into(), FieldSchema::I32),
+            ]),
+            indices: IndexMap::from([("id_index".into(), vec!["id".into()])]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    for (id, manager_id) in [(1, 3), (2, 3), (3, 4), (4, 0)] {
+        db.run_insert_query(&InsertQuery {
+            table: "employees".into(),
+            values: HashMap::from([
+                ("id".into(), Value::I32(id)),
+                ("manager_id".into(), Value::I32(manager_id)),
+            ]),
+        })
+        .unwrap();
+    }
+
+    let field = |source: &str, name: &str| FieldSelector {
+        name: name.into(),
+        source: source.into(),
+    };
+    let mut query = SelectQuery {
+        from: "employees".into(),
+        joins: vec![JoinContract {
+            join_type: JoinType::Inner,
+            null_keys_match: false,
+            lhs: field("employees", "manager_id"),
+            rhs: field("employees", "id"),
+        }],
+        ..Default::default()
+    };
+    assert!(matches!(
+        *db.run_select_query(query.clone())
+            .unwrap_err()
+            .downcast::<PBaseError>()
+            .unwrap(),
+        PBaseError::AmbiguousTable(_)
+    ));
+
+    // The employees of manager 3, with the manager's own manager.
+    query.joins[0].rhs = field("managers", "id");
+    query.aliases = BTreeMap::from([("managers".into(), "employees".into())]);
+    query.filters = vec![RowFilter {
+        field: field("managers", "id"),
+        op: CompareOp::Eq,
+        rhs: RhsValue::Value(Value::I32(3)),
+    }];
+    let result = db.run_select_query(query.clone()).unwrap();
+    assert_eq!(
+        vec![
+            IndexMap::from([
+                ("employees.id".into(), Value::I32(1)),
+                ("employees.manager_id".into(), Value::I32(3)),
+                ("managers.id".into(), Value::I32(3)),
+                ("managers.manager_id".into(), Value::I32(4)),
+            ]),
+            IndexMap::from([
+                ("employees.id".into(), Value::I32(2)),
+                ("employees.manager_id".into(), Value::I32(3)),
+                ("managers.id".into(), Value::I32(3)),
+                ("managers.manager_id".into(), Value::I32(4)),
+            ]),
+        ],
+        result.rows
+    );
+    assert!(db
+        .explain_select_query(query.clone())
+        .unwrap()
+        .to_ascii_tree()
+        .contains("IndexScan"));
+
+    query.aliases = BTreeMap::from([("man.agers".into(), "employees".into())]);
+    assert!(db.run_select_query(query).is_err());
+}
 
-    delete_all_by_glob(format!("{t1_name}*").as_str());
-    delete_all_by_glob(format!("{t2_name}*").as_str());
+#[test]
+fn test_derived_table() {
+    let db = setup_multi_tables();
+    let field = |source: &str, name: &str| FieldSelector {
+        name: name.into(),
+        source: source.into(),
+    };
+    // t2 rows with a value above 1500: of t1 rows 0, 2 and 4 (which is not in t1).
+    let large_t2 = SelectQuery {
+        from: "t2".into(),
+        filters: vec![RowFilter {
+            field: field("t2", "value"),
+            op: CompareOp::Gt,
+            rhs: RhsValue::Value(Value::I32(1500)),
+        }],
+        ..Default::default()
+    };
+
+    let result = db
+        .run_select_query(SelectQuery {
+            from: "t1".into(),
+            joins: vec![JoinContract {
+                join_type: JoinType::Inner,
+                null_keys_match: false,
+                lhs: field("t1", "id"),
+                rhs: field("large", "t1_id"),
+            }],
+            filters: vec![RowFilter {
+                field: field("large", "v2"),
+                op: CompareOp::Gt,
+                rhs: RhsValue::Value(Value::I32(101)),
+            }],
+            derived_tables: BTreeMap::from([("large".into(), large_t2.clone())]),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(
+        vec![
+            "t1.id",
+            "t1.value",
+            "large.t1_id",
+            "large.value",
+            "large.v2"
+        ],
+        result
+            .columns
+            .iter()
+            .map(|column| column.name.as_str())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        vec![IndexMap::from([
+            ("t1.id".into(), Value::I32(2)),
+            ("t1.value".into(), Value::I32(102)),
+            ("large.t1_id".into(), Value::I32(2)),
+            ("large.value".into(), Value::I32(3002)),
+            ("large.v2".into(), Value::I32(102)),
+        ])],
+        result.rows
+    );
+
+    // Derived tables nest, and are parsed from FROM (SELECT ...) AS name.
+    let tokens = Lexer::tokenize(b"SELECT FROM (SELECT FROM (SELECT FROM t2) AS a) AS b").unwrap();
+    let Query::Select(query) = db.parse_statement(&tokens).unwrap() else {
+        panic!("Expected a select");
+    };
+    let result = db.run_select_query(query).unwrap();
+    assert_eq!(4, result.len());
+    assert_eq!(Value::I32(4004), result.rows[3]["b.value"]);
+
+    // Fields of the joined tables with the same name would be the same field.
+    let err = db
+        .run_select_query(SelectQuery {
+            from: "both".into(),
+            derived_tables: BTreeMap::from([(
+                "both".into(),
+                SelectQuery {
+                    from: "t1".into(),
+                    joins: vec![JoinContract {
+                        join_type: JoinType::Inner,
+                        null_keys_match: false,
+                        lhs: field("t1", "id"),
+                        rhs: field("t2", "t1_id"),
+                    }],
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        })
+        .unwrap_err();
+    assert_eq!("Duplicate column: both.value", err.to_string());
+}
+
+#[test]
+fn test_explain_join_table_filtered() {
+    let db = setup_multi_tables();
+
+    let query = SelectQuery {
+        from: "t1".into(),
+        joins: vec![JoinContract {
+            join_type: pbase::query::JoinType::Inner,
+            null_keys_match: false,
+            lhs: FieldSelector {
+                name: "id".into(),
+                source: "t1".into(),
+            },
+            rhs: FieldSelector {
+                name: "t1_id".into(),
+                source: "t2".into(),
+            },
+        }],
+        filters: vec![RowFilter {
+            field: FieldSelector {
+                name: "value".to_string(),
+                source: "t2".to_string(),
+            },
+            op: CompareOp::Gt,
+            rhs: RhsValue::Value(Value::I32(1500)),
+        }],
+        ..Default::default()
+    };
+
+    let plan = db.explain_select_query(query.clone()).unwrap();
+
+    assert_eq!(
+        "HashJoin t1.id = t2.t1_id (rows: 4)\n\
+         ├── Scan t1 (rows: 4)\n\
+         └── Filter t2.value > 1500 (rows: 2)\n\
+         \x20   └── Scan t2 (rows: 4)\n",
+        plan.to_ascii_tree()
+    );
+    assert_eq!(None, plan.runtime);
+
+    let plan = db.explain_analyze_select_query(query).unwrap();
+
+    let actual_rows = |plan: &QueryPlan| plan.runtime.unwrap().rows;
+    assert_eq!(2, actual_rows(&plan));
+    assert_eq!(4, actual_rows(&plan.children[0]));
+    assert_eq!(3, actual_rows(&plan.children[1]));
+    assert_eq!(4, actual_rows(&plan.children[1].children[0]));
+}
+
+#[test]
+fn test_subquery_filter() {
+    let db = setup_multi_tables();
+    db.run_create_index_query("t1", "value_index", &["value".into()])
+        .unwrap();
+    let field = |source: &str, name: &str| FieldSelector {
+        name: name.into(),
+        source: source.into(),
+    };
+    // t1 rows with a value above the largest v2 of the t2 rows of t1 row `t1_id`.
+    let query = |t1_id: i32| SelectQuery {
+        from: "t1".into(),
+        filters: vec![RowFilter {
+            field: field("t1", "value"),
+            op: CompareOp::Gt,
+            rhs: RhsValue::Subquery {
+                aggregate: Aggregate::Max(field("t2", "v2")),
+                query: Box::new(SelectQuery {
+                    from: "t2".into(),
+                    filters: vec![RowFilter {
+                        field: field("t2", "t1_id"),
+                        op: CompareOp::Eq,
+                        rhs: RhsValue::Value(Value::I32(t1_id)),
+                    }],
+                    ..Default::default()
+                }),
+            },
+        }],
+        ..Default::default()
+    };
+    assert_eq!(
+        "t1.value > (SELECT MAX(t2.v2) FROM t2)",
+        query(2).filters[0].to_string()
+    );
+
+    let result = db.run_select_query(query(2)).unwrap();
+    assert_eq!(1, result.len());
+    assert_eq!(Value::I32(103), result.rows[0]["t1.value"]);
+
+    // The subquery is evaluated before planning: its value narrows the index.
+    let plan = db.explain_select_query(query(2)).unwrap();
+    assert!(
+        matches!(plan.node, PlanNode::IndexScan { ref index, .. } if index == "value_index"),
+        "{}",
+        plan.to_ascii_tree()
+    );
+
+    // The MAX of no rows is NULL, which no value is greater than.
+    assert_eq!(0, db.run_select_query(query(9)).unwrap().len());
+}
 
-    let db = PBase::new(std::env::current_dir().unwrap_or_else(|_| PathBuf::new()));
+#[test]
+fn test_union_of_compatible_selects() {
+    let db = setup_multi_tables();
+
+    let union_query = |all: bool| UnionQuery {
+        selects: vec![
+            SelectQuery {
+                from: "t1".into(),
+                joins: vec![],
+                filters: vec![RowFilter {
+                    field: FieldSelector {
+                        name: "id".to_string(),
+                        source: "t1".to_string(),
+                    },
+                    op: CompareOp::Lt,
+                    rhs: RhsValue::Value(Value::I32(2)),
+                }],
+                ..Default::default()
+            },
+            SelectQuery {
+                from: "t1".into(),
+                joins: vec![],
+                filters: vec![],
+                ..Default::default()
+            },
+        ],
+        op: SetOperation::Union,
+        all,
+    };
+
+    // ┌──┬─────┐
+    // │id│value│
+    // ├──┼─────┤
+    // │0 │100  │
+    // │1 │101  │
+    // │2 │102  │
+    // │3 │103  │
+    // └──┴─────┘
+
+    let result = db.run_union_query(union_query(true)).unwrap().rows;
+    assert_eq!(6, result.len());
+
+    let result = db.run_union_query(union_query(false)).unwrap().rows;
+    assert_eq!(4, result.len());
+    assert_eq!(
+        vec![0, 1, 2, 3],
+        result
+            .iter()
+            .map(|row| match row["t1.id"] {
+                Value::I32(id) => id,
+                _ => panic!("Unexpected id value"),
+            })
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_union_of_incompatible_selects() {
+    let db = setup_multi_tables();
+
+    // t1 has 2 columns, t2 has 3.
+    let result = db.run_union_query(UnionQuery {
+        selects: vec![
+            SelectQuery {
+                from: "t1".into(),
+                joins: vec![],
+                filters: vec![],
+                ..Default::default()
+            },
+            SelectQuery {
+                from: "t2".into(),
+                joins: vec![],
+                filters: vec![],
+                ..Default::default()
+            },
+        ],
+        op: SetOperation::Union,
+        all: true,
+    });
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_intersect_and_except() {
+    let db = setup_multi_tables();
+
+    let id_filter = |op, id| SelectQuery {
+        from: "t1".into(),
+        filters: vec![RowFilter {
+            field: FieldSelector {
+                name: "id".to_string(),
+                source: "t1".to_string(),
+            },
+            op,
+            rhs: RhsValue::Value(Value::I32(id)),
+        }],
+        ..Default::default()
+    };
+    let ids = |op, all, selects| -> Vec<Value> {
+        db.run_union_query(UnionQuery { selects, op, all })
+            .unwrap()
+            .rows
+            .into_iter()
+            .map(|row| row["t1.id"].clone())
+            .collect()
+    };
+
+    // Ids 0, 1, 2 and ids 1, 2, 3.
+    let selects = vec![id_filter(CompareOp::Lt, 3), id_filter(CompareOp::Gt, 0)];
+    assert_eq!(
+        vec![Value::I32(1), Value::I32(2)],
+        ids(SetOperation::Intersect, false, selects.clone())
+    );
+    assert_eq!(
+        vec![Value::I32(0)],
+        ids(SetOperation::Except, false, selects.clone())
+    );
+    assert_eq!(
+        Vec::<Value>::new(),
+        ids(
+            SetOperation::Except,
+            false,
+            vec![
+                selects[0].clone(),
+                selects[1].clone(),
+                id_filter(CompareOp::Eq, 0)
+            ]
+        )
+    );
+
+    // Two more rows of id 1, read last. With ALL each row of the other select matches one row at
+    // most, the first one left.
+    for _ in 0..2 {
+        db.run_insert_query(&InsertQuery {
+            table: "t1".into(),
+            values: HashMap::from([
+                ("id".into(), Value::I32(1)),
+                ("value".into(), Value::I32(101)),
+            ]),
+        })
+        .unwrap();
+    }
+    let one_of_id_1 = SelectQuery {
+        limit: Some(1),
+        ..id_filter(CompareOp::Eq, 1)
+    };
+    let selects = vec![id_filter(CompareOp::Lt, 4), one_of_id_1];
+    assert_eq!(
+        vec![
+            Value::I32(0),
+            Value::I32(2),
+            Value::I32(3),
+            Value::I32(1),
+            Value::I32(1)
+        ],
+        ids(SetOperation::Except, true, selects.clone())
+    );
+    assert_eq!(
+        vec![Value::I32(0), Value::I32(2), Value::I32(3)],
+        ids(SetOperation::Except, false, selects.clone())
+    );
+    assert_eq!(
+        vec![Value::I32(1)],
+        ids(SetOperation::Intersect, true, selects)
+    );
+}
+
+#[test]
+fn test_correlated_scalar_subqueries() {
+    let db = setup_multi_tables();
+
+    let t2_query = SelectQuery {
+        from: "t2".into(),
+        ..Default::default()
+    };
+    let correlation = Some(Correlation {
+        outer: FieldSelector {
+            name: "id".into(),
+            source: "t1".into(),
+        },
+        inner: FieldSelector {
+            name: "t1_id".into(),
+            source: "t2".into(),
+        },
+    });
+
+    // SELECT *,
+    //   (SELECT COUNT(*) FROM t2 WHERE t2.t1_id = t1.id) AS t2_count,
+    //   (SELECT MAX(t2.value) FROM t2 WHERE t2.t1_id = t1.id) AS t2_max,
+    //   (SELECT SUM(t2.value) FROM t2 WHERE t2.t1_id = t1.id) AS t2_sum,
+    //   (SELECT COUNT(*) FROM t2) AS t2_total,
+    //   (SELECT AVG(t2.value) FROM t2) AS t2_avg,
+    //   (SELECT APPROX_COUNT_DISTINCT(t2.t1_id) FROM t2) AS t2_t1_ids
+    // FROM t1
+    let query = SelectQuery {
+        from: "t1".into(),
+        scalar_subqueries: vec![
+            ScalarSubquery {
+                alias: "t2_count".into(),
+                query: t2_query.clone(),
+                aggregate: Aggregate::Count,
+                correlation: correlation.clone(),
+            },
+            ScalarSubquery {
+                alias: "t2_max".into(),
+                query: t2_query.clone(),
+                aggregate: Aggregate::Max(FieldSelector {
+                    name: "value".into(),
+                    source: "t2".into(),
+                }),
+                correlation: correlation.clone(),
+            },
+            ScalarSubquery {
+                alias: "t2_sum".into(),
+                query: t2_query.clone(),
+                aggregate: Aggregate::Sum(FieldSelector {
+                    name: "value".into(),
+                    source: "t2".into(),
+                }),
+                correlation,
+            },
+            ScalarSubquery {
+                alias: "t2_total".into(),
+                query: t2_query.clone(),
+                aggregate: Aggregate::Count,
+                correlation: None,
+            },
+            ScalarSubquery {
+                alias: "t2_avg".into(),
+                query: t2_query.clone(),
+                aggregate: Aggregate::Avg(FieldSelector {
+                    name: "value".into(),
+                    source: "t2".into(),
+                }),
+                correlation: None,
+            },
+            ScalarSubquery {
+                alias: "t2_t1_ids".into(),
+                query: t2_query,
+                aggregate: Aggregate::ApproxCountDistinct(FieldSelector {
+                    name: "t1_id".into(),
+                    source: "t2".into(),
+                }),
+                correlation: None,
+            },
+        ],
+        ..Default::default()
+    };
+
+    // ┌──┬─────┐   ┌─────┬─────┬───┐
+    // │id│value│   │t1_id│value│v2 │
+    // ├──┼─────┤   ├─────┼─────┼───┤
+    // │0 │100  │   │0    │1000 │555│
+    // │1 │101  │   │0    │2000 │101│
+    // │2 │102  │   │2    │3002 │102│
+    // │3 │103  │   │4    │4004 │99 │
+    // └──┴─────┘   └─────┴─────┴───┘
+
+    let result = db.run_select_query(query).unwrap().rows;
+    assert_eq!(4, result.len());
+
+    let column =
+        |alias: &str| -> Vec<Value> { result.iter().map(|row| row[alias].clone()).collect() };
+    assert_eq!(
+        vec![Value::I32(2), Value::I32(0), Value::I32(1), Value::I32(0)],
+        column("t2_count")
+    );
+    assert_eq!(
+        vec![Value::I32(2000), Value::NULL, Value::I32(3002), Value::NULL],
+        column("t2_max")
+    );
+    assert_eq!(
+        vec![Value::I32(3000), Value::NULL, Value::I32(3002), Value::NULL],
+        column("t2_sum")
+    );
+    assert_eq!(vec![Value::I32(4); 4], column("t2_total"));
+    assert_eq!(vec![Value::I32(2501); 4], column("t2_avg"));
+    assert_eq!(vec![Value::I32(3); 4], column("t2_t1_ids"));
+}
+
+#[test]
+fn test_min_max_scalar_subqueries_on_index() {
+    let db = setup_multi_tables();
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "prices".into(),
+            fields: IndexMap::from([
+                ("item".into(), FieldSchema::I32),
+                ("price".into(), FieldSchema::I32),
+            ]),
+            indices: IndexMap::from([("price_index".into(), vec!["price".into()])]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    let insert = |item: i32, price: i32| {
+        db.run_insert_query(&InsertQuery {
+            table: "prices".into(),
+            values: HashMap::from([
+                ("item".into(), Value::I32(item)),
+                ("price".into(), Value::I32(price)),
+            ]),
+        })
+        .unwrap();
+    };
+    for item in 0..10 {
+        insert(item, 100 + item * 37 % 50);
+    }
+    db.merge_index_deltas().unwrap();
+    // The new extremes are in the index delta.
+    insert(10, 7);
+    insert(11, 900);
+
+    let prices_subquery = |alias: &str, aggregate, filters| ScalarSubquery {
+        alias: alias.into(),
+        query: SelectQuery {
+            from: "prices".into(),
+            filters,
+            ..Default::default()
+        },
+        aggregate,
+        correlation: None,
+    };
+    let price = FieldSelector {
+        name: "price".into(),
+        source: "prices".into(),
+    };
+    let early_items = vec![RowFilter {
+        field: FieldSelector {
+            name: "item".into(),
+            source: "prices".into(),
+        },
+        op: CompareOp::Lt,
+        rhs: RhsValue::Value(Value::I32(5)),
+    }];
+    let result = db
+        .run_select_query(SelectQuery {
+            from: "t1".into(),
+            scalar_subqueries: vec![
+                prices_subquery("min", Aggregate::Min(price.clone()), vec![]),
+                prices_subquery("max", Aggregate::Max(price.clone()), vec![]),
+                prices_subquery(
+                    "early_min",
+                    Aggregate::Min(price.clone()),
+                    early_items.clone(),
+                ),
+                prices_subquery("early_max", Aggregate::Max(price), early_items),
+            ],
+            limit: Some(1),
+            ..Default::default()
+        })
+        .unwrap();
+
+    // Early prices: 100, 137, 124, 111, 148.
+    let row = &result.rows[0];
+    assert_eq!(Value::I32(7), row["min"]);
+    assert_eq!(Value::I32(900), row["max"]);
+    assert_eq!(Value::I32(100), row["early_min"]);
+    assert_eq!(Value::I32(148), row["early_max"]);
+}
+
+#[test]
+fn test_first_match_join() {
+    let db = setup_multi_tables();
+
+    let query = |first_match, filters| SelectQuery {
+        from: "t1".into(),
+        joins: vec![JoinContract {
+            join_type: pbase::query::JoinType::Inner,
+            null_keys_match: false,
+            lhs: FieldSelector {
+                name: "id".into(),
+                source: "t1".into(),
+            },
+            rhs: FieldSelector {
+                name: "t1_id".into(),
+                source: "t2".into(),
+            },
+        }],
+        filters,
+        first_match,
+        ..Default::default()
+    };
+    let values = |query| {
+        db.run_select_query(query)
+            .unwrap()
+            .rows
+            .iter()
+            .map(|row| (row["t1.id"].clone(), row["t2.value"].clone()))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(3, db.run_select_query(query(false, vec![])).unwrap().len());
+    assert_eq!(
+        vec![
+            (Value::I32(0), Value::I32(1000)),
+            (Value::I32(2), Value::I32(3002)),
+        ],
+        values(query(true, vec![]))
+    );
+
+    // Filters on the joined table select among the matches.
+    let value_filter = RowFilter {
+        field: FieldSelector {
+            name: "value".to_string(),
+            source: "t2".to_string(),
+        },
+        op: CompareOp::Gt,
+        rhs: RhsValue::Value(Value::I32(1500)),
+    };
+    assert_eq!(
+        vec![
+            (Value::I32(0), Value::I32(2000)),
+            (Value::I32(2), Value::I32(3002)),
+        ],
+        values(query(true, vec![value_filter]))
+    );
+
+    assert!(db
+        .explain_select_query(query(true, vec![]))
+        .unwrap()
+        .to_ascii_tree()
+        .starts_with("HashJoin t1.id = t2.t1_id (first match) (rows: 4)"));
+}
+
+#[test]
+fn test_left_join() {
+    let db = setup_multi_tables();
+
+    let query = |filters| SelectQuery {
+        from: "t1".into(),
+        joins: vec![JoinContract {
+            join_type: JoinType::Left,
+            null_keys_match: false,
+            lhs: FieldSelector {
+                name: "id".into(),
+                source: "t1".into(),
+            },
+            rhs: FieldSelector {
+                name: "t1_id".into(),
+                source: "t2".into(),
+            },
+        }],
+        filters,
+        ..Default::default()
+    };
+    let values = |query| {
+        db.run_select_query(query)
+            .unwrap()
+            .rows
+            .iter()
+            .map(|row| (row["t1.id"].clone(), row["t2.value"].clone()))
+            .collect::<Vec<_>>()
+    };
+
+    // t1 rows without a t2 row keep NULL t2 columns.
+    assert_eq!(
+        vec![
+            (Value::I32(0), Value::I32(1000)),
+            (Value::I32(0), Value::I32(2000)),
+            (Value::I32(1), Value::NULL),
+            (Value::I32(2), Value::I32(3002)),
+            (Value::I32(3), Value::NULL),
+        ],
+        values(query(vec![]))
+    );
+
+    // Filters on the joined table apply to the joined rows, NULL columns included.
+    let value_filter = RowFilter {
+        field: FieldSelector {
+            name: "value".to_string(),
+            source: "t2".to_string(),
+        },
+        op: CompareOp::Gt,
+        rhs: RhsValue::Value(Value::I32(1500)),
+    };
+    assert_eq!(
+        vec![
+            (Value::I32(0), Value::I32(2000)),
+            (Value::I32(2), Value::I32(3002)),
+        ],
+        values(query(vec![value_filter.clone()]))
+    );
+
+    // NULL compares to nothing: not even `!=` or `<` pass the NULL columns.
+    let with_op = |op, value| RowFilter {
+        op,
+        rhs: RhsValue::Value(value),
+        ..value_filter.clone()
+    };
+    assert_eq!(
+        vec![
+            (Value::I32(0), Value::I32(1000)),
+            (Value::I32(0), Value::I32(2000)),
+        ],
+        values(query(vec![with_op(CompareOp::Ne, Value::I32(3002))]))
+    );
+    assert_eq!(
+        vec![(Value::I32(0), Value::I32(1000))],
+        values(query(vec![with_op(CompareOp::Lt, Value::I32(1500))]))
+    );
+    for op in [CompareOp::Ne, CompareOp::Lt, CompareOp::Ge] {
+        assert!(values(query(vec![with_op(op, Value::NULL)])).is_empty());
+    }
+
+    // Deleted t2 rows no longer match.
+    db.run_delete_query(&DeleteQuery {
+        table: "t2".into(),
+        filters: vec![RowFilter {
+            field: FieldSelector {
+                name: "t1_id".to_string(),
+                source: "t2".to_string(),
+            },
+            op: CompareOp::Eq,
+            rhs: RhsValue::Value(Value::I32(2)),
+        }],
+    })
+    .unwrap();
+    assert_eq!((Value::I32(2), Value::NULL), values(query(vec![]))[3]);
+
+    assert!(db
+        .explain_select_query(query(vec![]))
+        .unwrap()
+        .to_ascii_tree()
+        .starts_with("HashJoin t1.id = t2.t1_id (left outer)"));
+}
+
+#[test]
+fn test_right_and_full_joins() {
+    let db = setup_multi_tables();
+
+    let query = |join_type, filters| SelectQuery {
+        from: "t1".into(),
+        joins: vec![JoinContract {
+            join_type,
+            null_keys_match: false,
+            lhs: FieldSelector {
+                name: "id".into(),
+                source: "t1".into(),
+            },
+            rhs: FieldSelector {
+                name: "t1_id".into(),
+                source: "t2".into(),
+            },
+        }],
+        filters,
+        ..Default::default()
+    };
+    let values = |query| {
+        db.run_select_query(query)
+            .unwrap()
+            .rows
+            .iter()
+            .map(|row| (row["t1.id"].clone(), row["t2.value"].clone()))
+            .collect::<Vec<_>>()
+    };
+
+    // t2 rows without a t1 row follow the joined rows, with NULL t1 columns.
+    assert_eq!(
+        vec![
+            (Value::I32(0), Value::I32(1000)),
+            (Value::I32(0), Value::I32(2000)),
+            (Value::I32(2), Value::I32(3002)),
+            (Value::NULL, Value::I32(4004)),
+        ],
+        values(query(JoinType::Right, vec![]))
+    );
+    assert_eq!(
+        vec![
+            (Value::I32(0), Value::I32(1000)),
+            (Value::I32(0), Value::I32(2000)),
+            (Value::I32(1), Value::NULL),
+            (Value::I32(2), Value::I32(3002)),
+            (Value::I32(3), Value::NULL),
+            (Value::NULL, Value::I32(4004)),
+        ],
+        values(query(JoinType::Full, vec![]))
+    );
+
+    // Filters on the left table apply to the joined rows, NULL columns included.
+    let value_filter = RowFilter {
+        field: FieldSelector {
+            name: "value".to_string(),
+            source: "t1".to_string(),
+        },
+        op: CompareOp::Gt,
+        rhs: RhsValue::Value(Value::I32(100)),
+    };
+    assert_eq!(
+        vec![(Value::I32(2), Value::I32(3002))],
+        values(query(JoinType::Right, vec![value_filter]))
+    );
+
+    assert!(db
+        .explain_select_query(query(JoinType::Full, vec![]))
+        .unwrap()
+        .to_ascii_tree()
+        .starts_with("HashJoin t1.id = t2.t1_id (full outer)"));
+}
+
+#[test]
+fn test_external_tables() {
+    let db = setup_multi_tables();
+
+    let dir = std::env::temp_dir().join("pbase_external_test");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let csv_path = dir.join("scores.csv");
+    fs::write(&csv_path, "t1_id,score\n0,10\n1,\n3,30\n3,31\n").unwrap();
+    let schema = db.register_external_table("ext_csv", &csv_path).unwrap();
+    assert_eq!(
+        IndexMap::from([
+            ("t1_id".to_string(), FieldSchema::I32),
+            ("score".to_string(), FieldSchema::I32),
+        ]),
+        schema.fields
+    );
+
+    let joined_query = |from: &str, filters| SelectQuery {
+        from: from.into(),
+        joins: vec![JoinContract {
+            join_type: pbase::query::JoinType::Inner,
+            null_keys_match: false,
+            lhs: FieldSelector {
+                name: "t1_id".into(),
+                source: from.into(),
+            },
+            rhs: FieldSelector {
+                name: "id".into(),
+                source: "t1".into(),
+            },
+        }],
+        filters,
+        ..Default::default()
+    };
+    let score_filter = |op, value| RowFilter {
+        field: FieldSelector {
+            name: "score".into(),
+            source: "ext_csv".into(),
+        },
+        op,
+        rhs: RhsValue::Value(value),
+    };
+
+    let result = db
+        .run_select_query(joined_query("ext_csv", vec![]))
+        .unwrap();
+    assert_eq!(4, result.len());
+    assert_eq!(Value::I32(0), result.rows[1]["ext_csv.score"]);
+    assert_eq!(Value::I32(101), result.rows[1]["t1.value"]);
+
+    let result = db
+        .run_select_query(joined_query(
+            "ext_csv",
+            vec![score_filter(CompareOp::Gt, Value::I32(15))],
+        ))
+        .unwrap();
+    assert_eq!(
+        vec![Value::I32(30), Value::I32(31)],
+        result
+            .rows
+            .iter()
+            .map(|row| row["ext_csv.score"].clone())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        1,
+        db.run_select_query(joined_query(
+            "ext_csv",
+            vec![score_filter(CompareOp::Eq, Value::I32(0))],
+        ))
+        .unwrap()
+        .len()
+    );
+
+    // The file is read on each select.
+    fs::write(&csv_path, "t1_id,score\n2,20\n").unwrap();
+    assert_eq!(
+        1,
+        db.run_select_query(joined_query("ext_csv", vec![]))
+            .unwrap()
+            .len()
+    );
+    fs::write(&csv_path, "t1_id,score\n2,high\n").unwrap();
+    assert!(db
+        .run_select_query(joined_query("ext_csv", vec![]))
+        .is_err());
+
+    // Read-only, and the name is taken.
+    assert!(db.register_external_table("ext_csv", &csv_path).is_err());
+    assert!(db.register_external_table("t1", &csv_path).is_err());
+    assert!(db
+        .run_create_table_query(&CreateTableQuery {
+            schema: TableSchema {
+                name: "ext_csv".into(),
+                ..Default::default()
+            },
+        })
+        .is_err());
+    assert!(db
+        .run_insert_query(&InsertQuery {
+            table: "ext_csv".into(),
+            values: HashMap::from([("t1_id".into(), Value::I32(1))]),
+        })
+        .is_err());
+
+    db.unregister_external_table("ext_csv").unwrap();
+    assert!(db
+        .run_select_query(joined_query("ext_csv", vec![]))
+        .is_err());
+
+    let parquet_path = dir.join("flags.parquet");
+    write_parquet_flags(&parquet_path);
+    let schema = db
+        .register_external_table("ext_parquet", &parquet_path)
+        .unwrap();
+    assert_eq!(
+        IndexMap::from([
+            ("t1_id".to_string(), FieldSchema::I32),
+            ("flag".to_string(), FieldSchema::U8),
+        ]),
+        schema.fields
+    );
+    let result = db
+        .run_select_query(joined_query("ext_parquet", vec![]))
+        .unwrap();
+    assert_eq!(
+        vec![
+            (Value::I32(100), Value::U8(7)),
+            (Value::I32(102), Value::U8(0))
+        ],
+        result
+            .rows
+            .iter()
+            .map(|row| (row["t1.value"].clone(), row["ext_parquet.flag"].clone()))
+            .collect::<Vec<_>>()
+    );
+
+    assert!(db
+        .register_external_table("ext_other", dir.join("flags.json"))
+        .is_err());
+
+    db.unregister_external_table("ext_parquet").unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_read_snapshot() {
+    let db = setup_multi_tables();
+    db.run_create_index_query("t2", "t1_id_index", &["t1_id".into()])
+        .unwrap();
+    let join_query = || SelectQuery {
+        from: "t1".into(),
+        joins: vec![JoinContract {
+            join_type: pbase::query::JoinType::Inner,
+            null_keys_match: false,
+            lhs: FieldSelector {
+                name: "id".into(),
+                source: "t1".into(),
+            },
+            rhs: FieldSelector {
+                name: "t1_id".into(),
+                source: "t2".into(),
+            },
+        }],
+        filters: vec![RowFilter {
+            field: FieldSelector {
+                name: "t1_id".into(),
+                source: "t2".into(),
+            },
+            op: CompareOp::Eq,
+            rhs: RhsValue::Value(Value::I32(1)),
+        }],
+        ..Default::default()
+    };
+
+    let snapshot = db.read_snapshot(&["t1", "t2"]).unwrap();
+    db.run_insert_query(&InsertQuery {
+        table: "t2".into(),
+        values: HashMap::from([
+            ("t1_id".into(), Value::I32(1)),
+            ("value".into(), Value::I32(5001)),
+            ("v2".into(), Value::I32(0)),
+        ]),
+    })
+    .unwrap();
+    db.update_row_at("t2", 0, &HashMap::from([("value".into(), Value::I32(1))]))
+        .unwrap();
+    db.run_delete_query(&DeleteQuery {
+        table: "t2".into(),
+        filters: vec![RowFilter {
+            field: FieldSelector {
+                name: "t1_id".into(),
+                source: "t2".into(),
+            },
+            op: CompareOp::Eq,
+            rhs: RhsValue::Value(Value::I32(4)),
+        }],
+    })
+    .unwrap();
+
+    // The snapshot misses the update and the delete: it reads the table as it was.
+    let t2_values = |result: pbase::result_set::ResultSet| {
+        result
+            .rows
+            .iter()
+            .map(|row| row["t2.value"].clone())
+            .collect::<Vec<_>>()
+    };
+    let t2_query = || SelectQuery {
+        from: "t2".into(),
+        ..Default::default()
+    };
+    assert_eq!(
+        vec![
+            Value::I32(1000),
+            Value::I32(2000),
+            Value::I32(3002),
+            Value::I32(4004)
+        ],
+        t2_values(db.run_select_query_in(&snapshot, t2_query()).unwrap())
+    );
+    assert_eq!(
+        vec![
+            Value::I32(1),
+            Value::I32(2000),
+            Value::I32(3002),
+            Value::I32(5001)
+        ],
+        t2_values(db.run_select_query(t2_query()).unwrap())
+    );
+
+    // The snapshot misses the new row, in the table and in the index.
+    assert_eq!(
+        0,
+        db.run_select_query_in(&snapshot, join_query())
+            .unwrap()
+            .len()
+    );
+    let result = db.run_select_query(join_query()).unwrap();
+    assert_eq!(1, result.len());
+    assert_eq!(Value::I32(5001), result.rows[0]["t2.value"]);
+    assert_eq!(
+        1,
+        db.run_select_query_in(&db.read_snapshot(&["t1", "t2"]).unwrap(), join_query())
+            .unwrap()
+            .len()
+    );
+}
+
+#[test]
+fn test_snapshot_export() {
+    let db = setup_multi_tables();
+    db.run_create_index_query("t2", "t1_id_index", &["t1_id".into()])
+        .unwrap();
+    let t2_query = |t1_id: i32| SelectQuery {
+        from: "t2".into(),
+        filters: vec![RowFilter {
+            field: FieldSelector {
+                name: "t1_id".into(),
+                source: "t2".into(),
+            },
+            op: CompareOp::Eq,
+            rhs: RhsValue::Value(Value::I32(t1_id)),
+        }],
+        ..Default::default()
+    };
+
+    db.analyze_table("t2").unwrap();
+
+    let dest_dir = TempDir::new().unwrap();
+    let dest = dest_dir.path().join("snapshot");
+    db.snapshot(&dest).unwrap();
+    db.run_insert_query(&InsertQuery {
+        table: "t2".into(),
+        values: HashMap::from([
+            ("t1_id".into(), Value::I32(0)),
+            ("value".into(), Value::I32(5000)),
+            ("v2".into(), Value::I32(0)),
+        ]),
+    })
+    .unwrap();
+    assert_eq!(3, db.run_select_query(t2_query(0)).unwrap().len());
+
+    // The snapshot misses the new row, in the table and in the index.
+    let snapshot = PBase::new(dest.clone()).with_read_only();
+    assert_eq!(2, snapshot.run_select_query(t2_query(0)).unwrap().len());
+    assert_eq!(1, snapshot.run_select_query(t2_query(2)).unwrap().len());
+    assert_eq!(
+        4,
+        snapshot
+            .run_select_query(SelectQuery {
+                from: "t1".into(),
+                ..Default::default()
+            })
+            .unwrap()
+            .len()
+    );
+
+    // Stats are linked, ANALYZE replaces the live ones only.
+    assert_eq!(5, db.analyze_table("t2").unwrap().row_count);
+    let snapshot_stats = TableOpener::new(dest.clone())
+        .open_stats("t2")
+        .unwrap()
+        .unwrap();
+    assert_eq!(4, snapshot_stats.row_count);
+
+    // Snapshots are exported to new directories.
+    assert!(db.snapshot(&dest).is_err());
+}
+
+// Parquet file of (t1_id INT32, flag UINT_8): (0, 7), (2, null).
+fn write_parquet_flags(path: &std::path::Path) {
+    use parquet::{
+        data_type::Int32Type,
+        file::{properties::WriterProperties, writer::SerializedFileWriter},
+        schema::parser::parse_message_type,
+    };
+    use std::sync::Arc;
+
+    let schema =
+        parse_message_type("message flags { required int32 t1_id; optional int32 flag (UINT_8); }")
+            .unwrap();
+    let mut writer = SerializedFileWriter::new(
+        fs::File::create(path).unwrap(),
+        Arc::new(schema),
+        Arc::new(WriterProperties::builder().build()),
+    )
+    .unwrap();
+
+    let mut row_group = writer.next_row_group().unwrap();
+    let mut column = row_group.next_column().unwrap().unwrap();
+    column
+        .typed::<Int32Type>()
+        .write_batch(&[0, 2], None, None)
+        .unwrap();
+    column.close().unwrap();
+    let mut column = row_group.next_column().unwrap().unwrap();
+    column
+        .typed::<Int32Type>()
+        .write_batch(&[7], Some(&[1, 0]), None)
+        .unwrap();
+    column.close().unwrap();
+    row_group.close().unwrap();
+    writer.close().unwrap();
+}
+
+#[test]
+fn test_column_name_conflicts() {
+    let db = setup_multi_tables();
+    let join = |lhs: &str, rhs: &str| JoinContract {
+        join_type: pbase::query::JoinType::Inner,
+        null_keys_match: false,
+        lhs: FieldSelector {
+            name: "id".into(),
+            source: lhs.into(),
+        },
+        rhs: FieldSelector {
+            name: if rhs == "t1" { "id" } else { "t1_id" }.into(),
+            source: rhs.into(),
+        },
+    };
+    let count = |alias: &str| ScalarSubquery {
+        alias: alias.into(),
+        query: SelectQuery {
+            from: "t2".into(),
+            ..Default::default()
+        },
+        aggregate: Aggregate::Count,
+        correlation: Some(Correlation {
+            outer: FieldSelector {
+                name: "id".into(),
+                source: "t1".into(),
+            },
+            inner: FieldSelector {
+                name: "t1_id".into(),
+                source: "t2".into(),
+            },
+        }),
+    };
+
+    // Both tables have a `value` field: the flat keys keep them apart by table, and so do the
+    // nested rows.
+    let result = db
+        .run_select_query(SelectQuery {
+            from: "t1".into(),
+            joins: vec![join("t1", "t2")],
+            scalar_subqueries: vec![count("t2_count")],
+            limit: Some(1),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(
+        vec![IndexMap::from([
+            (
+                "t1".to_string(),
+                IndexMap::from([
+                    ("id".to_string(), Value::I32(0)),
+                    ("value".to_string(), Value::I32(100)),
+                ])
+            ),
+            (
+                "t2".to_string(),
+                IndexMap::from([
+                    ("t1_id".to_string(), Value::I32(0)),
+                    ("value".to_string(), Value::I32(1000)),
+                    ("v2".to_string(), Value::I32(555)),
+                ])
+            ),
+            (
+                COMPUTED_COLUMNS.to_string(),
+                IndexMap::from([("t2_count".to_string(), Value::I32(2))])
+            ),
+        ])],
+        result.nested_rows()
+    );
+
+    // A table read twice would give two columns of every name.
+    let err = db
+        .run_select_query(SelectQuery {
+            from: "t1".into(),
+            joins: vec![join("t1", "t1")],
+            ..Default::default()
+        })
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<PBaseError>(),
+            Some(PBaseError::AmbiguousTable(table)) if table == "t1"
+        ),
+        "{err}"
+    );
+
+    // Aliases are unique, and cannot look like table columns.
+    for (aliases, expected) in [
+        (["n", "n"], "Duplicate column: n"),
+        (
+            ["n", "t2.value"],
+            "Alias t2.value cannot contain '.', which is reserved for table.field columns",
+        ),
+    ] {
+        let err = db
+            .run_select_query(SelectQuery {
+                from: "t1".into(),
+                joins: vec![join("t1", "t2")],
+                scalar_subqueries: aliases.into_iter().map(count).collect(),
+                ..Default::default()
+            })
+            .unwrap_err();
+        assert_eq!(expected, err.to_string());
+    }
+}
+
+#[test]
+fn test_errors_name_the_failing_query_fragment() {
+    let db = setup_multi_tables();
+    let filter = |field: &str, value: Value| RowFilter {
+        field: FieldSelector {
+            name: field.into(),
+            source: "t1".into(),
+        },
+        op: CompareOp::Eq,
+        rhs: RhsValue::Value(value),
+    };
+    let run = |query: SelectQuery| db.run_select_query(query).unwrap_err();
+
+    let err = run(SelectQuery {
+        from: "t1".into(),
+        filters: vec![filter("missing", Value::I32(1))],
+        ..Default::default()
+    });
+    assert_eq!(
+        "filter t1.missing = 1 of select from t1: Unknown field: t1.missing",
+        err.to_string()
+    );
+    // The original error is kept as the source.
+    let source = err.source().unwrap().downcast_ref::<PBaseError>();
+    assert!(
+        matches!(source, Some(PBaseError::UnknownField(field)) if field == "t1.missing"),
+        "{err}"
+    );
+
+    // Values of another type fail before the scan compares them.
+    let err = run(SelectQuery {
+        from: "t1".into(),
+        filters: vec![filter("value", Value::U8(1))],
+        ..Default::default()
+    });
+    assert_eq!(
+        "filter t1.value = 1 of select from t1: Value 1 does not fit field t1.value",
+        err.to_string()
+    );
+
+    let err = run(SelectQuery {
+        from: "t1".into(),
+        joins: vec![JoinContract {
+            join_type: JoinType::Inner,
+            null_keys_match: false,
+            lhs: FieldSelector {
+                name: "id".into(),
+                source: "t1".into(),
+            },
+            rhs: FieldSelector {
+                name: "t2_id".into(),
+                source: "t2".into(),
+            },
+        }],
+        ..Default::default()
+    });
+    assert_eq!(
+        "join t1.id = t2.t2_id of select from t1: Unknown field: t2.t2_id",
+        err.to_string()
+    );
+
+    // Correlation fields are read from the rows of both sides.
+    let t2_count = |outer: &str, inner: &str| ScalarSubquery {
+        alias: "t2_count".into(),
+        query: SelectQuery {
+            from: "t2".into(),
+            ..Default::default()
+        },
+        aggregate: Aggregate::Count,
+        correlation: Some(Correlation {
+            outer: FieldSelector {
+                name: outer.into(),
+                source: "t1".into(),
+            },
+            inner: FieldSelector {
+                name: inner.into(),
+                source: "t2".into(),
+            },
+        }),
+    };
+    for (scalar_subquery, unknown_field) in [
+        (t2_count("t1_id", "t1_id"), "t1.t1_id"),
+        (t2_count("id", "id"), "t2.id"),
+        (
+            ScalarSubquery {
+                aggregate: Aggregate::Max(FieldSelector {
+                    name: "v3".into(),
+                    source: "t2".into(),
+                }),
+                ..t2_count("id", "t1_id")
+            },
+            "t2.v3",
+        ),
+    ] {
+        let err = run(SelectQuery {
+            from: "t1".into(),
+            scalar_subqueries: vec![scalar_subquery],
+            ..Default::default()
+        });
+        assert_eq!(
+            format!("subquery t2_count of select from t1: Unknown field: {unknown_field}"),
+            err.to_string()
+        );
+    }
+}
+
+#[test]
+fn test_attached_database() {
+    let db = setup_multi_tables();
+    let other = setup_multi_tables();
+    db.attach(other.dir(), "other").unwrap();
+
+    // Tables of both directories join like local ones.
+    let result = db
+        .run_select_query(SelectQuery {
+            from: "t1".into(),
+            joins: vec![JoinContract {
+                join_type: pbase::query::JoinType::Inner,
+                null_keys_match: false,
+                lhs: FieldSelector {
+                    name: "id".into(),
+                    source: "t1".into(),
+                },
+                rhs: FieldSelector {
+                    name: "t1_id".into(),
+                    source: "other.t2".into(),
+                },
+            }],
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(3, result.len());
+    assert_eq!(
+        IndexMap::from([
+            ("t1.id".into(), Value::I32(2)),
+            ("t1.value".into(), Value::I32(102)),
+            ("other.t2.t1_id".into(), Value::I32(2)),
+            ("other.t2.value".into(), Value::I32(3002)),
+            ("other.t2.v2".into(), Value::I32(102)),
+        ]),
+        result.rows[2],
+    );
+
+    let Query::Select(select_query) = db
+        .parse_statement(&Lexer::tokenize(b"SELECT FROM other.t1").unwrap())
+        .unwrap()
+    else {
+        panic!("not a select");
+    };
+    assert_eq!(4, db.run_select_query(select_query).unwrap().len());
+
+    // Attached tables are only read.
+    let err = db
+        .run_insert_query(&InsertQuery {
+            table: "other.t1".into(),
+            values: HashMap::from([("id".into(), Value::I32(4))]),
+        })
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<PBaseError>(),
+            Some(PBaseError::AttachedTableWrite(table)) if table == "other.t1"
+        ),
+        "{err}"
+    );
+    assert_eq!(
+        4,
+        other
+            .run_select_query(SelectQuery {
+                from: "t1".into(),
+                ..Default::default()
+            })
+            .unwrap()
+            .len()
+    );
+
+    assert!(matches!(
+        db.attach(other.dir(), "other")
+            .unwrap_err()
+            .downcast_ref::<PBaseError>(),
+        Some(PBaseError::DatabaseAlreadyAttached(_))
+    ));
+    assert!(db.attach(other.dir().join("missing"), "missing").is_err());
+    assert!(db.attach(other.dir(), "a.b").is_err());
+}
+
+fn setup_multi_tables() -> PBase {
+    let db = PBase::new_temp().unwrap();
 
     // ┌─────┐   ┌─────┐
     // │t1   │   │t2   │
@@ -246,12 +1863,13 @@ fn setup_multi_tables(prefix: &str) -> PBase {
     // Create tables.
     let create_table_query = CreateTableQuery {
         schema: TableSchema {
-            name: t1_name.clone(),
+            name: "t1".into(),
             fields: IndexMap::from([
                 ("id".into(), FieldSchema::I32),
                 ("value".into(), FieldSchema::I32),
             ]),
-            indices: HashMap::new(),
+            indices: IndexMap::new(),
+            ..Default::default()
         },
     };
     let create_result = db.run_create_table_query(&create_table_query);
@@ -259,13 +1877,14 @@ fn setup_multi_tables(prefix: &str) -> PBase {
 
     let create_table_query = CreateTableQuery {
         schema: TableSchema {
-            name: t2_name.clone(),
+            name: "t2".into(),
             fields: IndexMap::from([
                 ("t1_id".into(), FieldSchema::I32),
                 ("value".into(), FieldSchema::I32),
                 ("v2".into(), FieldSchema::I32),
             ]),
-            indices: HashMap::new(),
+            indices: IndexMap::new(),
+            ..Default::default()
         },
     };
     let create_result = db.run_create_table_query(&create_table_query);
@@ -282,7 +1901,7 @@ fn setup_multi_tables(prefix: &str) -> PBase {
 
     // Insert.
     let insert_query = InsertQuery {
-        table: t1_name.clone(),
+        table: "t1".into(),
         values: HashMap::from([
             ("id".into(), Value::I32(0)),
             ("value".into(), Value::I32(100)),
@@ -292,7 +1911,7 @@ fn setup_multi_tables(prefix: &str) -> PBase {
     assert!(insert_result.is_ok());
 
     let insert_query = InsertQuery {
-        table: t1_name.clone(),
+        table: "t1".into(),
         values: HashMap::from([
             ("id".into(), Value::I32(1)),
             ("value".into(), Value::I32(101)),
@@ -302,7 +1921,7 @@ fn setup_multi_tables(prefix: &str) -> PBase {
     assert!(insert_result.is_ok());
 
     let insert_query = InsertQuery {
-        table: t1_name.clone(),
+        table: "t1".into(),
         values: HashMap::from([
             ("id".into(), Value::I32(2)),
             ("value".into(), Value::I32(102)),
@@ -312,7 +1931,7 @@ fn setup_multi_tables(prefix: &str) -> PBase {
     assert!(insert_result.is_ok());
 
     let insert_query = InsertQuery {
-        table: t1_name.clone(),
+        table: "t1".into(),
         values: HashMap::from([
             ("id".into(), Value::I32(3)),
             ("value".into(), Value::I32(103)),
@@ -322,7 +1941,7 @@ fn setup_multi_tables(prefix: &str) -> PBase {
     assert!(insert_result.is_ok());
 
     let insert_query = InsertQuery {
-        table: t2_name.clone(),
+        table: "t2".into(),
         values: HashMap::from([
             ("t1_id".into(), Value::I32(0)),
             ("value".into(), Value::I32(1000)),
@@ -333,7 +1952,7 @@ fn setup_multi_tables(prefix: &str) -> PBase {
     assert!(insert_result.is_ok());
 
     let insert_query = InsertQuery {
-        table: t2_name.clone(),
+        table: "t2".into(),
         values: HashMap::from([
             ("t1_id".into(), Value::I32(0)),
             ("value".into(), Value::I32(2000)),
@@ -344,7 +1963,7 @@ fn setup_multi_tables(prefix: &str) -> PBase {
     assert!(insert_result.is_ok());
 
     let insert_query = InsertQuery {
-        table: t2_name.clone(),
+        table: "t2".into(),
         values: HashMap::from([
             ("t1_id".into(), Value::I32(2)),
             ("value".into(), Value::I32(3002)),
@@ -355,7 +1974,7 @@ fn setup_multi_tables(prefix: &str) -> PBase {
     assert!(insert_result.is_ok());
 
     let insert_query = InsertQuery {
-        table: t2_name.clone(),
+        table: "t2".into(),
         values: HashMap::from([
             ("t1_id".into(), Value::I32(4)),
             ("value".into(), Value::I32(4004)),
@@ -367,9 +1986,3 @@ fn setup_multi_tables(prefix: &str) -> PBase {
 
     db
 }
-
-fn delete_all_by_glob(pattern: &str) {
-    for entry in glob::glob(pattern).expect("Failed to read 'testtable*' files") {
-        fs::remove_file(entry.expect("Failed loading path")).expect("Failed deleting file");
-    }
-}
@@ -0,0 +1,315 @@
+use std::{
+    collections::HashMap,
+    sync::{mpsc, Barrier},
+    time::Duration,
+};
+
+use indexmap::IndexMap;
+use pbase::{
+    common::PBaseError,
+    pbase::PBase,
+    pool::PBasePool,
+    query::{CreateTableQuery, InsertQuery, SelectQuery},
+    schema::{FieldSchema, TableSchema},
+    value::Value,
+};
+
+#[test]
+fn test_pool_connections() {
+    let dir = std::env::temp_dir().join("pbase_pool_connections_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let pool = PBasePool::new(PBase::new(dir.clone()))
+        .with_max_connections(2)
+        .with_acquire_timeout(Duration::from_millis(50));
+    assert_eq!(2, pool.max_connections());
+
+    // Readers share the database.
+    let read_1 = pool.read().unwrap();
+    let read_2 = pool.read().unwrap();
+    assert_eq!(2, pool.in_use());
+    let err = pool.read().map(|_| ()).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<PBaseError>(),
+        Some(PBaseError::PoolExhausted(_))
+    ));
+
+    drop(read_1);
+    drop(read_2);
+    assert_eq!(0, pool.in_use());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_pool_isolation() {
+    let dir = std::env::temp_dir().join("pbase_pool_isolation_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let pool = PBasePool::new(PBase::new(dir.clone()));
+    pool.write()
+        .unwrap()
+        .run_create_table_query(&CreateTableQuery {
+            schema: TableSchema {
+                name: "pooled".into(),
+                fields: IndexMap::from([("field1".into(), FieldSchema::I32)]),
+                ..Default::default()
+            },
+        })
+        .unwrap();
+    let row_count = |pool: &PBasePool| {
+        pool.read()
+            .unwrap()
+            .run_select_query(SelectQuery {
+                from: "pooled".into(),
+                ..Default::default()
+            })
+            .unwrap()
+            .len()
+    };
+
+    // A write waits for the open read to end.
+    let read = pool.read().unwrap();
+    let (written_tx, written_rx) = mpsc::channel();
+    let writer_pool = pool.clone();
+    let writer = std::thread::spawn(move || {
+        writer_pool
+            .write()
+            .unwrap()
+            .run_insert_query(&InsertQuery {
+                table: "pooled".into(),
+                values: HashMap::from([("field1".into(), Value::I32(1))]),
+            })
+            .unwrap();
+        written_tx.send(()).unwrap();
+    });
+    assert!(written_rx.recv_timeout(Duration::from_millis(100)).is_err());
+    assert_eq!(
+        0,
+        read.run_select_query(SelectQuery {
+            from: "pooled".into(),
+            ..Default::default()
+        })
+        .unwrap()
+        .len()
+    );
+
+    drop(read);
+    written_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    writer.join().unwrap();
+    assert_eq!(1, row_count(&pool));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_pool_transaction_locks() {
+    let pool = PBasePool::new(PBase::new_temp().unwrap());
+    let both_locked = Barrier::new(2);
+
+    // Each transaction holds one table and wants the other's: one of them is the victim, the
+    // other gets the lock once the victim is dropped.
+    let deadlocked: Vec<bool> = std::thread::scope(|scope| {
+        let handles: Vec<_> = [("t1", "t2"), ("t2", "t1")]
+            .into_iter()
+            .map(|(first, second)| {
+                let (pool, both_locked) = (&pool, &both_locked);
+                scope.spawn(move || {
+                    let transaction = pool.transaction().unwrap();
+                    transaction.lock_exclusive(first).unwrap();
+                    both_locked.wait();
+                    match transaction.lock_exclusive(second) {
+                        Ok(()) => false,
+                        Err(err) => matches!(
+                            err.downcast_ref::<PBaseError>(),
+                            Some(PBaseError::Deadlock { .. })
+                        ),
+                    }
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    });
+    assert_eq!(
+        1,
+        deadlocked.iter().filter(|deadlocked| **deadlocked).count()
+    );
+    assert_eq!(0, pool.locks().waiting("t1"));
+
+    let pool = pool.with_lock_wait_timeout(Duration::from_millis(10));
+    let holder = pool.transaction().unwrap();
+    holder.lock_shared("t1").unwrap();
+    let waiter = pool.transaction().unwrap();
+    waiter.lock_shared("t1").unwrap();
+    let err = waiter.lock_exclusive("t1").unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<PBaseError>(),
+        Some(PBaseError::LockTimeout { .. })
+    ));
+    drop(holder);
+    waiter.lock_exclusive("t1").unwrap();
+}
+
+#[test]
+fn test_pool_with_transaction() {
+    let pool = PBasePool::new(PBase::new_temp().unwrap())
+        .with_lock_wait_timeout(Duration::from_millis(10))
+        .with_transaction_attempts(3);
+
+    // The first attempt times out on the held lock, the second one gets it.
+    let mut holder = Some(pool.transaction().unwrap());
+    holder.as_ref().unwrap().lock_exclusive("t1").unwrap();
+    let mut attempts = 0;
+    let result = pool.with_transaction(|transaction| {
+        attempts += 1;
+        if attempts == 2 {
+            holder = None;
+        }
+        transaction.lock_exclusive("t1")?;
+        Ok(transaction.id())
+    });
+    assert_eq!(2, attempts);
+    assert!(result.is_ok());
+    assert_eq!(0, pool.locks().waiting("t1"));
+
+    // Gives up after the last attempt.
+    let holder = pool.transaction().unwrap();
+    holder.lock_exclusive("t1").unwrap();
+    let mut attempts = 0;
+    let err = pool
+        .with_transaction(|transaction| {
+            attempts += 1;
+            transaction.lock_shared("t1")
+        })
+        .unwrap_err();
+    assert_eq!(3, attempts);
+    assert!(matches!(
+        err.downcast_ref::<PBaseError>(),
+        Some(PBaseError::LockTimeout { .. })
+    ));
+    drop(holder);
+
+    // Other errors are not retried.
+    let mut attempts = 0;
+    let err = pool
+        .with_transaction(|transaction| {
+            attempts += 1;
+            transaction.run_select_query(SelectQuery {
+                from: "missing".into(),
+                ..Default::default()
+            })
+        })
+        .unwrap_err();
+    assert_eq!(1, attempts);
+    assert!(!matches!(
+        err.downcast_ref::<PBaseError>(),
+        Some(PBaseError::LockTimeout { .. })
+    ));
+}
+
+#[test]
+fn test_transaction_writes_are_buffered() {
+    let pool = PBasePool::new(PBase::new_temp().unwrap())
+        .with_lock_wait_timeout(Duration::from_millis(10))
+        .with_transaction_attempts(3);
+    create_i32_table(&pool.write().unwrap(), "t1");
+    let insert_query = |value| InsertQuery {
+        table: "t1".into(),
+        values: HashMap::from([("field1".into(), Value::I32(value))]),
+    };
+
+    // The first attempt times out after its insert, the second one commits it: the row is
+    // inserted once.
+    let mut holder = Some(pool.transaction().unwrap());
+    holder.as_ref().unwrap().lock_exclusive("t2").unwrap();
+    let mut attempts = 0;
+    pool.with_transaction(|transaction| {
+        attempts += 1;
+        transaction.insert(insert_query(1))?;
+        if attempts == 2 {
+            holder = None;
+        }
+        transaction.lock_exclusive("t2")
+    })
+    .unwrap();
+    assert_eq!(2, attempts);
+    assert_eq!(vec![Value::I32(1)], field1_values(&pool.read().unwrap()));
+
+    // A failing closure writes nothing.
+    let result = pool.with_transaction(|transaction| {
+        transaction.insert(insert_query(2))?;
+        transaction.insert(InsertQuery {
+            table: "missing".into(),
+            values: HashMap::new(),
+        })
+    });
+    assert!(result.is_err());
+    assert_eq!(vec![Value::I32(1)], field1_values(&pool.read().unwrap()));
+
+    // Uncommitted transactions write nothing either.
+    let transaction = pool.transaction().unwrap();
+    transaction.insert(insert_query(3)).unwrap();
+    assert_eq!(vec![Value::I32(1)], field1_values(&transaction));
+    drop(transaction);
+    let transaction = pool.transaction().unwrap();
+    transaction.insert(insert_query(4)).unwrap();
+    transaction.commit().unwrap();
+    assert_eq!(
+        vec![Value::I32(1), Value::I32(4)],
+        field1_values(&pool.read().unwrap())
+    );
+}
+
+#[test]
+fn test_handle_with_transaction() {
+    let db = PBase::new_temp().unwrap();
+    let other_db = PBase::new(db.dir().to_path_buf());
+    create_i32_table(&db, "t1");
+    let insert_query = |value| InsertQuery {
+        table: "t1".into(),
+        values: HashMap::from([("field1".into(), Value::I32(value))]),
+    };
+    db.run_insert_query(&insert_query(1)).unwrap();
+    other_db.run_insert_query(&insert_query(2)).unwrap();
+
+    // The commit finds the handle stale, which is refreshed before the second attempt.
+    let mut attempts = 0;
+    db.with_transaction(|transaction| {
+        attempts += 1;
+        transaction.insert(insert_query(3))
+    })
+    .unwrap();
+    assert_eq!(2, attempts);
+    assert_eq!(
+        vec![Value::I32(1), Value::I32(2), Value::I32(3)],
+        field1_values(&db)
+    );
+}
+
+fn create_i32_table(db: &PBase, name: &str) {
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: name.into(),
+            fields: IndexMap::from([("field1".into(), FieldSchema::I32)]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+}
+
+fn field1_values(db: &PBase) -> Vec<Value> {
+    db.run_select_query(SelectQuery {
+        from: "t1".into(),
+        ..Default::default()
+    })
+    .unwrap()
+    .rows
+    .iter()
+    .map(|row| row["t1.field1"].clone())
+    .collect()
+}
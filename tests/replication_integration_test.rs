@@ -0,0 +1,123 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use indexmap::IndexMap;
+use pbase::{
+    common::PBaseError,
+    operator::Row,
+    pbase::PBase,
+    query::{CreateTableQuery, InsertQuery, SelectQuery},
+    schema::{FieldSchema, TableSchema},
+    value::Value,
+    wal::WalRecord,
+};
+
+#[test]
+fn test_replica_follows_primary() {
+    let primary = PBase::new(fresh_dir("replica_primary"));
+    let replica = PBase::new(fresh_dir("replica_replica"));
+
+    primary
+        .run_create_table_query(&CreateTableQuery {
+            schema: TableSchema {
+                name: "replicated".into(),
+                fields: IndexMap::from([
+                    ("field1".into(), FieldSchema::I32),
+                    ("field2".into(), FieldSchema::I32),
+                ]),
+                indices: IndexMap::from([("field1_index".into(), vec!["field1".into()])]),
+                ..Default::default()
+            },
+        })
+        .unwrap();
+
+    for (field1, field2) in [(1, 10), (2, 20)] {
+        insert(&primary, field1, field2);
+    }
+    assert_eq!(3, sync(&primary, &replica));
+    assert_eq!(select_all(&primary), select_all(&replica));
+
+    insert(&primary, 3, 30);
+    primary
+        .update_row_at(
+            "replicated",
+            0,
+            &HashMap::from([("field2".into(), Value::I32(11))]),
+        )
+        .unwrap();
+    assert_eq!(5, sync(&primary, &replica));
+    assert_eq!(3, select_all(&replica).len());
+    assert_eq!(select_all(&primary), select_all(&replica));
+
+    // Re-applying records the replica already has is a no-op.
+    assert_eq!(
+        5,
+        replica.apply_wal(&primary.stream_wal(1).unwrap()).unwrap()
+    );
+    assert_eq!(select_all(&primary), select_all(&replica));
+}
+
+#[test]
+fn test_replica_rejects_wal_gap() {
+    let primary = PBase::new(fresh_dir("replica_gap_primary"));
+    let replica = PBase::new(fresh_dir("replica_gap_replica"));
+
+    primary
+        .run_create_table_query(&CreateTableQuery {
+            schema: TableSchema {
+                name: "replicated".into(),
+                fields: IndexMap::from([
+                    ("field1".into(), FieldSchema::I32),
+                    ("field2".into(), FieldSchema::I32),
+                ]),
+                indices: IndexMap::new(),
+                ..Default::default()
+            },
+        })
+        .unwrap();
+    insert(&primary, 1, 10);
+
+    let batch: Vec<WalRecord> = primary.stream_wal(2).unwrap();
+    let err = replica.apply_wal(&batch).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<PBaseError>(),
+        Some(PBaseError::WalGap {
+            expected_lsn: 1,
+            lsn: 2
+        })
+    ));
+    assert_eq!(0, replica.last_lsn().unwrap());
+}
+
+fn sync(primary: &PBase, replica: &PBase) -> u64 {
+    let from_lsn = replica.last_lsn().unwrap() + 1;
+    replica
+        .apply_wal(&primary.stream_wal(from_lsn).unwrap())
+        .unwrap()
+}
+
+fn insert(db: &PBase, field1: i32, field2: i32) {
+    db.run_insert_query(&InsertQuery {
+        table: "replicated".into(),
+        values: HashMap::from([
+            ("field1".into(), Value::I32(field1)),
+            ("field2".into(), Value::I32(field2)),
+        ]),
+    })
+    .unwrap();
+}
+
+fn select_all(db: &PBase) -> Vec<Row> {
+    db.run_select_query(SelectQuery {
+        from: "replicated".into(),
+        ..Default::default()
+    })
+    .unwrap()
+    .rows
+}
+
+fn fresh_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pbase_{name}_test"));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
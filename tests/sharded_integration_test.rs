@@ -0,0 +1,254 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use indexmap::IndexMap;
+use pbase::{
+    query::{
+        CompareOp, CreateTableQuery, FieldSelector, InsertQuery, JoinContract, JoinType, OrderBy,
+        RhsValue, RowFilter, SelectQuery,
+    },
+    schema::{FieldSchema, TableSchema},
+    sharding::ShardedPBase,
+    value::Value,
+};
+
+#[test]
+fn test_sharded_select() {
+    let db = setup_sharded("sharded_select");
+
+    // All orders, from all shards.
+    let result = db
+        .run_select_query(&SelectQuery {
+            from: "orders".into(),
+            ..Default::default()
+        })
+        .unwrap()
+        .rows;
+    assert_eq!(6, result.len());
+
+    // Each shard only has its own orders.
+    for (shard_idx, shard) in db.shards().iter().enumerate() {
+        let result = shard
+            .run_select_query(SelectQuery {
+                from: "orders".into(),
+                ..Default::default()
+            })
+            .unwrap()
+            .rows;
+        assert_eq!(2, result.len());
+        assert!(result
+            .iter()
+            .all(|row| { db.shard_for(&row["orders.customer_id"]) == shard_idx }));
+    }
+
+    // Shard key equality only reads one shard.
+    let result = db
+        .run_select_query(&SelectQuery {
+            from: "orders".into(),
+            filters: vec![RowFilter {
+                field: field("orders", "customer_id"),
+                op: CompareOp::Eq,
+                rhs: RhsValue::Value(Value::I32(4)),
+            }],
+            ..Default::default()
+        })
+        .unwrap()
+        .rows;
+    assert_eq!(
+        vec![Value::I32(40), Value::I32(41)],
+        result
+            .iter()
+            .map(|row| row["orders.amount"].clone())
+            .collect::<Vec<_>>()
+    );
+
+    // Limit is applied on the merged result.
+    let result = db
+        .run_select_query(&SelectQuery {
+            from: "orders".into(),
+            limit: Some(3),
+            ..Default::default()
+        })
+        .unwrap()
+        .rows;
+    assert_eq!(3, result.len());
+
+    // Ordered limit merges the shards by the order, not by shard order.
+    let result = db
+        .run_select_query(&SelectQuery {
+            from: "orders".into(),
+            order_by: Some(OrderBy {
+                field: field("orders", "amount"),
+                descending: true,
+            }),
+            limit: Some(3),
+            ..Default::default()
+        })
+        .unwrap()
+        .rows;
+    assert_eq!(
+        vec![Value::I32(41), Value::I32(40), Value::I32(21)],
+        result
+            .iter()
+            .map(|row| row["orders.amount"].clone())
+            .collect::<Vec<_>>()
+    );
+
+    // Distinct values are distinct across the shards, not only on each.
+    let result = db
+        .run_select_query(&SelectQuery {
+            from: "orders".into(),
+            distinct_field: Some(field("orders", "region_id")),
+            order_by: Some(OrderBy {
+                field: field("orders", "region_id"),
+                descending: false,
+            }),
+            ..Default::default()
+        })
+        .unwrap()
+        .rows;
+    assert_eq!(
+        vec![Value::I32(0), Value::I32(1)],
+        result
+            .iter()
+            .map(|row| row["orders.region_id"].clone())
+            .collect::<Vec<_>>()
+    );
+
+    // Distinct rows too: only the first shard has the orders of amounts 0 and 1, the others each
+    // have the same two unmatched regions. The limit is applied after dropping the duplicates.
+    let unmatched_regions_query = SelectQuery {
+        from: "regions".into(),
+        joins: vec![JoinContract {
+            join_type: JoinType::Left,
+            null_keys_match: false,
+            lhs: field("regions", "id"),
+            rhs: field("orders", "amount"),
+        }],
+        distinct: true,
+        ..Default::default()
+    };
+    let result = db.run_select_query(&unmatched_regions_query).unwrap().rows;
+    assert_eq!(4, result.len());
+    let result = db
+        .run_select_query(&SelectQuery {
+            limit: Some(3),
+            ..unmatched_regions_query
+        })
+        .unwrap()
+        .rows;
+    assert_eq!(3, result.len());
+    assert_eq!(
+        1,
+        result
+            .iter()
+            .filter(|row| row["regions.id"] == Value::I32(0) && row["orders.amount"] == Value::NULL)
+            .count()
+    );
+
+    // Distinct values ordered by another field lose that order on the shards.
+    assert!(db
+        .run_select_query(&SelectQuery {
+            from: "orders".into(),
+            distinct_field: Some(field("orders", "region_id")),
+            order_by: Some(OrderBy {
+                field: field("orders", "amount"),
+                descending: false,
+            }),
+            ..Default::default()
+        })
+        .is_err());
+
+    // Reference table is not duplicated.
+    let result = db
+        .run_select_query(&SelectQuery {
+            from: "regions".into(),
+            ..Default::default()
+        })
+        .unwrap()
+        .rows;
+    assert_eq!(2, result.len());
+
+    // Sharded table joined to the reference table.
+    let result = db
+        .run_select_query(&SelectQuery {
+            from: "orders".into(),
+            joins: vec![JoinContract {
+                join_type: JoinType::Inner,
+                null_keys_match: false,
+                lhs: field("orders", "region_id"),
+                rhs: field("regions", "id"),
+            }],
+            ..Default::default()
+        })
+        .unwrap()
+        .rows;
+    assert_eq!(6, result.len());
+}
+
+fn field(source: &str, name: &str) -> FieldSelector {
+    FieldSelector {
+        name: name.into(),
+        source: source.into(),
+    }
+}
+
+fn setup_sharded(name: &str) -> ShardedPBase {
+    let dirs: Vec<PathBuf> = (0..3)
+        .map(|i| std::env::temp_dir().join(format!("pbase_{name}_test/shard{i}")))
+        .collect();
+    for dir in &dirs {
+        let _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir_all(dir).unwrap();
+    }
+
+    let db = ShardedPBase::new(
+        dirs,
+        HashMap::from([("orders".to_string(), "customer_id".to_string())]),
+    );
+
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "orders".into(),
+            fields: IndexMap::from([
+                ("customer_id".into(), FieldSchema::I32),
+                ("region_id".into(), FieldSchema::I32),
+                ("amount".into(), FieldSchema::I32),
+            ]),
+            indices: IndexMap::new(),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "regions".into(),
+            fields: IndexMap::from([("id".into(), FieldSchema::I32)]),
+            indices: IndexMap::new(),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+
+    for customer_id in 0..3 {
+        for n in 0..2 {
+            db.run_insert_query(&InsertQuery {
+                table: "orders".into(),
+                values: HashMap::from([
+                    ("customer_id".into(), Value::I32(customer_id * 2)),
+                    ("region_id".into(), Value::I32(n)),
+                    ("amount".into(), Value::I32(customer_id * 20 + n)),
+                ]),
+            })
+            .unwrap();
+        }
+    }
+    for id in 0..2 {
+        db.run_insert_query(&InsertQuery {
+            table: "regions".into(),
+            values: HashMap::from([("id".into(), Value::I32(id))]),
+        })
+        .unwrap();
+    }
+
+    db
+}
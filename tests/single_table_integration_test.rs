@@ -1,12 +1,36 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 use indexmap::IndexMap;
 use pbase::{
-    common::delete_all_files_by_glob,
-    pbase::PBase,
-    query::{CreateTableQuery, FieldSelector, InsertQuery, RhsValue, RowFilter, SelectQuery},
-    schema::{FieldSchema, TableSchema},
+    audit::{audit_table_id, AUDIT_TABLE},
+    common::{delete_all_files_by_glob, PBaseError},
+    consistency::ConsistencyIssue,
+    copy::{CopyHeader, CopyWriter},
+    custom_type::TypeDef,
+    decimal::Decimal,
+    lexer::Lexer,
+    maintenance::{MaintenancePolicy, MaintenanceScheduler, StatsRefreshTask},
+    parser::Parser,
+    pbase::{DryRunReport, MutationResult, PBase, INDEX_DELTA_MERGE_ROWS},
+    plan::PlanNode,
+    progress::ProgressReporter,
+    query::{
+        Aggregate, CallFilter, CompareOp, CreateTableQuery, DeleteQuery, FieldSelector,
+        InsertQuery, JoinContract, JoinType, MutationQuery, OrderBy, Query, RhsValue, RowCap,
+        RowFilter, SampleSpec, ScalarSubquery, SelectQuery, SetQuery, SettingValue,
+    },
+    quota::Quota,
+    result_set::ColumnInfo,
+    schema::{FieldSchema, InsertMode, TableSchema},
+    table::Table,
+    table_opener::{IoStrategy, TableOpener},
     value::Value,
+    wal::WalOp,
 };
 
 #[test]
@@ -24,10 +48,11 @@ fn test_basic_single_table_create_and_load() {
                 ("field2".into(), FieldSchema::I32),
                 ("field3".into(), FieldSchema::I32),
             ]),
-            indices: HashMap::from([(
+            indices: IndexMap::from([(
                 "field_1_and_2".into(),
                 vec!["field1".into(), "field2".into()],
             )]),
+            ..Default::default()
         },
     };
 
@@ -77,9 +102,10 @@ fn test_basic_single_table_create_and_load() {
                 name: "field1".to_string(),
                 source: "testtable".to_string(),
             },
-            op: std::cmp::Ordering::Equal,
+            op: CompareOp::Eq,
             rhs: RhsValue::Value(Value::I32(2)),
         }],
+        ..Default::default()
     };
 
     let query_result = db.run_select_query(query);
@@ -87,7 +113,7 @@ fn test_basic_single_table_create_and_load() {
     assert_eq!(1, query_result.as_ref().unwrap().len());
     assert_eq!(
         Value::I32(20),
-        query_result.as_ref().unwrap()[0]["testtable.field2"]
+        query_result.as_ref().unwrap().rows[0]["testtable.field2"]
     );
 
     let query = SelectQuery {
@@ -98,9 +124,10 @@ fn test_basic_single_table_create_and_load() {
                 name: "field1".to_string(),
                 source: "testtable".to_string(),
             },
-            op: std::cmp::Ordering::Less,
+            op: CompareOp::Lt,
             rhs: RhsValue::Value(Value::I32(2)),
         }],
+        ..Default::default()
     };
 
     let query_result = db.run_select_query(query);
@@ -108,7 +135,7 @@ fn test_basic_single_table_create_and_load() {
     assert_eq!(1, query_result.as_ref().unwrap().len());
     assert_eq!(
         Value::I32(10),
-        query_result.as_ref().unwrap()[0]["testtable.field2"]
+        query_result.as_ref().unwrap().rows[0]["testtable.field2"]
     );
 
     let query = SelectQuery {
@@ -119,9 +146,10 @@ fn test_basic_single_table_create_and_load() {
                 name: "field1".to_string(),
                 source: "testtable".to_string(),
             },
-            op: std::cmp::Ordering::Greater,
+            op: CompareOp::Gt,
             rhs: RhsValue::Value(Value::I32(2)),
         }],
+        ..Default::default()
     };
 
     let query_result = db.run_select_query(query);
@@ -129,7 +157,7 @@ fn test_basic_single_table_create_and_load() {
     assert_eq!(1, query_result.as_ref().unwrap().len());
     assert_eq!(
         Value::I32(30),
-        query_result.as_ref().unwrap()[0]["testtable.field2"]
+        query_result.as_ref().unwrap().rows[0]["testtable.field2"]
     );
 }
 
@@ -147,7 +175,8 @@ fn test_single_table_field_reference_filter() {
                 ("f1".into(), FieldSchema::U8),
                 ("f2".into(), FieldSchema::U8),
             ]),
-            indices: HashMap::new(),
+            indices: IndexMap::new(),
+            ..Default::default()
         },
     };
     db.run_create_table_query(&create_table_query).unwrap();
@@ -185,15 +214,16 @@ fn test_single_table_field_reference_filter() {
                 name: "f1".into(),
                 source: "singleref_t".into(),
             },
-            op: std::cmp::Ordering::Equal,
+            op: CompareOp::Eq,
             rhs: RhsValue::Ref(FieldSelector {
                 name: "f2".into(),
                 source: "singleref_t".into(),
             }),
         }],
+        ..Default::default()
     };
 
-    let result = db.run_select_query(query).unwrap();
+    let result = db.run_select_query(query).unwrap().rows;
     assert_eq!(2, result.len());
 
     assert_eq!(Value::U8(2), result[0]["singleref_t.f1"]);
@@ -202,3 +232,4518 @@ fn test_single_table_field_reference_filter() {
     assert_eq!(Value::U8(1), result[1]["singleref_t.f1"]);
     assert_eq!(Value::U8(1), result[1]["singleref_t.f2"]);
 }
+
+#[test]
+fn test_check_all() {
+    // Own directory, so tables of the other (concurrently running) tests are not checked.
+    let dir = std::env::temp_dir().join("pbase_check_all_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+
+    let create_table_query = CreateTableQuery {
+        schema: TableSchema {
+            name: "checked".into(),
+            fields: IndexMap::from([
+                ("field1".into(), FieldSchema::I32),
+                ("field2".into(), FieldSchema::I32),
+            ]),
+            indices: IndexMap::from([("field1_index".into(), vec!["field1".into()])]),
+            ..Default::default()
+        },
+    };
+    db.run_create_table_query(&create_table_query).unwrap();
+
+    for (field1, field2) in [(3, 30), (1, 10), (2, 20)] {
+        let insert_query = InsertQuery {
+            table: "checked".into(),
+            values: HashMap::from([
+                ("field1".into(), Value::I32(field1)),
+                ("field2".into(), Value::I32(field2)),
+            ]),
+        };
+        db.run_insert_query(&insert_query).unwrap();
+    }
+
+    let report = db.check_all().unwrap();
+    assert!(report.is_ok());
+    assert_eq!(1, report.tables.len());
+    assert_eq!(3, report.tables[0].rows);
+
+    // Swap the first two index rows (index rows are: field1 (4 bytes) + row pointer (8 bytes)).
+    db.merge_index_deltas().unwrap();
+    let index_file_name = dir.join("checked__field1_index.pbi");
+    let mut index_bytes = std::fs::read(&index_file_name).unwrap();
+    let (first, second) = index_bytes.split_at_mut(12);
+    first.swap_with_slice(&mut second[..12]);
+    std::fs::write(&index_file_name, &index_bytes).unwrap();
+
+    // Cut the last row in half.
+    let data_file_name = dir.join("checked.pbd");
+    let data_bytes = std::fs::read(&data_file_name).unwrap();
+    std::fs::write(&data_file_name, &data_bytes[..data_bytes.len() - 4]).unwrap();
+
+    let report = db.check_all().unwrap();
+    assert!(!report.is_ok());
+    assert_eq!(
+        vec![
+            ConsistencyIssue::DataSizeNotRowMultiple {
+                data_byte_size: 20,
+                row_byte_size: 8,
+            },
+            ConsistencyIssue::IndexRowCountMismatch {
+                index: "field1_index".into(),
+                index_rows: 3,
+                table_rows: 2,
+            },
+            // Now first in the index, pointing to the cut row.
+            ConsistencyIssue::InvalidRowPointer {
+                index: "field1_index".into(),
+                index_row: 0,
+                row_ptr: 16,
+            },
+            ConsistencyIssue::IndexNotSorted {
+                index: "field1_index".into(),
+                index_row: 1,
+            },
+        ],
+        report.tables[0].issues
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_read_your_writes() {
+    delete_all_files_by_glob("rywtable*");
+
+    let db = PBase::new(std::env::current_dir().unwrap_or_else(|_| PathBuf::new()));
+
+    let create_table_query = CreateTableQuery {
+        schema: TableSchema {
+            name: "rywtable".into(),
+            fields: IndexMap::from([("field1".into(), FieldSchema::I32)]),
+            indices: IndexMap::from([("field1_index".into(), vec!["field1".into()])]),
+            ..Default::default()
+        },
+    };
+    db.run_create_table_query(&create_table_query).unwrap();
+
+    for i in 0..50 {
+        let insert_query = InsertQuery {
+            table: "rywtable".into(),
+            values: HashMap::from([("field1".into(), Value::I32(i))]),
+        };
+        db.run_insert_query(&insert_query).unwrap();
+
+        // Full scan.
+        let query = SelectQuery {
+            from: "rywtable".into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            usize::try_from(i + 1).unwrap(),
+            db.run_select_query(query).unwrap().len()
+        );
+
+        // Index scan on the newest row.
+        let query = SelectQuery {
+            from: "rywtable".into(),
+            filters: vec![RowFilter {
+                field: FieldSelector {
+                    name: "field1".into(),
+                    source: "rywtable".into(),
+                },
+                op: CompareOp::Eq,
+                rhs: RhsValue::Value(Value::I32(i)),
+            }],
+            ..Default::default()
+        };
+        assert_eq!(1, db.run_select_query(query).unwrap().len());
+    }
+}
+
+#[test]
+fn test_update_row_at() {
+    let dir = std::env::temp_dir().join("pbase_update_row_at_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+
+    let create_table_query = CreateTableQuery {
+        schema: TableSchema {
+            name: "updated".into(),
+            fields: IndexMap::from([
+                ("field1".into(), FieldSchema::I32),
+                ("field2".into(), FieldSchema::I32),
+            ]),
+            indices: IndexMap::from([("field1_index".into(), vec!["field1".into()])]),
+            ..Default::default()
+        },
+    };
+    db.run_create_table_query(&create_table_query).unwrap();
+
+    for (field1, field2) in [(1, 10), (2, 20), (3, 30)] {
+        let insert_query = InsertQuery {
+            table: "updated".into(),
+            values: HashMap::from([
+                ("field1".into(), Value::I32(field1)),
+                ("field2".into(), Value::I32(field2)),
+            ]),
+        };
+        db.run_insert_query(&insert_query).unwrap();
+    }
+
+    // Second row (rows are 8 bytes).
+    db.update_row_at(
+        "updated",
+        8,
+        &HashMap::from([("field1".into(), Value::I32(5))]),
+    )
+    .unwrap();
+
+    let select_by_field1 = |value: i32| {
+        db.run_select_query(SelectQuery {
+            from: "updated".into(),
+            filters: vec![RowFilter {
+                field: FieldSelector {
+                    name: "field1".into(),
+                    source: "updated".into(),
+                },
+                op: CompareOp::Eq,
+                rhs: RhsValue::Value(Value::I32(value)),
+            }],
+            ..Default::default()
+        })
+        .unwrap()
+        .rows
+    };
+
+    assert!(select_by_field1(2).is_empty());
+    assert_eq!(
+        vec![IndexMap::from([
+            ("updated.field1".into(), Value::I32(5)),
+            ("updated.field2".into(), Value::I32(20)),
+        ])],
+        select_by_field1(5)
+    );
+    assert!(db.check_all().unwrap().is_ok());
+
+    assert!(db
+        .update_row_at(
+            "updated",
+            4,
+            &HashMap::from([("field1".into(), Value::I32(0))])
+        )
+        .is_err());
+    assert!(db
+        .update_row_at(
+            "updated",
+            0,
+            &HashMap::from([("nope".into(), Value::I32(0))])
+        )
+        .is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_system_tables() {
+    let dir = std::env::temp_dir().join("pbase_system_tables_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir);
+
+    for (name, indices) in [
+        ("described_a", IndexMap::new()),
+        (
+            "described_b",
+            IndexMap::from([("by_field2".into(), vec!["field2".into(), "field1".into()])]),
+        ),
+    ] {
+        db.run_create_table_query(&CreateTableQuery {
+            schema: TableSchema {
+                name: name.into(),
+                fields: IndexMap::from([
+                    ("field1".into(), FieldSchema::U8),
+                    ("field2".into(), FieldSchema::I32),
+                ]),
+                indices,
+                ..Default::default()
+            },
+        })
+        .unwrap();
+    }
+    for field2 in 0..3 {
+        db.run_insert_query(&InsertQuery {
+            table: "described_b".into(),
+            values: HashMap::from([
+                ("field1".into(), Value::U8(1)),
+                ("field2".into(), Value::I32(field2)),
+            ]),
+        })
+        .unwrap();
+    }
+
+    assert!(db
+        .run_create_table_query(&CreateTableQuery {
+            schema: TableSchema {
+                name: "pbase_tables".into(),
+                fields: IndexMap::from([("field1".into(), FieldSchema::U8)]),
+                indices: IndexMap::new(),
+                ..Default::default()
+            },
+        })
+        .is_err());
+
+    let tables = db
+        .run_select_query(SelectQuery {
+            from: "pbase_tables".into(),
+            filters: vec![RowFilter {
+                field: FieldSelector {
+                    name: "row_count".into(),
+                    source: "pbase_tables".into(),
+                },
+                op: CompareOp::Gt,
+                rhs: RhsValue::Value(Value::I32(0)),
+            }],
+            ..Default::default()
+        })
+        .unwrap()
+        .rows;
+    assert_eq!(
+        vec![IndexMap::from([
+            ("pbase_tables.id".into(), Value::I32(1)),
+            ("pbase_tables.row_count".into(), Value::I32(3)),
+            ("pbase_tables.row_byte_size".into(), Value::I32(5)),
+            ("pbase_tables.column_count".into(), Value::I32(2)),
+            ("pbase_tables.index_count".into(), Value::I32(1)),
+        ])],
+        tables
+    );
+
+    // Columns of the indexed table, through a join.
+    let indexed_columns = db
+        .run_select_query(SelectQuery {
+            from: "pbase_indices".into(),
+            joins: vec![JoinContract {
+                join_type: JoinType::Inner,
+                null_keys_match: false,
+                lhs: FieldSelector {
+                    name: "column_position".into(),
+                    source: "pbase_indices".into(),
+                },
+                rhs: FieldSelector {
+                    name: "position".into(),
+                    source: "pbase_columns".into(),
+                },
+            }],
+            filters: vec![RowFilter {
+                field: FieldSelector {
+                    name: "table_id".into(),
+                    source: "pbase_columns".into(),
+                },
+                op: CompareOp::Eq,
+                rhs: RhsValue::Value(Value::I32(1)),
+            }],
+            ..Default::default()
+        })
+        .unwrap()
+        .rows;
+    let mut column_types: Vec<(Value, Value)> = indexed_columns
+        .iter()
+        .map(|row| {
+            (
+                row["pbase_indices.position"].clone(),
+                row["pbase_columns.type"].clone(),
+            )
+        })
+        .collect();
+    column_types.sort();
+    assert_eq!(
+        vec![(Value::I32(0), Value::U8(1)), (Value::I32(1), Value::U8(0))],
+        column_types
+    );
+}
+
+#[test]
+fn test_result_set_columns() {
+    let dir = std::env::temp_dir().join("pbase_result_set_columns_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir);
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "typed".into(),
+            fields: IndexMap::from([
+                ("field1".into(), FieldSchema::I32),
+                ("field2".into(), FieldSchema::U8),
+            ]),
+            indices: IndexMap::new(),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+
+    // Columns are known without rows.
+    let result = db
+        .run_select_query(SelectQuery {
+            from: "typed".into(),
+            ..Default::default()
+        })
+        .unwrap();
+    assert!(result.is_empty());
+    assert_eq!(
+        vec![
+            ColumnInfo {
+                name: "typed.field1".into(),
+                source: Some("typed".into()),
+                field_schema: FieldSchema::I32,
+            },
+            ColumnInfo {
+                name: "typed.field2".into(),
+                source: Some("typed".into()),
+                field_schema: FieldSchema::U8,
+            },
+        ],
+        result.columns
+    );
+    assert_eq!(
+        Some(&FieldSchema::U8),
+        result
+            .column("typed.field2")
+            .map(|column| &column.field_schema)
+    );
+}
+
+#[test]
+fn test_for_each_row_view() {
+    let dir = std::env::temp_dir().join("pbase_row_view_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir);
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "viewed".into(),
+            fields: IndexMap::from([
+                ("field1".into(), FieldSchema::I32),
+                ("field2".into(), FieldSchema::I32),
+            ]),
+            indices: IndexMap::from([("field1_index".into(), vec!["field1".into()])]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    for field1 in [5, 3, 8, 1, 9, 4] {
+        db.run_insert_query(&InsertQuery {
+            table: "viewed".into(),
+            values: HashMap::from([
+                ("field1".into(), Value::I32(field1)),
+                ("field2".into(), Value::I32(field1 % 2)),
+            ]),
+        })
+        .unwrap();
+    }
+
+    let field = |name: &str| FieldSelector {
+        name: name.into(),
+        source: "viewed".into(),
+    };
+    // Served by the index on field1, with field2 filtered on the views.
+    let query = SelectQuery {
+        from: "viewed".into(),
+        filters: vec![
+            RowFilter {
+                field: field("field1"),
+                op: CompareOp::Gt,
+                rhs: RhsValue::Value(Value::I32(2)),
+            },
+            RowFilter {
+                field: field("field2"),
+                op: CompareOp::Eq,
+                rhs: RhsValue::Value(Value::I32(1)),
+            },
+        ],
+        ..Default::default()
+    };
+
+    let mut sum = 0;
+    let mut rows = vec![];
+    let count = db
+        .for_each_row_view(query.clone(), |row_view| {
+            if let Some(Value::I32(v)) = row_view.get("field1") {
+                sum += v;
+            }
+            rows.push(row_view.to_row());
+        })
+        .unwrap();
+
+    assert_eq!(3, count);
+    assert_eq!(5 + 3 + 9, sum);
+    assert_eq!(db.run_select_query(query.clone()).unwrap().rows, rows);
+
+    let limited = db
+        .for_each_row_view(
+            SelectQuery {
+                limit: Some(2),
+                ..query
+            },
+            |_| {},
+        )
+        .unwrap();
+    assert_eq!(2, limited);
+
+    assert!(db
+        .for_each_row_view(
+            SelectQuery {
+                from: "viewed".into(),
+                joins: vec![JoinContract {
+                    join_type: JoinType::Inner,
+                    null_keys_match: false,
+                    lhs: field("field1"),
+                    rhs: field("field2"),
+                }],
+                ..Default::default()
+            },
+            |_| {},
+        )
+        .is_err());
+}
+
+#[test]
+fn test_index_delta() {
+    let dir = std::env::temp_dir().join("pbase_index_delta_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "deltas".into(),
+            fields: IndexMap::from([
+                ("field1".into(), FieldSchema::I32),
+                ("field2".into(), FieldSchema::I32),
+            ]),
+            indices: IndexMap::from([("field1_index".into(), vec!["field1".into()])]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+
+    // One batch is merged into the sorted index, the rest stays in the delta.
+    let row_count = i32::try_from(INDEX_DELTA_MERGE_ROWS).unwrap() + 100;
+    for i in 0..row_count {
+        db.run_insert_query(&InsertQuery {
+            table: "deltas".into(),
+            values: HashMap::from([
+                ("field1".into(), Value::I32((i * 7) % 50)),
+                ("field2".into(), Value::I32(i)),
+            ]),
+        })
+        .unwrap();
+    }
+    let index_delta_file_name = dir.join("deltas__field1_index.pbx");
+    assert_eq!(
+        100 * 12,
+        std::fs::metadata(&index_delta_file_name).unwrap().len()
+    );
+    assert!(db.check_all().unwrap().is_ok());
+
+    let query = SelectQuery {
+        from: "deltas".into(),
+        filters: vec![RowFilter {
+            field: FieldSelector {
+                name: "field1".into(),
+                source: "deltas".into(),
+            },
+            op: CompareOp::Gt,
+            rhs: RhsValue::Value(Value::I32(45)),
+        }],
+        ..Default::default()
+    };
+    let field_values = |rows: &[IndexMap<_, Value>]| -> Vec<(Value, Value)> {
+        rows.iter()
+            .map(|row| (row["deltas.field1"].clone(), row["deltas.field2"].clone()))
+            .collect()
+    };
+
+    // Index order, rows of the same key in insertion order.
+    let mut expected: Vec<(Value, Value)> = (0..row_count)
+        .filter(|i| (i * 7) % 50 > 45)
+        .map(|i| (Value::I32((i * 7) % 50), Value::I32(i)))
+        .collect();
+    expected.sort_by(|lhs, rhs| lhs.0.cmp(&rhs.0));
+    let rows = db.run_select_query(query.clone()).unwrap().rows;
+    assert_eq!(expected, field_values(&rows));
+
+    db.merge_index_deltas().unwrap();
+    assert!(!index_delta_file_name.exists());
+    assert!(db.check_all().unwrap().is_ok());
+    let rows = db.run_select_query(query).unwrap().rows;
+    assert_eq!(expected, field_values(&rows));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_throttled_index_merge() {
+    let db = PBase::new_temp()
+        .unwrap()
+        .with_maintenance_io_limit(1 << 20);
+    assert_eq!(Some(1 << 20), db.maintenance_io_limit());
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "throttled".into(),
+            fields: IndexMap::from([("field1".into(), FieldSchema::I32)]),
+            indices: IndexMap::from([("field1_index".into(), vec!["field1".into()])]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    for i in 0..200 {
+        db.run_insert_query(&InsertQuery {
+            table: "throttled".into(),
+            values: HashMap::from([("field1".into(), Value::I32(200 - i))]),
+        })
+        .unwrap();
+    }
+
+    let set = |value| SetQuery {
+        name: "maintenance_io_limit".into(),
+        value,
+    };
+    for value in [SettingValue::Int(0), SettingValue::Word("on".into())] {
+        assert!(matches!(
+            db.set(&set(value)).unwrap_err().downcast_ref(),
+            Some(PBaseError::InvalidSettingValue { .. })
+        ));
+    }
+    assert!(matches!(
+        db.set(&SetQuery {
+            name: "max_rows".into(),
+            value: SettingValue::Int(1),
+        })
+        .unwrap_err()
+        .downcast_ref(),
+        Some(PBaseError::UnknownSetting(_))
+    ));
+
+    // 200 delta entries of 12 bytes are read, then written merged: 4800 bytes at 4000 bytes/sec.
+    db.set(&set(SettingValue::Int(4000))).unwrap();
+    let started = Instant::now();
+    db.merge_index_deltas().unwrap();
+    assert!(started.elapsed() >= Duration::from_secs(1));
+    assert!(db.check_all().unwrap().is_ok());
+
+    db.set(&set(SettingValue::Word("off".into()))).unwrap();
+    assert_eq!(None, db.maintenance_io_limit());
+    let rows = db
+        .run_select_query(SelectQuery {
+            from: "throttled".into(),
+            filters: vec![RowFilter {
+                field: FieldSelector {
+                    name: "field1".into(),
+                    source: "throttled".into(),
+                },
+                op: CompareOp::Lt,
+                rhs: RhsValue::Value(Value::I32(3)),
+            }],
+            ..Default::default()
+        })
+        .unwrap()
+        .rows;
+    assert_eq!(
+        vec![Value::I32(1), Value::I32(2)],
+        rows.iter()
+            .map(|row| row["throttled.field1"].clone())
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_standalone_table() {
+    let dir = std::env::temp_dir().join("pbase_standalone_table_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+    for (name, indices) in [
+        ("plain", IndexMap::new()),
+        (
+            "indexed",
+            IndexMap::from([("field1_index".into(), vec!["field1".into()])]),
+        ),
+    ] {
+        db.run_create_table_query(&CreateTableQuery {
+            schema: TableSchema {
+                name: name.into(),
+                fields: IndexMap::from([
+                    ("field1".into(), FieldSchema::I32),
+                    ("field2".into(), FieldSchema::U8),
+                ]),
+                indices,
+                ..Default::default()
+            },
+        })
+        .unwrap();
+    }
+
+    let table = Table::open(dir.join("plain.pbs"), dir.join("plain.pbd")).unwrap();
+    for (field1, field2) in [(3, 1), (1, 0), (2, 1)] {
+        table
+            .insert(&HashMap::from([
+                ("field1".into(), Value::I32(field1)),
+                ("field2".into(), Value::U8(field2)),
+            ]))
+            .unwrap();
+    }
+    assert_eq!(
+        15,
+        table
+            .insert(&HashMap::from([("field1".into(), Value::I32(4))]))
+            .unwrap()
+    );
+    assert!(table
+        .insert(&HashMap::from([("field3".into(), Value::I32(4))]))
+        .is_err());
+
+    let rows = table.scan().unwrap();
+    assert_eq!(4, rows.len());
+    assert_eq!(
+        db.run_select_query(SelectQuery {
+            from: "plain".into(),
+            ..Default::default()
+        })
+        .unwrap()
+        .rows,
+        rows
+    );
+
+    let filter = |name: &str, value| RowFilter {
+        field: FieldSelector {
+            name: name.into(),
+            source: "plain".into(),
+        },
+        op: CompareOp::Eq,
+        rhs: RhsValue::Value(value),
+    };
+    let rows = table.filter(&[filter("field2", Value::U8(1))]).unwrap();
+    assert_eq!(
+        vec![Value::I32(3), Value::I32(2)],
+        rows.iter()
+            .map(|row| row["plain.field1"].clone())
+            .collect::<Vec<_>>()
+    );
+    assert!(table.filter(&[filter("field3", Value::U8(1))]).is_err());
+    // Subqueries and field references are refused rather than panicking mid-scan.
+    assert!(table
+        .filter(&[RowFilter {
+            rhs: RhsValue::Ref(FieldSelector {
+                name: "field2".into(),
+                source: "plain".into(),
+            }),
+            ..filter("field1", Value::NULL)
+        }])
+        .is_err());
+    assert!(table
+        .filter(&[RowFilter {
+            rhs: RhsValue::Subquery {
+                aggregate: Aggregate::Count,
+                query: Box::new(SelectQuery {
+                    from: "plain".into(),
+                    ..Default::default()
+                }),
+            },
+            ..filter("field1", Value::NULL)
+        }])
+        .is_err());
+
+    let indexed = Table::open(dir.join("indexed.pbs"), dir.join("indexed.pbd")).unwrap();
+    assert!(indexed
+        .insert(&HashMap::from([("field1".into(), Value::I32(1))]))
+        .is_err());
+    assert!(Table::open(dir.join("missing.pbs"), dir.join("missing.pbd")).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_strict_insert_mode() {
+    let dir = std::env::temp_dir().join("pbase_strict_insert_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone()).with_insert_mode(InsertMode::Strict);
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "strict".into(),
+            fields: IndexMap::from([
+                ("field1".into(), FieldSchema::I32),
+                ("field2".into(), FieldSchema::U8),
+            ]),
+            indices: IndexMap::from([("field2_index".into(), vec!["field2".into()])]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+
+    let insert = |db: &PBase, values: Vec<(&str, Value)>| {
+        db.run_insert_query(&InsertQuery {
+            table: "strict".into(),
+            values: values
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        })
+    };
+    insert(
+        &db,
+        vec![("field1", Value::I32(1)), ("field2", Value::U8(1))],
+    )
+    .unwrap();
+    assert!(insert(&db, vec![("field1", Value::I32(2))]).is_err());
+    assert!(insert(
+        &db,
+        vec![("field1", Value::I32(2)), ("field2", Value::I32(2))]
+    )
+    .is_err());
+    assert!(insert(
+        &db,
+        vec![
+            ("field1", Value::I32(2)),
+            ("field2", Value::U8(2)),
+            ("field3", Value::U8(2)),
+        ]
+    )
+    .is_err());
+    // Rejected inserts write nothing, not even to the WAL.
+    assert_eq!(2, db.last_lsn().unwrap());
+
+    // Lenient (the default) stores the converted row, and logs it as stored.
+    let db = PBase::new(dir.clone());
+    insert(
+        &db,
+        vec![("field2", Value::I32(3)), ("field3", Value::U8(3))],
+    )
+    .unwrap();
+    let rows = db
+        .run_select_query(SelectQuery {
+            from: "strict".into(),
+            ..Default::default()
+        })
+        .unwrap()
+        .rows;
+    assert_eq!(2, rows.len());
+    assert_eq!(Value::I32(0), rows[1]["strict.field1"]);
+    assert_eq!(Value::U8(3), rows[1]["strict.field2"]);
+    assert_eq!(
+        WalOp::Insert(InsertQuery {
+            table: "strict".into(),
+            values: HashMap::from([
+                ("field1".into(), Value::I32(0)),
+                ("field2".into(), Value::U8(3)),
+            ]),
+        }),
+        db.stream_wal(3).unwrap()[0].op
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_dry_run() {
+    let dir = std::env::temp_dir().join("pbase_dry_run_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone()).with_insert_mode(InsertMode::Strict);
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "dry".into(),
+            fields: IndexMap::from([
+                ("field1".into(), FieldSchema::I32),
+                ("field2".into(), FieldSchema::I32),
+            ]),
+            indices: IndexMap::from([
+                ("field1_index".into(), vec!["field1".into()]),
+                ("field2_index".into(), vec!["field2".into()]),
+            ]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    let insert_query = InsertQuery {
+        table: "dry".into(),
+        values: HashMap::from([
+            ("field1".into(), Value::I32(1)),
+            ("field2".into(), Value::I32(10)),
+        ]),
+    };
+    db.run_insert_query(&insert_query).unwrap();
+
+    let data_file_name = dir.join("dry.pbd");
+    let data_bytes = std::fs::read(&data_file_name).unwrap();
+
+    assert_eq!(
+        DryRunReport {
+            table: "dry".into(),
+            affected_rows: 1,
+            changed_indices: vec!["field1_index".into(), "field2_index".into()],
+        },
+        db.dry_run(&MutationQuery::Insert(insert_query)).unwrap()
+    );
+    assert!(db
+        .dry_run(&MutationQuery::Insert(InsertQuery {
+            table: "dry".into(),
+            values: HashMap::from([("field1".into(), Value::I32(1))]),
+        }))
+        .is_err());
+
+    let update = |row_pos, field: &str, value| MutationQuery::UpdateRowAt {
+        table: "dry".into(),
+        row_pos,
+        values: HashMap::from([(field.to_string(), Value::I32(value))]),
+    };
+    assert_eq!(
+        vec!["field2_index".to_string()],
+        db.dry_run(&update(0, "field2", 20))
+            .unwrap()
+            .changed_indices
+    );
+    assert!(db
+        .dry_run(&update(0, "field2", 10))
+        .unwrap()
+        .changed_indices
+        .is_empty());
+    assert!(db.dry_run(&update(8, "field2", 20)).is_err());
+    assert!(db.dry_run(&update(0, "field3", 20)).is_err());
+
+    // Nothing was written.
+    assert_eq!(data_bytes, std::fs::read(&data_file_name).unwrap());
+    assert_eq!(2, db.last_lsn().unwrap());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_analyze_chooses_scan_for_unselective_filters() {
+    let dir = std::env::temp_dir().join("pbase_analyze_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "stats".into(),
+            fields: IndexMap::from([
+                ("field1".into(), FieldSchema::I32),
+                ("field2".into(), FieldSchema::U8),
+            ]),
+            indices: IndexMap::from([("field1_index".into(), vec!["field1".into()])]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    for i in 0..100 {
+        db.run_insert_query(&InsertQuery {
+            table: "stats".into(),
+            values: HashMap::from([
+                ("field1".into(), Value::I32(i)),
+                ("field2".into(), Value::U8(1)),
+            ]),
+        })
+        .unwrap();
+    }
+
+    let query = |value| SelectQuery {
+        from: "stats".into(),
+        filters: vec![RowFilter {
+            field: FieldSelector {
+                name: "field1".into(),
+                source: "stats".into(),
+            },
+            op: CompareOp::Gt,
+            rhs: RhsValue::Value(Value::I32(value)),
+        }],
+        ..Default::default()
+    };
+    let plan_of = |value| {
+        db.explain_select_query(query(value))
+            .unwrap()
+            .to_ascii_tree()
+    };
+
+    // Without stats the index is always used.
+    assert!(plan_of(10).starts_with("IndexScan"));
+
+    let stats = db.analyze_table("stats").unwrap();
+    assert_eq!(100, stats.row_count);
+    assert_eq!(vec!["field1"], stats.histograms.keys().collect::<Vec<_>>());
+    assert!(dir.join("stats.pbt").exists());
+
+    assert!(plan_of(10).starts_with("Filter"));
+    assert!(plan_of(95).starts_with("IndexScan"));
+    assert_eq!(89, db.run_select_query(query(10)).unwrap().len());
+    assert_eq!(4, db.run_select_query(query(95)).unwrap().len());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_contradicting_filters_read_no_data() {
+    let dir = std::env::temp_dir().join("pbase_contradiction_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "folded".into(),
+            fields: IndexMap::from([("field1".into(), FieldSchema::I32)]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    db.run_insert_query(&InsertQuery {
+        table: "folded".into(),
+        values: HashMap::from([("field1".into(), Value::I32(4))]),
+    })
+    .unwrap();
+
+    let filter = |op, value| RowFilter {
+        field: FieldSelector {
+            name: "field1".into(),
+            source: "folded".into(),
+        },
+        op,
+        rhs: RhsValue::Value(Value::I32(value)),
+    };
+    let query = |filters| SelectQuery {
+        from: "folded".into(),
+        filters,
+        ..Default::default()
+    };
+    assert_eq!(
+        1,
+        db.run_select_query(query(vec![
+            filter(CompareOp::Gt, 3),
+            filter(CompareOp::Lt, 5),
+        ]))
+        .unwrap()
+        .len()
+    );
+
+    // The data is gone: only queries answered by the planner still succeed.
+    std::fs::remove_file(dir.join("folded.pbd")).unwrap();
+    let contradiction = query(vec![filter(CompareOp::Gt, 5), filter(CompareOp::Lt, 3)]);
+    let result = db.run_select_query(contradiction.clone()).unwrap();
+    assert_eq!(0, result.len());
+    assert_eq!(1, result.columns.len());
+    assert_eq!(
+        "Empty (rows: 0)\n",
+        db.explain_select_query(contradiction)
+            .unwrap()
+            .to_ascii_tree()
+    );
+    assert!(db
+        .run_select_query(query(vec![filter(CompareOp::Gt, 3)]))
+        .is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_scalar_functions() {
+    let dir = std::env::temp_dir().join("pbase_scalar_functions_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let mut db = PBase::new(dir.clone());
+    db.register_scalar("double", FieldSchema::I32, |value| match value {
+        Value::I32(v) => v.checked_mul(2).map_or(Value::NULL, Value::I32),
+        _ => Value::NULL,
+    });
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "calls".into(),
+            fields: IndexMap::from([("field1".into(), FieldSchema::I32)]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    for i in 0..10 {
+        db.run_insert_query(&InsertQuery {
+            table: "calls".into(),
+            values: HashMap::from([("field1".into(), Value::I32(i))]),
+        })
+        .unwrap();
+    }
+
+    let tokens = Lexer::tokenize(b"SELECT double(calls.field1) AS doubled FROM calls").unwrap();
+    let Query::Select(mut query) = Parser::new(&tokens[..]).parse().unwrap() else {
+        panic!("expected a select");
+    };
+    query.call_filters = vec![CallFilter {
+        alias: "doubled".into(),
+        op: CompareOp::Gt,
+        rhs: Value::I32(10),
+    }];
+    query.limit = Some(3);
+
+    let result = db.run_select_query(query.clone()).unwrap();
+    assert_eq!(
+        vec!["calls.field1".to_string(), "doubled".to_string()],
+        result
+            .columns
+            .iter()
+            .map(|column| column.name.clone())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        vec![Value::I32(12), Value::I32(14), Value::I32(16)],
+        result
+            .rows
+            .iter()
+            .map(|row| row["doubled"].clone())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        "Limit 3 (rows: 3)\n\
+         └── Compute double(calls.field1) AS doubled WHERE doubled > 10 (rows: 4)\n\
+         \x20   └── Scan calls (rows: 10)\n",
+        db.explain_select_query(query.clone())
+            .unwrap()
+            .to_ascii_tree()
+    );
+
+    query.scalar_calls[0].function = "triple".into();
+    assert!(db.run_select_query(query).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_table_sample() {
+    let dir = std::env::temp_dir().join("pbase_table_sample_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "sampled".into(),
+            fields: IndexMap::from([("field1".into(), FieldSchema::I32)]),
+            indices: IndexMap::from([("field1_index".into(), vec!["field1".into()])]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    for i in (0..400).rev() {
+        db.run_insert_query(&InsertQuery {
+            table: "sampled".into(),
+            values: HashMap::from([("field1".into(), Value::I32(i))]),
+        })
+        .unwrap();
+    }
+
+    let query = |sample| SelectQuery {
+        from: "sampled".into(),
+        sample: Some(sample),
+        ..Default::default()
+    };
+    let rows = |query| db.run_select_query(query).unwrap().len();
+
+    assert_eq!(0, rows(query(SampleSpec::Percent(0))));
+    assert_eq!(400, rows(query(SampleSpec::Percent(100))));
+    let half = rows(query(SampleSpec::Percent(50)));
+    assert!((120..280).contains(&half), "{half} rows sampled");
+
+    // The first rows of the data, not of the index.
+    let first = SelectQuery {
+        filters: vec![RowFilter {
+            field: FieldSelector {
+                name: "field1".into(),
+                source: "sampled".into(),
+            },
+            op: CompareOp::Gt,
+            rhs: RhsValue::Value(Value::I32(396)),
+        }],
+        ..query(SampleSpec::First(5))
+    };
+    assert_eq!(
+        vec![Value::I32(399), Value::I32(398), Value::I32(397)],
+        db.run_select_query(first.clone())
+            .unwrap()
+            .rows
+            .iter()
+            .map(|row| row["sampled.field1"].clone())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        "Filter sampled.field1 > 396 (rows: 2)\n\
+         └── Scan sampled TABLESAMPLE FIRST 5 (rows: 5)\n",
+        db.explain_select_query(first).unwrap().to_ascii_tree()
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_table_archive_export_import() {
+    let dir = std::env::temp_dir().join("pbase_archive_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("source")).unwrap();
+    std::fs::create_dir_all(dir.join("target")).unwrap();
+
+    let source = PBase::new(dir.join("source"));
+    source
+        .run_create_table_query(&CreateTableQuery {
+            schema: TableSchema {
+                name: "archived".into(),
+                fields: IndexMap::from([
+                    ("field1".into(), FieldSchema::I32),
+                    ("field2".into(), FieldSchema::U8),
+                ]),
+                indices: IndexMap::from([("field1_index".into(), vec!["field1".into()])]),
+                ..Default::default()
+            },
+        })
+        .unwrap();
+    for i in 0..20 {
+        source
+            .run_insert_query(&InsertQuery {
+                table: "archived".into(),
+                values: HashMap::from([
+                    ("field1".into(), Value::I32(-i)),
+                    (
+                        "field2".into(),
+                        if i % 2 == 0 {
+                            Value::U8(1)
+                        } else {
+                            Value::NULL
+                        },
+                    ),
+                ]),
+            })
+            .unwrap();
+    }
+
+    let archive_path = dir.join("archived.pbz");
+    let header = source
+        .export_table_archive("archived", &archive_path)
+        .unwrap();
+    assert_eq!(20, header.row_count);
+
+    let target = PBase::new(dir.join("target"));
+    assert_eq!(header, target.import_table_archive(&archive_path).unwrap());
+    assert!(target.import_table_archive(&archive_path).is_err());
+
+    let query = SelectQuery {
+        from: "archived".into(),
+        filters: vec![RowFilter {
+            field: FieldSelector {
+                name: "field1".into(),
+                source: "archived".into(),
+            },
+            op: CompareOp::Lt,
+            rhs: RhsValue::Value(Value::I32(-14)),
+        }],
+        ..Default::default()
+    };
+    assert!(target
+        .explain_select_query(query.clone())
+        .unwrap()
+        .to_ascii_tree()
+        .starts_with("IndexScan"));
+    assert_eq!(
+        source.run_select_query(query.clone()).unwrap(),
+        target.run_select_query(query).unwrap()
+    );
+
+    std::fs::write(dir.join("bad.pbz"), b"not an archive").unwrap();
+    assert!(target.import_table_archive(dir.join("bad.pbz")).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_traverse_edges() {
+    let dir = std::env::temp_dir().join("pbase_traverse_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "edges".into(),
+            fields: IndexMap::from([
+                ("src".into(), FieldSchema::U8),
+                ("dst".into(), FieldSchema::U8),
+            ]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    // 1 -> 2 -> 3 -> 1 (a cycle), 3 -> 4, 5 -> 6.
+    for (src, dst) in [(1, 2), (2, 3), (3, 1), (3, 4), (5, 6)] {
+        db.run_insert_query(&InsertQuery {
+            table: "edges".into(),
+            values: HashMap::from([
+                ("src".into(), Value::U8(src)),
+                ("dst".into(), Value::U8(dst)),
+            ]),
+        })
+        .unwrap();
+    }
+
+    let traverse = |start_values: &[Value], depth| {
+        db.traverse("edges", start_values, depth)
+            .unwrap()
+            .into_iter()
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(vec![(Value::U8(1), 0)], traverse(&[Value::I32(1)], 0));
+    assert_eq!(
+        vec![(Value::U8(1), 0), (Value::U8(2), 1), (Value::U8(3), 2)],
+        traverse(&[Value::U8(1)], 2)
+    );
+    assert_eq!(
+        vec![
+            (Value::U8(1), 0),
+            (Value::U8(2), 1),
+            (Value::U8(3), 2),
+            (Value::U8(4), 3)
+        ],
+        traverse(&[Value::U8(1)], 10)
+    );
+    assert_eq!(
+        vec![
+            (Value::U8(1), 1),
+            (Value::U8(2), 2),
+            (Value::U8(3), 0),
+            (Value::U8(4), 1),
+            (Value::U8(5), 0),
+            (Value::U8(6), 1),
+        ],
+        traverse(&[Value::U8(5), Value::U8(3)], 2)
+    );
+
+    assert!(db.traverse("edges", &[Value::I32(300)], 1).is_err());
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "not_edges".into(),
+            fields: IndexMap::from([("src".into(), FieldSchema::U8)]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    assert!(db.traverse("not_edges", &[Value::U8(1)], 1).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_select_into() {
+    let dir = std::env::temp_dir().join("pbase_select_into_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let mut db = PBase::new(dir.clone());
+    db.register_scalar("double", FieldSchema::I32, |value| match value {
+        Value::U8(v) => Value::I32(i32::from(*v) * 2),
+        _ => Value::NULL,
+    });
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "source".into(),
+            fields: IndexMap::from([
+                ("field1".into(), FieldSchema::I32),
+                ("field2".into(), FieldSchema::U8),
+            ]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    for i in 0..10 {
+        db.run_insert_query(&InsertQuery {
+            table: "source".into(),
+            values: HashMap::from([
+                ("field1".into(), Value::I32(-i)),
+                ("field2".into(), Value::U8(u8::try_from(i).unwrap())),
+            ]),
+        })
+        .unwrap();
+    }
+
+    let parse = |raw: &[u8]| {
+        let Query::Select(query) = Parser::new(&Lexer::tokenize(raw).unwrap()[..])
+            .parse()
+            .unwrap()
+        else {
+            panic!("expected a select");
+        };
+        query
+    };
+    let mut query = parse(b"SELECT INTO copied FROM source");
+    query.filters = vec![RowFilter {
+        field: FieldSelector {
+            name: "field2".into(),
+            source: "source".into(),
+        },
+        op: CompareOp::Gt,
+        rhs: RhsValue::Value(Value::U8(5)),
+    }];
+    assert!(db.run_select_query(query.clone()).is_err());
+
+    // Created from the result columns, then appended to.
+    assert_eq!(4, db.run_select_into_query(query.clone()).unwrap());
+    assert_eq!(
+        IndexMap::from([
+            ("field1".to_string(), FieldSchema::I32),
+            ("field2".to_string(), FieldSchema::U8),
+        ]),
+        db.table_schema("copied").unwrap().fields
+    );
+    assert_eq!(4, db.run_select_into_query(query).unwrap());
+    let copied = db
+        .run_select_query(SelectQuery {
+            from: "copied".into(),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(8, copied.len());
+    assert_eq!(Value::I32(-6), copied.rows[0]["copied.field1"]);
+    assert_eq!(Value::U8(9), copied.rows[7]["copied.field2"]);
+
+    assert_eq!(
+        10,
+        db.run_select_into_query(parse(
+            b"SELECT double(source.field2) AS doubled INTO doubles FROM source"
+        ))
+        .unwrap()
+    );
+    let doubles = db
+        .run_select_query(SelectQuery {
+            from: "doubles".into(),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(Value::I32(18), doubles.rows[9]["doubles.doubled"]);
+
+    // Columns of joined tables with the same field name.
+    let mut query = parse(b"SELECT INTO joined FROM source");
+    query.joins = vec![JoinContract {
+        join_type: JoinType::Inner,
+        null_keys_match: false,
+        lhs: FieldSelector {
+            name: "field1".into(),
+            source: "source".into(),
+        },
+        rhs: FieldSelector {
+            name: "field1".into(),
+            source: "copied".into(),
+        },
+    }];
+    assert!(db.run_select_into_query(query).is_err());
+    assert!(!db.is_table_exist("joined"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_recommend_indexes() {
+    let dir = std::env::temp_dir().join("pbase_recommend_indexes_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "advised".into(),
+            fields: IndexMap::from([
+                ("field1".into(), FieldSchema::I32),
+                ("field2".into(), FieldSchema::U8),
+            ]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    for i in 0..100 {
+        db.run_insert_query(&InsertQuery {
+            table: "advised".into(),
+            values: HashMap::from([
+                ("field1".into(), Value::I32(i)),
+                ("field2".into(), Value::U8(u8::try_from(i % 10).unwrap())),
+            ]),
+        })
+        .unwrap();
+    }
+
+    let filter = |name: &str, op, value| RowFilter {
+        field: FieldSelector {
+            name: name.into(),
+            source: "advised".into(),
+        },
+        op,
+        rhs: RhsValue::Value(value),
+    };
+    let query = SelectQuery {
+        from: "advised".into(),
+        filters: vec![
+            filter("field2", CompareOp::Eq, Value::U8(3)),
+            filter("field1", CompareOp::Lt, Value::I32(50)),
+        ],
+        ..Default::default()
+    };
+    assert!(db.recommend_indexes().unwrap().is_empty());
+    assert_eq!(5, db.run_select_query(query.clone()).unwrap().len());
+    db.run_select_query(query.clone()).unwrap();
+    db.run_select_query(SelectQuery {
+        from: "advised".into(),
+        ..Default::default()
+    })
+    .unwrap();
+
+    let recommendations = db.recommend_indexes().unwrap();
+    assert_eq!(1, recommendations.len());
+    assert_eq!("advised", recommendations[0].table);
+    assert_eq!(vec!["field2", "field1"], recommendations[0].fields);
+    assert_eq!(2, recommendations[0].queries);
+    // 100 rows, 10% of them match the equality and 30% of those the range.
+    assert_eq!(194, recommendations[0].estimated_rows_saved);
+
+    assert!(db.create_recommended_indexes(1000).unwrap().is_empty());
+    assert_eq!(recommendations, db.create_recommended_indexes(100).unwrap());
+    assert_eq!(
+        vec!["field2".to_string(), "field1".to_string()],
+        db.table_schema("advised").unwrap().indices["field2_field1_auto_index"]
+    );
+    assert!(db
+        .explain_select_query(query.clone())
+        .unwrap()
+        .to_ascii_tree()
+        .starts_with("IndexScan"));
+    assert_eq!(5, db.run_select_query(query).unwrap().len());
+    assert!(db.recommend_indexes().unwrap().is_empty());
+    assert!(db
+        .run_create_index_query("advised", "field2_field1_auto_index", &["field1".into()])
+        .is_err());
+    assert!(db
+        .run_create_index_query("advised", "other_index", &["field3".into()])
+        .is_err());
+
+    // Rows inserted after the index is built are indexed as well.
+    db.run_insert_query(&InsertQuery {
+        table: "advised".into(),
+        values: HashMap::from([
+            ("field1".into(), Value::I32(-1)),
+            ("field2".into(), Value::U8(3)),
+        ]),
+    })
+    .unwrap();
+    assert!(db.check_all().unwrap().is_ok());
+    let rows = db
+        .run_select_query(SelectQuery {
+            from: "advised".into(),
+            filters: vec![filter("field2", CompareOp::Eq, Value::U8(3))],
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(11, rows.len());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_unique_index_row_cache() {
+    let dir = std::env::temp_dir().join("pbase_unique_index_row_cache_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+    let schema = |unique_index: &str| TableSchema {
+        name: "users".into(),
+        fields: IndexMap::from([
+            ("id".into(), FieldSchema::I32),
+            ("age".into(), FieldSchema::U8),
+        ]),
+        indices: IndexMap::from([("id_index".into(), vec!["id".into()])]),
+        unique_indices: vec![unique_index.into()],
+        ..Default::default()
+    };
+    assert!(db
+        .run_create_table_query(&CreateTableQuery {
+            schema: schema("age_index"),
+        })
+        .is_err());
+    db.run_create_table_query(&CreateTableQuery {
+        schema: schema("id_index"),
+    })
+    .unwrap();
+
+    let insert = |id, age| {
+        db.run_insert_query(&InsertQuery {
+            table: "users".into(),
+            values: HashMap::from([
+                ("id".into(), Value::I32(id)),
+                ("age".into(), Value::U8(age)),
+            ]),
+        })
+    };
+    for id in 0..10 {
+        insert(id, 20).unwrap();
+    }
+    assert!(insert(3, 30).is_err());
+    assert!(db
+        .dry_run(&MutationQuery::Insert(InsertQuery {
+            table: "users".into(),
+            values: HashMap::from([("id".into(), Value::I32(3))]),
+        }))
+        .is_err());
+
+    let lookup = |id| SelectQuery {
+        from: "users".into(),
+        filters: vec![RowFilter {
+            field: FieldSelector {
+                name: "id".into(),
+                source: "users".into(),
+            },
+            op: CompareOp::Eq,
+            rhs: RhsValue::Value(Value::I32(id)),
+        }],
+        ..Default::default()
+    };
+    let age_of = |id| {
+        db.run_select_query(lookup(id))
+            .unwrap()
+            .rows
+            .first()
+            .map(|row| row["users.age"].clone())
+    };
+    assert_eq!(Some(Value::U8(20)), age_of(3));
+    assert_eq!(Some(Value::U8(20)), age_of(3));
+    assert_eq!(None, age_of(42));
+    assert_eq!(None, age_of(42));
+    assert_eq!(1, db.row_cache().hits());
+    assert_eq!(1, db.row_cache().len());
+
+    // Overwritten rows are invalidated.
+    db.update_row_at(
+        "users",
+        3 * 5,
+        &HashMap::from([("age".into(), Value::U8(33))]),
+    )
+    .unwrap();
+    assert!(db.row_cache().is_empty());
+    assert_eq!(Some(Value::U8(33)), age_of(3));
+    assert!(db
+        .update_row_at(
+            "users",
+            3 * 5,
+            &HashMap::from([("id".into(), Value::I32(4))])
+        )
+        .is_err());
+    db.update_row_at(
+        "users",
+        3 * 5,
+        &HashMap::from([("id".into(), Value::I32(42))]),
+    )
+    .unwrap();
+    assert_eq!(None, age_of(3));
+    assert_eq!(Some(Value::U8(33)), age_of(42));
+    assert_eq!(1, db.row_cache().hits());
+
+    let uncached = PBase::new(dir.clone()).with_row_cache_capacity(0);
+    uncached.run_select_query(lookup(42)).unwrap();
+    uncached.run_select_query(lookup(42)).unwrap();
+    assert_eq!(0, uncached.row_cache().hits());
+
+    // Writes of other handles move the table generation past the cached results.
+    assert_eq!(Some(Value::U8(33)), age_of(42));
+    assert_eq!(2, db.row_cache().hits());
+    let other = PBase::new(dir.clone());
+    other
+        .update_row_at(
+            "users",
+            3 * 5,
+            &HashMap::from([("age".into(), Value::U8(44))]),
+        )
+        .unwrap();
+    assert_eq!(Some(Value::U8(44)), age_of(42));
+    other
+        .run_delete_query(&DeleteQuery {
+            table: "users".into(),
+            filters: lookup(42).filters,
+        })
+        .unwrap();
+    assert_eq!(None, age_of(42));
+    assert_eq!(2, db.row_cache().hits());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_io_strategy() {
+    let dir = std::env::temp_dir().join("pbase_io_strategy_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+    for (table, io_strategy) in [("mapped", None), ("buffered", Some(IoStrategy::Buffered))] {
+        db.run_create_table_query(&CreateTableQuery {
+            schema: TableSchema {
+                name: table.into(),
+                fields: IndexMap::from([
+                    ("field1".into(), FieldSchema::I32),
+                    ("field2".into(), FieldSchema::U8),
+                ]),
+                indices: IndexMap::from([("field1_index".into(), vec!["field1".into()])]),
+                io_strategy,
+                ..Default::default()
+            },
+        })
+        .unwrap();
+    }
+
+    let query = |table: &str, field_name: &str, value| SelectQuery {
+        from: table.into(),
+        filters: vec![RowFilter {
+            field: FieldSelector {
+                name: field_name.into(),
+                source: table.into(),
+            },
+            op: CompareOp::Lt,
+            rhs: RhsValue::Value(value),
+        }],
+        ..Default::default()
+    };
+    let buffered = PBase::new(dir.clone()).with_io_strategy(IoStrategy::Buffered);
+    for table in ["mapped", "buffered"] {
+        assert!(db
+            .run_select_query(query(table, "field1", Value::I32(5)))
+            .unwrap()
+            .is_empty());
+
+        for i in 0..10 {
+            db.run_insert_query(&InsertQuery {
+                table: table.into(),
+                values: HashMap::from([
+                    ("field1".into(), Value::I32(9 - i)),
+                    ("field2".into(), Value::U8(u8::try_from(i).unwrap())),
+                ]),
+            })
+            .unwrap();
+        }
+
+        for db in [&db, &buffered] {
+            let by_index = db
+                .run_select_query(query(table, "field1", Value::I32(5)))
+                .unwrap();
+            assert_eq!(5, by_index.len());
+            let by_scan = db
+                .run_select_query(query(table, "field2", Value::U8(5)))
+                .unwrap();
+            assert_eq!(5, by_scan.len());
+        }
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_invalid_file_names() {
+    let dir = std::env::temp_dir().join("pbase_invalid_file_names_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+    let schema = |name: &str, index_name: &str| TableSchema {
+        name: name.into(),
+        fields: IndexMap::from([("field1".into(), FieldSchema::I32)]),
+        indices: IndexMap::from([(index_name.into(), vec!["field1".into()])]),
+        ..Default::default()
+    };
+    for (name, index_name) in [("aux", "index"), ("a/b", "index"), ("t1", "index?")] {
+        assert!(db
+            .run_create_table_query(&CreateTableQuery {
+                schema: schema(name, index_name),
+            })
+            .is_err());
+    }
+    assert_eq!(0, std::fs::read_dir(&dir).unwrap().count());
+
+    db.run_create_table_query(&CreateTableQuery {
+        schema: schema("t1", "index"),
+    })
+    .unwrap();
+    assert!(db
+        .run_create_index_query("t1", "index:2", &["field1".into()])
+        .is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_writer_lock_and_read_only() {
+    let dir = std::env::temp_dir().join("pbase_writer_lock_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let mut writer = PBase::new(dir.clone());
+    assert!(writer.try_lock_writer(Duration::ZERO).unwrap());
+    writer
+        .run_create_table_query(&CreateTableQuery {
+            schema: TableSchema {
+                name: "locked".into(),
+                fields: IndexMap::from([("field1".into(), FieldSchema::I32)]),
+                ..Default::default()
+            },
+        })
+        .unwrap();
+    let insert = InsertQuery {
+        table: "locked".into(),
+        values: HashMap::from([("field1".into(), Value::I32(1))]),
+    };
+    writer.run_insert_query(&insert).unwrap();
+
+    let mut other = PBase::new(dir.clone());
+    assert!(!other.try_lock_writer(Duration::from_millis(60)).unwrap());
+
+    let reader = other.with_read_only();
+    assert!(reader.is_read_only());
+    assert!(reader.run_insert_query(&insert).is_err());
+    assert!(reader.analyze_table("locked").is_err());
+    assert!(reader
+        .update_row_at(
+            "locked",
+            0,
+            &HashMap::from([("field1".into(), Value::I32(2))])
+        )
+        .is_err());
+    let all = SelectQuery {
+        from: "locked".into(),
+        ..Default::default()
+    };
+    assert_eq!(1, reader.run_select_query(all.clone()).unwrap().len());
+
+    drop(writer);
+    let mut next_writer = PBase::new(dir.clone());
+    assert!(next_writer.try_lock_writer(Duration::ZERO).unwrap());
+    next_writer.run_insert_query(&insert).unwrap();
+    assert_eq!(2, reader.run_select_query(all).unwrap().len());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// Records the progress calls of bulk operations.
+#[derive(Default)]
+struct RecordingProgress {
+    events: Mutex<Vec<String>>,
+}
+
+impl ProgressReporter for RecordingProgress {
+    fn start(&self, operation: &str, total: Option<usize>) {
+        self.events
+            .lock()
+            .unwrap()
+            .push(format!("start {operation} {total:?}"));
+    }
+
+    fn advance(&self, processed: usize) {
+        self.events
+            .lock()
+            .unwrap()
+            .push(format!("advance {processed}"));
+    }
+
+    fn finish(&self) {
+        self.events.lock().unwrap().push("finish".into());
+    }
+}
+
+#[test]
+fn test_progress_reporting() {
+    let dir = std::env::temp_dir().join("pbase_progress_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let progress = Arc::new(RecordingProgress::default());
+    let db = PBase::new(dir.clone()).with_progress_reporter(progress.clone());
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "progressed".into(),
+            fields: IndexMap::from([("field1".into(), FieldSchema::I32)]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    for i in 0..10 {
+        db.run_insert_query(&InsertQuery {
+            table: "progressed".into(),
+            values: HashMap::from([("field1".into(), Value::I32(i))]),
+        })
+        .unwrap();
+    }
+    let events = || std::mem::take(&mut *progress.events.lock().unwrap());
+    // Single row writes report nothing.
+    assert!(events().is_empty());
+
+    db.run_create_index_query("progressed", "field1_index", &["field1".into()])
+        .unwrap();
+    assert_eq!(
+        vec![
+            "start index build progressed.field1_index Some(10)",
+            "advance 0",
+            "finish"
+        ],
+        events()
+    );
+
+    db.analyze_table("progressed").unwrap();
+    assert_eq!(
+        vec!["start analyze progressed Some(10)", "advance 10", "finish"],
+        events()
+    );
+
+    assert_eq!(
+        10,
+        db.run_select_into_query(SelectQuery {
+            from: "progressed".into(),
+            into: Some("progressed_copy".into()),
+            ..Default::default()
+        })
+        .unwrap()
+    );
+    assert_eq!(
+        vec!["start select into progressed_copy None", "finish"],
+        events()
+    );
+
+    db.merge_index_deltas().unwrap();
+    assert_eq!(
+        vec!["start index merge Some(0)", "advance 0", "finish"],
+        events()
+    );
+
+    assert!(db.check_all().unwrap().is_ok());
+    assert_eq!(
+        vec![
+            "start consistency check None",
+            "advance 10",
+            "advance 20",
+            "finish"
+        ],
+        events()
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_corrupt_index() {
+    let dir = std::env::temp_dir().join("pbase_corrupt_index_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "corrupted".into(),
+            fields: IndexMap::from([("field1".into(), FieldSchema::I32)]),
+            indices: IndexMap::from([("field1_index".into(), vec!["field1".into()])]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    for i in 0..10 {
+        db.run_insert_query(&InsertQuery {
+            table: "corrupted".into(),
+            values: HashMap::from([("field1".into(), Value::I32(i))]),
+        })
+        .unwrap();
+    }
+    db.merge_index_deltas().unwrap();
+
+    let query = || SelectQuery {
+        from: "corrupted".into(),
+        filters: vec![RowFilter {
+            field: FieldSelector {
+                name: "field1".into(),
+                source: "corrupted".into(),
+            },
+            op: CompareOp::Gt,
+            rhs: RhsValue::Value(Value::I32(2)),
+        }],
+        ..Default::default()
+    };
+    assert_eq!(7, db.run_select_query(query()).unwrap().len());
+
+    let index_file_name =
+        TableOpener::new(dir.clone()).index_file_name("corrupted", "field1_index");
+    let index_bytes = std::fs::read(&index_file_name).unwrap();
+    let is_corrupt_index = |err: pbase::common::Error| {
+        matches!(
+            err.downcast_ref::<PBaseError>(),
+            Some(PBaseError::CorruptIndex { table, index, .. })
+                if table == "corrupted" && index == "field1_index"
+        )
+    };
+
+    // Truncated.
+    std::fs::write(&index_file_name, &index_bytes[..index_bytes.len() - 1]).unwrap();
+    assert!(is_corrupt_index(db.run_select_query(query()).unwrap_err()));
+
+    // Row pointers past the end of the data, and between rows.
+    for row_ptr in [u64::MAX, 1_000, 3] {
+        let mut garbage = index_bytes.clone();
+        let last_row_ptr_pos = garbage.len() - 8;
+        garbage[last_row_ptr_pos..].copy_from_slice(&row_ptr.to_le_bytes());
+        std::fs::write(&index_file_name, &garbage).unwrap();
+        assert!(is_corrupt_index(db.run_select_query(query()).unwrap_err()));
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_quota() {
+    let dir = std::env::temp_dir().join("pbase_quota_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let create_table = |db: &PBase, name: &str| {
+        db.run_create_table_query(&CreateTableQuery {
+            schema: TableSchema {
+                name: name.into(),
+                fields: IndexMap::from([("field1".into(), FieldSchema::I32)]),
+                ..Default::default()
+            },
+        })
+        .unwrap();
+    };
+    let insert = |db: &PBase, name: &str| {
+        db.run_insert_query(&InsertQuery {
+            table: name.into(),
+            values: HashMap::from([("field1".into(), Value::I32(1))]),
+        })
+    };
+    let quota_error = |err: pbase::common::Error| *err.downcast::<PBaseError>().unwrap();
+
+    let db = PBase::new(dir.clone()).with_quota(Quota {
+        max_table_rows: Some(3),
+        ..Default::default()
+    });
+    create_table(&db, "rows_capped");
+    for _ in 0..3 {
+        insert(&db, "rows_capped").unwrap();
+    }
+    assert!(matches!(
+        quota_error(insert(&db, "rows_capped").unwrap_err()),
+        PBaseError::RowCountQuotaExceeded { table, limit: 3 } if table == "rows_capped"
+    ));
+
+    // An import over the quota is rejected before creating the table.
+    let archive_file_name = dir.join("rows_capped.pba");
+    db.export_table_archive("rows_capped", &archive_file_name)
+        .unwrap();
+    let db = PBase::new(dir.join("imported")).with_quota(Quota {
+        max_table_rows: Some(2),
+        ..Default::default()
+    });
+    std::fs::create_dir_all(dir.join("imported")).unwrap();
+    assert!(matches!(
+        quota_error(db.import_table_archive(&archive_file_name).unwrap_err()),
+        PBaseError::RowCountQuotaExceeded { limit: 2, .. }
+    ));
+    assert!(!db.is_table_exist("rows_capped"));
+
+    // 4 byte rows.
+    let db = PBase::new(dir.clone()).with_quota(Quota {
+        max_table_bytes: Some(10),
+        ..Default::default()
+    });
+    create_table(&db, "bytes_capped");
+    insert(&db, "bytes_capped").unwrap();
+    insert(&db, "bytes_capped").unwrap();
+    assert!(matches!(
+        quota_error(insert(&db, "bytes_capped").unwrap_err()),
+        PBaseError::TableSizeQuotaExceeded { table, limit: 10 } if table == "bytes_capped"
+    ));
+
+    // Already over, the WAL and schema files count too.
+    let db = PBase::new(dir.clone()).with_quota(Quota {
+        max_dir_bytes: Some(100),
+        ..Default::default()
+    });
+    assert!(matches!(
+        quota_error(insert(&db, "bytes_capped").unwrap_err()),
+        PBaseError::DirectorySizeQuotaExceeded { limit: 100 }
+    ));
+    assert_eq!(
+        2,
+        db.run_select_query(SelectQuery {
+            from: "bytes_capped".into(),
+            ..Default::default()
+        })
+        .unwrap()
+        .len()
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_versioned_table() {
+    let dir = std::env::temp_dir().join("pbase_versioned_table_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone()).with_insert_mode(InsertMode::Strict);
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "versioned".into(),
+            fields: IndexMap::from([("field1".into(), FieldSchema::I32)]),
+            versioned: true,
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    // The version is not passed to inserts, even strict ones.
+    db.run_insert_query(&InsertQuery {
+        table: "versioned".into(),
+        values: HashMap::from([("field1".into(), Value::I32(10))]),
+    })
+    .unwrap();
+
+    let row = || {
+        db.run_select_query(SelectQuery {
+            from: "versioned".into(),
+            ..Default::default()
+        })
+        .unwrap()
+        .rows
+        .remove(0)
+    };
+    assert_eq!(Value::I32(1), row()["versioned._version"]);
+
+    let update = |field1, version| {
+        db.update_row_at(
+            "versioned",
+            0,
+            &HashMap::from([
+                ("field1".into(), Value::I32(field1)),
+                ("_version".into(), Value::I32(version)),
+            ]),
+        )
+    };
+    update(11, 1).unwrap();
+    assert_eq!(Value::I32(11), row()["versioned.field1"]);
+    assert_eq!(Value::I32(2), row()["versioned._version"]);
+
+    // A concurrent edit of the version 1 row lost the race.
+    let err = update(12, 1).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<PBaseError>(),
+        Some(PBaseError::VersionConflict {
+            expected: 1,
+            actual: 2,
+            ..
+        })
+    ));
+    assert!(db
+        .dry_run(&MutationQuery::UpdateRowAt {
+            table: "versioned".into(),
+            row_pos: 0,
+            values: HashMap::from([("_version".into(), Value::I32(1))]),
+        })
+        .is_err());
+    let err = db
+        .update_row_at(
+            "versioned",
+            0,
+            &HashMap::from([("field1".into(), Value::I32(12))]),
+        )
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<PBaseError>(),
+        Some(PBaseError::MissingField(field)) if field == "_version"
+    ));
+    assert_eq!(Value::I32(11), row()["versioned.field1"]);
+
+    update(12, 2).unwrap();
+    assert_eq!(Value::I32(3), row()["versioned._version"]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_soft_delete() {
+    let dir = std::env::temp_dir().join("pbase_soft_delete_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+    for (name, deleted_schema) in [("flagged", FieldSchema::U8), ("stamped", FieldSchema::I32)] {
+        db.run_create_table_query(&CreateTableQuery {
+            schema: TableSchema {
+                name: name.into(),
+                fields: IndexMap::from([
+                    ("field1".into(), FieldSchema::I32),
+                    ("deleted".into(), deleted_schema),
+                ]),
+                indices: IndexMap::from([("field1_index".into(), vec!["field1".into()])]),
+                soft_delete_column: Some("deleted".into()),
+                versioned: name == "stamped",
+                ..Default::default()
+            },
+        })
+        .unwrap();
+        for i in 0..5 {
+            db.run_insert_query(&InsertQuery {
+                table: name.into(),
+                values: HashMap::from([("field1".into(), Value::I32(i))]),
+            })
+            .unwrap();
+        }
+    }
+
+    let filter = |table: &str, op, value| RowFilter {
+        field: FieldSelector {
+            name: "field1".into(),
+            source: table.into(),
+        },
+        op,
+        rhs: RhsValue::Value(Value::I32(value)),
+    };
+    let field1s = |table: &str, with_deleted| -> Vec<Value> {
+        db.run_select_query(SelectQuery {
+            from: table.into(),
+            with_deleted,
+            ..Default::default()
+        })
+        .unwrap()
+        .rows
+        .into_iter()
+        .map(|row| row[format!("{table}.field1").as_str()].clone())
+        .collect()
+    };
+
+    for table in ["flagged", "stamped"] {
+        let delete_query = DeleteQuery {
+            table: table.into(),
+            filters: vec![filter(table, CompareOp::Gt, 2)],
+        };
+        assert_eq!(2, db.run_delete_query(&delete_query).unwrap().modified);
+        assert_eq!(
+            vec![Value::I32(0), Value::I32(1), Value::I32(2)],
+            field1s(table, false)
+        );
+        assert_eq!(5, field1s(table, true).len());
+        // Already deleted.
+        assert_eq!(0, db.run_delete_query(&delete_query).unwrap().matched);
+
+        // Index scans skip them too.
+        let index_query = |with_deleted| SelectQuery {
+            from: table.into(),
+            filters: vec![filter(table, CompareOp::Eq, 4)],
+            with_deleted,
+            ..Default::default()
+        };
+        assert!(db.run_select_query(index_query(false)).unwrap().is_empty());
+        assert_eq!(1, db.run_select_query(index_query(true)).unwrap().len());
+    }
+
+    let deleted_row = db
+        .run_select_query(
+            Parser::new(&Lexer::tokenize(b"SELECT FROM stamped WITH DELETED").unwrap())
+                .parse()
+                .map(|query| match query {
+                    Query::Select(select_query) => select_query,
+                    _ => panic!("expected a select query"),
+                })
+                .unwrap(),
+        )
+        .unwrap()
+        .rows
+        .remove(4);
+    assert!(matches!(deleted_row["stamped.deleted"], Value::I32(time) if time > 0));
+    assert_eq!(Value::I32(2), deleted_row["stamped._version"]);
+
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "plain".into(),
+            fields: IndexMap::from([("field1".into(), FieldSchema::I32)]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    // Without a soft delete column rows are removed, see `test_hard_delete`.
+    assert_eq!(
+        0,
+        db.run_delete_query(&DeleteQuery {
+            table: "plain".into(),
+            filters: vec![],
+        })
+        .unwrap()
+        .matched
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_audit_table() {
+    let dir = std::env::temp_dir().join("pbase_audit_table_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone()).with_audit_actor(42);
+    for (name, audited) in [("audited", true), ("unaudited", false)] {
+        db.run_create_table_query(&CreateTableQuery {
+            schema: TableSchema {
+                name: name.into(),
+                fields: IndexMap::from([
+                    ("field1".into(), FieldSchema::I32),
+                    ("field2".into(), FieldSchema::U8),
+                ]),
+                audited,
+                ..Default::default()
+            },
+        })
+        .unwrap();
+        db.run_insert_query(&InsertQuery {
+            table: name.into(),
+            values: HashMap::from([
+                ("field1".into(), Value::I32(1)),
+                ("field2".into(), Value::U8(2)),
+            ]),
+        })
+        .unwrap();
+    }
+    db.update_row_at(
+        "audited",
+        0,
+        &HashMap::from([
+            ("field1".into(), Value::I32(10)),
+            ("field2".into(), Value::U8(2)),
+        ]),
+    )
+    .unwrap();
+
+    let audit_rows = db
+        .run_select_query(SelectQuery {
+            from: AUDIT_TABLE.into(),
+            ..Default::default()
+        })
+        .unwrap()
+        .rows;
+    let column =
+        |row: usize, name: &str| audit_rows[row][format!("{AUDIT_TABLE}.{name}").as_str()].clone();
+    // Both inserted columns, then the changed one.
+    assert_eq!(3, audit_rows.len());
+    for row in 0..3 {
+        assert_eq!(Value::I32(42), column(row, "actor"));
+        assert_eq!(
+            Value::I32(audit_table_id("audited")),
+            column(row, "table_id")
+        );
+        assert_eq!(Value::I32(0), column(row, "row_pos"));
+    }
+    assert_eq!(
+        vec![Value::U8(0), Value::U8(0), Value::U8(1)],
+        (0..3).map(|row| column(row, "op")).collect::<Vec<_>>()
+    );
+    assert_eq!(column(0, "lsn"), column(1, "lsn"));
+    assert_ne!(column(0, "lsn"), column(2, "lsn"));
+    assert_eq!(Value::I32(0), column(2, "column"));
+    assert_eq!(Value::I32(1), column(2, "old_value"));
+    assert_eq!(Value::I32(10), column(2, "new_value"));
+
+    // Append-only.
+    assert!(db
+        .run_insert_query(&InsertQuery {
+            table: AUDIT_TABLE.into(),
+            values: HashMap::new(),
+        })
+        .is_err());
+    assert!(db.update_row_at(AUDIT_TABLE, 0, &HashMap::new()).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_describe_table() {
+    let dir = std::env::temp_dir().join("pbase_describe_table_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "described".into(),
+            fields: IndexMap::from([
+                ("field1".into(), FieldSchema::I32),
+                ("field2".into(), FieldSchema::U8),
+            ]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    for i in 0..20 {
+        db.run_insert_query(&InsertQuery {
+            table: "described".into(),
+            values: HashMap::from([
+                ("field1".into(), Value::I32(i - 5)),
+                ("field2".into(), Value::U8(u8::try_from(i % 4).unwrap())),
+            ]),
+        })
+        .unwrap();
+    }
+
+    let columns = db.describe_table("described").unwrap();
+    assert_eq!(
+        vec!["field1", "field2"],
+        columns
+            .iter()
+            .map(|column| column.name.as_str())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(FieldSchema::U8, columns[1].field_schema);
+    assert!(columns.iter().all(|column| column.stats.is_none()));
+
+    db.analyze_table("described").unwrap();
+    let columns = db.describe_table("described").unwrap();
+    let stats = columns[0].stats.as_ref().unwrap();
+    assert_eq!(Value::I32(-5), stats.min);
+    assert_eq!(Value::I32(14), stats.max);
+    assert_eq!(0, stats.null_count);
+    assert_eq!(20, stats.distinct_estimate);
+    assert_eq!(4, columns[1].stats.as_ref().unwrap().distinct_estimate);
+
+    assert!(db.describe_table("missing").is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_new_temp() {
+    let create_table = |db: &PBase| {
+        db.run_create_table_query(&CreateTableQuery {
+            schema: TableSchema {
+                name: "t1".into(),
+                fields: IndexMap::from([("field1".into(), FieldSchema::I32)]),
+                ..Default::default()
+            },
+        })
+    };
+
+    // Same table names, separate directories.
+    let db_1 = PBase::new_temp().unwrap();
+    let db_2 = PBase::new_temp().unwrap();
+    assert_ne!(db_1.dir(), db_2.dir());
+    create_table(&db_1).unwrap();
+    create_table(&db_2).unwrap();
+    assert_eq!(vec!["t1"], db_2.table_names().unwrap());
+
+    let dir = db_1.dir().to_path_buf();
+    assert!(dir.join("t1.pbd").exists());
+    drop(db_1);
+    assert!(!dir.exists());
+    assert!(db_2.dir().exists());
+}
+
+#[test]
+fn test_insert_returning() {
+    let db = PBase::new_temp().unwrap();
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "returned".into(),
+            fields: IndexMap::from([
+                ("field1".into(), FieldSchema::I32),
+                ("field2".into(), FieldSchema::U8),
+            ]),
+            versioned: true,
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    let insert_query = |field1| InsertQuery {
+        table: "returned".into(),
+        values: HashMap::from([("field1".into(), Value::I32(field1))]),
+    };
+
+    // Defaults and the version are filled in.
+    let inserted = db
+        .run_insert_query_returning(&insert_query(7), &[])
+        .unwrap();
+    assert_eq!(0, inserted.row_pos);
+    assert_eq!(
+        vec!["returned.field1", "returned.field2", "returned._version"],
+        inserted
+            .result
+            .columns
+            .iter()
+            .map(|column| column.name.as_str())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        vec![Value::I32(7), Value::U8(0), Value::I32(1)],
+        inserted.result.rows[0]
+            .values()
+            .cloned()
+            .collect::<Vec<_>>()
+    );
+
+    let inserted = db
+        .run_insert_query_returning(&insert_query(8), &["field1".into()])
+        .unwrap();
+    let row_byte_size =
+        u64::try_from(db.table_schema("returned").unwrap().row_byte_size()).unwrap();
+    assert_eq!(row_byte_size, inserted.row_pos);
+    assert_eq!(1, inserted.result.columns.len());
+    assert_eq!(Value::I32(8), inserted.result.rows[0]["returned.field1"]);
+
+    // Unknown fields fail before inserting.
+    let err = db
+        .run_insert_query_returning(&insert_query(9), &["missing".into()])
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<PBaseError>(),
+        Some(PBaseError::UnknownField(_))
+    ));
+    let rows = db
+        .run_select_query(SelectQuery {
+            from: "returned".into(),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(2, rows.len());
+}
+
+#[test]
+fn test_mutation_result() {
+    let db = PBase::new_temp().unwrap();
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "mutated".into(),
+            fields: IndexMap::from([
+                ("field1".into(), FieldSchema::I32),
+                ("field2".into(), FieldSchema::U8),
+                ("deleted".into(), FieldSchema::U8),
+            ]),
+            indices: IndexMap::from([("field1_index".into(), vec!["field1".into()])]),
+            soft_delete_column: Some("deleted".into()),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    for i in 0..3 {
+        db.run_insert_query(&InsertQuery {
+            table: "mutated".into(),
+            values: HashMap::from([("field1".into(), Value::I32(i))]),
+        })
+        .unwrap();
+    }
+    let row_byte_size = db.table_schema("mutated").unwrap().row_byte_size();
+    let index_byte_size = db
+        .table_schema("mutated")
+        .unwrap()
+        .index_row_byte_size("field1_index");
+
+    // An indexed field moves the index entry, rewriting the index.
+    let result = db
+        .update_row_at(
+            "mutated",
+            0,
+            &HashMap::from([("field1".into(), Value::I32(10))]),
+        )
+        .unwrap();
+    assert_eq!(
+        MutationResult {
+            matched: 1,
+            modified: 1,
+            index_entries: 1,
+            bytes_written: row_byte_size + 3 * index_byte_size,
+        },
+        result
+    );
+
+    // Same value: matched, not modified.
+    let result = db
+        .update_row_at(
+            "mutated",
+            0,
+            &HashMap::from([("field2".into(), Value::U8(0))]),
+        )
+        .unwrap();
+    assert_eq!(
+        (1, 0, 0),
+        (result.matched, result.modified, result.index_entries)
+    );
+
+    let result = db
+        .run_delete_query(&DeleteQuery {
+            table: "mutated".into(),
+            filters: vec![],
+        })
+        .unwrap();
+    assert_eq!(
+        MutationResult {
+            matched: 3,
+            modified: 3,
+            index_entries: 0,
+            bytes_written: 3 * row_byte_size,
+        },
+        result
+    );
+    assert_eq!(
+        "3 rows matched, 3 modified, 0 index entries moved, 18 bytes written",
+        result.to_string()
+    );
+}
+
+#[test]
+fn test_schema_diff() {
+    let deployed = PBase::new_temp().unwrap();
+    let expected = PBase::new_temp().unwrap();
+    let table_schema = |fields: IndexMap<String, FieldSchema>, indexed: bool| TableSchema {
+        name: "drifted".into(),
+        indices: if indexed {
+            IndexMap::from([("field1_index".into(), vec!["field1".into()])])
+        } else {
+            IndexMap::new()
+        },
+        fields,
+        ..Default::default()
+    };
+    deployed
+        .run_create_table_query(&CreateTableQuery {
+            schema: table_schema(IndexMap::from([("field1".into(), FieldSchema::I32)]), false),
+        })
+        .unwrap();
+    assert!(deployed.schema_diff(deployed.dir()).unwrap().is_empty());
+
+    expected
+        .run_create_table_query(&CreateTableQuery {
+            schema: table_schema(
+                IndexMap::from([
+                    ("field1".into(), FieldSchema::I32),
+                    ("field2".into(), FieldSchema::U8),
+                ]),
+                true,
+            ),
+        })
+        .unwrap();
+    expected
+        .run_create_table_query(&CreateTableQuery {
+            schema: TableSchema {
+                name: "added".into(),
+                fields: IndexMap::from([("field1".into(), FieldSchema::U8)]),
+                ..Default::default()
+            },
+        })
+        .unwrap();
+
+    assert_eq!(
+        vec![
+            "ALTER TABLE drifted ADD COLUMN field2 U8",
+            "CREATE INDEX field1_index ON drifted (field1)",
+            "CREATE TABLE added (field1 U8)",
+        ],
+        deployed
+            .schema_diff(expected.dir())
+            .unwrap()
+            .alter_statements()
+    );
+
+    // A single schema file is compared to the table of its name only.
+    let diff = deployed
+        .schema_diff(&expected.dir().join("drifted.pbs"))
+        .unwrap();
+    assert_eq!(2, diff.changes.len());
+
+    assert!(deployed
+        .schema_diff(&expected.dir().join("missing.pbs"))
+        .is_err());
+}
+
+#[test]
+fn test_rename_and_clone_table() {
+    let db = PBase::new_temp().unwrap();
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "blue".into(),
+            fields: IndexMap::from([("field1".into(), FieldSchema::I32)]),
+            indices: IndexMap::from([("field1_index".into(), vec!["field1".into()])]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    for i in 0..5 {
+        db.run_insert_query(&InsertQuery {
+            table: "blue".into(),
+            values: HashMap::from([("field1".into(), Value::I32(i))]),
+        })
+        .unwrap();
+    }
+    db.analyze_table("blue").unwrap();
+    let field1_query = |table: &str, value| SelectQuery {
+        from: table.into(),
+        filters: vec![RowFilter {
+            field: FieldSelector {
+                name: "field1".into(),
+                source: table.into(),
+            },
+            op: CompareOp::Eq,
+            rhs: RhsValue::Value(Value::I32(value)),
+        }],
+        ..Default::default()
+    };
+
+    db.clone_table("blue", "green").unwrap();
+    db.run_insert_query(&InsertQuery {
+        table: "green".into(),
+        values: HashMap::from([("field1".into(), Value::I32(3))]),
+    })
+    .unwrap();
+    assert_eq!(
+        1,
+        db.run_select_query(field1_query("blue", 3)).unwrap().len()
+    );
+    assert_eq!(
+        2,
+        db.run_select_query(field1_query("green", 3)).unwrap().len()
+    );
+    assert_eq!("green", db.table_schema("green").unwrap().name);
+
+    // Switch over, the old table goes away with all its files.
+    db.rename_table("blue", "retired").unwrap();
+    db.rename_table("green", "blue").unwrap();
+    assert_eq!(vec!["blue", "retired"], db.table_names().unwrap());
+    assert!(db.describe_table("retired").unwrap()[0].stats.is_some());
+    assert_eq!(
+        2,
+        db.run_select_query(field1_query("blue", 3)).unwrap().len()
+    );
+    assert!(!db.dir().join("green__field1_index.pbi").exists());
+    assert!(!db.dir().join("green.pbd").exists());
+
+    for (from, to) in [
+        ("missing", "t1"),
+        ("blue", "retired"),
+        ("blue", "pbase_tables"),
+    ] {
+        assert!(db.rename_table(from, to).is_err());
+    }
+    assert!(db.clone_table("blue", "a/b").is_err());
+    assert_eq!(vec!["blue", "retired"], db.table_names().unwrap());
+}
+
+#[test]
+fn test_copy_rows() {
+    let db = PBase::new_temp().unwrap();
+    let table_schema = TableSchema {
+        name: "bulk".into(),
+        fields: IndexMap::from([
+            ("id".into(), FieldSchema::I32),
+            ("kind".into(), FieldSchema::U8),
+        ]),
+        indices: IndexMap::from([
+            ("id_index".into(), vec!["id".into()]),
+            ("kind_index".into(), vec!["kind".into()]),
+        ]),
+        unique_indices: vec!["id_index".into()],
+        ..Default::default()
+    };
+    db.run_create_table_query(&CreateTableQuery {
+        schema: table_schema.clone(),
+    })
+    .unwrap();
+    db.run_insert_query(&InsertQuery {
+        table: "bulk".into(),
+        values: HashMap::from([("id".into(), Value::I32(0)), ("kind".into(), Value::U8(1))]),
+    })
+    .unwrap();
+    let copy_stream = |ids: &[i32]| {
+        let mut writer =
+            CopyWriter::new(vec![], CopyHeader::new(&table_schema, ids.len())).unwrap();
+        for id in ids {
+            writer
+                .write_row_bytes(&table_schema.data_row_to_bytes(&HashMap::from([
+                    ("id".into(), Value::I32(*id)),
+                    ("kind".into(), Value::U8(u8::try_from(id % 2).unwrap())),
+                ])))
+                .unwrap();
+        }
+        writer.finish().unwrap()
+    };
+    let kind_query = |kind| SelectQuery {
+        from: "bulk".into(),
+        filters: vec![RowFilter {
+            field: FieldSelector {
+                name: "kind".into(),
+                source: "bulk".into(),
+            },
+            op: CompareOp::Eq,
+            rhs: RhsValue::Value(Value::U8(kind)),
+        }],
+        ..Default::default()
+    };
+
+    assert_eq!(
+        4,
+        db.copy_rows("bulk", &copy_stream(&[4, 3, 2, 1])[..])
+            .unwrap()
+    );
+    assert_eq!(3, db.run_select_query(kind_query(1)).unwrap().len());
+    assert_eq!(2, db.run_select_query(kind_query(0)).unwrap().len());
+    // Entries went straight into the sorted index.
+    assert!(!db.dir().join("bulk__kind_index.pbx").exists());
+    assert!(db.check_all().unwrap().is_ok());
+
+    // Nothing is loaded on a duplicate key, of the table or of the stream.
+    for ids in [&[5, 3][..], &[5, 6, 5][..]] {
+        let err = db.copy_rows("bulk", &copy_stream(ids)[..]).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<PBaseError>(),
+            Some(PBaseError::DuplicateKey { .. })
+        ));
+    }
+    let mut other_schema = table_schema.clone();
+    other_schema.fields.swap_indices(0, 1);
+    let writer = CopyWriter::new(vec![], CopyHeader::new(&other_schema, 0)).unwrap();
+    let err = db
+        .copy_rows("bulk", &writer.finish().unwrap()[..])
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<PBaseError>(),
+        Some(PBaseError::InvalidCopyStream(_))
+    ));
+    assert_eq!(
+        5,
+        db.run_select_query(SelectQuery {
+            from: "bulk".into(),
+            ..Default::default()
+        })
+        .unwrap()
+        .len()
+    );
+}
+
+#[test]
+fn test_dictionary_encoding() {
+    let db = PBase::new_temp().unwrap();
+    let table_schema = TableSchema {
+        name: "events".into(),
+        fields: IndexMap::from([
+            ("id".into(), FieldSchema::I32),
+            ("kind".into(), FieldSchema::I32),
+            ("color".into(), FieldSchema::I32),
+            ("flag".into(), FieldSchema::U8),
+        ]),
+        indices: IndexMap::from([
+            ("id_index".into(), vec!["id".into()]),
+            ("kind_index".into(), vec!["kind".into()]),
+        ]),
+        unique_indices: vec!["id_index".into()],
+        ..Default::default()
+    };
+    db.run_create_table_query(&CreateTableQuery {
+        schema: table_schema.clone(),
+    })
+    .unwrap();
+    let copy_stream = |ids: std::ops::Range<i32>| {
+        let mut writer =
+            CopyWriter::new(vec![], CopyHeader::new(&table_schema, ids.len())).unwrap();
+        for id in ids {
+            writer
+                .write_row_bytes(&table_schema.data_row_to_bytes(&HashMap::from([
+                    ("id".into(), Value::I32(id)),
+                    ("kind".into(), Value::I32(id % 4 * 1000)),
+                    ("color".into(), Value::I32(-(id % 3))),
+                    ("flag".into(), Value::U8(1)),
+                ])))
+                .unwrap();
+        }
+        writer.finish().unwrap()
+    };
+    let count = |field: &str, value: i32| {
+        db.run_select_query(SelectQuery {
+            from: "events".into(),
+            filters: vec![RowFilter {
+                field: FieldSelector {
+                    name: field.into(),
+                    source: "events".into(),
+                },
+                op: CompareOp::Eq,
+                rhs: RhsValue::Value(Value::I32(value)),
+            }],
+            ..Default::default()
+        })
+        .unwrap()
+        .len()
+    };
+    db.copy_rows("events", &copy_stream(0..1000)[..]).unwrap();
+
+    // ANALYZE leaves the table as it is, compaction encodes it.
+    db.analyze_table("events").unwrap();
+    assert!(db
+        .table_schema("events")
+        .unwrap()
+        .dictionary_columns
+        .is_empty());
+    assert_eq!(vec!["kind", "color"], db.compact_table("events").unwrap());
+    let encoded_schema = db.table_schema("events").unwrap();
+    // Ids are distinct, and U8 fields are a byte already.
+    assert_eq!(vec!["kind", "color"], encoded_schema.dictionary_columns);
+    assert_eq!(7, encoded_schema.row_byte_size());
+    assert_eq!(
+        7000,
+        std::fs::metadata(db.dir().join("events.pbd"))
+            .unwrap()
+            .len()
+    );
+    assert!(db.dir().join("events.pbv").exists());
+    assert_eq!(250, count("kind", 2000));
+    assert_eq!(333, count("color", -2));
+    assert_eq!(0, count("color", 7));
+    assert!(db.check_all().unwrap().is_ok());
+
+    // New values extend the dictionaries.
+    db.copy_rows("events", &copy_stream(1000..1004)[..])
+        .unwrap();
+    let inserted = db
+        .run_insert_query_returning(
+            &InsertQuery {
+                table: "events".into(),
+                values: HashMap::from([
+                    ("id".into(), Value::I32(-1)),
+                    ("kind".into(), Value::I32(9)),
+                ]),
+            },
+            &[],
+        )
+        .unwrap();
+    db.update_row_at(
+        "events",
+        inserted.row_pos,
+        &HashMap::from([("color".into(), Value::I32(7))]),
+    )
+    .unwrap();
+    assert_eq!(1, count("kind", 9));
+    assert_eq!(1, count("color", 7));
+    assert_eq!(251, count("kind", 2000));
+    assert_eq!(
+        vec!["kind", "color"],
+        db.table_schema("events").unwrap().dictionary_columns
+    );
+
+    // A field outgrowing its dictionary is stored as its type again.
+    for id in 0..300 {
+        db.run_insert_query(&InsertQuery {
+            table: "events".into(),
+            values: HashMap::from([
+                ("id".into(), Value::I32(2000 + id)),
+                ("color".into(), Value::I32(100 + id)),
+            ]),
+        })
+        .unwrap();
+    }
+    let decoded_schema = db.table_schema("events").unwrap();
+    assert_eq!(vec!["kind"], decoded_schema.dictionary_columns);
+    assert_eq!(
+        1305 * 10,
+        db.dir().join("events.pbd").metadata().unwrap().len()
+    );
+    assert_eq!(1, count("color", 7));
+    assert_eq!(1, count("color", 399));
+    assert_eq!(551, count("kind", 0));
+    assert!(db.check_all().unwrap().is_ok());
+}
+
+#[test]
+fn test_hard_delete() {
+    let db = PBase::new_temp().unwrap();
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "items".into(),
+            fields: IndexMap::from([
+                ("id".into(), FieldSchema::I32),
+                ("group".into(), FieldSchema::U8),
+            ]),
+            indices: IndexMap::from([
+                ("id_index".into(), vec!["id".into()]),
+                ("group_index".into(), vec!["group".into()]),
+            ]),
+            unique_indices: vec!["id_index".into()],
+            audited: true,
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    for id in 0..10 {
+        db.run_insert_query(&InsertQuery {
+            table: "items".into(),
+            values: HashMap::from([
+                ("id".into(), Value::I32(id)),
+                ("group".into(), Value::U8(u8::try_from(id % 3).unwrap())),
+            ]),
+        })
+        .unwrap();
+    }
+    let filter = |name: &str, op, value| RowFilter {
+        field: FieldSelector {
+            name: name.into(),
+            source: "items".into(),
+        },
+        op,
+        rhs: RhsValue::Value(value),
+    };
+    let ids = |filters: Vec<RowFilter>| -> Vec<Value> {
+        db.run_select_query(SelectQuery {
+            from: "items".into(),
+            filters,
+            ..Default::default()
+        })
+        .unwrap()
+        .rows
+        .into_iter()
+        .map(|row| row["items.id"].clone())
+        .collect()
+    };
+
+    // Found through the group index, out of row order.
+    let result = db
+        .run_delete_query(&DeleteQuery {
+            table: "items".into(),
+            filters: vec![filter("group", CompareOp::Eq, Value::U8(1))],
+        })
+        .unwrap();
+    assert_eq!(3, result.matched);
+    assert_eq!(6, result.index_entries);
+    assert_eq!(7 * 5, db.dir().join("items.pbd").metadata().unwrap().len());
+    assert_eq!([0, 2, 3, 5, 6, 8, 9].map(Value::I32).to_vec(), ids(vec![]));
+    // Remaining rows are still found through the indices, at their new positions.
+    assert_eq!(
+        vec![Value::I32(8)],
+        ids(vec![filter("id", CompareOp::Eq, Value::I32(8))])
+    );
+    assert!(ids(vec![filter("id", CompareOp::Eq, Value::I32(4))]).is_empty());
+    assert_eq!(
+        [2, 5, 8].map(Value::I32).to_vec(),
+        ids(vec![filter("group", CompareOp::Eq, Value::U8(2))])
+    );
+    assert!(db.check_all().unwrap().is_ok());
+
+    // The deleted key can be inserted again.
+    db.run_insert_query(&InsertQuery {
+        table: "items".into(),
+        values: HashMap::from([("id".into(), Value::I32(4))]),
+    })
+    .unwrap();
+    assert_eq!(
+        1,
+        ids(vec![filter("id", CompareOp::Eq, Value::I32(4))]).len()
+    );
+
+    // Replicas remove the same rows.
+    let replica = PBase::new_temp().unwrap();
+    replica.apply_wal(&db.stream_wal(1).unwrap()).unwrap();
+    assert_eq!(
+        db.run_select_query(SelectQuery {
+            from: "items".into(),
+            ..Default::default()
+        })
+        .unwrap(),
+        replica
+            .run_select_query(SelectQuery {
+                from: "items".into(),
+                ..Default::default()
+            })
+            .unwrap()
+    );
+    assert!(replica.check_all().unwrap().is_ok());
+}
+
+#[test]
+fn test_plan_cache() {
+    let dir = std::env::temp_dir().join("pbase_plan_cache_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "events".into(),
+            fields: IndexMap::from([
+                ("id".into(), FieldSchema::I32),
+                ("kind".into(), FieldSchema::I32),
+            ]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+
+    let run = |statement: &str| {
+        let tokens = Lexer::tokenize(statement.as_bytes()).unwrap();
+        match db.parse_statement(&tokens).unwrap() {
+            Query::Insert(insert_statement) => {
+                db.run_insert_query(&insert_statement.query).unwrap();
+                0
+            }
+            Query::Select(select_query) => db.run_select_query(select_query).unwrap().len(),
+            query => panic!("unexpected query {query:?}"),
+        }
+    };
+    for id in 0..10 {
+        run(&format!(
+            "INSERT INTO events (id, kind) VALUES ({id}, {})",
+            id % 3
+        ));
+    }
+    assert_eq!(5, run("SELECT FROM events TABLESAMPLE FIRST 5"));
+    assert_eq!(7, run("SELECT FROM events TABLESAMPLE FIRST 7"));
+    assert_eq!(10, run("SELECT FROM events"));
+
+    let stats = db.plan_cache().stats();
+    assert_eq!(
+        (10, 3),
+        (stats.statement_hits, stats.statement_misses),
+        "{stats:?}"
+    );
+    // The sample size is not part of the logical plan.
+    assert_eq!((2, 1), (stats.plan_hits, stats.plan_misses), "{stats:?}");
+    assert_eq!((3, 1), (stats.statements, stats.plans));
+
+    // Schema changes drop the cached plans, the counters stay.
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "other".into(),
+            fields: IndexMap::from([("id".into(), FieldSchema::I32)]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    let stats = db.plan_cache().stats();
+    assert_eq!(
+        (0, 0, 10),
+        (stats.statements, stats.plans, stats.statement_hits)
+    );
+    assert_eq!(10, run("SELECT FROM events"));
+    assert_eq!(2, db.plan_cache().stats().plan_misses);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_create_table_storage_options() {
+    let dir = std::env::temp_dir().join("pbase_create_table_storage_options_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+    let create_table = |statement: &str| {
+        let tokens = Lexer::tokenize(statement.as_bytes()).unwrap();
+        let Query::CreateTable(mut query) = db.parse_statement(&tokens)? else {
+            panic!("expected a create table query");
+        };
+        query.schema.indices = IndexMap::from([("id_index".into(), vec!["id".into()])]);
+        db.run_create_table_query(&query)
+    };
+    assert!(matches!(
+        create_table("CREATE TABLE narrow (id I32, v U8) WITH (layout='columnar')")
+            .unwrap_err()
+            .downcast_ref::<PBaseError>(),
+        Some(PBaseError::UnsupportedStorageOption(_))
+    ));
+    assert!(!db.is_table_exist("narrow"));
+    create_table("CREATE TABLE narrow (id I32, v U8) WITH (ptr_width=4, compression='none')")
+        .unwrap();
+    create_table("CREATE TABLE wide (id I32, v U8) WITH (ptr_width=8)").unwrap();
+
+    let schema = TableOpener::new(dir.clone()).open_schema("narrow").unwrap();
+    assert_eq!(4, schema.storage.ptr_width);
+    assert_eq!(8, schema.index_row_byte_size("id_index"));
+
+    for table in ["narrow", "wide"] {
+        for id in (0..20).rev() {
+            db.run_insert_query(&InsertQuery {
+                table: table.into(),
+                values: HashMap::from([
+                    ("id".into(), Value::I32(id)),
+                    ("v".into(), Value::U8(u8::try_from(id).unwrap())),
+                ]),
+            })
+            .unwrap();
+        }
+        let rows = db
+            .run_select_query(SelectQuery {
+                from: table.into(),
+                filters: vec![RowFilter {
+                    field: FieldSelector {
+                        name: "id".into(),
+                        source: table.into(),
+                    },
+                    op: CompareOp::Eq,
+                    rhs: RhsValue::Value(Value::I32(7)),
+                }],
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(1, rows.len());
+        assert_eq!(
+            Some(&Value::U8(7)),
+            rows.rows[0].get(format!("{table}.v").as_str())
+        );
+    }
+    assert!(db.check_all().unwrap().is_ok());
+
+    let index_len = |table: &str| {
+        std::fs::metadata(dir.join(format!("{table}__id_index.pbi")))
+            .map_or(0, |metadata| metadata.len())
+            + std::fs::metadata(dir.join(format!("{table}__id_index.pbx")))
+                .map_or(0, |metadata| metadata.len())
+    };
+    assert_eq!(20 * 8, index_len("narrow"));
+    assert_eq!(20 * 12, index_len("wide"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_select_query_stats() {
+    let dir = std::env::temp_dir().join("pbase_select_query_stats_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "users".into(),
+            fields: IndexMap::from([
+                ("id".into(), FieldSchema::I32),
+                ("age".into(), FieldSchema::U8),
+            ]),
+            indices: IndexMap::from([("id_index".into(), vec!["id".into()])]),
+            unique_indices: vec!["id_index".into()],
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    for id in 0..20 {
+        db.run_insert_query(&InsertQuery {
+            table: "users".into(),
+            values: HashMap::from([
+                ("id".into(), Value::I32(id)),
+                ("age".into(), Value::U8(u8::try_from(id % 4).unwrap())),
+            ]),
+        })
+        .unwrap();
+    }
+
+    let filtered = |field: &str, value: Value| SelectQuery {
+        from: "users".into(),
+        filters: vec![RowFilter {
+            field: FieldSelector {
+                name: field.into(),
+                source: "users".into(),
+            },
+            op: CompareOp::Eq,
+            rhs: RhsValue::Value(value),
+        }],
+        ..Default::default()
+    };
+
+    let (rows, stats) = db
+        .run_select_query_with_stats(filtered("id", Value::I32(7)))
+        .unwrap();
+    assert_eq!(1, rows.len());
+    assert_eq!((1, 1), (stats.rows, stats.scanned_rows));
+    assert_eq!(vec!["id_index".to_string()], stats.indices);
+    assert!(!stats.row_cache_hit);
+
+    // The same unique lookup again is served by the row cache.
+    let (_, stats) = db
+        .run_select_query_with_stats(filtered("id", Value::I32(7)))
+        .unwrap();
+    assert!(stats.row_cache_hit, "{stats:?}");
+
+    let (rows, stats) = db
+        .run_select_query_with_stats(filtered("age", Value::U8(1)))
+        .unwrap();
+    assert_eq!(5, rows.len());
+    assert_eq!((5, 20), (stats.rows, stats.scanned_rows));
+    assert!(stats.indices.is_empty());
+    assert!(stats.to_string().ends_with("(no index, scanned 20 rows)"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_schema_edited_without_migrating_data() {
+    let dir = std::env::temp_dir().join("pbase_schema_layout_mismatch_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "users".into(),
+            fields: IndexMap::from([("id".into(), FieldSchema::I32)]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    for id in 0..5 {
+        db.run_insert_query(&InsertQuery {
+            table: "users".into(),
+            values: HashMap::from([("id".into(), Value::I32(id))]),
+        })
+        .unwrap();
+    }
+
+    // A field added by hand: 20 bytes of data still split into rows of 5 bytes.
+    let schema_file_name = dir.join("users.pbs");
+    let mut schema: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&schema_file_name).unwrap()).unwrap();
+    schema["fields"]["age"] = "U8".into();
+    std::fs::write(&schema_file_name, serde_json::to_vec(&schema).unwrap()).unwrap();
+
+    let err = db
+        .run_select_query(SelectQuery {
+            from: "users".into(),
+            ..Default::default()
+        })
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<PBaseError>(),
+            Some(PBaseError::SchemaLayoutMismatch(table)) if table == "users"
+        ),
+        "{err}"
+    );
+    assert!(!db.check_all().unwrap().is_ok());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_default_expressions() {
+    let dir = std::env::temp_dir().join("pbase_default_expressions_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let mut db = PBase::new(dir.clone());
+    db.register_scalar("double", FieldSchema::I32, |value| match value {
+        Value::I32(v) => Value::I32(v * 2),
+        _ => Value::NULL,
+    });
+    let Query::CreateTable(create_table_query) = Parser::new(
+        &Lexer::tokenize(
+            b"CREATE TABLE events (id I32 DEFAULT next_id(), at I32 DEFAULT now(), \
+              kind U8 DEFAULT 2, weight I32 DEFAULT double(21))",
+        )
+        .unwrap(),
+    )
+    .parse()
+    .unwrap() else {
+        panic!("expected a create table query");
+    };
+    db.run_create_table_query(&create_table_query).unwrap();
+
+    let insert = |values: &[(&str, Value)]| {
+        db.run_insert_query(&InsertQuery {
+            table: "events".into(),
+            values: values
+                .iter()
+                .map(|(field, value)| ((*field).to_string(), value.clone()))
+                .collect(),
+        })
+        .unwrap();
+    };
+    insert(&[]);
+    insert(&[("kind", Value::U8(5)), ("id", Value::NULL)]);
+    insert(&[("id", Value::I32(10))]);
+    insert(&[]);
+
+    // Bulk loads fill the fields their stream leaves out.
+    let mut header = CopyHeader::new(&db.table_schema("events").unwrap(), 2);
+    header.fields.shift_remove("id");
+    let stream_schema = TableSchema {
+        fields: header.fields.clone(),
+        ..Default::default()
+    };
+    let mut writer = CopyWriter::new(vec![], header).unwrap();
+    for kind in [7, 8] {
+        writer
+            .write_row_bytes(&stream_schema.data_row_to_bytes(&HashMap::from([
+                ("at".into(), Value::I32(0)),
+                ("kind".into(), Value::U8(kind)),
+                ("weight".into(), Value::I32(1)),
+            ])))
+            .unwrap();
+    }
+    assert_eq!(
+        2,
+        db.copy_rows("events", &writer.finish().unwrap()[..])
+            .unwrap()
+    );
+
+    let rows = db
+        .run_select_query(SelectQuery {
+            from: "events".into(),
+            ..Default::default()
+        })
+        .unwrap();
+    let column = |name: &str| -> Vec<Value> {
+        rows.rows
+            .iter()
+            .map(|row| row.get(format!("events.{name}").as_str()).unwrap().clone())
+            .collect()
+    };
+    assert_eq!(
+        vec![0, 1, 10, 11, 12, 13],
+        column("id")
+            .iter()
+            .map(|value| match value {
+                Value::I32(v) => *v,
+                _ => panic!("unexpected id {value}"),
+            })
+            .collect::<Vec<_>>()
+    );
+    assert_eq!([2, 5, 2, 2, 7, 8].map(Value::U8).to_vec(), column("kind"));
+    assert_eq!(Value::I32(42), column("weight")[0]);
+    assert!(matches!(column("at")[0], Value::I32(at) if at > 1_700_000_000));
+
+    // Defaults are checked on create.
+    for statement in [
+        "CREATE TABLE bad (a U8 DEFAULT now())",
+        "CREATE TABLE bad (a U8 DEFAULT 300)",
+    ] {
+        let Query::CreateTable(query) =
+            Parser::new(&Lexer::tokenize(statement.as_bytes()).unwrap())
+                .parse()
+                .unwrap()
+        else {
+            panic!("expected a create table query");
+        };
+        assert!(db.run_create_table_query(&query).is_err(), "{statement}");
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_select_distinct() {
+    let db = PBase::new_temp().unwrap();
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "visits".into(),
+            fields: IndexMap::from([
+                ("user".into(), FieldSchema::I32),
+                ("page".into(), FieldSchema::U8),
+            ]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    for (user, page) in [(1, 1), (1, 1), (2, 1), (1, 2), (2, 1), (3, 3), (1, 1)] {
+        db.run_insert_query(&InsertQuery {
+            table: "visits".into(),
+            values: HashMap::from([
+                ("user".into(), Value::I32(user)),
+                ("page".into(), Value::U8(page)),
+            ]),
+        })
+        .unwrap();
+    }
+
+    let distinct = |limit: Option<usize>| SelectQuery {
+        from: "visits".into(),
+        distinct: true,
+        limit,
+        ..Default::default()
+    };
+    let users = |query: SelectQuery| -> Vec<Value> {
+        db.run_select_query(query)
+            .unwrap()
+            .rows
+            .iter()
+            .map(|row| row["visits.user"].clone())
+            .collect()
+    };
+
+    // First occurrences, in data order.
+    assert_eq!([1, 2, 1, 3].map(Value::I32).to_vec(), users(distinct(None)));
+    // The limit counts distinct rows.
+    assert_eq!([1, 2, 1].map(Value::I32).to_vec(), users(distinct(Some(3))));
+    assert_eq!(
+        7,
+        db.run_select_query(SelectQuery {
+            distinct: false,
+            ..distinct(None)
+        })
+        .unwrap()
+        .len()
+    );
+
+    let plan = db.explain_select_query(distinct(Some(3))).unwrap();
+    assert_eq!(
+        "Limit 3 (rows: 3)\n└── Distinct (rows: 7)\n    └── Scan visits (rows: 7)\n",
+        plan.to_ascii_tree()
+    );
+}
+
+#[test]
+fn test_limit_on_index_scan() {
+    let db = PBase::new_temp().unwrap();
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "scores".into(),
+            fields: IndexMap::from([("score".into(), FieldSchema::I32)]),
+            indices: IndexMap::from([("score_index".into(), vec!["score".into()])]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    // Scores out of order, the last ones still in the index delta.
+    for score in [50, 10, 40, 20, 30, 5, 45, 15, 35, 25] {
+        db.run_insert_query(&InsertQuery {
+            table: "scores".into(),
+            values: HashMap::from([("score".into(), Value::I32(score))]),
+        })
+        .unwrap();
+    }
+
+    let query = |limit| SelectQuery {
+        from: "scores".into(),
+        filters: vec![RowFilter {
+            field: FieldSelector {
+                name: "score".into(),
+                source: "scores".into(),
+            },
+            op: CompareOp::Gt,
+            rhs: RhsValue::Value(Value::I32(12)),
+        }],
+        limit,
+        ..Default::default()
+    };
+    let scores = |limit| -> Vec<Value> {
+        db.run_select_query(query(limit))
+            .unwrap()
+            .rows
+            .iter()
+            .map(|row| row["scores.score"].clone())
+            .collect()
+    };
+
+    let all_scores = scores(None);
+    assert_eq!(8, all_scores.len());
+    for limit in [0, 1, 3, 8, 20] {
+        assert_eq!(
+            all_scores[..limit.min(8)],
+            scores(Some(limit))[..],
+            "limit {limit}"
+        );
+    }
+
+    let plan = db.explain_select_query(query(Some(3))).unwrap();
+    assert_eq!(PlanNode::Limit { limit: 3 }, plan.node);
+    assert!(
+        matches!(&plan.children[0].node, PlanNode::IndexScan { ranges, .. } if ranges[0].1 - ranges[0].0 - 1 <= 3),
+        "{plan:?}"
+    );
+}
+
+#[test]
+fn test_in_list_filter() {
+    let db = PBase::new_temp().unwrap();
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "parts".into(),
+            fields: IndexMap::from([
+                ("id".into(), FieldSchema::I32),
+                ("kind".into(), FieldSchema::I32),
+                ("weight".into(), FieldSchema::I32),
+            ]),
+            indices: IndexMap::from([("kind_index".into(), vec!["kind".into(), "id".into()])]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    let insert = |id: i32| {
+        db.run_insert_query(&InsertQuery {
+            table: "parts".into(),
+            values: HashMap::from([
+                ("id".into(), Value::I32(id)),
+                ("kind".into(), Value::I32(id % 5)),
+                ("weight".into(), Value::I32(id * 2)),
+            ]),
+        })
+        .unwrap();
+    };
+    for id in 0..30 {
+        insert(id);
+    }
+    db.merge_index_deltas().unwrap();
+    // The last ones in the index delta.
+    for id in 30..35 {
+        insert(id);
+    }
+
+    let filter = |name: &str, op, rhs| RowFilter {
+        field: FieldSelector {
+            name: name.into(),
+            source: "parts".into(),
+        },
+        op,
+        rhs,
+    };
+    let kinds_in = |values: Vec<i32>| {
+        filter(
+            "kind",
+            CompareOp::Eq,
+            RhsValue::In(values.into_iter().map(Value::I32).collect()),
+        )
+    };
+    let ids = |filters| -> Vec<Value> {
+        db.run_select_query(SelectQuery {
+            from: "parts".into(),
+            filters,
+            ..Default::default()
+        })
+        .unwrap()
+        .rows
+        .iter()
+        .map(|row| row["parts.id"].clone())
+        .collect()
+    };
+
+    // Probed in index order, whatever the order (and repeats) of the list.
+    let filters = vec![
+        kinds_in(vec![3, 1, 9, 1]),
+        filter("id", CompareOp::Gt, RhsValue::Value(Value::I32(15))),
+    ];
+    assert_eq!(
+        [16, 21, 26, 31, 18, 23, 28, 33].map(Value::I32)[..],
+        ids(filters.clone())[..]
+    );
+    let plan = db
+        .explain_select_query(SelectQuery {
+            from: "parts".into(),
+            filters,
+            ..Default::default()
+        })
+        .unwrap();
+    assert!(
+        matches!(&plan.node, PlanNode::IndexScan { ranges, delta_rows, .. } if ranges.len() == 2 && delta_rows.len() == 2),
+        "{plan:?}"
+    );
+
+    // Fields without an index are filtered while scanning.
+    assert_eq!(
+        [2, 4, 32].map(Value::I32)[..],
+        ids(vec![filter(
+            "weight",
+            CompareOp::Eq,
+            RhsValue::In([64, 4, 8, 100].map(Value::I32).to_vec()),
+        )])[..]
+    );
+
+    assert!(ids(vec![kinds_in(vec![])]).is_empty());
+    assert!(ids(vec![kinds_in(vec![7, 8])]).is_empty());
+}
+
+#[test]
+fn test_order_by() {
+    let db = PBase::new_temp().unwrap();
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "scores".into(),
+            fields: IndexMap::from([
+                ("score".into(), FieldSchema::I32),
+                ("player".into(), FieldSchema::I32),
+            ]),
+            indices: IndexMap::from([("score_index".into(), vec!["score".into()])]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    let insert = |score: i32| {
+        db.run_insert_query(&InsertQuery {
+            table: "scores".into(),
+            values: HashMap::from([
+                ("score".into(), Value::I32(score)),
+                ("player".into(), Value::I32(score % 7)),
+            ]),
+        })
+        .unwrap();
+    };
+    for score in [50, 10, 40, 20, 30] {
+        insert(score);
+    }
+    db.merge_index_deltas().unwrap();
+    // The last ones in the index delta.
+    for score in [5, 45, 15, 35, 25] {
+        insert(score);
+    }
+
+    let query = |field: &str, descending, limit| SelectQuery {
+        from: "scores".into(),
+        order_by: Some(OrderBy {
+            field: FieldSelector {
+                name: field.into(),
+                source: "scores".into(),
+            },
+            descending,
+        }),
+        limit,
+        ..Default::default()
+    };
+    let column = |query, field: &str| -> Vec<Value> {
+        db.run_select_query(query)
+            .unwrap()
+            .rows
+            .iter()
+            .map(|row| row[format!("scores.{field}").as_str()].clone())
+            .collect()
+    };
+
+    // The index is read backwards, only as far as the limit.
+    assert_eq!(
+        [50, 45, 40].map(Value::I32)[..],
+        column(query("score", true, Some(3)), "score")[..]
+    );
+    let plan = db
+        .explain_select_query(query("score", true, Some(3)))
+        .unwrap();
+    assert_eq!(
+        "Limit 3 (rows: 3)\n└── IndexScan scores using score_index DESC (rows: 3)\n",
+        plan.to_ascii_tree()
+    );
+    assert_eq!(
+        [5, 10, 15, 20].map(Value::I32)[..],
+        column(query("score", false, Some(4)), "score")[..]
+    );
+
+    // Without a limit, or without an index, the rows are sorted.
+    assert_eq!(
+        [5, 10, 15, 20, 25, 30, 35, 40, 45, 50].map(Value::I32)[..],
+        column(query("score", false, None), "score")[..]
+    );
+    assert_eq!(
+        [6, 5, 5, 4, 3, 3, 2, 1, 1, 0].map(Value::I32)[..],
+        column(query("player", true, None), "player")[..]
+    );
+    assert!(db
+        .explain_select_query(query("player", true, Some(2)))
+        .unwrap()
+        .to_ascii_tree()
+        .contains("Sort scores.player DESC"));
+
+    assert!(db.run_select_query(query("rank", false, None)).is_err());
+}
+
+#[test]
+fn test_range_filter() {
+    let db = PBase::new_temp().unwrap();
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "readings".into(),
+            fields: IndexMap::from([
+                ("ts".into(), FieldSchema::I32),
+                ("sensor".into(), FieldSchema::I32),
+            ]),
+            indices: IndexMap::from([("ts_index".into(), vec!["ts".into(), "sensor".into()])]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    let insert = |ts: i32| {
+        db.run_insert_query(&InsertQuery {
+            table: "readings".into(),
+            values: HashMap::from([
+                ("ts".into(), Value::I32(ts)),
+                ("sensor".into(), Value::I32(ts % 3)),
+            ]),
+        })
+        .unwrap();
+    };
+    for ts in 0..20 {
+        insert(ts);
+    }
+    db.merge_index_deltas().unwrap();
+    // The last ones in the index delta.
+    for ts in 20..25 {
+        insert(ts);
+    }
+
+    let between = |low, high, inclusive| RowFilter {
+        field: FieldSelector {
+            name: "ts".into(),
+            source: "readings".into(),
+        },
+        op: CompareOp::Eq,
+        rhs: RhsValue::Range {
+            low: Value::I32(low),
+            high: Value::I32(high),
+            inclusive,
+        },
+    };
+    let query = |filters| SelectQuery {
+        from: "readings".into(),
+        filters,
+        ..Default::default()
+    };
+    let timestamps = |filters| -> Vec<Value> {
+        db.run_select_query(query(filters))
+            .unwrap()
+            .rows
+            .iter()
+            .map(|row| row["readings.ts"].clone())
+            .collect()
+    };
+
+    assert_eq!(
+        [17, 18, 19, 20, 21].map(Value::I32)[..],
+        timestamps(vec![between(17, 21, true)])[..]
+    );
+    assert_eq!(
+        [18, 19, 20].map(Value::I32)[..],
+        timestamps(vec![between(17, 21, false)])[..]
+    );
+    let plan = db
+        .explain_select_query(query(vec![between(17, 21, true)]))
+        .unwrap();
+    assert!(
+        matches!(&plan.node, PlanNode::IndexScan { ranges, delta_rows, .. } if ranges == &[(16, 20)] && delta_rows.len() == 2),
+        "{plan:?}"
+    );
+
+    // The entries of the range are not sorted by the next index field, which is filtered after.
+    let sensor_filter = RowFilter {
+        field: FieldSelector {
+            name: "sensor".into(),
+            source: "readings".into(),
+        },
+        op: CompareOp::Eq,
+        rhs: RhsValue::Value(Value::I32(0)),
+    };
+    assert_eq!(
+        [3, 6, 9, 12].map(Value::I32)[..],
+        timestamps(vec![between(2, 12, true), sensor_filter])[..]
+    );
+
+    assert!(timestamps(vec![between(12, 2, true)]).is_empty());
+}
+
+#[test]
+fn test_inclusive_and_not_equal_filters() {
+    let db = PBase::new_temp().unwrap();
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "readings".into(),
+            fields: IndexMap::from([
+                ("ts".into(), FieldSchema::I32),
+                ("level".into(), FieldSchema::I32),
+            ]),
+            indices: IndexMap::from([("ts_index".into(), vec!["ts".into()])]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    let insert = |ts: i32| {
+        db.run_insert_query(&InsertQuery {
+            table: "readings".into(),
+            values: HashMap::from([
+                ("ts".into(), Value::I32(ts)),
+                ("level".into(), Value::I32(ts)),
+            ]),
+        })
+        .unwrap();
+    };
+    for ts in 0..10 {
+        insert(ts);
+    }
+    db.merge_index_deltas().unwrap();
+    // The last ones in the index delta.
+    for ts in 10..12 {
+        insert(ts);
+    }
+
+    // The same comparisons on the indexed field and on the unindexed one.
+    for field in ["ts", "level"] {
+        let filter = |op, value| RowFilter {
+            field: FieldSelector {
+                name: field.into(),
+                source: "readings".into(),
+            },
+            op,
+            rhs: RhsValue::Value(Value::I32(value)),
+        };
+        let timestamps = |filters| -> Vec<i32> {
+            let mut timestamps: Vec<i32> = db
+                .run_select_query(SelectQuery {
+                    from: "readings".into(),
+                    filters,
+                    ..Default::default()
+                })
+                .unwrap()
+                .rows
+                .iter()
+                .map(|row| match row["readings.ts"] {
+                    Value::I32(ts) => ts,
+                    _ => panic!("ts is an I32"),
+                })
+                .collect();
+            timestamps.sort_unstable();
+            timestamps
+        };
+
+        assert_eq!(
+            vec![8, 9, 10, 11],
+            timestamps(vec![filter(CompareOp::Ge, 8)])
+        );
+        assert_eq!(vec![0, 1, 2], timestamps(vec![filter(CompareOp::Le, 2)]));
+        assert_eq!(
+            vec![3, 4, 6, 7],
+            timestamps(vec![
+                filter(CompareOp::Ge, 3),
+                filter(CompareOp::Ne, 5),
+                filter(CompareOp::Le, 7),
+            ])
+        );
+        assert_eq!(
+            (0..12)
+                .filter(|ts| *ts != 4 && *ts != 11)
+                .collect::<Vec<_>>(),
+            timestamps(vec![filter(CompareOp::Ne, 4), filter(CompareOp::Ne, 11)])
+        );
+        assert!(timestamps(vec![filter(CompareOp::Ge, 6), filter(CompareOp::Lt, 6)]).is_empty());
+        // No row compares to NULL, whatever the operator.
+        for op in [CompareOp::Ne, CompareOp::Lt, CompareOp::Le, CompareOp::Gt] {
+            let null_filter = RowFilter {
+                rhs: RhsValue::Value(Value::NULL),
+                ..filter(op, 0)
+            };
+            assert!(timestamps(vec![null_filter]).is_empty());
+        }
+    }
+
+    let plan = db
+        .explain_select_query(SelectQuery {
+            from: "readings".into(),
+            filters: vec![RowFilter {
+                field: FieldSelector {
+                    name: "ts".into(),
+                    source: "readings".into(),
+                },
+                op: CompareOp::Ne,
+                rhs: RhsValue::Value(Value::I32(4)),
+            }],
+            ..Default::default()
+        })
+        .unwrap();
+    assert!(
+        matches!(&plan.node, PlanNode::IndexScan { ranges, .. } if ranges == &[(-1, 4), (4, 10)]),
+        "{plan:?}"
+    );
+}
+
+#[test]
+fn test_stale_handle() {
+    let dir = std::env::temp_dir().join("pbase_stale_handle_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+    let other_db = PBase::new(dir.clone());
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "shared".into(),
+            fields: IndexMap::from([("field1".into(), FieldSchema::I32)]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    let insert_query = |i| InsertQuery {
+        table: "shared".into(),
+        values: HashMap::from([("field1".into(), Value::I32(i))]),
+    };
+
+    db.run_insert_query(&insert_query(1)).unwrap();
+    db.run_insert_query(&insert_query(2)).unwrap();
+    other_db.run_insert_query(&insert_query(3)).unwrap();
+    assert_eq!(
+        3,
+        TableOpener::new(dir.clone())
+            .table_generation("shared")
+            .unwrap()
+    );
+
+    match db.run_insert_query(&insert_query(4)) {
+        Err(err) => assert!(matches!(
+            err.downcast_ref::<PBaseError>(),
+            Some(PBaseError::StaleHandle {
+                seen: 2,
+                generation: 3,
+                ..
+            })
+        )),
+        Ok(_) => panic!("stale handle wrote the table"),
+    }
+
+    db.refresh();
+    db.run_insert_query(&insert_query(4)).unwrap();
+    let query = SelectQuery {
+        from: "shared".into(),
+        ..Default::default()
+    };
+    assert_eq!(4, db.run_select_query(query).unwrap().len());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_concurrent_handles_write_in_turn() {
+    let db = PBase::new_temp().unwrap();
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "shared".into(),
+            fields: IndexMap::from([("field1".into(), FieldSchema::I32)]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+
+    // Each insert checks and bumps the generation under the table lock: no bump is lost, and a
+    // handle another one wrote past fails instead of writing.
+    std::thread::scope(|scope| {
+        for _ in 0..2 {
+            scope.spawn(|| {
+                let handle = PBase::new(db.dir().to_path_buf());
+                let mut inserted = 0;
+                while inserted < 50 {
+                    let result = handle.run_insert_query(&InsertQuery {
+                        table: "shared".into(),
+                        values: HashMap::from([("field1".into(), Value::I32(inserted))]),
+                    });
+                    match result {
+                        Ok(_) => inserted += 1,
+                        Err(err) => {
+                            assert!(matches!(
+                                err.downcast_ref::<PBaseError>(),
+                                Some(PBaseError::StaleHandle { .. })
+                            ));
+                            handle.refresh();
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    assert_eq!(
+        100,
+        TableOpener::new(db.dir().to_path_buf())
+            .table_generation("shared")
+            .unwrap()
+    );
+    let query = SelectQuery {
+        from: "shared".into(),
+        ..Default::default()
+    };
+    assert_eq!(100, db.run_select_query(query).unwrap().len());
+}
+
+#[test]
+fn test_read_after_delete_by_other_handle() {
+    let db = PBase::new_temp().unwrap();
+    let other_db = PBase::new(db.dir().to_path_buf());
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "shared".into(),
+            fields: IndexMap::from([("field1".into(), FieldSchema::I32)]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    for i in 0..5 {
+        db.run_insert_query(&InsertQuery {
+            table: "shared".into(),
+            values: HashMap::from([("field1".into(), Value::I32(i))]),
+        })
+        .unwrap();
+    }
+
+    other_db
+        .run_delete_query(&DeleteQuery {
+            table: "shared".into(),
+            filters: vec![RowFilter {
+                field: FieldSelector {
+                    name: "field1".into(),
+                    source: "shared".into(),
+                },
+                op: CompareOp::Lt,
+                rhs: RhsValue::Value(Value::I32(2)),
+            }],
+        })
+        .unwrap();
+
+    // The shorter file is the table as the other handle left it, not a stale read.
+    let query = SelectQuery {
+        from: "shared".into(),
+        ..Default::default()
+    };
+    assert_eq!(3, db.run_select_query(query.clone()).unwrap().len());
+    db.refresh();
+    db.run_insert_query(&InsertQuery {
+        table: "shared".into(),
+        values: HashMap::from([("field1".into(), Value::I32(5))]),
+    })
+    .unwrap();
+    assert_eq!(4, db.run_select_query(query).unwrap().len());
+}
+
+#[test]
+fn test_stats_refresh_task() {
+    let db = PBase::new_temp().unwrap();
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "refreshed".into(),
+            fields: IndexMap::from([("field1".into(), FieldSchema::I32)]),
+            indices: IndexMap::from([("field1_index".into(), vec!["field1".into()])]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    let insert = |i| {
+        db.run_insert_query(&InsertQuery {
+            table: "refreshed".into(),
+            values: HashMap::from([("field1".into(), Value::I32(i))]),
+        })
+        .unwrap();
+    };
+
+    // Never analyzed tables are left alone.
+    insert(0);
+    assert!(db.refresh_stale_stats(0.5).unwrap().is_empty());
+
+    for i in 1..10 {
+        insert(i);
+    }
+    assert_eq!(10, db.analyze_table("refreshed").unwrap().row_count);
+    assert_eq!(0, db.modified_rows("refreshed"));
+
+    let mut scheduler = MaintenanceScheduler::new();
+    scheduler.register(
+        Box::new(StatsRefreshTask {
+            changed_fraction: 0.5,
+        }),
+        MaintenancePolicy {
+            interval: Duration::ZERO,
+        },
+    );
+    // Values are inserted in order.
+    let analyzed_max = || {
+        db.describe_table("refreshed").unwrap()[0]
+            .stats
+            .clone()
+            .unwrap()
+            .max
+    };
+
+    for i in 10..14 {
+        insert(i);
+    }
+    scheduler.run_maintenance(&db, Instant::now());
+    assert_eq!(4, db.modified_rows("refreshed"));
+    assert_eq!(Value::I32(9), analyzed_max());
+
+    insert(14);
+    scheduler.run_maintenance(&db, Instant::now());
+    assert_eq!(0, db.modified_rows("refreshed"));
+    assert_eq!(Value::I32(14), analyzed_max());
+}
+
+#[test]
+fn test_distinct_field() {
+    let db = PBase::new_temp().unwrap();
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "visits".into(),
+            fields: IndexMap::from([
+                ("page".into(), FieldSchema::I32),
+                ("user".into(), FieldSchema::I32),
+            ]),
+            indices: IndexMap::from([("page_index".into(), vec!["page".into()])]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    let insert = |page: i32| {
+        db.run_insert_query(&InsertQuery {
+            table: "visits".into(),
+            values: HashMap::from([
+                ("page".into(), Value::I32(page)),
+                ("user".into(), Value::I32(page % 3)),
+            ]),
+        })
+        .unwrap();
+    };
+    for page in [5, 3, 5, 1, 3, 3, 9, 5] {
+        insert(page);
+    }
+    db.merge_index_deltas().unwrap();
+    // Keys both new and already in the sorted index.
+    for page in [7, 3, 0, 7] {
+        insert(page);
+    }
+
+    let query = |statement: &str| {
+        let tokens = Lexer::tokenize(statement.as_bytes()).unwrap();
+        let Query::Select(query) = db.parse_statement(&tokens).unwrap() else {
+            panic!("not a select: {statement}");
+        };
+        query
+    };
+    let query_values = |query: SelectQuery| -> Vec<Value> {
+        let result = db.run_select_query(query).unwrap();
+        assert_eq!(1, result.columns.len());
+        result
+            .rows
+            .iter()
+            .map(|row| row[result.columns[0].name.as_str()].clone())
+            .collect()
+    };
+    let values = |statement: &str| query_values(query(statement));
+    let ints = |values: &[i32]| -> Vec<Value> { values.iter().copied().map(Value::I32).collect() };
+
+    assert_eq!(
+        ints(&[0, 1, 3, 5, 7, 9]),
+        values("SELECT DISTINCT visits.page FROM visits")
+    );
+    assert_eq!(
+        ints(&[9, 7, 5, 3, 1, 0]),
+        values("SELECT DISTINCT visits.page FROM visits ORDER BY visits.page DESC")
+    );
+    let mut limited = query("SELECT DISTINCT visits.page FROM visits");
+    limited.limit = Some(2);
+    assert_eq!(ints(&[0, 1]), query_values(limited));
+
+    let plan = db
+        .explain_select_query(query("SELECT DISTINCT visits.page FROM visits"))
+        .unwrap();
+    assert!(
+        matches!(&plan.node, PlanNode::IndexDistinct { delta_keys, .. } if delta_keys == &ints(&[0, 3, 7])),
+        "{plan:?}"
+    );
+
+    // Not indexed: the first row of each value, in table order.
+    assert_eq!(
+        ints(&[2, 0, 1]),
+        values("SELECT DISTINCT visits.user FROM visits")
+    );
+    let plan = db
+        .explain_select_query(query("SELECT DISTINCT visits.user FROM visits"))
+        .unwrap();
+    assert!(
+        matches!(&plan.node, PlanNode::DistinctValues { column } if column == "visits.user"),
+        "{plan:?}"
+    );
+}
+
+// Cents stored little-endian: byte order is not the order of the amounts.
+struct Cents;
+
+impl TypeDef for Cents {
+    fn byte_size(&self) -> usize {
+        4
+    }
+
+    fn encode(&self, value: &Value) -> Option<Vec<u8>> {
+        match value {
+            Value::I32(cents) => Some(cents.to_le_bytes().to_vec()),
+            Value::U8(cents) => Some(i32::from(*cents).to_le_bytes().to_vec()),
+            _ => None,
+        }
+    }
+
+    fn compare(&self, lhs: &[u8], rhs: &[u8]) -> std::cmp::Ordering {
+        let cents = |bytes: &[u8]| i32::from_le_bytes(bytes.try_into().unwrap());
+        cents(lhs).cmp(&cents(rhs))
+    }
+
+    fn format(&self, bytes: &[u8]) -> String {
+        let cents = i32::from_le_bytes(bytes.try_into().unwrap());
+        format!("${}.{:02}", cents / 100, cents % 100)
+    }
+}
+
+#[test]
+fn test_custom_type() {
+    let mut db = PBase::new_temp().unwrap();
+    let tokens = Lexer::tokenize(b"CREATE TABLE prices (id I32, price cents)").unwrap();
+    let Query::CreateTable(mut create_table_query) = db.parse_statement(&tokens).unwrap() else {
+        panic!("expected a create table query");
+    };
+    create_table_query
+        .schema
+        .indices
+        .insert("price_index".into(), vec!["price".into()]);
+
+    assert!(matches!(
+        *db.run_create_table_query(&create_table_query)
+            .unwrap_err()
+            .downcast::<PBaseError>()
+            .unwrap(),
+        PBaseError::UnknownType(type_name) if type_name == "cents"
+    ));
+
+    db.register_type("cents", Cents);
+    db.run_create_table_query(&create_table_query).unwrap();
+    for (id, cents) in [(1, 250), (2, -5), (3, 1000), (4, 99), (5, 256)] {
+        db.run_insert_query(&InsertQuery {
+            table: "prices".into(),
+            values: HashMap::from([
+                ("id".into(), Value::I32(id)),
+                ("price".into(), Value::I32(cents)),
+            ]),
+        })
+        .unwrap();
+    }
+
+    let price = FieldSelector {
+        name: "price".into(),
+        source: "prices".into(),
+    };
+    let query = SelectQuery {
+        from: "prices".into(),
+        filters: vec![RowFilter {
+            field: price.clone(),
+            op: CompareOp::Ge,
+            rhs: RhsValue::Value(Value::I32(99)),
+        }],
+        order_by: Some(OrderBy {
+            field: price,
+            descending: false,
+        }),
+        ..Default::default()
+    };
+    assert!(db
+        .explain_select_query(query.clone())
+        .unwrap()
+        .to_ascii_tree()
+        .contains("IndexScan"));
+    let result = db.run_select_query(query).unwrap();
+    assert_eq!(
+        vec!["$0.99", "$2.50", "$2.56", "$10.00"],
+        result
+            .rows
+            .iter()
+            .map(|row| row["prices.price"].to_string())
+            .collect::<Vec<_>>()
+    );
+
+    // Tables of the type are opened with its registered byte size only.
+    let all_prices = || SelectQuery {
+        from: "prices".into(),
+        ..Default::default()
+    };
+    let mut other = PBase::new(db.dir().to_path_buf());
+    assert!(other.run_select_query(all_prices()).is_err());
+    other.register_type("cents", Cents);
+    assert_eq!(5, other.run_select_query(all_prices()).unwrap().len());
+}
+
+#[test]
+fn test_decimal_field() {
+    let db = PBase::new_temp().unwrap();
+    let tokens =
+        Lexer::tokenize(b"CREATE TABLE accounts (id I32, balance DECIMAL(10, 2))").unwrap();
+    let Query::CreateTable(mut create_table_query) = db.parse_statement(&tokens).unwrap() else {
+        panic!("expected a create table query");
+    };
+    create_table_query
+        .schema
+        .indices
+        .insert("balance_index".into(), vec!["balance".into()]);
+    db.run_create_table_query(&create_table_query).unwrap();
+
+    let insert = |id: i32, balance: Value| {
+        db.run_insert_query(&InsertQuery {
+            table: "accounts".into(),
+            values: HashMap::from([("id".into(), Value::I32(id)), ("balance".into(), balance)]),
+        })
+    };
+    insert(1, Value::Decimal(Decimal::new(1234, 2))).unwrap();
+    insert(2, Value::I32(-5)).unwrap();
+    insert(3, Value::Decimal(Decimal::new(5, 1))).unwrap();
+    insert(4, Value::Decimal(Decimal::new(-1, 2))).unwrap();
+    // Neither more digits than the precision, nor more decimals than the scale.
+    assert!(insert(5, Value::Decimal(Decimal::new(1_000_000_000, 0))).is_err());
+    assert!(insert(5, Value::Decimal(Decimal::new(1, 3))).is_err());
+
+    let balance = FieldSelector {
+        name: "balance".into(),
+        source: "accounts".into(),
+    };
+    let query = SelectQuery {
+        from: "accounts".into(),
+        filters: vec![RowFilter {
+            field: balance.clone(),
+            op: CompareOp::Gt,
+            rhs: RhsValue::Value(Value::Decimal(Decimal::new(-1, 1))),
+        }],
+        order_by: Some(OrderBy {
+            field: balance.clone(),
+            descending: false,
+        }),
+        ..Default::default()
+    };
+    assert!(db
+        .explain_select_query(query.clone())
+        .unwrap()
+        .to_ascii_tree()
+        .contains("IndexScan"));
+    let result = db.run_select_query(query).unwrap();
+    assert_eq!(
+        vec!["-0.01", "0.50", "12.34"],
+        result
+            .rows
+            .iter()
+            .map(|row| row["accounts.balance"].to_string())
+            .collect::<Vec<_>>()
+    );
+
+    // IN lists match decimals by value, integers included.
+    let in_filter = RowFilter {
+        field: balance.clone(),
+        op: CompareOp::Eq,
+        rhs: RhsValue::In(vec![
+            Value::I32(-5),
+            Value::Decimal(Decimal::new(5, 1)),
+            Value::Decimal(Decimal::new(-5, 0)),
+        ]),
+    };
+    let result = db
+        .run_select_query(SelectQuery {
+            from: "accounts".into(),
+            filters: vec![in_filter.clone()],
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(
+        vec![Value::I32(2), Value::I32(3)],
+        result
+            .rows
+            .iter()
+            .map(|row| row["accounts.id"].clone())
+            .collect::<Vec<_>>()
+    );
+    assert!(in_filter.matches_value(&Value::Decimal(Decimal::new(-500, 2))));
+    assert!(in_filter.matches_value(&Value::Decimal(Decimal::new(50, 2))));
+    assert!(!in_filter.matches_value(&Value::Decimal(Decimal::new(1234, 2))));
+
+    // Sums are exact, at the field's scale.
+    let aggregate = |alias: &str, aggregate: Aggregate| ScalarSubquery {
+        alias: alias.into(),
+        query: SelectQuery {
+            from: "accounts".into(),
+            ..Default::default()
+        },
+        aggregate,
+        correlation: None,
+    };
+    let result = db
+        .run_select_query(SelectQuery {
+            from: "accounts".into(),
+            filters: vec![RowFilter {
+                field: FieldSelector {
+                    name: "id".into(),
+                    source: "accounts".into(),
+                },
+                op: CompareOp::Eq,
+                rhs: RhsValue::Value(Value::I32(1)),
+            }],
+            scalar_subqueries: vec![
+                aggregate("total", Aggregate::Sum(balance.clone())),
+                aggregate("mean", Aggregate::Avg(balance)),
+            ],
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!("7.83", result.rows[0]["total"].to_string());
+    assert_eq!("1.95", result.rows[0]["mean"].to_string());
+    assert_eq!(
+        FieldSchema::Decimal {
+            precision: 38,
+            scale: 2
+        },
+        result.columns[2].field_schema
+    );
+
+    let tokens = Lexer::tokenize(b"CREATE TABLE t2 (a DECIMAL(4, 5))").unwrap();
+    let Query::CreateTable(create_table_query) = db.parse_statement(&tokens).unwrap() else {
+        panic!("expected a create table query");
+    };
+    assert!(db.run_create_table_query(&create_table_query).is_err());
+}
+
+#[test]
+fn test_row_cap() {
+    let db = PBase::new_temp().unwrap().with_row_cap(RowCap {
+        max_rows: 3,
+        truncate: false,
+    });
+    let tokens = Lexer::tokenize(b"CREATE TABLE t1 (a I32)").unwrap();
+    let Query::CreateTable(create_table_query) = db.parse_statement(&tokens).unwrap() else {
+        panic!("expected a create table query");
+    };
+    db.run_create_table_query(&create_table_query).unwrap();
+    for a in 0..5 {
+        db.run_insert_query(&InsertQuery {
+            table: "t1".into(),
+            values: HashMap::from([("a".into(), Value::I32(a))]),
+        })
+        .unwrap();
+    }
+
+    let mut query = SelectQuery {
+        from: "t1".into(),
+        ..Default::default()
+    };
+    assert!(matches!(
+        *db.run_select_query(query.clone())
+            .unwrap_err()
+            .downcast::<PBaseError>()
+            .unwrap(),
+        PBaseError::RowCapExceeded(3)
+    ));
+
+    // Exactly at the cap.
+    query.limit = Some(3);
+    let result = db.run_select_query(query.clone()).unwrap();
+    assert_eq!(3, result.len());
+    assert!(!result.truncated);
+
+    query.limit = None;
+    query.row_cap = Some(RowCap {
+        max_rows: 2,
+        truncate: true,
+    });
+    let result = db.run_select_query(query.clone()).unwrap();
+    assert_eq!(2, result.len());
+    assert!(result.truncated);
+
+    query.row_cap = Some(RowCap::default());
+    let result = db.run_select_query(query).unwrap();
+    assert_eq!(5, result.len());
+    assert!(!result.truncated);
+}
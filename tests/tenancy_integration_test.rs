@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use indexmap::IndexMap;
+use pbase::{
+    pbase::PBase,
+    query::{
+        CreateTableQuery, FieldSelector, InsertQuery, JoinContract, JoinType, SelectQuery,
+        SetOperation, UnionQuery,
+    },
+    schema::{FieldSchema, TableSchema},
+    tenancy::TenantScopedPBase,
+    value::Value,
+};
+
+#[test]
+fn test_tenant_scoped_queries() {
+    let dir = std::env::temp_dir().join("pbase_tenancy_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "notes".into(),
+            fields: IndexMap::from([
+                ("tenant_id".into(), FieldSchema::I32),
+                ("kind".into(), FieldSchema::U8),
+            ]),
+            indices: IndexMap::from([("tenant_index".into(), vec!["tenant_id".into()])]),
+            tenant_column: Some("tenant_id".into()),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    // Shared by all tenants.
+    db.run_create_table_query(&CreateTableQuery {
+        schema: TableSchema {
+            name: "kinds".into(),
+            fields: IndexMap::from([("id".into(), FieldSchema::U8)]),
+            ..Default::default()
+        },
+    })
+    .unwrap();
+    for id in [1, 2] {
+        db.run_insert_query(&InsertQuery {
+            table: "kinds".into(),
+            values: HashMap::from([("id".into(), Value::U8(id))]),
+        })
+        .unwrap();
+    }
+
+    let tenant1 = TenantScopedPBase::new(&db, Value::I32(1));
+    let tenant2 = TenantScopedPBase::new(&db, Value::I32(2));
+    for (tenant, kind) in [(&tenant1, 1), (&tenant1, 2), (&tenant2, 1)] {
+        tenant
+            .run_insert_query(&InsertQuery {
+                table: "notes".into(),
+                values: HashMap::from([("kind".into(), Value::U8(kind))]),
+            })
+            .unwrap();
+    }
+    // Writing into another tenant's rows is rejected.
+    assert!(tenant1
+        .run_insert_query(&InsertQuery {
+            table: "notes".into(),
+            values: HashMap::from([
+                ("tenant_id".into(), Value::I32(2)),
+                ("kind".into(), Value::U8(2)),
+            ]),
+        })
+        .is_err());
+
+    let notes = SelectQuery {
+        from: "notes".into(),
+        ..Default::default()
+    };
+    assert_eq!(3, db.run_select_query(notes.clone()).unwrap().len());
+    assert_eq!(2, tenant1.run_select_query(notes.clone()).unwrap().len());
+    let rows = tenant2.run_select_query(notes.clone()).unwrap().rows;
+    assert_eq!(1, rows.len());
+    assert_eq!(Value::I32(2), rows[0]["notes.tenant_id"]);
+
+    // Joined shared tables are not filtered, joined scoped ones are.
+    let joined = SelectQuery {
+        from: "kinds".into(),
+        joins: vec![JoinContract {
+            join_type: JoinType::Inner,
+            null_keys_match: false,
+            lhs: FieldSelector {
+                name: "id".into(),
+                source: "kinds".into(),
+            },
+            rhs: FieldSelector {
+                name: "kind".into(),
+                source: "notes".into(),
+            },
+        }],
+        ..Default::default()
+    };
+    assert_eq!(2, tenant1.run_select_query(joined.clone()).unwrap().len());
+    assert_eq!(1, tenant2.run_select_query(joined).unwrap().len());
+
+    let union = UnionQuery {
+        selects: vec![notes.clone(), notes],
+        op: SetOperation::Union,
+        all: true,
+    };
+    assert_eq!(2, tenant2.run_union_query(union).unwrap().len());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
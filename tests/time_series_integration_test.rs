@@ -0,0 +1,80 @@
+use pbase::{
+    pbase::PBase,
+    time_series::{TimeSeriesBucket, TimeSeriesTable},
+};
+
+#[test]
+fn test_time_series_range_queries() {
+    let dir = std::env::temp_dir().join("pbase_time_series_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let db = PBase::new(dir.clone());
+    assert!(TimeSeriesTable::open(&db, "metrics", 0).is_err());
+    let metrics = TimeSeriesTable::open(&db, "metrics", 100).unwrap();
+    assert!(metrics.query_range(0, 1000, None).unwrap().is_empty());
+
+    for (ts, key, value) in [
+        (-5, 1, 7),
+        (0, 1, 1),
+        (10, 1, 4),
+        (10, 2, 100),
+        (99, 1, 10),
+        (150, 1, 20),
+        (250, 2, 200),
+    ] {
+        metrics.insert(ts, key, value).unwrap();
+    }
+    assert_eq!(
+        vec![
+            "metrics__ts-1",
+            "metrics__ts0",
+            "metrics__ts1",
+            "metrics__ts2"
+        ],
+        db.table_names().unwrap()
+    );
+
+    let bucket = |start, key, count, min, max, avg| TimeSeriesBucket {
+        start,
+        key,
+        count,
+        min,
+        max,
+        avg,
+    };
+
+    assert_eq!(
+        vec![
+            bucket(0, 1, 1, 1, 1, 1),
+            bucket(10, 1, 1, 4, 4, 4),
+            bucket(10, 2, 1, 100, 100, 100),
+            bucket(99, 1, 1, 10, 10, 10),
+        ],
+        metrics.query_range(0, 150, None).unwrap()
+    );
+    assert_eq!(
+        vec![
+            bucket(-50, 1, 1, 7, 7, 7),
+            bucket(0, 1, 2, 1, 4, 2),
+            bucket(0, 2, 1, 100, 100, 100),
+            bucket(50, 1, 1, 10, 10, 10),
+            bucket(150, 1, 1, 20, 20, 20),
+        ],
+        metrics.query_range(-10, 200, Some(50)).unwrap()
+    );
+    assert_eq!(
+        vec![bucket(200, 2, 1, 200, 200, 200)],
+        metrics.query_range(151, i32::MAX, Some(100)).unwrap()
+    );
+    assert!(metrics.query_range(0, 10, Some(-1)).is_err());
+
+    // Reopened from the partitions.
+    let metrics = TimeSeriesTable::open(&db, "metrics", 100).unwrap();
+    assert_eq!(
+        7,
+        metrics.query_range(i32::MIN, i32::MAX, None).unwrap().len()
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}